@@ -9,6 +9,11 @@ use rascaline::calculators::LodeSphericalExpansionParameters;
 use rascaline::calculators::PowerSpectrumParameters;
 use rascaline::calculators::RadialSpectrumParameters;
 use rascaline::calculators::NeighborList;
+use rascaline::calculators::BondFeatures;
+use rascaline::calculators::PermutationInvariantVector;
+use rascaline::calculators::CommonNeighborAnalysis;
+use rascaline::calculators::CentroSymmetry;
+use rascaline::calculators::EwaldDipolarTensor;
 
 
 macro_rules! generate_schema {
@@ -37,6 +42,11 @@ fn main() {
     generate_schema!(AtomicComposition);
     generate_schema!(NeighborList);
     generate_schema!(SortedDistances);
+    generate_schema!(BondFeatures);
+    generate_schema!(PermutationInvariantVector);
+    generate_schema!(CommonNeighborAnalysis);
+    generate_schema!(CentroSymmetry);
+    generate_schema!(EwaldDipolarTensor);
     generate_schema!("SphericalExpansionByPair", SphericalExpansionParameters);
     generate_schema!("SphericalExpansion", SphericalExpansionParameters);
     generate_schema!("LodeSphericalExpansion", LodeSphericalExpansionParameters);
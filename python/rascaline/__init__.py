@@ -4,6 +4,7 @@ from pkg_resources import DistributionNotFound, get_distribution
 
 from .log import set_logging_callback  # noqa
 from .profiling import Profiler  # noqa
+from .threads import set_max_threads  # noqa
 from .status import RascalError  # noqa
 from .systems import IntoSystem, SystemBase  # noqa
 
@@ -12,6 +13,10 @@ from .calculators import CalculatorBase  # noqa  isort: skip
 from .calculators import AtomicComposition  # noqa  isort: skip
 from .calculators import SortedDistances  # noqa  isort: skip
 from .calculators import NeighborList  # noqa  isort: skip
+from .calculators import PermutationInvariantVector  # noqa  isort: skip
+from .calculators import CommonNeighborAnalysis  # noqa  isort: skip
+from .calculators import CentroSymmetry  # noqa  isort: skip
+from .calculators import EwaldDipolarTensor  # noqa  isort: skip
 from .calculators import LodeSphericalExpansion  # noqa isort: skip
 from .calculators import SphericalExpansion  # noqa  isort: skip
 from .calculators import SphericalExpansionByPair  # noqa  isort: skip
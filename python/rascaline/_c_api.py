@@ -45,6 +45,14 @@ class rascal_pair_t(ctypes.Structure):
         ("second", c_uintptr_t),
         ("distance", ctypes.c_double),
         ("vector", ctypes.c_double * 3),
+        ("cell_shift", ctypes.c_int32 * 3),
+    ]
+
+
+class rascal_ghost_atom_t(ctypes.Structure):
+    _fields_ = [
+        ("rank", c_uintptr_t),
+        ("local_index", c_uintptr_t),
     ]
 
 
@@ -58,6 +66,16 @@ class rascal_system_t(ctypes.Structure):
         ("compute_neighbors", CFUNCTYPE(rascal_status_t, ctypes.c_void_p, ctypes.c_double)),
         ("pairs", CFUNCTYPE(rascal_status_t, ctypes.c_void_p, POINTER(ndpointer(rascal_pair_t, flags='C_CONTIGUOUS')), POINTER(c_uintptr_t))),
         ("pairs_containing", CFUNCTYPE(rascal_status_t, ctypes.c_void_p, c_uintptr_t, POINTER(ndpointer(rascal_pair_t, flags='C_CONTIGUOUS')), POINTER(c_uintptr_t))),
+        ("atomic_gaussian_width", CFUNCTYPE(rascal_status_t, ctypes.c_void_p, POINTER(ndpointer(ctypes.c_double, flags='C_CONTIGUOUS')))),
+        ("periodic", CFUNCTYPE(rascal_status_t, ctypes.c_void_p, POINTER(ctypes.c_bool))),
+        ("local_size", CFUNCTYPE(rascal_status_t, ctypes.c_void_p, POINTER(c_uintptr_t))),
+        ("ghost_atoms", CFUNCTYPE(rascal_status_t, ctypes.c_void_p, POINTER(ndpointer(rascal_ghost_atom_t, flags='C_CONTIGUOUS')))),
+        ("charges", CFUNCTYPE(rascal_status_t, ctypes.c_void_p, POINTER(ndpointer(ctypes.c_double, flags='C_CONTIGUOUS')))),
+        ("masses", CFUNCTYPE(rascal_status_t, ctypes.c_void_p, POINTER(ndpointer(ctypes.c_double, flags='C_CONTIGUOUS')))),
+        ("velocities", CFUNCTYPE(rascal_status_t, ctypes.c_void_p, POINTER(ndpointer(ctypes.c_double, flags='C_CONTIGUOUS')))),
+        ("get_atom_data", CFUNCTYPE(rascal_status_t, ctypes.c_void_p, ctypes.c_char_p, POINTER(ndpointer(ctypes.c_double, flags='C_CONTIGUOUS')))),
+        ("centers", CFUNCTYPE(rascal_status_t, ctypes.c_void_p, POINTER(ndpointer(ctypes.c_bool, flags='C_CONTIGUOUS')))),
+        ("bonds", CFUNCTYPE(rascal_status_t, ctypes.c_void_p, POINTER(ndpointer(c_uintptr_t, flags='C_CONTIGUOUS')), POINTER(c_uintptr_t))),
     ]
 
 
@@ -65,6 +83,13 @@ class rascal_labels_selection_t(ctypes.Structure):
     _fields_ = [
         ("subset", POINTER(eqs_labels_t)),
         ("predefined", POINTER(eqs_tensormap_t)),
+        ("centers", POINTER(c_uintptr_t)),
+        ("centers_per_system", POINTER(c_uintptr_t)),
+        ("centers_systems_count", c_uintptr_t),
+        ("subset_names", POINTER(ctypes.c_char_p)),
+        ("subset_size", c_uintptr_t),
+        ("subset_values", POINTER(ctypes.c_int32)),
+        ("subset_count", c_uintptr_t),
     ]
 
 
@@ -76,6 +101,13 @@ class rascal_calculation_options_t(ctypes.Structure):
         ("selected_samples", rascal_labels_selection_t),
         ("selected_properties", rascal_labels_selection_t),
         ("selected_keys", POINTER(eqs_labels_t)),
+        ("selected_gradient_atoms", POINTER(eqs_labels_t)),
+        ("finite_differences_displacement", ctypes.c_double),
+        ("max_neighbors", c_uintptr_t),
+        ("remove_empty_blocks", ctypes.c_bool),
+        ("selected_structures", POINTER(c_uintptr_t)),
+        ("selected_structures_count", c_uintptr_t),
+        ("sort_labels", ctypes.c_bool),
     ]
 
 
@@ -105,6 +137,63 @@ def setup_functions(lib):
     ]
     lib.rascal_basic_systems_free.restype = _check_rascal_status_t
 
+    lib.rascal_basic_systems_set_positions.argtypes = [
+        POINTER(rascal_system_t),
+        POINTER(ctypes.c_double),
+        c_uintptr_t
+    ]
+    lib.rascal_basic_systems_set_positions.restype = _check_rascal_status_t
+
+    lib.rascal_basic_systems_set_bonds.argtypes = [
+        POINTER(rascal_system_t),
+        POINTER(c_uintptr_t),
+        c_uintptr_t
+    ]
+    lib.rascal_basic_systems_set_bonds.restype = _check_rascal_status_t
+
+    lib.rascal_system_validate.argtypes = [
+        POINTER(rascal_system_t)
+    ]
+    lib.rascal_system_validate.restype = _check_rascal_status_t
+
+    lib.rascal_cell_parameters.argtypes = [
+        POINTER(ctypes.c_double),
+        POINTER(ctypes.c_double),
+        POINTER(ctypes.c_double)
+    ]
+    lib.rascal_cell_parameters.restype = _check_rascal_status_t
+
+    lib.rascal_cell_fractional.argtypes = [
+        POINTER(ctypes.c_double),
+        POINTER(ctypes.c_double),
+        c_uintptr_t
+    ]
+    lib.rascal_cell_fractional.restype = _check_rascal_status_t
+
+    lib.rascal_cell_cartesian.argtypes = [
+        POINTER(ctypes.c_double),
+        POINTER(ctypes.c_double),
+        c_uintptr_t
+    ]
+    lib.rascal_cell_cartesian.restype = _check_rascal_status_t
+
+    lib.rascal_cell_wrap.argtypes = [
+        POINTER(ctypes.c_double),
+        POINTER(ctypes.c_bool),
+        POINTER(ctypes.c_double),
+        c_uintptr_t
+    ]
+    lib.rascal_cell_wrap.restype = _check_rascal_status_t
+
+    lib.rascal_cell_distance.argtypes = [
+        POINTER(ctypes.c_double),
+        POINTER(ctypes.c_bool),
+        POINTER(ctypes.c_double),
+        POINTER(ctypes.c_double),
+        POINTER(ctypes.c_double)
+    ]
+    lib.rascal_cell_distance.restype = _check_rascal_status_t
+
     lib.rascal_calculator.argtypes = [
         ctypes.c_char_p,
         ctypes.c_char_p
@@ -139,6 +228,15 @@ def setup_functions(lib):
     ]
     lib.rascal_calculator_compute.restype = _check_rascal_status_t
 
+    lib.rascal_calculator_compute_metadata.argtypes = [
+        POINTER(rascal_calculator_t),
+        POINTER(POINTER(eqs_tensormap_t)),
+        POINTER(rascal_system_t),
+        c_uintptr_t,
+        rascal_calculation_options_t
+    ]
+    lib.rascal_calculator_compute_metadata.restype = _check_rascal_status_t
+
     lib.rascal_profiling_clear.argtypes = [
         
     ]
@@ -155,3 +253,8 @@ def setup_functions(lib):
         c_uintptr_t
     ]
     lib.rascal_profiling_get.restype = _check_rascal_status_t
+
+    lib.rascal_set_max_threads.argtypes = [
+        c_uintptr_t
+    ]
+    lib.rascal_set_max_threads.restype = _check_rascal_status_t
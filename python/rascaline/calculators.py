@@ -1,11 +1,12 @@
 import ctypes
 import json
+import numbers
 from typing import List, Optional, Union
 
 from equistore.core import Labels, TensorMap
 from equistore.core._c_api import eqs_tensormap_t
 
-from ._c_api import rascal_calculation_options_t, rascal_system_t
+from ._c_api import c_uintptr_t, rascal_calculation_options_t, rascal_system_t
 from ._c_lib import _get_library
 from .status import _check_rascal_pointer
 from .systems import IntoSystem, wrap_system
@@ -28,6 +29,12 @@ def _options_to_c(
     selected_samples,
     selected_properties,
     selected_keys,
+    selected_gradient_atoms,
+    finite_differences_displacement,
+    max_neighbors,
+    remove_empty_blocks,
+    selected_structures,
+    sort_labels,
 ):
     if gradients is None:
         gradients = []
@@ -64,11 +71,34 @@ def _options_to_c(
         c_options.__keepalive["selected_samples"] = selected_samples
     elif isinstance(selected_samples, TensorMap):
         c_options.selected_samples.predefined = selected_samples._ptr
+    elif isinstance(selected_samples, list):
+        # list of centers for each system
+        centers = []
+        centers_per_system = []
+        for system_centers in selected_samples:
+            system_centers = list(system_centers)
+            for center in system_centers:
+                if not isinstance(center, numbers.Integral) or center < 0:
+                    raise ValueError(
+                        "centers in `selected_samples` must be non-negative "
+                        f"integers, got {center}"
+                    )
+            centers.extend(int(center) for center in system_centers)
+            centers_per_system.append(len(system_centers))
+
+        c_centers = ctypes.ARRAY(c_uintptr_t, len(centers))(*centers)
+        c_centers_per_system = ctypes.ARRAY(c_uintptr_t, len(centers_per_system))(
+            *centers_per_system
+        )
+        c_options.selected_samples.centers = c_centers
+        c_options.selected_samples.centers_per_system = c_centers_per_system
+        c_options.selected_samples.centers_systems_count = len(centers_per_system)
+        c_options.__keepalive["selected_centers"] = (c_centers, c_centers_per_system)
     else:
         raise ValueError(
             "expected selected samples to be either an `equistore.Labels` "
-            "instance, or an got `equistore.TensorMap` instance, got "
-            f"{type(selected_samples)} instead"
+            "instance, an `equistore.TensorMap` instance, or a list of centers "
+            f"for each system, got {type(selected_samples)} instead"
         )
 
     if selected_properties is None:
@@ -94,6 +124,50 @@ def _options_to_c(
         selected_keys = selected_keys._as_eqs_labels_t()
         c_options.selected_keys = ctypes.pointer(selected_keys)
         c_options.__keepalive["selected_keys"] = selected_keys
+
+    if selected_gradient_atoms is None:
+        # nothing to do, all pointers are already NULL
+        pass
+    elif isinstance(selected_gradient_atoms, Labels):
+        selected_gradient_atoms = selected_gradient_atoms._as_eqs_labels_t()
+        c_options.selected_gradient_atoms = ctypes.pointer(selected_gradient_atoms)
+        c_options.__keepalive["selected_gradient_atoms"] = selected_gradient_atoms
+    else:
+        raise ValueError(
+            "expected selected gradient atoms to be an `equistore.Labels` "
+            f"instance, got {type(selected_gradient_atoms)} instead"
+        )
+
+    if finite_differences_displacement is not None:
+        c_options.finite_differences_displacement = finite_differences_displacement
+
+    if max_neighbors is not None:
+        if max_neighbors <= 0:
+            raise ValueError(
+                f"`max_neighbors` must be a positive integer, got {max_neighbors}"
+            )
+        c_options.max_neighbors = max_neighbors
+
+    c_options.remove_empty_blocks = bool(remove_empty_blocks)
+
+    if selected_structures is not None:
+        selected_structures = list(selected_structures)
+        for structure in selected_structures:
+            if not isinstance(structure, int) or structure < 0:
+                raise ValueError(
+                    "`selected_structures` must be a list of non-negative "
+                    f"integers, got {structure} in the list"
+                )
+
+        c_selected_structures = ctypes.ARRAY(c_uintptr_t, len(selected_structures))(
+            *selected_structures
+        )
+        c_options.selected_structures = c_selected_structures
+        c_options.selected_structures_count = len(selected_structures)
+        c_options.__keepalive["selected_structures"] = c_selected_structures
+
+    c_options.sort_labels = bool(sort_labels)
+
     return c_options
 
 
@@ -146,9 +220,15 @@ class CalculatorBase:
         *,
         gradients: Optional[List[str]] = None,
         use_native_system: bool = True,
-        selected_samples: Optional[Union[Labels, TensorMap]] = None,
+        selected_samples: Optional[Union[Labels, TensorMap, List[List[int]]]] = None,
         selected_properties: Optional[Union[Labels, TensorMap]] = None,
         selected_keys: Optional[Labels] = None,
+        selected_gradient_atoms: Optional[Labels] = None,
+        finite_differences_displacement: Optional[float] = None,
+        max_neighbors: Optional[int] = None,
+        remove_empty_blocks: bool = False,
+        selected_structures: Optional[List[int]] = None,
+        sort_labels: bool = False,
     ) -> TensorMap:
         r"""Runs a calculation with this calculator on the given ``systems``.
 
@@ -167,7 +247,8 @@ class CalculatorBase:
             an empty list ``[]``, no gradients are computed. Gradients are
             stored inside the different blocks, and can be accessed with
             ``descriptor.block(...).gradient(<parameter>)``, where
-            ``<parameter>`` is ``"positions"`` or ``"cell"``. The following
+            ``<parameter>`` is ``"positions"``, ``"cell"`` or
+            ``"positions_hessian"``. The following
             gradients are available:
 
             - ``"positions"``, for gradients of the representation with respect to
@@ -206,6 +287,19 @@ class CalculatorBase:
                    = -\frac{\partial \langle q \vert A \rangle}
                            {\partial \mathbf{h}} \cdot \mathbf{h}
 
+            - ``"positions_hessian"``, for second derivatives of the
+              representation with respect to atomic positions, computed as
+
+              .. math::
+                  \frac{\partial^2 \langle q \vert A_i \rangle}
+                       {\partial \mathbf{r_j} \partial \mathbf{r_k}}
+
+              The samples of these gradients are ``["sample", "structure",
+              "atom_1", "atom_2"]``, and the first two components
+              (``"direction_1"`` and ``"direction_2"``) correspond to the
+              cartesian directions of atoms :math:`j` and :math:`k`
+              respectively. Only some calculators support these.
+
         :param selected_samples: Set of samples on which to run the calculation.
             Use ``None`` to run the calculation on all samples in the
             ``systems`` (this is the default).
@@ -225,6 +319,11 @@ class CalculatorBase:
             these variables as one of the entries in ``selected_samples`` will
             be used.
 
+            If ``selected_samples`` is a list, it should contain one list of
+            center atom indexes for each system, and only the samples for these
+            centers will be used. This can only be used with calculators using
+            ``"structure"`` and ``"center"`` samples.
+
         :param selected_properties: Set of properties to compute. Use ``None``
             to run the calculation on all properties (this is the default).
 
@@ -247,6 +346,52 @@ class CalculatorBase:
             If this is ``None``, the default set of keys (as determined by the
             calculator) will be used. Note that this default set of keys can
             depend on which systems we are running the calculation on.
+
+            The output contains exactly the selected keys, in the same order.
+            Keys which do not appear in the ``systems`` create empty blocks,
+            unless ``remove_empty_blocks`` is ``True``. Using the same
+            ``selected_keys`` for all batches of a dataset ensures that all the
+            corresponding descriptors share the same block structure.
+
+        :param selected_gradient_atoms: Selection of atoms for which to compute
+            gradients with respect to positions, as :py:class:`equistore.Labels`
+            with ``["structure", "atom"]`` names. If this is ``None``, the
+            gradients with respect to all atoms are computed. This is useful
+            when most of the atoms in the systems are frozen.
+
+        :param finite_differences_displacement: Displacement used to compute
+            gradients with respect to positions with finite differences, for
+            calculators which do not implement these gradients. If this is
+            ``None``, requesting ``"positions"`` gradients from such a
+            calculator raises an error. Finite differences gradients are
+            computed with respect to all atoms in the structure, and are much
+            slower than analytic gradients.
+
+        :param max_neighbors: Maximal number of neighbors around a single atom.
+            If any atom has more neighbors within the cutoff of the calculator,
+            the calculation stops with an error before allocating memory for
+            the output, which typically happens when the positions and cutoff
+            use different units. If this is ``None``, the number of neighbors
+            is not checked.
+
+        :param remove_empty_blocks: Remove the blocks without any sample from
+            the output. Such blocks are typically created for pairs of species
+            which are present in the systems but never within the cutoff of one
+            another. If all the blocks are empty, they are all kept.
+
+        :param selected_structures: Indexes of the systems on which to run the
+            calculation. If this is ``None``, all the systems are used.
+            Otherwise, the other systems are ignored, but the ``"structure"``
+            samples still refer to the position of each system in the full
+            list of ``systems``.
+
+        :param sort_labels: Sort the keys, and the samples inside each block, in
+            lexicographic order. The keys and samples created by the
+            calculators in rascaline are already sorted, and do not depend on
+            the neighbor list implementation or the number of threads.
+            User-provided selections are however used in the order they are
+            given, and setting this to ``True`` ensures the output always uses
+            the same order.
         """
 
         c_systems = _convert_systems(systems)
@@ -258,6 +403,12 @@ class CalculatorBase:
             selected_samples=selected_samples,
             selected_properties=selected_properties,
             selected_keys=selected_keys,
+            selected_gradient_atoms=selected_gradient_atoms,
+            finite_differences_displacement=finite_differences_displacement,
+            max_neighbors=max_neighbors,
+            remove_empty_blocks=remove_empty_blocks,
+            selected_structures=selected_structures,
+            sort_labels=sort_labels,
         )
         self._lib.rascal_calculator_compute(
             self, tensor_map_ptr, c_systems, c_systems._length_, c_options
@@ -265,6 +416,55 @@ class CalculatorBase:
 
         return TensorMap._from_ptr(tensor_map_ptr)
 
+    def compute_metadata(
+        self,
+        systems: Union[IntoSystem, List[IntoSystem]],
+        *,
+        gradients: Optional[List[str]] = None,
+        use_native_system: bool = True,
+        selected_samples: Optional[Union[Labels, TensorMap, List[List[int]]]] = None,
+        selected_properties: Optional[Union[Labels, TensorMap]] = None,
+        selected_keys: Optional[Labels] = None,
+        selected_gradient_atoms: Optional[Labels] = None,
+        finite_differences_displacement: Optional[float] = None,
+        max_neighbors: Optional[int] = None,
+        remove_empty_blocks: bool = False,
+        selected_structures: Optional[List[int]] = None,
+        sort_labels: bool = False,
+    ) -> TensorMap:
+        """Get the metadata (keys, samples, components and properties) of the
+        representation :py:meth:`compute` would produce for the given
+        ``systems``, without computing the values.
+
+        The blocks in the returned :py:class:`equistore.TensorMap` do not
+        contain any data, only the corresponding metadata. This can be used to
+        check the effect of the different selections before running the full
+        calculation. All the parameters have the same meaning as for
+        :py:meth:`compute`.
+        """
+
+        c_systems = _convert_systems(systems)
+        tensor_map_ptr = ctypes.POINTER(eqs_tensormap_t)()
+
+        c_options = _options_to_c(
+            gradients=gradients,
+            use_native_system=use_native_system,
+            selected_samples=selected_samples,
+            selected_properties=selected_properties,
+            selected_keys=selected_keys,
+            selected_gradient_atoms=selected_gradient_atoms,
+            finite_differences_displacement=finite_differences_displacement,
+            max_neighbors=max_neighbors,
+            remove_empty_blocks=remove_empty_blocks,
+            selected_structures=selected_structures,
+            sort_labels=sort_labels,
+        )
+        self._lib.rascal_calculator_compute_metadata(
+            self, tensor_map_ptr, c_systems, c_systems._length_, c_options
+        )
+
+        return TensorMap._from_ptr(tensor_map_ptr)
+
 
 class AtomicComposition(CalculatorBase):
     """An atomic composition calculator for obtaining the stoichiometric information.
@@ -350,6 +550,93 @@ class SortedDistances(CalculatorBase):
         super().__init__("sorted_distances", parameters)
 
 
+class PermutationInvariantVector(CalculatorBase):
+    """Permutation invariant vector (PIV) representation of a structure.
+
+    For each pair of species, all the pairs of atoms within the ``cutoff`` are
+    transformed with a rational switching function ``(1 - (r/r_0)^n) / (1 -
+    (r/r_0)^m)`` (shifted and scaled to go to zero at the cutoff), and the
+    ``max_pairs`` largest values are kept, sorted from largest to smallest.
+
+    For a full description of the hyper-parameters, see the corresponding
+    :ref:`documentation <permutation-invariant-vector>`.
+    """
+
+    def __init__(self, cutoff, r_0, max_pairs, n=6, m=12):
+        parameters = {
+            "cutoff": cutoff,
+            "r_0": r_0,
+            "n": n,
+            "m": m,
+            "max_pairs": max_pairs,
+        }
+        super().__init__("permutation_invariant_vector", parameters)
+
+
+class CommonNeighborAnalysis(CalculatorBase):
+    """Common neighbor analysis (CNA) signatures of each atom.
+
+    For each atom, this calculator counts how many of its neighbors have the CNA
+    signatures corresponding to the FCC, HCP, BCC and icosahedral structures.
+    Two atoms are bonded if they are closer than ``cutoff``, or using a local
+    cutoff determined from the nearest neighbors of each atom if ``adaptive`` is
+    ``True``.
+
+    For a full description of the hyper-parameters, see the corresponding
+    :ref:`documentation <common-neighbor-analysis>`.
+    """
+
+    def __init__(self, cutoff, adaptive=False):
+        parameters = {
+            "cutoff": cutoff,
+            "adaptive": adaptive,
+        }
+        super().__init__("common_neighbor_analysis", parameters)
+
+
+class CentroSymmetry(CalculatorBase):
+    """Centro-symmetry parameter of each atom.
+
+    For each atom, the vectors to its ``num_neighbors`` nearest neighbors are
+    combined in pairs, and the centro-symmetry parameter is the sum of the
+    ``num_neighbors / 2`` smallest values of ``|R_j + R_k|^2``. This is zero in
+    perfectly centro-symmetric environments, and larger close to defects.
+
+    For a full description of the hyper-parameters, see the corresponding
+    :ref:`documentation <centro-symmetry>`.
+    """
+
+    def __init__(self, cutoff, num_neighbors):
+        parameters = {
+            "cutoff": cutoff,
+            "num_neighbors": num_neighbors,
+        }
+        super().__init__("centro_symmetry", parameters)
+
+
+class EwaldDipolarTensor(CalculatorBase):
+    """Long-range dipole-dipole interaction tensor, computed with an Ewald sum.
+
+    For each central atom and each neighbor species, this computes the second
+    derivatives of the periodic Coulomb potential created by Gaussian densities
+    on the neighbor atoms, evaluated at the position of the central atom. This
+    tensor decays as :math:`r^{-3}` and captures anisotropic (dipolar)
+    long-range correlations between environments. This is a direct Ewald
+    summation in reciprocal space, and not an expansion on the LODE basis.
+
+    For a full description of the hyper-parameters, see the corresponding
+    :ref:`documentation <ewald-dipolar-tensor>`.
+    """
+
+    def __init__(self, atomic_gaussian_width, k_cutoff=None):
+        parameters = {
+            "atomic_gaussian_width": atomic_gaussian_width,
+            "k_cutoff": k_cutoff,
+        }
+
+        super().__init__("ewald_dipolar_tensor", parameters)
+
+
 class SphericalExpansion(CalculatorBase):
     """Spherical expansion of Smooth Overlap of Atomic Positions (SOAP).
 
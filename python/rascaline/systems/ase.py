@@ -60,12 +60,8 @@ class AseSystem(SystemBase):
                     stacklevel=1,
                 )
                 self._cell[:, :] = 0.0
-            else:
-                raise Exception(
-                    "different periodic boundary conditions on different axis "
-                    "are not supported"
-                )
 
+        self._pbc = [bool(pbc) for pbc in atoms_pbc]
         self._atoms = atoms
         self._pairs = []
         self._pairs_by_center = []
@@ -83,27 +79,52 @@ class AseSystem(SystemBase):
     def cell(self):
         return self._cell
 
+    def periodic(self):
+        return self._pbc
+
+    def masses(self):
+        return self._atoms.get_masses()
+
+    def velocities(self):
+        if "momenta" in self._atoms.arrays:
+            return self._atoms.get_velocities()
+        else:
+            return None
+
+    def atom_data(self, name):
+        values = self._atoms.arrays.get(name)
+        if values is None or len(values.shape) != 1:
+            return None
+
+        return values
+
+    def charges(self):
+        if "initial_charges" in self._atoms.arrays:
+            return self._atoms.get_initial_charges()
+        else:
+            return None
+
     def compute_neighbors(self, cutoff):
         if self._last_cutoff == cutoff:
             return
 
         self._pairs = []
 
-        nl_result = neighborlist.neighbor_list("ijdD", self._atoms, cutoff)
-        for i, j, d, D in zip(*nl_result):
+        nl_result = neighborlist.neighbor_list("ijdDS", self._atoms, cutoff)
+        for i, j, d, D, S in zip(*nl_result):
             if j < i:
                 # we want a half neighbor list, so drop all duplicated
                 # neighbors
                 continue
-            self._pairs.append((i, j, d, D))
+            self._pairs.append((i, j, d, D, S))
 
         self._pairs_by_center = []
         for _ in range(self.size()):
             self._pairs_by_center.append([])
 
-        for i, j, d, D in self._pairs:
-            self._pairs_by_center[i].append((i, j, d, D))
-            self._pairs_by_center[j].append((i, j, d, D))
+        for pair in self._pairs:
+            self._pairs_by_center[pair[0]].append(pair)
+            self._pairs_by_center[pair[1]].append(pair)
 
     def pairs(self):
         return self._pairs
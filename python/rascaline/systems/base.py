@@ -3,7 +3,12 @@ from ctypes import POINTER, c_double, c_void_p, pointer
 
 import numpy as np
 
-from .._c_api import c_uintptr_t, rascal_pair_t, rascal_system_t
+from .._c_api import (
+    c_uintptr_t,
+    rascal_ghost_atom_t,
+    rascal_pair_t,
+    rascal_system_t,
+)
 from ..status import _save_exception
 
 
@@ -21,6 +26,21 @@ def catch_exceptions(function):
     return inner
 
 
+def _pairs_array(pairs):
+    """Convert the pairs returned by a system to an array of ``rascal_pair_t``.
+
+    Pairs given as tuples without the cell shift use a zero cell shift.
+    """
+    if isinstance(pairs, np.ndarray) and pairs.dtype == rascal_pair_t:
+        return np.ascontiguousarray(pairs)
+
+    pairs = [
+        tuple(pair) if len(pair) == 5 else (*pair, (0, 0, 0))
+        for pair in pairs
+    ]
+    return np.asarray(pairs, order="C", dtype=rascal_pair_t)
+
+
 class SystemBase:
     """Base class implementing the ``System`` trait in rascaline.
 
@@ -140,11 +160,7 @@ class SystemBase:
             """
             self = get_self(user_data)
 
-            pairs = np.asarray(
-                self.pairs(),
-                order="C",
-                dtype=rascal_pair_t,
-            )
+            pairs = _pairs_array(self.pairs())
 
             count[0] = c_uintptr_t(len(pairs))
             data[0] = pairs.ctypes.data
@@ -160,11 +176,7 @@ class SystemBase:
             """
             self = get_self(user_data)
 
-            pairs = np.asarray(
-                self.pairs_containing(center),
-                order="C",
-                dtype=rascal_pair_t,
-            )
+            pairs = _pairs_array(self.pairs_containing(center))
 
             count[0] = c_uintptr_t(len(pairs))
             data[0] = pairs.ctypes.data
@@ -174,6 +186,215 @@ class SystemBase:
             rascal_system_pairs_containing
         )
 
+        @catch_exceptions
+        def rascal_system_atomic_gaussian_width(user_data, data):
+            """
+            Implementation of ``rascal_system_t::atomic_gaussian_width`` using
+            :py:func:`SystemBase.atomic_gaussian_width`.
+            """
+            self = get_self(user_data)
+
+            widths = self.atomic_gaussian_width()
+            if widths is None:
+                data[0] = None
+                return
+
+            widths = np.asarray(widths, order="C", dtype=c_double)
+            assert len(widths.shape) == 1
+
+            data[0] = widths.ctypes.data
+            self._keepalive["atomic_gaussian_width"] = widths
+
+        struct.atomic_gaussian_width = struct.atomic_gaussian_width.__class__(
+            rascal_system_atomic_gaussian_width
+        )
+
+        @catch_exceptions
+        def rascal_system_periodic(user_data, data):
+            """
+            Implementation of ``rascal_system_t::periodic`` using
+            :py:func:`SystemBase.periodic`.
+            """
+            self = get_self(user_data)
+
+            periodic = self.periodic()
+            if periodic is None:
+                periodic = [True, True, True]
+
+            periodic = np.asarray(periodic, dtype=bool)
+            assert periodic.shape == (3,)
+
+            for i in range(3):
+                data[i] = bool(periodic[i])
+
+        struct.periodic = struct.periodic.__class__(rascal_system_periodic)
+
+        @catch_exceptions
+        def rascal_system_local_size(user_data, local_size):
+            """
+            Implementation of ``rascal_system_t::local_size`` using
+            :py:func:`SystemBase.local_size`.
+            """
+            local_size[0] = c_uintptr_t(get_self(user_data).local_size())
+
+        struct.local_size = struct.local_size.__class__(rascal_system_local_size)
+
+        @catch_exceptions
+        def rascal_system_ghost_atoms(user_data, data):
+            """
+            Implementation of ``rascal_system_t::ghost_atoms`` using
+            :py:func:`SystemBase.ghost_atoms`.
+            """
+            self = get_self(user_data)
+
+            ghosts = self.ghost_atoms()
+            if ghosts is None:
+                data[0] = None
+                return
+
+            ghosts = np.asarray(
+                [tuple(ghost) for ghost in ghosts],
+                order="C",
+                dtype=rascal_ghost_atom_t,
+            )
+
+            data[0] = ghosts.ctypes.data
+            self._keepalive["ghost_atoms"] = ghosts
+
+        struct.ghost_atoms = struct.ghost_atoms.__class__(rascal_system_ghost_atoms)
+
+        @catch_exceptions
+        def rascal_system_charges(user_data, data):
+            """
+            Implementation of ``rascal_system_t::charges`` using
+            :py:func:`SystemBase.charges`.
+            """
+            self = get_self(user_data)
+
+            charges = self.charges()
+            if charges is None:
+                data[0] = None
+                return
+
+            charges = np.asarray(charges, order="C", dtype=c_double)
+            assert len(charges.shape) == 1
+
+            data[0] = charges.ctypes.data
+            self._keepalive["charges"] = charges
+
+        struct.charges = struct.charges.__class__(rascal_system_charges)
+
+        @catch_exceptions
+        def rascal_system_masses(user_data, data):
+            """
+            Implementation of ``rascal_system_t::masses`` using
+            :py:func:`SystemBase.masses`.
+            """
+            self = get_self(user_data)
+
+            masses = self.masses()
+            if masses is None:
+                data[0] = None
+                return
+
+            masses = np.asarray(masses, order="C", dtype=c_double)
+            assert len(masses.shape) == 1
+
+            data[0] = masses.ctypes.data
+            self._keepalive["masses"] = masses
+
+        struct.masses = struct.masses.__class__(rascal_system_masses)
+
+        @catch_exceptions
+        def rascal_system_velocities(user_data, data):
+            """
+            Implementation of ``rascal_system_t::velocities`` using
+            :py:func:`SystemBase.velocities`.
+            """
+            self = get_self(user_data)
+
+            velocities = self.velocities()
+            if velocities is None:
+                data[0] = None
+                return
+
+            velocities = np.asarray(velocities, order="C", dtype=c_double)
+            assert len(velocities.shape) == 2
+            assert velocities.shape[1] == 3
+
+            data[0] = velocities.ctypes.data
+            self._keepalive["velocities"] = velocities
+
+        struct.velocities = struct.velocities.__class__(rascal_system_velocities)
+
+        @catch_exceptions
+        def rascal_system_get_atom_data(user_data, name, data):
+            """
+            Implementation of ``rascal_system_t::get_atom_data`` using
+            :py:func:`SystemBase.atom_data`.
+            """
+            self = get_self(user_data)
+
+            name = name.decode("utf8")
+            values = self.atom_data(name)
+            if values is None:
+                data[0] = None
+                return
+
+            values = np.asarray(values, order="C", dtype=c_double)
+            assert len(values.shape) == 1
+
+            data[0] = values.ctypes.data
+            self._keepalive[f"atom_data:{name}"] = values
+
+        struct.get_atom_data = struct.get_atom_data.__class__(
+            rascal_system_get_atom_data
+        )
+
+        @catch_exceptions
+        def rascal_system_centers(user_data, data):
+            """
+            Implementation of ``rascal_system_t::centers`` using
+            :py:func:`SystemBase.centers`.
+            """
+            self = get_self(user_data)
+
+            centers = self.centers()
+            if centers is None:
+                data[0] = None
+                return
+
+            centers = np.asarray(centers, order="C", dtype=np.bool_)
+            assert len(centers.shape) == 1
+
+            data[0] = centers.ctypes.data
+            self._keepalive["centers"] = centers
+
+        struct.centers = struct.centers.__class__(rascal_system_centers)
+
+        @catch_exceptions
+        def rascal_system_bonds(user_data, data, count):
+            """
+            Implementation of ``rascal_system_t::bonds`` using
+            :py:func:`SystemBase.bonds`.
+            """
+            self = get_self(user_data)
+
+            bonds = self.bonds()
+            if bonds is None or len(bonds) == 0:
+                data[0] = None
+                count[0] = c_uintptr_t(0)
+                return
+
+            bonds = np.asarray(bonds, order="C", dtype=c_uintptr_t)
+            assert len(bonds.shape) == 2 and bonds.shape[1] == 2
+
+            count[0] = c_uintptr_t(bonds.shape[0])
+            data[0] = bonds.ctypes.data
+            self._keepalive["bonds"] = bonds
+
+        struct.bonds = struct.bonds.__class__(rascal_system_bonds)
+
         return struct
 
     def size(self):
@@ -181,6 +402,17 @@ class SystemBase:
 
         raise NotImplementedError("System.size method is not implemented")
 
+    def local_size(self):
+        """Get the number of local atoms in this system as an integer.
+
+        Local atoms must come first, and all atoms with an index larger than or
+        equal to ``local_size()`` are ghost atoms: they are used as neighbors
+        of the local atoms, but never as centers. The default implementation
+        returns ``self.size()``, i.e. there are no ghost atoms.
+        """
+
+        return self.size()
+
     def species(self):
         """Get the atomic species of all atoms in the system.
 
@@ -230,10 +462,13 @@ class SystemBase:
         :py:func:`SystemBase.compute_neighbors`
 
         Get all neighbor pairs in this system as a list of tuples ``(int, int,
-        float, (float, float, float))`` containing the indexes of the first and
-        second atom in the pair, the distance between the atoms, and the wrapped
-        between them. Alternatively, this function can return a 1D numpy array
-        with ``dtype=rascal_pair_t``.
+        float, (float, float, float), (int, int, int))`` containing the indexes
+        of the first and second atom in the pair, the distance between the
+        atoms, the wrapped vector between them, and the number of cell vectors
+        to add to the position of the second atom to get the periodic image
+        used in this pair. The cell shift can be omitted for systems without
+        periodic boundary conditions. Alternatively, this function can return a
+        1D numpy array with ``dtype=rascal_pair_t``.
 
         The list of pair should only contain each pair once (and not twice as
         ``i-j`` and ``j-i``), should not contain self pairs (``i-i``); and
@@ -260,3 +495,111 @@ class SystemBase:
         """
 
         raise NotImplementedError("System.pairs_containing method is not implemented")
+
+    def atomic_gaussian_width(self):
+        """Get the width of the gaussian atomic density for each atom.
+
+        This function can return ``None`` (the default) if the system does not
+        define per-atom widths. Otherwise, the returned widths must be
+        convertible to a numpy array of shape ``(self.size(),)``, with a dtype
+        of `np.float64`, and only contain strictly positive values.
+        """
+
+        return None
+
+    def periodic(self):
+        """Get the periodic boundary conditions along each cell vector.
+
+        This function should return a list of three booleans, indicating if the
+        system is periodic along the corresponding cell vector, or ``None``
+        (the default) if the system is periodic along all directions. This is
+        ignored when the cell matrix is zero.
+        """
+
+        return None
+
+    def ghost_atoms(self):
+        """Get the owner of each ghost atom in this system.
+
+        This function can return ``None`` (the default) if the owners are not
+        known. Otherwise, it should return a list of ``(rank, local_index)``
+        tuples, one for each ghost atom, where ``rank`` is the index of the
+        domain owning the original atom, and ``local_index`` the index of the
+        original atom in this domain.
+        """
+
+        return None
+
+    def charges(self):
+        """Get the charge of each atom in this system.
+
+        This function can return ``None`` (the default) if the system does not
+        define charges. Otherwise, the returned charges must be convertible to a
+        numpy array of shape ``(self.size(),)``, with a dtype of `np.float64`.
+        """
+
+        return None
+
+    def masses(self):
+        """Get the mass of each atom in this system.
+
+        This function can return ``None`` (the default) if the system does not
+        define masses. Otherwise, the returned masses must be convertible to a
+        numpy array of shape ``(self.size(),)``, with a dtype of `np.float64`,
+        and only contain strictly positive values.
+        """
+
+        return None
+
+    def velocities(self):
+        """Get the velocity of each atom in this system.
+
+        This function can return ``None`` (the default) if the system does not
+        define velocities. Otherwise, the returned velocities must be
+        convertible to a numpy array of shape ``(self.size(), 3)``, with a dtype
+        of `np.float64`.
+        """
+
+        return None
+
+    def atom_data(self, name):
+        """Get the values of the per-atom data called ``name``.
+
+        This allows systems to carry arbitrary named per-atom properties (for
+        example ``"hirshfeld_volume"`` or ``"spin"``) that calculators can
+        query. This function can return ``None`` (the default) if the system
+        does not define data with this name. Otherwise, the returned values must
+        be convertible to a numpy array of shape ``(self.size(),)``, with a
+        dtype of `np.float64`.
+
+        The ``"atomic_gaussian_width"``, ``"charges"`` and ``"masses"`` data is
+        used when the corresponding function returns ``None``.
+        """
+
+        return None
+
+    def centers(self):
+        """Get which atoms should be used as centers by atom-centered
+        calculators.
+
+        This can be used to only compute representations for a subset of the
+        atoms (for example only the adsorbate on a large slab), while still
+        using all atoms as neighbors. This function can return ``None`` (the
+        default) if all atoms should be used as centers. Otherwise, the returned
+        mask must be convertible to a numpy array of shape ``(self.size(),)``,
+        with a dtype of `np.bool_`. Ghost atoms are never used as centers,
+        regardless of this mask.
+        """
+
+        return None
+
+    def bonds(self):
+        """Get the chemical bonds in this system.
+
+        This function can return ``None`` (the default) if the system does not
+        contain any bond. Otherwise, the bonds must be convertible to a numpy
+        array of integers with shape ``(n_bonds, 2)``, containing the indexes of
+        the two atoms in each bond. Each bond should only be included once.
+        """
+
+        return None
@@ -2,7 +2,6 @@ import warnings
 
 import numpy as np
 
-from .._c_api import c_uintptr_t
 from .base import SystemBase
 
 
@@ -66,7 +65,7 @@ class ChemfilesSystem(SystemBase):
             raise Exception("this class expects chemfiles.Frame objects")
 
         self._frame = frame
-        self._species = np.zeros((self.size()), dtype=c_uintptr_t)
+        self._species = np.zeros((self.size()), dtype=np.int32)
         for i, atom in enumerate(self._frame.atoms):
             if atom.atomic_number != 0:
                 self._species[i] = atom.atomic_number
@@ -87,6 +86,9 @@ class ChemfilesSystem(SystemBase):
         # major format
         return self._frame.cell.matrix.T
 
+    def bonds(self):
+        return self._frame.topology.bonds
+
     def compute_neighbors(self, cutoff):
         raise Exception(
             "chemfiles systems can only be used with 'use_native_system=True'"
@@ -0,0 +1,21 @@
+from ._c_lib import _get_library
+
+
+def set_max_threads(max_threads):
+    """Set the maximal number of threads used by all calculators.
+
+    Using ``max_threads=None`` removes the limit. When no limit is set with this
+    function, the ``RASCALINE_NUM_THREADS`` environment variable is used if it
+    is set, and all available threads are used otherwise. Limiting the number of
+    threads is useful when calling rascaline from code which is already running
+    in parallel (e.g. with ``multiprocessing``).
+
+    :param max_threads: maximal number of threads, or ``None`` to remove the
+        limit
+    """
+    if max_threads is None:
+        max_threads = 0
+    elif max_threads <= 0:
+        raise ValueError("max_threads must be a positive integer or None")
+
+    _get_library().rascal_set_max_threads(max_threads)
@@ -145,6 +145,142 @@ class TestDummyCalculator(unittest.TestCase):
         O_block = descriptor.block(species_center=8)
         self.assertEqual(O_block.values.shape, (6, 2))
 
+    def test_negative_species(self):
+        class NegativeSpeciesSystem(TestSystem):
+            def species(self):
+                return [1, 1, -42, -42]
+
+        calculator = DummyCalculator(cutoff=3.2, delta=2, name="")
+        for use_native_system in [False, True]:
+            descriptor = calculator.compute(
+                NegativeSpeciesSystem(), use_native_system=use_native_system
+            )
+            self.assertEqual(
+                descriptor.keys.view(np.int32).reshape(-1).tolist(), [-42, 1]
+            )
+
+            block = descriptor.block(species_center=-42)
+            self.assertEqual(block.values.shape, (2, 2))
+
+    def test_centers_mask(self):
+        class MaskedSystem(TestSystem):
+            def centers(self):
+                return [False, True, True, False]
+
+        calculator = DummyCalculator(cutoff=3.2, delta=2, name="")
+        for use_native_system in [False, True]:
+            descriptor = calculator.compute(
+                MaskedSystem(), use_native_system=use_native_system
+            )
+            self.assertEqual(
+                descriptor.keys.view(np.int32).reshape(-1).tolist(), [1, 8]
+            )
+
+            block = descriptor.block(species_center=1)
+            self.assertEqual(len(block.samples), 1)
+            self.assertEqual(tuple(block.samples[0]), (0, 1))
+
+            block = descriptor.block(species_center=8)
+            self.assertEqual(len(block.samples), 1)
+            self.assertEqual(tuple(block.samples[0]), (0, 2))
+
+    def test_max_neighbors(self):
+        system = TestSystem()
+        calculator = DummyCalculator(cutoff=3.2, delta=2, name="")
+
+        descriptor = calculator.compute(
+            system, use_native_system=False, max_neighbors=2
+        )
+        self.assertEqual(len(descriptor.keys), 2)
+
+        with self.assertRaises(RascalError) as cm:
+            calculator.compute(system, use_native_system=False, max_neighbors=1)
+
+        self.assertIn(
+            "atom 1 in system 0 has 2 neighbors within the cutoff of 3.2",
+            str(cm.exception),
+        )
+
+        with self.assertRaises(ValueError) as cm:
+            calculator.compute(system, max_neighbors=0)
+
+        self.assertEqual(
+            str(cm.exception), "`max_neighbors` must be a positive integer, got 0"
+        )
+
+    def test_remove_empty_blocks(self):
+        system = TestSystem()
+        calculator = DummyCalculator(cutoff=3.2, delta=2, name="")
+
+        keys = Labels(
+            names=["species_center"],
+            values=np.array([[1], [6], [8]], dtype=np.int32),
+        )
+
+        descriptor = calculator.compute(system, selected_keys=keys)
+        self.assertEqual(len(descriptor.keys), 3)
+        self.assertEqual(len(descriptor.block(species_center=6).samples), 0)
+
+        descriptor = calculator.compute(
+            system, selected_keys=keys, remove_empty_blocks=True
+        )
+        self.assertEqual(len(descriptor.keys), 2)
+        self.assertEqual(tuple(descriptor.keys[0]), (1,))
+        self.assertEqual(tuple(descriptor.keys[1]), (8,))
+
+    def test_sort_labels(self):
+        system = TestSystem()
+        calculator = DummyCalculator(cutoff=3.2, delta=2, name="")
+
+        keys = Labels(
+            names=["species_center"],
+            values=np.array([[8], [1]], dtype=np.int32),
+        )
+
+        descriptor = calculator.compute(system, selected_keys=keys)
+        self.assertEqual(tuple(descriptor.keys[0]), (8,))
+        self.assertEqual(tuple(descriptor.keys[1]), (1,))
+
+        descriptor = calculator.compute(system, selected_keys=keys, sort_labels=True)
+        self.assertEqual(tuple(descriptor.keys[0]), (1,))
+        self.assertEqual(tuple(descriptor.keys[1]), (8,))
+
+    def test_selected_structures(self):
+        systems = [TestSystem(), TestSystem(), TestSystem()]
+        calculator = DummyCalculator(cutoff=3.2, delta=2, name="")
+
+        descriptor = calculator.compute(systems, selected_structures=[2, 0])
+        for block in descriptor.blocks():
+            structures = {tuple(sample)[0] for sample in block.samples}
+            self.assertEqual(structures, {0, 2})
+
+        with self.assertRaises(ValueError) as cm:
+            calculator.compute(systems, selected_structures=[-1])
+
+        self.assertEqual(
+            str(cm.exception),
+            "`selected_structures` must be a list of non-negative integers, "
+            "got -1 in the list",
+        )
+
+    def test_compute_metadata(self):
+        system = TestSystem()
+        calculator = DummyCalculator(cutoff=3.2, delta=2, name="")
+
+        metadata = calculator.compute_metadata(system, gradients=["positions"])
+        descriptor = calculator.compute(system, gradients=["positions"])
+
+        self.assertTrue(np.all(metadata.keys == descriptor.keys))
+        for i in range(len(descriptor.keys)):
+            block = descriptor.block(i)
+            metadata_block = metadata.block(i)
+            self.assertTrue(np.all(metadata_block.samples == block.samples))
+            self.assertTrue(np.all(metadata_block.properties == block.properties))
+
+            gradient = block.gradient("positions")
+            metadata_gradient = metadata_block.gradient("positions")
+            self.assertTrue(np.all(metadata_gradient.samples == gradient.samples))
+
 
 class TestComputePartialSamples(unittest.TestCase):
     def test_selection(self):
@@ -169,6 +305,32 @@ class TestComputePartialSamples(unittest.TestCase):
         self.assertEqual(O_block.values.shape, (1, 2))
         self.assertTrue(np.all(O_block.values[0] == (5, 5)))
 
+    def test_centers(self):
+        system = TestSystem()
+        calculator = DummyCalculator(cutoff=3.2, delta=2, name="")
+
+        # list of centers for each system
+        descriptor = calculator.compute(
+            system, use_native_system=False, selected_samples=[[0, 3, 1]]
+        )
+
+        H_block = descriptor.block(species_center=1)
+        self.assertEqual(H_block.values.shape, (2, 2))
+        self.assertTrue(np.all(H_block.values[0] == (2, 1)))
+        self.assertTrue(np.all(H_block.values[1] == (3, 3)))
+
+        O_block = descriptor.block(species_center=8)
+        self.assertEqual(O_block.values.shape, (1, 2))
+        self.assertTrue(np.all(O_block.values[0] == (5, 5)))
+
+        with self.assertRaises(ValueError) as cm:
+            calculator.compute(system, selected_samples=[[-1]])
+
+        self.assertEqual(
+            str(cm.exception),
+            "centers in `selected_samples` must be non-negative integers, got -1",
+        )
+
     def test_subset_variables(self):
         system = TestSystem()
         calculator = DummyCalculator(cutoff=3.2, delta=2, name="")
@@ -541,6 +703,57 @@ class TestComputeSelectedKeys(unittest.TestCase):
         )
 
 
+class TestComputeSelectedGradientAtoms(unittest.TestCase):
+    def test_selection(self):
+        system = TestSystem()
+        calculator = DummyCalculator(cutoff=3.2, delta=2, name="")
+
+        selected_gradient_atoms = Labels(
+            names=["structure", "atom"],
+            values=np.array([[0, 1]], dtype=np.int32),
+        )
+        descriptor = calculator.compute(
+            system,
+            use_native_system=False,
+            gradients=["positions"],
+            selected_gradient_atoms=selected_gradient_atoms,
+        )
+
+        H_block = descriptor.block(species_center=1)
+        gradient = H_block.gradient("positions")
+        self.assertEqual(gradient.values.shape, (2, 3, 2))
+
+        self.assertEqual(len(gradient.samples), 2)
+        self.assertEqual(tuple(gradient.samples[0]), (0, 0, 1))
+        self.assertEqual(tuple(gradient.samples[1]), (1, 0, 1))
+
+        for i in range(gradient.values.shape[0]):
+            self.assertTrue(np.all(gradient.values[i, 0, :] == (0, 1)))
+
+    def test_errors(self):
+        system = TestSystem()
+        calculator = DummyCalculator(cutoff=3.2, delta=2, name="")
+
+        selected_gradient_atoms = Labels(
+            names=["structure", "center"],
+            values=np.array([[0, 1]], dtype=np.int32),
+        )
+
+        with self.assertRaises(RascalError) as cm:
+            calculator.compute(
+                system,
+                use_native_system=False,
+                gradients=["positions"],
+                selected_gradient_atoms=selected_gradient_atoms,
+            )
+
+        self.assertEqual(
+            str(cm.exception),
+            "invalid parameter: expected [structure, atom] names for the "
+            "selected gradient atoms, got [structure, center]",
+        )
+
+
 class TestSortedDistances(unittest.TestCase):
     def test_name(self):
         calculator = SortedDistances(
@@ -22,5 +22,14 @@ class TestCMakePrefixPath(unittest.TestCase):
         )
 
 
+class TestMaxThreads(unittest.TestCase):
+    def test_set_max_threads(self):
+        rascaline.set_max_threads(2)
+        rascaline.set_max_threads(None)
+
+        with self.assertRaises(ValueError):
+            rascaline.set_max_threads(0)
+
+
 if __name__ == "__main__":
     unittest.main()
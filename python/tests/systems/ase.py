@@ -127,15 +127,9 @@ class TestAseSystemErrors(unittest.TestCase):
         atoms.cell = [[10, 0, 0], [0, 10, 0], [0, 0, 10]]
         atoms.pbc = [True, True, False]
 
-        with self.assertRaises(Exception) as cm:
-            _ = AseSystem(atoms)
-
-        message = (
-            "different periodic boundary conditions on different axis "
-            "are not supported"
-        )
-
-        self.assertEqual(cm.exception.args[0], message)
+        system = AseSystem(atoms)
+        self.assertEqual(system.periodic(), [True, True, False])
+        self.assertTrue(np.all(system.cell() == np.array(atoms.cell)))
 
     def test_no_pbc_cell(self):
         atoms = ase.Atoms("C", positions=[(0, 0, 0)])
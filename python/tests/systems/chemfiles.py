@@ -26,6 +26,7 @@ class TestChemfilesSystem(unittest.TestCase):
 
         self.assertEqual(system.size(), 4)
         self.assertTrue(np.all(system.species() == [6, 120, 121, 30]))
+        self.assertEqual(system.species().dtype, np.int32)
         positions = [
             (0, 0, 0),
             (0, 1, 0),
@@ -34,6 +35,19 @@ class TestChemfilesSystem(unittest.TestCase):
         ]
         self.assertTrue(np.all(system.positions() == positions))
 
+    def test_bonds(self):
+        frame = chemfiles.Frame()
+        frame.add_atom(chemfiles.Atom("O"), (0, 0, 0))
+        frame.add_atom(chemfiles.Atom("H"), (0, 0.75, -0.59))
+        frame.add_atom(chemfiles.Atom("H"), (0, -0.75, -0.59))
+
+        system = ChemfilesSystem(frame)
+        self.assertEqual(len(system.bonds()), 0)
+
+        frame.add_bond(0, 1)
+        frame.add_bond(0, 2)
+        self.assertEqual(system.bonds().tolist(), [[0, 1], [0, 2]])
+
     def test_cell(self):
         frame = chemfiles.Frame()
 
@@ -135,8 +135,29 @@ typedef struct rascal_pair_t {
    * cell as required by periodic boundary conditions.
    */
   double vector[3];
+  /**
+   * number of unit cell vectors to add to the position of the second atom
+   * to get the periodic image used in this pair. This should be set to
+   * zero for systems without periodic boundary conditions.
+   */
+  int32_t cell_shift[3];
 } rascal_pair_t;
 
+/**
+ * Owner of a ghost atom, i.e. a copy of an atom owned by another domain in a
+ * domain-decomposed simulation
+ */
+typedef struct rascal_ghost_atom_t {
+  /**
+   * index of the domain (e.g. the MPI rank) owning the original atom
+   */
+  uintptr_t rank;
+  /**
+   * index of the original atom in the local atoms of the owning domain
+   */
+  uintptr_t local_index;
+} rascal_ghost_atom_t;
+
 /**
  * A `rascal_system_t` deals with the storage of atoms and related information,
  * as well as the computation of neighbor lists.
@@ -223,14 +244,123 @@ typedef struct rascal_system_t {
    * `pairs_containing(j)`.
    */
   rascal_status_t (*pairs_containing)(const void *user_data, uintptr_t center, const struct rascal_pair_t **pairs, uintptr_t *count);
+  /**
+   * This function should set `*widths` to a pointer to the first element
+   * of a contiguous array containing the width of the gaussian atomic
+   * density for each atom in the system, or to `NULL` if the system does
+   * not define per-atom widths. The array should contain
+   * `rascal_system_t::size()` strictly positive elements.
+   *
+   * This function pointer is optional, and can be set to `NULL` if the
+   * system does not define per-atom widths.
+   */
+  rascal_status_t (*atomic_gaussian_width)(const void *user_data, const double **widths);
+  /**
+   * This function should write the periodic boundary conditions of the
+   * system along each of the three cell vectors to `periodic`, which is
+   * an array of 3 booleans.
+   *
+   * This function pointer is optional, and can be set to `NULL` if the
+   * system is periodic along all directions (or not periodic at all, if the
+   * cell matrix is zero).
+   */
+  rascal_status_t (*periodic)(const void *user_data, bool *periodic);
+  /**
+   * This function should set `*local_size` to the number of local atoms in
+   * the system. Local atoms must come first, and all atoms with an index
+   * larger than or equal to `local_size` are ghost atoms: they are used as
+   * neighbors of the local atoms, but never as centers.
+   *
+   * This function pointer is optional, and can be set to `NULL` if the
+   * system does not contain ghost atoms.
+   */
+  rascal_status_t (*local_size)(const void *user_data, uintptr_t *local_size);
+  /**
+   * This function should set `*ghosts` to a pointer to the first element of
+   * a contiguous array containing the owner of each ghost atom in the
+   * system, or to `NULL` if the owners are not known. The array should
+   * contain `rascal_system_t::size() - rascal_system_t::local_size()`
+   * elements.
+   *
+   * This function pointer is optional, and can be set to `NULL` if the
+   * system does not contain ghost atoms.
+   */
+  rascal_status_t (*ghost_atoms)(const void *user_data, const struct rascal_ghost_atom_t **ghosts);
+  /**
+   * This function should set `*charges` to a pointer to the first element
+   * of a contiguous array containing the charge of each atom in the
+   * system, or to `NULL` if the system does not define charges. The array
+   * should contain `rascal_system_t::size()` elements.
+   *
+   * This function pointer is optional, and can be set to `NULL` if the
+   * system does not define charges.
+   */
+  rascal_status_t (*charges)(const void *user_data, const double **charges);
+  /**
+   * This function should set `*masses` to a pointer to the first element
+   * of a contiguous array containing the mass of each atom in the system,
+   * or to `NULL` if the system does not define masses. The array should
+   * contain `rascal_system_t::size()` strictly positive elements.
+   *
+   * This function pointer is optional, and can be set to `NULL` if the
+   * system does not define masses.
+   */
+  rascal_status_t (*masses)(const void *user_data, const double **masses);
+  /**
+   * This function should set `*velocities` to a pointer to the first
+   * element of a contiguous array containing the velocity of each atom in
+   * the system, or to `NULL` if the system does not define velocities. The
+   * array should contain `3 x rascal_system_t::size()` elements.
+   *
+   * This function pointer is optional, and can be set to `NULL` if the
+   * system does not define velocities.
+   */
+  rascal_status_t (*velocities)(const void *user_data, const double **velocities);
+  /**
+   * This function should set `*data` to a pointer to the first element of
+   * a contiguous array containing the values of the per-atom data with the
+   * given `name` (a NULL-terminated string), or to `NULL` if the system
+   * does not define such data. The array should contain
+   * `rascal_system_t::size()` elements.
+   *
+   * When `atomic_gaussian_width`, `charges` or `masses` are `NULL` (or
+   * give a `NULL` pointer), the per-atom data with the same name is used
+   * instead.
+   *
+   * This function pointer is optional, and can be set to `NULL` if the
+   * system does not define any per-atom data.
+   */
+  rascal_status_t (*get_atom_data)(const void *user_data, const char *name, const double **data);
+  /**
+   * This function should set `*centers` to a pointer to the first element
+   * of a contiguous array indicating which atoms should be used as centers
+   * by atom-centered calculators, or to `NULL` if all atoms should be used.
+   * The array should contain `rascal_system_t::size()` elements. Ghost
+   * atoms are never used as centers, regardless of this mask.
+   *
+   * This function pointer is optional, and can be set to `NULL` if all
+   * atoms should be used as centers.
+   */
+  rascal_status_t (*centers)(const void *user_data, const bool **centers);
+  /**
+   * This function should set `*bonds` to a pointer to the first element of
+   * a contiguous array containing the chemical bonds in this system, and
+   * `*count` to the number of bonds. The array should contain `2 x count`
+   * values, with the indexes of the two atoms in each bond. Each bond
+   * should only be included once.
+   *
+   * This function pointer is optional, and can be set to `NULL` if the
+   * system does not contain any bond.
+   */
+  rascal_status_t (*bonds)(const void *user_data, const uintptr_t **bonds, uintptr_t *count);
 } rascal_system_t;
 
 /**
  * Rules to select labels (either samples or properties) on which the user
  * wants to run a calculation
  *
- * To run the calculation for all possible labels, users should set both fields
- * to NULL.
+ * To run the calculation for all possible labels, users should set all the
+ * pointer fields to NULL.
  */
 typedef struct rascal_labels_selection_t {
   /**
@@ -256,6 +386,52 @@ typedef struct rascal_labels_selection_t {
    * full calculation.
    */
   const eqs_tensormap_t *predefined;
+  /**
+   * Select samples using, for each system, a list of the center atoms to
+   * include in the calculation. This array contains the indexes of the
+   * selected centers for all systems, one system after the other, and
+   * `centers_per_system` contains the number of selected centers in each
+   * system.
+   *
+   * This can only be used with samples containing both `"structure"` and
+   * `"center"`, such as the samples of atom-centered representations.
+   */
+  const uintptr_t *centers;
+  /**
+   * Number of selected centers in each system, set this to NULL to not
+   * select samples by centers. Systems after the end of this array do not
+   * have any selected center.
+   */
+  const uintptr_t *centers_per_system;
+  /**
+   * Size of the `centers_per_system` array
+   */
+  uintptr_t centers_systems_count;
+  /**
+   * Select a subset of labels in the same way as `subset`, using plain
+   * arrays instead of an `eqs_labels_t`. This array contains the names of
+   * the variables in the selection, as NULL-terminated strings. Set this to
+   * NULL to not use this selection.
+   *
+   * This is useful for callers which can not easily create
+   * `eqs_labels_t`, for example to select specific `(n1, n2, l)` properties
+   * from Fortran.
+   */
+  const char *const *subset_names;
+  /**
+   * Number of variables in the selection, i.e. size of the `subset_names`
+   * array
+   */
+  uintptr_t subset_size;
+  /**
+   * Values of the selected entries, as a row-major array of shape
+   * `(subset_count, subset_size)`
+   */
+  const int32_t *subset_values;
+  /**
+   * Number of selected entries
+   */
+  uintptr_t subset_count;
 } rascal_labels_selection_t;
 
 /**
@@ -266,7 +442,9 @@ typedef struct rascal_calculation_options_t {
    * @verbatim embed:rst:leading-asterisk
    * Array of NULL-terminated strings containing the gradients to compute.
    * If this field is `NULL` and `gradients_count` is 0, no gradients are
-   * computed.
+   * computed. Each kind of gradients is computed independently of the
+   * others, e.g. cell gradients can be requested without also computing
+   * positions gradients.
    *
    * The following gradients are available:
    *
@@ -305,6 +483,19 @@ typedef struct rascal_calculation_options_t {
    *             {\partial\epsilon}
    *         = -\frac{\partial \langle q \vert A \rangle}
    *                 {\partial \mathbf{h}} \cdot \mathbf{h}
+   *
+   * - ``"positions_hessian"``, for second derivatives of the representation
+   *   with respect to atomic positions, computed as
+   *
+   *   .. math::
+   *       \frac{\partial^2 \langle q \vert A_i \rangle}
+   *            {\partial \mathbf{r_j} \partial \mathbf{r_k}}
+   *
+   *   The samples of these gradients are ``["sample", "structure",
+   *   "atom_1", "atom_2"]``, and the first two components
+   *   (``"direction_1"`` and ``"direction_2"``) correspond to the cartesian
+   *   directions of atoms :math:`j` and :math:`k` respectively. Only some
+   *   calculators support these.
    * @endverbatim
    */
   const char *const *gradients;
@@ -330,10 +521,78 @@ typedef struct rascal_calculation_options_t {
    * `NULL` to use the default set of keys, as determined by the calculator.
    * Note that this default set of keys can depend on which systems we are
    * running the calculation on.
+   *
+   * The output contains exactly the selected keys, in the same order. Keys
+   * which do not appear in the current systems create empty blocks, unless
+   * `remove_empty_blocks` is set. Using the same selected keys for all
+   * batches of a dataset ensures that all the corresponding descriptors
+   * share the same block structure.
    */
   const eqs_labels_t *selected_keys;
+  /**
+   * Selection of atoms for which to compute gradients with respect to
+   * positions, as labels with `["structure", "atom"]` names. Set this
+   * parameter to `NULL` to compute gradients with respect to all atoms.
+   * Otherwise, positions gradients are only computed with respect to the
+   * atoms in this selection, which is useful when most of the atoms in the
+   * systems are frozen.
+   */
+  const eqs_labels_t *selected_gradient_atoms;
+  /**
+   * Displacement used to compute gradients with respect to positions with
+   * finite differences, for calculators which do not implement these
+   * gradients. Set this parameter to 0 to disable finite differences, in
+   * which case requesting `"positions"` gradients from such a calculator is
+   * an error.
+   */
+  double finite_differences_displacement;
+  /**
+   * Maximal number of neighbors around a single atom. If any atom has more
+   * neighbors within the cutoff of the calculator, the calculation stops
+   * with an error before allocating memory for the output. Set this
+   * parameter to 0 to disable this check.
+   */
+  uintptr_t max_neighbors;
+  /**
+   * Remove the blocks without any sample from the output. Such blocks are
+   * typically created for pairs of species which are present in the
+   * systems but never within the cutoff of one another. If all the blocks
+   * are empty, they are all kept.
+   */
+  bool remove_empty_blocks;
+  /**
+   * Indexes of the systems on which to run the calculation. Set this
+   * parameter to `NULL` to use all the systems. Otherwise, the other
+   * systems are ignored, but the `"structure"` variable in the samples
+   * still refers to the position of each system in the full list.
+   */
+  const uintptr_t *selected_structures;
+  /**
+   * Size of the `selected_structures` array
+   */
+  uintptr_t selected_structures_count;
+  /**
+   * Sort the keys, and the samples inside each block, in lexicographic
+   * order. The keys and samples created by the calculators in rascaline
+   * are already sorted, but user-provided selections are used in the order
+   * they are given.
+   */
+  bool sort_labels;
 } rascal_calculation_options_t;
 
+/**
+ * Callback function type used to evaluate a radial integral when generating
+ * splines with `rascal_generate_splines`.
+ *
+ * The first argument is the `user_data` pointer given to
+ * `rascal_generate_splines`, and the second one is the position at which the
+ * radial integral should be evaluated. The function should write the values of
+ * the radial integral in the third argument and the derivatives of the radial
+ * integral with respect to the position in the fourth argument. Both are
+ * row-major arrays of shape `(max_angular + 1) x max_radial`.
+ */
+typedef void (*rascal_spline_function_t)(void *user_data, double position, double *values, double *derivatives);
+
 #ifdef __cplusplus
 extern "C" {
 #endif // __cplusplus
@@ -396,6 +655,161 @@ rascal_status_t rascal_basic_systems_read(const char *path,
  */
 rascal_status_t rascal_basic_systems_free(struct rascal_system_t *systems, uintptr_t count);
 
+/**
+ * Update the positions of the atoms in a system created by
+ * `rascal_basic_systems_read`, for example between two steps of a molecular
+ * dynamics simulation.
+ *
+ * The positions are updated in place, and the neighbor list of this system is
+ * invalidated. It will be re-computed on the next calculation.
+ *
+ * This function is only valid to call with one of the systems obtained from
+ * `rascal_basic_systems_read`. Any other use will probably result in
+ * segmentation faults.
+ *
+ * @param system pointer to a single system from `rascal_basic_systems_read`
+ * @param positions pointer to an array of `3 x count` values containing the
+ *                  new cartesian positions of all the atoms
+ * @param count number of atoms in the system
+ *
+ * @returns The status code of this operation. If the status is not
+ *          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+ *          error message.
+ */
+rascal_status_t rascal_basic_systems_set_positions(struct rascal_system_t *system,
+                                                   const double *positions,
+                                                   uintptr_t count);
+
+/**
+ * Replace the bonds of a `system` coming from `rascal_basic_systems_read` with
+ * the given `bonds`.
+ *
+ * @param system pointer to a single system from `rascal_basic_systems_read`
+ * @param bonds pointer to an array of `2 x count` values containing the
+ *              indexes of the atoms in each bond
+ * @param count number of bonds
+ *
+ * @returns The status code of this operation. If the status is not
+ *          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+ *          error message.
+ */
+rascal_status_t rascal_basic_systems_set_bonds(struct rascal_system_t *system,
+                                               const uintptr_t *bonds,
+                                               uintptr_t count);
+
+/**
+ * Check that the given `system` is valid: all the per-atom data must contain
+ * one value per atom, all positions must be finite, the unit cell must have a
+ * positive volume (and right-handed cell vectors), and no two atoms should
+ * overlap.
+ *
+ * This can be used to get clearer error messages than the NaN values produced
+ * by calculators when running on invalid systems.
+ *
+ * @param system pointer to the system to validate
+ *
+ * @returns The status code of this operation. If the system is invalid, this
+ *          returns `RASCAL_INVALID_PARAMETER_ERROR`, and you can use
+ *          `rascal_last_error()` to get the full error message.
+ */
+rascal_status_t rascal_system_validate(struct rascal_system_t *system);
+
+/**
+ * Get the lengths and angles of the unit cell defined by the given `cell`
+ * matrix.
+ *
+ * @param cell the 9 values of the cell matrix, with the same convention as
+ *             `rascal_system_t.cell`
+ * @param lengths `lengths` will be filled with the three lengths of the cell
+ *                (a, b, c)
+ * @param angles `angles` will be filled with the three angles of the cell
+ *               (α, β, γ) in degrees
+ *
+ * @returns The status code of this operation. If the status is not
+ *          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+ *          error message.
+ */
+rascal_status_t rascal_cell_parameters(const double *cell,
+                                       double *lengths,
+                                       double *angles);
+
+/**
+ * Convert the `count` cartesian `vectors` to fractional coordinates in the
+ * given `cell`. The conversion happens in place.
+ *
+ * @param cell the 9 values of the cell matrix, with the same convention as
+ *             `rascal_system_t.cell`. This must not be an infinite cell.
+ * @param vectors array of `3 x count` values containing the vectors to convert
+ * @param count number of vectors to convert
+ *
+ * @returns The status code of this operation. If the status is not
+ *          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+ *          error message.
+ */
+rascal_status_t rascal_cell_fractional(const double *cell,
+                                       double *vectors,
+                                       uintptr_t count);
+
+/**
+ * Convert the `count` fractional `vectors` to cartesian coordinates in the
+ * given `cell`. The conversion happens in place.
+ *
+ * @param cell the 9 values of the cell matrix, with the same convention as
+ *             `rascal_system_t.cell`
+ * @param vectors array of `3 x count` values containing the vectors to convert
+ * @param count number of vectors to convert
+ *
+ * @returns The status code of this operation. If the status is not
+ *          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+ *          error message.
+ */
+rascal_status_t rascal_cell_cartesian(const double *cell,
+                                      double *vectors,
+                                      uintptr_t count);
+
+/**
+ * Wrap the `count` `positions` inside the given `cell`, along the periodic
+ * directions. The positions are modified in place.
+ *
+ * @param cell the 9 values of the cell matrix, with the same convention as
+ *             `rascal_system_t.cell`
+ * @param periodic periodic boundary conditions along each of the cell
+ *                 vectors, or NULL if the cell is periodic in all directions
+ * @param positions array of `3 x count` values containing the positions to
+ *                  wrap
+ * @param count number of positions to wrap
+ *
+ * @returns The status code of this operation. If the status is not
+ *          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+ *          error message.
+ */
+rascal_status_t rascal_cell_wrap(const double *cell,
+                                 const bool *periodic,
+                                 double *positions,
+                                 uintptr_t count);
+
+/**
+ * Get the `distance` between the `first` and `second` points in the given
+ * `cell`, following the minimum image convention.
+ *
+ * @param cell the 9 values of the cell matrix, with the same convention as
+ *             `rascal_system_t.cell`
+ * @param periodic periodic boundary conditions along each of the cell
+ *                 vectors, or NULL if the cell is periodic in all directions
+ * @param first cartesian coordinates of the first point
+ * @param second cartesian coordinates of the second point
+ * @param distance `*distance` will be set to the distance between the points
+ *
+ * @returns The status code of this operation. If the status is not
+ *          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+ *          error message.
+ */
+rascal_status_t rascal_cell_distance(const double *cell,
+                                     const bool *periodic,
+                                     const double *first,
+                                     const double *second,
+                                     double *distance);
+
 /**
  * Create a new calculator with the given `name` and `parameters`.
  *
@@ -499,6 +913,36 @@ rascal_status_t rascal_calculator_compute(struct rascal_calculator_t *calculator
                                           uintptr_t systems_count,
                                           struct rascal_calculation_options_t options);
 
+/**
+ * Get the metadata (keys, samples, components and properties) of the
+ * representation `rascal_calculator_compute` would produce for the given
+ * list of `systems`, without computing the values.
+ *
+ * The blocks in the resulting `eqs_tensormap_t` contain empty arrays, which
+ * only store the shape of the data. This can be used to inspect the
+ * descriptor, or check the effect of the different selections in `options`,
+ * before running the full calculation.
+ *
+ * This function allocates a new `eqs_tensormap_t` in `*descriptor`, which
+ * memory needs to be released by the user with `eqs_tensormap_free`.
+ *
+ * @param calculator pointer to an existing calculator
+ * @param descriptor pointer to an `eqs_tensormap_t *` that will be allocated
+ *                   by this function
+ * @param systems pointer to an array of systems implementation
+ * @param systems_count number of systems in `systems`
+ * @param options options for this calculation
+ *
+ * @returns The status code of this operation. If the status is not
+ *          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+ *          error message.
+ */
+rascal_status_t rascal_calculator_compute_metadata(struct rascal_calculator_t *calculator,
+                                                   eqs_tensormap_t **descriptor,
+                                                   struct rascal_system_t *systems,
+                                                   uintptr_t systems_count,
+                                                   struct rascal_calculation_options_t options);
+
 /**
  * Clear all collected profiling data
  *
@@ -548,6 +992,57 @@ rascal_status_t rascal_profiling_enable(bool enabled);
  */
 rascal_status_t rascal_profiling_get(const char *format, char *buffer, uintptr_t bufflen);
 
+/**
+ * Set the maximal number of threads used by all calculators, or remove the
+ * limit by using `max_threads = 0`.
+ *
+ * When no limit is set with this function, the `RASCALINE_NUM_THREADS`
+ * environment variable is used if it is set, and all available threads are
+ * used otherwise. Limiting the number of threads is useful when calling
+ * rascaline from code which is already running in parallel.
+ *
+ * @param max_threads maximal number of threads, or 0 to remove the limit
+ *
+ * @returns The status code of this operation. If the status is not
+ *          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+ *          error message.
+ */
+rascal_status_t rascal_set_max_threads(uintptr_t max_threads);
+
+/**
+ * Generate the spline points for a tabulated radial integral from the given
+ * `function`, and store them as JSON in the given `buffer`.
+ *
+ * The JSON data contains a list of spline points, which can be used directly
+ * in the `"TabulatedRadialIntegral": {"points": ...}` radial basis of the SOAP
+ * calculators. Points are added to the spline until either the mean absolute
+ * or the mean relative error of the spline gets below `accuracy`.
+ *
+ * @param function callback evaluating the radial integral and its derivative
+ * @param user_data arbitrary pointer that will be passed to `function`
+ * @param max_radial number of radial basis functions
+ * @param max_angular maximal angular channel
+ * @param cutoff spherical cutoff of the radial integral. The spline covers
+ *               positions between 0 and `cutoff`.
+ * @param accuracy requested accuracy for the spline
+ * @param buffer pre-allocated buffer in which the JSON spline points will be
+ *               written. If the buffer is too small, this function will return
+ *               `RASCAL_BUFFER_SIZE_ERROR`
+ * @param bufflen size of the `buffer`
+ *
+ * @returns The status code of this operation. If the status is not
+ *          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+ *          error message.
+ */
+rascal_status_t rascal_generate_splines(rascal_spline_function_t function,
+                                        void *user_data,
+                                        uintptr_t max_radial,
+                                        uintptr_t max_angular,
+                                        double cutoff,
+                                        double accuracy,
+                                        char *buffer,
+                                        uintptr_t bufflen);
+
 #ifdef __cplusplus
 } // extern "C"
 #endif // __cplusplus
@@ -13,6 +13,7 @@
 #include <utility>
 #include <stdexcept>
 #include <exception>
+#include <functional>
 #include <unordered_map>
 
 #include "equistore.h"
@@ -216,6 +217,108 @@ public:
     /// `System::pairs_containing(j)`.
     virtual const std::vector<rascal_pair_t>& pairs_containing(uintptr_t center) const = 0;
 
+    /// Get a pointer to the first element of a contiguous array containing the
+    /// width of the gaussian atomic density for each atom in this system, or
+    /// `nullptr` if this system does not define per-atom widths. The array
+    /// should contain `System::size()` strictly positive elements.
+    ///
+    /// The default implementation returns `nullptr`.
+    virtual const double* atomic_gaussian_width() const {
+        return nullptr;
+    }
+
+    /// Get the periodic boundary conditions of this system along each of the
+    /// three cell vectors. This is ignored if the cell matrix is zero.
+    ///
+    /// The default implementation returns `{true, true, true}`.
+    virtual std::array<bool, 3> periodic() const {
+        return {true, true, true};
+    }
+
+    /// Get the number of local atoms in this system. Local atoms must come
+    /// first, and all atoms with an index larger than or equal to
+    /// `local_size()` are ghost atoms, which are used as neighbors of the local
+    /// atoms but never as centers.
+    ///
+    /// The default implementation returns `System::size()`, i.e. there are no
+    /// ghost atoms.
+    virtual uintptr_t local_size() const {
+        return this->size();
+    }
+
+    /// Get a pointer to the first element of a contiguous array containing
+    /// the owner of each ghost atom in this system, or `nullptr` if the owners
+    /// are not known. The array should contain `System::size() -
+    /// System::local_size()` elements.
+    ///
+    /// The default implementation returns `nullptr`.
+    virtual const rascal_ghost_atom_t* ghost_atoms() const {
+        return nullptr;
+    }
+
+    /// Get a pointer to the first element of a contiguous array containing the
+    /// charge of each atom in this system, or `nullptr` if this system does not
+    /// define charges. The array should contain `System::size()` elements.
+    ///
+    /// The default implementation returns `nullptr`.
+    virtual const double* charges() const {
+        return nullptr;
+    }
+
+    /// Get a pointer to the first element of a contiguous array containing the
+    /// mass of each atom in this system, or `nullptr` if this system does not
+    /// define masses. The array should contain `System::size()` strictly
+    /// positive elements.
+    ///
+    /// The default implementation returns `nullptr`.
+    virtual const double* masses() const {
+        return nullptr;
+    }
+
+    /// Get a pointer to the first element of a contiguous array containing the
+    /// velocity of each atom in this system, or `nullptr` if this system does
+    /// not define velocities. The array should contain `3 x System::size()`
+    /// elements.
+    ///
+    /// The default implementation returns `nullptr`.
+    virtual const double* velocities() const {
+        return nullptr;
+    }
+
+    /// Get a pointer to the first element of a contiguous array containing the
+    /// values of the per-atom data with the given `name` for all atoms in this
+    /// system, or `nullptr` if this system does not define such data. The array
+    /// should contain `System::size()` elements.
+    ///
+    /// The `"atomic_gaussian_width"`, `"charges"` and `"masses"` data is used
+    /// when the corresponding function returns `nullptr`.
+    ///
+    /// The default implementation returns `nullptr`.
+    virtual const double* atom_data(const std::string& name) const {
+        (void)name;
+        return nullptr;
+    }
+
+    /// Get a pointer to the first element of a contiguous array indicating
+    /// which atoms in this system should be used as centers by atom-centered
+    /// calculators, or `nullptr` if all atoms should be used. The array should
+    /// contain `System::size()` elements. Ghost atoms are never used as
+    /// centers, regardless of this mask.
+    ///
+    /// The default implementation returns `nullptr`.
+    virtual const bool* centers() const {
+        return nullptr;
+    }
+
+    /// Get the list of chemical bonds in this system, as pairs of atomic
+    /// indexes. Each bond should only be included once.
+    ///
+    /// The default implementation returns an empty list.
+    virtual const std::vector<std::array<uintptr_t, 2>>& bonds() const {
+        static const auto NO_BONDS = std::vector<std::array<uintptr_t, 2>>();
+        return NO_BONDS;
+    }
+
     /// Convert a child instance of the `System` class to a `rascal_system_t` to
     /// be passed to the rascaline functions.
     ///
@@ -271,6 +374,69 @@ public:
                     *pairs = cpp_pairs.data();
                     *size = cpp_pairs.size();
                 );
+            },
+            // atomic_gaussian_width
+            [](const void* self, const double** widths) {
+                RASCAL_SYSTEM_CATCH_EXCEPTIONS(
+                    *widths = reinterpret_cast<const System*>(self)->atomic_gaussian_width();
+                );
+            },
+            // periodic
+            [](const void* self, bool* periodic) {
+                RASCAL_SYSTEM_CATCH_EXCEPTIONS(
+                    auto cpp_periodic = reinterpret_cast<const System*>(self)->periodic();
+                    std::memcpy(periodic, cpp_periodic.data(), 3 * sizeof(bool));
+                );
+            },
+            // local_size
+            [](const void* self, uintptr_t* local_size) {
+                RASCAL_SYSTEM_CATCH_EXCEPTIONS(
+                    *local_size = reinterpret_cast<const System*>(self)->local_size();
+                );
+            },
+            // ghost_atoms
+            [](const void* self, const rascal_ghost_atom_t** ghosts) {
+                RASCAL_SYSTEM_CATCH_EXCEPTIONS(
+                    *ghosts = reinterpret_cast<const System*>(self)->ghost_atoms();
+                );
+            },
+            // charges
+            [](const void* self, const double** charges) {
+                RASCAL_SYSTEM_CATCH_EXCEPTIONS(
+                    *charges = reinterpret_cast<const System*>(self)->charges();
+                );
+            },
+            // masses
+            [](const void* self, const double** masses) {
+                RASCAL_SYSTEM_CATCH_EXCEPTIONS(
+                    *masses = reinterpret_cast<const System*>(self)->masses();
+                );
+            },
+            // velocities
+            [](const void* self, const double** velocities) {
+                RASCAL_SYSTEM_CATCH_EXCEPTIONS(
+                    *velocities = reinterpret_cast<const System*>(self)->velocities();
+                );
+            },
+            // get_atom_data
+            [](const void* self, const char* name, const double** data) {
+                RASCAL_SYSTEM_CATCH_EXCEPTIONS(
+                    *data = reinterpret_cast<const System*>(self)->atom_data(std::string(name));
+                );
+            },
+            // centers
+            [](const void* self, const bool** centers) {
+                RASCAL_SYSTEM_CATCH_EXCEPTIONS(
+                    *centers = reinterpret_cast<const System*>(self)->centers();
+                );
+            },
+            // bonds
+            [](const void* self, const uintptr_t** bonds, uintptr_t* count) {
+                RASCAL_SYSTEM_CATCH_EXCEPTIONS(
+                    const auto& cpp_bonds = reinterpret_cast<const System*>(self)->bonds();
+                    *bonds = reinterpret_cast<const uintptr_t*>(cpp_bonds.data());
+                    *count = cpp_bonds.size();
+                );
             }
         };
     }
@@ -334,6 +500,40 @@ public:
         return count_;
     }
 
+    /// Update the positions of all atoms in the system at index `system`. The
+    /// `positions` should contain 3 values for each atom in this system.
+    ///
+    /// @throws RascalineError if `system` is out of bounds, or if the number of
+    ///         positions does not match the number of atoms
+    void set_positions(uintptr_t system, const std::vector<double>& positions) {
+        if (system >= count_) {
+            throw RascalineError("system index out of bounds in BasicSystems::set_positions");
+        }
+
+        if (positions.size() % 3 != 0) {
+            throw RascalineError("the number of positions values must be a multiple of 3");
+        }
+
+        details::check_status(rascal_basic_systems_set_positions(
+            &systems_[system], positions.data(), positions.size() / 3
+        ));
+    }
+
+    /// Replace the bonds in the system at index `system` with the given
+    /// `bonds`, as pairs of atomic indexes.
+    ///
+    /// @throws RascalineError if `system` is out of bounds, or if some bonds
+    ///         refer to atoms which are not part of the system
+    void set_bonds(uintptr_t system, const std::vector<std::array<uintptr_t, 2>>& bonds) {
+        if (system >= count_) {
+            throw RascalineError("system index out of bounds in BasicSystems::set_bonds");
+        }
+
+        details::check_status(rascal_basic_systems_set_bonds(
+            &systems_[system], reinterpret_cast<const uintptr_t*>(bonds.data()), bonds.size()
+        ));
+    }
+
 private:
     rascal_system_t* systems_ = nullptr;
     uintptr_t count_ = 0;
@@ -719,6 +919,67 @@ private:
     Profiler();
 };
 
+
+/// Set the maximal number of threads used by all calculators, or remove the
+/// limit by using `max_threads = 0`.
+///
+/// When no limit is set with this function, the `RASCALINE_NUM_THREADS`
+/// environment variable is used if it is set, and all available threads are
+/// used otherwise.
+///
+/// @param max_threads maximal number of threads, or 0 to remove the limit
+inline void set_max_threads(size_t max_threads) {
+    details::check_status(rascal_set_max_threads(max_threads));
+}
+
+
+/// Generate the spline points for a tabulated radial integral, returning them
+/// as a JSON string which can be used in the `"TabulatedRadialIntegral":
+/// {"points": ...}` radial basis of the SOAP calculators.
+///
+/// @param function function evaluating the radial integral at a given
+///                 position. It should fill the `values` and `derivatives`
+///                 row-major arrays of shape `(max_angular + 1) x max_radial`.
+/// @param max_radial number of radial basis functions
+/// @param max_angular maximal angular channel
+/// @param cutoff spherical cutoff of the radial integral
+/// @param accuracy requested accuracy for the spline
+/// @returns the spline points, as a JSON string
+inline std::string generate_splines(
+    std::function<void(double position, double* values, double* derivatives)> function,
+    size_t max_radial,
+    size_t max_angular,
+    double cutoff,
+    double accuracy = 1e-8
+) {
+    auto callback = [](void* user_data, double position, double* values, double* derivatives) {
+        auto& function = *static_cast<std::function<void(double, double*, double*)>*>(user_data);
+        function(position, values, derivatives);
+    };
+
+    auto buffer = std::vector<char>(64 * 1024, '\0');
+    while (true) {
+        auto status = rascal_generate_splines(
+            callback,
+            static_cast<void*>(&function),
+            max_radial,
+            max_angular,
+            cutoff,
+            accuracy,
+            &buffer[0],
+            buffer.size()
+        );
+
+        if (status != RASCAL_BUFFER_SIZE_ERROR) {
+            details::check_status(status);
+            return std::string(buffer.data());
+        }
+
+        // grow the buffer and retry
+        buffer.resize(buffer.size() * 2, '\0');
+    }
+}
+
 }
 
 #endif
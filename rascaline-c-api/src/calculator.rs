@@ -153,8 +153,8 @@ pub unsafe extern fn rascal_calculator_parameters(
 /// Rules to select labels (either samples or properties) on which the user
 /// wants to run a calculation
 ///
-/// To run the calculation for all possible labels, users should set both fields
-/// to NULL.
+/// To run the calculation for all possible labels, users should set all the
+/// pointer fields to NULL.
 #[repr(C)]
 #[derive(Debug)]
 pub struct rascal_labels_selection_t {
@@ -177,6 +177,38 @@ pub struct rascal_labels_selection_t {
     /// `eqs_tensormap_t` instance, which must have the same set of keys as the
     /// full calculation.
     predefined: *const eqs_tensormap_t,
+    /// Select samples using, for each system, a list of the center atoms to
+    /// include in the calculation. This array contains the indexes of the
+    /// selected centers for all systems, one system after the other, and
+    /// `centers_per_system` contains the number of selected centers in each
+    /// system.
+    ///
+    /// This can only be used with samples containing both `"structure"` and
+    /// `"center"`, such as the samples of atom-centered representations.
+    centers: *const usize,
+    /// Number of selected centers in each system, set this to NULL to not
+    /// select samples by centers. Systems after the end of this array do not
+    /// have any selected center.
+    centers_per_system: *const usize,
+    /// Size of the `centers_per_system` array
+    centers_systems_count: usize,
+    /// Select a subset of labels in the same way as `subset`, using plain
+    /// arrays instead of an `eqs_labels_t`. This array contains the names of
+    /// the variables in the selection, as NULL-terminated strings. Set this to
+    /// NULL to not use this selection.
+    ///
+    /// This is useful for callers which can not easily create
+    /// `eqs_labels_t`, for example to select specific `(n1, n2, l)` properties
+    /// from Fortran.
+    subset_names: *const *const c_char,
+    /// Number of variables in the selection, i.e. size of the `subset_names`
+    /// array
+    subset_size: usize,
+    /// Values of the selected entries, as a row-major array of shape
+    /// `(subset_count, subset_size)`
+    subset_values: *const i32,
+    /// Number of selected entries
+    subset_count: usize,
 }
 
 fn c_labels_to_rust(mut labels: eqs_labels_t) -> Result<eqs_labels_t, rascaline::Error> {
@@ -211,7 +243,71 @@ fn convert_labels_selection<'a>(
     selection: &'a rascal_labels_selection_t,
     labels: &'a mut Option<Labels>,
     predefined: &'a mut Option<TensorMap>,
+    centers: &'a mut Option<Vec<Vec<usize>>>,
 ) -> Result<LabelsSelection<'a>, rascaline::Error> {
+    if !selection.subset_names.is_null() {
+        if !selection.subset.is_null() || !selection.predefined.is_null() || !selection.centers_per_system.is_null() {
+            return Err(rascaline::Error::InvalidParameter(
+                "can not have both subset_names and global, predefined or centers non-NULL in rascal_labels_selection_t".into()
+            ));
+        }
+
+        if selection.subset_count != 0 && selection.subset_values.is_null() {
+            return Err(rascaline::Error::InvalidParameter(
+                "got invalid NULL pointer for subset_values in rascal_labels_selection_t".into()
+            ));
+        }
+
+        let raw_labels = eqs_labels_t {
+            internal_ptr_: std::ptr::null_mut(),
+            names: selection.subset_names,
+            values: selection.subset_values,
+            size: selection.subset_size,
+            count: selection.subset_count,
+        };
+
+        *labels = unsafe {
+            Some(Labels::from_raw(c_labels_to_rust(raw_labels)?))
+        };
+
+        return Ok(LabelsSelection::Subset(labels.as_ref().expect("just created it")));
+    }
+
+    if !selection.centers_per_system.is_null() {
+        if !selection.subset.is_null() || !selection.predefined.is_null() {
+            return Err(rascaline::Error::InvalidParameter(
+                "can not have both centers and global or predefined non-NULL in rascal_labels_selection_t".into()
+            ));
+        }
+
+        let centers_per_system = unsafe {
+            std::slice::from_raw_parts(selection.centers_per_system, selection.centers_systems_count)
+        };
+
+        let total: usize = centers_per_system.iter().sum();
+        if total != 0 && selection.centers.is_null() {
+            return Err(rascaline::Error::InvalidParameter(
+                "got invalid NULL pointer for centers in rascal_labels_selection_t".into()
+            ));
+        }
+
+        let all_centers: &[usize] = if total == 0 {
+            &[]
+        } else {
+            unsafe { std::slice::from_raw_parts(selection.centers, total) }
+        };
+
+        let mut start = 0;
+        let mut centers_by_system = Vec::with_capacity(centers_per_system.len());
+        for &count in centers_per_system {
+            centers_by_system.push(all_centers[start..start + count].to_vec());
+            start += count;
+        }
+        *centers = Some(centers_by_system);
+
+        return Ok(LabelsSelection::Centers(centers.as_ref().expect("just created it")));
+    }
+
     match (selection.subset.is_null(), selection.predefined.is_null()) {
         (true, true) => Ok(LabelsSelection::All),
         (false, true) => {
@@ -270,7 +366,9 @@ pub struct rascal_calculation_options_t {
     /// @verbatim embed:rst:leading-asterisk
     /// Array of NULL-terminated strings containing the gradients to compute.
     /// If this field is `NULL` and `gradients_count` is 0, no gradients are
-    /// computed.
+    /// computed. Each kind of gradients is computed independently of the
+    /// others, e.g. cell gradients can be requested without also computing
+    /// positions gradients.
     ///
     /// The following gradients are available:
     ///
@@ -309,6 +407,19 @@ pub struct rascal_calculation_options_t {
     ///             {\partial\epsilon}
     ///         = -\frac{\partial \langle q \vert A \rangle}
     ///                 {\partial \mathbf{h}} \cdot \mathbf{h}
+    ///
+    /// - ``"positions_hessian"``, for second derivatives of the representation
+    ///   with respect to atomic positions, computed as
+    ///
+    ///   .. math::
+    ///       \frac{\partial^2 \langle q \vert A_i \rangle}
+    ///            {\partial \mathbf{r_j} \partial \mathbf{r_k}}
+    ///
+    ///   The samples of these gradients are ``["sample", "structure",
+    ///   "atom_1", "atom_2"]``, and the first two components
+    ///   (``"direction_1"`` and ``"direction_2"``) correspond to the cartesian
+    ///   directions of atoms :math:`j` and :math:`k` respectively. Only some
+    ///   calculators support these.
     /// @endverbatim
     gradients: *const *const c_char,
     /// Size of the `gradients` array
@@ -324,7 +435,48 @@ pub struct rascal_calculation_options_t {
     /// `NULL` to use the default set of keys, as determined by the calculator.
     /// Note that this default set of keys can depend on which systems we are
     /// running the calculation on.
+    ///
+    /// The output contains exactly the selected keys, in the same order. Keys
+    /// which do not appear in the current systems create empty blocks, unless
+    /// `remove_empty_blocks` is set. Using the same selected keys for all
+    /// batches of a dataset ensures that all the corresponding descriptors
+    /// share the same block structure.
     selected_keys: *const eqs_labels_t,
+    /// Selection of atoms for which to compute gradients with respect to
+    /// positions, as labels with `["structure", "atom"]` names. Set this
+    /// parameter to `NULL` to compute gradients with respect to all atoms.
+    /// Otherwise, positions gradients are only computed with respect to the
+    /// atoms in this selection, which is useful when most of the atoms in the
+    /// systems are frozen.
+    selected_gradient_atoms: *const eqs_labels_t,
+    /// Displacement used to compute gradients with respect to positions with
+    /// finite differences, for calculators which do not implement these
+    /// gradients. Set this parameter to 0 to disable finite differences, in
+    /// which case requesting `"positions"` gradients from such a calculator is
+    /// an error.
+    finite_differences_displacement: f64,
+    /// Maximal number of neighbors around a single atom. If any atom has more
+    /// neighbors within the cutoff of the calculator, the calculation stops
+    /// with an error before allocating memory for the output. Set this
+    /// parameter to 0 to disable this check.
+    max_neighbors: usize,
+    /// Remove the blocks without any sample from the output. Such blocks are
+    /// typically created for pairs of species which are present in the
+    /// systems but never within the cutoff of one another. If all the blocks
+    /// are empty, they are all kept.
+    remove_empty_blocks: bool,
+    /// Indexes of the systems on which to run the calculation. Set this
+    /// parameter to `NULL` to use all the systems. Otherwise, the other
+    /// systems are ignored, but the `"structure"` variable in the samples
+    /// still refers to the position of each system in the full list.
+    selected_structures: *const usize,
+    /// Size of the `selected_structures` array
+    selected_structures_count: usize,
+    /// Sort the keys, and the samples inside each block, in lexicographic
+    /// order. The keys and samples created by the calculators in rascaline
+    /// are already sorted, but user-provided selections are used in the order
+    /// they are given.
+    sort_labels: bool,
 }
 
 #[allow(clippy::doc_markdown)]
@@ -366,42 +518,141 @@ pub unsafe extern fn rascal_calculator_compute(
             systems.push(Box::new(system) as Box<dyn System>);
         }
 
-        let c_gradients = std::slice::from_raw_parts(options.gradients, options.gradients_count);
-        let mut gradients = Vec::new();
-        for &parameter in c_gradients {
-            gradients.push(CStr::from_ptr(parameter).to_str()?);
-        }
+        let tensor = with_rust_options(&options, |rust_options| {
+            (*calculator).compute(&mut systems, rust_options)
+        })?;
+
+        *descriptor = TensorMap::into_raw(tensor);
+        Ok(())
+    })
+}
 
-        let mut selected_samples = None;
-        let mut predefined_samples = None;
-        let selected_samples = convert_labels_selection(
-            &options.selected_samples,
-            &mut selected_samples,
-            &mut predefined_samples
-        )?;
-
-        let mut selected_properties = None;
-        let mut predefined_properties = None;
-        let selected_properties = convert_labels_selection(
-            &options.selected_properties,
-            &mut selected_properties,
-            &mut predefined_properties
-        )?;
-
-        let mut selected_keys = None;
-        let selected_keys = key_selection(options.selected_keys, &mut selected_keys)?;
-
-        let rust_options = CalculationOptions {
-            gradients: &gradients,
-            use_native_system: options.use_native_system,
-            selected_samples,
-            selected_properties,
-            selected_keys,
+#[allow(clippy::doc_markdown)]
+/// Get the metadata (keys, samples, components and properties) of the
+/// representation `rascal_calculator_compute` would produce for the given
+/// list of `systems`, without computing the values.
+///
+/// The blocks in the resulting `eqs_tensormap_t` contain empty arrays, which
+/// only store the shape of the data. This can be used to inspect the
+/// descriptor, or check the effect of the different selections in `options`,
+/// before running the full calculation.
+///
+/// This function allocates a new `eqs_tensormap_t` in `*descriptor`, which
+/// memory needs to be released by the user with `eqs_tensormap_free`.
+///
+/// @param calculator pointer to an existing calculator
+/// @param descriptor pointer to an `eqs_tensormap_t *` that will be allocated
+///                   by this function
+/// @param systems pointer to an array of systems implementation
+/// @param systems_count number of systems in `systems`
+/// @param options options for this calculation
+///
+/// @returns The status code of this operation. If the status is not
+///          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+///          error message.
+#[no_mangle]
+pub unsafe extern fn rascal_calculator_compute_metadata(
+    calculator: *mut rascal_calculator_t,
+    descriptor: *mut *mut eqs_tensormap_t,
+    systems: *mut rascal_system_t,
+    systems_count: usize,
+    options: rascal_calculation_options_t,
+) -> rascal_status_t {
+    catch_unwind(move || {
+        check_pointers!(calculator, descriptor);
+
+        let mut systems = if systems_count == 0 {
+            Vec::new()
+        } else {
+            check_pointers!(systems);
+            let c_systems = std::slice::from_raw_parts_mut(systems, systems_count);
+            let mut rust_systems = Vec::with_capacity(c_systems.len());
+            for system in c_systems {
+                rust_systems.push(Box::new(system) as Box<dyn System>);
+            }
+            rust_systems
         };
 
-        let tensor = (*calculator).compute(&mut systems, rust_options)?;
+        let tensor = with_rust_options(&options, |rust_options| {
+            (*calculator).compute_metadata(&mut systems, rust_options)
+        })?;
 
         *descriptor = TensorMap::into_raw(tensor);
         Ok(())
     })
 }
+
+/// Convert the C calculation `options` to the Rust `CalculationOptions`, and
+/// call `function` with them.
+unsafe fn with_rust_options<T, F>(options: &rascal_calculation_options_t, function: F) -> Result<T, rascaline::Error>
+    where F: FnOnce(CalculationOptions) -> Result<T, rascaline::Error>
+{
+    let c_gradients = std::slice::from_raw_parts(options.gradients, options.gradients_count);
+    let mut gradients = Vec::new();
+    for &parameter in c_gradients {
+        gradients.push(CStr::from_ptr(parameter).to_str()?);
+    }
+
+    let mut selected_samples = None;
+    let mut predefined_samples = None;
+    let mut selected_centers = None;
+    let selected_samples = convert_labels_selection(
+        &options.selected_samples,
+        &mut selected_samples,
+        &mut predefined_samples,
+        &mut selected_centers,
+    )?;
+
+    let mut selected_properties = None;
+    let mut predefined_properties = None;
+    let mut properties_centers = None;
+    let selected_properties = convert_labels_selection(
+        &options.selected_properties,
+        &mut selected_properties,
+        &mut predefined_properties,
+        &mut properties_centers,
+    )?;
+
+    let mut selected_keys = None;
+    let selected_keys = key_selection(options.selected_keys, &mut selected_keys)?;
+
+    let mut selected_gradient_atoms = None;
+    let selected_gradient_atoms = key_selection(options.selected_gradient_atoms, &mut selected_gradient_atoms)?;
+
+    // 0 is used to disable finite differences from C
+    #[allow(clippy::float_cmp)]
+    let finite_differences_displacement = if options.finite_differences_displacement == 0.0 {
+        None
+    } else {
+        Some(options.finite_differences_displacement)
+    };
+
+    // 0 is used to disable the check on the number of neighbors from C
+    let max_neighbors = if options.max_neighbors == 0 {
+        None
+    } else {
+        Some(options.max_neighbors)
+    };
+
+    let selected_structures = if options.selected_structures.is_null() {
+        None
+    } else {
+        Some(std::slice::from_raw_parts(options.selected_structures, options.selected_structures_count))
+    };
+
+    let rust_options = CalculationOptions {
+        gradients: &gradients,
+        use_native_system: options.use_native_system,
+        selected_samples,
+        selected_properties,
+        selected_keys,
+        selected_gradient_atoms,
+        finite_differences_displacement,
+        max_neighbors,
+        remove_empty_blocks: options.remove_empty_blocks,
+        selected_structures,
+        sort_labels: options.sort_labels,
+    };
+
+    return function(rust_options);
+}
@@ -0,0 +1,202 @@
+use rascaline::types::{Vector3D, Matrix3};
+use rascaline::systems::UnitCell;
+use rascaline::Error;
+
+use super::{catch_unwind, rascal_status_t};
+
+/// Create a `UnitCell` from the 9 values in `cell` and the optional
+/// `periodic` flags, using the same conventions as `rascal_system_t.cell` and
+/// `rascal_system_t.periodic`.
+unsafe fn unit_cell(cell: *const f64, periodic: *const bool) -> Result<UnitCell, Error> {
+    let values = std::slice::from_raw_parts(cell, 9);
+    let matrix = Matrix3::new([
+        [values[0], values[1], values[2]],
+        [values[3], values[4], values[5]],
+        [values[6], values[7], values[8]],
+    ]);
+
+    if matrix == Matrix3::zero() {
+        return Ok(UnitCell::infinite());
+    }
+
+    if matrix.determinant() <= 1e-6 {
+        return Err(Error::InvalidParameter(
+            "the cell matrix must be invertible and have a positive determinant".into()
+        ));
+    }
+
+    let mut flags = [true; 3];
+    if !periodic.is_null() {
+        flags.copy_from_slice(std::slice::from_raw_parts(periodic, 3));
+    }
+
+    return Ok(UnitCell::from(matrix).with_periodicity(flags));
+}
+
+/// Get the lengths and angles of the unit cell defined by the given `cell`
+/// matrix.
+///
+/// @param cell the 9 values of the cell matrix, with the same convention as
+///             `rascal_system_t.cell`
+/// @param lengths `lengths` will be filled with the three lengths of the cell
+///                (a, b, c)
+/// @param angles `angles` will be filled with the three angles of the cell
+///               (α, β, γ) in degrees
+///
+/// @returns The status code of this operation. If the status is not
+///          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+///          error message.
+#[no_mangle]
+pub unsafe extern fn rascal_cell_parameters(
+    cell: *const f64,
+    lengths: *mut f64,
+    angles: *mut f64,
+) -> rascal_status_t {
+    catch_unwind(move || {
+        check_pointers!(cell, lengths, angles);
+        let cell = unit_cell(cell, std::ptr::null())?;
+
+        lengths.add(0).write(cell.a());
+        lengths.add(1).write(cell.b());
+        lengths.add(2).write(cell.c());
+
+        angles.add(0).write(cell.alpha());
+        angles.add(1).write(cell.beta());
+        angles.add(2).write(cell.gamma());
+
+        Ok(())
+    })
+}
+
+/// Convert the `count` cartesian `vectors` to fractional coordinates in the
+/// given `cell`. The conversion happens in place.
+///
+/// @param cell the 9 values of the cell matrix, with the same convention as
+///             `rascal_system_t.cell`. This must not be an infinite cell.
+/// @param vectors array of `3 x count` values containing the vectors to convert
+/// @param count number of vectors to convert
+///
+/// @returns The status code of this operation. If the status is not
+///          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+///          error message.
+#[no_mangle]
+pub unsafe extern fn rascal_cell_fractional(
+    cell: *const f64,
+    vectors: *mut f64,
+    count: usize,
+) -> rascal_status_t {
+    catch_unwind(move || {
+        check_pointers!(cell, vectors);
+        let cell = unit_cell(cell, std::ptr::null())?;
+        if cell.is_infinite() {
+            return Err(Error::InvalidParameter(
+                "can not compute fractional coordinates in an infinite cell".into()
+            ));
+        }
+
+        let vectors = std::slice::from_raw_parts_mut(vectors.cast::<Vector3D>(), count);
+        for vector in vectors {
+            *vector = cell.fractional(*vector);
+        }
+
+        Ok(())
+    })
+}
+
+/// Convert the `count` fractional `vectors` to cartesian coordinates in the
+/// given `cell`. The conversion happens in place.
+///
+/// @param cell the 9 values of the cell matrix, with the same convention as
+///             `rascal_system_t.cell`
+/// @param vectors array of `3 x count` values containing the vectors to convert
+/// @param count number of vectors to convert
+///
+/// @returns The status code of this operation. If the status is not
+///          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+///          error message.
+#[no_mangle]
+pub unsafe extern fn rascal_cell_cartesian(
+    cell: *const f64,
+    vectors: *mut f64,
+    count: usize,
+) -> rascal_status_t {
+    catch_unwind(move || {
+        check_pointers!(cell, vectors);
+        let cell = unit_cell(cell, std::ptr::null())?;
+
+        let vectors = std::slice::from_raw_parts_mut(vectors.cast::<Vector3D>(), count);
+        for vector in vectors {
+            *vector = cell.cartesian(*vector);
+        }
+
+        Ok(())
+    })
+}
+
+/// Wrap the `count` `positions` inside the given `cell`, along the periodic
+/// directions. The positions are modified in place.
+///
+/// @param cell the 9 values of the cell matrix, with the same convention as
+///             `rascal_system_t.cell`
+/// @param periodic periodic boundary conditions along each of the cell
+///                 vectors, or NULL if the cell is periodic in all directions
+/// @param positions array of `3 x count` values containing the positions to
+///                  wrap
+/// @param count number of positions to wrap
+///
+/// @returns The status code of this operation. If the status is not
+///          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+///          error message.
+#[no_mangle]
+pub unsafe extern fn rascal_cell_wrap(
+    cell: *const f64,
+    periodic: *const bool,
+    positions: *mut f64,
+    count: usize,
+) -> rascal_status_t {
+    catch_unwind(move || {
+        check_pointers!(cell, positions);
+        let cell = unit_cell(cell, periodic)?;
+
+        let positions = std::slice::from_raw_parts_mut(positions.cast::<Vector3D>(), count);
+        for position in positions {
+            *position = cell.wrap(*position);
+        }
+
+        Ok(())
+    })
+}
+
+/// Get the `distance` between the `first` and `second` points in the given
+/// `cell`, following the minimum image convention.
+///
+/// @param cell the 9 values of the cell matrix, with the same convention as
+///             `rascal_system_t.cell`
+/// @param periodic periodic boundary conditions along each of the cell
+///                 vectors, or NULL if the cell is periodic in all directions
+/// @param first cartesian coordinates of the first point
+/// @param second cartesian coordinates of the second point
+/// @param distance `*distance` will be set to the distance between the points
+///
+/// @returns The status code of this operation. If the status is not
+///          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+///          error message.
+#[no_mangle]
+pub unsafe extern fn rascal_cell_distance(
+    cell: *const f64,
+    periodic: *const bool,
+    first: *const f64,
+    second: *const f64,
+    distance: *mut f64,
+) -> rascal_status_t {
+    catch_unwind(move || {
+        check_pointers!(cell, first, second, distance);
+        let cell = unit_cell(cell, periodic)?;
+
+        let first = Vector3D::new(*first, *first.add(1), *first.add(2));
+        let second = Vector3D::new(*second, *second.add(1), *second.add(2));
+        *distance = cell.distance(first, second);
+
+        Ok(())
+    })
+}
@@ -20,6 +20,10 @@ pub use self::logging::{RASCAL_LOG_LEVEL_DEBUG, RASCAL_LOG_LEVEL_TRACE};
 pub use self::logging::{rascal_logging_callback_t, rascal_set_logging_callback};
 
 pub mod system;
+pub mod cell;
 pub mod calculator;
 
 pub mod profiling;
+pub mod threads;
+
+pub mod splines;
@@ -1,8 +1,6 @@
 use std::os::raw::c_char;
 use std::ffi::CStr;
 
-use rascaline::Error;
-
 use crate::{catch_unwind, rascal_status_t};
 use crate::utils::copy_str_to_c;
 
@@ -16,7 +14,7 @@ use crate::utils::copy_str_to_c;
 #[no_mangle]
 pub unsafe extern fn rascal_profiling_clear() -> rascal_status_t {
     catch_unwind(|| {
-        time_graph::clear_collected_data();
+        rascaline::profiling::clear();
         Ok(())
     })
 }
@@ -41,7 +39,7 @@ pub unsafe extern fn rascal_profiling_clear() -> rascal_status_t {
 #[no_mangle]
 pub unsafe extern fn rascal_profiling_enable(enabled: bool) -> rascal_status_t {
     catch_unwind(|| {
-        time_graph::enable_data_collection(enabled);
+        rascaline::profiling::enable(enabled);
         Ok(())
     })
 }
@@ -69,21 +67,7 @@ pub unsafe extern fn rascal_profiling_get(
     catch_unwind(|| {
         check_pointers!(format);
 
-        let data = match CStr::from_ptr(format).to_str()? {
-            "table" => {
-                time_graph::get_full_graph().as_table()
-            },
-            "short_table" => {
-                time_graph::get_full_graph().as_short_table()
-            },
-            "json" => {
-                time_graph::get_full_graph().as_json()
-            },
-            format => return Err(Error::InvalidParameter(format!(
-                "invalid data format in rascal_profiling_get: {}, expected 'table', 'short_table' or 'json'",
-                format
-            )))
-        };
+        let data = rascaline::profiling::report(CStr::from_ptr(format).to_str()?)?;
         copy_str_to_c(&data, buffer, bufflen)?;
 
         Ok(())
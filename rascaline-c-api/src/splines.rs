@@ -0,0 +1,81 @@
+use std::os::raw::{c_char, c_void};
+
+use rascaline::calculators::generate_splines;
+
+use crate::{catch_unwind, rascal_status_t};
+use crate::utils::copy_str_to_c;
+
+/// Callback function type used to evaluate a radial integral when generating
+/// splines with `rascal_generate_splines`.
+///
+/// The first argument is the `user_data` pointer given to
+/// `rascal_generate_splines`, and the second one is the position at which the
+/// radial integral should be evaluated. The function should write the values of
+/// the radial integral in the third argument and the derivatives of the radial
+/// integral with respect to the position in the fourth argument. Both are
+/// row-major arrays of shape `(max_angular + 1) x max_radial`.
+#[allow(non_camel_case_types)]
+pub type rascal_spline_function_t = Option<unsafe extern fn(
+    user_data: *mut c_void,
+    position: f64,
+    values: *mut f64,
+    derivatives: *mut f64,
+)>;
+
+/// Generate the spline points for a tabulated radial integral from the given
+/// `function`, and store them as JSON in the given `buffer`.
+///
+/// The JSON data contains a list of spline points, which can be used directly
+/// in the `"TabulatedRadialIntegral": {"points": ...}` radial basis of the SOAP
+/// calculators. Points are added to the spline until either the mean absolute
+/// or the mean relative error of the spline gets below `accuracy`.
+///
+/// @param function callback evaluating the radial integral and its derivative
+/// @param user_data arbitrary pointer that will be passed to `function`
+/// @param max_radial number of radial basis functions
+/// @param max_angular maximal angular channel
+/// @param cutoff spherical cutoff of the radial integral. The spline covers
+///               positions between 0 and `cutoff`.
+/// @param accuracy requested accuracy for the spline
+/// @param buffer pre-allocated buffer in which the JSON spline points will be
+///               written. If the buffer is too small, this function will return
+///               `RASCAL_BUFFER_SIZE_ERROR`
+/// @param bufflen size of the `buffer`
+///
+/// @returns The status code of this operation. If the status is not
+///          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+///          error message.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern fn rascal_generate_splines(
+    function: rascal_spline_function_t,
+    user_data: *mut c_void,
+    max_radial: usize,
+    max_angular: usize,
+    cutoff: f64,
+    accuracy: f64,
+    buffer: *mut c_char,
+    bufflen: usize,
+) -> rascal_status_t {
+    catch_unwind(|| {
+        check_pointers!(buffer);
+        let function = match function {
+            Some(function) => function,
+            None => {
+                return Err(rascaline::Error::InvalidParameter(
+                    "got invalid NULL pointer for function".into()
+                ));
+            }
+        };
+
+        let points = generate_splines(max_radial, max_angular, cutoff, accuracy, |position, mut values, mut derivatives| {
+            assert!(values.is_standard_layout() && derivatives.is_standard_layout());
+            function(user_data, position, values.as_mut_ptr(), derivatives.as_mut_ptr());
+        })?;
+
+        let json = serde_json::to_string(&points)?;
+        copy_str_to_c(&json, buffer, bufflen)?;
+
+        Ok(())
+    })
+}
@@ -62,7 +62,7 @@ impl From<Error> for rascal_status_t {
             *message.borrow_mut() = CString::new(format!("{}", error)).expect("error message contains a null byte");
         });
         match error {
-            Error::InvalidParameter(_) => rascal_status_t(RASCAL_INVALID_PARAMETER_ERROR),
+            Error::InvalidParameter(_) | Error::InvalidSystem(_) => rascal_status_t(RASCAL_INVALID_PARAMETER_ERROR),
             Error::Json(_) => rascal_status_t(RASCAL_JSON_ERROR),
             Error::Utf8(_) => rascal_status_t(RASCAL_UTF8_ERROR),
             Error::Chemfiles(_) => rascal_status_t(RASCAL_CHEMFILES_ERROR),
@@ -1,8 +1,8 @@
 use std::os::raw::{c_char, c_void};
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 
 use rascaline::types::{Vector3D, Matrix3};
-use rascaline::systems::{SimpleSystem, Pair, UnitCell};
+use rascaline::systems::{SimpleSystem, Pair, UnitCell, GhostAtom};
 use rascaline::{Error, System};
 
 use crate::RASCAL_SYSTEM_ERROR;
@@ -21,6 +21,20 @@ pub struct rascal_pair_t {
     /// vector from the first atom to the second atom, wrapped inside the unit
     /// cell as required by periodic boundary conditions.
     pub vector: [f64; 3],
+    /// number of unit cell vectors to add to the position of the second atom
+    /// to get the periodic image used in this pair. This should be set to
+    /// zero for systems without periodic boundary conditions.
+    pub cell_shift: [i32; 3],
+}
+
+/// Owner of a ghost atom, i.e. a copy of an atom owned by another domain in a
+/// domain-decomposed simulation
+#[repr(C)]
+pub struct rascal_ghost_atom_t {
+    /// index of the domain (e.g. the MPI rank) owning the original atom
+    pub rank: usize,
+    /// index of the original atom in the local atoms of the owning domain
+    pub local_index: usize,
 }
 
 /// A `rascal_system_t` deals with the storage of atoms and related information,
@@ -99,6 +113,95 @@ pub struct rascal_system_t {
     /// included both in the return of `pairs_containing(i)` and
     /// `pairs_containing(j)`.
     pairs_containing: Option<unsafe extern fn(user_data: *const c_void, center: usize, pairs: *mut *const rascal_pair_t, count: *mut usize) -> rascal_status_t>,
+    /// This function should set `*widths` to a pointer to the first element
+    /// of a contiguous array containing the width of the gaussian atomic
+    /// density for each atom in the system, or to `NULL` if the system does
+    /// not define per-atom widths. The array should contain
+    /// `rascal_system_t::size()` strictly positive elements.
+    ///
+    /// This function pointer is optional, and can be set to `NULL` if the
+    /// system does not define per-atom widths.
+    atomic_gaussian_width: Option<unsafe extern fn(user_data: *const c_void, widths: *mut *const f64) -> rascal_status_t>,
+    /// This function should write the periodic boundary conditions of the
+    /// system along each of the three cell vectors to `periodic`, which is
+    /// an array of 3 booleans.
+    ///
+    /// This function pointer is optional, and can be set to `NULL` if the
+    /// system is periodic along all directions (or not periodic at all, if the
+    /// cell matrix is zero).
+    periodic: Option<unsafe extern fn(user_data: *const c_void, periodic: *mut bool) -> rascal_status_t>,
+    /// This function should set `*local_size` to the number of local atoms in
+    /// the system. Local atoms must come first, and all atoms with an index
+    /// larger than or equal to `local_size` are ghost atoms: they are used as
+    /// neighbors of the local atoms, but never as centers.
+    ///
+    /// This function pointer is optional, and can be set to `NULL` if the
+    /// system does not contain ghost atoms.
+    local_size: Option<unsafe extern fn(user_data: *const c_void, local_size: *mut usize) -> rascal_status_t>,
+    /// This function should set `*ghosts` to a pointer to the first element of
+    /// a contiguous array containing the owner of each ghost atom in the
+    /// system, or to `NULL` if the owners are not known. The array should
+    /// contain `rascal_system_t::size() - rascal_system_t::local_size()`
+    /// elements.
+    ///
+    /// This function pointer is optional, and can be set to `NULL` if the
+    /// system does not contain ghost atoms.
+    ghost_atoms: Option<unsafe extern fn(user_data: *const c_void, ghosts: *mut *const rascal_ghost_atom_t) -> rascal_status_t>,
+    /// This function should set `*charges` to a pointer to the first element
+    /// of a contiguous array containing the charge of each atom in the
+    /// system, or to `NULL` if the system does not define charges. The array
+    /// should contain `rascal_system_t::size()` elements.
+    ///
+    /// This function pointer is optional, and can be set to `NULL` if the
+    /// system does not define charges.
+    charges: Option<unsafe extern fn(user_data: *const c_void, charges: *mut *const f64) -> rascal_status_t>,
+    /// This function should set `*masses` to a pointer to the first element
+    /// of a contiguous array containing the mass of each atom in the system,
+    /// or to `NULL` if the system does not define masses. The array should
+    /// contain `rascal_system_t::size()` strictly positive elements.
+    ///
+    /// This function pointer is optional, and can be set to `NULL` if the
+    /// system does not define masses.
+    masses: Option<unsafe extern fn(user_data: *const c_void, masses: *mut *const f64) -> rascal_status_t>,
+    /// This function should set `*velocities` to a pointer to the first
+    /// element of a contiguous array containing the velocity of each atom in
+    /// the system, or to `NULL` if the system does not define velocities. The
+    /// array should contain `3 x rascal_system_t::size()` elements.
+    ///
+    /// This function pointer is optional, and can be set to `NULL` if the
+    /// system does not define velocities.
+    velocities: Option<unsafe extern fn(user_data: *const c_void, velocities: *mut *const f64) -> rascal_status_t>,
+    /// This function should set `*data` to a pointer to the first element of
+    /// a contiguous array containing the values of the per-atom data with the
+    /// given `name` (a NULL-terminated string), or to `NULL` if the system
+    /// does not define such data. The array should contain
+    /// `rascal_system_t::size()` elements.
+    ///
+    /// When `atomic_gaussian_width`, `charges` or `masses` are `NULL` (or
+    /// give a `NULL` pointer), the per-atom data with the same name is used
+    /// instead.
+    ///
+    /// This function pointer is optional, and can be set to `NULL` if the
+    /// system does not define any per-atom data.
+    get_atom_data: Option<unsafe extern fn(user_data: *const c_void, name: *const c_char, data: *mut *const f64) -> rascal_status_t>,
+    /// This function should set `*centers` to a pointer to the first element
+    /// of a contiguous array indicating which atoms should be used as centers
+    /// by atom-centered calculators, or to `NULL` if all atoms should be used.
+    /// The array should contain `rascal_system_t::size()` elements. Ghost
+    /// atoms are never used as centers, regardless of this mask.
+    ///
+    /// This function pointer is optional, and can be set to `NULL` if all
+    /// atoms should be used as centers.
+    centers: Option<unsafe extern fn(user_data: *const c_void, centers: *mut *const bool) -> rascal_status_t>,
+    /// This function should set `*bonds` to a pointer to the first element of
+    /// a contiguous array containing the chemical bonds in this system, and
+    /// `*count` to the number of bonds. The array should contain `2 x count`
+    /// values, with the indexes of the two atoms in each bond. Each bond
+    /// should only be included once.
+    ///
+    /// This function pointer is optional, and can be set to `NULL` if the
+    /// system does not contain any bond.
+    bonds: Option<unsafe extern fn(user_data: *const c_void, bonds: *mut *const usize, count: *mut usize) -> rascal_status_t>,
 }
 
 unsafe impl Send for rascal_system_t {}
@@ -126,6 +229,29 @@ impl<'a> System for &'a mut rascal_system_t {
         return Ok(value);
     }
 
+    fn local_size(&self) -> Result<usize, Error> {
+        let function = if let Some(function) = self.local_size {
+            function
+        } else {
+            // this function is optional
+            return self.size();
+        };
+
+        let mut value = 0;
+        let status = unsafe {
+            function(self.user_data, &mut value)
+        };
+
+        if !status.is_success() {
+            return Err(Error::External {
+                status: status.as_i32(),
+                message: "call to rascal_system_t.local_size failed".into(),
+            });
+        }
+
+        return Ok(value);
+    }
+
     fn species(&self) -> Result<&[i32], Error> {
         let function = self.species.ok_or_else(|| Error::External {
             status: RASCAL_SYSTEM_ERROR,
@@ -207,10 +333,24 @@ impl<'a> System for &'a mut rascal_system_t {
 
         let matrix = Matrix3::from(value);
         if matrix == Matrix3::zero() {
-            Ok(UnitCell::infinite())
-        } else {
-            Ok(UnitCell::from(matrix))
+            return Ok(UnitCell::infinite());
         }
+
+        let mut periodic = [true; 3];
+        if let Some(function) = self.periodic {
+            let status = unsafe {
+                function(self.user_data, periodic.as_mut_ptr())
+            };
+
+            if !status.is_success() {
+                return Err(Error::External {
+                    status: status.as_i32(),
+                    message: "call to rascal_system_t.periodic failed".into(),
+                });
+            }
+        }
+
+        return Ok(UnitCell::from(matrix).with_periodicity(periodic));
     }
 
     fn compute_neighbors(&mut self, cutoff: f64) -> Result<(), Error> {
@@ -291,6 +431,253 @@ impl<'a> System for &'a mut rascal_system_t {
             return Ok(std::slice::from_raw_parts(ptr.cast(), count));
         }
     }
+
+    fn atomic_gaussian_width(&self) -> Result<Option<&[f64]>, Error> {
+        let function = if let Some(function) = self.atomic_gaussian_width {
+            function
+        } else {
+            // this function is optional, fall back to the named atom data
+            return self.atom_data("atomic_gaussian_width");
+        };
+
+        let mut ptr = std::ptr::null();
+        let status = unsafe {
+            function(self.user_data, &mut ptr)
+        };
+
+        if !status.is_success() {
+            return Err(Error::External {
+                status: status.as_i32(),
+                message: "call to rascal_system_t.atomic_gaussian_width failed".into(),
+            });
+        }
+
+        if ptr.is_null() {
+            return self.atom_data("atomic_gaussian_width");
+        }
+
+        unsafe {
+            return Ok(Some(std::slice::from_raw_parts(ptr, self.size()?)));
+        }
+    }
+
+    fn charges(&self) -> Result<Option<&[f64]>, Error> {
+        let function = if let Some(function) = self.charges {
+            function
+        } else {
+            // this function is optional, fall back to the named atom data
+            return self.atom_data("charges");
+        };
+
+        let mut ptr = std::ptr::null();
+        let status = unsafe {
+            function(self.user_data, &mut ptr)
+        };
+
+        if !status.is_success() {
+            return Err(Error::External {
+                status: status.as_i32(),
+                message: "call to rascal_system_t.charges failed".into(),
+            });
+        }
+
+        if ptr.is_null() {
+            return self.atom_data("charges");
+        }
+
+        unsafe {
+            return Ok(Some(std::slice::from_raw_parts(ptr, self.size()?)));
+        }
+    }
+
+    fn masses(&self) -> Result<Option<&[f64]>, Error> {
+        let function = if let Some(function) = self.masses {
+            function
+        } else {
+            // this function is optional, fall back to the named atom data
+            return self.atom_data("masses");
+        };
+
+        let mut ptr = std::ptr::null();
+        let status = unsafe {
+            function(self.user_data, &mut ptr)
+        };
+
+        if !status.is_success() {
+            return Err(Error::External {
+                status: status.as_i32(),
+                message: "call to rascal_system_t.masses failed".into(),
+            });
+        }
+
+        if ptr.is_null() {
+            return self.atom_data("masses");
+        }
+
+        unsafe {
+            return Ok(Some(std::slice::from_raw_parts(ptr, self.size()?)));
+        }
+    }
+
+    fn velocities(&self) -> Result<Option<&[Vector3D]>, Error> {
+        let function = if let Some(function) = self.velocities {
+            function
+        } else {
+            // this function is optional
+            return Ok(None);
+        };
+
+        let mut ptr = std::ptr::null();
+        let status = unsafe {
+            function(self.user_data, &mut ptr)
+        };
+
+        if !status.is_success() {
+            return Err(Error::External {
+                status: status.as_i32(),
+                message: "call to rascal_system_t.velocities failed".into(),
+            });
+        }
+
+        if ptr.is_null() {
+            return Ok(None);
+        }
+
+        unsafe {
+            return Ok(Some(std::slice::from_raw_parts(ptr.cast(), self.size()?)));
+        }
+    }
+
+    fn atom_data(&self, name: &str) -> Result<Option<&[f64]>, Error> {
+        let function = if let Some(function) = self.get_atom_data {
+            function
+        } else {
+            // this function is optional
+            return Ok(None);
+        };
+
+        let c_name = CString::new(name).map_err(|_| Error::InvalidParameter(format!(
+            "atom data name '{}' contains a NULL byte", name
+        )))?;
+
+        let mut ptr = std::ptr::null();
+        let status = unsafe {
+            function(self.user_data, c_name.as_ptr(), &mut ptr)
+        };
+
+        if !status.is_success() {
+            return Err(Error::External {
+                status: status.as_i32(),
+                message: "call to rascal_system_t.get_atom_data failed".into(),
+            });
+        }
+
+        if ptr.is_null() {
+            return Ok(None);
+        }
+
+        unsafe {
+            return Ok(Some(std::slice::from_raw_parts(ptr, self.size()?)));
+        }
+    }
+
+    fn ghost_atoms(&self) -> Result<Option<&[GhostAtom]>, Error> {
+        let function = if let Some(function) = self.ghost_atoms {
+            function
+        } else {
+            // this function is optional
+            return Ok(None);
+        };
+
+        let mut ptr = std::ptr::null();
+        let status = unsafe {
+            function(self.user_data, &mut ptr)
+        };
+
+        if !status.is_success() {
+            return Err(Error::External {
+                status: status.as_i32(),
+                message: "call to rascal_system_t.ghost_atoms failed".into(),
+            });
+        }
+
+        if ptr.is_null() {
+            return Ok(None);
+        }
+
+        let count = self.size()? - self.local_size()?;
+        unsafe {
+            // SAFETY: ptr is non null, and GhostAtom / rascal_ghost_atom_t
+            // have the same layout
+            return Ok(Some(std::slice::from_raw_parts(ptr.cast(), count)));
+        }
+    }
+
+    fn centers(&self) -> Result<Option<&[bool]>, Error> {
+        let function = if let Some(function) = self.centers {
+            function
+        } else {
+            // this function is optional
+            return Ok(None);
+        };
+
+        let mut ptr = std::ptr::null();
+        let status = unsafe {
+            function(self.user_data, &mut ptr)
+        };
+
+        if !status.is_success() {
+            return Err(Error::External {
+                status: status.as_i32(),
+                message: "call to rascal_system_t.centers failed".into(),
+            });
+        }
+
+        if ptr.is_null() {
+            return Ok(None);
+        }
+
+        unsafe {
+            return Ok(Some(std::slice::from_raw_parts(ptr, self.size()?)));
+        }
+    }
+
+    fn bonds(&self) -> Result<&[[usize; 2]], Error> {
+        let function = if let Some(function) = self.bonds {
+            function
+        } else {
+            // this function is optional
+            return Ok(&[]);
+        };
+
+        let mut ptr = std::ptr::null();
+        let mut count = 0;
+        let status = unsafe {
+            function(self.user_data, &mut ptr, &mut count)
+        };
+
+        if !status.is_success() {
+            return Err(Error::External {
+                status: status.as_i32(),
+                message: "call to rascal_system_t.bonds failed".into(),
+            });
+        }
+
+        if count == 0 {
+            return Ok(&[]);
+        }
+
+        if ptr.is_null() {
+            return Err(Error::External {
+                status: RASCAL_SYSTEM_ERROR,
+                message: "rascal_system_t.bonds returned a NULL pointer with non zero size".into(),
+            });
+        }
+
+        unsafe {
+            return Ok(std::slice::from_raw_parts(ptr.cast::<[usize; 2]>(), count));
+        }
+    }
 }
 
 /// Convert a Simple System to a `rascal_system_t`
@@ -373,6 +760,112 @@ impl From<SimpleSystem> for rascal_system_t {
             })
         }
 
+        unsafe extern fn atomic_gaussian_width(this: *const c_void, widths: *mut *const f64) -> rascal_status_t {
+            catch_unwind(|| {
+                *widths = match (*this.cast::<SimpleSystem>()).atomic_gaussian_width()? {
+                    Some(widths) => widths.as_ptr(),
+                    None => std::ptr::null(),
+                };
+
+                Ok(())
+            })
+        }
+
+        unsafe extern fn periodic(this: *const c_void, periodic: *mut bool) -> rascal_status_t {
+            catch_unwind(|| {
+                let flags = (*this.cast::<SimpleSystem>()).cell()?.periodic();
+                periodic.add(0).write(flags[0]);
+                periodic.add(1).write(flags[1]);
+                periodic.add(2).write(flags[2]);
+
+                Ok(())
+            })
+        }
+
+        unsafe extern fn local_size(this: *const c_void, local_size: *mut usize) -> rascal_status_t {
+            catch_unwind(|| {
+                *local_size = (*this.cast::<SimpleSystem>()).local_size()?;
+                Ok(())
+            })
+        }
+
+        unsafe extern fn ghost_atoms(this: *const c_void, ghosts: *mut *const rascal_ghost_atom_t) -> rascal_status_t {
+            catch_unwind(|| {
+                *ghosts = match (*this.cast::<SimpleSystem>()).ghost_atoms()? {
+                    Some(ghosts) => ghosts.as_ptr().cast(),
+                    None => std::ptr::null(),
+                };
+
+                Ok(())
+            })
+        }
+
+        unsafe extern fn charges(this: *const c_void, charges: *mut *const f64) -> rascal_status_t {
+            catch_unwind(|| {
+                *charges = match (*this.cast::<SimpleSystem>()).charges()? {
+                    Some(charges) => charges.as_ptr(),
+                    None => std::ptr::null(),
+                };
+
+                Ok(())
+            })
+        }
+
+        unsafe extern fn masses(this: *const c_void, masses: *mut *const f64) -> rascal_status_t {
+            catch_unwind(|| {
+                *masses = match (*this.cast::<SimpleSystem>()).masses()? {
+                    Some(masses) => masses.as_ptr(),
+                    None => std::ptr::null(),
+                };
+
+                Ok(())
+            })
+        }
+
+        unsafe extern fn velocities(this: *const c_void, velocities: *mut *const f64) -> rascal_status_t {
+            catch_unwind(|| {
+                *velocities = match (*this.cast::<SimpleSystem>()).velocities()? {
+                    Some(velocities) => velocities.as_ptr().cast(),
+                    None => std::ptr::null(),
+                };
+
+                Ok(())
+            })
+        }
+
+        unsafe extern fn get_atom_data(this: *const c_void, name: *const c_char, data: *mut *const f64) -> rascal_status_t {
+            catch_unwind(|| {
+                let name = CStr::from_ptr(name).to_str()?;
+                *data = match (*this.cast::<SimpleSystem>()).atom_data(name)? {
+                    Some(values) => values.as_ptr(),
+                    None => std::ptr::null(),
+                };
+
+                Ok(())
+            })
+        }
+
+        unsafe extern fn centers(this: *const c_void, centers: *mut *const bool) -> rascal_status_t {
+            catch_unwind(|| {
+                *centers = match (*this.cast::<SimpleSystem>()).centers()? {
+                    Some(centers) => centers.as_ptr(),
+                    None => std::ptr::null(),
+                };
+
+                Ok(())
+            })
+        }
+
+        unsafe extern fn bonds(this: *const c_void, bonds: *mut *const usize, count: *mut usize) -> rascal_status_t {
+            catch_unwind(|| {
+                let all_bonds = (*this.cast::<SimpleSystem>()).bonds()?;
+                *bonds = all_bonds.as_ptr().cast();
+                *count = all_bonds.len();
+
+                Ok(())
+            })
+        }
+
         rascal_system_t {
             user_data: Box::into_raw(Box::new(system)).cast(),
             size: Some(size),
@@ -382,6 +875,16 @@ impl From<SimpleSystem> for rascal_system_t {
             compute_neighbors: Some(compute_neighbors),
             pairs: Some(pairs),
             pairs_containing: Some(pairs_containing),
+            atomic_gaussian_width: Some(atomic_gaussian_width),
+            periodic: Some(periodic),
+            local_size: Some(local_size),
+            ghost_atoms: Some(ghost_atoms),
+            charges: Some(charges),
+            masses: Some(masses),
+            velocities: Some(velocities),
+            get_atom_data: Some(get_atom_data),
+            centers: Some(centers),
+            bonds: Some(bonds),
         }
     }
 }
@@ -462,3 +965,98 @@ pub unsafe extern fn rascal_basic_systems_free(systems: *mut rascal_system_t, co
         Ok(())
     })
 }
+
+/// Update the positions of the atoms in a system created by
+/// `rascal_basic_systems_read`, for example between two steps of a molecular
+/// dynamics simulation.
+///
+/// The positions are updated in place, and the neighbor list of this system is
+/// invalidated. It will be re-computed on the next calculation.
+///
+/// This function is only valid to call with one of the systems obtained from
+/// `rascal_basic_systems_read`. Any other use will probably result in
+/// segmentation faults.
+///
+/// @param system pointer to a single system from `rascal_basic_systems_read`
+/// @param positions pointer to an array of `3 x count` values containing the
+///                  new cartesian positions of all the atoms
+/// @param count number of atoms in the system
+///
+/// @returns The status code of this operation. If the status is not
+///          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+///          error message.
+#[no_mangle]
+pub unsafe extern fn rascal_basic_systems_set_positions(
+    system: *mut rascal_system_t,
+    positions: *const f64,
+    count: usize,
+) -> rascal_status_t {
+    catch_unwind(move || {
+        check_pointers!(system, positions);
+        let user_data = (*system).user_data;
+        check_pointers!(user_data);
+
+        let positions = std::slice::from_raw_parts(positions.cast::<Vector3D>(), count);
+        (*user_data.cast::<SimpleSystem>()).set_positions(positions)?;
+
+        Ok(())
+    })
+}
+
+/// Replace the bonds of a `system` coming from `rascal_basic_systems_read` with
+/// the given `bonds`.
+///
+/// @param system pointer to a single system from `rascal_basic_systems_read`
+/// @param bonds pointer to an array of `2 x count` values containing the
+///              indexes of the atoms in each bond
+/// @param count number of bonds
+///
+/// @returns The status code of this operation. If the status is not
+///          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+///          error message.
+#[no_mangle]
+pub unsafe extern fn rascal_basic_systems_set_bonds(
+    system: *mut rascal_system_t,
+    bonds: *const usize,
+    count: usize,
+) -> rascal_status_t {
+    catch_unwind(move || {
+        check_pointers!(system);
+        let user_data = (*system).user_data;
+        check_pointers!(user_data);
+
+        let bonds = if count == 0 {
+            &[]
+        } else {
+            check_pointers!(bonds);
+            std::slice::from_raw_parts(bonds.cast::<[usize; 2]>(), count)
+        };
+        (*user_data.cast::<SimpleSystem>()).set_bonds(bonds)?;
+
+        Ok(())
+    })
+}
+
+/// Check that the given `system` is valid: all the per-atom data must contain
+/// one value per atom, all positions must be finite, the unit cell must have a
+/// positive volume (and right-handed cell vectors), and no two atoms should
+/// overlap.
+///
+/// This can be used to get clearer error messages than the NaN values produced
+/// by calculators when running on invalid systems.
+///
+/// @param system pointer to the system to validate
+///
+/// @returns The status code of this operation. If the system is invalid, this
+///          returns `RASCAL_INVALID_PARAMETER_ERROR`, and you can use
+///          `rascal_last_error()` to get the full error message.
+#[no_mangle]
+pub unsafe extern fn rascal_system_validate(system: *mut rascal_system_t) -> rascal_status_t {
+    catch_unwind(move || {
+        check_pointers!(system);
+        let system = &mut *system;
+        system.validate()?;
+
+        Ok(())
+    })
+}
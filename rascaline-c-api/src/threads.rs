@@ -0,0 +1,27 @@
+use crate::{catch_unwind, rascal_status_t};
+
+/// Set the maximal number of threads used by all calculators, or remove the
+/// limit by using `max_threads = 0`.
+///
+/// When no limit is set with this function, the `RASCALINE_NUM_THREADS`
+/// environment variable is used if it is set, and all available threads are
+/// used otherwise. Limiting the number of threads is useful when calling
+/// rascaline from code which is already running in parallel.
+///
+/// @param max_threads maximal number of threads, or 0 to remove the limit
+///
+/// @returns The status code of this operation. If the status is not
+///          `RASCAL_SUCCESS`, you can use `rascal_last_error()` to get the full
+///          error message.
+#[no_mangle]
+pub unsafe extern fn rascal_set_max_threads(max_threads: usize) -> rascal_status_t {
+    catch_unwind(|| {
+        let max_threads = if max_threads == 0 {
+            None
+        } else {
+            Some(max_threads)
+        };
+        rascaline::set_max_threads(max_threads)?;
+        Ok(())
+    })
+}
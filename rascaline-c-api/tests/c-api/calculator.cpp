@@ -271,6 +271,64 @@ TEST_CASE("Compute descriptor") {
         rascal_calculator_free(calculator);
     }
 
+    SECTION("Partial compute -- centers") {
+        auto centers = std::vector<uintptr_t>{1, 3};
+        auto centers_per_system = std::vector<uintptr_t>{2};
+
+        auto system = simple_system();
+
+        rascal_calculation_options_t options = {0};
+        const char* gradients_list[] = {"positions"};
+        options.gradients = gradients_list;
+        options.gradients_count = 1;
+        options.selected_samples.centers = centers.data();
+        options.selected_samples.centers_per_system = centers_per_system.data();
+        options.selected_samples.centers_systems_count = 1;
+        auto* calculator = rascal_calculator("dummy_calculator", HYPERS_JSON);
+        REQUIRE(calculator != nullptr);
+
+        eqs_tensormap_t* descriptor = nullptr;
+        auto status = rascal_calculator_compute(
+            calculator, &descriptor, &system, 1, options
+        );
+        CHECK_SUCCESS(status);
+
+        auto samples = std::vector<int32_t>{
+            0, 1, /**/ 0, 3,
+        };
+        auto properties = std::vector<int32_t>{
+            1, 0, /**/ 0, 1,
+        };
+        auto values = std::vector<double>{
+            5, 9, /**/ 7, 15,
+        };
+        auto gradient_samples = std::vector<int32_t>{
+            0, 0, 0, /**/ 0, 0, 1, /**/ 0, 0, 2,
+            1, 0, 2, /**/ 1, 0, 3,
+        };
+        auto gradients = std::vector<double>{
+            0.0, 1.0, /**/ 0.0, 1.0, /**/ 0.0, 1.0,
+            0.0, 1.0, /**/ 0.0, 1.0, /**/ 0.0, 1.0,
+            0.0, 1.0, /**/ 0.0, 1.0, /**/ 0.0, 1.0,
+            0.0, 1.0, /**/ 0.0, 1.0, /**/ 0.0, 1.0,
+            0.0, 1.0, /**/ 0.0, 1.0, /**/ 0.0, 1.0
+        };
+
+        // H block
+        check_block(descriptor, 0, samples, properties, values, gradient_samples, gradients);
+
+        samples = std::vector<int32_t>{};
+        values = std::vector<double>{};
+        gradient_samples = std::vector<int32_t>{};
+        gradients = std::vector<double>{};
+
+        // C block
+        check_block(descriptor, 1, samples, properties, values, gradient_samples, gradients);
+
+        eqs_tensormap_free(descriptor);
+        rascal_calculator_free(calculator);
+    }
+
     SECTION("Partial compute -- features") {
         auto selected_properties_values = std::vector<int32_t>{
             0, 1,
@@ -361,6 +419,80 @@ TEST_CASE("Compute descriptor") {
         rascal_calculator_free(calculator);
     }
 
+    SECTION("Partial compute -- named features") {
+        auto selected_properties_values = std::vector<int32_t>{
+            0, 1,
+        };
+        auto selected_properties_names = std::vector<const char*>{
+            "index_delta", "x_y_z"
+        };
+
+        auto system = simple_system();
+
+        rascal_calculation_options_t options = {0};
+        const char* gradients_list[] = {"positions"};
+        options.gradients = gradients_list;
+        options.gradients_count = 1;
+        options.selected_properties.subset_names = selected_properties_names.data();
+        options.selected_properties.subset_size = 2;
+        options.selected_properties.subset_values = selected_properties_values.data();
+        options.selected_properties.subset_count = 1;
+        auto* calculator = rascal_calculator("dummy_calculator", HYPERS_JSON);
+        REQUIRE(calculator != nullptr);
+
+        eqs_tensormap_t* descriptor = nullptr;
+        auto status = rascal_calculator_compute(
+            calculator, &descriptor, &system, 1, options
+        );
+        CHECK_SUCCESS(status);
+
+        auto samples = std::vector<int32_t>{
+            0, 1, /**/ 0, 2, /**/ 0, 3,
+        };
+        auto properties = std::vector<int32_t>{
+            0, 1,
+        };
+        auto values = std::vector<double>{
+            9, /**/ 18, /**/ 15,
+        };
+        auto gradient_samples = std::vector<int32_t>{
+            0, 0, 0, /**/ 0, 0, 1, /**/ 0, 0, 2,
+            1, 0, 1, /**/ 1, 0, 2, /**/ 1, 0, 3,
+            2, 0, 2, /**/ 2, 0, 3,
+        };
+        auto gradients = std::vector<double>{
+            1.0, /**/ 1.0, /**/ 1.0,
+            1.0, /**/ 1.0, /**/ 1.0,
+            1.0, /**/ 1.0, /**/ 1.0,
+            1.0, /**/ 1.0, /**/ 1.0,
+            1.0, /**/ 1.0, /**/ 1.0,
+            1.0, /**/ 1.0, /**/ 1.0,
+            1.0, /**/ 1.0, /**/ 1.0,
+            1.0, /**/ 1.0, /**/ 1.0
+        };
+
+        // H block
+        check_block(descriptor, 0, samples, properties, values, gradient_samples, gradients);
+
+        eqs_tensormap_free(descriptor);
+
+        // named selection can not be combined with other selections
+        eqs_labels_t selected_properties = {0};
+        selected_properties.names = selected_properties_names.data();
+        selected_properties.values = selected_properties_values.data();
+        selected_properties.count = 1;
+        selected_properties.size = 2;
+        options.selected_properties.subset = &selected_properties;
+
+        descriptor = nullptr;
+        status = rascal_calculator_compute(
+            calculator, &descriptor, &system, 1, options
+        );
+        CHECK(status != RASCAL_SUCCESS);
+
+        rascal_calculator_free(calculator);
+    }
+
     SECTION("Partial compute -- preselected") {
         auto samples_names = std::vector<const char*>{
             "structure", "center"
@@ -1,3 +1,4 @@
+#include <cmath>
 #include <vector>
 #include <string>
 
@@ -414,3 +415,32 @@ TEST_CASE("Compute descriptor") {
         ));
     }
 }
+
+TEST_CASE("Generate splines") {
+    size_t max_radial = 3;
+    size_t max_angular = 2;
+    auto function = [&](double position, double* values, double* derivatives) {
+        for (size_t i=0; i<(max_angular + 1) * max_radial; i++) {
+            values[i] = std::exp(-position * position) * static_cast<double>(i);
+            derivatives[i] = -2.0 * position * values[i];
+        }
+    };
+
+    auto points = rascaline::generate_splines(function, max_radial, max_angular, 3.5);
+    CHECK(points.substr(0, 16) == "[{\"position\":0.0");
+
+    auto HYPERS_JSON = R"({
+        "cutoff": 3.5,
+        "max_radial": 3,
+        "max_angular": 2,
+        "atomic_gaussian_width": 0.3,
+        "center_atom_weight": 1.0,
+        "radial_basis": {"TabulatedRadialIntegral": {"points": )" + points + R"(}},
+        "cutoff_function": {"ShiftedCosine": {"width": 0.5}}
+    })";
+
+    auto calculator = rascaline::Calculator("spherical_expansion", HYPERS_JSON);
+    auto system = TestSystem();
+    auto systems = std::vector<rascaline::System*>{&system};
+    CHECK_NOTHROW(calculator.compute(systems));
+}
@@ -1,3 +1,6 @@
+#include <vector>
+#include <string>
+
 #include "rascaline.h"
 #include "catch.hpp"
 #include "helpers.hpp"
@@ -43,6 +46,23 @@ TEST_CASE("basic systems") {
     CHECK_THAT(cell[7], Catch::Matchers::WithinULP(7.84785, 10));
     CHECK_THAT(cell[8], Catch::Matchers::WithinULP(7.84785, 10));
 
+    auto new_positions = std::vector<double>(3 * size, 1.5);
+    CHECK_SUCCESS(rascal_basic_systems_set_positions(&systems[0], new_positions.data(), size));
+    system.positions(system.user_data, &positions);
+    CHECK(positions[0] == 1.5);
+    CHECK(positions[3 * size - 1] == 1.5);
+
+    CHECK_SUCCESS(rascal_system_validate(&systems[1]));
+
+    // all atoms are now overlapping
+    auto status = rascal_system_validate(&systems[0]);
+    CHECK(status == RASCAL_INVALID_PARAMETER_ERROR);
+    CHECK(std::string(rascal_last_error()) == "invalid system: atoms 0 and 1 are overlapping (distance is 0)");
+
+    status = rascal_basic_systems_set_positions(&systems[0], new_positions.data(), 3);
+    CHECK(status == RASCAL_INVALID_PARAMETER_ERROR);
+    CHECK(std::string(rascal_last_error()) == "invalid parameter: expected 54 positions, got 3");
+
     CHECK_SUCCESS(rascal_basic_systems_free(systems, count));
 }
 
@@ -85,3 +105,44 @@ TEST_CASE("systems errors") {
     rascal_calculator_free(calculator);
     eqs_tensormap_free(descriptor);
 }
+
+
+TEST_CASE("unit cell utilities") {
+    double cell[9] = {
+        5.0, 0.0, 0.0,
+        0.0, 5.0, 0.0,
+        0.0, 0.0, 5.0,
+    };
+
+    double lengths[3] = {0.0};
+    double angles[3] = {0.0};
+    CHECK_SUCCESS(rascal_cell_parameters(cell, lengths, angles));
+    CHECK(lengths[0] == 5.0);
+    CHECK(angles[2] == 90.0);
+
+    double vectors[6] = {2.5, 0.0, 10.0, -1.0, 6.0, 0.0};
+    CHECK_SUCCESS(rascal_cell_fractional(cell, vectors, 2));
+    CHECK_THAT(vectors[0], Catch::Matchers::WithinULP(0.5, 10));
+    CHECK_THAT(vectors[2], Catch::Matchers::WithinULP(2.0, 10));
+    CHECK_THAT(vectors[3], Catch::Matchers::WithinULP(-0.2, 10));
+
+    CHECK_SUCCESS(rascal_cell_cartesian(cell, vectors, 2));
+    CHECK_THAT(vectors[0], Catch::Matchers::WithinULP(2.5, 10));
+    CHECK_THAT(vectors[2], Catch::Matchers::WithinULP(10.0, 10));
+
+    bool periodic[3] = {true, false, true};
+    CHECK_SUCCESS(rascal_cell_wrap(cell, periodic, vectors, 2));
+    CHECK_THAT(vectors[2], Catch::Matchers::WithinAbs(0.0, 1e-12));
+    CHECK_THAT(vectors[3], Catch::Matchers::WithinAbs(4.0, 1e-12));
+    CHECK_THAT(vectors[4], Catch::Matchers::WithinAbs(6.0, 1e-12));
+
+    double first[3] = {0.5, 0.0, 0.0};
+    double second[3] = {4.5, 0.0, 0.0};
+    double distance = 0.0;
+    CHECK_SUCCESS(rascal_cell_distance(cell, nullptr, first, second, &distance));
+    CHECK_THAT(distance, Catch::Matchers::WithinAbs(1.0, 1e-12));
+
+    periodic[0] = false;
+    CHECK_SUCCESS(rascal_cell_distance(cell, periodic, first, second, &distance));
+    CHECK_THAT(distance, Catch::Matchers::WithinAbs(4.0, 1e-12));
+}
@@ -1,5 +1,6 @@
 #![allow(clippy::needless_return)]
 
+use rascaline::calculators::GtoOrthonormalization;
 use rascaline::calculators::soap::SoapRadialIntegral;
 use rascaline::calculators::soap::{SoapRadialIntegralGtoParameters, SoapRadialIntegralGto};
 use rascaline::calculators::soap::{SoapRadialIntegralSpline, SoapRadialIntegralSplineParameters};
@@ -51,6 +52,8 @@ fn gto_radial_integral(c: &mut Criterion) {
             max_angular,
             cutoff: 4.5,
             atomic_gaussian_width: 0.5,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         };
         return Box::new(SoapRadialIntegralGto::new(parameters).unwrap()) as Box<dyn SoapRadialIntegral>;
     };
@@ -72,6 +75,8 @@ fn splined_gto_radial_integral(c: &mut Criterion) {
             max_angular,
             cutoff,
             atomic_gaussian_width: 0.5,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         };
         let gto = SoapRadialIntegralGto::new(parameters).unwrap();
 
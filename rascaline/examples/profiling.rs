@@ -5,21 +5,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let path = std::env::args().nth(1).expect("expected a command line argument");
 
     // enable collection of profiling data
-    time_graph::enable_data_collection(true);
+    rascaline::profiling::enable(true);
     // clear any existing collected data
-    time_graph::clear_collected_data();
+    rascaline::profiling::clear();
 
     // run the calculation
     let _descriptor = compute_soap(&path)?;
 
     // get the call graph and display it
-    let graph = time_graph::get_full_graph();
-    // (this requires the "table" feature for the time_graph crate)
-    println!("{}", graph.as_short_table());
+    println!("{}", rascaline::profiling::report("short_table")?);
 
     // also available for saving profiling data to the disk & future analysis
-    // (this requires the "json" feature for the time_graph crate)
-    println!("{}", graph.as_json());
+    println!("{}", rascaline::profiling::report("json")?);
 
     Ok(())
 }
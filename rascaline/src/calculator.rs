@@ -1,24 +1,34 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryFrom;
+use std::sync::Arc;
 
 use once_cell::sync::Lazy;
 
-use equistore::{Labels, LabelsBuilder};
-use equistore::{TensorBlockRef, TensorBlock, TensorMap};
-use ndarray::ArrayD;
+use equistore::{Labels, LabelsBuilder, LabelValue};
+use equistore::{TensorBlockRef, TensorBlock, TensorMap, EmptyArray};
+use ndarray::{ArrayD, Axis};
 
 use crate::{SimpleSystem, System, Error};
 
 use crate::calculators::CalculatorBase;
+use crate::postprocessing::GRADIENTS;
 
 pub struct Calculator {
     implementation: Box<dyn CalculatorBase>,
     parameters: String,
+    /// maximal number of threads to use for this calculator, overriding the
+    /// global value from `crate::max_threads()`
+    max_threads: Option<usize>,
+    /// thread pool provided by the user, in which all calculations run
+    thread_pool: Option<Arc<rayon::ThreadPool>>,
+    /// should we collect profiling data for the calculations of this
+    /// calculator, even if profiling is disabled globally?
+    profiling: bool,
 }
 
 /// Rules to select labels (either samples or properties) on which the user
 /// wants to run a calculation
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy)]
 pub enum LabelsSelection<'a> {
     /// Default, use all possible labels
     All,
@@ -40,6 +50,47 @@ pub enum LabelsSelection<'a> {
     /// `TensorMap`. The inner `TensorMap` must have the same set of keys as the
     /// full calculation.
     Predefined(&'a TensorMap),
+    /// Select labels with a predicate, called with the key of the block and
+    /// a candidate entry in the labels. Only the entries for which the
+    /// predicate returns `true` are used.
+    ///
+    /// The predicate is evaluated before allocating any memory for the
+    /// corresponding data, so excluded entries are never computed. The
+    /// values in the key and the entry follow the key names and labels names
+    /// of the calculator.
+    Predicate(&'a (dyn Fn(&[LabelValue], &[LabelValue]) -> bool + Sync)),
+    /// Select a different subset of labels for each key of the final
+    /// `TensorMap`, for example a different active set for each species.
+    ///
+    /// The first `Labels` contains keys, and the slice contains the
+    /// corresponding selection for each of these keys, in the same order. Each
+    /// selection follows the same rules as `LabelsSelection::Subset`. Blocks
+    /// with a key which is not part of the selection use all possible labels.
+    SubsetByKey(&'a Labels, &'a [Labels]),
+    /// Select samples using, for each system, a list of the center atoms to
+    /// include in the calculation.
+    ///
+    /// The slice contains one entry for each system, with the indexes of the
+    /// selected centers in this system. Systems after the end of the slice do
+    /// not have any selected center. This can only be used with labels
+    /// containing both `"structure"` and `"center"`, such as the samples of
+    /// atom-centered representations.
+    Centers(&'a [Vec<usize>]),
+}
+
+impl<'a> std::fmt::Debug for LabelsSelection<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LabelsSelection::All => write!(f, "All"),
+            LabelsSelection::Subset(labels) => f.debug_tuple("Subset").field(labels).finish(),
+            LabelsSelection::Predefined(tensor) => f.debug_tuple("Predefined").field(tensor).finish(),
+            LabelsSelection::Predicate(_) => write!(f, "Predicate(..)"),
+            LabelsSelection::SubsetByKey(keys, selections) => {
+                f.debug_tuple("SubsetByKey").field(keys).field(selections).finish()
+            }
+            LabelsSelection::Centers(centers) => f.debug_tuple("Centers").field(centers).finish(),
+        }
+    }
 }
 
 impl<'a> LabelsSelection<'a> {
@@ -66,51 +117,93 @@ impl<'a> LabelsSelection<'a> {
                 let default_names = get_default_names();
 
                 let mut results = Vec::new();
-                if selection.names() == default_names {
-                    for labels in default_labels {
-                        let mut builder = LabelsBuilder::new(default_names.clone());
-                        for entry in selection.iter() {
-                            if labels.contains(entry) {
-                                builder.add(entry);
-                            }
+                for labels in default_labels {
+                    results.push(select_subset(label_kind, selection, &default_names, &labels)?);
+                }
+
+                return Ok(results);
+            },
+            LabelsSelection::SubsetByKey(selection_keys, selections) => {
+                if selection_keys.names() != keys.names() {
+                    return Err(Error::InvalidParameter(format!(
+                        "invalid key names in {} selection by key: expected [{}], but got [{}]",
+                        label_kind,
+                        keys.names().join(", "),
+                        selection_keys.names().join(", ")
+                    )));
+                }
+
+                if selection_keys.count() != selections.len() {
+                    return Err(Error::InvalidParameter(format!(
+                        "expected {} {} selections (one for each key), got {}",
+                        selection_keys.count(), label_kind, selections.len()
+                    )));
+                }
+
+                let default_labels = get_default_labels(keys)?;
+                let default_names = get_default_names();
+
+                let mut results = Vec::new();
+                for (key, labels) in keys.iter().zip(default_labels) {
+                    match selection_keys.position(key) {
+                        Some(position) => {
+                            let selection = &selections[position];
+                            results.push(select_subset(label_kind, selection, &default_names, &labels)?);
                         }
-                        results.push(builder.finish());
+                        None => results.push(labels),
                     }
-                } else {
-                    let mut variables_to_match = Vec::new();
-                    for variable in selection.names() {
-                        let i = match default_names.iter().position(|&v| v == variable) {
-                            Some(index) => index,
-                            None => {
-                                return Err(Error::InvalidParameter(format!(
-                                    "'{}' in {} selection is not one of the {} of this calculator",
-                                    variable, label_kind, label_kind
-                                )))
-                            }
-                        };
-                        variables_to_match.push(i);
+                }
+
+                return Ok(results);
+            },
+            LabelsSelection::Centers(centers) => {
+                let default_names = get_default_names();
+                let structure_i = default_names.iter().position(|&name| name == "structure");
+                let center_i = default_names.iter().position(|&name| name == "center");
+                let (structure_i, center_i) = match (structure_i, center_i) {
+                    (Some(structure_i), Some(center_i)) => (structure_i, center_i),
+                    _ => {
+                        return Err(Error::InvalidParameter(format!(
+                            "selecting centers requires \"structure\" and \"center\" in the {} of this calculator, got [{}]",
+                            label_kind, default_names.join(", ")
+                        )));
                     }
+                };
+
+                let centers = centers.iter()
+                    .map(|centers| centers.iter().copied().collect::<BTreeSet<_>>())
+                    .collect::<Vec<_>>();
+
+                let default_labels = get_default_labels(keys)?;
 
-                    for labels in default_labels {
-                        let mut builder = LabelsBuilder::new(default_names.clone());
-                        for entry in labels.iter() {
-                            for selected in selection.iter() {
-                                let mut matches = true;
-                                for (i, &v) in variables_to_match.iter().enumerate() {
-                                    if selected[i] != entry[v] {
-                                        matches = false;
-                                        break;
-                                    }
-                                }
-
-                                if matches {
-                                    builder.add(entry);
-                                }
-                            }
+                let mut results = Vec::new();
+                for labels in default_labels {
+                    let mut builder = LabelsBuilder::new(labels.names());
+                    for entry in labels.iter() {
+                        let selected = centers.get(entry[structure_i].usize())
+                            .map_or(false, |centers| centers.contains(&entry[center_i].usize()));
+
+                        if selected {
+                            builder.add(entry);
                         }
-                        results.push(builder.finish());
                     }
+                    results.push(builder.finish());
+                }
+
+                return Ok(results);
+            },
+            LabelsSelection::Predicate(predicate) => {
+                let default_labels = get_default_labels(keys)?;
 
+                let mut results = Vec::new();
+                for (key, labels) in keys.iter().zip(default_labels) {
+                    let mut builder = LabelsBuilder::new(labels.names());
+                    for entry in labels.iter() {
+                        if predicate(key, entry) {
+                            builder.add(entry);
+                        }
+                    }
+                    results.push(builder.finish());
                 }
 
                 return Ok(results);
@@ -163,7 +256,9 @@ impl<'a> LabelsSelection<'a> {
 #[derive(Debug, Clone, Copy)]
 pub struct CalculationOptions<'a> {
     /// List of gradients that should be computed. If this list is empty no
-    /// gradients are computed.
+    /// gradients are computed. Each kind of gradients is computed
+    /// independently of the others, e.g. cell gradients can be requested
+    /// without also computing positions gradients.
     ///
     /// The following gradients are available:
     ///
@@ -181,6 +276,12 @@ pub struct CalculationOptions<'a> {
     ///   other atoms within the representation. To recover the force one has to
     ///   accumulate all pairs associated with atom $i$.
     ///
+    ///   The gradients are stored in a sparse format: the samples of the
+    ///   gradients (`["sample", "structure", "atom"]`) only contain the atoms
+    ///   $j$ which contribute to a given sample, i.e. the neighbors of the
+    ///   central atom for atom-centered representations. Gradients with
+    ///   respect to all other atoms are zero and not stored.
+    ///
     /// - ``"cell"``, for gradients of the representation with respect to cell
     ///   vectors. Cell gradients are computed as
     ///
@@ -199,6 +300,28 @@ pub struct CalculationOptions<'a> {
     ///            {\partial\epsilon}
     ///        = -\frac{\partial \langle q \vert A \rangle}
     ///                {\partial \mathbf{h}} \cdot \mathbf{h} $$
+    ///
+    /// - ``"positions_hessian"``, for second derivatives of the representation
+    ///   with respect to atomic positions, computed as
+    ///
+    ///   $$ \frac{\partial^2 \langle q \vert A_i \rangle}
+    ///           {\partial \mathbf{r_j} \partial \mathbf{r_k}} $$
+    ///
+    ///   The samples of these gradients are `["sample", "structure", "atom_1",
+    ///   "atom_2"]`, and the first two components (`"direction_1"` and
+    ///   `"direction_2"`) correspond to the cartesian directions of atoms $j$
+    ///   and $k$ respectively. Only some calculators support these.
+    ///
+    /// - ``"density_scaling"``, for gradients of the representation with
+    ///   respect to the per-atom density scaling factors $w_j$ given by the
+    ///   systems, computed as
+    ///
+    ///   $$ \frac{\partial \langle q \vert A_i \rangle}
+    ///           {\partial w_j} $$
+    ///
+    ///   The samples of these gradients are the same as for positions
+    ///   gradients, without the direction component. Only some calculators
+    ///   support these.
     pub gradients: &'a[&'a str],
     /// Copy the data from systems into native `SimpleSystem`. This can be
     /// faster than having to cross the FFI boundary too often.
@@ -211,7 +334,62 @@ pub struct CalculationOptions<'a> {
     /// default set of keys (as determined by the calculator) will be used. Note
     /// that this default set of keys can depend on which systems we are running
     /// the calculation on.
+    ///
+    /// The output contains exactly the selected keys, in the same order. Keys
+    /// which do not appear in the current systems (for example a pair of
+    /// species which is only present in another part of a dataset) create
+    /// empty blocks, unless `remove_empty_blocks` is set. Using the same
+    /// selected keys for all batches of a dataset ensures that all the
+    /// corresponding descriptors share the same block structure.
     pub selected_keys: Option<&'a Labels>,
+    /// Selection of atoms for which to compute gradients with respect to
+    /// positions, as labels with `["structure", "atom"]` names. If this is
+    /// `None`, gradients with respect to all atoms are computed. Otherwise,
+    /// the samples for `"positions"` (and `"positions_hessian"`) gradients
+    /// only contain atoms which are part of the selection, which is useful
+    /// when most of the atoms in the systems are frozen.
+    pub selected_gradient_atoms: Option<&'a Labels>,
+    /// Displacement used to compute gradients with respect to positions with
+    /// finite differences, for calculators which do not implement these
+    /// gradients. If this is `None` (the default), requesting `"positions"`
+    /// gradients from such a calculator is an error.
+    ///
+    /// The finite differences gradients are computed with central differences
+    /// with respect to all the atoms in the structure corresponding to each
+    /// sample, which requires two additional calculations for each atom and
+    /// each cartesian direction. This is much slower than analytic gradients,
+    /// and should only be used when no analytic gradients are available.
+    pub finite_differences_displacement: Option<f64>,
+    /// Maximal number of neighbors around a single atom. If any atom has more
+    /// neighbors within the cutoff of the calculator, the calculation stops
+    /// with an error before allocating memory for the output. Such a large
+    /// number of neighbors is typically caused by positions and cutoff in
+    /// different units, or by overlapping atoms. If this is `None` (the
+    /// default), the number of neighbors is not checked.
+    pub max_neighbors: Option<usize>,
+    /// Remove the blocks without any sample from the output. Such blocks are
+    /// typically created for pairs of species which are present in the
+    /// systems but never within the cutoff of one another. If all the blocks
+    /// are empty, they are all kept.
+    pub remove_empty_blocks: bool,
+    /// Indexes of the systems on which to run the calculation. If this is
+    /// `None` (the default), all the systems are used. Otherwise, the other
+    /// systems are ignored, but the `"structure"` variable in the samples
+    /// still refers to the position of each system in the full list. This is
+    /// useful to compute a shard of a shared list of systems without copying
+    /// the systems around.
+    pub selected_structures: Option<&'a [usize]>,
+    /// Sort the keys, and the samples inside each block, in lexicographic
+    /// order.
+    ///
+    /// The keys and samples created by the calculators in rascaline are
+    /// already sorted, and do not depend on the neighbor list implementation
+    /// or the number of threads. However, user-provided selections (such as
+    /// `selected_keys` or `selected_samples`) are used in the order they are
+    /// given, and external calculators can use any order. Setting this to
+    /// `true` makes sure the output always uses the same order, so
+    /// descriptors can be compared or cached across calculations.
+    pub sort_labels: bool,
 }
 
 impl<'a> Default for CalculationOptions<'a> {
@@ -222,6 +400,12 @@ impl<'a> Default for CalculationOptions<'a> {
             selected_samples: LabelsSelection::All,
             selected_properties: LabelsSelection::All,
             selected_keys: None,
+            selected_gradient_atoms: None,
+            finite_differences_displacement: None,
+            max_neighbors: None,
+            remove_empty_blocks: false,
+            selected_structures: None,
+            sort_labels: false,
         }
     }
 }
@@ -232,6 +416,9 @@ impl From<Box<dyn CalculatorBase>> for Calculator {
         Calculator {
             implementation: implementation,
             parameters: parameters,
+            max_threads: None,
+            thread_pool: None,
+            profiling: false,
         }
     }
 }
@@ -259,6 +446,9 @@ impl Calculator {
         return Ok(Calculator {
             implementation: creator(&parameters)?,
             parameters: parameters,
+            max_threads: None,
+            thread_pool: None,
+            profiling: false,
         })
     }
 
@@ -273,9 +463,92 @@ impl Calculator {
         &self.parameters
     }
 
+    /// Set the maximal number of threads used by this calculator, overriding
+    /// the global value set with [`crate::set_max_threads`]. Using `None`
+    /// goes back to the global value.
+    pub fn set_max_threads(&mut self, max_threads: Option<usize>) -> Result<(), Error> {
+        if max_threads == Some(0) {
+            return Err(Error::InvalidParameter(
+                "the maximal number of threads must be at least 1".into()
+            ));
+        }
+
+        self.max_threads = max_threads;
+        return Ok(());
+    }
+
+    /// Get the maximal number of threads used by this calculator, or `None`
+    /// if all available threads are used.
+    pub fn max_threads(&self) -> Option<usize> {
+        if let Some(pool) = &self.thread_pool {
+            return Some(pool.current_num_threads());
+        }
+        self.max_threads.or_else(crate::max_threads)
+    }
 
-    #[time_graph::instrument(name="Calculator::prepare")]
+    /// Run all the calculations with this calculator inside the given rayon
+    /// thread `pool`, instead of rayon's global thread pool. This takes
+    /// precedence over the maximal number of threads set with
+    /// `set_max_threads`. Using `None` goes back to the default behavior.
+    ///
+    /// This allows applications to share the same thread pool between
+    /// rascaline and their own code, or to dedicate some of the cores to
+    /// rascaline. Alternatively, when no maximal number of threads is set,
+    /// `compute` can also be called inside `pool.install(|| ...)` to use the
+    /// corresponding pool.
+    pub fn set_thread_pool(&mut self, pool: Option<Arc<rayon::ThreadPool>>) {
+        self.thread_pool = pool;
+    }
+
+    /// Enable or disable profiling data collection for the calculations
+    /// running with this calculator, regardless of the global setting from
+    /// [`crate::profiling::enable`]. The data can then be accessed with
+    /// [`crate::profiling::report`]. This requires the `profiling` cargo
+    /// feature, and does nothing otherwise.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiling = enabled;
+    }
+
+    /// Run `function` in the thread pool given to `set_thread_pool` or using
+    /// at most `self.max_threads()` threads, collecting profiling data if
+    /// requested with `set_profiling`.
+    fn install<F, T>(&mut self, function: F) -> Result<T, Error>
+        where F: FnOnce(&mut Calculator) -> Result<T, Error> + Send,
+              T: Send,
+    {
+        if self.profiling {
+            return crate::profiling::collect(|| self.install_in_pool(function));
+        }
+
+        return self.install_in_pool(function);
+    }
+
+    fn install_in_pool<F, T>(&mut self, function: F) -> Result<T, Error>
+        where F: FnOnce(&mut Calculator) -> Result<T, Error> + Send,
+              T: Send,
+    {
+        if let Some(pool) = self.thread_pool.clone() {
+            return pool.install(|| function(self));
+        }
+
+        let max_threads = self.max_threads();
+        return crate::threads::install(max_threads, || function(self));
+    }
+
+
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name="Calculator::prepare"))]
     fn prepare(&mut self, systems: &mut [Box<dyn System>], options: CalculationOptions) -> Result<TensorMap, Error> {
+        return self.prepare_metadata(systems, options)?.allocate();
+    }
+
+    /// Get the metadata (keys, samples, components and properties) of the
+    /// descriptor and its gradients, without allocating the corresponding
+    /// data
+    fn prepare_metadata(&mut self, systems: &mut [Box<dyn System>], options: CalculationOptions) -> Result<DescriptorMetadata, Error> {
+        if let Some(max_neighbors) = options.max_neighbors {
+            self.check_max_neighbors(systems, max_neighbors)?;
+        }
+
         let default_keys = self.implementation.keys(systems)?;
         let keys = match options.selected_keys {
             Some(keys) if keys.is_empty() => {
@@ -303,26 +576,90 @@ impl Calculator {
             |block| block.samples(),
         )?;
 
+        let (keys, samples) = if options.remove_empty_blocks {
+            remove_empty_blocks(keys, samples)
+        } else {
+            (keys, samples)
+        };
+
+        let (keys, samples) = if options.sort_labels {
+            sort_labels(&keys, samples)
+        } else {
+            (keys, samples)
+        };
+
         for &parameter in options.gradients {
-            if parameter == "positions" || parameter == "cell" {
+            if parameter == "positions" || parameter == "cell" || parameter == "positions_hessian" || parameter == "density_scaling" {
                 continue;
             }
 
             return Err(Error::InvalidParameter(format!(
-                "unexpected gradient \"{}\", should be one of \"positions\" or \"cell\"",
+                "unexpected gradient \"{}\", should be one of \"positions\", \"cell\", \"positions_hessian\" or \"density_scaling\"",
                 parameter
             )));
         }
 
+        if let Some(selected_atoms) = options.selected_gradient_atoms {
+            if selected_atoms.names() != ["structure", "atom"] {
+                return Err(Error::InvalidParameter(format!(
+                    "expected [structure, atom] names for the selected gradient atoms, got [{}]",
+                    selected_atoms.names().join(", ")
+                )));
+            }
+        }
+
+        if let Some(displacement) = options.finite_differences_displacement {
+            if !(displacement > 0.0 && displacement.is_finite()) {
+                return Err(Error::InvalidParameter(format!(
+                    "expected a positive displacement for finite differences gradients, got {}",
+                    displacement
+                )));
+            }
+        }
+
         let positions_gradient_samples = if options.gradients.contains(&"positions") {
-            if !self.implementation.supports_gradient("positions") {
+            let gradient_samples = if self.implementation.supports_gradient("positions") {
+                self.implementation.positions_gradient_samples(&keys, &samples, systems)?
+            } else if options.finite_differences_displacement.is_some() {
+                // the gradients will be computed with finite differences, we
+                // don't know which atoms contribute to which sample so we
+                // include all of them
+                all_atoms_gradient_samples(&samples, systems)?
+            } else {
                 return Err(Error::InvalidParameter(format!(
                     "the {} calculator does not support gradients with respect to positions",
                     self.name()
                 )));
+            };
+
+            Some(restrict_gradient_samples(gradient_samples, options.selected_gradient_atoms, &[2]))
+        } else {
+            None
+        };
+
+        let positions_hessian_samples = if options.gradients.contains(&"positions_hessian") {
+            if !self.implementation.supports_gradient("positions_hessian") {
+                return Err(Error::InvalidParameter(format!(
+                    "the {} calculator does not support second derivatives with respect to positions",
+                    self.name()
+                )));
+            }
+
+            let hessian_samples = self.implementation.positions_hessian_samples(&keys, &samples, systems)?;
+            Some(restrict_gradient_samples(hessian_samples, options.selected_gradient_atoms, &[2, 3]))
+        } else {
+            None
+        };
+
+        let density_scaling_gradient_samples = if options.gradients.contains(&"density_scaling") {
+            if !self.implementation.supports_gradient("density_scaling") {
+                return Err(Error::InvalidParameter(format!(
+                    "the {} calculator does not support gradients with respect to the density scaling",
+                    self.name()
+                )));
             }
 
-            Some(self.implementation.positions_gradient_samples(&keys, &samples, systems)?)
+            Some(self.implementation.density_scaling_gradient_samples(&keys, &samples, systems)?)
         } else {
             None
         };
@@ -363,6 +700,658 @@ impl Calculator {
         assert_eq!(keys.count(), components.len());
         assert_eq!(keys.count(), properties.len());
 
+        return Ok(DescriptorMetadata {
+            keys: keys,
+            samples: samples,
+            components: components,
+            properties: properties,
+            positions_gradient_samples: positions_gradient_samples,
+            positions_hessian_samples: positions_hessian_samples,
+            density_scaling_gradient_samples: density_scaling_gradient_samples,
+            cell_gradient_samples: cell_gradient_samples,
+        });
+    }
+
+    /// Check that no atom in the `systems` has more than `max_neighbors`
+    /// neighbors within the cutoff of this calculator
+    fn check_max_neighbors(&self, systems: &mut [Box<dyn System>], max_neighbors: usize) -> Result<(), Error> {
+        let cutoff = match self.implementation.cutoff() {
+            Some(cutoff) => cutoff,
+            None => return Ok(()),
+        };
+
+        for (system_i, system) in systems.iter_mut().enumerate() {
+            system.compute_neighbors(cutoff)?;
+            for atom in 0..system.size()? {
+                let count = system.pairs_containing(atom)?.len();
+                if count > max_neighbors {
+                    return Err(Error::InvalidParameter(format!(
+                        "atom {} in system {} has {} neighbors within the cutoff of {}, \
+                        more than the maximal number of neighbors ({}). Check that \
+                        the positions and the cutoff use the same units, and that \
+                        there are no overlapping atoms",
+                        atom, system_i, count, cutoff, max_neighbors
+                    )));
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Get the metadata (keys, samples, components, properties and gradients
+    /// samples) of the descriptor that `compute` would produce for the given
+    /// `systems` and `options`, without running the calculation.
+    ///
+    /// The blocks of the returned `TensorMap` contain `EmptyArray` data with
+    /// the right shape, and no memory is allocated for the values or the
+    /// gradients. This can be used to allocate and shard the data before
+    /// running the actual calculation.
+    pub fn compute_metadata(
+        &mut self,
+        systems: &mut [Box<dyn System>],
+        options: CalculationOptions,
+    ) -> Result<TensorMap, Error> {
+        return self.install(|calculator| {
+            let metadata = with_selected_structures(systems, options.selected_structures, |systems| {
+                calculator.prepare_metadata(systems, options)
+            })?;
+
+            return metadata.empty();
+        });
+    }
+
+    /// Estimate the size of the descriptor that `compute` would produce for
+    /// the given `systems` and `options`, without running the calculation.
+    ///
+    /// This computes the metadata of the descriptor (which includes running
+    /// the neighbors list for most calculators), but does not allocate or
+    /// fill the arrays.
+    pub fn estimate(
+        &mut self,
+        systems: &mut [Box<dyn System>],
+        options: CalculationOptions,
+    ) -> Result<CostEstimate, Error> {
+        return self.install(|calculator| calculator.estimate_impl(systems, options));
+    }
+
+    fn estimate_impl(
+        &mut self,
+        systems: &mut [Box<dyn System>],
+        options: CalculationOptions,
+    ) -> Result<CostEstimate, Error> {
+        let (metadata, work) = with_selected_structures(systems, options.selected_structures, |systems| {
+            let metadata = self.prepare_metadata(systems, options)?;
+            let work = self.implementation.work_estimate(systems)?;
+            return Ok((metadata, work));
+        })?;
+
+        let mut estimate = CostEstimate {
+            blocks: metadata.keys.count(),
+            work: work,
+            ..Default::default()
+        };
+
+        for block_i in 0..metadata.keys.count() {
+            let n_components = metadata.components[block_i].iter().map(|c| c.count()).product::<usize>();
+            let n_properties = metadata.properties[block_i].count();
+            let row_size = n_components * n_properties;
+
+            let n_samples = metadata.samples[block_i].count();
+            estimate.samples += n_samples;
+            estimate.properties += n_properties;
+            estimate.values += n_samples * row_size;
+
+            let gradients = [
+                (&metadata.positions_gradient_samples, 3),
+                (&metadata.positions_hessian_samples, 9),
+                (&metadata.density_scaling_gradient_samples, 1),
+                (&metadata.cell_gradient_samples, 9),
+            ];
+            for (gradient_samples, directions) in gradients {
+                if let Some(gradient_samples) = gradient_samples {
+                    let n_gradient_samples = gradient_samples[block_i].count();
+                    estimate.gradient_samples += n_gradient_samples;
+                    estimate.gradient_values += n_gradient_samples * directions * row_size;
+                }
+            }
+        }
+
+        estimate.memory = (estimate.values + estimate.gradient_values) * std::mem::size_of::<f64>();
+
+        return Ok(estimate);
+    }
+
+    /// Compute the descriptor for all the given `systems` and store it in
+    /// `descriptor`
+    ///
+    /// This function computes the full descriptor, using all samples and all
+    /// features. The calculation runs in the thread pool given to
+    /// `set_thread_pool`, or uses at most `self.max_threads()` threads.
+    pub fn compute(
+        &mut self,
+        systems: &mut [Box<dyn System>],
+        options: CalculationOptions,
+    ) -> Result<TensorMap, Error> {
+        return self.install(|calculator| calculator.compute_impl(systems, options));
+    }
+
+    fn compute_impl(
+        &mut self,
+        systems: &mut [Box<dyn System>],
+        options: CalculationOptions,
+    ) -> Result<TensorMap, Error> {
+        return with_selected_structures(systems, options.selected_structures, |systems| {
+            let mut native_systems;
+            let systems = if options.use_native_system {
+                native_systems = Vec::with_capacity(systems.len());
+                for system in systems {
+                    native_systems.push(Box::new(SimpleSystem::try_from(&**system)?) as Box<dyn System>);
+                }
+                &mut native_systems
+            } else {
+                systems
+            };
+
+            let mut tensor = self.prepare(systems, options)?;
+            self.run(systems, &mut tensor, options)?;
+
+            return Ok(tensor);
+        });
+    }
+
+    /// Compute the descriptor for all the given `systems`, storing it in
+    /// `descriptor`.
+    ///
+    /// `descriptor` should typically come from a previous call to `compute`
+    /// or `compute_into`. If its metadata (keys, samples, components,
+    /// properties and gradients) is the same as the metadata of the new
+    /// descriptor, the existing memory is re-used and the data is overwritten
+    /// in place. This avoids allocating new arrays at every step of a
+    /// molecular dynamics simulation. Otherwise, `descriptor` is replaced with
+    /// a newly allocated descriptor.
+    pub fn compute_into(
+        &mut self,
+        systems: &mut [Box<dyn System>],
+        descriptor: &mut TensorMap,
+        options: CalculationOptions,
+    ) -> Result<(), Error> {
+        return self.install(|calculator| calculator.compute_into_impl(systems, descriptor, options));
+    }
+
+    fn compute_into_impl(
+        &mut self,
+        systems: &mut [Box<dyn System>],
+        descriptor: &mut TensorMap,
+        options: CalculationOptions,
+    ) -> Result<(), Error> {
+        return with_selected_structures(systems, options.selected_structures, |systems| {
+            let mut native_systems;
+            let systems = if options.use_native_system {
+                native_systems = Vec::with_capacity(systems.len());
+                for system in systems {
+                    native_systems.push(Box::new(SimpleSystem::try_from(&**system)?) as Box<dyn System>);
+                }
+                &mut native_systems
+            } else {
+                systems
+            };
+
+            let metadata = self.prepare_metadata(systems, options)?;
+            if metadata.matches(descriptor) {
+                for (_, mut block) in descriptor.iter_mut() {
+                    block.data_mut().values.to_array_mut().fill(0.0);
+                    for parameter in ["positions", "positions_hessian", "density_scaling", "cell"] {
+                        if let Some(mut gradient) = block.gradient_mut(parameter) {
+                            gradient.data_mut().values.to_array_mut().fill(0.0);
+                        }
+                    }
+                }
+            } else {
+                *descriptor = metadata.allocate()?;
+            }
+
+            return self.run(systems, descriptor, options);
+        });
+    }
+
+    /// Run the calculation for the `systems`, filling the pre-allocated
+    /// `tensor`
+    fn run(
+        &mut self,
+        systems: &mut [Box<dyn System>],
+        tensor: &mut TensorMap,
+        options: CalculationOptions,
+    ) -> Result<(), Error> {
+        self.implementation.compute(systems, tensor)?;
+
+        if let Some(displacement) = options.finite_differences_displacement {
+            if options.gradients.contains(&"positions") && !self.implementation.supports_gradient("positions") {
+                self.finite_differences_positions_gradients(systems, tensor, displacement)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Fill the gradients with respect to positions in `descriptor` using
+    /// central finite differences of the values, displacing each atom by
+    /// `displacement` in total.
+    fn finite_differences_positions_gradients(
+        &mut self,
+        systems: &mut [Box<dyn System>],
+        descriptor: &mut TensorMap,
+        displacement: f64,
+    ) -> Result<(), Error> {
+        let mut gradient_atoms = BTreeSet::new();
+        for (_, block) in descriptor.iter() {
+            let gradient = block.gradient("positions").expect("missing positions gradients");
+            for &[_, structure, atom] in gradient.samples().iter_fixed_size() {
+                gradient_atoms.insert((structure.usize(), atom.usize()));
+            }
+        }
+
+        for (structure_i, atom_i) in gradient_atoms {
+            for spatial in 0..3 {
+                // compute the values for the displaced structure, using the
+                // same keys and properties as the full descriptor. The
+                // displaced structure will be structure 0 in the output.
+                let mut displaced = Vec::new();
+                for sign in [1.0, -1.0] {
+                    let mut system = SimpleSystem::try_from(&*systems[structure_i])?;
+                    system.positions_mut()[atom_i][spatial] += 0.5 * sign * displacement;
+
+                    let options = CalculationOptions {
+                        selected_properties: LabelsSelection::Predefined(&*descriptor),
+                        selected_keys: Some(descriptor.keys()),
+                        ..Default::default()
+                    };
+                    displaced.push(self.compute(&mut [Box::new(system) as Box<dyn System>], options)?);
+                }
+
+                for (block_i, (_, mut block)) in descriptor.iter_mut().enumerate() {
+                    let samples = block.samples();
+                    let structure_column = samples.names().iter()
+                        .position(|&name| name == "structure")
+                        .expect("missing structure in samples");
+
+                    let block_plus = displaced[0].block_by_id(block_i);
+                    let block_minus = displaced[1].block_by_id(block_i);
+                    let samples_plus = block_plus.samples();
+                    let samples_minus = block_minus.samples();
+                    let values_plus = block_plus.values().to_array();
+                    let values_minus = block_minus.values().to_array();
+
+                    let mut gradient = block.gradient_mut("positions").expect("missing positions gradients");
+                    let gradient = gradient.data_mut();
+                    let gradient_values = gradient.values.to_array_mut();
+
+                    for (grad_sample_i, &[sample_i, structure, atom]) in gradient.samples.iter_fixed_size().enumerate() {
+                        if structure.usize() != structure_i || atom.usize() != atom_i {
+                            continue;
+                        }
+
+                        let mut sample = samples[sample_i.usize()].to_vec();
+                        sample[structure_column] = LabelValue::new(0);
+
+                        let mut gradient_row = gradient_values.index_axis_mut(Axis(0), grad_sample_i);
+                        let mut gradient_row = gradient_row.index_axis_mut(Axis(0), spatial);
+                        // samples missing from the displaced calculations
+                        // have a value of zero
+                        if let Some(position) = samples_plus.position(&sample) {
+                            gradient_row.scaled_add(1.0 / displacement, &values_plus.index_axis(Axis(0), position));
+                        }
+
+                        if let Some(position) = samples_minus.position(&sample) {
+                            gradient_row.scaled_add(-1.0 / displacement, &values_minus.index_axis(Axis(0), position));
+                        }
+                    }
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Update `descriptor`, previously computed by this calculator for the
+    /// same `systems`, after some of the atoms moved.
+    ///
+    /// `moved_atoms` must contain the `"structure"` and `"atom"` indexes of
+    /// all the atoms which moved since the last calculation, and `skin` the
+    /// maximal distance any of these atoms moved. Only the samples centered on
+    /// atoms within `cutoff + skin` of a moved atom are re-computed, and the
+    /// corresponding values are replaced in place in `descriptor`; all other
+    /// samples are left untouched.
+    ///
+    /// This is only supported for calculators with atom-centered samples and
+    /// for descriptors without gradients. If the moved atoms create new keys
+    /// or new samples (for example if an atom gets its first neighbor of a
+    /// given species), the descriptor can not be updated in place and this
+    /// function returns an error; the descriptor should then be re-computed
+    /// from scratch with `compute`.
+    #[allow(clippy::too_many_lines)]
+    pub fn update(
+        &mut self,
+        systems: &mut [Box<dyn System>],
+        descriptor: &mut TensorMap,
+        moved_atoms: &Labels,
+        skin: f64,
+    ) -> Result<(), Error> {
+        if moved_atoms.names() != ["structure", "atom"] {
+            return Err(Error::InvalidParameter(format!(
+                "moved atoms must have [\"structure\", \"atom\"] names, got [{}]",
+                moved_atoms.names().iter().map(|name| format!("\"{}\"", name)).collect::<Vec<_>>().join(", ")
+            )));
+        }
+
+        if !skin.is_finite() || skin < 0.0 {
+            return Err(Error::InvalidParameter(format!(
+                "skin must be a positive number, got {}", skin
+            )));
+        }
+
+        let samples_names = self.implementation.samples_names();
+        if samples_names.len() < 2 || samples_names[..2] != ["structure", "center"] {
+            return Err(Error::InvalidParameter(format!(
+                "{} does not use atom-centered samples and can not be updated in place",
+                self.name()
+            )));
+        }
+
+        for (_, block) in descriptor.iter() {
+            if GRADIENTS.iter().any(|&parameter| block.gradient(parameter).is_some()) {
+                return Err(Error::InvalidParameter(
+                    "descriptors containing gradients can not be updated in place".into()
+                ));
+            }
+        }
+
+        // find all the centers affected by the move: the moved atoms
+        // themselves, and all the atoms which are or were neighbors of the
+        // moved atoms
+        let mut moved_by_system = BTreeMap::new();
+        for &[structure, atom] in moved_atoms.iter_fixed_size() {
+            let structure = structure.usize();
+            if structure >= systems.len() {
+                return Err(Error::InvalidParameter(format!(
+                    "moved atom is in structure {}, but we only have {} systems",
+                    structure, systems.len()
+                )));
+            }
+
+            let atom = atom.usize();
+            if atom >= systems[structure].size()? {
+                return Err(Error::InvalidParameter(format!(
+                    "moved atom {} is out of bounds for structure {} with {} atoms",
+                    atom, structure, systems[structure].size()?
+                )));
+            }
+
+            moved_by_system.entry(structure).or_insert_with(BTreeSet::new).insert(atom);
+        }
+
+        let mut affected = BTreeSet::new();
+        for (&structure, moved) in &moved_by_system {
+            let system = &mut systems[structure];
+            if let Some(cutoff) = self.implementation.cutoff() {
+                system.compute_neighbors(cutoff + skin)?;
+                for pair in system.pairs()? {
+                    if moved.contains(&pair.first) {
+                        affected.insert((structure, pair.second));
+                    }
+                    if moved.contains(&pair.second) {
+                        affected.insert((structure, pair.first));
+                    }
+                }
+                affected.extend(moved.iter().map(|&atom| (structure, atom)));
+            } else {
+                // without a cutoff, all atoms can be affected
+                affected.extend((0..system.size()?).map(|atom| (structure, atom)));
+            }
+        }
+
+        if affected.is_empty() {
+            return Ok(());
+        }
+
+        for key in &self.implementation.keys(systems)? {
+            if descriptor.keys().position(key).is_none() {
+                return Err(Error::InvalidParameter(
+                    "the moved atoms created new blocks, the descriptor must be re-computed".into()
+                ));
+            }
+        }
+
+        let mut selected_samples = LabelsBuilder::new(vec!["structure", "center"]);
+        for &(structure, center) in &affected {
+            selected_samples.add(&[structure.into(), center.into()]);
+        }
+        let selected_samples = selected_samples.finish();
+
+        let options = CalculationOptions {
+            selected_samples: LabelsSelection::Subset(&selected_samples),
+            selected_properties: LabelsSelection::Predefined(&*descriptor),
+            selected_keys: Some(descriptor.keys()),
+            ..Default::default()
+        };
+        let updated = self.compute(systems, options)?;
+
+        // check that all the updated samples already exist before modifying
+        // anything in the descriptor
+        for (block_i, (_, block)) in updated.iter().enumerate() {
+            let samples = descriptor.block_by_id(block_i).samples();
+            for sample in block.samples().iter() {
+                if samples.position(sample).is_none() {
+                    return Err(Error::InvalidParameter(
+                        "the moved atoms created new samples, the descriptor must be re-computed".into()
+                    ));
+                }
+            }
+        }
+
+        for (block_i, (_, mut block)) in descriptor.iter_mut().enumerate() {
+            let updated_block = updated.block_by_id(block_i);
+            let updated_samples = updated_block.samples();
+            let updated_values = updated_block.values().to_array();
+
+            let block = block.data_mut();
+            let values = block.values.to_array_mut();
+            for (sample_i, sample) in block.samples.iter().enumerate() {
+                if !affected.contains(&(sample[0].usize(), sample[1].usize())) {
+                    continue;
+                }
+
+                let mut row = values.index_axis_mut(Axis(0), sample_i);
+                // samples which are no longer present after the move (e.g.
+                // without neighbors of a given species) have a value of zero
+                match updated_samples.position(sample) {
+                    Some(position) => row.assign(&updated_values.index_axis(Axis(0), position)),
+                    None => row.fill(0.0),
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Compute the descriptor for all the given `systems` in multiple chunks
+    /// of systems, calling `callback` with the descriptor for each chunk.
+    ///
+    /// The chunks are built such that the memory used by the values and
+    /// gradients of each chunk stays below `max_memory` bytes. The memory
+    /// needed per atom is estimated from the chunks computed so far, and the
+    /// first chunk contains a single system. A chunk always contains at least
+    /// one system, even if it does not fit in the budget.
+    ///
+    /// `callback` is called with the index of the first system in the chunk,
+    /// and the corresponding descriptor. The `"structure"` samples in this
+    /// descriptor are relative to the first system of the chunk. This can be
+    /// used to store the results on disk or to process them on the fly, for
+    /// datasets which do not fit in memory all at once.
+    ///
+    /// The `options` are used for all chunks. Samples selections and selected
+    /// gradient atoms containing a `"structure"` variable refer to the full
+    /// list of systems, and are adjusted for each chunk. Predefined samples
+    /// and predicate selections are not supported.
+    pub fn compute_chunked<F>(
+        &mut self,
+        systems: &mut [Box<dyn System>],
+        options: CalculationOptions,
+        max_memory: usize,
+        mut callback: F,
+    ) -> Result<(), Error>
+        where F: FnMut(usize, TensorMap) -> Result<(), Error>
+    {
+        if max_memory == 0 {
+            return Err(Error::InvalidParameter(
+                "the memory budget for chunked calculations must be larger than 0".into()
+            ));
+        }
+
+        if let LabelsSelection::Predefined(_) = options.selected_samples {
+            return Err(Error::InvalidParameter(
+                "predefined samples selection is not supported with chunked calculations".into()
+            ));
+        }
+
+        if let LabelsSelection::Predicate(_) = options.selected_samples {
+            return Err(Error::InvalidParameter(
+                "predicate samples selection is not supported with chunked calculations".into()
+            ));
+        }
+
+        let mut bytes_per_atom: Option<f64> = None;
+        let mut start = 0;
+        while start < systems.len() {
+            let mut n_atoms = systems[start].size()?;
+            let mut stop = start + 1;
+            if let Some(bytes_per_atom) = bytes_per_atom {
+                while stop < systems.len() {
+                    let size = systems[stop].size()?;
+                    if (n_atoms + size) as f64 * bytes_per_atom > max_memory as f64 {
+                        break;
+                    }
+                    n_atoms += size;
+                    stop += 1;
+                }
+            }
+
+            let selected_samples = match options.selected_samples {
+                LabelsSelection::Subset(selection) => vec![chunk_selection(selection, start, stop)],
+                LabelsSelection::SubsetByKey(_, selections) => {
+                    selections.iter().map(|selection| chunk_selection(selection, start, stop)).collect()
+                }
+                _ => Vec::new(),
+            };
+            let selected_gradient_atoms = options.selected_gradient_atoms.map(|selection| {
+                chunk_selection(selection, start, stop)
+            });
+            let selected_structures = options.selected_structures.map(|selection| {
+                selection.iter()
+                    .filter(|&&structure| structure >= start && structure < stop)
+                    .map(|&structure| structure - start)
+                    .collect::<Vec<_>>()
+            });
+
+            let chunk_options = CalculationOptions {
+                selected_samples: match options.selected_samples {
+                    LabelsSelection::Subset(_) => LabelsSelection::Subset(&selected_samples[0]),
+                    LabelsSelection::SubsetByKey(keys, _) => LabelsSelection::SubsetByKey(keys, &selected_samples),
+                    LabelsSelection::Centers(centers) => {
+                        let chunk_start = usize::min(start, centers.len());
+                        let chunk_stop = usize::min(stop, centers.len());
+                        LabelsSelection::Centers(&centers[chunk_start..chunk_stop])
+                    }
+                    selection => selection,
+                },
+                selected_gradient_atoms: selected_gradient_atoms.as_ref(),
+                selected_structures: selected_structures.as_deref(),
+                ..options
+            };
+            let descriptor = self.compute(&mut systems[start..stop], chunk_options)?;
+
+            let chunk_bytes_per_atom = tensor_memory(&descriptor) as f64 / usize::max(n_atoms, 1) as f64;
+            bytes_per_atom = Some(bytes_per_atom.map_or(chunk_bytes_per_atom, |previous| {
+                f64::max(previous, chunk_bytes_per_atom)
+            }));
+
+            callback(start, descriptor)?;
+            start = stop;
+        }
+
+        return Ok(());
+    }
+}
+
+/// Result of [`Calculator::estimate`], describing the size of a descriptor
+/// before computing it. All the counts are summed over the blocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CostEstimate {
+    /// Number of blocks in the descriptor
+    pub blocks: usize,
+    /// Total number of samples
+    pub samples: usize,
+    /// Total number of properties
+    pub properties: usize,
+    /// Total number of values, i.e. the number of samples times the number
+    /// of components and properties for each block
+    pub values: usize,
+    /// Total number of gradient samples, for all the requested gradients
+    pub gradient_samples: usize,
+    /// Total number of gradient values, for all the requested gradients
+    pub gradient_values: usize,
+    /// Approximate memory needed to store the values and gradients, in bytes
+    pub memory: usize,
+    /// Amount of work needed to compute the values, in arbitrary units
+    /// roughly proportional to the number of floating point operations. This
+    /// does not include the gradients, and is only comparable between
+    /// calculations using the same calculator. This is `None` if the
+    /// calculator does not provide an estimate, which is currently the case
+    /// for all calculators except the spherical expansion.
+    pub work: Option<usize>,
+}
+
+/// Metadata of a descriptor and its gradients, computed before allocating
+/// the corresponding data
+struct DescriptorMetadata {
+    keys: Labels,
+    samples: Vec<Labels>,
+    components: Vec<Vec<Labels>>,
+    properties: Vec<Labels>,
+    positions_gradient_samples: Option<Vec<Labels>>,
+    positions_hessian_samples: Option<Vec<Labels>>,
+    density_scaling_gradient_samples: Option<Vec<Labels>>,
+    cell_gradient_samples: Option<Vec<Labels>>,
+}
+
+impl DescriptorMetadata {
+    /// Allocate a new `TensorMap` with this metadata, filled with zeros
+    fn allocate(self) -> Result<TensorMap, Error> {
+        return self.build(|shape| ArrayD::from_elem(shape, 0.0));
+    }
+
+    /// Create a new `TensorMap` with this metadata, without allocating any
+    /// memory for the values and gradients
+    fn empty(self) -> Result<TensorMap, Error> {
+        return self.build(EmptyArray::new);
+    }
+
+    /// Create a new `TensorMap` with this metadata, using `create_array` to
+    /// create the arrays for the values and gradients with a given shape
+    fn build<A, F>(self, create_array: F) -> Result<TensorMap, Error>
+        where A: equistore::Array, F: Fn(Vec<usize>) -> A
+    {
+        let DescriptorMetadata {
+            keys,
+            samples,
+            components,
+            properties,
+            positions_gradient_samples,
+            positions_hessian_samples,
+            density_scaling_gradient_samples,
+            cell_gradient_samples,
+        } = self;
+
         let direction = Labels::new(["direction"], &[[0], [1], [2]]);
         let direction_1 = Labels::new(["direction_1"], &[[0], [1], [2]]);
         let direction_2 = Labels::new(["direction_2"], &[[0], [1], [2]]);
@@ -373,7 +1362,7 @@ impl Calculator {
                 &samples, &components, &properties
             );
             let mut new_block = TensorBlock::new(
-                ArrayD::from_elem(shape, 0.0),
+                create_array(shape),
                 &samples,
                 &components,
                 &properties,
@@ -393,7 +1382,49 @@ impl Calculator {
                 new_block.add_gradient(
                     "positions",
                     TensorBlock::new(
-                        ArrayD::from_elem(shape, 0.0),
+                        create_array(shape),
+                        gradient_samples,
+                        &components,
+                        &properties
+                    ).expect("generated invalid gradient")
+                ).expect("generated invalid gradient");
+            }
+
+            if let Some(ref hessian_samples) = positions_hessian_samples {
+                let hessian_samples = &hessian_samples[block_i];
+                assert_eq!(hessian_samples.names(), ["sample", "structure", "atom_1", "atom_2"]);
+
+                // add the components for both atoms
+                let mut components = components.clone();
+                components.insert(0, direction_2.clone());
+                components.insert(0, direction_1.clone());
+                let shape = shape_from_labels(
+                    hessian_samples, &components, &properties
+                );
+
+                new_block.add_gradient(
+                    "positions_hessian",
+                    TensorBlock::new(
+                        create_array(shape),
+                        hessian_samples,
+                        &components,
+                        &properties
+                    ).expect("generated invalid gradient")
+                ).expect("generated invalid gradient");
+            }
+
+            if let Some(ref gradient_samples) = density_scaling_gradient_samples {
+                let gradient_samples = &gradient_samples[block_i];
+                assert_eq!(gradient_samples.names(), ["sample", "structure", "atom"]);
+
+                let shape = shape_from_labels(
+                    gradient_samples, &components, &properties
+                );
+
+                new_block.add_gradient(
+                    "density_scaling",
+                    TensorBlock::new(
+                        create_array(shape),
                         gradient_samples,
                         &components,
                         &properties
@@ -415,7 +1446,7 @@ impl Calculator {
                 new_block.add_gradient(
                     "cell",
                     TensorBlock::new(
-                        ArrayD::from_elem(shape, 0.0),
+                        create_array(shape),
                         gradient_samples,
                         &components,
                         &properties
@@ -429,33 +1460,289 @@ impl Calculator {
         return Ok(TensorMap::new(keys, blocks)?);
     }
 
-    /// Compute the descriptor for all the given `systems` and store it in
-    /// `descriptor`
-    ///
-    /// This function computes the full descriptor, using all samples and all
-    /// features.
-    pub fn compute(
-        &mut self,
-        systems: &mut [Box<dyn System>],
-        options: CalculationOptions,
-    ) -> Result<TensorMap, Error> {
-        let mut native_systems;
-        let systems = if options.use_native_system {
-            native_systems = Vec::with_capacity(systems.len());
-            for system in systems {
-                native_systems.push(Box::new(SimpleSystem::try_from(&**system)?) as Box<dyn System>);
+    /// Check if `descriptor` already has exactly this metadata, including
+    /// the same set of gradients
+    fn matches(&self, descriptor: &TensorMap) -> bool {
+        if *descriptor.keys() != self.keys {
+            return false;
+        }
+
+        let gradients = [
+            ("positions", &self.positions_gradient_samples),
+            ("positions_hessian", &self.positions_hessian_samples),
+            ("density_scaling", &self.density_scaling_gradient_samples),
+            ("cell", &self.cell_gradient_samples),
+        ];
+
+        for (block_i, (_, block)) in descriptor.iter().enumerate() {
+            if block.samples() != self.samples[block_i]
+                || block.components() != self.components[block_i]
+                || block.properties() != self.properties[block_i] {
+                return false;
+            }
+
+            for (parameter, gradient_samples) in gradients {
+                let matching = match (block.gradient(parameter), gradient_samples) {
+                    (Some(gradient), Some(gradient_samples)) => gradient.samples() == gradient_samples[block_i],
+                    (None, None) => true,
+                    _ => false,
+                };
+
+                if !matching {
+                    return false;
+                }
+            }
+        }
+
+        return true;
+    }
+}
+
+/// Only keep the entries in `labels` matching one of the entries in
+/// `selection`, see `LabelsSelection::Subset` for the corresponding rules.
+/// `default_names` are the names of `labels`.
+fn select_subset(label_kind: &str, selection: &Labels, default_names: &[&str], labels: &Labels) -> Result<Labels, Error> {
+    let mut builder = LabelsBuilder::new(default_names.to_vec());
+    if selection.names() == default_names {
+        for entry in selection.iter() {
+            if labels.contains(entry) {
+                builder.add(entry);
+            }
+        }
+        return Ok(builder.finish());
+    }
+
+    let mut variables_to_match = Vec::new();
+    for variable in selection.names() {
+        let i = match default_names.iter().position(|&v| v == variable) {
+            Some(index) => index,
+            None => {
+                return Err(Error::InvalidParameter(format!(
+                    "'{}' in {} selection is not one of the {} of this calculator",
+                    variable, label_kind, label_kind
+                )))
             }
-            &mut native_systems
-        } else {
-            systems
         };
+        variables_to_match.push(i);
+    }
+
+    for entry in labels.iter() {
+        for selected in selection.iter() {
+            let mut matches = true;
+            for (i, &v) in variables_to_match.iter().enumerate() {
+                if selected[i] != entry[v] {
+                    matches = false;
+                    break;
+                }
+            }
+
+            if matches {
+                builder.add(entry);
+            }
+        }
+    }
+
+    return Ok(builder.finish());
+}
+
+/// Remove the entries in `keys` (and the corresponding entries in `samples`)
+/// without any samples. If all the entries are empty, they are all kept.
+fn remove_empty_blocks(keys: Labels, samples: Vec<Labels>) -> (Labels, Vec<Labels>) {
+    if samples.iter().all(|samples| samples.count() == 0) {
+        return (keys, samples);
+    }
+
+    let mut new_keys = LabelsBuilder::new(keys.names());
+    let mut new_samples = Vec::new();
+    for (key, samples) in keys.iter().zip(samples) {
+        if samples.count() != 0 {
+            new_keys.add(key);
+            new_samples.push(samples);
+        }
+    }
+
+    return (new_keys.finish(), new_samples);
+}
+
+/// Sort the entries in `keys` (and the corresponding entries in `samples`), as
+/// well as the entries inside each set of `samples`, in lexicographic order
+fn sort_labels(keys: &Labels, samples: Vec<Labels>) -> (Labels, Vec<Labels>) {
+    let mut blocks = keys.iter()
+        .map(|key| key.iter().map(|value| value.i32()).collect::<Vec<_>>())
+        .zip(samples)
+        .collect::<Vec<_>>();
+    blocks.sort_by(|(first, _), (second, _)| first.cmp(second));
 
-        let mut tensor = self.prepare(systems, options)?;
+    let mut new_keys = LabelsBuilder::new(keys.names());
+    let mut new_samples = Vec::new();
+    for (key, samples) in blocks {
+        new_keys.add(&key);
 
-        self.implementation.compute(systems, &mut tensor)?;
+        let mut entries = samples.iter()
+            .map(|sample| sample.iter().map(|value| value.i32()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        entries.sort_unstable();
 
-        return Ok(tensor);
+        let mut sorted = LabelsBuilder::new(samples.names());
+        for entry in entries {
+            sorted.add(&entry);
+        }
+        new_samples.push(sorted.finish());
     }
+
+    return (new_keys.finish(), new_samples);
+}
+
+/// Call `function` with the systems which are not part of `selected_structures`
+/// replaced by empty systems, such that the selected systems keep their
+/// position (and structure index) in the slice. The original systems are put
+/// back before returning, even if `function` panics. If `selected_structures`
+/// is `None`, `function` is called with all the systems.
+fn with_selected_structures<T, F>(
+    systems: &mut [Box<dyn System>],
+    selected_structures: Option<&[usize]>,
+    function: F,
+) -> Result<T, Error>
+    where F: FnOnce(&mut [Box<dyn System>]) -> Result<T, Error>
+{
+    let selected_structures = match selected_structures {
+        Some(selected) => selected.iter().copied().collect::<BTreeSet<_>>(),
+        None => return function(systems),
+    };
+
+    if let Some(&structure) = selected_structures.iter().next_back() {
+        if structure >= systems.len() {
+            return Err(Error::InvalidParameter(format!(
+                "selected structure {} is out of bounds, we only have {} systems",
+                structure, systems.len()
+            )));
+        }
+    }
+
+    // get all the cells first, to make sure we don't return early with some
+    // systems missing. The empty systems keep the same cell as the original
+    // ones, since some calculators can not handle all kinds of cells.
+    let mut cells = Vec::new();
+    for (structure, system) in systems.iter().enumerate() {
+        if !selected_structures.contains(&structure) {
+            cells.push((structure, system.cell()?));
+        }
+    }
+
+    let mut removed = Vec::with_capacity(cells.len());
+    for (structure, cell) in cells {
+        let empty = Box::new(SimpleSystem::new(cell)) as Box<dyn System>;
+        removed.push((structure, std::mem::replace(&mut systems[structure], empty)));
+    }
+
+    let guard = RestoreSystems {
+        systems: systems,
+        removed: removed,
+    };
+
+    return function(&mut *guard.systems);
+}
+
+/// Guard putting the systems removed by `with_selected_structures` back in
+/// their original position when dropped
+struct RestoreSystems<'a> {
+    systems: &'a mut [Box<dyn System>],
+    removed: Vec<(usize, Box<dyn System>)>,
+}
+
+impl Drop for RestoreSystems<'_> {
+    fn drop(&mut self) {
+        for (structure, system) in self.removed.drain(..) {
+            self.systems[structure] = system;
+        }
+    }
+}
+
+/// Restrict the selection `labels` to the systems in `start..stop`, making the
+/// `"structure"` variable (if any) relative to `start`.
+fn chunk_selection(labels: &Labels, start: usize, stop: usize) -> Labels {
+    let structure_column = if let Some(column) = labels.names().iter().position(|&name| name == "structure") {
+        column
+    } else {
+        return labels.clone();
+    };
+
+    let mut builder = LabelsBuilder::new(labels.names());
+    for entry in labels {
+        let structure = entry[structure_column].usize();
+        if structure >= start && structure < stop {
+            let mut entry = entry.to_vec();
+            entry[structure_column] = (structure - start).into();
+            builder.add(&entry);
+        }
+    }
+
+    return builder.finish();
+}
+
+/// Get the number of bytes used by the values and gradients in `tensor`
+fn tensor_memory(tensor: &TensorMap) -> usize {
+    let mut n_elements = 0;
+    for (_, block) in tensor.iter() {
+        n_elements += block.values().to_array().len();
+        for parameter in GRADIENTS {
+            if let Some(gradient) = block.gradient(parameter) {
+                n_elements += gradient.values().to_array().len();
+            }
+        }
+    }
+
+    return n_elements * std::mem::size_of::<f64>();
+}
+
+/// Only keep the gradient samples where all the atoms (in the columns given by
+/// `atom_columns`) are part of `selected_atoms`. The structure is always in the
+/// second column of the gradient samples.
+fn restrict_gradient_samples(all_samples: Vec<Labels>, selected_atoms: Option<&Labels>, atom_columns: &[usize]) -> Vec<Labels> {
+    let selected_atoms = if let Some(selected_atoms) = selected_atoms {
+        selected_atoms
+    } else {
+        return all_samples;
+    };
+
+    return all_samples.iter().map(|samples| {
+        let mut builder = LabelsBuilder::new(samples.names());
+        for sample in samples {
+            let structure = sample[1];
+            let all_selected = atom_columns.iter().all(|&column| {
+                selected_atoms.position(&[structure, sample[column]]).is_some()
+            });
+
+            if all_selected {
+                builder.add(sample);
+            }
+        }
+        builder.finish()
+    }).collect();
+}
+
+/// Get gradient samples containing all the atoms in the structure of each
+/// sample, for gradients computed with finite differences.
+fn all_atoms_gradient_samples(samples: &[Labels], systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
+    let mut gradient_samples = Vec::new();
+    for samples in samples {
+        let structure_column = samples.names().iter()
+            .position(|&name| name == "structure")
+            .ok_or_else(|| Error::InvalidParameter(
+                "samples must contain a \"structure\" variable to compute finite differences gradients".into()
+            ))?;
+
+        let mut builder = LabelsBuilder::new(vec!["sample", "structure", "atom"]);
+        for (sample_i, sample) in samples.iter().enumerate() {
+            let structure = sample[structure_column];
+            for atom in 0..systems[structure.usize()].size()? {
+                builder.add(&[sample_i.into(), structure, atom.into()]);
+            }
+        }
+        gradient_samples.push(builder.finish());
+    }
+
+    return Ok(gradient_samples);
 }
 
 fn shape_from_labels(samples: &Labels, components: &[Labels], properties: &Labels) -> Vec<usize> {
@@ -476,11 +1763,16 @@ use crate::calculators::AtomicComposition;
 use crate::calculators::DummyCalculator;
 use crate::calculators::SortedDistances;
 use crate::calculators::NeighborList;
+use crate::calculators::BondFeatures;
+use crate::calculators::PermutationInvariantVector;
+use crate::calculators::CommonNeighborAnalysis;
+use crate::calculators::CentroSymmetry;
 use crate::calculators::{SphericalExpansionByPair, SphericalExpansionParameters};
 use crate::calculators::SphericalExpansion;
 use crate::calculators::{SoapPowerSpectrum, PowerSpectrumParameters};
 use crate::calculators::{SoapRadialSpectrum, RadialSpectrumParameters};
 use crate::calculators::{LodeSphericalExpansion, LodeSphericalExpansionParameters};
+use crate::calculators::EwaldDipolarTensor;
 type CalculatorCreator = fn(&str) -> Result<Box<dyn CalculatorBase>, Error>;
 
 macro_rules! add_calculator {
@@ -507,6 +1799,11 @@ static REGISTERED_CALCULATORS: Lazy<BTreeMap<&'static str, CalculatorCreator>> =
     add_calculator!(map, "dummy_calculator", DummyCalculator);
     add_calculator!(map, "neighbor_list", NeighborList);
     add_calculator!(map, "sorted_distances", SortedDistances);
+    add_calculator!(map, "bond_features", BondFeatures);
+    add_calculator!(map, "permutation_invariant_vector", PermutationInvariantVector);
+    add_calculator!(map, "common_neighbor_analysis", CommonNeighborAnalysis);
+    add_calculator!(map, "centro_symmetry", CentroSymmetry);
+    add_calculator!(map, "ewald_dipolar_tensor", EwaldDipolarTensor);
 
     add_calculator!(map, "spherical_expansion_by_pair", SphericalExpansionByPair, SphericalExpansionParameters);
     add_calculator!(map, "spherical_expansion", SphericalExpansion, SphericalExpansionParameters);
@@ -517,3 +1814,26 @@ static REGISTERED_CALCULATORS: Lazy<BTreeMap<&'static str, CalculatorCreator>> =
     return map;
 });
 // [calculator-registration]
+
+#[cfg(test)]
+mod tests {
+    use crate::systems::test_utils::test_systems;
+    use crate::Error;
+
+    use super::with_selected_structures;
+
+    #[test]
+    fn selected_structures_restored_after_panic() {
+        let mut systems = test_systems(&["water", "methane"]);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            with_selected_structures(&mut systems, Some(&[0]), |systems| -> Result<(), Error> {
+                assert_eq!(systems[1].size().unwrap(), 0);
+                panic!("failure during the calculation");
+            })
+        }));
+        assert!(result.is_err());
+
+        assert_eq!(systems[0].size().unwrap(), 3);
+        assert_eq!(systems[1].size().unwrap(), 5);
+    }
+}
@@ -209,7 +209,7 @@ mod tests {
         }) as Box<dyn CalculatorBase>);
 
         let system = test_system("water");
-        let options = crate::calculators::tests_utils::FinalDifferenceOptions {
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
             displacement: 1e-6,
             max_relative: 1e-5,
             epsilon: 1e-16,
@@ -224,7 +224,7 @@ mod tests {
         }) as Box<dyn CalculatorBase>);
 
         let system = test_system("water");
-        let options = crate::calculators::tests_utils::FinalDifferenceOptions {
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
             displacement: 1e-6,
             max_relative: 1e-5,
             epsilon: 1e-16,
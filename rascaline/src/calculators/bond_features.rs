@@ -0,0 +1,592 @@
+use std::collections::BTreeSet;
+
+use equistore::{Labels, LabelsBuilder, TensorMap};
+
+use super::CalculatorBase;
+
+use crate::{Error, System, Vector3D};
+
+
+/// Per-bond features, computed from the bonds (molecular topology) carried by
+/// the systems.
+///
+/// For each bond `i - j` in a system (as given by [`System::bonds`]), this
+/// calculator produces a set of features made of three parts, identified by
+/// the `"feature"` property:
+///
+/// - `feature = 0` contains the expansion of the bond length on a set of
+///   `max_radial` Gaussian functions, evenly spaced between 0 and `cutoff`
+///   (a single Gaussian function is centered on 0);
+/// - `feature = 1` contains a summary of the environment of the first atom in
+///   the bond, i.e. the sum of the same Gaussian functions over all the
+///   neighbors of this atom within the `cutoff` (excluding the bond itself),
+///   weighted by a cosine cutoff function;
+/// - `feature = 2` contains the same summary for the environment of the second
+///   atom in the bond.
+///
+/// The features are separated in blocks according to the species of the two
+/// bonded atoms, the first atom always having the smallest species.
+/// Systems without bonds do not contribute any sample.
+#[derive(Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+pub struct BondFeatures {
+    /// Spherical cutoff used to describe the environments of the atoms in a
+    /// bond. This is also the position of the last Gaussian function used to
+    /// expand the bond length, if there is more than one.
+    pub cutoff: f64,
+    /// Number of Gaussian functions used in the expansion of the bond length
+    /// and environments.
+    pub max_radial: usize,
+    /// Width of the Gaussian functions used in the expansions.
+    pub gaussian_width: f64,
+}
+
+impl BondFeatures {
+    fn validate(&self) -> Result<(), Error> {
+        if !(self.cutoff > 0.0 && self.cutoff.is_finite()) {
+            return Err(Error::InvalidParameter(
+                "cutoff must be a positive number for bond features".into()
+            ));
+        }
+
+        if self.max_radial == 0 {
+            return Err(Error::InvalidParameter(
+                "max_radial must be at least 1 for bond features".into()
+            ));
+        }
+
+        if !(self.gaussian_width > 0.0 && self.gaussian_width.is_finite()) {
+            return Err(Error::InvalidParameter(
+                "gaussian_width must be a positive number for bond features".into()
+            ));
+        }
+
+        return Ok(());
+    }
+
+    /// Add the expansion of `distance` on the Gaussian basis, multiplied by
+    /// `weight`, to `output`
+    fn add_gaussian_expansion(&self, distance: f64, weight: f64, output: &mut [f64]) {
+        let spacing = if self.max_radial > 1 {
+            self.cutoff / (self.max_radial - 1) as f64
+        } else {
+            0.0
+        };
+        let factor = -0.5 / (self.gaussian_width * self.gaussian_width);
+        for (n, value) in output.iter_mut().enumerate() {
+            let delta = distance - n as f64 * spacing;
+            *value += weight * f64::exp(factor * delta * delta);
+        }
+    }
+
+    /// Smooth cutoff function used to weight the neighbors contributions
+    fn cutoff_function(&self, distance: f64) -> f64 {
+        if distance >= self.cutoff {
+            return 0.0;
+        }
+
+        return 0.5 * (1.0 + f64::cos(std::f64::consts::PI * distance / self.cutoff));
+    }
+
+    /// Compute the environment summary of the atom `center`, ignoring the
+    /// pair corresponding to the bond with `partner`. `partner_shift` is the
+    /// cell shift of the bond vector going from `center` to `partner`.
+    fn environment(
+        &self,
+        system: &dyn System,
+        center: usize,
+        partner: usize,
+        partner_shift: [i32; 3],
+        output: &mut [f64],
+    ) -> Result<(), Error> {
+        let reversed_shift = [-partner_shift[0], -partner_shift[1], -partner_shift[2]];
+        for pair in system.pairs_containing(center)? {
+            // the pair can either go from `center` to `partner` or from
+            // `partner` to `center`, with the opposite cell shift
+            let is_bond = (pair.first == center && pair.second == partner && pair.cell_shift == partner_shift)
+                || (pair.first == partner && pair.second == center && pair.cell_shift == reversed_shift);
+            if is_bond {
+                continue;
+            }
+
+            let weight = self.cutoff_function(pair.distance);
+            self.add_gaussian_expansion(pair.distance, weight, output);
+        }
+
+        return Ok(());
+    }
+}
+
+/// Get the vector between the atoms `first` and `second`, using the minimal
+/// image convention for periodic systems, together with the corresponding
+/// cell shift (see [`crate::systems::Pair::cell_shift`])
+fn bond_vector(system: &dyn System, first: usize, second: usize) -> Result<(Vector3D, [i32; 3]), Error> {
+    let positions = system.positions()?;
+    let cell = system.cell()?;
+
+    let vector = positions[second] - positions[first];
+    let image = cell.vector_image(vector);
+    if cell.is_infinite() {
+        return Ok((image, [0, 0, 0]));
+    }
+
+    let shift = cell.fractional(image - vector);
+    let shift = [
+        f64::round(shift[0]) as i32,
+        f64::round(shift[1]) as i32,
+        f64::round(shift[2]) as i32,
+    ];
+
+    return Ok((image, shift));
+}
+
+/// Get the bonds in the system, oriented so that the first atom has the
+/// smallest species (or the smallest index if both atoms have the same
+/// species)
+fn oriented_bonds(system: &dyn System) -> Result<BTreeSet<(usize, usize)>, Error> {
+    let species = system.species()?;
+    let size = species.len();
+
+    let mut bonds = BTreeSet::new();
+    for &[i, j] in system.bonds()? {
+        if i >= size || j >= size {
+            return Err(Error::InvalidParameter(format!(
+                "bond between atoms {} and {} is out of bounds for a system with {} atoms",
+                i, j, size
+            )));
+        }
+
+        if (species[i], i) <= (species[j], j) {
+            bonds.insert((i, j));
+        } else {
+            bonds.insert((j, i));
+        }
+    }
+
+    return Ok(bonds);
+}
+
+impl CalculatorBase for BondFeatures {
+    fn name(&self) -> String {
+        "bond features".into()
+    }
+
+    fn parameters(&self) -> String {
+        serde_json::to_string(self).expect("failed to serialize to JSON")
+    }
+
+    fn cutoff(&self) -> Option<f64> {
+        Some(self.cutoff)
+    }
+
+    fn keys(&self, systems: &mut [Box<dyn System>]) -> Result<Labels, Error> {
+        self.validate()?;
+
+        let mut all_species_pairs = BTreeSet::new();
+        for system in systems {
+            let species = system.species()?;
+            for (first, second) in oriented_bonds(&**system)? {
+                all_species_pairs.insert((species[first], species[second]));
+            }
+        }
+
+        let mut keys = LabelsBuilder::new(vec!["species_first_atom", "species_second_atom"]);
+        for (species_first, species_second) in all_species_pairs {
+            keys.add(&[species_first, species_second]);
+        }
+
+        return Ok(keys.finish());
+    }
+
+    fn samples_names(&self) -> Vec<&str> {
+        return vec!["structure", "first_atom", "second_atom"];
+    }
+
+    fn samples(&self, keys: &Labels, systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
+        assert_eq!(keys.names(), ["species_first_atom", "species_second_atom"]);
+
+        let mut all_bonds = Vec::new();
+        for system in systems.iter() {
+            all_bonds.push(oriented_bonds(&**system)?);
+        }
+
+        let mut samples = Vec::new();
+        for [species_first, species_second] in keys.iter_fixed_size() {
+            let mut builder = LabelsBuilder::new(self.samples_names());
+            for (system_i, (system, bonds)) in systems.iter().zip(&all_bonds).enumerate() {
+                let species = system.species()?;
+                for &(first, second) in bonds {
+                    if species[first] == species_first.i32() && species[second] == species_second.i32() {
+                        builder.add(&[system_i, first, second]);
+                    }
+                }
+            }
+            samples.push(builder.finish());
+        }
+
+        return Ok(samples);
+    }
+
+    fn supports_gradient(&self, _parameter: &str) -> bool {
+        return false;
+    }
+
+    fn positions_gradient_samples(&self, _: &Labels, _: &[Labels], _: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
+        unimplemented!()
+    }
+
+    fn components(&self, keys: &Labels) -> Vec<Vec<Labels>> {
+        return vec![Vec::new(); keys.count()];
+    }
+
+    fn properties_names(&self) -> Vec<&str> {
+        return vec!["feature", "n"];
+    }
+
+    fn properties(&self, keys: &Labels) -> Vec<Labels> {
+        let mut properties = LabelsBuilder::new(self.properties_names());
+        for feature in 0..3 {
+            for n in 0..self.max_radial {
+                properties.add(&[feature, n]);
+            }
+        }
+        let properties = properties.finish();
+
+        return vec![properties; keys.count()];
+    }
+
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "BondFeatures::compute"))]
+    fn compute(&mut self, systems: &mut [Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
+        assert_eq!(descriptor.keys().names(), ["species_first_atom", "species_second_atom"]);
+
+        for system in systems.iter_mut() {
+            system.compute_neighbors(self.cutoff)?;
+        }
+
+        let mut bond_length_features = vec![0.0; self.max_radial];
+        let mut first_environment = vec![0.0; self.max_radial];
+        let mut second_environment = vec![0.0; self.max_radial];
+
+        for (_, mut block) in descriptor.iter_mut() {
+            let block = block.data_mut();
+            let array = block.values.to_array_mut();
+
+            for (sample_i, [structure_i, first, second]) in block.samples.iter_fixed_size().enumerate() {
+                let system = &*systems[structure_i.usize()];
+                let first = first.usize();
+                let second = second.usize();
+
+                bond_length_features.fill(0.0);
+                first_environment.fill(0.0);
+                second_environment.fill(0.0);
+
+                let (bond, shift) = bond_vector(system, first, second)?;
+                self.add_gaussian_expansion(bond.norm(), 1.0, &mut bond_length_features);
+                self.environment(system, first, second, shift, &mut first_environment)?;
+                self.environment(system, second, first, [-shift[0], -shift[1], -shift[2]], &mut second_environment)?;
+
+                for (property_i, [feature, n]) in block.properties.iter_fixed_size().enumerate() {
+                    let n = n.usize();
+                    array[[sample_i, property_i]] = match feature.usize() {
+                        0 => bond_length_features[n],
+                        1 => first_environment[n],
+                        2 => second_environment[n],
+                        _ => unreachable!("invalid feature index in properties"),
+                    };
+                }
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use ndarray::{s, Array2, Axis};
+    use equistore::Labels;
+
+    use crate::systems::test_utils::test_system;
+    use crate::{Calculator, CalculationOptions, System, Vector3D};
+
+    use super::super::CalculatorBase;
+    use super::BondFeatures;
+
+    fn calculator() -> Calculator {
+        Calculator::from(Box::new(BondFeatures {
+            cutoff: 2.0,
+            max_radial: 4,
+            gaussian_width: 0.3,
+        }) as Box<dyn CalculatorBase>)
+    }
+
+    fn bonded_water() -> Box<dyn System> {
+        let mut water = test_system("water");
+        water.add_bond(0, 1).unwrap();
+        water.add_bond(2, 0).unwrap();
+        return Box::new(water);
+    }
+
+    fn bonded_methane() -> Box<dyn System> {
+        let mut methane = test_system("methane");
+        for i in 1..5 {
+            methane.add_bond(0, i).unwrap();
+        }
+        return Box::new(methane);
+    }
+
+    fn gaussians(distance: f64) -> Vec<f64> {
+        (0..4).map(|n| {
+            let delta = distance - n as f64 * 2.0 / 3.0;
+            f64::exp(-0.5 * delta * delta / (0.3 * 0.3))
+        }).collect()
+    }
+
+    fn cutoff_function(distance: f64) -> f64 {
+        0.5 * (1.0 + f64::cos(std::f64::consts::PI * distance / 2.0))
+    }
+
+    #[test]
+    fn name_and_parameters() {
+        let calculator = calculator();
+        assert_eq!(calculator.name(), "bond features");
+        assert_eq!(calculator.parameters(), "{\"cutoff\":2.0,\"max_radial\":4,\"gaussian_width\":0.3}");
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        let mut calculator = Calculator::from(Box::new(BondFeatures {
+            cutoff: 2.0,
+            max_radial: 0,
+            gaussian_width: 0.3,
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = vec![bonded_water()];
+        let error = calculator.compute(&mut systems, Default::default()).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: max_radial must be at least 1 for bond features");
+    }
+
+    #[test]
+    fn values() {
+        let mut calculator = calculator();
+        let mut systems = vec![bonded_water()];
+        let descriptor = calculator.compute(&mut systems, Default::default()).unwrap();
+
+        assert_eq!(*descriptor.keys(), Labels::new(
+            ["species_first_atom", "species_second_atom"],
+            &[[-42, 1]],
+        ));
+
+        let block = descriptor.block_by_id(0);
+        assert_eq!(block.samples(), Labels::new(
+            ["structure", "first_atom", "second_atom"],
+            &[[0, 0, 1], [0, 0, 2]],
+        ));
+
+        let positions = systems[0].positions().unwrap();
+        let oh_distance = (positions[1] - positions[0]).norm();
+        let hh_distance = (positions[2] - positions[1]).norm();
+
+        let values = block.values().to_array();
+        assert_eq!(values.shape(), [2, 12]);
+
+        let bond_length = gaussians(oh_distance);
+        let first_environment = gaussians(oh_distance).into_iter()
+            .map(|v| v * cutoff_function(oh_distance))
+            .collect::<Vec<_>>();
+        let second_environment = gaussians(hh_distance).into_iter()
+            .map(|v| v * cutoff_function(hh_distance))
+            .collect::<Vec<_>>();
+
+        for sample_i in 0..2 {
+            for n in 0..4 {
+                assert_relative_eq!(values[[sample_i, n]], bond_length[n], max_relative=1e-12);
+                assert_relative_eq!(values[[sample_i, 4 + n]], first_environment[n], max_relative=1e-12);
+                assert_relative_eq!(values[[sample_i, 8 + n]], second_environment[n], max_relative=1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn no_bonds() {
+        let mut systems = vec![Box::new(test_system("water")) as Box<dyn System>];
+        let calculator = BondFeatures {
+            cutoff: 2.0,
+            max_radial: 4,
+            gaussian_width: 0.3,
+        };
+
+        let keys = calculator.keys(&mut systems).unwrap();
+        assert_eq!(keys.count(), 0);
+    }
+
+    #[test]
+    fn periodic_bonds() {
+        // bond crossing the periodic boundary
+        let mut system = crate::SimpleSystem::new(crate::systems::UnitCell::cubic(5.0));
+        system.add_atom(6, Vector3D::new(0.2, 0.0, 0.0));
+        system.add_atom(8, Vector3D::new(3.9, 0.0, 0.0));
+        system.add_bond(0, 1).unwrap();
+
+        let mut calculator = calculator();
+        let mut systems = vec![Box::new(system) as Box<dyn System>];
+        let descriptor = calculator.compute(&mut systems, Default::default()).unwrap();
+
+        let values = descriptor.block_by_id(0).values().to_array();
+        let expected = gaussians(1.3);
+        for n in 0..4 {
+            assert_relative_eq!(values[[0, n]], expected[n], max_relative=1e-12);
+        }
+    }
+
+    #[test]
+    fn skewed_periodic_bonds() {
+        // in this cell, rounding the fractional coordinates of the bond
+        // vector does not give the shortest image
+        let cell = crate::systems::UnitCell::triclinic(5.0, 5.0, 5.0, 90.0, 90.0, 30.0);
+        let start = Vector3D::new(0.1, 0.1, 0.1);
+        let mut system = crate::SimpleSystem::new(cell);
+        system.add_atom(6, start);
+        system.add_atom(8, start + cell.cartesian(Vector3D::new(0.45, 0.45, 0.0)));
+        system.add_bond(0, 1).unwrap();
+
+        let mut calculator = calculator();
+        let mut systems = vec![Box::new(system) as Box<dyn System>];
+        let descriptor = calculator.compute(&mut systems, Default::default()).unwrap();
+
+        let values = descriptor.block_by_id(0).values().to_array();
+        let expected = gaussians(cell.cartesian(Vector3D::new(-0.55, 0.45, 0.0)).norm());
+        for n in 0..4 {
+            assert_relative_eq!(values[[0, n]], expected[n], max_relative=1e-12);
+        }
+    }
+
+    #[test]
+    fn bond_partner_images() {
+        // the second atom is at the same distance of the first one through
+        // two different periodic images, only the bond itself should be
+        // excluded from the environments
+        let mut system = crate::SimpleSystem::new(crate::systems::UnitCell::cubic(3.0));
+        system.add_atom(6, Vector3D::new(0.0, 0.0, 0.0));
+        system.add_atom(8, Vector3D::new(1.5, 0.0, 0.0));
+        system.add_bond(0, 1).unwrap();
+
+        let mut calculator = calculator();
+        let mut systems = vec![Box::new(system) as Box<dyn System>];
+        let descriptor = calculator.compute(&mut systems, Default::default()).unwrap();
+
+        let values = descriptor.block_by_id(0).values().to_array();
+        let bond_length = gaussians(1.5);
+        for n in 0..4 {
+            let environment = bond_length[n] * cutoff_function(1.5);
+            assert_relative_eq!(values[[0, n]], bond_length[n], max_relative=1e-12);
+            assert_relative_eq!(values[[0, 4 + n]], environment, max_relative=1e-12);
+            assert_relative_eq!(values[[0, 8 + n]], environment, max_relative=1e-12);
+        }
+    }
+
+    #[test]
+    fn single_gaussian() {
+        let mut calculator = Calculator::from(Box::new(BondFeatures {
+            cutoff: 2.0,
+            max_radial: 1,
+            gaussian_width: 0.3,
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = vec![bonded_water()];
+        let descriptor = calculator.compute(&mut systems, Default::default()).unwrap();
+
+        let positions = systems[0].positions().unwrap();
+        let oh_distance = (positions[1] - positions[0]).norm();
+
+        let values = descriptor.block_by_id(0).values().to_array();
+        let expected = f64::exp(-0.5 * oh_distance * oh_distance / (0.3 * 0.3));
+        assert_relative_eq!(values[[0, 0]], expected, max_relative=1e-12);
+    }
+
+    #[test]
+    fn finite_differences_gradients() {
+        let mut calculator = calculator();
+        let mut systems = vec![bonded_water()];
+
+        let options = CalculationOptions {
+            gradients: &["positions"],
+            ..Default::default()
+        };
+        let error = calculator.compute(&mut systems, options).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: the bond features calculator does not support gradients with respect to positions"
+        );
+
+        let options = CalculationOptions {
+            gradients: &["positions"],
+            finite_differences_displacement: Some(1e-6),
+            ..Default::default()
+        };
+        let descriptor = calculator.compute(&mut systems, options).unwrap();
+
+        let block = descriptor.block_by_id(0);
+        let gradient = block.gradient("positions").unwrap();
+        // gradients with respect to all atoms in the structure
+        assert_eq!(gradient.samples(), Labels::new(
+            ["sample", "structure", "atom"],
+            &[[0, 0, 0], [0, 0, 1], [0, 0, 2], [1, 0, 0], [1, 0, 1], [1, 0, 2]],
+        ));
+
+        let gradient = gradient.values().to_array();
+        assert_eq!(gradient.shape(), [6, 3, 12]);
+
+        let positions = systems[0].positions().unwrap();
+        for (sample_i, second) in [1, 2].into_iter().enumerate() {
+            // translational invariance
+            let total = gradient.slice(s![3 * sample_i..3 * sample_i + 3, .., ..]).sum_axis(Axis(0));
+            assert_relative_eq!(total, Array2::zeros((3, 12)), epsilon=1e-6);
+
+            // the bond length features only depend on the atoms in the bond
+            let bond = positions[second] - positions[0];
+            let distance = bond.norm();
+            for n in 0..4 {
+                let delta = distance - n as f64 * 2.0 / 3.0;
+                let derivative = -delta / (0.3 * 0.3) * gaussians(distance)[n];
+                for spatial in 0..3 {
+                    let expected = derivative * bond[spatial] / distance;
+                    assert_relative_eq!(gradient[[3 * sample_i + second, spatial, n]], expected, epsilon=1e-6, max_relative=1e-5);
+                    assert_relative_eq!(gradient[[3 * sample_i, spatial, n]], -expected, epsilon=1e-6, max_relative=1e-5);
+                }
+            }
+        }
+
+        let options = CalculationOptions {
+            gradients: &["positions"],
+            finite_differences_displacement: Some(-1.0),
+            ..Default::default()
+        };
+        let error = calculator.compute(&mut systems, options).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: expected a positive displacement for finite differences gradients, got -1"
+        );
+    }
+
+    #[test]
+    fn compute_partial() {
+        let calculator = calculator();
+        let mut systems = vec![bonded_water(), bonded_methane()];
+
+        let keys = Labels::new(["species_first_atom", "species_second_atom"], &[
+            [-42, 1], [1, 6], [1, 1], [6, 6], [-42, -42],
+        ]);
+        let samples = Labels::new(["structure", "first_atom", "second_atom"], &[
+            [0, 0, 2], [1, 3, 0], [1, 1, 0],
+        ]);
+        let properties = Labels::new(["feature", "n"], &[
+            [0, 1], [2, 3], [1, 0],
+        ]);
+
+        crate::calculators::tests_utils::compute_partial(
+            calculator, &mut systems, &keys, &samples, &properties
+        );
+    }
+}
@@ -0,0 +1,380 @@
+use equistore::{Labels, LabelsBuilder, TensorMap};
+
+use super::CalculatorBase;
+
+use crate::{Error, System, Vector3D};
+use crate::labels::{SpeciesFilter, SamplesBuilder};
+use crate::labels::AtomCenteredSamples;
+use crate::labels::{KeysBuilder, CenterSpeciesKeys};
+
+/// Centro-symmetry parameter, measuring the loss of centro-symmetry in the
+/// local environment of each atom (see <https://doi.org/10.1103/PhysRevB.58.11085>).
+///
+/// For a center `i` with `num_neighbors` nearest neighbors, the vectors `R_j`
+/// from the center to each neighbor are combined in all possible pairs, and the
+/// parameter is given by the sum of the `num_neighbors / 2` smallest values of
+/// `|R_j + R_k|^2`. This is zero for a perfectly centro-symmetric environment
+/// (such as the FCC or BCC crystals with respectively 12 and 8 neighbors), and
+/// increases close to defects. Centers with less than `num_neighbors`
+/// neighbors inside the `cutoff` have a centro-symmetry parameter of 0.
+///
+/// The gradients are computed for a fixed choice of neighbors and pairs of
+/// neighbors, and are discontinuous when this choice changes.
+#[derive(Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+pub struct CentroSymmetry {
+    /// Spherical cutoff used to search for the nearest neighbors
+    pub cutoff: f64,
+    /// Number of nearest neighbors to use for each center. This should be an
+    /// even number, typically 12 for FCC and 8 for BCC crystals.
+    pub num_neighbors: usize,
+}
+
+/// Value of the centro-symmetry parameter for a single center, and the
+/// contributions to the gradients
+struct CentroSymmetryContribution {
+    value: f64,
+    /// `(atom, gradient)` for all atoms with non-zero gradient. The same atom
+    /// can appear multiple times.
+    gradients: Vec<(usize, Vector3D)>,
+}
+
+impl CentroSymmetry {
+    fn validate(&self) -> Result<(), Error> {
+        if !(self.cutoff > 0.0 && self.cutoff.is_finite()) {
+            return Err(Error::InvalidParameter(
+                "cutoff must be a positive number for centro-symmetry".into()
+            ));
+        }
+
+        if self.num_neighbors == 0 || self.num_neighbors % 2 != 0 {
+            return Err(Error::InvalidParameter(format!(
+                "num_neighbors must be a positive even number for centro-symmetry, got {}",
+                self.num_neighbors
+            )));
+        }
+
+        return Ok(());
+    }
+
+    /// Compute the centro-symmetry parameter for the given `center` in the
+    /// `system`, which must already contain a neighbor list
+    fn compute_for_center(&self, system: &dyn System, center: usize) -> Result<CentroSymmetryContribution, Error> {
+        let mut neighbors = Vec::new();
+        for pair in system.pairs_containing(center)? {
+            // pairs between an atom and its own periodic image contribute two
+            // neighbors
+            if pair.first == center {
+                neighbors.push((pair.second, pair.vector));
+            }
+
+            if pair.second == center {
+                neighbors.push((pair.first, -pair.vector));
+            }
+        }
+
+        if neighbors.len() < self.num_neighbors {
+            return Ok(CentroSymmetryContribution {
+                value: 0.0,
+                gradients: Vec::new(),
+            });
+        }
+
+        if !system.pairs_sorted_by_distance()? {
+            neighbors.sort_unstable_by(|a, b| a.1.norm2().partial_cmp(&b.1.norm2()).expect("got NaN distance"));
+        }
+        neighbors.truncate(self.num_neighbors);
+
+        let mut pairs = Vec::with_capacity(self.num_neighbors * (self.num_neighbors - 1) / 2);
+        for (j, &(_, vector_j)) in neighbors.iter().enumerate() {
+            for (k, &(_, vector_k)) in neighbors.iter().enumerate().skip(j + 1) {
+                let sum = vector_j + vector_k;
+                pairs.push((sum.norm2(), j, k, sum));
+            }
+        }
+        pairs.sort_unstable_by(|a, b| a.0.partial_cmp(&b.0).expect("got NaN distance"));
+
+        let mut contribution = CentroSymmetryContribution {
+            value: 0.0,
+            gradients: Vec::new(),
+        };
+
+        for &(value, j, k, sum) in pairs.iter().take(self.num_neighbors / 2) {
+            contribution.value += value;
+
+            let gradient = 2.0 * sum;
+            contribution.gradients.push((neighbors[j].0, gradient));
+            contribution.gradients.push((neighbors[k].0, gradient));
+            contribution.gradients.push((center, -2.0 * gradient));
+        }
+
+        return Ok(contribution);
+    }
+}
+
+impl CalculatorBase for CentroSymmetry {
+    fn name(&self) -> String {
+        "centro-symmetry parameter".into()
+    }
+
+    fn parameters(&self) -> String {
+        serde_json::to_string(self).expect("failed to serialize to JSON")
+    }
+
+    fn cutoff(&self) -> Option<f64> {
+        Some(self.cutoff)
+    }
+
+    fn keys(&self, systems: &mut [Box<dyn System>]) -> Result<Labels, Error> {
+        self.validate()?;
+        return CenterSpeciesKeys.keys(systems);
+    }
+
+    fn samples_names(&self) -> Vec<&str> {
+        AtomCenteredSamples::samples_names()
+    }
+
+    fn samples(&self, keys: &Labels, systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
+        assert_eq!(keys.names(), ["species_center"]);
+        let mut samples = Vec::new();
+        for [species_center] in keys.iter_fixed_size() {
+            let builder = AtomCenteredSamples {
+                cutoff: self.cutoff,
+                species_center: SpeciesFilter::Single(species_center.i32()),
+                species_neighbor: SpeciesFilter::Any,
+                self_pairs: false,
+            };
+
+            samples.push(builder.samples(systems)?);
+        }
+
+        return Ok(samples);
+    }
+
+    fn supports_gradient(&self, parameter: &str) -> bool {
+        match parameter {
+            "positions" => true,
+            _ => false,
+        }
+    }
+
+    fn positions_gradient_samples(&self, keys: &Labels, samples: &[Labels], systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
+        assert_eq!(keys.names(), ["species_center"]);
+        assert_eq!(keys.count(), samples.len());
+
+        let mut gradient_samples = Vec::new();
+        for ([species_center], samples) in keys.iter_fixed_size().zip(samples) {
+            let builder = AtomCenteredSamples {
+                cutoff: self.cutoff,
+                species_center: SpeciesFilter::Single(species_center.i32()),
+                species_neighbor: SpeciesFilter::Any,
+                self_pairs: true,
+            };
+
+            gradient_samples.push(builder.gradients_for(systems, samples)?);
+        }
+
+        return Ok(gradient_samples);
+    }
+
+    fn components(&self, keys: &Labels) -> Vec<Vec<Labels>> {
+        return vec![Vec::new(); keys.count()];
+    }
+
+    fn properties_names(&self) -> Vec<&str> {
+        vec!["centro_symmetry"]
+    }
+
+    fn properties(&self, keys: &Labels) -> Vec<Labels> {
+        let mut properties = LabelsBuilder::new(self.properties_names());
+        properties.add(&[0]);
+        let properties = properties.finish();
+
+        return vec![properties; keys.count()];
+    }
+
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "CentroSymmetry::compute"))]
+    fn compute(&mut self, systems: &mut [Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
+        assert_eq!(descriptor.keys().names(), ["species_center"]);
+        self.validate()?;
+
+        for (_, mut block) in descriptor.iter_mut() {
+            let block_data = block.data_mut();
+            let property_i = match block_data.properties.position(&[0.into()]) {
+                Some(property_i) => property_i,
+                // the user did not request this property
+                None => continue,
+            };
+
+            let mut all_contributions = Vec::new();
+            let array = block_data.values.to_array_mut();
+            for (sample_i, [structure_i, center_i]) in block_data.samples.iter_fixed_size().enumerate() {
+                let structure_i = structure_i.usize();
+                let system = &mut systems[structure_i];
+                system.compute_neighbors(self.cutoff)?;
+
+                let contribution = self.compute_for_center(&**system, center_i.usize())?;
+                array[[sample_i, property_i]] = contribution.value;
+
+                all_contributions.push((structure_i, contribution));
+            }
+
+            if let Some(mut gradient) = block.gradient_mut("positions") {
+                let gradient = gradient.data_mut();
+                let array = gradient.values.to_array_mut();
+
+                for (sample_i, (structure_i, contribution)) in all_contributions.iter().enumerate() {
+                    for &(atom, atom_gradient) in &contribution.gradients {
+                        let grad_sample_i = gradient.samples.position(&[
+                            sample_i.into(), (*structure_i).into(), atom.into()
+                        ]);
+
+                        let grad_sample_i = if let Some(grad_sample_i) = grad_sample_i {
+                            grad_sample_i
+                        } else {
+                            // the user restricted the gradients to other atoms
+                            continue;
+                        };
+
+                        for xyz in 0..3 {
+                            array[[grad_sample_i, xyz, property_i]] += atom_gradient[xyz];
+                        }
+                    }
+                }
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use equistore::Labels;
+
+    use crate::systems::test_utils::{test_system, test_systems};
+    use crate::systems::{SimpleSystem, UnitCell};
+    use crate::{Calculator, System, Vector3D};
+
+    use super::super::CalculatorBase;
+    use super::CentroSymmetry;
+
+    #[test]
+    fn name_and_parameters() {
+        let calculator = Calculator::from(Box::new(CentroSymmetry {
+            cutoff: 3.0,
+            num_neighbors: 12,
+        }) as Box<dyn CalculatorBase>);
+
+        assert_eq!(calculator.name(), "centro-symmetry parameter");
+        assert_eq!(calculator.parameters(), "{\"cutoff\":3.0,\"num_neighbors\":12}");
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        let mut calculator = Calculator::from(Box::new(CentroSymmetry {
+            cutoff: 3.0,
+            num_neighbors: 3,
+        }) as Box<dyn CalculatorBase>);
+
+        let error = calculator.compute(&mut test_systems(&["water"]), Default::default()).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: num_neighbors must be a positive even number for centro-symmetry, got 3");
+    }
+
+    #[test]
+    fn values() {
+        let mut calculator = Calculator::from(Box::new(CentroSymmetry {
+            cutoff: 1.5,
+            num_neighbors: 2,
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water"]);
+        let descriptor = calculator.compute(&mut systems, Default::default()).unwrap();
+
+        // oxygen, with the two hydrogen as neighbors
+        let values = descriptor.block_by_id(0).values().to_array();
+        assert_relative_eq!(values[[0, 0]], 4.0 * 0.58895 * 0.58895, max_relative=1e-12);
+
+        // hydrogen only have a single neighbor
+        let values = descriptor.block_by_id(1).values().to_array();
+        assert_eq!(values[[0, 0]], 0.0);
+        assert_eq!(values[[1, 0]], 0.0);
+
+        // perfect FCC crystal
+        let lattice = 3.6;
+        let mut system = SimpleSystem::new(UnitCell::cubic(3.0 * lattice));
+        for i in 0..3 {
+            for j in 0..3 {
+                for k in 0..3 {
+                    for position in [[0.0, 0.0, 0.0], [0.5, 0.5, 0.0], [0.5, 0.0, 0.5], [0.0, 0.5, 0.5]] {
+                        system.add_atom(29, Vector3D::new(
+                            lattice * (i as f64 + position[0]),
+                            lattice * (j as f64 + position[1]),
+                            lattice * (k as f64 + position[2]),
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mut calculator = Calculator::from(Box::new(CentroSymmetry {
+            cutoff: 3.0,
+            num_neighbors: 12,
+        }) as Box<dyn CalculatorBase>);
+
+        let descriptor = calculator.compute(&mut [Box::new(system) as Box<dyn System>], Default::default()).unwrap();
+        let values = descriptor.block_by_id(0).values().to_array();
+        for &value in values.iter() {
+            assert!(value.abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn finite_differences_positions() {
+        let calculator = Calculator::from(Box::new(CentroSymmetry {
+            cutoff: 2.0,
+            num_neighbors: 4,
+        }) as Box<dyn CalculatorBase>);
+
+        let system = test_system("methane");
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
+            displacement: 1e-6,
+            max_relative: 1e-6,
+            epsilon: 1e-16,
+        };
+        crate::calculators::tests_utils::finite_differences_positions(calculator, &system, options);
+    }
+
+    #[test]
+    fn compute_partial() {
+        let calculator = Calculator::from(Box::new(CentroSymmetry {
+            cutoff: 2.0,
+            num_neighbors: 2,
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water", "methane"]);
+
+        let samples = Labels::new(["structure", "center"], &[
+            [0, 1],
+            [0, 0],
+            [1, 0],
+            [1, 2],
+        ]);
+
+        let properties = Labels::new(["centro_symmetry"], &[
+            [0],
+        ]);
+
+        let keys = Labels::new(["species_center"], &[
+            [-42],
+            [1],
+            [6],
+            [7],
+        ]);
+
+        crate::calculators::tests_utils::compute_partial(
+            calculator, &mut systems, &keys, &samples, &properties
+        );
+    }
+}
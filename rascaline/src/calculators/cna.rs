@@ -0,0 +1,439 @@
+use equistore::{Labels, LabelsBuilder, LabelValue, TensorMap};
+
+use super::CalculatorBase;
+
+use crate::{Error, System, Vector3D};
+use crate::labels::{SpeciesFilter, SamplesBuilder};
+use crate::labels::AtomCenteredSamples;
+use crate::labels::{KeysBuilder, CenterSpeciesKeys};
+
+/// CNA signatures used as properties, in order. The last entry is used to count
+/// all the neighbors with a signature different from the ones above.
+const SIGNATURES: [[i32; 3]; 6] = [
+    [4, 2, 1],
+    [4, 2, 2],
+    [4, 4, 4],
+    [5, 5, 5],
+    [6, 6, 6],
+    [-1, -1, -1],
+];
+
+/// Common neighbor analysis (CNA) signatures, used to classify the local
+/// crystal structure around each atom.
+///
+/// For each bond `i - j` between a center `i` and one of its neighbors, the
+/// CNA signature is a triplet of integers containing the number of neighbors
+/// common to `i` and `j`, the number of bonds between these common neighbors,
+/// and the number of bonds in the longest chain of bonds between the common
+/// neighbors. This calculator counts how many neighbors of each center have a
+/// given signature, for the signatures corresponding to the FCC (`4-2-1`), HCP
+/// (`4-2-1` and `4-2-2`), BCC (`6-6-6` and `4-4-4`) and icosahedral (`5-5-5`)
+/// structures. All other signatures are counted together, with a signature of
+/// `-1 -1 -1`.
+///
+/// In the conventional CNA, two atoms are bonded if they are closer than the
+/// `cutoff`. With the adaptive CNA (see <https://doi.org/10.1088/0965-0393/20/4/045021>),
+/// a separate cutoff is determined for each atom from the distances to its 12
+/// (FCC/HCP/icosahedral) or 14 (BCC) nearest neighbors, and the global `cutoff`
+/// is only used to search for these nearest neighbors. Atoms with less than 12
+/// neighbors inside the `cutoff` have all their adaptive signatures set to 0.
+#[derive(Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+pub struct CommonNeighborAnalysis {
+    /// Cutoff defining bonds between atoms in the conventional CNA, or
+    /// distance used to search for the nearest neighbors in the adaptive CNA.
+    pub cutoff: f64,
+    /// Should we use the adaptive CNA instead of the conventional one?
+    #[serde(default)]
+    pub adaptive: bool,
+}
+
+impl CommonNeighborAnalysis {
+    fn validate(&self) -> Result<(), Error> {
+        if !(self.cutoff > 0.0 && self.cutoff.is_finite()) {
+            return Err(Error::InvalidParameter(
+                "cutoff must be a positive number for common neighbor analysis".into()
+            ));
+        }
+
+        return Ok(());
+    }
+
+    /// Compute the CNA signatures of all the bonds around one center, given
+    /// the vectors from the center to all its neighbors
+    fn signatures(&self, mut neighbors: Vec<Vector3D>) -> Vec<[usize; 3]> {
+        if !self.adaptive {
+            return (0..neighbors.len())
+                .map(|j| signature(&neighbors, j, self.cutoff))
+                .collect();
+        }
+
+        neighbors.sort_unstable_by(|a, b| a.norm2().partial_cmp(&b.norm2()).expect("got NaN distance"));
+        let factor = 0.5 * (1.0 + f64::sqrt(2.0));
+
+        if neighbors.len() < 12 {
+            return Vec::new();
+        }
+
+        // try FCC, HCP and icosahedral structures with the 12 nearest neighbors
+        let nearest = &neighbors[..12];
+        let local_cutoff = factor * nearest.iter().map(|v| v.norm()).sum::<f64>() / 12.0;
+        let signatures_12 = (0..12)
+            .map(|j| signature(nearest, j, local_cutoff))
+            .collect::<Vec<_>>();
+
+        let count = |signatures: &[[usize; 3]], expected: [usize; 3]| {
+            signatures.iter().filter(|&&s| s == expected).count()
+        };
+
+        let n_421 = count(&signatures_12, [4, 2, 1]);
+        let n_422 = count(&signatures_12, [4, 2, 2]);
+        let n_555 = count(&signatures_12, [5, 5, 5]);
+        if n_421 == 12 || (n_421 == 6 && n_422 == 6) || n_555 == 12 {
+            return signatures_12;
+        }
+
+        // then try BCC structure with the 14 nearest neighbors
+        if neighbors.len() >= 14 {
+            let nearest = &neighbors[..14];
+            let first_shell = nearest[..8].iter().map(|v| v.norm()).sum::<f64>();
+            let second_shell = nearest[8..].iter().map(|v| v.norm()).sum::<f64>();
+            let local_cutoff = factor * (2.0 / f64::sqrt(3.0) * first_shell + second_shell) / 14.0;
+
+            let signatures_14 = (0..14)
+                .map(|j| signature(nearest, j, local_cutoff))
+                .collect::<Vec<_>>();
+
+            if count(&signatures_14, [6, 6, 6]) == 8 && count(&signatures_14, [4, 4, 4]) == 6 {
+                return signatures_14;
+            }
+        }
+
+        return signatures_12;
+    }
+}
+
+/// Compute the CNA signature of the bond between the center and the neighbor
+/// `j`, where `neighbors` contains the vectors from the center to all its
+/// neighbors, and atoms closer than `cutoff` are considered bonded.
+fn signature(neighbors: &[Vector3D], j: usize, cutoff: f64) -> [usize; 3] {
+    let cutoff2 = cutoff * cutoff;
+
+    let common = (0..neighbors.len())
+        .filter(|&k| k != j && (neighbors[k] - neighbors[j]).norm2() < cutoff2)
+        .collect::<Vec<_>>();
+
+    let mut bonds = Vec::new();
+    for (i, &k) in common.iter().enumerate() {
+        for &l in &common[(i + 1)..] {
+            if (neighbors[k] - neighbors[l]).norm2() < cutoff2 {
+                bonds.push([k, l]);
+            }
+        }
+    }
+
+    return [common.len(), bonds.len(), longest_chain(&bonds)];
+}
+
+/// Get the number of bonds in the largest cluster of bonds connected by
+/// sharing an atom
+fn longest_chain(bonds: &[[usize; 2]]) -> usize {
+    let mut visited = vec![false; bonds.len()];
+    let mut longest = 0;
+    for start in 0..bonds.len() {
+        if visited[start] {
+            continue;
+        }
+
+        visited[start] = true;
+        let mut stack = vec![start];
+        let mut size = 0;
+        while let Some(current) = stack.pop() {
+            size += 1;
+            let [a, b] = bonds[current];
+            for (other, bond) in bonds.iter().enumerate() {
+                if !visited[other] && (bond.contains(&a) || bond.contains(&b)) {
+                    visited[other] = true;
+                    stack.push(other);
+                }
+            }
+        }
+
+        longest = usize::max(longest, size);
+    }
+
+    return longest;
+}
+
+impl CalculatorBase for CommonNeighborAnalysis {
+    fn name(&self) -> String {
+        "common neighbor analysis".into()
+    }
+
+    fn parameters(&self) -> String {
+        serde_json::to_string(self).expect("failed to serialize to JSON")
+    }
+
+    fn cutoff(&self) -> Option<f64> {
+        Some(self.cutoff)
+    }
+
+    fn keys(&self, systems: &mut [Box<dyn System>]) -> Result<Labels, Error> {
+        self.validate()?;
+        return CenterSpeciesKeys.keys(systems);
+    }
+
+    fn samples_names(&self) -> Vec<&str> {
+        AtomCenteredSamples::samples_names()
+    }
+
+    fn samples(&self, keys: &Labels, systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
+        assert_eq!(keys.names(), ["species_center"]);
+        let mut samples = Vec::new();
+        for [species_center] in keys.iter_fixed_size() {
+            let builder = AtomCenteredSamples {
+                cutoff: self.cutoff,
+                species_center: SpeciesFilter::Single(species_center.i32()),
+                species_neighbor: SpeciesFilter::Any,
+                self_pairs: false,
+            };
+
+            samples.push(builder.samples(systems)?);
+        }
+
+        return Ok(samples);
+    }
+
+    fn supports_gradient(&self, _parameter: &str) -> bool {
+        return false;
+    }
+
+    fn positions_gradient_samples(&self, _: &Labels, _: &[Labels], _: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
+        unimplemented!()
+    }
+
+    fn components(&self, keys: &Labels) -> Vec<Vec<Labels>> {
+        return vec![Vec::new(); keys.count()];
+    }
+
+    fn properties_names(&self) -> Vec<&str> {
+        vec!["common_neighbors", "bonds", "longest_chain"]
+    }
+
+    fn properties(&self, keys: &Labels) -> Vec<Labels> {
+        let mut properties = LabelsBuilder::new(self.properties_names());
+        for signature in SIGNATURES {
+            properties.add(&signature);
+        }
+        let properties = properties.finish();
+
+        return vec![properties; keys.count()];
+    }
+
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "CommonNeighborAnalysis::compute"))]
+    fn compute(&mut self, systems: &mut [Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
+        assert_eq!(descriptor.keys().names(), ["species_center"]);
+        self.validate()?;
+
+        for (_, mut block) in descriptor.iter_mut() {
+            let block_data = block.data_mut();
+            let array = block_data.values.to_array_mut();
+
+            for (sample_i, [structure_i, center_i]) in block_data.samples.iter_fixed_size().enumerate() {
+                let center_i = center_i.usize();
+
+                let system = &mut systems[structure_i.usize()];
+                system.compute_neighbors(self.cutoff)?;
+
+                let mut neighbors = Vec::new();
+                for pair in system.pairs_containing(center_i)? {
+                    // pairs between an atom and its own periodic image
+                    // contribute two neighbors
+                    if pair.first == center_i {
+                        neighbors.push(pair.vector);
+                    }
+
+                    if pair.second == center_i {
+                        neighbors.push(-pair.vector);
+                    }
+                }
+
+                for signature in self.signatures(neighbors) {
+                    let signature = [signature[0] as i32, signature[1] as i32, signature[2] as i32];
+                    let position = SIGNATURES.iter()
+                        .position(|&s| s == signature)
+                        .unwrap_or(SIGNATURES.len() - 1);
+
+                    let property: [LabelValue; 3] = [
+                        SIGNATURES[position][0].into(),
+                        SIGNATURES[position][1].into(),
+                        SIGNATURES[position][2].into(),
+                    ];
+
+                    if let Some(property_i) = block_data.properties.position(&property) {
+                        array[[sample_i, property_i]] += 1.0;
+                    }
+                }
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{aview1, s};
+    use equistore::Labels;
+
+    use crate::systems::{SimpleSystem, UnitCell};
+    use crate::{Calculator, System, Vector3D};
+
+    use super::super::CalculatorBase;
+    use super::CommonNeighborAnalysis;
+
+    /// Create a cubic crystal from the fractional positions of atoms in the
+    /// conventional unit cell, repeated `repeat` times in each direction
+    fn crystal(lattice: f64, basis: &[[f64; 3]], repeat: usize) -> Box<dyn System> {
+        let mut system = SimpleSystem::new(UnitCell::cubic(lattice * repeat as f64));
+        for i in 0..repeat {
+            for j in 0..repeat {
+                for k in 0..repeat {
+                    for position in basis {
+                        system.add_atom(29, Vector3D::new(
+                            lattice * (i as f64 + position[0]),
+                            lattice * (j as f64 + position[1]),
+                            lattice * (k as f64 + position[2]),
+                        ));
+                    }
+                }
+            }
+        }
+        return Box::new(system) as Box<dyn System>;
+    }
+
+    fn fcc(lattice: f64) -> Box<dyn System> {
+        crystal(lattice, &[[0.0, 0.0, 0.0], [0.5, 0.5, 0.0], [0.5, 0.0, 0.5], [0.0, 0.5, 0.5]], 3)
+    }
+
+    fn bcc(lattice: f64) -> Box<dyn System> {
+        crystal(lattice, &[[0.0, 0.0, 0.0], [0.5, 0.5, 0.5]], 3)
+    }
+
+    #[test]
+    fn name_and_parameters() {
+        let calculator = Calculator::from(Box::new(CommonNeighborAnalysis {
+            cutoff: 3.0,
+            adaptive: false,
+        }) as Box<dyn CalculatorBase>);
+
+        assert_eq!(calculator.name(), "common neighbor analysis");
+        assert_eq!(calculator.parameters(), "{\"cutoff\":3.0,\"adaptive\":false}");
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        let mut calculator = Calculator::from(Box::new(CommonNeighborAnalysis {
+            cutoff: -3.0,
+            adaptive: false,
+        }) as Box<dyn CalculatorBase>);
+
+        let error = calculator.compute(&mut [fcc(3.6)], Default::default()).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: cutoff must be a positive number for common neighbor analysis");
+    }
+
+    #[test]
+    fn conventional() {
+        let mut calculator = Calculator::from(Box::new(CommonNeighborAnalysis {
+            cutoff: 3.0,
+            adaptive: false,
+        }) as Box<dyn CalculatorBase>);
+
+        let descriptor = calculator.compute(&mut [fcc(3.6)], Default::default()).unwrap();
+        assert_eq!(descriptor.keys().count(), 1);
+        let values = descriptor.block_by_id(0).values().to_array();
+        assert_eq!(values.shape(), [108, 6]);
+        for atom in 0..108 {
+            assert_eq!(values.slice(s![atom, ..]), aview1(&[12.0, 0.0, 0.0, 0.0, 0.0, 0.0]).into_dyn());
+        }
+
+        // BCC requires a cutoff between the second and third neighbors shells
+        let mut calculator = Calculator::from(Box::new(CommonNeighborAnalysis {
+            cutoff: 3.5,
+            adaptive: false,
+        }) as Box<dyn CalculatorBase>);
+
+        let descriptor = calculator.compute(&mut [bcc(2.87)], Default::default()).unwrap();
+        let values = descriptor.block_by_id(0).values().to_array();
+        assert_eq!(values.shape(), [54, 6]);
+        for atom in 0..54 {
+            assert_eq!(values.slice(s![atom, ..]), aview1(&[0.0, 0.0, 6.0, 0.0, 8.0, 0.0]).into_dyn());
+        }
+    }
+
+    #[test]
+    fn adaptive() {
+        let mut calculator = Calculator::from(Box::new(CommonNeighborAnalysis {
+            cutoff: 3.5,
+            adaptive: true,
+        }) as Box<dyn CalculatorBase>);
+
+        // the conventional CNA would include the 6 second neighbors with this
+        // cutoff, giving different signatures
+        let descriptor = calculator.compute(&mut [fcc(3.4)], Default::default()).unwrap();
+        let values = descriptor.block_by_id(0).values().to_array();
+        for atom in 0..108 {
+            assert_eq!(values.slice(s![atom, ..]), aview1(&[12.0, 0.0, 0.0, 0.0, 0.0, 0.0]).into_dyn());
+        }
+
+        let descriptor = calculator.compute(&mut [bcc(2.87)], Default::default()).unwrap();
+        let values = descriptor.block_by_id(0).values().to_array();
+        for atom in 0..54 {
+            assert_eq!(values.slice(s![atom, ..]), aview1(&[0.0, 0.0, 6.0, 0.0, 8.0, 0.0]).into_dyn());
+        }
+
+        // not enough neighbors for the adaptive CNA
+        let mut calculator = Calculator::from(Box::new(CommonNeighborAnalysis {
+            cutoff: 1.5,
+            adaptive: true,
+        }) as Box<dyn CalculatorBase>);
+        let mut systems = crate::systems::test_utils::test_systems(&["water"]);
+        let descriptor = calculator.compute(&mut systems, Default::default()).unwrap();
+        for block in descriptor.blocks() {
+            assert!(block.values().to_array().iter().all(|&v| v == 0.0));
+        }
+    }
+
+    #[test]
+    fn compute_partial() {
+        let calculator = Calculator::from(Box::new(CommonNeighborAnalysis {
+            cutoff: 2.0,
+            adaptive: false,
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = crate::systems::test_utils::test_systems(&["water", "methane"]);
+
+        let samples = Labels::new(["structure", "center"], &[
+            [0, 1],
+            [0, 2],
+            [1, 0],
+            [1, 3],
+        ]);
+
+        let properties = Labels::new(["common_neighbors", "bonds", "longest_chain"], &[
+            [-1, -1, -1],
+            [4, 2, 1],
+        ]);
+
+        let keys = Labels::new(["species_center"], &[
+            [-42],
+            [1],
+            [6],
+            [7],
+        ]);
+
+        crate::calculators::tests_utils::compute_partial(
+            calculator, &mut systems, &keys, &samples, &properties
+        );
+    }
+}
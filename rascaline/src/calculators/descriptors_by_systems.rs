@@ -106,19 +106,25 @@ impl<'a> std::ops::DerefMut for TensorMapView<'a> {
 
 
 /// Split a descriptor into multiple descriptors, one by system. The resulting
-/// descriptors contain views inside the descriptor
+/// descriptors contain views inside the descriptor.
+///
+/// The first dimension of the samples in all blocks must be `"structure"`, and
+/// the samples must be ordered by structure. The samples can contain any
+/// number of additional dimensions (atoms, pairs, …).
 #[allow(clippy::too_many_lines)]
 pub fn split_tensor_map_by_system(descriptor: &mut TensorMap, n_systems: usize) -> Vec<TensorMapView<'_>> {
     #[derive(Debug, Clone, Copy, PartialEq)]
     struct GradientPosition {
         positions: usize,
         cell: usize,
+        positions_hessian: usize,
+        density_scaling: usize,
     }
 
     let mut descriptor_by_system = Vec::new();
 
     let mut values_end = vec![0; descriptor.keys().count()];
-    let mut gradients_end = vec![GradientPosition { positions: 0, cell: 0 }; descriptor.keys().count()];
+    let mut gradients_end = vec![GradientPosition { positions: 0, cell: 0, positions_hessian: 0, density_scaling: 0 }; descriptor.keys().count()];
     for system_i in 0..n_systems {
         let blocks = descriptor.par_iter_mut()
             .zip_eq(&mut values_end)
@@ -131,12 +137,13 @@ pub fn split_tensor_map_by_system(descriptor: &mut TensorMap, n_systems: usize)
                 let mut structure_per_sample = vec![LabelValue::new(-1); block_data.samples.count()];
 
                 let system_start = *system_end;
-                for (sample_i, &[structure, center]) in block_data.samples.iter_fixed_size().enumerate().skip(system_start) {
+                for (sample_i, sample) in block_data.samples.iter().enumerate().skip(system_start) {
+                    let structure = sample[0];
                     structure_per_sample[sample_i] = structure;
 
                     if structure.usize() == system_i {
                         // this sample is part of to the current system
-                        samples.add(&[structure, center]);
+                        samples.add(sample);
                         let new_sample = samples_mapping.len();
                         samples_mapping.insert(sample_i, new_sample);
 
@@ -189,6 +196,8 @@ pub fn split_tensor_map_by_system(descriptor: &mut TensorMap, n_systems: usize)
                     let system_end_grad = match parameter {
                         "positions" => &mut system_end_grad.positions,
                         "cell" => &mut system_end_grad.cell,
+                        "positions_hessian" => &mut system_end_grad.positions_hessian,
+                        "density_scaling" => &mut system_end_grad.density_scaling,
                         other => panic!("unsupported gradient parameter {}", other)
                     };
                     let system_start_grad = *system_end_grad;
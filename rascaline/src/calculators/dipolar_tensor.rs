@@ -0,0 +1,463 @@
+use ndarray::Array2;
+
+use equistore::{Labels, LabelsBuilder, TensorMap};
+
+use crate::{Error, Matrix3, System, Vector3D};
+
+use crate::labels::{SamplesBuilder, SpeciesFilter, LongRangeSamplesPerAtom};
+use crate::labels::{KeysBuilder, AllSpeciesPairsKeys};
+
+use crate::math::{KVector, compute_k_vectors};
+
+use super::CalculatorBase;
+
+/// Long-range dipolar tensor, computed with an Ewald sum in reciprocal space.
+///
+/// For each central atom `i` and each neighbor species `a`, this calculator
+/// computes the Cartesian tensor
+///
+/// $$ T^a_{\alpha\beta}(i) = \sum_{j \in a} \partial_\alpha \partial_\beta
+///     \phi(\mathbf{r}_i - \mathbf{r}_j), $$
+///
+/// where $\phi$ is the periodic Coulomb potential created by a Gaussian
+/// density of width `atomic_gaussian_width`, evaluated with an Ewald sum over
+/// k-vectors (without the `k = 0` term, i.e. with conducting boundary
+/// conditions). Taking the gradient of the potential twice gives the
+/// dipole-dipole interaction tensor, which decays as `r^-3` and captures
+/// anisotropic long-range correlations between the central atom and its
+/// (periodic) neighbors.
+///
+/// This uses the same k-vectors as the LODE spherical expansion, but is not an
+/// expansion of the LODE density on a radial basis: the tensor is computed
+/// directly in Cartesian coordinates.
+///
+/// The sum over `j` includes the central atom itself, which accounts for the
+/// interaction of the center with its own periodic images. This calculator
+/// only works with periodic systems.
+#[derive(Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+pub struct EwaldDipolarTensor {
+    /// Width of the atom-centered gaussian used to create the atomic density.
+    pub atomic_gaussian_width: f64,
+    /// Spherical reciprocal cutoff. If `k_cutoff` is `None` a cutoff of `1.2 π
+    /// / atomic_gaussian_width`, which is a reasonable value for most systems,
+    /// is used.
+    pub k_cutoff: Option<f64>,
+}
+
+/// Pre-computed k-space data for a single system
+struct KSpaceData {
+    /// k-vectors as 3D vectors
+    k_vectors: Vec<Vector3D>,
+    /// `2 * 4π/V * exp(-k^2 σ^2 / 2) / k^2` for each k-vector (the factor 2
+    /// accounts for the k-vectors in the other half-space)
+    factors: Vec<f64>,
+    /// `cos(k r)`, the array shape is `(n_atoms, k_vector)`
+    cosines: Array2<f64>,
+    /// `sin(k r)`, the array shape is `(n_atoms, k_vector)`
+    sines: Array2<f64>,
+}
+
+impl EwaldDipolarTensor {
+    /// Get the value of the k-space cutoff (either provided by the user or a
+    /// default).
+    pub fn get_k_cutoff(&self) -> f64 {
+        return self.k_cutoff.unwrap_or(1.2 * std::f64::consts::PI / self.atomic_gaussian_width);
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if !(self.atomic_gaussian_width > 0.0 && self.atomic_gaussian_width.is_finite()) {
+            return Err(Error::InvalidParameter(
+                "atomic_gaussian_width must be a positive number for Ewald dipolar tensor".into()
+            ));
+        }
+
+        if let Some(k_cutoff) = self.k_cutoff {
+            if !(k_cutoff > 0.0 && k_cutoff.is_finite()) {
+                return Err(Error::InvalidParameter(
+                    "k_cutoff must be a positive number for Ewald dipolar tensor".into()
+                ));
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn k_space_data(&self, system: &dyn System) -> Result<KSpaceData, Error> {
+        let cell = system.cell()?;
+        if !cell.is_fully_periodic() {
+            return Err(Error::InvalidParameter("Ewald dipolar tensor can only be used with periodic systems".into()));
+        }
+
+        if system.local_size()? != system.size()? {
+            return Err(Error::InvalidParameter("Ewald dipolar tensor can not be used with systems containing ghost atoms".into()));
+        }
+
+        let k_vectors = compute_k_vectors(&cell, self.get_k_cutoff());
+        if k_vectors.is_empty() {
+            return Err(Error::InvalidParameter("No k-vectors for current combination of hyper parameters.".into()));
+        }
+
+        let smearing_squared = self.atomic_gaussian_width * self.atomic_gaussian_width;
+        let global_factor = 2.0 * 4.0 * std::f64::consts::PI / cell.volume();
+        let factors = k_vectors.iter().map(|KVector { norm, .. }| {
+            let k_norm_squared = norm * norm;
+            global_factor * f64::exp(-0.5 * k_norm_squared * smearing_squared) / k_norm_squared
+        }).collect();
+
+        let positions = system.positions()?;
+        let mut cosines = Array2::from_elem((positions.len(), k_vectors.len()), 0.0);
+        let mut sines = Array2::from_elem((positions.len(), k_vectors.len()), 0.0);
+        for (atom_i, position) in positions.iter().enumerate() {
+            for (ik, k_vector) in k_vectors.iter().enumerate() {
+                let s = k_vector.norm * k_vector.direction * position;
+                cosines[[atom_i, ik]] = f64::cos(s);
+                sines[[atom_i, ik]] = f64::sin(s);
+            }
+        }
+
+        return Ok(KSpaceData {
+            k_vectors: k_vectors.iter().map(|k| k.norm * k.direction).collect(),
+            factors: factors,
+            cosines: cosines,
+            sines: sines,
+        });
+    }
+}
+
+impl KSpaceData {
+    /// Compute the dipolar tensor between atoms `i` and `j`
+    fn tensor(&self, i: usize, j: usize) -> Matrix3 {
+        let mut tensor = Matrix3::zero();
+        for (ik, k_vector) in self.k_vectors.iter().enumerate() {
+            // cos(k (r_i - r_j))
+            let cos = self.cosines[[i, ik]] * self.cosines[[j, ik]] + self.sines[[i, ik]] * self.sines[[j, ik]];
+            tensor -= (self.factors[ik] * cos) * k_vector.tensorial(k_vector);
+        }
+        return tensor;
+    }
+
+    /// Compute the gradient of the dipolar tensor between atoms `i` and `j`
+    /// with respect to the position of atom `j`. The gradient with respect to
+    /// the position of atom `i` is the opposite of this one.
+    fn tensor_gradient(&self, i: usize, j: usize) -> [Matrix3; 3] {
+        let mut gradient = [Matrix3::zero(); 3];
+        for (ik, k_vector) in self.k_vectors.iter().enumerate() {
+            // sin(k (r_i - r_j))
+            let sin = self.sines[[i, ik]] * self.cosines[[j, ik]] - self.cosines[[i, ik]] * self.sines[[j, ik]];
+            let kk = (self.factors[ik] * sin) * k_vector.tensorial(k_vector);
+            for xyz in 0..3 {
+                gradient[xyz] -= k_vector[xyz] * kk;
+            }
+        }
+        return gradient;
+    }
+}
+
+impl CalculatorBase for EwaldDipolarTensor {
+    fn name(&self) -> String {
+        "Ewald dipolar tensor".into()
+    }
+
+    fn parameters(&self) -> String {
+        serde_json::to_string(self).expect("failed to serialize to JSON")
+    }
+
+    fn keys(&self, systems: &mut [Box<dyn System>]) -> Result<Labels, Error> {
+        self.validate()?;
+        return AllSpeciesPairsKeys {}.keys(systems);
+    }
+
+    fn samples_names(&self) -> Vec<&str> {
+        LongRangeSamplesPerAtom::samples_names()
+    }
+
+    fn samples(&self, keys: &Labels, systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
+        assert_eq!(keys.names(), ["species_center", "species_neighbor"]);
+
+        let mut samples = Vec::new();
+        for [species_center, species_neighbor] in keys.iter_fixed_size() {
+            let builder = LongRangeSamplesPerAtom {
+                species_center: SpeciesFilter::Single(species_center.i32()),
+                species_neighbor: SpeciesFilter::Single(species_neighbor.i32()),
+                self_pairs: true,
+            };
+
+            samples.push(builder.samples(systems)?);
+        }
+
+        return Ok(samples);
+    }
+
+    fn supports_gradient(&self, parameter: &str) -> bool {
+        match parameter {
+            "positions" => true,
+            _ => false,
+        }
+    }
+
+    fn positions_gradient_samples(&self, keys: &Labels, samples: &[Labels], systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
+        assert_eq!(keys.names(), ["species_center", "species_neighbor"]);
+        assert_eq!(keys.count(), samples.len());
+
+        let mut gradient_samples = Vec::new();
+        for ([species_center, species_neighbor], samples) in keys.iter_fixed_size().zip(samples) {
+            let builder = LongRangeSamplesPerAtom {
+                species_center: SpeciesFilter::Single(species_center.i32()),
+                species_neighbor: SpeciesFilter::Single(species_neighbor.i32()),
+                self_pairs: true,
+            };
+
+            gradient_samples.push(builder.gradients_for(systems, samples)?);
+        }
+
+        return Ok(gradient_samples);
+    }
+
+    fn components(&self, keys: &Labels) -> Vec<Vec<Labels>> {
+        let direction_1 = Labels::new(["direction_1"], &[[0], [1], [2]]);
+        let direction_2 = Labels::new(["direction_2"], &[[0], [1], [2]]);
+        return vec![vec![direction_1, direction_2]; keys.count()];
+    }
+
+    fn properties_names(&self) -> Vec<&str> {
+        vec!["dipolar"]
+    }
+
+    fn properties(&self, keys: &Labels) -> Vec<Labels> {
+        let mut properties = LabelsBuilder::new(self.properties_names());
+        properties.add(&[0]);
+        let properties = properties.finish();
+
+        return vec![properties; keys.count()];
+    }
+
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "EwaldDipolarTensor::compute"))]
+    fn compute(&mut self, systems: &mut [Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
+        assert_eq!(descriptor.keys().names(), ["species_center", "species_neighbor"]);
+
+        let mut k_space_data = Vec::new();
+        for system in systems.iter() {
+            k_space_data.push(self.k_space_data(&**system)?);
+        }
+
+        for (key, mut block) in descriptor.iter_mut() {
+            let species_neighbor = key[1].i32();
+
+            let block_data = block.data_mut();
+            let array = block_data.values.to_array_mut();
+
+            let mut samples = Vec::new();
+            for (sample_i, [structure_i, center_i]) in block_data.samples.iter_fixed_size().enumerate() {
+                let structure_i = structure_i.usize();
+                let center_i = center_i.usize();
+                samples.push((structure_i, center_i));
+
+                let data = &k_space_data[structure_i];
+                let species = systems[structure_i].species()?;
+
+                let mut tensor = Matrix3::zero();
+                for (neighbor_i, &species) in species.iter().enumerate() {
+                    if species == species_neighbor {
+                        tensor += data.tensor(center_i, neighbor_i);
+                    }
+                }
+
+                for direction_1 in 0..3 {
+                    for direction_2 in 0..3 {
+                        array[[sample_i, direction_1, direction_2, 0]] = tensor[direction_1][direction_2];
+                    }
+                }
+            }
+
+            if let Some(mut gradient) = block.gradient_mut("positions") {
+                let gradient = gradient.data_mut();
+                let array = gradient.values.to_array_mut();
+
+                for (grad_sample_i, [sample_i, structure_i, atom_i]) in gradient.samples.iter_fixed_size().enumerate() {
+                    let structure_i = structure_i.usize();
+                    let atom_i = atom_i.usize();
+                    let (_, center_i) = samples[sample_i.usize()];
+
+                    let data = &k_space_data[structure_i];
+                    let species = systems[structure_i].species()?;
+
+                    let mut tensor_gradient = [Matrix3::zero(); 3];
+                    if atom_i == center_i {
+                        for (neighbor_i, &species) in species.iter().enumerate() {
+                            if neighbor_i != center_i && species == species_neighbor {
+                                let pair_gradient = data.tensor_gradient(center_i, neighbor_i);
+                                for xyz in 0..3 {
+                                    tensor_gradient[xyz] -= pair_gradient[xyz];
+                                }
+                            }
+                        }
+                    } else if species[atom_i] == species_neighbor {
+                        tensor_gradient = data.tensor_gradient(center_i, atom_i);
+                    }
+
+                    for xyz in 0..3 {
+                        for direction_1 in 0..3 {
+                            for direction_2 in 0..3 {
+                                array[[grad_sample_i, xyz, direction_1, direction_2, 0]] = tensor_gradient[xyz][direction_1][direction_2];
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use equistore::Labels;
+
+    use crate::systems::test_utils::test_system;
+    use crate::systems::UnitCell;
+    use crate::{Calculator, Matrix3, SimpleSystem, Vector3D};
+
+    use super::super::CalculatorBase;
+    use super::EwaldDipolarTensor;
+
+    fn calculator() -> Calculator {
+        Calculator::from(Box::new(EwaldDipolarTensor {
+            atomic_gaussian_width: 1.0,
+            k_cutoff: None,
+        }) as Box<dyn CalculatorBase>)
+    }
+
+    #[test]
+    fn name_and_parameters() {
+        let calculator = calculator();
+        assert_eq!(calculator.name(), "Ewald dipolar tensor");
+        assert_eq!(calculator.parameters(), "{\"atomic_gaussian_width\":1.0,\"k_cutoff\":null}");
+    }
+
+    #[test]
+    fn symmetric_tensor() {
+        let mut system = test_system("water");
+        system.cell = UnitCell::cubic(3.0);
+
+        let mut calculator = calculator();
+        let descriptor = calculator.compute(&mut [Box::new(system)], Default::default()).unwrap();
+
+        for (_, block) in descriptor.iter() {
+            let values = block.values().to_array();
+            for sample_i in 0..values.shape()[0] {
+                for direction_1 in 0..3 {
+                    for direction_2 in 0..3 {
+                        assert_relative_eq!(
+                            values[[sample_i, direction_1, direction_2, 0]],
+                            values[[sample_i, direction_2, direction_1, 0]],
+                            max_relative=1e-12
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn direct_sum() {
+        let cell_size = 3.0;
+        let mut system = SimpleSystem::new(UnitCell::cubic(cell_size));
+        system.add_atom(1, Vector3D::new(0.1, 0.2, 0.3));
+        system.add_atom(8, Vector3D::new(1.2, 0.9, 0.4));
+
+        let mut calculator = Calculator::from(Box::new(EwaldDipolarTensor {
+            atomic_gaussian_width: 1.0,
+            // large enough for the k-space sum to be converged
+            k_cutoff: Some(10.0),
+        }) as Box<dyn CalculatorBase>);
+        let descriptor = calculator.compute(&mut [Box::new(system)], Default::default()).unwrap();
+
+        let block_i = descriptor.keys().position(&[1.into(), 8.into()]).unwrap();
+        let values = descriptor.block_by_id(block_i).values().to_array();
+
+        // sum the second derivatives of the potential created by a gaussian
+        // density, erf(r / (sqrt(2) σ)) / r, over the periodic images in a
+        // sphere. This spherical summation differs from the Ewald sum by the
+        // field of a uniformly charged sphere, i.e. 4π/3V along the diagonal.
+        let width = f64::sqrt(2.0);
+        let vector = Vector3D::new(0.1 - 1.2, 0.2 - 0.9, 0.3 - 0.4);
+        let max_image = 10;
+        let mut reference = 4.0 * std::f64::consts::PI / (3.0 * cell_size.powi(3)) * Matrix3::one();
+        for a in -max_image..=max_image {
+            for b in -max_image..=max_image {
+                for c in -max_image..=max_image {
+                    if a * a + b * b + c * c > max_image * max_image {
+                        continue;
+                    }
+
+                    let r = vector + cell_size * Vector3D::new(a as f64, b as f64, c as f64);
+                    let distance = r.norm();
+                    let gaussian = 2.0 / (width * f64::sqrt(std::f64::consts::PI)) * f64::exp(-distance * distance / (width * width));
+                    let erf = crate::math::erf(distance / width);
+
+                    // first and second derivatives of the potential with
+                    // respect to the distance
+                    let first = gaussian / distance - erf / (distance * distance);
+                    let second = -2.0 * gaussian / (width * width)
+                        - 2.0 * gaussian / (distance * distance)
+                        + 2.0 * erf / (distance * distance * distance);
+
+                    let radial = r.tensorial(&r) / (distance * distance);
+                    reference += second * radial + (first / distance) * (Matrix3::one() - radial);
+                }
+            }
+        }
+
+        for direction_1 in 0..3 {
+            for direction_2 in 0..3 {
+                assert_relative_eq!(
+                    values[[0, direction_1, direction_2, 0]],
+                    reference[direction_1][direction_2],
+                    epsilon=1e-4
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn non_periodic() {
+        let mut calculator = calculator();
+        let mut system = test_system("water");
+        system.cell = UnitCell::infinite();
+
+        let error = calculator.compute(&mut [Box::new(system)], Default::default()).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: Ewald dipolar tensor can only be used with periodic systems");
+    }
+
+    #[test]
+    fn finite_differences_positions() {
+        let mut system = test_system("water");
+        system.cell = UnitCell::cubic(3.0);
+
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
+            displacement: 1e-5,
+            max_relative: 1e-4,
+            epsilon: 1e-10,
+        };
+        crate::calculators::tests_utils::finite_differences_positions(calculator(), &system, options);
+    }
+
+    #[test]
+    fn compute_partial() {
+        let mut system = test_system("water");
+        system.cell = UnitCell::cubic(3.0);
+
+        let keys = Labels::new(["species_center", "species_neighbor"], &[
+            [-42, -42], [-42, 1], [1, -42], [1, 1], [6, 1],
+        ]);
+        let samples = Labels::new(["structure", "center"], &[[0, 1], [0, 2]]);
+        let properties = Labels::new(["dipolar"], &[[0]]);
+
+        crate::calculators::tests_utils::compute_partial(
+            calculator(), &mut [Box::new(system)], &keys, &samples, &properties
+        );
+    }
+}
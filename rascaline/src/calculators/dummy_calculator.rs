@@ -39,6 +39,10 @@ impl CalculatorBase for DummyCalculator {
         serde_json::to_string(self).expect("failed to serialize to JSON")
     }
 
+    fn cutoff(&self) -> Option<f64> {
+        Some(self.cutoff)
+    }
+
     fn keys(&self, systems: &mut [Box<dyn System>]) -> Result<Labels, Error> {
         return CenterSpeciesKeys.keys(systems);
     }
@@ -105,7 +109,7 @@ impl CalculatorBase for DummyCalculator {
         return vec![properties; keys.count()];
     }
 
-    #[time_graph::instrument(name = "DummyCalculator::compute")]
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "DummyCalculator::compute"))]
     fn compute(&mut self, systems: &mut [Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
         if self.name.contains("log-test-info:") {
             info!("{}", self.name);
@@ -176,11 +180,11 @@ impl CalculatorBase for DummyCalculator {
 
 #[cfg(test)]
 mod tests {
-    use ndarray::{s, aview1};
-    use equistore::Labels;
+    use ndarray::{s, aview1, Axis};
+    use equistore::{Labels, LabelValue};
 
     use crate::systems::test_utils::test_systems;
-    use crate::Calculator;
+    use crate::{Calculator, CalculationOptions, LabelsSelection, System};
 
     use super::DummyCalculator;
     use super::super::CalculatorBase;
@@ -233,6 +237,521 @@ mod tests {
         assert_eq!(values.slice(s![1, ..]), aview1(&[11.0, -1.3443999999999998]));
     }
 
+    #[test]
+    fn max_neighbors() {
+        let mut calculator = Calculator::from(Box::new(DummyCalculator{
+            cutoff: 1.0,
+            delta: 9,
+            name: String::new(),
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water", "methane"]);
+        let options = CalculationOptions {
+            max_neighbors: Some(4),
+            ..Default::default()
+        };
+        calculator.compute(&mut systems, options).unwrap();
+
+        let options = CalculationOptions {
+            max_neighbors: Some(1),
+            ..Default::default()
+        };
+        let error = calculator.compute(&mut systems, options).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: atom 0 in system 0 has 2 neighbors within the \
+            cutoff of 1, more than the maximal number of neighbors (1). Check \
+            that the positions and the cutoff use the same units, and that \
+            there are no overlapping atoms"
+        );
+    }
+
+    #[test]
+    fn max_threads() {
+        let mut calculator = Calculator::from(Box::new(DummyCalculator{
+            cutoff: 1.0,
+            delta: 9,
+            name: String::new(),
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water", "methane"]);
+        let reference = calculator.compute(&mut systems, Default::default()).unwrap();
+
+        calculator.set_max_threads(Some(1)).unwrap();
+        assert_eq!(calculator.max_threads(), Some(1));
+        let descriptor = calculator.compute(&mut systems, Default::default()).unwrap();
+        for ((_, block), (_, reference)) in descriptor.iter().zip(reference.iter()) {
+            assert_eq!(block.values().to_array(), reference.values().to_array());
+        }
+
+        let error = calculator.set_max_threads(Some(0)).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: the maximal number of threads must be at least 1");
+    }
+
+    #[test]
+    fn thread_pool() {
+        let mut calculator = Calculator::from(Box::new(DummyCalculator{
+            cutoff: 1.0,
+            delta: 9,
+            name: String::new(),
+        }) as Box<dyn CalculatorBase>);
+
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(3).build().unwrap();
+        calculator.set_thread_pool(Some(std::sync::Arc::new(pool)));
+        calculator.set_max_threads(Some(1)).unwrap();
+        assert_eq!(calculator.max_threads(), Some(3));
+
+        let mut systems = test_systems(&["water"]);
+        let descriptor = calculator.compute(&mut systems, Default::default()).unwrap();
+        assert_eq!(descriptor.keys().count(), 2);
+
+        calculator.set_thread_pool(None);
+        assert_eq!(calculator.max_threads(), Some(1));
+    }
+
+    #[test]
+    fn compute_chunked() {
+        let mut calculator = Calculator::from(Box::new(DummyCalculator{
+            cutoff: 1.0,
+            delta: 9,
+            name: String::new(),
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water", "methane", "water", "methane"]);
+        let reference = calculator.compute(&mut systems, Default::default()).unwrap();
+
+        // each atom uses 2 values of 8 bytes, so there is only space for a
+        // single system in each chunk
+        let mut chunks = Vec::new();
+        calculator.compute_chunked(&mut systems, Default::default(), 100, |start, descriptor| {
+            chunks.push((start, descriptor));
+            Ok(())
+        }).unwrap();
+
+        let starts = chunks.iter().map(|(start, _)| *start).collect::<Vec<_>>();
+        assert_eq!(starts, [0, 1, 2, 3]);
+
+        for (start, descriptor) in &chunks {
+            for (key, block) in descriptor.iter() {
+                let reference = reference.block_by_id(reference.keys().position(key).unwrap());
+                let reference_values = reference.values().to_array();
+                let values = block.values().to_array();
+                for (sample_i, sample) in block.samples().iter().enumerate() {
+                    let reference_i = reference.samples().position(&[
+                        (sample[0].usize() + start).into(), sample[1]
+                    ]).unwrap();
+                    assert_eq!(values.index_axis(Axis(0), sample_i), reference_values.index_axis(Axis(0), reference_i));
+                }
+            }
+        }
+
+        // everything fits in a single chunk after the first one
+        let mut starts = Vec::new();
+        calculator.compute_chunked(&mut systems, Default::default(), 1_000_000, |start, _| {
+            starts.push(start);
+            Ok(())
+        }).unwrap();
+        assert_eq!(starts, [0, 1]);
+
+        // the selection is adjusted for each chunk
+        let samples = Labels::new(["structure", "center"], &[[1, 0], [3, 2]]);
+        let options = CalculationOptions {
+            selected_samples: LabelsSelection::Subset(&samples),
+            ..Default::default()
+        };
+        let mut n_samples = 0;
+        calculator.compute_chunked(&mut systems, options, 1_000_000, |_, descriptor| {
+            n_samples += descriptor.blocks().iter().map(|block| block.samples().count()).sum::<usize>();
+            Ok(())
+        }).unwrap();
+        assert_eq!(n_samples, 2);
+
+        let error = calculator.compute_chunked(&mut systems, Default::default(), 0, |_, _| Ok(())).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: the memory budget for chunked calculations must be larger than 0");
+    }
+
+    #[test]
+    fn compute_into() {
+        let mut calculator = Calculator::from(Box::new(DummyCalculator{
+            cutoff: 1.0,
+            delta: 9,
+            name: String::new(),
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water"]);
+        let mut descriptor = calculator.compute(&mut systems, Default::default()).unwrap();
+        let pointer = descriptor.block_by_id(0).values().to_array().as_ptr();
+
+        let mut moved = test_systems(&["water"]);
+        let mut system = crate::systems::SimpleSystem::try_from(&*moved[0]).unwrap();
+        system.positions_mut()[1][0] += 0.5;
+        moved[0] = Box::new(system);
+
+        // same metadata: the memory is re-used
+        calculator.compute_into(&mut moved, &mut descriptor, Default::default()).unwrap();
+        assert_eq!(descriptor.block_by_id(0).values().to_array().as_ptr(), pointer);
+
+        let expected = calculator.compute(&mut moved, Default::default()).unwrap();
+        for ((_, block), (_, expected)) in descriptor.iter().zip(expected.iter()) {
+            assert_eq!(block.values().to_array(), expected.values().to_array());
+        }
+
+        // different metadata: the descriptor is replaced
+        let mut systems = test_systems(&["methane"]);
+        let options = CalculationOptions {
+            gradients: &["positions"],
+            ..Default::default()
+        };
+        calculator.compute_into(&mut systems, &mut descriptor, options).unwrap();
+        let expected = calculator.compute(&mut systems, options).unwrap();
+        assert_eq!(descriptor.keys(), expected.keys());
+        for ((_, block), (_, expected)) in descriptor.iter().zip(expected.iter()) {
+            assert_eq!(block.values().to_array(), expected.values().to_array());
+            assert_eq!(
+                block.gradient("positions").unwrap().values().to_array(),
+                expected.gradient("positions").unwrap().values().to_array()
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "profiling")]
+    fn profiling() {
+        let mut calculator = Calculator::from(Box::new(DummyCalculator{
+            cutoff: 1.0,
+            delta: 9,
+            name: String::new(),
+        }) as Box<dyn CalculatorBase>);
+        calculator.set_profiling(true);
+
+        let mut systems = test_systems(&["water"]);
+        calculator.compute(&mut systems, Default::default()).unwrap();
+
+        assert!(!crate::profiling::is_enabled());
+        let report = crate::profiling::report("short_table").unwrap();
+        assert!(report.contains("DummyCalculator::compute"));
+    }
+
+    #[test]
+    fn estimate() {
+        let mut calculator = Calculator::from(Box::new(DummyCalculator{
+            cutoff: 1.0,
+            delta: 9,
+            name: String::new(),
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water"]);
+        let estimate = calculator.estimate(&mut systems, Default::default()).unwrap();
+        assert_eq!(estimate, crate::CostEstimate {
+            blocks: 2,
+            samples: 3,
+            properties: 4,
+            values: 6,
+            gradient_samples: 0,
+            gradient_values: 0,
+            memory: 48,
+            work: None,
+        });
+
+        let options = CalculationOptions {
+            gradients: &["positions"],
+            ..Default::default()
+        };
+        let estimate = calculator.estimate(&mut systems, options).unwrap();
+        let descriptor = calculator.compute(&mut systems, options).unwrap();
+
+        let mut gradient_samples = 0;
+        let mut gradient_values = 0;
+        for (_, block) in descriptor.iter() {
+            let gradient = block.gradient("positions").unwrap();
+            gradient_samples += gradient.samples().count();
+            gradient_values += gradient.values().to_array().len();
+        }
+        assert_eq!(estimate.gradient_samples, gradient_samples);
+        assert_eq!(estimate.gradient_values, gradient_values);
+        assert_eq!(estimate.memory, 8 * (6 + gradient_values));
+    }
+
+    #[test]
+    fn compute_metadata() {
+        let mut calculator = Calculator::from(Box::new(DummyCalculator{
+            cutoff: 1.0,
+            delta: 9,
+            name: String::new(),
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water", "methane"]);
+        let options = CalculationOptions {
+            gradients: &["positions"],
+            ..Default::default()
+        };
+        let metadata = calculator.compute_metadata(&mut systems, options).unwrap();
+        let descriptor = calculator.compute(&mut systems, options).unwrap();
+
+        assert_eq!(metadata.keys(), descriptor.keys());
+        for ((_, block), (_, expected)) in metadata.iter().zip(descriptor.iter()) {
+            assert_eq!(block.samples(), expected.samples());
+            assert_eq!(block.components(), expected.components());
+            assert_eq!(block.properties(), expected.properties());
+
+            let gradient = block.gradient("positions").unwrap();
+            let expected = expected.gradient("positions").unwrap();
+            assert_eq!(gradient.samples(), expected.samples());
+            assert_eq!(gradient.components(), expected.components());
+        }
+    }
+
+    #[test]
+    fn predicate_selection() {
+        let mut calculator = Calculator::from(Box::new(DummyCalculator{
+            cutoff: 1.0,
+            delta: 9,
+            name: String::new(),
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water", "methane"]);
+        // only hydrogen centers in the second structure
+        let predicate = |key: &[LabelValue], sample: &[LabelValue]| {
+            key[0].i32() == 1 && sample[0].i32() == 1
+        };
+        let options = CalculationOptions {
+            selected_samples: LabelsSelection::Predicate(&predicate),
+            ..Default::default()
+        };
+        let descriptor = calculator.compute(&mut systems, options).unwrap();
+
+        for (key, block) in descriptor.iter() {
+            let samples = block.samples();
+            if key[0].i32() == 1 {
+                assert_eq!(samples.count(), 4);
+                for sample in samples.iter() {
+                    assert_eq!(sample[0].i32(), 1);
+                }
+            } else {
+                assert_eq!(samples.count(), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn subset_by_key_selection() {
+        let mut calculator = Calculator::from(Box::new(DummyCalculator{
+            cutoff: 1.0,
+            delta: 9,
+            name: String::new(),
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water", "methane"]);
+
+        // different samples for H and O, all the samples for C
+        let keys = Labels::new(["species_center"], &[[1], [-42]]);
+        let selected_samples = [
+            Labels::new(["structure"], &[[1]]),
+            Labels::new(["structure", "center"], &[[0, 0]]),
+        ];
+        // only select properties for C
+        let properties_keys = Labels::new(["species_center"], &[[6]]);
+        let selected_properties = [Labels::new(["index_delta"], &[[1]])];
+
+        let options = CalculationOptions {
+            selected_samples: LabelsSelection::SubsetByKey(&keys, &selected_samples),
+            selected_properties: LabelsSelection::SubsetByKey(&properties_keys, &selected_properties),
+            ..Default::default()
+        };
+        let descriptor = calculator.compute(&mut systems, options).unwrap();
+
+        let block = descriptor.block_by_id(descriptor.keys().position(&[1.into()]).unwrap());
+        assert_eq!(block.samples().count(), 4);
+        assert!(block.samples().iter().all(|sample| sample[0].i32() == 1));
+        assert_eq!(block.properties().count(), 2);
+
+        let block = descriptor.block_by_id(descriptor.keys().position(&[(-42).into()]).unwrap());
+        assert_eq!(block.samples(), Labels::new(["structure", "center"], &[[0, 0]]));
+        assert_eq!(block.properties().count(), 2);
+
+        let block = descriptor.block_by_id(descriptor.keys().position(&[6.into()]).unwrap());
+        assert_eq!(block.samples(), Labels::new(["structure", "center"], &[[1, 0]]));
+        assert_eq!(block.properties(), Labels::new(["index_delta", "x_y_z"], &[[1, 0]]));
+
+        let options = CalculationOptions {
+            selected_samples: LabelsSelection::SubsetByKey(&keys, &selected_samples[..1]),
+            ..Default::default()
+        };
+        let error = calculator.compute(&mut systems, options).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: expected 2 samples selections (one for each key), got 1");
+    }
+
+    #[test]
+    fn centers_selection() {
+        let mut calculator = Calculator::from(Box::new(DummyCalculator{
+            cutoff: 1.0,
+            delta: 9,
+            name: String::new(),
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water", "methane", "water"]);
+        let centers = [vec![1], vec![0, 2]];
+        let options = CalculationOptions {
+            selected_samples: LabelsSelection::Centers(&centers),
+            ..Default::default()
+        };
+        let descriptor = calculator.compute(&mut systems, options).unwrap();
+
+        let samples = Labels::new(["structure", "center"], &[[0, 1], [1, 2]]);
+        let block = descriptor.block_by_id(descriptor.keys().position(&[1.into()]).unwrap());
+        assert_eq!(block.samples(), samples);
+
+        let block = descriptor.block_by_id(descriptor.keys().position(&[6.into()]).unwrap());
+        assert_eq!(block.samples(), Labels::new(["structure", "center"], &[[1, 0]]));
+
+        let block = descriptor.block_by_id(descriptor.keys().position(&[(-42).into()]).unwrap());
+        assert_eq!(block.samples().count(), 0);
+
+        // same result as selecting the corresponding samples
+        let samples = Labels::new(["structure", "center"], &[[0, 1], [1, 0], [1, 2]]);
+        let options = CalculationOptions {
+            selected_samples: LabelsSelection::Subset(&samples),
+            ..Default::default()
+        };
+        let reference = calculator.compute(&mut systems, options).unwrap();
+        for ((_, block), (_, reference)) in descriptor.iter().zip(reference.iter()) {
+            assert_eq!(block.samples(), reference.samples());
+            assert_eq!(block.values().to_array(), reference.values().to_array());
+        }
+
+        let options = CalculationOptions {
+            selected_properties: LabelsSelection::Centers(&centers),
+            ..Default::default()
+        };
+        let error = calculator.compute(&mut systems, options).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: selecting centers requires \"structure\" and \"center\" in the properties of this calculator, got [index_delta, x_y_z]"
+        );
+    }
+
+    #[test]
+    fn selected_structures() {
+        let mut calculator = Calculator::from(Box::new(DummyCalculator{
+            cutoff: 1.0,
+            delta: 9,
+            name: String::new(),
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water", "methane", "water"]);
+        let options = CalculationOptions {
+            selected_structures: Some(&[1]),
+            ..Default::default()
+        };
+        let descriptor = calculator.compute(&mut systems, options).unwrap();
+        let reference = calculator.compute(&mut test_systems(&["methane"]), Default::default()).unwrap();
+
+        // only the species in methane are present, and the structure index
+        // refers to the full list of systems
+        assert_eq!(*descriptor.keys(), Labels::new(["species_center"], &[[1], [6]]));
+        for ((_, block), (_, reference)) in descriptor.iter().zip(reference.iter()) {
+            let samples = block.samples();
+            assert_eq!(samples.count(), reference.samples().count());
+            for sample in samples.iter() {
+                assert_eq!(sample[0].i32(), 1);
+            }
+            assert_eq!(block.values().to_array(), reference.values().to_array());
+        }
+
+        let options = CalculationOptions {
+            selected_structures: Some(&[2, 0]),
+            ..Default::default()
+        };
+        let descriptor = calculator.compute(&mut systems, options).unwrap();
+        let block = descriptor.block_by_id(descriptor.keys().position(&[1.into()]).unwrap());
+        let structures = block.samples().iter().map(|sample| sample[0].i32()).collect::<Vec<_>>();
+        assert_eq!(structures, [0, 0, 2, 2]);
+
+        // the original systems are restored after the calculation
+        assert_eq!(systems[1].size().unwrap(), 5);
+        let descriptor = calculator.compute(&mut systems, Default::default()).unwrap();
+        assert_eq!(descriptor.keys().count(), 3);
+
+        let options = CalculationOptions {
+            selected_structures: Some(&[3]),
+            ..Default::default()
+        };
+        let error = calculator.compute(&mut systems, options).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: selected structure 3 is out of bounds, we only have 3 systems");
+    }
+
+    #[test]
+    fn fixed_keys() {
+        let mut calculator = Calculator::from(Box::new(DummyCalculator{
+            cutoff: 1.0,
+            delta: 9,
+            name: String::new(),
+        }) as Box<dyn CalculatorBase>);
+
+        // keys for a dataset containing both water and methane, not sorted
+        let keys = Labels::new(["species_center"], &[[6], [1], [-42]]);
+        for name in ["water", "methane"] {
+            let mut systems = test_systems(&[name]);
+            let options = CalculationOptions {
+                gradients: &["positions"],
+                selected_keys: Some(&keys),
+                ..Default::default()
+            };
+            let descriptor = calculator.compute(&mut systems, options).unwrap();
+            assert_eq!(*descriptor.keys(), keys);
+
+            let reference = calculator.compute(&mut systems, Default::default()).unwrap();
+            for (key, block) in descriptor.iter() {
+                let gradient = block.gradient("positions").unwrap();
+                if let Some(position) = reference.keys().position(key) {
+                    let reference = reference.block_by_id(position);
+                    assert_eq!(block.samples(), reference.samples());
+                    assert_eq!(block.values().to_array(), reference.values().to_array());
+                } else {
+                    // species which are not in the current system have empty blocks
+                    assert_eq!(block.samples().count(), 0);
+                    assert_eq!(block.properties().count(), 2);
+                    assert_eq!(gradient.samples().count(), 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sort_labels() {
+        let mut calculator = Calculator::from(Box::new(DummyCalculator{
+            cutoff: 1.0,
+            delta: 9,
+            name: String::new(),
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water"]);
+        let keys = Labels::new(["species_center"], &[[1], [-42]]);
+        let samples = Labels::new(["structure", "center"], &[[0, 2], [0, 0], [0, 1]]);
+
+        let options = CalculationOptions {
+            selected_keys: Some(&keys),
+            selected_samples: LabelsSelection::Subset(&samples),
+            ..Default::default()
+        };
+        let descriptor = calculator.compute(&mut systems, options).unwrap();
+        assert_eq!(*descriptor.keys(), keys);
+        assert_eq!(descriptor.block_by_id(0).samples(), Labels::new(["structure", "center"], &[[0, 2], [0, 1]]));
+
+        let options = CalculationOptions {
+            sort_labels: true,
+            ..options
+        };
+        let descriptor = calculator.compute(&mut systems, options).unwrap();
+        assert_eq!(*descriptor.keys(), Labels::new(["species_center"], &[[-42], [1]]));
+
+        let block = descriptor.block_by_id(1);
+        assert_eq!(block.samples(), Labels::new(["structure", "center"], &[[0, 1], [0, 2]]));
+        let values = block.values().to_array();
+        assert_eq!(values.slice(s![0, ..]), aview1(&[10.0, 0.16649999999999998]));
+        assert_eq!(values.slice(s![1, ..]), aview1(&[11.0, -1.3443999999999998]));
+    }
+
     #[test]
     fn compute_partial() {
         let calculator = Calculator::from(Box::new(DummyCalculator{
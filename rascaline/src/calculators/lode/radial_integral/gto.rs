@@ -6,10 +6,10 @@ use crate::math::{hyp2f1, gamma, DoubleRegularized1F1};
 use crate::Error;
 
 use super::LodeRadialIntegral;
-use crate::calculators::radial_basis::GtoRadialBasis;
+use crate::calculators::radial_basis::{GtoRadialBasis, GtoOrthonormalization};
 
 /// Parameters controlling the LODE radial integral with GTO radial basis
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct LodeRadialIntegralGtoParameters {
     /// Number of radial components
     pub max_radial: usize,
@@ -21,6 +21,10 @@ pub struct LodeRadialIntegralGtoParameters {
     pub potential_exponent: usize,
     /// cutoff radius
     pub cutoff: f64,
+    /// User-defined GTO gaussian widths, one for each radial basis function
+    pub sigmas: Option<Vec<f64>>,
+    /// Method used to orthonormalize the GTO basis
+    pub orthonormalization: GtoOrthonormalization,
 }
 
 impl LodeRadialIntegralGtoParameters {
@@ -43,8 +47,20 @@ impl LodeRadialIntegralGtoParameters {
             ));
         }
 
+        self.basis().validate()?;
+
         Ok(())
     }
+
+    /// Get the GTO radial basis corresponding to these parameters
+    fn basis(&self) -> GtoRadialBasis {
+        return GtoRadialBasis {
+            max_radial: self.max_radial,
+            cutoff: self.cutoff,
+            sigmas: self.sigmas.clone(),
+            orthonormalization: self.orthonormalization,
+        };
+    }
 }
 
 /// Implementation of the LODE radial integral for GTO radial basis and Gaussian
@@ -64,18 +80,17 @@ impl LodeRadialIntegralGto {
     pub fn new(parameters: LodeRadialIntegralGtoParameters) -> Result<LodeRadialIntegralGto, Error> {
         parameters.validate()?;
 
-        let basis = GtoRadialBasis {
-            max_radial: parameters.max_radial,
-            cutoff: parameters.cutoff,
-        };
+        let basis = parameters.basis();
         let gto_gaussian_widths = basis.gaussian_widths();
         let gto_orthonormalization = basis.orthonormalization_matrix();
 
+        let double_regularized_1f1 = DoubleRegularized1F1 {
+            max_angular: parameters.max_angular,
+        };
+
         return Ok(LodeRadialIntegralGto {
             parameters: parameters,
-            double_regularized_1f1: DoubleRegularized1F1 {
-                max_angular: parameters.max_angular,
-            },
+            double_regularized_1f1: double_regularized_1f1,
             gto_gaussian_widths: gto_gaussian_widths,
             gto_orthonormalization: gto_orthonormalization.t().to_owned(),
         })
@@ -83,7 +98,7 @@ impl LodeRadialIntegralGto {
 }
 
 impl LodeRadialIntegral for LodeRadialIntegralGto {
-    #[time_graph::instrument(name = "LodeRadialIntegralGto::compute")]
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "LodeRadialIntegralGto::compute"))]
     fn compute(
         &self,
         k_norm: f64,
@@ -167,11 +182,7 @@ impl LodeRadialIntegral for LodeRadialIntegralGto {
 
         let mut contrib = Array1::from_elem(max_radial, 0.0);
 
-        let basis = GtoRadialBasis {
-            max_radial,
-            cutoff: self.parameters.cutoff,
-        };
-        let gto_gaussian_widths = basis.gaussian_widths();
+        let gto_gaussian_widths = &self.gto_gaussian_widths;
         let n_eff: Vec<f64> = (0..max_radial)
             .map(|n| 0.5 * (3. + n as f64))
             .collect();
@@ -227,6 +238,8 @@ mod tests {
             cutoff: 5.0,
             atomic_gaussian_width: 0.5,
             potential_exponent: 1,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         }).unwrap();
 
         let shape = (max_angular + 1, max_radial);
@@ -251,6 +264,8 @@ mod tests {
             cutoff: 5.0,
             atomic_gaussian_width: 0.5,
             potential_exponent: 1,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         }).unwrap();
 
         let k = 3.4;
@@ -288,6 +303,8 @@ mod tests {
                 max_angular: 2,
                 atomic_gaussian_width: 1.0,
                 potential_exponent: p,
+                sigmas: None,
+                orthonormalization: GtoOrthonormalization::Lowdin {},
             }).unwrap();
 
             let center_contrib = gto.compute_center_contribution();
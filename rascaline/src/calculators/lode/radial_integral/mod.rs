@@ -2,6 +2,7 @@ use ndarray::{ArrayViewMut2, Array1, Array2};
 
 use crate::Error;
 use crate::calculators::radial_basis::RadialBasis;
+use crate::math::SplineAccuracy;
 
 /// A `LodeRadialIntegral` computes the LODE radial integral on a given radial basis.
 ///
@@ -64,14 +65,17 @@ impl LodeRadialIntegralCache {
     #[allow(clippy::needless_pass_by_value)]
     pub fn new(radial_basis: RadialBasis, parameters: LodeRadialIntegralParameters) -> Result<Self, Error> {
         let code = match radial_basis {
-            RadialBasis::Gto {splined_radial_integral, spline_accuracy} => {
+            RadialBasis::Gto {splined_radial_integral, spline_accuracy, spline_relative_accuracy, spline_max_points, sigmas, orthonormalization} => {
                 let gto_parameters = LodeRadialIntegralGtoParameters {
                     max_radial: parameters.max_radial,
                     max_angular: parameters.max_angular,
                     atomic_gaussian_width: parameters.atomic_gaussian_width,
                     potential_exponent: parameters.potential_exponent,
                     cutoff: parameters.cutoff,
+                    sigmas: sigmas,
+                    orthonormalization: orthonormalization,
                 };
+                let description = format!("{:?}", gto_parameters);
                 let gto = LodeRadialIntegralGto::new(gto_parameters)?;
 
                 if splined_radial_integral {
@@ -84,13 +88,22 @@ impl LodeRadialIntegralCache {
                         cutoff: parameters.k_cutoff,
                     };
 
-                    Box::new(LodeRadialIntegralSpline::with_accuracy(
-                        parameters, spline_accuracy, gto
+                    let accuracy = SplineAccuracy {
+                        absolute: spline_accuracy,
+                        relative: spline_relative_accuracy.unwrap_or(spline_accuracy),
+                        max_points: spline_max_points,
+                    };
+
+                    Box::new(LodeRadialIntegralSpline::with_accuracy_cached(
+                        &description, parameters, accuracy, gto
                     )?)
                 } else {
                     Box::new(gto) as Box<dyn LodeRadialIntegral>
                 }
             }
+            RadialBasis::Laguerre {..} => {
+                return Err(Error::InvalidParameter("LODE does not support the Laguerre radial basis for the moment".into()));
+            }
             RadialBasis::TabulatedRadialIntegral {points: _} => {
                 return Err(Error::InvalidParameter("LODE does not support a tabulated radial integral for the moment".into()));
             }
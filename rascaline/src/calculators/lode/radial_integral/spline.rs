@@ -1,7 +1,9 @@
+use std::sync::Arc;
+
 use ndarray::{Array1, Array2, ArrayViewMut2};
 
 use super::LodeRadialIntegral;
-use crate::math::{HermitCubicSpline, SplineParameters};
+use crate::math::{HermitCubicSpline, SplineParameters, cached_spline, SplineAccuracy};
 use crate::Error;
 
 /// `LodeRadialIntegralSpline` allows to evaluate another radial integral
@@ -12,7 +14,7 @@ use crate::Error;
 ///
 /// [splines-wiki]: https://en.wikipedia.org/wiki/Cubic_Hermite_spline
 pub struct LodeRadialIntegralSpline {
-    spline: HermitCubicSpline<ndarray::Ix2>,
+    spline: Arc<HermitCubicSpline<ndarray::Ix2>>,
     center_contribution: ndarray::Array1<f64>,
 }
 
@@ -32,41 +34,73 @@ impl LodeRadialIntegralSpline {
     /// `radial_integral`. Points are added to the spline until the requested
     /// accuracy is reached. We consider that the accuracy is reached when
     /// either the mean absolute error or the mean relative error gets below the
-    /// `accuracy` threshold.
-    #[time_graph::instrument(name = "LodeRadialIntegralSpline::with_accuracy")]
+    /// corresponding `accuracy` threshold. A single `f64` can be used as
+    /// `accuracy` to set both thresholds at once.
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "LodeRadialIntegralSpline::with_accuracy"))]
     pub fn with_accuracy(
         parameters: LodeRadialIntegralSplineParameters,
-        accuracy: f64,
+        accuracy: impl Into<SplineAccuracy>,
         radial_integral: impl LodeRadialIntegral
     ) -> Result<LodeRadialIntegralSpline, Error> {
-        let shape_tuple = (parameters.max_angular + 1, parameters.max_radial);
+        let spline = create_spline(parameters, accuracy.into(), &radial_integral)?;
 
-        let parameters = SplineParameters {
-            start: 0.0,
-            stop: parameters.cutoff,
-            shape: vec![parameters.max_angular + 1, parameters.max_radial],
-        };
+        return Ok(LodeRadialIntegralSpline {
+            spline: Arc::new(spline),
+            center_contribution: radial_integral.compute_center_contribution()
+        });
+    }
 
-        let spline = HermitCubicSpline::with_accuracy(
-            accuracy,
-            parameters,
-            |x| {
-                let mut values = Array2::from_elem(shape_tuple, 0.0);
-                let mut gradients = Array2::from_elem(shape_tuple, 0.0);
-                radial_integral.compute(x, values.view_mut(), Some(gradients.view_mut()));
-                (values, gradients)
-            },
-        )?;
+    /// Same as `LodeRadialIntegralSpline::with_accuracy`, but re-using the
+    /// spline from other calculators with the same parameters if possible.
+    ///
+    /// `description` must uniquely identify the `radial_integral` (including
+    /// all of its parameters), and is used together with `parameters` and
+    /// `accuracy` as the key in the process-wide cache of splines.
+    pub(crate) fn with_accuracy_cached(
+        description: &str,
+        parameters: LodeRadialIntegralSplineParameters,
+        accuracy: SplineAccuracy,
+        radial_integral: impl LodeRadialIntegral
+    ) -> Result<LodeRadialIntegralSpline, Error> {
+        let key = format!("LODE: {}, {:?}, {:?}", description, parameters, accuracy);
+        let spline = cached_spline(key, || create_spline(parameters, accuracy, &radial_integral))?;
 
         return Ok(LodeRadialIntegralSpline {
-            spline,
+            spline: spline,
             center_contribution: radial_integral.compute_center_contribution()
         });
     }
 }
 
+/// Create a spline for the given `radial_integral`, see
+/// `LodeRadialIntegralSpline::with_accuracy`
+fn create_spline(
+    parameters: LodeRadialIntegralSplineParameters,
+    accuracy: SplineAccuracy,
+    radial_integral: &impl LodeRadialIntegral,
+) -> Result<HermitCubicSpline<ndarray::Ix2>, Error> {
+    let shape_tuple = (parameters.max_angular + 1, parameters.max_radial);
+
+    let parameters = SplineParameters {
+        start: 0.0,
+        stop: parameters.cutoff,
+        shape: vec![parameters.max_angular + 1, parameters.max_radial],
+    };
+
+    return HermitCubicSpline::with_accuracy(
+        accuracy,
+        parameters,
+        |x| {
+            let mut values = Array2::from_elem(shape_tuple, 0.0);
+            let mut gradients = Array2::from_elem(shape_tuple, 0.0);
+            radial_integral.compute(x, values.view_mut(), Some(gradients.view_mut()));
+            (values, gradients)
+        },
+    );
+}
+
 impl LodeRadialIntegral for LodeRadialIntegralSpline {
-    #[time_graph::instrument(name = "SplinedRadialIntegral::compute")]
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "SplinedRadialIntegral::compute"))]
     fn compute(&self, x: f64, values: ArrayViewMut2<f64>, gradients: Option<ArrayViewMut2<f64>>) {
         self.spline.compute(x, values, gradients);
     }
@@ -82,6 +116,7 @@ mod tests {
 
     use super::*;
     use super::super::{LodeRadialIntegralGto, LodeRadialIntegralGtoParameters};
+    use crate::calculators::radial_basis::GtoOrthonormalization;
 
     #[test]
     fn high_accuracy() {
@@ -99,6 +134,8 @@ mod tests {
             cutoff: parameters.cutoff,
             atomic_gaussian_width: 0.5,
             potential_exponent: 1,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         }).unwrap();
 
         // this test only check that this code runs without crashing
@@ -121,6 +158,8 @@ mod tests {
             cutoff: parameters.cutoff,
             atomic_gaussian_width: 0.5,
             potential_exponent: 1,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         }).unwrap();
 
         // even with very bad accuracy, we want the gradients of the spline to
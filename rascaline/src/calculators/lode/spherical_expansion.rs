@@ -9,7 +9,6 @@ use equistore::TensorMap;
 use equistore::{LabelsBuilder, Labels, LabelValue};
 
 use crate::{Error, System, Vector3D};
-use crate::systems::UnitCell;
 
 use crate::labels::{SamplesBuilder, SpeciesFilter, LongRangeSamplesPerAtom};
 use crate::labels::{KeysBuilder, AllSpeciesPairsKeys};
@@ -62,6 +61,12 @@ pub struct LodeSphericalExpansionParameters {
     /// SOAP, p=1 uses 1/r Coulomb like densities, p=6 uses 1/r^6 dispersion
     /// like densities."
     pub potential_exponent: usize,
+    /// Species of the neighbors to remove from the density, for example `[1]`
+    /// to ignore all hydrogen atoms. Atoms with these species are still used
+    /// as centers, but they do not enter the structure factors, and the
+    /// corresponding blocks are not part of the output.
+    #[serde(default)]
+    pub excluded_neighbor_species: Vec<i32>,
 }
 
 impl LodeSphericalExpansionParameters {
@@ -102,7 +107,15 @@ struct StructureFactors {
     imag_per_center: BTreeMap<i32, Array2<f64>>,
 }
 
-fn compute_structure_factors(positions: &[Vector3D], species: &[i32], k_vectors: &[KVector]) -> StructureFactors {
+/// Compute the structure factors for the atoms at the given `positions`.
+/// Atoms with a species in `excluded_species` do not contribute to
+/// `real_per_center` and `imag_per_center`.
+fn compute_structure_factors(
+    positions: &[Vector3D],
+    species: &[i32],
+    excluded_species: &[i32],
+    k_vectors: &[KVector],
+) -> StructureFactors {
     let n_atoms = positions.len();
     let n_k_vectors = k_vectors.len();
 
@@ -118,7 +131,9 @@ fn compute_structure_factors(positions: &[Vector3D], species: &[i32], k_vectors:
         }
     }
 
-    let all_species = species.iter().copied().collect::<BTreeSet<_>>();
+    let all_species = species.iter().copied()
+        .filter(|s| !excluded_species.contains(s))
+        .collect::<BTreeSet<_>>();
     let mut real_per_center = all_species.iter().copied()
         .map(|s| (s, Array2::from_elem((n_atoms, n_k_vectors), 0.0)))
         .collect::<BTreeMap<_, _>>();
@@ -128,6 +143,10 @@ fn compute_structure_factors(positions: &[Vector3D], species: &[i32], k_vectors:
 
     for i in 0..n_atoms {
         for j in 0..n_atoms {
+            if excluded_species.contains(&species[j]) {
+                continue;
+            }
+
             for k in 0..n_k_vectors {
                 let real = cosines[[i, k]] * cosines[[j, k]] + sines[[i, k]] * sines[[j, k]];
                 let imag = sines[[i, k]] * cosines[[j, k]] - cosines[[i, k]] * sines[[j, k]];
@@ -438,6 +457,10 @@ impl CalculatorBase for LodeSphericalExpansion {
 
         let mut builder = LabelsBuilder::new(vec!["spherical_harmonics_l", "species_center", "species_neighbor"]);
         for &[species_center, species_neighbor] in keys.iter_fixed_size() {
+            if self.parameters.excluded_neighbor_species.contains(&species_neighbor.i32()) {
+                continue;
+            }
+
             for spherical_harmonics_l in 0..=self.parameters.max_angular {
                 builder.add(&[spherical_harmonics_l.into(), species_center, species_neighbor]);
             }
@@ -549,7 +572,7 @@ impl CalculatorBase for LodeSphericalExpansion {
         return vec![properties; keys.count()];
     }
 
-    #[time_graph::instrument(name = "LodeSphericalExpansion::compute")]
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "LodeSphericalExpansion::compute"))]
     fn compute(&mut self, systems: &mut [Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
         assert_eq!(descriptor.keys().names(), ["spherical_harmonics_l", "species_center", "species_neighbor"]);
 
@@ -563,10 +586,14 @@ impl CalculatorBase for LodeSphericalExpansion {
             .try_for_each(|(system_i, (system, descriptor))| {
                 let species = system.species()?;
                 let cell = system.cell()?;
-                if cell.shape() == UnitCell::infinite().shape() {
+                if !cell.is_fully_periodic() {
                     return Err(Error::InvalidParameter("LODE can only be used with periodic systems".into()));
                 }
 
+                if system.local_size()? != system.size()? {
+                    return Err(Error::InvalidParameter("LODE can not be used with systems containing ghost atoms".into()));
+                }
+
                 let k_vectors = compute_k_vectors(&cell, self.parameters.get_k_cutoff());
                 if k_vectors.is_empty() {
                     return Err(Error::InvalidParameter("No k-vectors for current combination of hyper parameters.".into()));
@@ -577,6 +604,7 @@ impl CalculatorBase for LodeSphericalExpansion {
                 let structure_factors = compute_structure_factors(
                     system.positions()?,
                     system.species()?,
+                    &self.parameters.excluded_neighbor_species,
                     &k_vectors
                 );
 
@@ -593,7 +621,12 @@ impl CalculatorBase for LodeSphericalExpansion {
                                 0.into(),
                                 species[center_i].into(),
                                 species_neighbor.into(),
-                            ]).expect("missing block");
+                            ]);
+
+                            if block_i.is_none() {
+                                continue;
+                            }
+                            let block_i = block_i.expect("we just checked");
 
                             let mut block = descriptor.block_mut_by_id(block_i);
                             let data = block.data_mut();
@@ -613,12 +646,21 @@ impl CalculatorBase for LodeSphericalExpansion {
                     }
                 }
 
+                // the thread local data is only accessible from the current
+                // thread, keep a plain reference for the parallel loop below
                 let k_vector_to_m_n = self.k_vector_to_m_n.get()
                     .expect("k_vector_to_m_n should have been created")
                     .borrow();
+                let k_vector_to_m_n = &*k_vector_to_m_n;
+
+                // Main loop: evaluate the projection coefficients for all
+                // atoms. Different blocks are independent, and computed in
+                // parallel.
+                descriptor.par_iter_mut().for_each(|(key, mut block)| {
+                    let spherical_harmonics_l = key[0].usize();
+                    let species_center = key[1].i32();
+                    let species_neighbor = key[2].i32();
 
-                // Main loop: Iterate over all atoms to evaluate the projection coefficients
-                for spherical_harmonics_l in 0..=self.parameters.max_angular {
                     let phase = if spherical_harmonics_l % 2 == 0 {
                         (-1.0_f64).powi(spherical_harmonics_l as i32 / 2)
                     } else {
@@ -631,127 +673,133 @@ impl CalculatorBase for LodeSphericalExpansion {
                         &structure_factors.imag_per_center
                     };
 
-                    let k_vector_to_m_n = &k_vector_to_m_n[spherical_harmonics_l];
-
-                    for (&species_neighbor, sf_per_center) in sf_per_center.iter() {
-                        for center_i in 0..system.size()? {
-                            let block_i = descriptor.keys().position(&[
-                                spherical_harmonics_l.into(),
-                                species[center_i].into(),
-                                species_neighbor.into(),
-                            ]);
+                    let sf_per_center = match sf_per_center.get(&species_neighbor) {
+                        Some(sf_per_center) => sf_per_center,
+                        // no atom with this species in the current system
+                        None => return,
+                    };
 
-                            if block_i.is_none() {
-                                continue;
-                            }
-                            let block_i = block_i.expect("we just checked");
+                    let k_vector_to_m_n = &k_vector_to_m_n[spherical_harmonics_l];
 
+                    for center_i in 0..species.len() {
+                        if species[center_i] != species_center {
+                            continue;
+                        }
 
-                            let mut block = descriptor.block_mut_by_id(block_i);
-                            let data = block.data_mut();
-                            let mut array = array_mut_for_system(data.values);
+                        let data = block.data_mut();
+                        let mut array = array_mut_for_system(data.values);
 
-                            let sample = [system_i.into(), center_i.into()];
-                            let sample_i = match data.samples.position(&sample) {
-                                Some(s) => s,
-                                None => continue
-                            };
+                        let sample = [system_i.into(), center_i.into()];
+                        let sample_i = match data.samples.position(&sample) {
+                            Some(s) => s,
+                            None => continue
+                        };
 
-                            for m in 0..(2 * spherical_harmonics_l + 1) {
-                                for (property_i, [n]) in data.properties.iter_fixed_size().enumerate() {
-                                    let n = n.usize();
+                        for m in 0..(2 * spherical_harmonics_l + 1) {
+                            for (property_i, [n]) in data.properties.iter_fixed_size().enumerate() {
+                                let n = n.usize();
 
-                                    let mut value = 0.0;
-                                    for ik in 0..k_vectors.len() {
-                                        // Use unsafe to remove bound checking in
-                                        // release mode with `uget` (everything is
-                                        // still bound checked in debug mode).
-                                        //
-                                        // This divides the calculation time by two
-                                        // for values.
-                                        unsafe {
-                                            value += global_factor * phase
-                                                * density_fourrier.uget(ik)
-                                                * sf_per_center.uget([center_i, ik])
-                                                * k_vector_to_m_n.uget([m, n, ik]);
-                                        }
+                                let mut value = 0.0;
+                                for ik in 0..k_vectors.len() {
+                                    // Use unsafe to remove bound checking in
+                                    // release mode with `uget` (everything is
+                                    // still bound checked in debug mode).
+                                    //
+                                    // This divides the calculation time by two
+                                    // for values.
+                                    unsafe {
+                                        value += global_factor * phase
+                                            * density_fourrier.uget(ik)
+                                            * sf_per_center.uget([center_i, ik])
+                                            * k_vector_to_m_n.uget([m, n, ik]);
                                     }
-                                    array[[sample_i, m, property_i]] += value;
                                 }
+                                array[[sample_i, m, property_i]] += value;
                             }
+                        }
 
-                            if let Some(mut gradient) = block.gradient_mut("positions") {
-                                let gradient = gradient.data_mut();
-                                let mut array = array_mut_for_system(gradient.values);
+                        if let Some(mut gradient) = block.gradient_mut("positions") {
+                            let gradient = gradient.data_mut();
+                            let mut array = array_mut_for_system(gradient.values);
 
-                                for (neighbor_i, &current_neighbor_species) in species.iter().enumerate() {
-                                    if neighbor_i == center_i {
-                                        continue;
-                                    }
+                            for (neighbor_i, &current_neighbor_species) in species.iter().enumerate() {
+                                if neighbor_i == center_i {
+                                    continue;
+                                }
 
-                                    if current_neighbor_species != species_neighbor {
-                                        continue;
-                                    }
+                                if current_neighbor_species != species_neighbor {
+                                    continue;
+                                }
 
-                                    let grad_sample_self_i = gradient.samples.position(&[
-                                        sample_i.into(), system_i.into(), center_i.into()
-                                    ]).expect("missing self gradient sample");
-
-                                    let grad_sample_other_i = gradient.samples.position(&[
-                                        sample_i.into(), system_i.into(), neighbor_i.into()
-                                    ]).expect("missing gradient sample");
-
-                                    let mut sf_grad = Vec::with_capacity(k_vectors.len());
-                                    let cosines = &structure_factors.real;
-                                    let sines = &structure_factors.imag;
-                                    if spherical_harmonics_l % 2 == 0 {
-                                        let i = center_i;
-                                        let j = neighbor_i;
-                                        // real part of i*e^{i k (rj - ri)}
-                                        for ik in 0..k_vectors.len() {
-                                            let factor = sines[[i, ik]] * cosines[[j, ik]]
-                                                - cosines[[i, ik]] * sines[[j, ik]];
-                                            sf_grad.push(2.0 * factor);
-                                        }
-                                    } else {
-                                        let i = center_i;
-                                        let j = neighbor_i;
-                                        // imaginary part of i*e^{i k (rj - ri)}
-                                        for ik in 0..k_vectors.len() {
-                                            let factor = cosines[[i, ik]] * cosines[[j, ik]]
-                                                + sines[[i, ik]] * sines[[j, ik]];
-                                            sf_grad.push(-2.0 * factor);
-                                        }
+                                // these samples might be missing if the
+                                // user restricted the gradients to some
+                                // of the atoms
+                                let grad_sample_self_i = gradient.samples.position(&[
+                                    sample_i.into(), system_i.into(), center_i.into()
+                                ]);
+
+                                let grad_sample_other_i = gradient.samples.position(&[
+                                    sample_i.into(), system_i.into(), neighbor_i.into()
+                                ]);
+
+                                if grad_sample_self_i.is_none() && grad_sample_other_i.is_none() {
+                                    continue;
+                                }
+
+                                let mut sf_grad = Vec::with_capacity(k_vectors.len());
+                                let cosines = &structure_factors.real;
+                                let sines = &structure_factors.imag;
+                                if spherical_harmonics_l % 2 == 0 {
+                                    let i = center_i;
+                                    let j = neighbor_i;
+                                    // real part of i*e^{i k (rj - ri)}
+                                    for ik in 0..k_vectors.len() {
+                                        let factor = sines[[i, ik]] * cosines[[j, ik]]
+                                            - cosines[[i, ik]] * sines[[j, ik]];
+                                        sf_grad.push(2.0 * factor);
+                                    }
+                                } else {
+                                    let i = center_i;
+                                    let j = neighbor_i;
+                                    // imaginary part of i*e^{i k (rj - ri)}
+                                    for ik in 0..k_vectors.len() {
+                                        let factor = cosines[[i, ik]] * cosines[[j, ik]]
+                                            + sines[[i, ik]] * sines[[j, ik]];
+                                        sf_grad.push(-2.0 * factor);
                                     }
-                                    let sf_grad = Array1::from(sf_grad);
-
-                                    for m in 0..(2 * spherical_harmonics_l + 1) {
-                                        for (property_i, [n]) in gradient.properties.iter_fixed_size().enumerate() {
-                                            let n = n.usize();
-
-                                            let mut grad = Vector3D::zero();
-                                            for (ik, k_vector) in k_vectors.iter().enumerate() {
-                                                // Use unsafe to remove bound
-                                                // checking in release mode with
-                                                // `uget` (everything is still bound
-                                                // checked in debug mode).
-                                                //
-                                                // This divides the calculation time
-                                                // by ten for gradients.
-                                                unsafe {
-                                                    grad += global_factor * phase
-                                                        * density_fourrier.uget(ik)
-                                                        * sf_grad.uget(ik)
-                                                        * k_vector_to_m_n.uget([m, n, ik])
-                                                        * k_vector.norm
-                                                        * k_vector.direction;
-                                                }
+                                }
+                                let sf_grad = Array1::from(sf_grad);
+
+                                for m in 0..(2 * spherical_harmonics_l + 1) {
+                                    for (property_i, [n]) in gradient.properties.iter_fixed_size().enumerate() {
+                                        let n = n.usize();
+
+                                        let mut grad = Vector3D::zero();
+                                        for (ik, k_vector) in k_vectors.iter().enumerate() {
+                                            // Use unsafe to remove bound
+                                            // checking in release mode with
+                                            // `uget` (everything is still bound
+                                            // checked in debug mode).
+                                            //
+                                            // This divides the calculation time
+                                            // by ten for gradients.
+                                            unsafe {
+                                                grad += global_factor * phase
+                                                    * density_fourrier.uget(ik)
+                                                    * sf_grad.uget(ik)
+                                                    * k_vector_to_m_n.uget([m, n, ik])
+                                                    * k_vector.norm
+                                                    * k_vector.direction;
                                             }
+                                        }
 
+                                        if let Some(grad_sample_other_i) = grad_sample_other_i {
                                             array[[grad_sample_other_i, 0, m, property_i]] += grad[0];
                                             array[[grad_sample_other_i, 1, m, property_i]] += grad[1];
                                             array[[grad_sample_other_i, 2, m, property_i]] += grad[2];
+                                        }
 
+                                        if let Some(grad_sample_self_i) = grad_sample_self_i {
                                             array[[grad_sample_self_i, 0, m, property_i]] -= grad[0];
                                             array[[grad_sample_self_i, 1, m, property_i]] -= grad[1];
                                             array[[grad_sample_self_i, 2, m, property_i]] -= grad[2];
@@ -761,7 +809,7 @@ impl CalculatorBase for LodeSphericalExpansion {
                             }
                         }
                     }
-                }
+                });
 
                 return Ok(());
             }
@@ -777,6 +825,7 @@ impl CalculatorBase for LodeSphericalExpansion {
 mod tests {
     use crate::Calculator;
     use crate::calculators::CalculatorBase;
+    use crate::systems::UnitCell;
     use crate::systems::test_utils::test_system;
 
     use Vector3D;
@@ -803,10 +852,11 @@ mod tests {
                     center_atom_weight: 1.0,
                     radial_basis: RadialBasis::splined_gto(1e-8),
                     potential_exponent: p,
+                    excluded_neighbor_species: Vec::new(),
                 }
             ).unwrap()) as Box<dyn CalculatorBase>);
 
-            let options = crate::calculators::tests_utils::FinalDifferenceOptions {
+            let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
                 displacement: 1e-5,
                 max_relative: 1e-4,
                 epsilon: 1e-10,
@@ -827,6 +877,7 @@ mod tests {
                 center_atom_weight: 1.0,
                 radial_basis: RadialBasis::splined_gto(1e-8),
                 potential_exponent: 1,
+                excluded_neighbor_species: Vec::new(),
             }
         ).unwrap()) as Box<dyn CalculatorBase>);
 
@@ -888,6 +939,7 @@ mod tests {
                     center_atom_weight: 1.0,
                     radial_basis: RadialBasis::splined_gto(1e-8),
                     potential_exponent: p,
+                    excluded_neighbor_species: Vec::new(),
                 }
             ).unwrap();
 
@@ -911,6 +963,7 @@ mod tests {
             center_atom_weight: 1.0,
             potential_exponent: 1,
             radial_basis: RadialBasis::splined_gto(1e-8),
+            excluded_neighbor_species: Vec::new(),
         };
 
         assert_eq!(
@@ -931,6 +984,7 @@ mod tests {
                 center_atom_weight: 1.0,
                 potential_exponent: 0,
                 radial_basis: RadialBasis::splined_gto(1e-8),
+                excluded_neighbor_species: Vec::new(),
             }
         ).unwrap();
 
@@ -952,6 +1006,7 @@ mod tests {
             center_atom_weight: 1.0,
             potential_exponent: 6,
             radial_basis: RadialBasis::splined_gto(1e-8),
+            excluded_neighbor_species: Vec::new(),
         }).unwrap();
 
         assert_relative_eq!(
@@ -1,6 +1,7 @@
 use equistore::{TensorMap, Labels};
 
 use crate::{Error, System};
+use crate::labels::hessian_samples_from_gradients;
 
 /// The `CalculatorBase` trait is the interface shared by all calculator
 /// implementations; and used by [`crate::Calculator`] to run the calculation.
@@ -9,14 +10,22 @@ use crate::{Error, System};
 /// in [`crate::Calculator`] instead.
 ///
 /// `std::panic::RefUnwindSafe` is a required super-trait to enable passing
-/// calculators across the C API.
-pub trait CalculatorBase: std::panic::RefUnwindSafe {
+/// calculators across the C API, and `Send` is required to run the
+/// calculations inside a thread pool with a limited number of threads.
+pub trait CalculatorBase: std::panic::RefUnwindSafe + Send {
     /// Get the name of this Calculator
     fn name(&self) -> String;
 
     /// Get the parameters used to create this Calculator as a JSON string
     fn parameters(&self) -> String;
 
+    /// Get the spherical cutoff used by this calculator to compute the
+    /// neighbor list of the systems, if any. The default implementation
+    /// returns `None`, i.e. the calculator does not use neighbor lists.
+    fn cutoff(&self) -> Option<f64> {
+        None
+    }
+
     /// Get the set of keys for this calculator and the given systems
     fn keys(&self, systems: &mut [Box<dyn System>]) -> Result<Labels, Error>;
 
@@ -28,7 +37,10 @@ pub trait CalculatorBase: std::panic::RefUnwindSafe {
     fn samples(&self, keys: &Labels, systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error>;
 
     /// Can this calculator compute gradients with respect to the `parameter`?
-    /// Right now, `parameter` can be either `"positions"` or `"cell"`.
+    /// Right now, `parameter` can be `"positions"`, `"cell"`,
+    /// `"positions_hessian"` for second derivatives with respect to positions,
+    /// or `"density_scaling"` for gradients with respect to the per-atom
+    /// density scaling of the systems.
     fn supports_gradient(&self, parameter: &str) -> bool;
 
     /// Get the samples for gradients with respect to positions, corresponding
@@ -40,6 +52,28 @@ pub trait CalculatorBase: std::panic::RefUnwindSafe {
     /// function should return an error.
     fn positions_gradient_samples(&self, keys: &Labels, samples: &[Labels], systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error>;
 
+    /// Get the samples for second derivatives with respect to positions,
+    /// corresponding the given values samples.
+    ///
+    /// This is only called for calculators supporting `"positions_hessian"`
+    /// gradients. The default implementation uses all couples of atoms
+    /// appearing in the same sample of [`CalculatorBase::positions_gradient_samples`].
+    fn positions_hessian_samples(&self, keys: &Labels, samples: &[Labels], systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
+        let gradient_samples = self.positions_gradient_samples(keys, samples, systems)?;
+        return Ok(gradient_samples.iter().map(hessian_samples_from_gradients).collect());
+    }
+
+    /// Get the samples for gradients with respect to the per-atom density
+    /// scaling (see [`System::density_scaling`]), corresponding the given
+    /// values samples.
+    ///
+    /// This is only called for calculators supporting `"density_scaling"`
+    /// gradients. The default implementation uses the same samples as
+    /// [`CalculatorBase::positions_gradient_samples`].
+    fn density_scaling_gradient_samples(&self, keys: &Labels, samples: &[Labels], systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
+        return self.positions_gradient_samples(keys, samples, systems);
+    }
+
     /// Get the components this calculator computes for each key.
     fn components(&self, keys: &Labels) -> Vec<Vec<Labels>>;
 
@@ -49,6 +83,16 @@ pub trait CalculatorBase: std::panic::RefUnwindSafe {
     /// Get the properties this calculator computes for each key.
     fn properties(&self, keys: &Labels) -> Vec<Labels>;
 
+    /// Estimate the amount of work needed to compute the values of this
+    /// calculator for the given `systems`, in arbitrary units roughly
+    /// proportional to the number of floating point operations. This is used
+    /// by [`crate::Calculator::estimate`]. The default implementation returns
+    /// `None`, i.e. no estimate is available for this calculator.
+    fn work_estimate(&self, systems: &mut [Box<dyn System>]) -> Result<Option<usize>, Error> {
+        let _ = systems;
+        return Ok(None);
+    }
+
     /// Actually run the calculation.
     ///
     /// This function is given a pre-allocated descriptor, filled with zeros.
@@ -57,10 +101,10 @@ pub trait CalculatorBase: std::panic::RefUnwindSafe {
     /// [`CalculatorBase::properties`]: instead they will only contain the
     /// values that where requested by the end user.
     ///
-    /// Gradients (with respect to positions or cell) are allocated in each
-    /// block if they are supported according to
-    /// [`CalculatorBase::supports_gradient`], and the users requested them as
-    /// part of the calculation options.
+    /// Gradients (with respect to positions, cell or density scaling, and
+    /// second derivatives with respect to positions) are allocated in each block if they are
+    /// supported according to [`CalculatorBase::supports_gradient`], and the
+    /// users requested them as part of the calculation options.
     fn compute(&mut self, systems: &mut [Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error>;
 }
 
@@ -80,8 +124,24 @@ pub use self::sorted_distances::SortedDistances;
 mod neighbor_list;
 pub use self::neighbor_list::NeighborList;
 
+mod bond_features;
+pub use self::bond_features::BondFeatures;
+
+mod piv;
+pub use self::piv::PermutationInvariantVector;
+
+mod cna;
+pub use self::cna::CommonNeighborAnalysis;
+
+mod centro_symmetry;
+pub use self::centro_symmetry::CentroSymmetry;
+
+mod dipolar_tensor;
+pub use self::dipolar_tensor::EwaldDipolarTensor;
+
 mod radial_basis;
-pub use self::radial_basis::{RadialBasis, GtoRadialBasis};
+pub use self::radial_basis::{RadialBasis, GtoRadialBasis, GtoOrthonormalization, LaguerreRadialBasis};
+pub use self::radial_basis::{SplinePoint, generate_splines};
 
 mod descriptors_by_systems;
 pub(crate) use self::descriptors_by_systems::{array_mut_for_system, split_tensor_map_by_system};
@@ -1,9 +1,12 @@
 use std::collections::BTreeSet;
 
+use rayon::prelude::*;
+
 use equistore::TensorMap;
 use equistore::{Labels, LabelsBuilder, LabelValue};
 
 use super::CalculatorBase;
+use super::{split_tensor_map_by_system, array_mut_for_system};
 
 use crate::{Error, System};
 
@@ -61,6 +64,10 @@ impl CalculatorBase for NeighborList {
         serde_json::to_string(self).expect("failed to serialize to JSON")
     }
 
+    fn cutoff(&self) -> Option<f64> {
+        Some(self.cutoff)
+    }
+
     fn keys(&self, systems: &mut [Box<dyn System>]) -> Result<Labels, Error> {
         assert!(self.cutoff > 0.0 && self.cutoff.is_finite());
 
@@ -88,11 +95,20 @@ impl CalculatorBase for NeighborList {
     fn supports_gradient(&self, parameter: &str) -> bool {
         match parameter {
             "positions" => true,
+            // the pair vectors are linear functions of the positions, so all
+            // second derivatives are zero
+            "positions_hessian" => true,
             // TODO: add support for cell gradients
             _ => false,
         }
     }
 
+    fn positions_hessian_samples(&self, _keys: &Labels, samples: &[Labels], _systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
+        // all the second derivatives are zero, there is no need to store them
+        let empty = Labels::empty(vec!["sample", "structure", "atom_1", "atom_2"]);
+        return Ok(vec![empty; samples.len()]);
+    }
+
     fn positions_gradient_samples(&self, _keys: &Labels, samples: &[Labels], _systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
         let mut results = Vec::new();
 
@@ -130,7 +146,7 @@ impl CalculatorBase for NeighborList {
         return vec![properties; keys.count()];
     }
 
-    #[time_graph::instrument(name = "NeighborList::compute")]
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "NeighborList::compute"))]
     fn compute(&mut self, systems: &mut [Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
         if self.full_neighbor_list {
             FullNeighborList { cutoff: self.cutoff, self_pairs: self.self_pairs }.compute(systems, descriptor)
@@ -225,74 +241,91 @@ impl HalfNeighborList {
     }
 
     fn compute(&mut self, systems: &mut [Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
-        for (system_i, system) in systems.iter_mut().enumerate() {
-            system.compute_neighbors(self.cutoff)?;
-            let species = system.species()?;
+        let mut descriptors_by_system = split_tensor_map_by_system(descriptor, systems.len());
+
+        systems.par_iter_mut()
+            .zip_eq(&mut descriptors_by_system)
+            .enumerate()
+            .try_for_each(|(system_i, (system, descriptor))| {
+                system.compute_neighbors(self.cutoff)?;
+                let species = system.species()?;
+
+                for (pair_id, pair) in system.pairs()?.iter().enumerate() {
+                    // Sort the species in the pair to ensure a canonical order of
+                    // the atoms in it. This guarantee that multiple call to this
+                    // calculator always returns pairs in the same order, even if
+                    // the underlying neighbor list implementation (which comes from
+                    // the systems) changes.
+                    //
+                    // The `invert` variable tells us if we need to invert the pair
+                    // vector or not.
+                    let ((species_i, species_j), invert) = sort_pair((species[pair.first], species[pair.second]));
+
+                    let pair_vector = if invert {
+                        -pair.vector
+                    } else {
+                        pair.vector
+                    };
+
+                    let (atom_i, atom_j) = if invert {
+                        (pair.second, pair.first)
+                    } else {
+                        (pair.first, pair.second)
+                    };
+
+                    // the block might be missing if the user selected a
+                    // subset of the keys
+                    let block_id = match descriptor.keys().position(&[
+                        species_i.into(), species_j.into()
+                    ]) {
+                        Some(block_id) => block_id,
+                        None => continue,
+                    };
+
+                    let mut block = descriptor.block_mut_by_id(block_id);
+                    let block_data = block.data_mut();
+
+                    let sample_i = block_data.samples.position(&[
+                        system_i.into(), pair_id.into(), atom_i.into(), atom_j.into()
+                    ]);
+
+                    if let Some(sample_i) = sample_i {
+                        let mut array = array_mut_for_system(block_data.values);
 
-            for (pair_id, pair) in system.pairs()?.iter().enumerate() {
-                // Sort the species in the pair to ensure a canonical order of
-                // the atoms in it. This guarantee that multiple call to this
-                // calculator always returns pairs in the same order, even if
-                // the underlying neighbor list implementation (which comes from
-                // the systems) changes.
-                //
-                // The `invert` variable tells us if we need to invert the pair
-                // vector or not.
-                let ((species_i, species_j), invert) = sort_pair((species[pair.first], species[pair.second]));
-
-                let pair_vector = if invert {
-                    -pair.vector
-                } else {
-                    pair.vector
-                };
-
-                let (atom_i, atom_j) = if invert {
-                    (pair.second, pair.first)
-                } else {
-                    (pair.first, pair.second)
-                };
-
-                let block_id = descriptor.keys().position(&[
-                    species_i.into(), species_j.into()
-                ]).expect("missing block");
-
-                let mut block = descriptor.block_mut_by_id(block_id);
-                let block_data = block.data_mut();
-
-                let sample_i = block_data.samples.position(&[
-                    system_i.into(), pair_id.into(), atom_i.into(), atom_j.into()
-                ]);
-
-                if let Some(sample_i) = sample_i {
-                    let array = block_data.values.to_array_mut();
-
-                    array[[sample_i, 0, 0]] = pair_vector[0];
-                    array[[sample_i, 1, 0]] = pair_vector[1];
-                    array[[sample_i, 2, 0]] = pair_vector[2];
-
-                    if let Some(mut gradient) = block.gradient_mut("positions") {
-                        let gradient = gradient.data_mut();
-
-                        let first_grad_sample_i = gradient.samples.position(&[
-                            sample_i.into(), system_i.into(), atom_i.into()
-                        ]).expect("missing gradient sample");
-                        let second_grad_sample_i = gradient.samples.position(&[
-                            sample_i.into(), system_i.into(), atom_j.into()
-                        ]).expect("missing gradient sample");
-
-                        let array = gradient.values.to_array_mut();
-
-                        array[[first_grad_sample_i, 0, 0, 0]] = -1.0;
-                        array[[first_grad_sample_i, 1, 1, 0]] = -1.0;
-                        array[[first_grad_sample_i, 2, 2, 0]] = -1.0;
-
-                        array[[second_grad_sample_i, 0, 0, 0]] = 1.0;
-                        array[[second_grad_sample_i, 1, 1, 0]] = 1.0;
-                        array[[second_grad_sample_i, 2, 2, 0]] = 1.0;
+                        array[[sample_i, 0, 0]] = pair_vector[0];
+                        array[[sample_i, 1, 0]] = pair_vector[1];
+                        array[[sample_i, 2, 0]] = pair_vector[2];
+
+                        if let Some(mut gradient) = block.gradient_mut("positions") {
+                            let gradient = gradient.data_mut();
+
+                            let mut array = array_mut_for_system(gradient.values);
+
+                            // gradient samples might be missing if the user restricted
+                            // the gradients to some of the atoms
+                            let first_grad_sample_i = gradient.samples.position(&[
+                                sample_i.into(), system_i.into(), atom_i.into()
+                            ]);
+                            if let Some(first_grad_sample_i) = first_grad_sample_i {
+                                array[[first_grad_sample_i, 0, 0, 0]] = -1.0;
+                                array[[first_grad_sample_i, 1, 1, 0]] = -1.0;
+                                array[[first_grad_sample_i, 2, 2, 0]] = -1.0;
+                            }
+
+                            let second_grad_sample_i = gradient.samples.position(&[
+                                sample_i.into(), system_i.into(), atom_j.into()
+                            ]);
+                            if let Some(second_grad_sample_i) = second_grad_sample_i {
+                                array[[second_grad_sample_i, 0, 0, 0]] = 1.0;
+                                array[[second_grad_sample_i, 1, 1, 0]] = 1.0;
+                                array[[second_grad_sample_i, 2, 2, 0]] = 1.0;
+                            }
+                        }
                     }
                 }
-            }
-        }
+
+                Ok::<_, Error>(())
+            })?;
 
         return Ok(());
     }
@@ -390,107 +423,82 @@ impl FullNeighborList {
     }
 
     fn compute(&mut self, systems: &mut [Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
-        for (system_i, system) in systems.iter_mut().enumerate() {
-            system.compute_neighbors(self.cutoff)?;
-            let species = system.species()?;
-
-            for (pair_id, pair) in system.pairs()?.iter().enumerate() {
-                let first_block_id = descriptor.keys().position(&[
-                    species[pair.first].into(), species[pair.second].into()
-                ]).expect("missing block");
-
-                let second_block_id = if species[pair.first] == species[pair.second] {
-                    None
-                } else {
-                    Some(descriptor.keys().position(&[
-                        species[pair.second].into(), species[pair.first].into()
-                    ]).expect("missing block"))
-                };
-
-                // first, the pair first -> second
-                let mut block = descriptor.block_mut_by_id(first_block_id);
-                let block_data = block.data_mut();
-
-                let sample_i = block_data.samples.position(&[
-                    system_i.into(), pair_id.into(), pair.first.into(), pair.second.into()
-                ]);
-
-                if let Some(sample_i) = sample_i {
-                    let array = block_data.values.to_array_mut();
-
-                    array[[sample_i, 0, 0]] = pair.vector[0];
-                    array[[sample_i, 1, 0]] = pair.vector[1];
-                    array[[sample_i, 2, 0]] = pair.vector[2];
-
-                    if let Some(mut gradient) = block.gradient_mut("positions") {
-                        let gradient = gradient.data_mut();
-
-                        let first_grad_sample_i = gradient.samples.position(&[
-                            sample_i.into(), system_i.into(), pair.first.into()
-                        ]).expect("missing gradient sample");
-                        let second_grad_sample_i = gradient.samples.position(&[
-                            sample_i.into(), system_i.into(), pair.second.into()
-                        ]).expect("missing gradient sample");
-
-                        let array = gradient.values.to_array_mut();
-
-                        array[[first_grad_sample_i, 0, 0, 0]] = -1.0;
-                        array[[first_grad_sample_i, 1, 1, 0]] = -1.0;
-                        array[[first_grad_sample_i, 2, 2, 0]] = -1.0;
-
-                        array[[second_grad_sample_i, 0, 0, 0]] = 1.0;
-                        array[[second_grad_sample_i, 1, 1, 0]] = 1.0;
-                        array[[second_grad_sample_i, 2, 2, 0]] = 1.0;
-                    }
-                }
-
-                // then the pair second -> first
-                let mut block = if let Some(second_block_id) = second_block_id {
-                    descriptor.block_mut_by_id(second_block_id)
-                } else {
-                    if pair.first == pair.second {
-                        // do not duplicate self pairs
-                        continue
-                    }
-                    // same species for both atoms in the pair, keep the same block
-                    block
-                };
-
-                let block_data = block.data_mut();
-                let sample_i = block_data.samples.position(&[
-                    system_i.into(), pair_id.into(), pair.second.into(), pair.first.into()
-                ]);
-
-                if let Some(sample_i) = sample_i {
-                    let array = block_data.values.to_array_mut();
-
-                    array[[sample_i, 0, 0]] = -pair.vector[0];
-                    array[[sample_i, 1, 0]] = -pair.vector[1];
-                    array[[sample_i, 2, 0]] = -pair.vector[2];
-
-                    if let Some(mut gradient) = block.gradient_mut("positions") {
-                        let gradient = gradient.data_mut();
+        let mut descriptors_by_system = split_tensor_map_by_system(descriptor, systems.len());
 
-                        let first_grad_sample_i = gradient.samples.position(&[
-                            sample_i.into(), system_i.into(), pair.second.into()
-                        ]).expect("missing gradient sample");
-                        let second_grad_sample_i = gradient.samples.position(&[
-                            sample_i.into(), system_i.into(), pair.first.into()
-                        ]).expect("missing gradient sample");
-
-                        let array = gradient.values.to_array_mut();
+        systems.par_iter_mut()
+            .zip_eq(&mut descriptors_by_system)
+            .enumerate()
+            .try_for_each(|(system_i, (system, descriptor))| {
+                system.compute_neighbors(self.cutoff)?;
+                let species = system.species()?;
 
-                        array[[first_grad_sample_i, 0, 0, 0]] = -1.0;
-                        array[[first_grad_sample_i, 1, 1, 0]] = -1.0;
-                        array[[first_grad_sample_i, 2, 2, 0]] = -1.0;
+                for (pair_id, pair) in system.pairs()?.iter().enumerate() {
+                    // first, the pair first -> second, then the pair second -> first
+                    let directions = [
+                        (pair.first, pair.second, pair.vector),
+                        (pair.second, pair.first, -pair.vector),
+                    ];
+
+                    for (direction_i, (atom_i, atom_j, pair_vector)) in directions.into_iter().enumerate() {
+                        if direction_i == 1 && pair.first == pair.second {
+                            // do not duplicate self pairs
+                            continue;
+                        }
 
-                        array[[second_grad_sample_i, 0, 0, 0]] = 1.0;
-                        array[[second_grad_sample_i, 1, 1, 0]] = 1.0;
-                        array[[second_grad_sample_i, 2, 2, 0]] = 1.0;
+                        // the block might be missing if the user selected a
+                        // subset of the keys
+                        let block_id = match descriptor.keys().position(&[
+                            species[atom_i].into(), species[atom_j].into()
+                        ]) {
+                            Some(block_id) => block_id,
+                            None => continue,
+                        };
+
+                        let mut block = descriptor.block_mut_by_id(block_id);
+                        let block_data = block.data_mut();
+
+                        let sample_i = block_data.samples.position(&[
+                            system_i.into(), pair_id.into(), atom_i.into(), atom_j.into()
+                        ]);
+
+                        if let Some(sample_i) = sample_i {
+                            let mut array = array_mut_for_system(block_data.values);
+
+                            array[[sample_i, 0, 0]] = pair_vector[0];
+                            array[[sample_i, 1, 0]] = pair_vector[1];
+                            array[[sample_i, 2, 0]] = pair_vector[2];
+
+                            if let Some(mut gradient) = block.gradient_mut("positions") {
+                                let gradient = gradient.data_mut();
+
+                                let mut array = array_mut_for_system(gradient.values);
+
+                                // gradient samples might be missing if the user restricted
+                                // the gradients to some of the atoms
+                                let first_grad_sample_i = gradient.samples.position(&[
+                                    sample_i.into(), system_i.into(), atom_i.into()
+                                ]);
+                                if let Some(first_grad_sample_i) = first_grad_sample_i {
+                                    array[[first_grad_sample_i, 0, 0, 0]] = -1.0;
+                                    array[[first_grad_sample_i, 1, 1, 0]] = -1.0;
+                                    array[[first_grad_sample_i, 2, 2, 0]] = -1.0;
+                                }
+
+                                let second_grad_sample_i = gradient.samples.position(&[
+                                    sample_i.into(), system_i.into(), atom_j.into()
+                                ]);
+                                if let Some(second_grad_sample_i) = second_grad_sample_i {
+                                    array[[second_grad_sample_i, 0, 0, 0]] = 1.0;
+                                    array[[second_grad_sample_i, 1, 1, 0]] = 1.0;
+                                    array[[second_grad_sample_i, 2, 2, 0]] = 1.0;
+                                }
+                            }
+                        }
                     }
                 }
-            }
-        }
+
+                Ok::<_, Error>(())
+            })?;
 
         return Ok(());
     }
@@ -643,7 +651,7 @@ mod tests {
         }) as Box<dyn CalculatorBase>);
 
         let system = test_system("water");
-        let options = crate::calculators::tests_utils::FinalDifferenceOptions {
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
             displacement: 1e-6,
             max_relative: 1e-9,
             epsilon: 1e-16,
@@ -659,6 +667,30 @@ mod tests {
         crate::calculators::tests_utils::finite_differences_positions(calculator, &system, options);
     }
 
+    #[test]
+    fn positions_hessian() {
+        let mut calculator = Calculator::from(Box::new(NeighborList{
+            cutoff: 2.0,
+            full_neighbor_list: true,
+            self_pairs: false,
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water"]);
+        let options = crate::CalculationOptions {
+            gradients: &["positions_hessian"],
+            ..Default::default()
+        };
+        let descriptor = calculator.compute(&mut systems, options).unwrap();
+
+        for (_, block) in descriptor.iter() {
+            // pair vectors are linear in positions, so all second derivatives
+            // are zero
+            let hessian = block.gradient("positions_hessian").unwrap();
+            assert_eq!(hessian.samples().names(), ["sample", "structure", "atom_1", "atom_2"]);
+            assert_eq!(hessian.samples().count(), 0);
+        }
+    }
+
     #[test]
     fn compute_partial() {
         // half neighbor list
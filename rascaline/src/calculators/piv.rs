@@ -0,0 +1,501 @@
+use std::collections::BTreeSet;
+
+use equistore::{Labels, LabelsBuilder, TensorMap};
+
+use super::CalculatorBase;
+
+use crate::{Error, System, Vector3D};
+use crate::systems::CellShape;
+
+
+/// Permutation invariant vector (PIV) representation of a structure.
+///
+/// The PIV was introduced by Gallet and Pietrucci (J. Chem. Phys. 139, 074101,
+/// 2013) as a collective variable for enhanced sampling simulations. For each
+/// pair of species, all the pairs of atoms within the `cutoff` are transformed
+/// with a rational switching function
+///
+/// $$ s(r) = \frac{1 - (r / r_0)^n}{1 - (r / r_0)^m}, $$
+///
+/// which is shifted and scaled to go to zero at the cutoff. The resulting
+/// values are sorted from largest to smallest, and the first `max_pairs`
+/// values are used as features. If there are less than `max_pairs` pairs for a
+/// given species pair, the remaining entries are set to zero.
+///
+/// Since the values are sorted, the PIV is invariant to permutations of atoms
+/// with the same species. The gradients are computed assuming a fixed order
+/// of the pairs, and are thus only well defined when there are no degenerate
+/// pair distances.
+#[derive(Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+pub struct PermutationInvariantVector {
+    /// Spherical cutoff. Pairs further apart than this are not included in
+    /// the PIV, and the switching function goes smoothly to zero there.
+    pub cutoff: f64,
+    /// Distance parameter `r_0` of the switching function
+    pub r_0: f64,
+    /// Exponent of the numerator of the switching function
+    pub n: i32,
+    /// Exponent of the denominator of the switching function, this must be
+    /// larger than `n`
+    pub m: i32,
+    /// Number of pairs to include in each species block. This is also the
+    /// number of properties.
+    pub max_pairs: usize,
+}
+
+/// Contribution of a single pair to the PIV
+#[derive(Debug, Clone, Copy)]
+struct PairContribution {
+    /// value of the switching function for this pair
+    value: f64,
+    /// gradient of the switching function w.r.t. the pair vector
+    gradient: Vector3D,
+    /// vector between the first and second atom in the pair
+    vector: Vector3D,
+    first: usize,
+    second: usize,
+}
+
+impl PermutationInvariantVector {
+    fn validate(&self) -> Result<(), Error> {
+        if !(self.cutoff > 0.0 && self.cutoff.is_finite()) {
+            return Err(Error::InvalidParameter(
+                "cutoff must be a positive number for PIV".into()
+            ));
+        }
+
+        if !(self.r_0 > 0.0 && self.r_0.is_finite()) {
+            return Err(Error::InvalidParameter(
+                "r_0 must be a positive number for PIV".into()
+            ));
+        }
+
+        if self.n <= 0 || self.m <= self.n {
+            return Err(Error::InvalidParameter(format!(
+                "expected 0 < n < m for PIV switching function, got n={} and m={}",
+                self.n, self.m
+            )));
+        }
+
+        return Ok(());
+    }
+
+    /// Compute the rational function and its derivative w.r.t. `r`
+    fn rational(&self, r: f64) -> (f64, f64) {
+        let x = r / self.r_0;
+        let n = f64::from(self.n);
+        let m = f64::from(self.m);
+
+        let delta = x - 1.0;
+        if delta.abs() < 1e-6 {
+            // use a first order expansion to avoid the 0/0 at r = r_0
+            let derivative = n * (n - m) / (2.0 * m);
+            return (n / m + derivative * delta, derivative / self.r_0);
+        }
+
+        let x_n_1 = x.powi(self.n - 1);
+        let x_m_1 = x.powi(self.m - 1);
+
+        let numerator = 1.0 - x_n_1 * x;
+        let denominator = 1.0 - x_m_1 * x;
+
+        let value = numerator / denominator;
+        let derivative = (-n * x_n_1 * denominator + m * x_m_1 * numerator) / (denominator * denominator);
+
+        return (value, derivative / self.r_0);
+    }
+
+    /// Compute the switching function, shifted and scaled to go to zero at the
+    /// cutoff, and its derivative w.r.t. `r`
+    fn switching(&self, r: f64) -> (f64, f64) {
+        if r >= self.cutoff {
+            return (0.0, 0.0);
+        }
+
+        let (value, derivative) = self.rational(r);
+        let (value_cutoff, _) = self.rational(self.cutoff);
+        let scaling = 1.0 / (1.0 - value_cutoff);
+
+        return ((value - value_cutoff) * scaling, derivative * scaling);
+    }
+
+    /// Get the contributions of all pairs between `species_first` and
+    /// `species_second` in the system, sorted by decreasing values
+    fn sorted_contributions(
+        &self,
+        system: &dyn System,
+        species_first: i32,
+        species_second: i32,
+    ) -> Result<Vec<PairContribution>, Error> {
+        let species = system.species()?;
+
+        let mut contributions = Vec::new();
+        for pair in system.pairs()? {
+            let pair_species = (species[pair.first], species[pair.second]);
+            if pair_species != (species_first, species_second) && pair_species != (species_second, species_first) {
+                continue;
+            }
+
+            if pair.distance >= self.cutoff {
+                continue;
+            }
+
+            let (value, derivative) = self.switching(pair.distance);
+
+            contributions.push(PairContribution {
+                value: value,
+                gradient: derivative * pair.vector / pair.distance,
+                vector: pair.vector,
+                first: pair.first,
+                second: pair.second,
+            });
+        }
+
+        // the sort is stable, so pairs with the same value stay in the
+        // neighbor list order
+        contributions.sort_by(|a, b| b.value.partial_cmp(&a.value).expect("got NaN in PIV"));
+
+        return Ok(contributions);
+    }
+}
+
+impl CalculatorBase for PermutationInvariantVector {
+    fn name(&self) -> String {
+        "permutation invariant vector".into()
+    }
+
+    fn parameters(&self) -> String {
+        serde_json::to_string(self).expect("failed to serialize to JSON")
+    }
+
+    fn cutoff(&self) -> Option<f64> {
+        Some(self.cutoff)
+    }
+
+    fn keys(&self, systems: &mut [Box<dyn System>]) -> Result<Labels, Error> {
+        self.validate()?;
+
+        let mut all_species = BTreeSet::new();
+        for system in systems {
+            all_species.extend(system.species()?.iter().copied());
+        }
+
+        let mut keys = LabelsBuilder::new(vec!["species_first_atom", "species_second_atom"]);
+        for &species_first in &all_species {
+            for &species_second in all_species.range(species_first..) {
+                keys.add(&[species_first, species_second]);
+            }
+        }
+
+        return Ok(keys.finish());
+    }
+
+    fn samples_names(&self) -> Vec<&str> {
+        return vec!["structure"];
+    }
+
+    fn samples(&self, keys: &Labels, systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
+        assert_eq!(keys.names(), ["species_first_atom", "species_second_atom"]);
+
+        let mut samples = Vec::new();
+        for [species_first, species_second] in keys.iter_fixed_size() {
+            let mut builder = LabelsBuilder::new(self.samples_names());
+            for (system_i, system) in systems.iter().enumerate() {
+                let species = system.species()?;
+                if species.contains(&species_first.i32()) && species.contains(&species_second.i32()) {
+                    builder.add(&[system_i]);
+                }
+            }
+            samples.push(builder.finish());
+        }
+
+        return Ok(samples);
+    }
+
+    fn supports_gradient(&self, parameter: &str) -> bool {
+        match parameter {
+            "positions" | "cell" => true,
+            _ => false,
+        }
+    }
+
+    fn positions_gradient_samples(&self, keys: &Labels, samples: &[Labels], systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
+        assert_eq!(keys.count(), samples.len());
+
+        let mut gradient_samples = Vec::new();
+        for ([species_first, species_second], samples) in keys.iter_fixed_size().zip(samples) {
+            let mut builder = LabelsBuilder::new(vec!["sample", "structure", "atom"]);
+            for (sample_i, [structure_i]) in samples.iter_fixed_size().enumerate() {
+                let species = systems[structure_i.usize()].species()?;
+                for (atom_i, &species_atom) in species.iter().enumerate() {
+                    if species_atom == species_first.i32() || species_atom == species_second.i32() {
+                        builder.add(&[sample_i, structure_i.usize(), atom_i]);
+                    }
+                }
+            }
+            gradient_samples.push(builder.finish());
+        }
+
+        return Ok(gradient_samples);
+    }
+
+    fn components(&self, keys: &Labels) -> Vec<Vec<Labels>> {
+        return vec![Vec::new(); keys.count()];
+    }
+
+    fn properties_names(&self) -> Vec<&str> {
+        return vec!["index"];
+    }
+
+    fn properties(&self, keys: &Labels) -> Vec<Labels> {
+        let mut properties = LabelsBuilder::new(self.properties_names());
+        for i in 0..self.max_pairs {
+            properties.add(&[i]);
+        }
+        let properties = properties.finish();
+
+        return vec![properties; keys.count()];
+    }
+
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "PermutationInvariantVector::compute"))]
+    fn compute(&mut self, systems: &mut [Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
+        assert_eq!(descriptor.keys().names(), ["species_first_atom", "species_second_atom"]);
+
+        for system in systems.iter_mut() {
+            system.compute_neighbors(self.cutoff)?;
+        }
+
+        for (key, mut block) in descriptor.iter_mut() {
+            let species_first = key[0].i32();
+            let species_second = key[1].i32();
+
+            let block_data = block.data_mut();
+            let properties = block_data.properties.iter_fixed_size()
+                .map(|[index]| index.usize())
+                .collect::<Vec<_>>();
+
+            let mut all_contributions = Vec::new();
+            let array = block_data.values.to_array_mut();
+            for (sample_i, [structure_i]) in block_data.samples.iter_fixed_size().enumerate() {
+                let structure_i = structure_i.usize();
+                let contributions = self.sorted_contributions(&*systems[structure_i], species_first, species_second)?;
+
+                for (property_i, &index) in properties.iter().enumerate() {
+                    if let Some(contribution) = contributions.get(index) {
+                        array[[sample_i, property_i]] = contribution.value;
+                    }
+                }
+
+                all_contributions.push((structure_i, contributions));
+            }
+
+            if let Some(mut gradient) = block.gradient_mut("positions") {
+                let gradient = gradient.data_mut();
+                let array = gradient.values.to_array_mut();
+
+                for (sample_i, (structure_i, contributions)) in all_contributions.iter().enumerate() {
+                    for (property_i, &index) in properties.iter().enumerate() {
+                        let contribution = match contributions.get(index) {
+                            Some(contribution) => contribution,
+                            None => continue,
+                        };
+
+                        // gradient samples might be missing if the user
+                        // restricted the gradients to some of the atoms
+                        let first_grad_sample_i = gradient.samples.position(&[
+                            sample_i.into(), (*structure_i).into(), contribution.first.into()
+                        ]);
+                        if let Some(first_grad_sample_i) = first_grad_sample_i {
+                            for xyz in 0..3 {
+                                array[[first_grad_sample_i, xyz, property_i]] -= contribution.gradient[xyz];
+                            }
+                        }
+
+                        let second_grad_sample_i = gradient.samples.position(&[
+                            sample_i.into(), (*structure_i).into(), contribution.second.into()
+                        ]);
+                        if let Some(second_grad_sample_i) = second_grad_sample_i {
+                            for xyz in 0..3 {
+                                array[[second_grad_sample_i, xyz, property_i]] += contribution.gradient[xyz];
+                            }
+                        }
+                    }
+                }
+            }
+
+            if let Some(mut gradient) = block.gradient_mut("cell") {
+                let gradient = gradient.data_mut();
+                let array = gradient.values.to_array_mut();
+
+                for (sample_i, (structure_i, contributions)) in all_contributions.iter().enumerate() {
+                    let cell = systems[*structure_i].cell()?;
+                    if cell.shape() == CellShape::Infinite {
+                        return Err(Error::InvalidParameter(
+                            "can not compute cell gradients for non periodic systems".into()
+                        ));
+                    }
+                    let inverse_cell = cell.matrix().inverse();
+
+                    let grad_sample_i = gradient.samples.position(&[sample_i.into()])
+                        .expect("missing gradient sample");
+
+                    for (property_i, &index) in properties.iter().enumerate() {
+                        let contribution = match contributions.get(index) {
+                            Some(contribution) => contribution,
+                            None => continue,
+                        };
+
+                        let vector = contribution.vector;
+                        let inverse_cell_pair_vector = Vector3D::new(
+                            vector[0] * inverse_cell[0][0] + vector[1] * inverse_cell[1][0] + vector[2] * inverse_cell[2][0],
+                            vector[0] * inverse_cell[0][1] + vector[1] * inverse_cell[1][1] + vector[2] * inverse_cell[2][1],
+                            vector[0] * inverse_cell[0][2] + vector[1] * inverse_cell[1][2] + vector[2] * inverse_cell[2][2],
+                        );
+
+                        for spatial_1 in 0..3 {
+                            for spatial_2 in 0..3 {
+                                array[[grad_sample_i, spatial_1, spatial_2, property_i]] +=
+                                    inverse_cell_pair_vector[spatial_2] * contribution.gradient[spatial_1];
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use equistore::Labels;
+
+    use crate::systems::test_utils::{test_system, test_systems};
+    use crate::Calculator;
+
+    use super::super::CalculatorBase;
+    use super::PermutationInvariantVector;
+
+    fn piv() -> PermutationInvariantVector {
+        PermutationInvariantVector {
+            cutoff: 3.0,
+            r_0: 1.2,
+            n: 6,
+            m: 12,
+            max_pairs: 4,
+        }
+    }
+
+    #[test]
+    fn name_and_parameters() {
+        let calculator = Calculator::from(Box::new(piv()) as Box<dyn CalculatorBase>);
+
+        assert_eq!(calculator.name(), "permutation invariant vector");
+        assert_eq!(calculator.parameters(), "{\"cutoff\":3.0,\"r_0\":1.2,\"n\":6,\"m\":12,\"max_pairs\":4}");
+    }
+
+    #[test]
+    fn switching_function() {
+        let piv = piv();
+        assert_eq!(piv.switching(3.0), (0.0, 0.0));
+        assert_eq!(piv.switching(3.5), (0.0, 0.0));
+
+        let (value, _) = piv.switching(1e-3);
+        assert_relative_eq!(value, 1.0, max_relative=1e-12);
+
+        // check derivatives, including close to r_0 where we use an expansion
+        for &r in &[0.5, 1.0, 1.2, 1.2 + 1e-7, 1.7, 2.9] {
+            let delta = 1e-6;
+            let (_, derivative) = piv.switching(r);
+            let finite_difference = (piv.switching(r + delta / 2.0).0 - piv.switching(r - delta / 2.0).0) / delta;
+            assert_relative_eq!(derivative, finite_difference, max_relative=1e-5);
+        }
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        let mut calculator = Calculator::from(Box::new(PermutationInvariantVector {
+            n: 6,
+            m: 6,
+            ..piv()
+        }) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water"]);
+        let error = calculator.compute(&mut systems, Default::default()).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: expected 0 < n < m for PIV switching function, got n=6 and m=6");
+    }
+
+    #[test]
+    fn values() {
+        let mut calculator = Calculator::from(Box::new(piv()) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water"]);
+        let descriptor = calculator.compute(&mut systems, Default::default()).unwrap();
+
+        assert_eq!(*descriptor.keys(), Labels::new(
+            ["species_first_atom", "species_second_atom"],
+            &[[-42, -42], [-42, 1], [1, 1]],
+        ));
+
+        let piv = piv();
+        let oh_distance = f64::sqrt(0.75545 * 0.75545 + 0.58895 * 0.58895);
+        let (oh_value, _) = piv.switching(oh_distance);
+        let (hh_value, _) = piv.switching(2.0 * 0.75545);
+
+        // no O-O pairs
+        let values = descriptor.block_by_id(0).values().to_array();
+        assert_eq!(values, ndarray::arr2(&[[0.0, 0.0, 0.0, 0.0]]).into_dyn());
+
+        let values = descriptor.block_by_id(1).values().to_array();
+        assert_relative_eq!(values, ndarray::arr2(&[[oh_value, oh_value, 0.0, 0.0]]).into_dyn(), max_relative=1e-12);
+
+        let values = descriptor.block_by_id(2).values().to_array();
+        assert_relative_eq!(values, ndarray::arr2(&[[hh_value, 0.0, 0.0, 0.0]]).into_dyn(), max_relative=1e-12);
+    }
+
+    #[test]
+    fn finite_differences_positions() {
+        let calculator = Calculator::from(Box::new(piv()) as Box<dyn CalculatorBase>);
+
+        let system = test_system("methane");
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
+            displacement: 1e-6,
+            max_relative: 1e-5,
+            epsilon: 1e-16,
+        };
+        crate::calculators::tests_utils::finite_differences_positions(calculator, &system, options);
+    }
+
+    #[test]
+    fn finite_differences_cell() {
+        let calculator = Calculator::from(Box::new(piv()) as Box<dyn CalculatorBase>);
+
+        let system = test_system("methane");
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
+            displacement: 1e-6,
+            max_relative: 1e-5,
+            epsilon: 1e-16,
+        };
+        crate::calculators::tests_utils::finite_differences_cell(calculator, &system, options);
+    }
+
+    #[test]
+    fn compute_partial() {
+        let calculator = Calculator::from(Box::new(piv()) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water", "methane"]);
+
+        let keys = Labels::new(["species_first_atom", "species_second_atom"], &[
+            [1, 1], [1, 6], [6, 6], [-42, 1], [-42, 6], [-42, -42], [1, 8],
+        ]);
+        let samples = Labels::new(["structure"], &[[1]]);
+        let properties = Labels::new(["index"], &[[0], [3]]);
+
+        crate::calculators::tests_utils::compute_partial(
+            calculator, &mut systems, &keys, &samples, &properties
+        );
+    }
+}
@@ -1,16 +1,45 @@
 use ndarray::{Array1, Array2};
 
 use crate::math::gamma;
+use crate::Error;
 
-#[derive(Debug, Clone, Copy)]
+/// Method used to orthonormalize the GTO radial basis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+pub enum GtoOrthonormalization {
+    /// Symmetric (Löwdin) orthonormalization, using `S^{-1/2}` with `S` the
+    /// overlap matrix. The resulting basis functions are the closest possible
+    /// to the original GTO.
+    Lowdin {},
+    /// Cholesky (Gram-Schmidt) orthonormalization, using `L^{-1}` with `S = L
+    /// L^T`. The `n`-th basis function only depends on the GTO up to `n`, and
+    /// the decomposition is more stable than the eigendecomposition for badly
+    /// conditioned overlap matrices.
+    Cholesky {},
+}
+
+impl Default for GtoOrthonormalization {
+    fn default() -> GtoOrthonormalization {
+        GtoOrthonormalization::Lowdin {}
+    }
+}
+
+#[derive(Debug, Clone)]
 #[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
 /// Use a radial basis similar to Gaussian-Type Orbitals.
 ///
 /// The basis is defined as `R_n(r) ∝ r^n e^{- r^2 / (2 σ_n^2)}`, where `σ_n
-/// = cutoff * \sqrt{n} / n_max`
+/// = cutoff * \sqrt{n} / n_max`, unless the widths are given explicitly in
+/// `sigmas`.
 pub struct GtoRadialBasis {
     pub max_radial: usize,
     pub cutoff: f64,
+    /// User-defined Gaussian width `σ_n` for each radial basis function
+    #[serde(default)]
+    pub sigmas: Option<Vec<f64>>,
+    /// Method used to orthonormalize the basis functions
+    #[serde(default)]
+    pub orthonormalization: GtoOrthonormalization,
 }
 
 impl GtoRadialBasis {
@@ -42,8 +71,59 @@ impl GtoRadialBasis {
         return overlap;
     }
 
+    /// Get the condition number of the overlap matrix, i.e. the ratio of its
+    /// largest and smallest eigenvalues. Large values (above ~1e12) indicate
+    /// that the basis functions are close to linearly dependent, and that the
+    /// orthonormalized basis will suffer from a large loss of numerical
+    /// precision. Decreasing `max_radial` or increasing the cutoff improves
+    /// the conditioning of the basis.
+    pub fn overlap_condition_number(&self) -> f64 {
+        let eigen = crate::math::SymmetricEigen::new(self.overlap());
+
+        let mut min = f64::INFINITY;
+        let mut max = 0.0;
+        for &eigenvalue in &eigen.eigenvalues {
+            min = f64::min(min, eigenvalue);
+            max = f64::max(max, eigenvalue);
+        }
+
+        if min <= 0.0 {
+            return f64::INFINITY;
+        }
+
+        return max / min;
+    }
+
+    /// Check that user-defined Gaussian widths (if any) are compatible with
+    /// this basis
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some(ref sigmas) = self.sigmas {
+            if sigmas.len() != self.max_radial {
+                return Err(Error::InvalidParameter(format!(
+                    "expected {} GTO gaussian widths (one for each radial \
+                    basis function), got {}", self.max_radial, sigmas.len()
+                )));
+            }
+
+            for &sigma in sigmas {
+                if sigma <= 0.0 || !sigma.is_finite() {
+                    return Err(Error::InvalidParameter(
+                        "GTO gaussian widths must be strictly positive numbers".into()
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Get the vector of GTO Gaussian width, i.e. `cutoff * max(√n, 1) / n_max`
+    /// or the user-defined `sigmas`
     pub fn gaussian_widths(&self) -> Vec<f64> {
+        if let Some(ref sigmas) = self.sigmas {
+            return sigmas.clone();
+        }
+
         return (0..self.max_radial).map(|n| {
             let n = n as f64;
             let n_max = self.max_radial as f64;
@@ -59,28 +139,79 @@ impl GtoRadialBasis {
             .collect::<Array1<_>>();
 
         let overlap = self.overlap();
-        // compute overlap^-1/2 through its eigendecomposition
-        let mut eigen = crate::math::SymmetricEigen::new(overlap);
-        for n in 0..self.max_radial {
-            if eigen.eigenvalues[n] <= f64::EPSILON {
-                panic!(
-                    "radial overlap matrix is singular, try with a lower \
-                    max_radial (current value is {})", self.max_radial
-                );
+        let orthonormalization = match self.orthonormalization {
+            GtoOrthonormalization::Lowdin {} => {
+                // compute overlap^-1/2 through its eigendecomposition
+                let mut eigen = crate::math::SymmetricEigen::new(overlap);
+                for n in 0..self.max_radial {
+                    if eigen.eigenvalues[n] <= f64::EPSILON {
+                        panic!(
+                            "radial overlap matrix is singular, try with a lower \
+                            max_radial (current value is {})", self.max_radial
+                        );
+                    }
+                    eigen.eigenvalues[n] = 1.0 / f64::sqrt(eigen.eigenvalues[n]);
+                }
+                eigen.recompose()
             }
-            eigen.eigenvalues[n] = 1.0 / f64::sqrt(eigen.eigenvalues[n]);
+            GtoOrthonormalization::Cholesky {} => {
+                inverse_cholesky(overlap, self.max_radial)
+            }
+        };
+
+        return orthonormalization.dot(&Array2::from_diag(&normalization));
+    }
+}
+
+/// Compute `L^{-1}` where `L` is the lower triangular Cholesky factor of the
+/// symmetric positive definite `matrix`, i.e. `matrix = L L^T`.
+fn inverse_cholesky(matrix: Array2<f64>, max_radial: usize) -> Array2<f64> {
+    let size = matrix.nrows();
+
+    let mut lower = Array2::from_elem((size, size), 0.0);
+    for j in 0..size {
+        let mut diagonal = matrix[(j, j)];
+        for k in 0..j {
+            diagonal -= lower[(j, k)] * lower[(j, k)];
         }
 
-        let orthonormalization = eigen.recompose().dot(&Array2::from_diag(&normalization));
+        if diagonal <= f64::EPSILON {
+            panic!(
+                "radial overlap matrix is singular, try with a lower \
+                max_radial (current value is {})", max_radial
+            );
+        }
+        lower[(j, j)] = f64::sqrt(diagonal);
 
-        return orthonormalization;
+        for i in (j + 1)..size {
+            let mut value = matrix[(i, j)];
+            for k in 0..j {
+                value -= lower[(i, k)] * lower[(j, k)];
+            }
+            lower[(i, j)] = value / lower[(j, j)];
+        }
     }
+
+    // invert the lower triangular matrix with forward substitution
+    let mut inverse = Array2::from_elem((size, size), 0.0);
+    for j in 0..size {
+        inverse[(j, j)] = 1.0 / lower[(j, j)];
+        for i in (j + 1)..size {
+            let mut value = 0.0;
+            for k in j..i {
+                value -= lower[(i, k)] * inverse[(k, j)];
+            }
+            inverse[(i, j)] = value / lower[(i, i)];
+        }
+    }
+
+    return inverse;
 }
 
 
 #[cfg(test)]
 mod tests {
-    use approx::assert_ulps_eq;
+    use approx::{assert_ulps_eq, assert_relative_eq};
     use super::*;
 
     #[test]
@@ -89,6 +220,8 @@ mod tests {
         let basis = GtoRadialBasis {
             max_radial: 8,
             cutoff: 6.3,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         };
 
         let overlap = basis.overlap();
@@ -103,4 +236,108 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn user_defined_sigmas() {
+        let basis = GtoRadialBasis {
+            max_radial: 4,
+            cutoff: 5.0,
+            sigmas: Some(vec![0.5, 0.8, 1.3, 2.1]),
+            orthonormalization: GtoOrthonormalization::Lowdin {},
+        };
+        basis.validate().unwrap();
+        assert_eq!(basis.gaussian_widths(), [0.5, 0.8, 1.3, 2.1]);
+
+        // the orthonormalized basis functions should have an identity overlap
+        let inverse_normalization = basis.gaussian_widths().iter()
+            .zip(0..basis.max_radial)
+            .map(|(sigma, n)| f64::sqrt(sigma.powi(2 * n as i32 + 3) * gamma(n as f64 + 1.5) / 2.0))
+            .collect::<Array1<_>>();
+        let inverse_normalization = Array2::from_diag(&inverse_normalization);
+        let raw_overlap = inverse_normalization.dot(&basis.overlap()).dot(&inverse_normalization);
+
+        let orthonormalization = basis.orthonormalization_matrix();
+        let overlap = orthonormalization.dot(&raw_overlap).dot(&orthonormalization.t());
+        for i in 0..basis.max_radial {
+            for j in 0..basis.max_radial {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert_relative_eq!(overlap[(i, j)], expected, epsilon=1e-10);
+            }
+        }
+
+        let basis = GtoRadialBasis {
+            max_radial: 4,
+            cutoff: 5.0,
+            sigmas: Some(vec![0.5, 0.8, 1.3]),
+            orthonormalization: GtoOrthonormalization::Lowdin {},
+        };
+        assert_eq!(
+            basis.validate().unwrap_err().to_string(),
+            "invalid parameter: expected 4 GTO gaussian widths (one for each radial basis function), got 3"
+        );
+
+        let basis = GtoRadialBasis {
+            max_radial: 2,
+            cutoff: 5.0,
+            sigmas: Some(vec![0.5, -0.8]),
+            orthonormalization: GtoOrthonormalization::Lowdin {},
+        };
+        assert!(basis.validate().is_err());
+    }
+
+    #[test]
+    fn cholesky_orthonormalization() {
+        let basis = GtoRadialBasis {
+            max_radial: 6,
+            cutoff: 5.0,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Cholesky {},
+        };
+
+        let inverse_normalization = basis.gaussian_widths().iter()
+            .zip(0..basis.max_radial)
+            .map(|(sigma, n)| f64::sqrt(sigma.powi(2 * n as i32 + 3) * gamma(n as f64 + 1.5) / 2.0))
+            .collect::<Array1<_>>();
+        let inverse_normalization = Array2::from_diag(&inverse_normalization);
+        let raw_overlap = inverse_normalization.dot(&basis.overlap()).dot(&inverse_normalization);
+
+        let orthonormalization = basis.orthonormalization_matrix();
+        let overlap = orthonormalization.dot(&raw_overlap).dot(&orthonormalization.t());
+        for i in 0..basis.max_radial {
+            for j in 0..basis.max_radial {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert_relative_eq!(overlap[(i, j)], expected, epsilon=1e-10);
+
+                // the n-th basis function only uses the first n GTO
+                if j > i {
+                    assert_eq!(orthonormalization[(i, j)], 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn overlap_condition_number() {
+        let mut previous = 1.0;
+        for max_radial in [2, 4, 6, 8] {
+            let basis = GtoRadialBasis {
+                max_radial: max_radial,
+                cutoff: 5.0,
+                sigmas: None,
+                orthonormalization: GtoOrthonormalization::Lowdin {},
+            };
+
+            let condition_number = basis.overlap_condition_number();
+            assert!(condition_number > previous);
+            previous = condition_number;
+        }
+
+        let basis = GtoRadialBasis {
+            max_radial: 1,
+            cutoff: 5.0,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
+        };
+        assert_ulps_eq!(basis.overlap_condition_number(), 1.0);
+    }
 }
@@ -0,0 +1,92 @@
+use ndarray::Array2;
+
+use crate::math::gamma;
+
+#[derive(Debug, Clone, Copy)]
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+/// Use a radial basis built from generalized Laguerre polynomials.
+///
+/// The basis is defined as `R_n(r) ∝ L_n^{(1/2)}(r^2 / σ^2) e^{- r^2 / (2
+/// σ^2)}`, where `L_n^{(1/2)}` is the generalized Laguerre polynomial of degree
+/// `n`, and `σ = cutoff / \sqrt{4 n_max - 1}`, such that the classical
+/// turning point of the last basis function is at the cutoff. These functions
+/// are the radial part of the `l=0` eigenstates of the 3D harmonic oscillator,
+/// and are orthonormal without requiring any additional orthonormalization.
+pub struct LaguerreRadialBasis {
+    pub max_radial: usize,
+    pub cutoff: f64,
+}
+
+impl LaguerreRadialBasis {
+    /// Get the Gaussian width `σ` shared by all the basis functions
+    pub fn gaussian_width(&self) -> f64 {
+        let n_max = self.max_radial as f64;
+        return self.cutoff / f64::sqrt(4.0 * n_max - 1.0);
+    }
+
+    /// Get the `n_max * n_max` matrix `C` of coefficients expressing the
+    /// normalized basis functions in terms of the primitive functions `r^{2k}
+    /// e^{- r^2 / (2 σ^2)}`, i.e. `R_n(r) = \sum_k C_{nk} r^{2k} e^{- r^2 / (2
+    /// σ^2)}`.
+    pub fn coefficients(&self) -> Array2<f64> {
+        let sigma = self.gaussian_width();
+        let mut coefficients = Array2::from_elem((self.max_radial, self.max_radial), 0.0);
+        for n in 0..self.max_radial {
+            // normalization such that \int r^2 R_n(r)^2 dr = 1
+            let normalization = f64::sqrt(
+                2.0 * gamma(n as f64 + 1.0) / (sigma.powi(3) * gamma(n as f64 + 1.5))
+            );
+
+            for k in 0..=n {
+                // coefficient of x^k in L_n^{(1/2)}(x)
+                let laguerre = f64::powi(-1.0, k as i32) * gamma(n as f64 + 1.5) / (
+                    gamma((n - k) as f64 + 1.0) * gamma(k as f64 + 1.5) * gamma(k as f64 + 1.0)
+                );
+
+                coefficients[[n, k]] = normalization * laguerre / sigma.powi(2 * k as i32);
+            }
+        }
+
+        return coefficients;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use super::*;
+
+    #[test]
+    fn orthonormal() {
+        let basis = LaguerreRadialBasis {
+            max_radial: 6,
+            cutoff: 4.5,
+        };
+
+        let sigma = basis.gaussian_width();
+        let coefficients = basis.coefficients();
+        let evaluate = |n: usize, r: f64| {
+            let mut value = 0.0;
+            for k in 0..basis.max_radial {
+                value += coefficients[[n, k]] * r.powi(2 * k as i32);
+            }
+            value * f64::exp(-0.5 * r * r / (sigma * sigma))
+        };
+
+        // simple midpoint integration of r^2 R_n(r) R_m(r)
+        let n_points = 20000;
+        let step = 4.0 * basis.cutoff / n_points as f64;
+        for n in 0..basis.max_radial {
+            for m in 0..basis.max_radial {
+                let mut overlap = 0.0;
+                for i in 0..n_points {
+                    let r = (i as f64 + 0.5) * step;
+                    overlap += r * r * evaluate(n, r) * evaluate(m, r) * step;
+                }
+
+                let expected = if n == m { 1.0 } else { 0.0 };
+                assert_relative_eq!(overlap, expected, epsilon=1e-8);
+            }
+        }
+    }
+}
@@ -1,8 +1,12 @@
 mod gto;
-pub use self::gto::GtoRadialBasis;
+pub use self::gto::{GtoRadialBasis, GtoOrthonormalization};
+
+mod laguerre;
+pub use self::laguerre::LaguerreRadialBasis;
 
 mod tabulated;
-pub use self::tabulated::SplinePoint;
+pub use self::tabulated::{SplinePoint, generate_splines};
+pub(crate) use self::tabulated::{JsonArray2, spline_points};
 
 #[derive(Debug, Clone)]
 #[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
@@ -11,7 +15,7 @@ pub enum RadialBasis {
     /// Use a radial basis similar to Gaussian-Type Orbitals.
     ///
     /// The basis is defined as `R_n(r) ∝ r^n e^{- r^2 / (2 σ_n^2)}`, where `σ_n
-    /// = cutoff * \sqrt{n} / n_max`
+    /// = cutoff * \sqrt{n} / n_max` by default.
     Gto {
         /// compute the radial integral using splines. This is much faster than
         /// the base GTO implementation.
@@ -22,6 +26,57 @@ pub enum RadialBasis {
         /// close to the requested accuracy.
         #[serde(default = "serde_default_spline_accuracy")]
         spline_accuracy: f64,
+        /// Target for the mean relative error of the spline. The spline is
+        /// accurate enough when either the absolute (`spline_accuracy`) or the
+        /// relative error target is reached. Defaults to `spline_accuracy`.
+        #[serde(default)]
+        spline_relative_accuracy: Option<f64>,
+        /// Maximal number of control points in the spline. Points are placed
+        /// where the radial integral varies the fastest, and creating the
+        /// spline fails if the accuracy targets can not be reached with this
+        /// many points.
+        #[serde(default = "serde_default_spline_max_points")]
+        spline_max_points: usize,
+        /// Explicit Gaussian width `σ_n` for each of the `max_radial` basis
+        /// functions, replacing the default `cutoff * \sqrt{n} / n_max`. The
+        /// orthonormalization of the basis is adapted accordingly.
+        #[serde(default)]
+        sigmas: Option<Vec<f64>>,
+        /// Method used to orthonormalize the GTO basis functions, defaults to
+        /// Löwdin orthonormalization. Use
+        /// `GtoRadialBasis::overlap_condition_number` to check if the basis
+        /// is well-conditioned for a given `max_radial` and cutoff.
+        #[serde(default)]
+        orthonormalization: GtoOrthonormalization,
+    },
+    /// Use a radial basis built from generalized Laguerre polynomials.
+    ///
+    /// The basis is defined as `R_n(r) ∝ L_n^{(1/2)}(r^2 / σ^2) e^{- r^2 / (2
+    /// σ^2)}`, where `σ = cutoff / \sqrt{4 n_max - 1}`. This basis is
+    /// orthonormal by construction, and the radial integral with a Gaussian
+    /// atomic density is computed analytically. Only the SOAP spherical
+    /// expansion supports this basis for now.
+    Laguerre {
+        /// compute the radial integral using splines. This is much faster than
+        /// the analytical implementation.
+        #[serde(default = "serde_default_splined_radial_integral")]
+        splined_radial_integral: bool,
+        /// Accuracy for the spline. The number of control points in the spline
+        /// is automatically determined to ensure the average absolute error is
+        /// close to the requested accuracy.
+        #[serde(default = "serde_default_spline_accuracy")]
+        spline_accuracy: f64,
+        /// Target for the mean relative error of the spline. The spline is
+        /// accurate enough when either the absolute (`spline_accuracy`) or the
+        /// relative error target is reached. Defaults to `spline_accuracy`.
+        #[serde(default)]
+        spline_relative_accuracy: Option<f64>,
+        /// Maximal number of control points in the spline. Points are placed
+        /// where the radial integral varies the fastest, and creating the
+        /// spline fails if the accuracy targets can not be reached with this
+        /// many points.
+        #[serde(default = "serde_default_spline_max_points")]
+        spline_max_points: usize,
     },
     /// Compute the radial integral with user-defined splines.
     ///
@@ -34,19 +89,57 @@ pub enum RadialBasis {
 
 fn serde_default_splined_radial_integral() -> bool { true }
 fn serde_default_spline_accuracy() -> f64 { 1e-8 }
+fn serde_default_spline_max_points() -> usize { 10_000 }
 
 impl RadialBasis {
     /// Use GTO as the radial basis, and do not spline the radial integral
     pub fn gto() -> RadialBasis {
         return RadialBasis::Gto {
-            splined_radial_integral: false, spline_accuracy: 0.0
+            splined_radial_integral: false, spline_accuracy: 0.0, sigmas: None,
+            spline_relative_accuracy: None, spline_max_points: serde_default_spline_max_points(),
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         };
     }
 
     /// Use GTO as the radial basis, and spline the radial integral
     pub fn splined_gto(accuracy: f64) -> RadialBasis {
         return RadialBasis::Gto {
-            splined_radial_integral: true, spline_accuracy: accuracy
+            splined_radial_integral: true, spline_accuracy: accuracy, sigmas: None,
+            spline_relative_accuracy: None, spline_max_points: serde_default_spline_max_points(),
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         };
     }
+
+    /// Use Laguerre polynomials as the radial basis, and do not spline the
+    /// radial integral
+    pub fn laguerre() -> RadialBasis {
+        return RadialBasis::Laguerre {
+            splined_radial_integral: false, spline_accuracy: 0.0,
+            spline_relative_accuracy: None, spline_max_points: serde_default_spline_max_points(),
+        };
+    }
+
+    /// Use Laguerre polynomials as the radial basis, and spline the radial
+    /// integral
+    pub fn splined_laguerre(accuracy: f64) -> RadialBasis {
+        return RadialBasis::Laguerre {
+            splined_radial_integral: true, spline_accuracy: accuracy,
+            spline_relative_accuracy: None, spline_max_points: serde_default_spline_max_points(),
+        };
+    }
+
+    /// Get the same radial basis, computing the radial integral directly
+    /// instead of using splines. Tabulated radial integrals are returned
+    /// unchanged.
+    pub(crate) fn without_splines(&self) -> RadialBasis {
+        let mut basis = self.clone();
+        match basis {
+            RadialBasis::Gto { ref mut splined_radial_integral, .. } |
+            RadialBasis::Laguerre { ref mut splined_radial_integral, .. } => {
+                *splined_radial_integral = false;
+            }
+            RadialBasis::TabulatedRadialIntegral { .. } => {}
+        }
+        return basis;
+    }
 }
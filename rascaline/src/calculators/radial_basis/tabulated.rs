@@ -1,9 +1,12 @@
 use std::collections::BTreeMap;
 
-use ndarray::Array2;
+use ndarray::{Array2, ArrayViewMut2};
 
 use schemars::schema::{SchemaObject, Schema, SingleOrVec, InstanceType, ObjectValidation, Metadata};
 
+use crate::math::{HermitCubicSpline, SplineParameters, SplineAccuracy};
+use crate::Error;
+
 /// A single point entering a spline used for the tabulated radial integrals.
 #[derive(Debug, Clone)]
 #[derive(serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
@@ -18,6 +21,64 @@ pub struct SplinePoint {
     pub derivatives: JsonArray2,
 }
 
+/// Generate the spline points for a tabulated radial integral (to be used with
+/// `RadialBasis::TabulatedRadialIntegral`) from an arbitrary `function`.
+///
+/// The `function` is called with a position `r` between 0 and `cutoff`, and
+/// should fill the `values` and `derivatives` arrays (with shape `(max_angular
+/// + 1) x max_radial`) with the radial integral and its derivative with respect
+/// to `r` at this position. Points are added to the spline until the requested
+/// `accuracy` is reached, in the same way as for `splined_radial_integral`.
+///
+/// For the built-in radial integrals, `SoapRadialIntegralSpline::points` can be
+/// used instead.
+pub fn generate_splines<F>(
+    max_radial: usize,
+    max_angular: usize,
+    cutoff: f64,
+    accuracy: impl Into<SplineAccuracy>,
+    function: F,
+) -> Result<Vec<SplinePoint>, Error> where
+    F: Fn(f64, ArrayViewMut2<f64>, ArrayViewMut2<f64>)
+{
+    if max_radial == 0 {
+        return Err(Error::InvalidParameter(
+            "max_radial must be at least 1 to generate splines".into()
+        ));
+    }
+
+    if cutoff <= 0.0 || !cutoff.is_finite() {
+        return Err(Error::InvalidParameter(
+            "cutoff must be a positive number to generate splines".into()
+        ));
+    }
+
+    let shape = (max_angular + 1, max_radial);
+    let parameters = SplineParameters {
+        start: 0.0,
+        stop: cutoff,
+        shape: vec![max_angular + 1, max_radial],
+    };
+
+    let spline = HermitCubicSpline::with_accuracy(accuracy, parameters, |r| {
+        let mut values = Array2::from_elem(shape, 0.0);
+        let mut derivatives = Array2::from_elem(shape, 0.0);
+        function(r, values.view_mut(), derivatives.view_mut());
+        (values, derivatives)
+    })?;
+
+    return Ok(spline_points(&spline));
+}
+
+/// Convert the control points of `spline` to `SplinePoint`
+pub(crate) fn spline_points(spline: &HermitCubicSpline<ndarray::Ix2>) -> Vec<SplinePoint> {
+    return spline.points().iter().map(|point| SplinePoint {
+        position: point.position,
+        values: JsonArray2(point.value.clone()),
+        derivatives: JsonArray2(point.derivative.clone()),
+    }).collect();
+}
+
 /// A simple wrapper around `ndarray::Array2<f64>` implementing
 /// `schemars::JsonSchema`
 #[derive(Debug, Clone)]
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use crate::Error;
 
 /// Possible values for the smoothing cutoff function
@@ -11,6 +13,12 @@ pub enum CutoffFunction {
     ShiftedCosine {
         width: f64,
     },
+    /// Fifth-order polynomial (smoothstep) switching function, with continuous
+    /// first and second derivatives at both ends of the switching region
+    /// `f(r) = 1 - (10 x^3 - 15 x^4 + 6 x^5)`, with `x = (r - cutoff + width) / width`
+    Polynomial {
+        width: f64,
+    },
 }
 
 impl CutoffFunction {
@@ -25,6 +33,14 @@ impl CutoffFunction {
                     )));
                 }
             }
+            CutoffFunction::Polynomial { width } => {
+                if *width <= 0.0 {
+                    return Err(Error::InvalidParameter(format!(
+                        "expected positive width for polynomial cutoff function, got {}",
+                        width
+                    )));
+                }
+            }
         }
         return Ok(());
     }
@@ -45,6 +61,16 @@ impl CutoffFunction {
                     0.5 * (1. + f64::cos(s))
                 }
             }
+            CutoffFunction::Polynomial { width } => {
+                if r <= (cutoff - width) {
+                    1.0
+                } else if r >= cutoff {
+                    0.0
+                } else {
+                    let x = (r - cutoff + width) / width;
+                    1.0 - x * x * x * (10.0 - 15.0 * x + 6.0 * x * x)
+                }
+            }
         }
     }
 
@@ -61,23 +87,120 @@ impl CutoffFunction {
                     return -0.5 * std::f64::consts::PI * f64::sin(s) / width;
                 }
             }
+            CutoffFunction::Polynomial { width } => {
+                if r <= (cutoff - width) || r >= cutoff {
+                    0.0
+                } else {
+                    let x = (r - cutoff + width) / width;
+                    let one_minus_x = 1.0 - x;
+                    return -30.0 * x * x * one_minus_x * one_minus_x / width;
+                }
+            }
         }
     }
 }
 
-/// Implemented options for radial scaling of the atomic density around an atom
+/// Smooth inner cutoff, removing the contribution of neighbors closer than
+/// `radius` to the central atom. The contribution of neighbors is switched on
+/// between `radius` and `radius + width` with
+/// `f(r) = 1/2 * (1 - cos(π (r - radius) / width))`.
+#[derive(Debug, Clone, Copy)]
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+pub struct InnerCutoff {
+    /// Neighbors closer than this distance do not contribute at all
+    pub radius: f64,
+    /// Width of the switching region
+    pub width: f64,
+}
+
+impl InnerCutoff {
+    pub fn validate(&self, cutoff: f64) -> Result<(), Error> {
+        if !(self.radius >= 0.0 && self.radius.is_finite()) {
+            return Err(Error::InvalidParameter(format!(
+                "expected positive radius for inner cutoff, got {}", self.radius
+            )));
+        }
+
+        if !(self.width > 0.0 && self.width.is_finite()) {
+            return Err(Error::InvalidParameter(format!(
+                "expected positive width for inner cutoff, got {}", self.width
+            )));
+        }
+
+        if self.radius + self.width > cutoff {
+            return Err(Error::InvalidParameter(format!(
+                "inner cutoff radius + width ({}) must be smaller than the cutoff ({})",
+                self.radius + self.width, cutoff
+            )));
+        }
+
+        return Ok(());
+    }
+
+    /// Evaluate the inner cutoff switching function at the distance `r`
+    pub fn compute(&self, r: f64) -> f64 {
+        if r <= self.radius {
+            0.0
+        } else if r >= self.radius + self.width {
+            1.0
+        } else {
+            let s = std::f64::consts::PI * (r - self.radius) / self.width;
+            0.5 * (1.0 - f64::cos(s))
+        }
+    }
+
+    /// Evaluate the derivative of the inner cutoff switching function at the
+    /// distance `r`
+    pub fn derivative(&self, r: f64) -> f64 {
+        if r <= self.radius || r >= self.radius + self.width {
+            0.0
+        } else {
+            let s = std::f64::consts::PI * (r - self.radius) / self.width;
+            0.5 * std::f64::consts::PI * f64::sin(s) / self.width
+        }
+    }
+}
+
+/// A single point in a tabulated radial scaling function
 #[derive(Debug, Clone, Copy)]
 #[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+pub struct RadialScalingPoint {
+    /// Distance at which the function is evaluated
+    pub position: f64,
+    /// Value of the function at this distance
+    pub value: f64,
+    /// Derivative of the function with respect to the distance
+    pub derivative: f64,
+}
+
+/// Implemented options for radial scaling of the atomic density around an atom
+#[derive(Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
 pub enum RadialScaling {
     /// No radial scaling
     None {},
     /// Use a long-range algebraic decay and smooth behavior at $r \rightarrow 0$
     /// as introduced in <https://doi.org/10.1039/C8CP05921G>:
     /// `f(r) = rate / (rate + (r / scale) ^ exponent)`
+    ///
+    /// The exponent can be any positive real number, and the function crosses
+    /// `f(r) = 1/2` at `r = scale * rate ^ (1 / exponent)`.
     Willatt2018 {
         scale: f64,
         rate: f64,
-        exponent: i32,
+        exponent: f64,
+        /// Species-dependent values of `scale`, applied depending on the
+        /// species of the neighbor atom. Species missing from this map use
+        /// the default `scale`.
+        #[serde(default)]
+        scale_by_species: Option<BTreeMap<i32, f64>>,
+    },
+    /// User-defined radial scaling, given as values and derivatives at a set of
+    /// distances. The function is evaluated with cubic Hermite interpolation
+    /// between these points, and the points must cover all distances from 0
+    /// to the cutoff.
+    Tabulated {
+        points: Vec<RadialScalingPoint>,
     },
 }
 
@@ -91,7 +214,7 @@ impl RadialScaling {
     pub fn validate(&self) -> Result<(), Error> {
         match self {
             RadialScaling::None {} => {},
-            RadialScaling::Willatt2018 { scale, rate, exponent } => {
+            RadialScaling::Willatt2018 { scale, rate, exponent, scale_by_species } => {
                 if *scale <= 0.0 {
                     return Err(Error::InvalidParameter(format!(
                         "expected positive scale for Willatt2018 radial scaling function, got {}",
@@ -99,6 +222,18 @@ impl RadialScaling {
                     )));
                 }
 
+                if let Some(scale_by_species) = scale_by_species {
+                    for (species, scale) in scale_by_species {
+                        if !(*scale > 0.0 && scale.is_finite()) {
+                            return Err(Error::InvalidParameter(format!(
+                                "expected positive scale for species {} in Willatt2018 \
+                                radial scaling function, got {}",
+                                species, scale
+                            )));
+                        }
+                    }
+                }
+
                 if *rate <= 0.0 {
                     return Err(Error::InvalidParameter(format!(
                         "expected positive rate for Willatt2018 radial scaling function, got {}",
@@ -106,43 +241,160 @@ impl RadialScaling {
                     )));
                 }
 
-                if *exponent <= 0 {
+                if !(*exponent > 0.0 && exponent.is_finite()) {
                     return Err(Error::InvalidParameter(format!(
                         "expected positive exponent for Willatt2018 radial scaling function, got {}",
                         exponent
                     )));
                 }
             }
+            RadialScaling::Tabulated { points } => {
+                if points.len() < 2 {
+                    return Err(Error::InvalidParameter(format!(
+                        "expected at least two points for tabulated radial scaling function, got {}",
+                        points.len()
+                    )));
+                }
+
+                for point in points {
+                    if !(point.position.is_finite() && point.value.is_finite() && point.derivative.is_finite()) {
+                        return Err(Error::InvalidParameter(
+                            "tabulated radial scaling function contains non-finite values".into()
+                        ));
+                    }
+                }
+
+                for window in points.windows(2) {
+                    if window[1].position <= window[0].position {
+                        return Err(Error::InvalidParameter(format!(
+                            "positions in tabulated radial scaling function must be \
+                            strictly increasing, got {} after {}",
+                            window[1].position, window[0].position
+                        )));
+                    }
+                }
+            }
         }
         return Ok(());
     }
 
-    /// Evaluate the radial scaling function at the distance `r`
-    pub fn compute(&self, r: f64) -> f64 {
+    /// Check that this radial scaling function can be evaluated for all
+    /// distances up to `cutoff`
+    pub fn validate_range(&self, cutoff: f64) -> Result<(), Error> {
+        if let RadialScaling::Tabulated { points } = self {
+            let first = points[0].position;
+            let last = points[points.len() - 1].position;
+            if first > 0.0 || last < cutoff {
+                return Err(Error::InvalidParameter(format!(
+                    "tabulated radial scaling function must cover all distances \
+                    from 0 to the cutoff ({}), got points from {} to {}",
+                    cutoff, first, last
+                )));
+            }
+        }
+        return Ok(());
+    }
+
+    /// Check if this radial scaling function takes the same values for
+    /// neighbors with species `first` and `second`
+    pub fn same_for_species(&self, first: i32, second: i32) -> bool {
+        match self {
+            RadialScaling::Willatt2018 { scale, scale_by_species, .. } => {
+                let first = species_scale(*scale, scale_by_species, first);
+                let second = species_scale(*scale, scale_by_species, second);
+                first.to_bits() == second.to_bits()
+            }
+            _ => true,
+        }
+    }
+
+    /// Evaluate the radial scaling function at the distance `r` for a neighbor
+    /// with the given `species`
+    pub fn compute(&self, r: f64, species: i32) -> f64 {
         match self {
             RadialScaling::None {} => 1.0,
-            RadialScaling::Willatt2018 { rate, scale, exponent } => {
-                rate / (rate + (r / scale).powi(*exponent))
+            RadialScaling::Willatt2018 { rate, scale, exponent, scale_by_species } => {
+                let scale = species_scale(*scale, scale_by_species, species);
+                rate / (rate + (r / scale).powf(*exponent))
             }
+            RadialScaling::Tabulated { points } => interpolate_tabulated(points, r).0,
         }
     }
 
-    /// Evaluate the derivative of the radial scaling function at the distance `r`
-    pub fn derivative(&self, r: f64) -> f64 {
+    /// Evaluate the derivative of the radial scaling function at the distance
+    /// `r` for a neighbor with the given `species`
+    pub fn derivative(&self, r: f64, species: i32) -> f64 {
         match self {
             RadialScaling::None {} => 0.0,
-            RadialScaling::Willatt2018 { scale, rate, exponent } => {
+            RadialScaling::Willatt2018 { scale, rate, exponent, scale_by_species } => {
+                let scale = species_scale(*scale, scale_by_species, species);
                 let rs = r / scale;
-                let rs_m1 = rs.powi(exponent - 1);
-                let rs_m = rs * rs_m1;
-                let factor = - rate * (*exponent as f64) / scale;
+                let rs_m1 = rs.powf(exponent - 1.0);
+                let rs_m = rs.powf(*exponent);
+                let factor = - rate * exponent / scale;
 
                 factor * rs_m1 / ((rate + rs_m) * (rate + rs_m))
             }
+            RadialScaling::Tabulated { points } => interpolate_tabulated(points, r).1,
         }
     }
 }
 
+/// Get the scale to use in `Willatt2018` radial scaling for the given species
+fn species_scale(scale: f64, scale_by_species: &Option<BTreeMap<i32, f64>>, species: i32) -> f64 {
+    scale_by_species.as_ref()
+        .and_then(|scale_by_species| scale_by_species.get(&species).copied())
+        .unwrap_or(scale)
+}
+
+/// Evaluate the value and derivative of a tabulated function at `r` with cubic
+/// Hermite interpolation. Outside of the tabulated range, the function is
+/// taken to be constant.
+fn interpolate_tabulated(points: &[RadialScalingPoint], r: f64) -> (f64, f64) {
+    let first = &points[0];
+    let last = &points[points.len() - 1];
+    if r <= first.position {
+        return (first.value, 0.0);
+    }
+
+    if r >= last.position {
+        return (last.value, 0.0);
+    }
+
+    // index of the first point after r
+    let k = points.partition_point(|point| point.position <= r);
+    let point_k = &points[k - 1];
+    let point_k_1 = &points[k];
+
+    let delta = point_k_1.position - point_k.position;
+    let t = (r - point_k.position) / delta;
+    let t_2 = t * t;
+    let t_3 = t_2 * t;
+
+    // Hermit base polynomials
+    let h00 = 2.0 * t_3 - 3.0 * t_2 + 1.0;
+    let h10 = t_3 - 2.0 * t_2 + t;
+    let h01 = -2.0 * t_3 + 3.0 * t_2;
+    let h11 = t_3 - t_2;
+
+    let value = h00 * point_k.value
+        + h10 * delta * point_k.derivative
+        + h01 * point_k_1.value
+        + h11 * delta * point_k_1.derivative;
+
+    // derivatives of the base polynomials with respect to t
+    let d_h00 = 6.0 * t_2 - 6.0 * t;
+    let d_h10 = 3.0 * t_2 - 4.0 * t + 1.0;
+    let d_h01 = -6.0 * t_2 + 6.0 * t;
+    let d_h11 = 3.0 * t_2 - 2.0 * t;
+
+    let derivative = (d_h00 * point_k.value + d_h01 * point_k_1.value) / delta
+        + d_h10 * point_k.derivative
+        + d_h11 * point_k_1.derivative;
+
+    return (value, derivative);
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -188,4 +440,143 @@ mod tests {
         assert_eq!(function.derivative(4.0, cutoff), 0.0);
         assert_eq!(function.derivative(5.0, cutoff), 0.0);
     }
+
+    #[test]
+    fn polynomial() {
+        let function = CutoffFunction::Polynomial { width: 0.5 };
+        let cutoff = 4.0;
+
+        assert_eq!(function.compute(2.0, cutoff), 1.0);
+        assert_eq!(function.compute(3.5, cutoff), 1.0);
+        assert_eq!(function.compute(3.75, cutoff), 0.5);
+        assert_eq!(function.compute(4.0, cutoff), 0.0);
+        assert_eq!(function.compute(5.0, cutoff), 0.0);
+    }
+
+    #[test]
+    fn polynomial_gradient() {
+        let function = CutoffFunction::Polynomial { width: 0.5 };
+        let cutoff = 4.0;
+
+        assert_eq!(function.derivative(2.0, cutoff), 0.0);
+        assert_eq!(function.derivative(3.5, cutoff), 0.0);
+        assert_eq!(function.derivative(3.75, cutoff), -3.75);
+        assert_eq!(function.derivative(4.0, cutoff), 0.0);
+        assert_eq!(function.derivative(5.0, cutoff), 0.0);
+
+        let delta = 1e-6;
+        for r in [3.6, 3.8, 3.95] {
+            let finite_difference = (
+                function.compute(r + delta, cutoff) - function.compute(r - delta, cutoff)
+            ) / (2.0 * delta);
+            approx::assert_relative_eq!(function.derivative(r, cutoff), finite_difference, max_relative=1e-6);
+        }
+    }
+
+    #[test]
+    fn inner_cutoff() {
+        let function = InnerCutoff { radius: 1.0, width: 0.5 };
+        function.validate(4.0).unwrap();
+
+        assert_eq!(function.compute(0.5), 0.0);
+        assert_eq!(function.compute(1.0), 0.0);
+        approx::assert_ulps_eq!(function.compute(1.25), 0.5);
+        assert_eq!(function.compute(1.5), 1.0);
+        assert_eq!(function.compute(3.0), 1.0);
+
+        assert_eq!(function.derivative(0.5), 0.0);
+        assert_eq!(function.derivative(1.25), std::f64::consts::PI);
+        assert_eq!(function.derivative(3.0), 0.0);
+
+        let error = function.validate(1.2).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: inner cutoff radius + width (1.5) must be smaller than the cutoff (1.2)"
+        );
+    }
+
+    #[test]
+    fn tabulated_radial_scaling() {
+        // tabulate f(r) = 1 / (1 + r^2)
+        let points = (0..=40).map(|i| {
+            let r = i as f64 / 10.0;
+            RadialScalingPoint {
+                position: r,
+                value: 1.0 / (1.0 + r * r),
+                derivative: -2.0 * r / ((1.0 + r * r) * (1.0 + r * r)),
+            }
+        }).collect::<Vec<_>>();
+        let scaling = RadialScaling::Tabulated { points };
+        scaling.validate().unwrap();
+        scaling.validate_range(4.0).unwrap();
+
+        for r in [0.0, 0.25, 1.33, 2.0, 3.99] {
+            approx::assert_relative_eq!(scaling.compute(r, 1), 1.0 / (1.0 + r * r), max_relative=1e-5);
+            approx::assert_relative_eq!(
+                scaling.derivative(r, 1), -2.0 * r / ((1.0 + r * r) * (1.0 + r * r)),
+                epsilon=1e-4, max_relative=1e-3
+            );
+        }
+
+        let error = scaling.validate_range(5.0).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: tabulated radial scaling function must cover \
+            all distances from 0 to the cutoff (5), got points from 0 to 4"
+        );
+
+        let scaling = RadialScaling::Tabulated { points: vec![
+            RadialScalingPoint { position: 0.0, value: 1.0, derivative: 0.0 },
+            RadialScalingPoint { position: 0.0, value: 1.0, derivative: 0.0 },
+        ]};
+        let error = scaling.validate().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: positions in tabulated radial scaling function \
+            must be strictly increasing, got 0 after 0"
+        );
+    }
+
+    #[test]
+    fn willatt2018_radial_scaling() {
+        let scaling = RadialScaling::Willatt2018 {
+            scale: 1.5,
+            rate: 0.8,
+            exponent: 2.5,
+            scale_by_species: Some([(8, 2.5)].into_iter().collect()),
+        };
+        scaling.validate().unwrap();
+
+        assert_eq!(scaling.compute(0.0, 1), 1.0);
+        // crossover at scale * rate^(1 / exponent)
+        approx::assert_relative_eq!(scaling.compute(1.5 * f64::powf(0.8, 0.4), 1), 0.5, max_relative=1e-12);
+        approx::assert_relative_eq!(scaling.compute(2.5 * f64::powf(0.8, 0.4), 8), 0.5, max_relative=1e-12);
+
+        assert!(scaling.same_for_species(1, 6));
+        assert!(!scaling.same_for_species(1, 8));
+
+        let delta = 1e-6;
+        for species in [1, 8] {
+            for r in [0.3, 1.2, 4.5] {
+                let finite_difference = (
+                    scaling.compute(r + delta, species) - scaling.compute(r - delta, species)
+                ) / (2.0 * delta);
+                approx::assert_relative_eq!(
+                    scaling.derivative(r, species), finite_difference, max_relative=1e-6
+                );
+            }
+        }
+
+        let scaling = RadialScaling::Willatt2018 {
+            scale: 1.5,
+            rate: 0.8,
+            exponent: -1.0,
+            scale_by_species: None,
+        };
+        let error = scaling.validate().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: expected positive exponent for Willatt2018 radial scaling function, got -1"
+        );
+    }
 }
@@ -0,0 +1,164 @@
+use crate::Error;
+
+/// Shape of the atomic density placed on each neighbor, before projecting it
+/// on the radial basis and spherical harmonics.
+///
+/// All smooth densities are normalized such that `\int g(r)^2 dr = 1`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+pub enum AtomicDensity {
+    /// Gaussian density, `g(r) = (π σ^2)^{-3/4} e^{-r^2 / (2 σ^2)}`, where the
+    /// width `σ` is given by `atomic_gaussian_width`
+    Gaussian {},
+    /// Slater-type exponential density, `g(r) = (π a^3)^{-1/2} e^{-r / a}`,
+    /// with `a` the `decay_length`
+    Slater {
+        decay_length: f64,
+    },
+    /// Lorentzian-type density, `g(r) = \sqrt{8 a^5} / (π (r^2 + a^2)^2)`,
+    /// with `a` the `width`
+    Lorentzian {
+        width: f64,
+    },
+    /// Dirac delta density `g(r) = δ(r)`, without any smearing. The radial
+    /// integral is then the radial basis evaluated at the neighbor distance.
+    Delta {},
+}
+
+impl Default for AtomicDensity {
+    fn default() -> AtomicDensity {
+        AtomicDensity::Gaussian {}
+    }
+}
+
+impl AtomicDensity {
+    pub fn validate(&self) -> Result<(), Error> {
+        match self {
+            AtomicDensity::Gaussian {} | AtomicDensity::Delta {} => {},
+            AtomicDensity::Slater { decay_length } => {
+                if !(*decay_length > 0.0 && decay_length.is_finite()) {
+                    return Err(Error::InvalidParameter(format!(
+                        "expected positive decay_length for Slater atomic density, got {}",
+                        decay_length
+                    )));
+                }
+            }
+            AtomicDensity::Lorentzian { width } => {
+                if !(*width > 0.0 && width.is_finite()) {
+                    return Err(Error::InvalidParameter(format!(
+                        "expected positive width for Lorentzian atomic density, got {}",
+                        width
+                    )));
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    /// Get the typical length over which this density varies, using
+    /// `atomic_gaussian_width` for the gaussian density
+    pub fn length_scale(&self, atomic_gaussian_width: f64) -> f64 {
+        match self {
+            AtomicDensity::Gaussian {} => atomic_gaussian_width,
+            AtomicDensity::Slater { decay_length } => *decay_length,
+            AtomicDensity::Lorentzian { width } => *width,
+            AtomicDensity::Delta {} => 0.0,
+        }
+    }
+
+    /// Evaluate the density at the distance `r` from the atom, using
+    /// `atomic_gaussian_width` for the gaussian density
+    pub fn compute(&self, r: f64, atomic_gaussian_width: f64) -> f64 {
+        match self {
+            AtomicDensity::Gaussian {} => {
+                let sigma_2 = atomic_gaussian_width * atomic_gaussian_width;
+                let normalization = (std::f64::consts::PI * sigma_2).powf(-0.75);
+                normalization * f64::exp(-0.5 * r * r / sigma_2)
+            }
+            AtomicDensity::Slater { decay_length } => {
+                let normalization = 1.0 / f64::sqrt(std::f64::consts::PI * decay_length.powi(3));
+                normalization * f64::exp(-r / decay_length)
+            }
+            AtomicDensity::Lorentzian { width } => {
+                let normalization = f64::sqrt(8.0 * width.powi(5)) / std::f64::consts::PI;
+                let denominator = r * r + width * width;
+                normalization / (denominator * denominator)
+            }
+            AtomicDensity::Delta {} => {
+                unreachable!("delta atomic density can not be evaluated")
+            }
+        }
+    }
+
+    /// Evaluate the derivative of the density with respect to the distance
+    /// `r`, using `atomic_gaussian_width` for the gaussian density
+    pub fn derivative(&self, r: f64, atomic_gaussian_width: f64) -> f64 {
+        match self {
+            AtomicDensity::Gaussian {} => {
+                let sigma_2 = atomic_gaussian_width * atomic_gaussian_width;
+                -r / sigma_2 * self.compute(r, atomic_gaussian_width)
+            }
+            AtomicDensity::Slater { decay_length } => {
+                -self.compute(r, atomic_gaussian_width) / decay_length
+            }
+            AtomicDensity::Lorentzian { width } => {
+                let denominator = r * r + width * width;
+                -4.0 * r / denominator * self.compute(r, atomic_gaussian_width)
+            }
+            AtomicDensity::Delta {} => {
+                unreachable!("delta atomic density can not be evaluated")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use super::*;
+
+    #[test]
+    fn normalization() {
+        let densities = [
+            AtomicDensity::Gaussian {},
+            AtomicDensity::Slater { decay_length: 0.4 },
+            AtomicDensity::Lorentzian { width: 0.3 },
+        ];
+
+        for density in densities {
+            // simple midpoint integration of 4π r^2 g(r)^2
+            let n_points = 200000;
+            let step = 200.0 / n_points as f64;
+            let mut norm = 0.0;
+            for i in 0..n_points {
+                let r = (i as f64 + 0.5) * step;
+                let value = density.compute(r, 0.5);
+                norm += 4.0 * std::f64::consts::PI * r * r * value * value * step;
+            }
+
+            assert_relative_eq!(norm, 1.0, max_relative=1e-6);
+        }
+    }
+
+    #[test]
+    fn finite_differences() {
+        let densities = [
+            AtomicDensity::Gaussian {},
+            AtomicDensity::Slater { decay_length: 0.4 },
+            AtomicDensity::Lorentzian { width: 0.3 },
+        ];
+
+        let delta = 1e-6;
+        for density in densities {
+            for r in [0.1, 0.6, 1.8] {
+                let finite_difference = (
+                    density.compute(r + delta, 0.5) - density.compute(r - delta, 0.5)
+                ) / (2.0 * delta);
+
+                assert_relative_eq!(
+                    density.derivative(r, 0.5), finite_difference, max_relative=1e-6
+                );
+            }
+        }
+    }
+}
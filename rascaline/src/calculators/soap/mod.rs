@@ -1,13 +1,20 @@
 mod radial_integral;
 pub use self::radial_integral::SoapRadialIntegral;
 pub use self::radial_integral::{SoapRadialIntegralGto, SoapRadialIntegralGtoParameters};
+pub use self::radial_integral::{SoapRadialIntegralLaguerre, SoapRadialIntegralLaguerreParameters};
 pub use self::radial_integral::{SoapRadialIntegralSpline, SoapRadialIntegralSplineParameters};
+pub use self::radial_integral::{SoapRadialIntegralNumerical, SoapRadialIntegralNumericalParameters};
+pub use self::radial_integral::{SoapRadialIntegralDelta, SoapRadialIntegralDeltaParameters};
 
 pub use self::radial_integral::{SoapRadialIntegralCache, SoapRadialIntegralParameters};
 
+mod density;
+pub use self::density::AtomicDensity;
+
 mod cutoff;
 pub use self::cutoff::CutoffFunction;
-pub use self::cutoff::RadialScaling;
+pub use self::cutoff::{RadialScaling, RadialScalingPoint};
+pub use self::cutoff::InnerCutoff;
 
 mod spherical_expansion_pair;
 pub use self::spherical_expansion_pair::{SphericalExpansionByPair, SphericalExpansionParameters};
@@ -1,4 +1,4 @@
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use ndarray::parallel::prelude::*;
 
@@ -10,7 +10,7 @@ use crate::{CalculationOptions, Calculator, LabelsSelection};
 use crate::{Error, System};
 
 use super::SphericalExpansionParameters;
-use super::{SphericalExpansion, CutoffFunction, RadialScaling};
+use super::{SphericalExpansion, AtomicDensity, CutoffFunction, InnerCutoff, RadialScaling};
 use crate::calculators::radial_basis::RadialBasis;
 
 use crate::labels::{SpeciesFilter, SamplesBuilder};
@@ -40,8 +40,20 @@ pub struct PowerSpectrumParameters {
     pub max_radial: usize,
     /// Number of spherical harmonics to use
     pub max_angular: usize,
+    /// Number of radial basis function to use for each angular channel `l`,
+    /// overriding `max_radial`. If given, this must contain `max_angular + 1`
+    /// values, all of them between 1 and `max_radial`.
+    #[serde(default)]
+    pub max_radial_by_angular: Option<Vec<usize>>,
     /// Width of the atom-centered gaussian creating the atomic density
     pub atomic_gaussian_width: f64,
+    /// Width of the atom-centered gaussian to use for specific neighbor
+    /// species, overriding `atomic_gaussian_width`
+    #[serde(default)]
+    pub atomic_gaussian_width_by_species: Option<BTreeMap<i32, f64>>,
+    /// shape of the atomic density placed on each neighbor
+    #[serde(default)]
+    pub density: AtomicDensity,
     /// Weight of the central atom contribution to the
     /// features. If `1.0` the center atom contribution is weighted the same
     /// as any other contribution. If `0.0` the central atom does not
@@ -51,11 +63,29 @@ pub struct PowerSpectrumParameters {
     pub radial_basis: RadialBasis,
     /// cutoff function used to smooth the behavior around the cutoff radius
     pub cutoff_function: CutoffFunction,
+    /// smooth inner cutoff, removing the contribution of neighbors closer than
+    /// a given distance to the central atom
+    #[serde(default)]
+    pub inner_cutoff: Option<InnerCutoff>,
     /// radial scaling can be used to reduce the importance of neighbor atoms
     /// further away from the center, usually improving the performance of the
     /// model
     #[serde(default)]
     pub radial_scaling: RadialScaling,
+    /// weight multiplying the density of atoms with the given species
+    #[serde(default)]
+    pub density_weight_by_species: Option<BTreeMap<i32, f64>>,
+    /// scale the density of each atom by the per-atom factor given by the
+    /// system (see `System::density_scaling`)
+    #[serde(default)]
+    pub use_system_density_scaling: bool,
+    /// use the per-atom width of the gaussian density given by the system (see
+    /// `System::atomic_gaussian_width`)
+    #[serde(default)]
+    pub use_system_atomic_gaussian_width: bool,
+    /// species of the neighbors to remove from the density
+    #[serde(default)]
+    pub excluded_neighbor_species: Vec<i32>,
 }
 
 /// Calculator implementing the Smooth Overlap of Atomic Position (SOAP) power
@@ -77,11 +107,19 @@ impl SoapPowerSpectrum {
             cutoff: parameters.cutoff,
             max_radial: parameters.max_radial,
             max_angular: parameters.max_angular,
+            max_radial_by_angular: parameters.max_radial_by_angular.clone(),
             atomic_gaussian_width: parameters.atomic_gaussian_width,
+            atomic_gaussian_width_by_species: parameters.atomic_gaussian_width_by_species.clone(),
+            density: parameters.density,
             center_atom_weight: parameters.center_atom_weight,
             radial_basis: parameters.radial_basis.clone(),
             cutoff_function: parameters.cutoff_function,
-            radial_scaling: parameters.radial_scaling,
+            inner_cutoff: parameters.inner_cutoff,
+            radial_scaling: parameters.radial_scaling.clone(),
+            density_weight_by_species: parameters.density_weight_by_species.clone(),
+            use_system_density_scaling: parameters.use_system_density_scaling,
+            use_system_atomic_gaussian_width: parameters.use_system_atomic_gaussian_width,
+            excluded_neighbor_species: parameters.excluded_neighbor_species.clone(),
         };
 
         let spherical_expansion = SphericalExpansion::new(expansion_parameters)?;
@@ -406,11 +444,17 @@ impl CalculatorBase for SoapPowerSpectrum {
         serde_json::to_string(&self.parameters).expect("failed to serialize to JSON")
     }
 
+    fn cutoff(&self) -> Option<f64> {
+        Some(self.parameters.cutoff)
+    }
+
     fn keys(&self, systems: &mut [Box<dyn System>]) -> Result<equistore::Labels, Error> {
         let builder = CenterTwoNeighborsSpeciesKeys {
             cutoff: self.parameters.cutoff,
             self_pairs: true,
             symmetric: true,
+            species_center: SpeciesFilter::Any,
+            species_neighbor: SpeciesFilter::NoneOf(self.parameters.excluded_neighbor_species.clone()),
         };
         return builder.keys(systems);
     }
@@ -485,8 +529,13 @@ impl CalculatorBase for SoapPowerSpectrum {
     fn properties(&self, keys: &equistore::Labels) -> Vec<Labels> {
         let mut properties = LabelsBuilder::new(self.properties_names());
         for l in 0..=self.parameters.max_angular {
-            for n1 in 0..self.parameters.max_radial {
-                for n2 in 0..self.parameters.max_radial {
+            let max_radial = match self.parameters.max_radial_by_angular {
+                Some(ref max_radial_by_angular) => max_radial_by_angular[l],
+                None => self.parameters.max_radial,
+            };
+
+            for n1 in 0..max_radial {
+                for n2 in 0..max_radial {
                     properties.add(&[l, n1, n2]);
                 }
             }
@@ -496,7 +545,7 @@ impl CalculatorBase for SoapPowerSpectrum {
         return vec![properties; keys.count()];
     }
 
-    #[time_graph::instrument(name = "SoapPowerSpectrum::compute")]
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "SoapPowerSpectrum::compute"))]
     #[allow(clippy::too_many_lines)]
     fn compute(&mut self, systems: &mut [Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
         let mut gradients = Vec::new();
@@ -534,7 +583,9 @@ impl CalculatorBase for SoapPowerSpectrum {
             (key, spx_block)
         }).collect();
 
-        for (key, mut block) in descriptor.iter_mut() {
+        // different blocks are independent, and computed in parallel in
+        // addition to the parallelism over samples inside each block
+        descriptor.par_iter_mut().for_each(|(key, mut block)| {
             let species_neighbor_1 = key[1];
             let species_neighbor_2 = key[2];
 
@@ -715,8 +766,7 @@ impl CalculatorBase for SoapPowerSpectrum {
                         }
                     });
             }
-
-        }
+        });
 
         Ok(())
     }
@@ -728,7 +778,7 @@ mod tests {
     use equistore::LabelValue;
 
     use crate::systems::test_utils::{test_systems, test_system};
-    use crate::Calculator;
+    use crate::{Calculator, CalculationOptions};
 
     use super::*;
     use crate::calculators::CalculatorBase;
@@ -738,11 +788,19 @@ mod tests {
             cutoff: 3.5,
             max_radial: 6,
             max_angular: 6,
+            max_radial_by_angular: None,
             atomic_gaussian_width: 0.3,
+            atomic_gaussian_width_by_species: None,
+            density: AtomicDensity::Gaussian {},
             center_atom_weight: 1.0,
             radial_basis: RadialBasis::splined_gto(1e-8),
             radial_scaling: RadialScaling::None {},
             cutoff_function: CutoffFunction::ShiftedCosine { width: 0.5 },
+            inner_cutoff: None,
+            density_weight_by_species: None,
+            use_system_density_scaling: false,
+            use_system_atomic_gaussian_width: false,
+            excluded_neighbor_species: Vec::new(),
         }
     }
 
@@ -787,7 +845,7 @@ mod tests {
         ).unwrap()) as Box<dyn CalculatorBase>);
 
         let system = test_system("water");
-        let options = crate::calculators::tests_utils::FinalDifferenceOptions {
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
             displacement: 1e-6,
             max_relative: 5e-5,
             epsilon: 1e-16,
@@ -802,7 +860,7 @@ mod tests {
         ).unwrap()) as Box<dyn CalculatorBase>);
 
         let system = test_system("water");
-        let options = crate::calculators::tests_utils::FinalDifferenceOptions {
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
             displacement: 1e-5,
             max_relative: 1e-5,
             epsilon: 1e-16,
@@ -938,4 +996,36 @@ mod tests {
             assert_eq!(block.values().as_array(), 4.0 * block_scaled.values().as_array());
         }
     }
+
+    #[test]
+    fn parallel_blocks() {
+        let mut calculator = Calculator::from(Box::new(SoapPowerSpectrum::new(
+            parameters()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water", "methane"]);
+        let options = CalculationOptions {
+            gradients: &["positions", "cell"],
+            ..Default::default()
+        };
+        let descriptor = calculator.compute(&mut systems, options).unwrap();
+
+        // compute the same descriptor without any parallelism
+        let mut serial = Calculator::from(Box::new(SoapPowerSpectrum::new(
+            parameters()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        serial.set_max_threads(Some(1)).unwrap();
+        let expected = serial.compute(&mut systems, options).unwrap();
+
+        assert_eq!(descriptor.keys().count(), expected.keys().count());
+        for (block, expected) in descriptor.blocks().iter().zip(expected.blocks()) {
+            assert_eq!(block.values().to_array(), expected.values().to_array());
+            for parameter in ["positions", "cell"] {
+                assert_eq!(
+                    block.gradient(parameter).unwrap().values().to_array(),
+                    expected.gradient(parameter).unwrap().values().to_array(),
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,194 @@
+use ndarray::{Array2, ArrayViewMut2};
+
+use crate::calculators::radial_basis::RadialBasis;
+use crate::Error;
+
+use super::SoapRadialIntegral;
+use super::numerical::primitive_functions;
+
+/// Parameters controlling the SOAP radial integral with delta atomic density
+#[derive(Debug, Clone, Copy)]
+pub struct SoapRadialIntegralDeltaParameters {
+    /// Number of radial components
+    pub max_radial: usize,
+    /// Number of angular components
+    pub max_angular: usize,
+    /// cutoff radius
+    pub cutoff: f64,
+}
+
+impl SoapRadialIntegralDeltaParameters {
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if self.max_radial == 0 {
+            return Err(Error::InvalidParameter(
+                "max_radial must be at least 1 for delta radial integral".into()
+            ));
+        }
+
+        if self.cutoff < 0.0 || !self.cutoff.is_finite() {
+            return Err(Error::InvalidParameter(
+                "cutoff must be a positive number for delta radial integral".into()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Implementation of the radial integral for a delta atomic density, i.e. the
+/// radial basis functions evaluated at the pair distance: `I_{nl}(r_{ij}) =
+/// R_n(r_{ij})`, for all `l`.
+#[derive(Debug, Clone)]
+pub struct SoapRadialIntegralDelta {
+    parameters: SoapRadialIntegralDeltaParameters,
+    /// exponent of r in each primitive function, i.e. `m`
+    primitive_exponents: Vec<usize>,
+    /// 1/2σ^2 for each primitive function
+    primitive_gaussian_constants: Vec<f64>,
+    /// `n_max * n_max` matrix going from primitives to basis functions
+    coefficients: Array2<f64>,
+}
+
+impl SoapRadialIntegralDelta {
+    pub fn new(
+        parameters: SoapRadialIntegralDeltaParameters,
+        radial_basis: &RadialBasis,
+    ) -> Result<SoapRadialIntegralDelta, Error> {
+        parameters.validate()?;
+
+        let (primitive_exponents, primitive_gaussian_widths, coefficients) = primitive_functions(
+            radial_basis, parameters.max_radial, parameters.cutoff
+        )?;
+
+        return Ok(SoapRadialIntegralDelta {
+            parameters: parameters,
+            primitive_exponents: primitive_exponents,
+            primitive_gaussian_constants: primitive_gaussian_widths.iter()
+                .map(|&sigma| 1.0 / (2.0 * sigma * sigma))
+                .collect(),
+            coefficients: coefficients,
+        });
+    }
+}
+
+impl SoapRadialIntegral for SoapRadialIntegralDelta {
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "DeltaRadialIntegral::compute"))]
+    fn compute(
+        &self,
+        distance: f64,
+        mut values: ArrayViewMut2<f64>,
+        mut gradients: Option<ArrayViewMut2<f64>>
+    ) {
+        let expected_shape = [self.parameters.max_angular + 1, self.parameters.max_radial];
+        assert_eq!(
+            values.shape(), expected_shape,
+            "wrong size for values array, expected [{}, {}] but got [{}, {}]",
+            expected_shape[0], expected_shape[1], values.shape()[0], values.shape()[1]
+        );
+
+        if let Some(ref gradients) = gradients {
+            assert_eq!(
+                gradients.shape(), expected_shape,
+                "wrong size for gradients array, expected [{}, {}] but got [{}, {}]",
+                expected_shape[0], expected_shape[1], gradients.shape()[0], gradients.shape()[1]
+            );
+        }
+
+        for n in 0..self.parameters.max_radial {
+            let mut value = 0.0;
+            let mut gradient = 0.0;
+            for k in 0..self.parameters.max_radial {
+                let exponent = self.primitive_exponents[k];
+                let constant = self.primitive_gaussian_constants[k];
+
+                // r^m e^{-b r^2} and its derivative (m r^{m - 1} - 2 b r^{m + 1}) e^{-b r^2}
+                let exp = f64::exp(-constant * distance * distance);
+                let r_m = distance.powi(exponent as i32);
+                let r_m_1 = if exponent == 0 { 0.0 } else { distance.powi(exponent as i32 - 1) };
+
+                value += self.coefficients[[n, k]] * r_m * exp;
+                gradient += self.coefficients[[n, k]] * (exponent as f64 * r_m_1 - 2.0 * constant * distance * r_m) * exp;
+            }
+
+            // the radial integral is the same for all angular channels
+            values.index_axis_mut(ndarray::Axis(1), n).fill(value);
+            if let Some(ref mut gradients) = gradients {
+                gradients.index_axis_mut(ndarray::Axis(1), n).fill(gradient);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use ndarray::Array2;
+
+    use crate::calculators::radial_basis::{RadialBasis, LaguerreRadialBasis};
+
+    use super::super::{SoapRadialIntegral, SoapRadialIntegralDelta, SoapRadialIntegralDeltaParameters};
+
+    #[test]
+    fn laguerre_values() {
+        let max_radial = 4;
+        let max_angular = 3;
+        let delta = SoapRadialIntegralDelta::new(SoapRadialIntegralDeltaParameters {
+            max_radial: max_radial,
+            max_angular: max_angular,
+            cutoff: 4.5,
+        }, &RadialBasis::laguerre()).unwrap();
+
+        let basis = LaguerreRadialBasis {
+            max_radial: max_radial,
+            cutoff: 4.5,
+        };
+        let sigma = basis.gaussian_width();
+        let coefficients = basis.coefficients();
+
+        let rij = 2.1;
+        let shape = (max_angular + 1, max_radial);
+        let mut values = Array2::from_elem(shape, 0.0);
+        delta.compute(rij, values.view_mut(), None);
+
+        for n in 0..max_radial {
+            let mut expected = 0.0;
+            for k in 0..max_radial {
+                expected += coefficients[[n, k]] * rij.powi(2 * k as i32);
+            }
+            expected *= f64::exp(-0.5 * rij * rij / (sigma * sigma));
+
+            for l in 0..=max_angular {
+                assert_relative_eq!(values[[l, n]], expected, max_relative=1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn finite_differences() {
+        let max_radial = 6;
+        let max_angular = 3;
+
+        for radial_basis in [RadialBasis::gto(), RadialBasis::laguerre()] {
+            let delta = SoapRadialIntegralDelta::new(SoapRadialIntegralDeltaParameters {
+                max_radial: max_radial,
+                max_angular: max_angular,
+                cutoff: 4.5,
+            }, &radial_basis).unwrap();
+
+            let rij = 3.4;
+            let displacement = 1e-9;
+
+            let shape = (max_angular + 1, max_radial);
+            let mut values = Array2::from_elem(shape, 0.0);
+            let mut values_delta = Array2::from_elem(shape, 0.0);
+            let mut gradients = Array2::from_elem(shape, 0.0);
+            delta.compute(rij, values.view_mut(), Some(gradients.view_mut()));
+            delta.compute(rij + displacement, values_delta.view_mut(), None);
+
+            let finite_differences = (&values_delta - &values) / displacement;
+            assert_relative_eq!(
+                finite_differences, gradients, epsilon=1e-6, max_relative=1e-4
+            );
+        }
+    }
+}
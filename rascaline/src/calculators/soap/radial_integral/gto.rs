@@ -2,14 +2,14 @@ use std::f64;
 
 use ndarray::{Array2, ArrayViewMut2};
 
-use crate::calculators::radial_basis::GtoRadialBasis;
+use crate::calculators::radial_basis::{GtoRadialBasis, GtoOrthonormalization};
 use crate::math::{gamma, DoubleRegularized1F1};
 use crate::Error;
 
 use super::SoapRadialIntegral;
 
 /// Parameters controlling the SOAP radial integral with GTO radial basis
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct SoapRadialIntegralGtoParameters {
     /// Number of radial components
     pub max_radial: usize,
@@ -19,6 +19,10 @@ pub struct SoapRadialIntegralGtoParameters {
     pub atomic_gaussian_width: f64,
     /// cutoff radius
     pub cutoff: f64,
+    /// User-defined GTO gaussian widths, one for each radial basis function
+    pub sigmas: Option<Vec<f64>>,
+    /// Method used to orthonormalize the GTO basis
+    pub orthonormalization: GtoOrthonormalization,
 }
 
 impl SoapRadialIntegralGtoParameters {
@@ -41,8 +45,20 @@ impl SoapRadialIntegralGtoParameters {
             ));
         }
 
+        self.basis().validate()?;
+
         Ok(())
     }
+
+    /// Get the GTO radial basis corresponding to these parameters
+    fn basis(&self) -> GtoRadialBasis {
+        return GtoRadialBasis {
+            max_radial: self.max_radial,
+            cutoff: self.cutoff,
+            sigmas: self.sigmas.clone(),
+            orthonormalization: self.orthonormalization,
+        };
+    }
 }
 
 /// Implementation of the radial integral for GTO radial basis and gaussian
@@ -52,8 +68,8 @@ pub struct SoapRadialIntegralGto {
     parameters: SoapRadialIntegralGtoParameters,
     /// σ^2, with σ the atomic density gaussian width
     atomic_gaussian_width_2: f64,
-    /// 1/2σ^2, with σ the atomic density gaussian width
-    atomic_gaussian_constant: f64,
+    /// exponent of r in each GTO, i.e. `n`
+    gto_exponents: Vec<usize>,
     /// 1/2σ_n^2, with σ_n the GTO gaussian width, i.e. `cutoff * max(√n, 1) / n_max`
     gto_gaussian_constants: Vec<f64>,
     /// `n_max * n_max` matrix to orthonormalize the GTO
@@ -67,10 +83,7 @@ impl SoapRadialIntegralGto {
     pub fn new(parameters: SoapRadialIntegralGtoParameters) -> Result<SoapRadialIntegralGto, Error> {
         parameters.validate()?;
 
-        let basis = GtoRadialBasis {
-            max_radial: parameters.max_radial,
-            cutoff: parameters.cutoff,
-        };
+        let basis = parameters.basis();
         let gto_gaussian_widths = basis.gaussian_widths();
         let gto_orthonormalization = basis.orthonormalization_matrix();
 
@@ -79,15 +92,16 @@ impl SoapRadialIntegralGto {
             .collect::<Vec<_>>();
 
         let atomic_gaussian_width_2 = parameters.atomic_gaussian_width * parameters.atomic_gaussian_width;
-        let atomic_gaussian_constant = 1.0 / (2.0 * atomic_gaussian_width_2);
+        let double_regularized_1f1 = DoubleRegularized1F1 {
+            max_angular: parameters.max_angular,
+        };
+        let gto_exponents = (0..parameters.max_radial).collect();
 
         return Ok(SoapRadialIntegralGto {
             parameters: parameters,
-            double_regularized_1f1: DoubleRegularized1F1 {
-                max_angular: parameters.max_angular,
-            },
+            double_regularized_1f1: double_regularized_1f1,
             atomic_gaussian_width_2: atomic_gaussian_width_2,
-            atomic_gaussian_constant: atomic_gaussian_constant,
+            gto_exponents: gto_exponents,
             gto_gaussian_constants: gto_gaussian_constants,
             gto_orthonormalization: gto_orthonormalization.t().to_owned(),
         })
@@ -95,7 +109,7 @@ impl SoapRadialIntegralGto {
 }
 
 impl SoapRadialIntegral for SoapRadialIntegralGto {
-    #[time_graph::instrument(name = "GtoRadialIntegral::compute")]
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "GtoRadialIntegral::compute"))]
     fn compute(
         &self,
         distance: f64,
@@ -117,82 +131,115 @@ impl SoapRadialIntegral for SoapRadialIntegralGto {
             );
         }
 
-        // Define global factor of radial integral arising from three parts:
-        // - a global 4 pi factor coming from integration of the angular part of
-        //   the radial integral (see the docs for `SoapRadialIntegral`)
-        // - a global factor of sqrt(pi)/4 from the calculation of the integral
-        //   of GTO basis functions and gaussian density
-        // - the normalization constant of the atomic Gaussian density. We use a
-        //   factor of 1/(pi*sigma^2)^0.75 which leads to Gaussian densities
-        //   that are normalized in the L2-sense, i.e. integral_{R^3} |g(r)|^2
-        //   d^3r = 1.
-        //
-        // These three factors simplify to (pi/sigma^2)^3/4
-        let global_factor = (std::f64::consts::PI / self.atomic_gaussian_width_2).powf(0.75);
-
-        let c = self.atomic_gaussian_constant;
-        let c_rij = c * distance;
-        let exp_c_rij = f64::exp(-distance * c_rij);
-
-        for n in 0..self.parameters.max_radial {
-            let gto_constant = self.gto_gaussian_constants[n];
-            // `global_factor * exp(-c rij^2) * (c * rij)^l`
-            let mut factor = global_factor * exp_c_rij;
-
-            let z = c_rij * c_rij / (self.atomic_gaussian_constant + gto_constant);
-            // Calculate Gamma(a) / Gamma(b) 1F1(a, b, z)
-            self.double_regularized_1f1.compute(
-                z, n,
-                values.index_axis_mut(ndarray::Axis(1), n),
-                gradients.as_mut().map(|g| g.index_axis_mut(ndarray::Axis(1), n))
-            );
+        gaussian_primitives_radial_integral(
+            distance,
+            self.atomic_gaussian_width_2,
+            &self.gto_exponents,
+            &self.gto_gaussian_constants,
+            self.double_regularized_1f1,
+            values.view_mut(),
+            gradients.as_mut().map(|g| g.view_mut()),
+        );
 
-            for l in 0..(self.parameters.max_angular + 1) {
-                let n_l_3_over_2 = 0.5 * (n + l) as f64 + 1.5;
-                let c_dn = (c + gto_constant).powf(-n_l_3_over_2);
+        values.assign(&values.dot(&self.gto_orthonormalization));
+        if let Some(ref mut gradients) = gradients {
+            gradients.assign(&gradients.dot(&self.gto_orthonormalization));
+        }
+    }
+}
 
-                if !values[[l, n]].is_finite() {
-                    panic!(
-                        "Failed to compute radial integral with GTO basis. \
-                        Try increasing decreasing the `cutoff`, or increasing `atomic_gaussian_width`."
-                    );
-                }
+/// Compute the radial integral between the Gaussian atomic density (with
+/// `atomic_gaussian_width_2 = σ^2`) and a set of non-normalized primitive
+/// functions `φ_n(r) = r^{m_n} exp(-b_n r^2)`, where `m_n = exponents[n]` and
+/// `b_n = gaussian_constants[n]`. The results are stored in `values` and
+/// `gradients`, which should have a shape of `(max_angular + 1) x n_primitives`.
+///
+/// This is used to compute the radial integral of all radial basis made of
+/// linear combinations of such primitives.
+#[allow(clippy::needless_pass_by_value)]
+pub(super) fn gaussian_primitives_radial_integral(
+    distance: f64,
+    atomic_gaussian_width_2: f64,
+    exponents: &[usize],
+    gaussian_constants: &[f64],
+    double_regularized_1f1: DoubleRegularized1F1,
+    mut values: ArrayViewMut2<f64>,
+    mut gradients: Option<ArrayViewMut2<f64>>,
+) {
+    debug_assert_eq!(exponents.len(), gaussian_constants.len());
+    let max_angular = double_regularized_1f1.max_angular;
+
+    // Define global factor of radial integral arising from three parts:
+    // - a global 4 pi factor coming from integration of the angular part of
+    //   the radial integral (see the docs for `SoapRadialIntegral`)
+    // - a global factor of sqrt(pi)/4 from the calculation of the integral
+    //   of GTO basis functions and gaussian density
+    // - the normalization constant of the atomic Gaussian density. We use a
+    //   factor of 1/(pi*sigma^2)^0.75 which leads to Gaussian densities
+    //   that are normalized in the L2-sense, i.e. integral_{R^3} |g(r)|^2
+    //   d^3r = 1.
+    //
+    // These three factors simplify to (pi/sigma^2)^3/4
+    let global_factor = (std::f64::consts::PI / atomic_gaussian_width_2).powf(0.75);
+
+    let atomic_gaussian_constant = 1.0 / (2.0 * atomic_gaussian_width_2);
+    let c = atomic_gaussian_constant;
+    let c_rij = c * distance;
+    let exp_c_rij = f64::exp(-distance * c_rij);
+
+    for n in 0..exponents.len() {
+        let gto_constant = gaussian_constants[n];
+        // `global_factor * exp(-c rij^2) * (c * rij)^l`
+        let mut factor = global_factor * exp_c_rij;
+
+        let z = c_rij * c_rij / (atomic_gaussian_constant + gto_constant);
+        // Calculate Gamma(a) / Gamma(b) 1F1(a, b, z)
+        double_regularized_1f1.compute(
+            z, exponents[n],
+            values.index_axis_mut(ndarray::Axis(1), n),
+            gradients.as_mut().map(|g| g.index_axis_mut(ndarray::Axis(1), n))
+        );
 
-                values[[l, n]] *= c_dn * factor;
-                if let Some(ref mut gradients) = gradients {
-                    gradients[[l, n]] *= c_dn * factor * 2.0 * z / distance;
-                    gradients[[l, n]] += values[[l, n]] * (l as f64 / distance - 2.0 * c_rij);
-                }
+        for l in 0..(max_angular + 1) {
+            let n_l_3_over_2 = 0.5 * (exponents[n] + l) as f64 + 1.5;
+            let c_dn = (c + gto_constant).powf(-n_l_3_over_2);
 
-                factor *= c_rij;
+            if !values[[l, n]].is_finite() {
+                panic!(
+                    "Failed to compute radial integral with Gaussian-based radial basis. \
+                    Try increasing decreasing the `cutoff`, or increasing `atomic_gaussian_width`."
+                );
             }
-        }
 
-        // for r = 0, the formula used in the calculations above yield NaN,
-        // which in turns breaks the SplinedGto radial integral. From the
-        // analytical formula, the gradient is 0 everywhere expect for l=1
-        if distance == 0.0 {
+            values[[l, n]] *= c_dn * factor;
             if let Some(ref mut gradients) = gradients {
-                gradients.fill(0.0);
-
-                if self.parameters.max_angular >= 1 {
-                    let l = 1;
-                    for n in 0..self.parameters.max_radial {
-                        let gto_constant = self.gto_gaussian_constants[n];
-                        let a = 0.5 * (n + l) as f64 + 1.5;
-                        let b = 2.5;
-                        let c_dn = (c + gto_constant).powf(-a);
-                        let factor = global_factor * c * c_dn;
-
-                        gradients[[l, n]] = gamma(a) / gamma(b) * factor;
-                    }
-                }
+                gradients[[l, n]] *= c_dn * factor * 2.0 * z / distance;
+                gradients[[l, n]] += values[[l, n]] * (l as f64 / distance - 2.0 * c_rij);
             }
+
+            factor *= c_rij;
         }
+    }
 
-        values.assign(&values.dot(&self.gto_orthonormalization));
+    // for r = 0, the formula used in the calculations above yield NaN,
+    // which in turns breaks the SplinedGto radial integral. From the
+    // analytical formula, the gradient is 0 everywhere expect for l=1
+    if distance == 0.0 {
         if let Some(ref mut gradients) = gradients {
-            gradients.assign(&gradients.dot(&self.gto_orthonormalization));
+            gradients.fill(0.0);
+
+            if max_angular >= 1 {
+                let l = 1;
+                for n in 0..exponents.len() {
+                    let gto_constant = gaussian_constants[n];
+                    let a = 0.5 * (exponents[n] + l) as f64 + 1.5;
+                    let b = 2.5;
+                    let c_dn = (c + gto_constant).powf(-a);
+                    let factor = global_factor * c * c_dn;
+
+                    gradients[[l, n]] = gamma(a) / gamma(b) * factor;
+                }
+            }
         }
     }
 }
@@ -202,6 +249,7 @@ mod tests {
     use approx::assert_relative_eq;
 
     use super::super::{SoapRadialIntegralGto, SoapRadialIntegralGtoParameters, SoapRadialIntegral};
+    use crate::calculators::radial_basis::GtoOrthonormalization;
     use ndarray::Array2;
 
     #[test]
@@ -211,8 +259,58 @@ mod tests {
             max_radial: 0,
             max_angular: 4,
             cutoff: 3.0,
-            atomic_gaussian_width: 0.5
+            atomic_gaussian_width: 0.5,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
+        }).unwrap();
+    }
+
+    #[test]
+    #[should_panic = "expected 10 GTO gaussian widths (one for each radial basis function), got 2"]
+    fn wrong_sigmas_size() {
+        SoapRadialIntegralGto::new(SoapRadialIntegralGtoParameters {
+            max_radial: 10,
+            max_angular: 4,
+            cutoff: 3.0,
+            atomic_gaussian_width: 0.5,
+            sigmas: Some(vec![0.3, 0.6]),
+            orthonormalization: GtoOrthonormalization::Lowdin {},
+        }).unwrap();
+    }
+
+    #[test]
+    fn explicit_default_sigmas() {
+        let max_radial = 6;
+        let max_angular = 4;
+        let cutoff = 4.5;
+        let default = SoapRadialIntegralGto::new(SoapRadialIntegralGtoParameters {
+            max_radial: max_radial,
+            max_angular: max_angular,
+            cutoff: cutoff,
+            atomic_gaussian_width: 0.5,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
+        }).unwrap();
+
+        let sigmas = (0..max_radial)
+            .map(|n| cutoff * f64::max(f64::sqrt(n as f64), 1.0) / max_radial as f64)
+            .collect();
+        let explicit = SoapRadialIntegralGto::new(SoapRadialIntegralGtoParameters {
+            max_radial: max_radial,
+            max_angular: max_angular,
+            cutoff: cutoff,
+            atomic_gaussian_width: 0.5,
+            sigmas: Some(sigmas),
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         }).unwrap();
+
+        let shape = (max_angular + 1, max_radial);
+        let mut values = Array2::from_elem(shape, 0.0);
+        let mut explicit_values = Array2::from_elem(shape, 0.0);
+        default.compute(2.3, values.view_mut(), None);
+        explicit.compute(2.3, explicit_values.view_mut(), None);
+
+        assert_relative_eq!(values, explicit_values, max_relative=1e-12);
     }
 
     #[test]
@@ -222,7 +320,9 @@ mod tests {
             max_radial: 10,
             max_angular: 4,
             cutoff: -3.0,
-            atomic_gaussian_width: 0.5
+            atomic_gaussian_width: 0.5,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         }).unwrap();
     }
 
@@ -233,7 +333,9 @@ mod tests {
             max_radial: 10,
             max_angular: 4,
             cutoff: std::f64::INFINITY,
-            atomic_gaussian_width: 0.5
+            atomic_gaussian_width: 0.5,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         }).unwrap();
     }
 
@@ -244,7 +346,9 @@ mod tests {
             max_radial: 10,
             max_angular: 4,
             cutoff: 3.0,
-            atomic_gaussian_width: -0.5
+            atomic_gaussian_width: -0.5,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         }).unwrap();
     }
 
@@ -256,6 +360,8 @@ mod tests {
             max_angular: 4,
             cutoff: 3.0,
             atomic_gaussian_width: std::f64::INFINITY,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         }).unwrap();
     }
 
@@ -267,6 +373,8 @@ mod tests {
             max_angular: 3,
             cutoff: 5.0,
             atomic_gaussian_width: 0.5,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         }).unwrap();
     }
 
@@ -278,6 +386,8 @@ mod tests {
             max_angular: 3,
             cutoff: 5.0,
             atomic_gaussian_width: 0.5,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         }).unwrap();
         let mut values = Array2::from_elem((3, 2), 0.0);
 
@@ -292,6 +402,8 @@ mod tests {
             max_angular: 3,
             cutoff: 5.0,
             atomic_gaussian_width: 0.5,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         }).unwrap();
         let mut values = Array2::from_elem((4, 2), 0.0);
         let mut gradients = Array2::from_elem((3, 2), 0.0);
@@ -308,6 +420,8 @@ mod tests {
             max_angular: max_angular,
             cutoff: 5.0,
             atomic_gaussian_width: 0.5,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         }).unwrap();
 
         let shape = (max_angular + 1, max_radial);
@@ -331,6 +445,8 @@ mod tests {
             max_angular: max_angular,
             cutoff: 5.0,
             atomic_gaussian_width: 0.5,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         }).unwrap();
 
         let rij = 3.4;
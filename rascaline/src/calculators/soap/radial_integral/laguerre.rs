@@ -0,0 +1,202 @@
+use ndarray::{Array2, ArrayViewMut2};
+
+use crate::calculators::radial_basis::LaguerreRadialBasis;
+use crate::math::DoubleRegularized1F1;
+use crate::Error;
+
+use super::SoapRadialIntegral;
+use super::gto::gaussian_primitives_radial_integral;
+
+/// Parameters controlling the SOAP radial integral with Laguerre radial basis
+#[derive(Debug, Clone, Copy)]
+pub struct SoapRadialIntegralLaguerreParameters {
+    /// Number of radial components
+    pub max_radial: usize,
+    /// Number of angular components
+    pub max_angular: usize,
+    /// atomic density gaussian width
+    pub atomic_gaussian_width: f64,
+    /// cutoff radius
+    pub cutoff: f64,
+}
+
+impl SoapRadialIntegralLaguerreParameters {
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if self.max_radial == 0 {
+            return Err(Error::InvalidParameter(
+                "max_radial must be at least 1 for Laguerre radial integral".into()
+            ));
+        }
+
+        if self.cutoff < 0.0 || !self.cutoff.is_finite() {
+            return Err(Error::InvalidParameter(
+                "cutoff must be a positive number for Laguerre radial integral".into()
+            ));
+        }
+
+        if self.atomic_gaussian_width < 0.0 || !self.atomic_gaussian_width.is_finite() {
+            return Err(Error::InvalidParameter(
+                "atomic_gaussian_width must be a positive number for Laguerre radial integral".into()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Implementation of the radial integral for Laguerre radial basis and gaussian
+/// atomic density.
+///
+/// Each basis function is a linear combination of the primitive functions
+/// `r^{2k} e^{- r^2 / (2 σ^2)}`, for which the radial integral is known
+/// analytically.
+#[derive(Debug, Clone)]
+pub struct SoapRadialIntegralLaguerre {
+    parameters: SoapRadialIntegralLaguerreParameters,
+    /// σ^2, with σ the atomic density gaussian width
+    atomic_gaussian_width_2: f64,
+    /// exponent of r in each primitive function, i.e. `2k`
+    primitive_exponents: Vec<usize>,
+    /// 1/2σ^2 for each primitive function, with σ the Laguerre gaussian width
+    primitive_gaussian_constants: Vec<f64>,
+    /// transposed `n_max * n_max` matrix going from primitives to basis functions
+    coefficients: Array2<f64>,
+    /// Implementation of `Gamma(a) / Gamma(b) 1F1(a, b, z)`
+    double_regularized_1f1: DoubleRegularized1F1,
+}
+
+impl SoapRadialIntegralLaguerre {
+    pub fn new(parameters: SoapRadialIntegralLaguerreParameters) -> Result<SoapRadialIntegralLaguerre, Error> {
+        parameters.validate()?;
+
+        let basis = LaguerreRadialBasis {
+            max_radial: parameters.max_radial,
+            cutoff: parameters.cutoff,
+        };
+        let sigma = basis.gaussian_width();
+
+        return Ok(SoapRadialIntegralLaguerre {
+            parameters: parameters,
+            atomic_gaussian_width_2: parameters.atomic_gaussian_width * parameters.atomic_gaussian_width,
+            primitive_exponents: (0..parameters.max_radial).map(|k| 2 * k).collect(),
+            primitive_gaussian_constants: vec![1.0 / (2.0 * sigma * sigma); parameters.max_radial],
+            coefficients: basis.coefficients().t().to_owned(),
+            double_regularized_1f1: DoubleRegularized1F1 {
+                max_angular: parameters.max_angular,
+            },
+        });
+    }
+}
+
+impl SoapRadialIntegral for SoapRadialIntegralLaguerre {
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "LaguerreRadialIntegral::compute"))]
+    fn compute(
+        &self,
+        distance: f64,
+        mut values: ArrayViewMut2<f64>,
+        mut gradients: Option<ArrayViewMut2<f64>>
+    ) {
+        let expected_shape = [self.parameters.max_angular + 1, self.parameters.max_radial];
+        assert_eq!(
+            values.shape(), expected_shape,
+            "wrong size for values array, expected [{}, {}] but got [{}, {}]",
+            expected_shape[0], expected_shape[1], values.shape()[0], values.shape()[1]
+        );
+
+        if let Some(ref gradients) = gradients {
+            assert_eq!(
+                gradients.shape(), expected_shape,
+                "wrong size for gradients array, expected [{}, {}] but got [{}, {}]",
+                expected_shape[0], expected_shape[1], gradients.shape()[0], gradients.shape()[1]
+            );
+        }
+
+        gaussian_primitives_radial_integral(
+            distance,
+            self.atomic_gaussian_width_2,
+            &self.primitive_exponents,
+            &self.primitive_gaussian_constants,
+            self.double_regularized_1f1,
+            values.view_mut(),
+            gradients.as_mut().map(|g| g.view_mut()),
+        );
+
+        let projected = values.dot(&self.coefficients);
+        values.assign(&projected);
+
+        if let Some(ref mut gradients) = gradients {
+            let projected = gradients.dot(&self.coefficients);
+            gradients.assign(&projected);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use ndarray::Array2;
+
+    use super::super::{SoapRadialIntegralLaguerre, SoapRadialIntegralLaguerreParameters, SoapRadialIntegral};
+
+    #[test]
+    #[should_panic = "max_radial must be at least 1"]
+    fn invalid_max_radial() {
+        SoapRadialIntegralLaguerre::new(SoapRadialIntegralLaguerreParameters {
+            max_radial: 0,
+            max_angular: 4,
+            cutoff: 3.0,
+            atomic_gaussian_width: 0.5
+        }).unwrap();
+    }
+
+    #[test]
+    fn finite_differences() {
+        let max_radial = 6;
+        let max_angular = 6;
+        let laguerre = SoapRadialIntegralLaguerre::new(SoapRadialIntegralLaguerreParameters {
+            max_radial: max_radial,
+            max_angular: max_angular,
+            cutoff: 5.0,
+            atomic_gaussian_width: 0.5,
+        }).unwrap();
+
+        let rij = 3.4;
+        let delta = 1e-9;
+
+        let shape = (max_angular + 1, max_radial);
+        let mut values = Array2::from_elem(shape, 0.0);
+        let mut values_delta = Array2::from_elem(shape, 0.0);
+        let mut gradients = Array2::from_elem(shape, 0.0);
+        laguerre.compute(rij, values.view_mut(), Some(gradients.view_mut()));
+        laguerre.compute(rij + delta, values_delta.view_mut(), None);
+
+        let finite_differences = (&values_delta - &values) / delta;
+
+        assert_relative_eq!(
+            finite_differences, gradients, epsilon=1e-6, max_relative=1e-4
+        );
+    }
+
+    #[test]
+    fn gradients_near_zero() {
+        let max_radial = 6;
+        let max_angular = 6;
+        let laguerre = SoapRadialIntegralLaguerre::new(SoapRadialIntegralLaguerreParameters {
+            max_radial: max_radial,
+            max_angular: max_angular,
+            cutoff: 5.0,
+            atomic_gaussian_width: 0.5,
+        }).unwrap();
+
+        let shape = (max_angular + 1, max_radial);
+        let mut values = Array2::from_elem(shape, 0.0);
+        let mut gradients = Array2::from_elem(shape, 0.0);
+        let mut gradients_plus = Array2::from_elem(shape, 0.0);
+        laguerre.compute(0.0, values.view_mut(), Some(gradients.view_mut()));
+        laguerre.compute(1e-12, values.view_mut(), Some(gradients_plus.view_mut()));
+
+        assert_relative_eq!(
+            gradients, gradients_plus, epsilon=1e-11, max_relative=1e-6,
+        );
+    }
+}
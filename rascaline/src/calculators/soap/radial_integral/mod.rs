@@ -2,6 +2,8 @@ use ndarray::{ArrayViewMut2, Array2};
 
 use crate::Error;
 use crate::calculators::radial_basis::RadialBasis;
+use crate::calculators::soap::AtomicDensity;
+use crate::math::SplineAccuracy;
 
 /// A `SoapRadialIntegral` computes the SOAP radial integral on a given radial
 /// basis.
@@ -54,9 +56,18 @@ pub trait SoapRadialIntegral: std::panic::RefUnwindSafe + Send {
 mod gto;
 pub use self::gto::{SoapRadialIntegralGto, SoapRadialIntegralGtoParameters};
 
+mod laguerre;
+pub use self::laguerre::{SoapRadialIntegralLaguerre, SoapRadialIntegralLaguerreParameters};
+
 mod spline;
 pub use self::spline::{SoapRadialIntegralSpline, SoapRadialIntegralSplineParameters};
 
+mod numerical;
+pub use self::numerical::{SoapRadialIntegralNumerical, SoapRadialIntegralNumericalParameters};
+
+mod delta;
+pub use self::delta::{SoapRadialIntegralDelta, SoapRadialIntegralDeltaParameters};
+
 /// Parameters controlling the radial integral for SOAP
 #[derive(Debug, Clone, Copy)]
 pub struct SoapRadialIntegralParameters {
@@ -64,6 +75,7 @@ pub struct SoapRadialIntegralParameters {
     pub max_angular: usize,
     pub atomic_gaussian_width: f64,
     pub cutoff: f64,
+    pub density: AtomicDensity,
 }
 
 /// Store together a Radial integral implementation and cached allocation for
@@ -80,40 +92,11 @@ pub struct SoapRadialIntegralCache {
 impl SoapRadialIntegralCache {
     /// Create a new `RadialIntegralCache` for the given radial basis & parameters
     pub fn new(radial_basis: RadialBasis, parameters: SoapRadialIntegralParameters) -> Result<Self, Error> {
-        let code = match radial_basis {
-            RadialBasis::Gto {splined_radial_integral, spline_accuracy} => {
-                let parameters = SoapRadialIntegralGtoParameters {
-                    max_radial: parameters.max_radial,
-                    max_angular: parameters.max_angular,
-                    atomic_gaussian_width: parameters.atomic_gaussian_width,
-                    cutoff: parameters.cutoff,
-                };
-                let gto = SoapRadialIntegralGto::new(parameters)?;
-
-                if splined_radial_integral {
-                    let parameters = SoapRadialIntegralSplineParameters {
-                        max_radial: parameters.max_radial,
-                        max_angular: parameters.max_angular,
-                        cutoff: parameters.cutoff,
-                    };
-
-                    Box::new(SoapRadialIntegralSpline::with_accuracy(
-                        parameters, spline_accuracy, gto
-                    )?)
-                } else {
-                    Box::new(gto) as Box<dyn SoapRadialIntegral>
-                }
-            }
-
-            RadialBasis::TabulatedRadialIntegral {points} => {
-                let parameters = SoapRadialIntegralSplineParameters {
-                    max_radial: parameters.max_radial,
-                    max_angular: parameters.max_angular,
-                    cutoff: parameters.cutoff,
-                };
-                Box::new(SoapRadialIntegralSpline::from_tabulated(
-                    parameters, points
-                )?)
+        let code = match parameters.density {
+            AtomicDensity::Gaussian {} => gaussian_radial_integral(radial_basis, parameters)?,
+            AtomicDensity::Delta {} => delta_radial_integral(&radial_basis, parameters)?,
+            AtomicDensity::Slater { .. } | AtomicDensity::Lorentzian { .. } => {
+                numerical_radial_integral(&radial_basis, parameters)?
             }
         };
 
@@ -142,3 +125,156 @@ impl SoapRadialIntegralCache {
         }
     }
 }
+
+/// Create the radial integral for gaussian atomic densities, using the
+/// analytical expressions (potentially splined)
+fn gaussian_radial_integral(
+    radial_basis: RadialBasis,
+    parameters: SoapRadialIntegralParameters,
+) -> Result<Box<dyn SoapRadialIntegral>, Error> {
+    let code = match radial_basis {
+        RadialBasis::Gto {splined_radial_integral, spline_accuracy, spline_relative_accuracy, spline_max_points, sigmas, orthonormalization} => {
+            let gto_parameters = SoapRadialIntegralGtoParameters {
+                max_radial: parameters.max_radial,
+                max_angular: parameters.max_angular,
+                atomic_gaussian_width: parameters.atomic_gaussian_width,
+                cutoff: parameters.cutoff,
+                sigmas: sigmas,
+                orthonormalization: orthonormalization,
+            };
+            let description = format!("{:?}", gto_parameters);
+            let gto = SoapRadialIntegralGto::new(gto_parameters)?;
+
+            if splined_radial_integral {
+                let parameters = SoapRadialIntegralSplineParameters {
+                    max_radial: parameters.max_radial,
+                    max_angular: parameters.max_angular,
+                    cutoff: parameters.cutoff,
+                };
+
+                let accuracy = SplineAccuracy {
+                    absolute: spline_accuracy,
+                    relative: spline_relative_accuracy.unwrap_or(spline_accuracy),
+                    max_points: spline_max_points,
+                };
+
+                Box::new(SoapRadialIntegralSpline::with_accuracy_cached(
+                    &description, parameters, accuracy, gto
+                )?)
+            } else {
+                Box::new(gto) as Box<dyn SoapRadialIntegral>
+            }
+        }
+
+        RadialBasis::Laguerre {splined_radial_integral, spline_accuracy, spline_relative_accuracy, spline_max_points} => {
+            let parameters = SoapRadialIntegralLaguerreParameters {
+                max_radial: parameters.max_radial,
+                max_angular: parameters.max_angular,
+                atomic_gaussian_width: parameters.atomic_gaussian_width,
+                cutoff: parameters.cutoff,
+            };
+            let description = format!("{:?}", parameters);
+            let laguerre = SoapRadialIntegralLaguerre::new(parameters)?;
+
+            if splined_radial_integral {
+                let parameters = SoapRadialIntegralSplineParameters {
+                    max_radial: parameters.max_radial,
+                    max_angular: parameters.max_angular,
+                    cutoff: parameters.cutoff,
+                };
+
+                let accuracy = SplineAccuracy {
+                    absolute: spline_accuracy,
+                    relative: spline_relative_accuracy.unwrap_or(spline_accuracy),
+                    max_points: spline_max_points,
+                };
+
+                Box::new(SoapRadialIntegralSpline::with_accuracy_cached(
+                    &description, parameters, accuracy, laguerre
+                )?)
+            } else {
+                Box::new(laguerre) as Box<dyn SoapRadialIntegral>
+            }
+        }
+
+        RadialBasis::TabulatedRadialIntegral {points} => {
+            let parameters = SoapRadialIntegralSplineParameters {
+                max_radial: parameters.max_radial,
+                max_angular: parameters.max_angular,
+                cutoff: parameters.cutoff,
+            };
+            Box::new(SoapRadialIntegralSpline::from_tabulated(
+                parameters, points
+            )?)
+        }
+    };
+
+    return Ok(code);
+}
+
+/// Create the radial integral for non-gaussian atomic densities. There is no
+/// analytical expression in this case, so we always spline the numerical
+/// integral.
+fn numerical_radial_integral(
+    radial_basis: &RadialBasis,
+    parameters: SoapRadialIntegralParameters,
+) -> Result<Box<dyn SoapRadialIntegral>, Error> {
+    let accuracy = match *radial_basis {
+        RadialBasis::Gto {spline_accuracy, spline_relative_accuracy, spline_max_points, ..} |
+        RadialBasis::Laguerre {spline_accuracy, spline_relative_accuracy, spline_max_points, ..} => {
+            SplineAccuracy {
+                absolute: spline_accuracy,
+                relative: spline_relative_accuracy.unwrap_or(spline_accuracy),
+                max_points: spline_max_points,
+            }
+        }
+        RadialBasis::TabulatedRadialIntegral { .. } => {
+            return Err(Error::InvalidParameter(
+                "only gaussian atomic densities can be used with a tabulated \
+                radial integral, the density should be included in the tabulated values".into()
+            ));
+        }
+    };
+
+    let numerical_parameters = SoapRadialIntegralNumericalParameters {
+        max_radial: parameters.max_radial,
+        max_angular: parameters.max_angular,
+        atomic_gaussian_width: parameters.atomic_gaussian_width,
+        cutoff: parameters.cutoff,
+        density: parameters.density,
+    };
+    let description = format!("{:?}, {:?}", numerical_parameters, radial_basis);
+    let numerical = SoapRadialIntegralNumerical::new(numerical_parameters, radial_basis)?;
+
+    let parameters = SoapRadialIntegralSplineParameters {
+        max_radial: parameters.max_radial,
+        max_angular: parameters.max_angular,
+        cutoff: parameters.cutoff,
+    };
+
+    return Ok(Box::new(SoapRadialIntegralSpline::with_accuracy_cached(
+        &description, parameters, accuracy, numerical
+    )?));
+}
+
+/// Create the radial integral for delta atomic densities, which directly
+/// evaluates the radial basis and does not need splines.
+fn delta_radial_integral(
+    radial_basis: &RadialBasis,
+    parameters: SoapRadialIntegralParameters,
+) -> Result<Box<dyn SoapRadialIntegral>, Error> {
+    if let RadialBasis::TabulatedRadialIntegral { .. } = radial_basis {
+        return Err(Error::InvalidParameter(
+            "only gaussian atomic densities can be used with a tabulated \
+            radial integral, the density should be included in the tabulated values".into()
+        ));
+    }
+
+    let delta = SoapRadialIntegralDelta::new(SoapRadialIntegralDeltaParameters {
+        max_radial: parameters.max_radial,
+        max_angular: parameters.max_angular,
+        cutoff: parameters.cutoff,
+    }, radial_basis)?;
+
+    return Ok(Box::new(delta));
+}
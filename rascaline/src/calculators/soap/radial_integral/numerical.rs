@@ -0,0 +1,488 @@
+use ndarray::{Array2, ArrayViewMut2};
+
+use crate::calculators::radial_basis::{RadialBasis, GtoRadialBasis, LaguerreRadialBasis};
+use crate::calculators::soap::AtomicDensity;
+use crate::Error;
+
+use super::SoapRadialIntegral;
+
+/// Number of Gauss-Legendre quadrature points in each integration panel
+const QUADRATURE_POINTS: usize = 10;
+
+/// Parameters controlling the numerical SOAP radial integral
+#[derive(Debug, Clone, Copy)]
+pub struct SoapRadialIntegralNumericalParameters {
+    /// Number of radial components
+    pub max_radial: usize,
+    /// Number of angular components
+    pub max_angular: usize,
+    /// atomic density gaussian width, only used for gaussian densities
+    pub atomic_gaussian_width: f64,
+    /// cutoff radius
+    pub cutoff: f64,
+    /// shape of the atomic density
+    pub density: AtomicDensity,
+}
+
+impl SoapRadialIntegralNumericalParameters {
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if self.max_radial == 0 {
+            return Err(Error::InvalidParameter(
+                "max_radial must be at least 1 for numerical radial integral".into()
+            ));
+        }
+
+        if self.cutoff < 0.0 || !self.cutoff.is_finite() {
+            return Err(Error::InvalidParameter(
+                "cutoff must be a positive number for numerical radial integral".into()
+            ));
+        }
+
+        if let AtomicDensity::Gaussian {} = self.density {
+            if self.atomic_gaussian_width <= 0.0 || !self.atomic_gaussian_width.is_finite() {
+                return Err(Error::InvalidParameter(
+                    "atomic_gaussian_width must be a positive number for numerical radial integral".into()
+                ));
+            }
+        }
+
+        if let AtomicDensity::Delta {} = self.density {
+            return Err(Error::InvalidParameter(
+                "numerical radial integral can not be used with a delta atomic density".into()
+            ));
+        }
+
+        self.density.validate()?;
+
+        Ok(())
+    }
+}
+
+/// Implementation of the radial integral for an arbitrary spherically
+/// symmetric atomic density, using numerical integration.
+///
+/// The radial basis functions are expressed as linear combinations of the
+/// primitive functions `r^m e^{-b r^2}`, which covers both the GTO and Laguerre
+/// radial basis. The double integral over `r` and `u` (see
+/// [`SoapRadialIntegral::compute`]) is then evaluated with composite
+/// Gauss-Legendre quadrature, after the change of variable `u -> s =
+/// \sqrt{r^2 + r_{ij}^2 - 2 r r_{ij} u}`, which makes the integrand smooth
+/// everywhere except at `r = r_{ij}`.
+///
+/// This is much slower than the analytical radial integrals, and should be
+/// used together with splines.
+#[derive(Debug, Clone)]
+pub struct SoapRadialIntegralNumerical {
+    parameters: SoapRadialIntegralNumericalParameters,
+    /// exponent of r in each primitive function, i.e. `m`
+    primitive_exponents: Vec<usize>,
+    /// `b` for each primitive function
+    primitive_gaussian_constants: Vec<f64>,
+    /// transposed `n_max * n_max` matrix going from primitives to basis functions
+    coefficients: Array2<f64>,
+    /// All basis functions are negligible after this distance
+    max_distance: f64,
+    /// Length of the integration panels
+    panel_length: f64,
+    /// Gauss-Legendre quadrature nodes and weights on `[-1, 1]`
+    quadrature: Vec<(f64, f64)>,
+}
+
+impl SoapRadialIntegralNumerical {
+    pub fn new(
+        parameters: SoapRadialIntegralNumericalParameters,
+        radial_basis: &RadialBasis,
+    ) -> Result<SoapRadialIntegralNumerical, Error> {
+        parameters.validate()?;
+
+        let (primitive_exponents, primitive_gaussian_widths, coefficients) = primitive_functions(
+            radial_basis, parameters.max_radial, parameters.cutoff
+        )?;
+
+        // the primitive functions `r^m e^{-r^2 / (2 σ^2)}` are below 1e-17
+        // times their maximal value after `σ (\sqrt{m} + 9)`
+        let mut max_distance = 0.0;
+        let mut smallest_width = f64::INFINITY;
+        for (&exponent, &sigma) in primitive_exponents.iter().zip(&primitive_gaussian_widths) {
+            max_distance = f64::max(max_distance, sigma * (f64::sqrt(exponent as f64) + 9.0));
+            smallest_width = f64::min(smallest_width, sigma);
+        }
+
+        let density_length = parameters.density.length_scale(parameters.atomic_gaussian_width);
+
+        return Ok(SoapRadialIntegralNumerical {
+            parameters: parameters,
+            primitive_exponents: primitive_exponents,
+            primitive_gaussian_constants: primitive_gaussian_widths.iter()
+                .map(|&sigma| 1.0 / (2.0 * sigma * sigma))
+                .collect(),
+            coefficients: coefficients.t().to_owned(),
+            max_distance: max_distance,
+            panel_length: f64::min(density_length, smallest_width),
+            quadrature: gauss_legendre(QUADRATURE_POINTS),
+        });
+    }
+
+    /// Call `function(x, weight)` for all points of a composite Gauss-Legendre
+    /// quadrature over `[start, stop]`
+    fn integrate(&self, start: f64, stop: f64, mut function: impl FnMut(f64, f64)) {
+        let n_panels = f64::ceil((stop - start) / self.panel_length).max(1.0);
+        let length = (stop - start) / n_panels;
+
+        for panel in 0..(n_panels as usize) {
+            let center = start + (panel as f64 + 0.5) * length;
+            for &(node, weight) in &self.quadrature {
+                function(center + 0.5 * length * node, 0.5 * length * weight);
+            }
+        }
+    }
+
+    /// Compute `r^m e^{-b r^2}` for all primitive functions
+    fn primitives(&self, r: f64, primitives: &mut [f64]) {
+        for (n, value) in primitives.iter_mut().enumerate() {
+            let exponent = self.primitive_exponents[n] as i32;
+            let constant = self.primitive_gaussian_constants[n];
+            *value = r.powi(exponent) * f64::exp(-constant * r * r);
+        }
+    }
+
+    /// Compute the radial integral at `r_{ij} = 0`, where only the `l=0`
+    /// values and `l=1` gradients are non-zero
+    #[allow(clippy::needless_pass_by_value)]
+    fn compute_at_zero(&self, mut values: ArrayViewMut2<f64>, mut gradients: Option<ArrayViewMut2<f64>>) {
+        let density = self.parameters.density;
+        let width = self.parameters.atomic_gaussian_width;
+
+        let mut primitives = vec![0.0; self.parameters.max_radial];
+        self.integrate(0.0, self.max_distance, |r, weight| {
+            self.primitives(r, &mut primitives);
+
+            let value = 4.0 * std::f64::consts::PI * weight * r * r * density.compute(r, width);
+            for (n, primitive) in primitives.iter().enumerate() {
+                values[[0, n]] += value * primitive;
+            }
+
+            if let Some(ref mut gradients) = gradients {
+                if self.parameters.max_angular >= 1 {
+                    let gradient = -4.0 / 3.0 * std::f64::consts::PI * weight * r * r * density.derivative(r, width);
+                    for (n, primitive) in primitives.iter().enumerate() {
+                        gradients[[1, n]] += gradient * primitive;
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl SoapRadialIntegral for SoapRadialIntegralNumerical {
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "NumericalRadialIntegral::compute"))]
+    fn compute(
+        &self,
+        distance: f64,
+        mut values: ArrayViewMut2<f64>,
+        mut gradients: Option<ArrayViewMut2<f64>>
+    ) {
+        let expected_shape = [self.parameters.max_angular + 1, self.parameters.max_radial];
+        assert_eq!(
+            values.shape(), expected_shape,
+            "wrong size for values array, expected [{}, {}] but got [{}, {}]",
+            expected_shape[0], expected_shape[1], values.shape()[0], values.shape()[1]
+        );
+
+        if let Some(ref gradients) = gradients {
+            assert_eq!(
+                gradients.shape(), expected_shape,
+                "wrong size for gradients array, expected [{}, {}] but got [{}, {}]",
+                expected_shape[0], expected_shape[1], gradients.shape()[0], gradients.shape()[1]
+            );
+        }
+
+        values.fill(0.0);
+        if let Some(ref mut gradients) = gradients {
+            gradients.fill(0.0);
+        }
+
+        if distance == 0.0 {
+            self.compute_at_zero(values.view_mut(), gradients.as_mut().map(|g| g.view_mut()));
+        } else {
+            let density = self.parameters.density;
+            let width = self.parameters.atomic_gaussian_width;
+            let max_angular = self.parameters.max_angular;
+
+            let mut primitives = vec![0.0; self.parameters.max_radial];
+            let mut legendre = vec![0.0; max_angular + 1];
+            let mut inner_values = vec![0.0; max_angular + 1];
+            let mut inner_gradients = vec![0.0; max_angular + 1];
+            let do_gradients = gradients.is_some();
+
+            let mut outer_integrand = |r: f64, weight: f64| {
+                // integrate over s = |r - r_ij| + t, for t in [0, 2 min(r, r_ij)]
+                inner_values.fill(0.0);
+                inner_gradients.fill(0.0);
+
+                let r_minus_rij = r - distance;
+                let abs_r_minus_rij = r_minus_rij.abs();
+                self.integrate(0.0, 2.0 * f64::min(r, distance), |t, inner_weight| {
+                    let s = abs_r_minus_rij + t;
+                    // 2 r r_ij (1 - u), computed without cancellations
+                    let q = 2.0 * abs_r_minus_rij * t + t * t;
+                    let u = 1.0 - q / (2.0 * r * distance);
+                    legendre_polynomials(u, &mut legendre);
+
+                    let value = inner_weight * s * density.compute(s, width);
+                    for l in 0..=max_angular {
+                        inner_values[l] += legendre[l] * value;
+                    }
+
+                    if do_gradients {
+                        // d s / d r_ij = (r_ij - r u) / s
+                        let gradient = inner_weight * density.derivative(s, width)
+                            * (q / (2.0 * distance) - r_minus_rij);
+                        for l in 0..=max_angular {
+                            inner_gradients[l] += legendre[l] * gradient;
+                        }
+                    }
+                });
+
+                self.primitives(r, &mut primitives);
+                let factor = 2.0 * std::f64::consts::PI * weight * r / distance;
+                for l in 0..=max_angular {
+                    for (n, primitive) in primitives.iter().enumerate() {
+                        values[[l, n]] += factor * primitive * inner_values[l];
+                    }
+                }
+
+                if let Some(ref mut gradients) = gradients {
+                    for l in 0..=max_angular {
+                        for (n, primitive) in primitives.iter().enumerate() {
+                            gradients[[l, n]] += factor * primitive * inner_gradients[l];
+                        }
+                    }
+                }
+            };
+
+            // the integrand is not smooth at r = r_ij, so we split the
+            // integration domain there
+            if distance < self.max_distance {
+                self.integrate(0.0, distance, &mut outer_integrand);
+                self.integrate(distance, self.max_distance, &mut outer_integrand);
+            } else {
+                self.integrate(0.0, self.max_distance, &mut outer_integrand);
+            }
+        }
+
+        values.assign(&values.dot(&self.coefficients));
+        if let Some(ref mut gradients) = gradients {
+            gradients.assign(&gradients.dot(&self.coefficients));
+        }
+    }
+}
+
+/// Express a GTO or Laguerre `radial_basis` as linear combinations of the
+/// primitive functions `r^m e^{-r^2 / (2 σ^2)}`. This returns the exponents
+/// `m`, the widths `σ` and the `n_max * n_max` matrix `C` such that `R_n(r) =
+/// \sum_k C_{nk} r^{m_k} e^{-r^2 / (2 σ_k^2)}`.
+pub(super) fn primitive_functions(
+    radial_basis: &RadialBasis,
+    max_radial: usize,
+    cutoff: f64,
+) -> Result<(Vec<usize>, Vec<f64>, Array2<f64>), Error> {
+    match radial_basis {
+        RadialBasis::Gto { sigmas, orthonormalization, .. } => {
+            let basis = GtoRadialBasis {
+                max_radial: max_radial,
+                cutoff: cutoff,
+                sigmas: sigmas.clone(),
+                orthonormalization: *orthonormalization,
+            };
+            basis.validate()?;
+
+            return Ok((
+                (0..max_radial).collect(),
+                basis.gaussian_widths(),
+                basis.orthonormalization_matrix(),
+            ));
+        }
+        RadialBasis::Laguerre { .. } => {
+            let basis = LaguerreRadialBasis {
+                max_radial: max_radial,
+                cutoff: cutoff,
+            };
+
+            return Ok((
+                (0..max_radial).map(|k| 2 * k).collect(),
+                vec![basis.gaussian_width(); max_radial],
+                basis.coefficients(),
+            ));
+        }
+        RadialBasis::TabulatedRadialIntegral { .. } => {
+            return Err(Error::InvalidParameter(
+                "tabulated radial integral can not be expressed with primitive functions".into()
+            ));
+        }
+    }
+}
+
+/// Compute the Legendre polynomials `P_l(u)` for all `l` up to
+/// `legendre.len() - 1`, using Bonnet's recursion formula
+fn legendre_polynomials(u: f64, legendre: &mut [f64]) {
+    legendre[0] = 1.0;
+    if legendre.len() > 1 {
+        legendre[1] = u;
+    }
+
+    for l in 1..(legendre.len() - 1) {
+        let l_f64 = l as f64;
+        legendre[l + 1] = ((2.0 * l_f64 + 1.0) * u * legendre[l] - l_f64 * legendre[l - 1]) / (l_f64 + 1.0);
+    }
+}
+
+/// Get the nodes and weights of the `n`-points Gauss-Legendre quadrature on
+/// `[-1, 1]`, using Newton's method to find the roots of `P_n`
+fn gauss_legendre(n: usize) -> Vec<(f64, f64)> {
+    let mut legendre = vec![0.0; n + 1];
+    let n_f64 = n as f64;
+
+    let mut quadrature = Vec::with_capacity(n);
+    for i in 0..n {
+        // initial guess for the i-th root
+        let mut x = f64::cos(std::f64::consts::PI * (i as f64 + 0.75) / (n_f64 + 0.5));
+        let mut derivative = 0.0;
+        for _ in 0..100 {
+            legendre_polynomials(x, &mut legendre);
+            derivative = n_f64 * (x * legendre[n] - legendre[n - 1]) / (x * x - 1.0);
+
+            let dx = legendre[n] / derivative;
+            x -= dx;
+            if dx.abs() < 1e-16 {
+                legendre_polynomials(x, &mut legendre);
+                derivative = n_f64 * (x * legendre[n] - legendre[n - 1]) / (x * x - 1.0);
+                break;
+            }
+        }
+
+        quadrature.push((x, 2.0 / ((1.0 - x * x) * derivative * derivative)));
+    }
+
+    return quadrature;
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use ndarray::Array2;
+
+    use crate::calculators::radial_basis::{RadialBasis, GtoOrthonormalization};
+    use crate::calculators::soap::AtomicDensity;
+
+    use super::super::{SoapRadialIntegral, SoapRadialIntegralGto, SoapRadialIntegralGtoParameters};
+    use super::super::{SoapRadialIntegralNumerical, SoapRadialIntegralNumericalParameters};
+
+    #[test]
+    fn gauss_legendre() {
+        let quadrature = super::gauss_legendre(10);
+        // integrate x^18 exactly
+        let integral = quadrature.iter().map(|(x, w)| w * x.powi(18)).sum::<f64>();
+        assert_relative_eq!(integral, 2.0 / 19.0, max_relative=1e-14);
+    }
+
+    #[test]
+    fn gaussian_density() {
+        // the numerical integral should match the analytical one for gaussian
+        // densities
+        let max_radial = 6;
+        let max_angular = 6;
+        let numerical = SoapRadialIntegralNumerical::new(SoapRadialIntegralNumericalParameters {
+            max_radial: max_radial,
+            max_angular: max_angular,
+            cutoff: 4.5,
+            atomic_gaussian_width: 0.5,
+            density: AtomicDensity::Gaussian {},
+        }, &RadialBasis::gto()).unwrap();
+
+        let gto = SoapRadialIntegralGto::new(SoapRadialIntegralGtoParameters {
+            max_radial: max_radial,
+            max_angular: max_angular,
+            cutoff: 4.5,
+            atomic_gaussian_width: 0.5,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
+        }).unwrap();
+
+        let shape = (max_angular + 1, max_radial);
+        let mut values = Array2::from_elem(shape, 0.0);
+        let mut gradients = Array2::from_elem(shape, 0.0);
+        let mut expected_values = Array2::from_elem(shape, 0.0);
+        let mut expected_gradients = Array2::from_elem(shape, 0.0);
+        for rij in [0.0, 1e-3, 0.8, 2.4, 4.5] {
+            numerical.compute(rij, values.view_mut(), Some(gradients.view_mut()));
+            gto.compute(rij, expected_values.view_mut(), Some(expected_gradients.view_mut()));
+
+            assert_relative_eq!(values, expected_values, epsilon=1e-10, max_relative=1e-8);
+            assert_relative_eq!(gradients, expected_gradients, epsilon=1e-10, max_relative=1e-8);
+        }
+    }
+
+    #[test]
+    fn finite_differences() {
+        let max_radial = 5;
+        let max_angular = 4;
+        let densities = [
+            AtomicDensity::Slater { decay_length: 0.4 },
+            AtomicDensity::Lorentzian { width: 0.3 },
+        ];
+
+        for density in densities {
+            for radial_basis in [RadialBasis::gto(), RadialBasis::laguerre()] {
+                let numerical = SoapRadialIntegralNumerical::new(SoapRadialIntegralNumericalParameters {
+                    max_radial: max_radial,
+                    max_angular: max_angular,
+                    cutoff: 4.0,
+                    atomic_gaussian_width: 0.5,
+                    density: density,
+                }, &radial_basis).unwrap();
+
+                let rij = 2.3;
+                let delta = 1e-6;
+
+                let shape = (max_angular + 1, max_radial);
+                let mut values = Array2::from_elem(shape, 0.0);
+                let mut values_plus = Array2::from_elem(shape, 0.0);
+                let mut values_minus = Array2::from_elem(shape, 0.0);
+                let mut gradients = Array2::from_elem(shape, 0.0);
+                numerical.compute(rij, values.view_mut(), Some(gradients.view_mut()));
+                numerical.compute(rij + delta, values_plus.view_mut(), None);
+                numerical.compute(rij - delta, values_minus.view_mut(), None);
+
+                let finite_differences = (&values_plus - &values_minus) / (2.0 * delta);
+                assert_relative_eq!(
+                    finite_differences, gradients, epsilon=1e-8, max_relative=1e-6
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn gradients_near_zero() {
+        let max_radial = 5;
+        let max_angular = 4;
+        let numerical = SoapRadialIntegralNumerical::new(SoapRadialIntegralNumericalParameters {
+            max_radial: max_radial,
+            max_angular: max_angular,
+            cutoff: 4.0,
+            atomic_gaussian_width: 0.5,
+            density: AtomicDensity::Slater { decay_length: 0.4 },
+        }, &RadialBasis::gto()).unwrap();
+
+        let shape = (max_angular + 1, max_radial);
+        let mut values = Array2::from_elem(shape, 0.0);
+        let mut values_plus = Array2::from_elem(shape, 0.0);
+        let mut gradients = Array2::from_elem(shape, 0.0);
+        let mut gradients_plus = Array2::from_elem(shape, 0.0);
+        numerical.compute(0.0, values.view_mut(), Some(gradients.view_mut()));
+        numerical.compute(1e-6, values_plus.view_mut(), Some(gradients_plus.view_mut()));
+
+        assert_relative_eq!(values, values_plus, epsilon=1e-5, max_relative=1e-5);
+        assert_relative_eq!(gradients, gradients_plus, epsilon=1e-5, max_relative=1e-5);
+    }
+}
@@ -1,8 +1,10 @@
+use std::sync::Arc;
+
 use ndarray::{Array2, ArrayViewMut2};
 
 use super::SoapRadialIntegral;
-use crate::math::{HermitCubicSpline, SplineParameters, HermitSplinePoint};
-use crate::calculators::radial_basis::SplinePoint;
+use crate::math::{HermitCubicSpline, SplineParameters, cached_spline, HermitSplinePoint, SplineAccuracy};
+use crate::calculators::radial_basis::{SplinePoint, spline_points};
 use crate::Error;
 
 /// `SoapRadialIntegralSpline` allows to evaluate another radial integral
@@ -13,7 +15,7 @@ use crate::Error;
 ///
 /// [splines-wiki]: https://en.wikipedia.org/wiki/Cubic_Hermite_spline
 pub struct SoapRadialIntegralSpline {
-    spline: HermitCubicSpline<ndarray::Ix2>,
+    spline: Arc<HermitCubicSpline<ndarray::Ix2>>,
 }
 
 /// Parameters for computing the radial integral using Hermit cubic splines
@@ -32,39 +34,44 @@ impl SoapRadialIntegralSpline {
     /// `radial_integral`. Points are added to the spline until the requested
     /// accuracy is reached. We consider that the accuracy is reached when
     /// either the mean absolute error or the mean relative error gets below the
-    /// `accuracy` threshold.
-    #[time_graph::instrument(name = "SoapRadialIntegralSpline::with_accuracy")]
+    /// corresponding `accuracy` threshold. A single `f64` can be used as
+    /// `accuracy` to set both thresholds at once.
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "SoapRadialIntegralSpline::with_accuracy"))]
     pub fn with_accuracy(
         parameters: SoapRadialIntegralSplineParameters,
-        accuracy: f64,
+        accuracy: impl Into<SplineAccuracy>,
         radial_integral: impl SoapRadialIntegral
     ) -> Result<SoapRadialIntegralSpline, Error> {
-        let shape_tuple = (parameters.max_angular + 1, parameters.max_radial);
-
-        let parameters = SplineParameters {
-            start: 0.0,
-            stop: parameters.cutoff,
-            shape: vec![parameters.max_angular + 1, parameters.max_radial],
-        };
-
-        let spline = HermitCubicSpline::with_accuracy(
-            accuracy,
-            parameters,
-            |x| {
-                let mut values = Array2::from_elem(shape_tuple, 0.0);
-                let mut gradients = Array2::from_elem(shape_tuple, 0.0);
-                radial_integral.compute(x, values.view_mut(), Some(gradients.view_mut()));
-                (values, gradients)
-            },
-        )?;
+        let spline = create_spline(parameters, accuracy.into(), &radial_integral)?;
+        return Ok(SoapRadialIntegralSpline { spline: Arc::new(spline) });
+    }
 
+    /// Same as `SoapRadialIntegralSpline::with_accuracy`, but re-using the
+    /// spline from other calculators with the same parameters if possible.
+    ///
+    /// `description` must uniquely identify the `radial_integral` (including
+    /// all of its parameters and the atomic density), and is used together
+    /// with `parameters` and `accuracy` as the key in the process-wide cache
+    /// of splines.
+    pub(crate) fn with_accuracy_cached(
+        description: &str,
+        parameters: SoapRadialIntegralSplineParameters,
+        accuracy: SplineAccuracy,
+        radial_integral: impl SoapRadialIntegral
+    ) -> Result<SoapRadialIntegralSpline, Error> {
+        let key = format!("SOAP: {}, {:?}, {:?}", description, parameters, accuracy);
+        let spline = cached_spline(key, || create_spline(parameters, accuracy, &radial_integral))?;
         return Ok(SoapRadialIntegralSpline { spline });
     }
 
+    /// Create a new `SoapRadialIntegralSpline` from user-provided spline
+    /// points. The points are checked for consistency before creating the
+    /// spline, see `validate_tabulated` for more information.
     pub fn from_tabulated(
         parameters: SoapRadialIntegralSplineParameters,
         spline_points: Vec<SplinePoint>
     ) -> Result<SoapRadialIntegralSpline, Error> {
+        validate_tabulated(&parameters, &spline_points)?;
 
         let spline_parameters = SplineParameters {
             start: 0.0,
@@ -84,12 +91,171 @@ impl SoapRadialIntegralSpline {
         }
 
         let spline = HermitCubicSpline::new(spline_parameters, new_spline_points);
-        return Ok(SoapRadialIntegralSpline{spline});
+        return Ok(SoapRadialIntegralSpline { spline: Arc::new(spline) });
+    }
+
+    /// Get the control points of this spline. These can be used to create a
+    /// `RadialBasis::TabulatedRadialIntegral`, for example to store a splined
+    /// version of the built-in radial integrals.
+    pub fn points(&self) -> Vec<SplinePoint> {
+        return spline_points(&self.spline);
     }
 }
 
+/// Create a spline for the given `radial_integral`, see
+/// `SoapRadialIntegralSpline::with_accuracy`
+fn create_spline(
+    parameters: SoapRadialIntegralSplineParameters,
+    accuracy: SplineAccuracy,
+    radial_integral: &impl SoapRadialIntegral,
+) -> Result<HermitCubicSpline<ndarray::Ix2>, Error> {
+    let shape_tuple = (parameters.max_angular + 1, parameters.max_radial);
+
+    let parameters = SplineParameters {
+        start: 0.0,
+        stop: parameters.cutoff,
+        shape: vec![parameters.max_angular + 1, parameters.max_radial],
+    };
+
+    return HermitCubicSpline::with_accuracy(
+        accuracy,
+        parameters,
+        |x| {
+            let mut values = Array2::from_elem(shape_tuple, 0.0);
+            let mut gradients = Array2::from_elem(shape_tuple, 0.0);
+            radial_integral.compute(x, values.view_mut(), Some(gradients.view_mut()));
+            (values, gradients)
+        },
+    );
+}
+
+/// Check that the tabulated `spline_points` can be used to create a spline
+/// with the given `parameters`. This checks that
+///
+/// - values and derivatives have the right shape, and only contain finite
+///   numbers;
+/// - positions are strictly increasing, starting at 0 and going up to at least
+///   the cutoff;
+/// - values and derivatives are consistent with one another, i.e. the finite
+///   difference derivative between two consecutive points is close to the
+///   average of the derivatives at these points.
+#[allow(clippy::float_cmp)]
+fn validate_tabulated(
+    parameters: &SoapRadialIntegralSplineParameters,
+    spline_points: &[SplinePoint],
+) -> Result<(), Error> {
+    if spline_points.len() < 2 {
+        return Err(Error::InvalidParameter(format!(
+            "we need at least two points to create a tabulated radial integral, got {}",
+            spline_points.len()
+        )));
+    }
+
+    let shape = [parameters.max_angular + 1, parameters.max_radial];
+    let mut max_value = 0.0;
+    let mut max_derivative = 0.0;
+    for point in spline_points {
+        if point.values.shape() != shape || point.derivatives.shape() != shape {
+            return Err(Error::InvalidParameter(format!(
+                "invalid shape for tabulated radial integral at position {}: \
+                expected {:?}, got {:?} for values and {:?} for derivatives",
+                point.position, shape, point.values.shape(), point.derivatives.shape()
+            )));
+        }
+
+        if !point.position.is_finite() {
+            return Err(Error::InvalidParameter(
+                "tabulated radial integral contains a non-finite position".into()
+            ));
+        }
+
+        for (&value, &derivative) in point.values.iter().zip(point.derivatives.iter()) {
+            if !value.is_finite() || !derivative.is_finite() {
+                return Err(Error::InvalidParameter(format!(
+                    "tabulated radial integral contains non-finite values or \
+                    derivatives at position {}", point.position
+                )));
+            }
+            max_value = f64::max(max_value, value.abs());
+            max_derivative = f64::max(max_derivative, derivative.abs());
+        }
+    }
+
+    let first = spline_points[0].position;
+    if first != 0.0 {
+        return Err(Error::InvalidParameter(format!(
+            "tabulated radial integral must start at position 0, got {}", first
+        )));
+    }
+
+    let last = spline_points[spline_points.len() - 1].position;
+    if last < parameters.cutoff {
+        return Err(Error::InvalidParameter(format!(
+            "tabulated radial integral must cover positions up to the cutoff \
+            ({}), but the last point is at {}", parameters.cutoff, last
+        )));
+    }
+
+    for window in spline_points.windows(2) {
+        if window[1].position <= window[0].position {
+            return Err(Error::InvalidParameter(format!(
+                "positions in tabulated radial integral must be strictly \
+                increasing, got {} after {}", window[1].position, window[0].position
+            )));
+        }
+    }
+
+    // Check the consistency of values and derivatives for each function
+    // separately. Integrating the derivatives with the trapezoidal rule should
+    // give back the change in values between consecutive points, up to the
+    // integration error. We compare the accumulated error to the total
+    // variation of the function, which makes the check insensitive to local
+    // extrema and to the behavior of the functions close to 0.
+    let absolute_tolerance = 1e-8 * (max_value + max_derivative * last);
+    for l in 0..shape[0] {
+        for n in 0..shape[1] {
+            let mut mismatch = 0.0;
+            let mut variation = 0.0;
+            let mut worst = (0.0, 0);
+            for (k, window) in spline_points.windows(2).enumerate() {
+                let (point, next) = (&window[0], &window[1]);
+                let delta = next.position - point.position;
+
+                let change = next.values[[l, n]] - point.values[[l, n]];
+                let integrated = 0.5 * delta * (point.derivatives[[l, n]] + next.derivatives[[l, n]]);
+
+                let error = (change - integrated).abs();
+                if error > worst.0 {
+                    worst = (error, k);
+                }
+
+                mismatch += error;
+                variation += change.abs();
+            }
+
+            if mismatch > 0.25 * variation + absolute_tolerance {
+                let point = &spline_points[worst.1];
+                let next = &spline_points[worst.1 + 1];
+                let delta = next.position - point.position;
+                return Err(Error::InvalidParameter(format!(
+                    "values and derivatives in tabulated radial integral are \
+                    inconsistent for l={} and n={}: the largest difference is \
+                    between positions {} and {}, where the finite difference \
+                    derivative is {} but the average of the given derivatives \
+                    is {}",
+                    l, n, point.position, next.position,
+                    (next.values[[l, n]] - point.values[[l, n]]) / delta,
+                    0.5 * (point.derivatives[[l, n]] + next.derivatives[[l, n]]),
+                )));
+            }
+        }
+    }
+
+    return Ok(());
+}
+
 impl SoapRadialIntegral for SoapRadialIntegralSpline {
-    #[time_graph::instrument(name = "SplinedRadialIntegral::compute")]
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "SplinedRadialIntegral::compute"))]
     fn compute(&self, x: f64, values: ArrayViewMut2<f64>, gradients: Option<ArrayViewMut2<f64>>) {
         self.spline.compute(x, values, gradients);
     }
@@ -101,6 +267,7 @@ mod tests {
 
     use super::*;
     use super::super::{SoapRadialIntegralGto, SoapRadialIntegralGtoParameters};
+    use crate::calculators::radial_basis::{JsonArray2, GtoOrthonormalization};
 
     #[test]
     fn high_accuracy() {
@@ -117,6 +284,8 @@ mod tests {
             max_angular: parameters.max_angular,
             cutoff: parameters.cutoff,
             atomic_gaussian_width: 0.5,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         }).unwrap();
 
         // this test only check that this code runs without crashing
@@ -138,6 +307,8 @@ mod tests {
             max_angular: parameters.max_angular,
             cutoff: parameters.cutoff,
             atomic_gaussian_width: 0.5,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
         }).unwrap();
 
         // even with very bad accuracy, we want the gradients of the spline to
@@ -161,4 +332,85 @@ mod tests {
             epsilon=delta, max_relative=1e-6
         );
     }
+
+    #[test]
+    fn tabulated_roundtrip() {
+        let max_radial = 6;
+        let max_angular = 4;
+        let parameters = SoapRadialIntegralSplineParameters {
+            max_radial: max_radial,
+            max_angular: max_angular,
+            cutoff: 4.5,
+        };
+
+        let gto = SoapRadialIntegralGto::new(SoapRadialIntegralGtoParameters {
+            max_radial: parameters.max_radial,
+            max_angular: parameters.max_angular,
+            cutoff: parameters.cutoff,
+            atomic_gaussian_width: 0.5,
+            sigmas: None,
+            orthonormalization: GtoOrthonormalization::Lowdin {},
+        }).unwrap();
+
+        let spline = SoapRadialIntegralSpline::with_accuracy(parameters, 1e-8, gto.clone()).unwrap();
+        let tabulated = SoapRadialIntegralSpline::from_tabulated(parameters, spline.points()).unwrap();
+
+        // the same can be done with an arbitrary function
+        let points = crate::calculators::generate_splines(max_radial, max_angular, 4.5, 1e-8, |r, values, derivatives| {
+            gto.compute(r, values, Some(derivatives));
+        }).unwrap();
+        let generated = SoapRadialIntegralSpline::from_tabulated(parameters, points).unwrap();
+
+        let shape = (max_angular + 1, max_radial);
+        let mut expected = Array2::from_elem(shape, 0.0);
+        let mut values = Array2::from_elem(shape, 0.0);
+        let mut generated_values = Array2::from_elem(shape, 0.0);
+        for &r in &[0.0, 0.3, 1.2, 2.76, 4.1] {
+            spline.compute(r, expected.view_mut(), None);
+            tabulated.compute(r, values.view_mut(), None);
+            generated.compute(r, generated_values.view_mut(), None);
+
+            assert_eq!(values, expected);
+            assert_eq!(generated_values, expected);
+        }
+    }
+
+    #[test]
+    fn invalid_tabulated() {
+        let parameters = SoapRadialIntegralSplineParameters {
+            max_radial: 2,
+            max_angular: 1,
+            cutoff: 3.0,
+        };
+
+        let points = |positions: &[f64], derivative_factor: f64| {
+            positions.iter().map(|&r| SplinePoint {
+                position: r,
+                values: JsonArray2(Array2::from_elem((2, 2), f64::sin(r))),
+                derivatives: JsonArray2(Array2::from_elem((2, 2), derivative_factor * f64::cos(r))),
+            }).collect::<Vec<_>>()
+        };
+
+        let positions = (0..=30).map(|i| 0.1 * i as f64).collect::<Vec<_>>();
+        SoapRadialIntegralSpline::from_tabulated(parameters, points(&positions, 1.0)).unwrap();
+
+        let error = SoapRadialIntegralSpline::from_tabulated(parameters, points(&positions, -1.0)).err().unwrap();
+        assert!(error.to_string().contains("values and derivatives in tabulated radial integral are inconsistent for l=0 and n=0"));
+
+        let error = SoapRadialIntegralSpline::from_tabulated(parameters, points(&positions[..20], 1.0)).err().unwrap();
+        assert_eq!(error.to_string(), "invalid parameter: tabulated radial integral must cover positions up to the cutoff (3), but the last point is at 1.9000000000000001");
+
+        let error = SoapRadialIntegralSpline::from_tabulated(parameters, points(&positions[1..], 1.0)).err().unwrap();
+        assert_eq!(error.to_string(), "invalid parameter: tabulated radial integral must start at position 0, got 0.1");
+
+        let mut unsorted = positions.clone();
+        unsorted.swap(4, 5);
+        let error = SoapRadialIntegralSpline::from_tabulated(parameters, points(&unsorted, 1.0)).err().unwrap();
+        assert!(error.to_string().contains("positions in tabulated radial integral must be strictly increasing"));
+
+        let mut nan = points(&positions, 1.0);
+        nan[3].values[[1, 0]] = f64::NAN;
+        let error = SoapRadialIntegralSpline::from_tabulated(parameters, nan).err().unwrap();
+        assert!(error.to_string().contains("tabulated radial integral contains non-finite values or derivatives"));
+    }
 }
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use equistore::{EmptyArray, TensorBlock, TensorMap};
 use equistore::{LabelValue, Labels, LabelsBuilder};
 
@@ -6,7 +8,7 @@ use crate::{CalculationOptions, Calculator, LabelsSelection};
 use crate::{Error, System};
 
 use super::SphericalExpansionParameters;
-use super::{CutoffFunction, RadialScaling, SphericalExpansion};
+use super::{AtomicDensity, CutoffFunction, InnerCutoff, RadialScaling, SphericalExpansion};
 use crate::calculators::radial_basis::RadialBasis;
 
 use crate::labels::AtomCenteredSamples;
@@ -31,6 +33,13 @@ pub struct RadialSpectrumParameters {
     pub max_radial: usize,
     /// Width of the atom-centered gaussian creating the atomic density
     pub atomic_gaussian_width: f64,
+    /// Width of the atom-centered gaussian to use for specific neighbor
+    /// species, overriding `atomic_gaussian_width`
+    #[serde(default)]
+    pub atomic_gaussian_width_by_species: Option<BTreeMap<i32, f64>>,
+    /// shape of the atomic density placed on each neighbor
+    #[serde(default)]
+    pub density: AtomicDensity,
     /// Weight of the central atom contribution to the
     /// features. If `1` the center atom contribution is weighted the same
     /// as any other contribution. If `0` the central atom does not
@@ -40,11 +49,29 @@ pub struct RadialSpectrumParameters {
     pub radial_basis: RadialBasis,
     /// cutoff function used to smooth the behavior around the cutoff radius
     pub cutoff_function: CutoffFunction,
+    /// smooth inner cutoff, removing the contribution of neighbors closer than
+    /// a given distance to the central atom
+    #[serde(default)]
+    pub inner_cutoff: Option<InnerCutoff>,
     /// radial scaling can be used to reduce the importance of neighbor atoms
     /// further away from the center, usually improving the performance of the
     /// model
     #[serde(default)]
     pub radial_scaling: RadialScaling,
+    /// weight multiplying the density of atoms with the given species
+    #[serde(default)]
+    pub density_weight_by_species: Option<BTreeMap<i32, f64>>,
+    /// scale the density of each atom by the per-atom factor given by the
+    /// system (see `System::density_scaling`)
+    #[serde(default)]
+    pub use_system_density_scaling: bool,
+    /// use the per-atom width of the gaussian density given by the system (see
+    /// `System::atomic_gaussian_width`)
+    #[serde(default)]
+    pub use_system_atomic_gaussian_width: bool,
+    /// species of the neighbors to remove from the density
+    #[serde(default)]
+    pub excluded_neighbor_species: Vec<i32>,
 }
 
 /// Calculator implementing the Radial
@@ -66,11 +93,19 @@ impl SoapRadialSpectrum {
             cutoff: parameters.cutoff,
             max_radial: parameters.max_radial,
             max_angular: 0,
+            max_radial_by_angular: None,
             atomic_gaussian_width: parameters.atomic_gaussian_width,
+            atomic_gaussian_width_by_species: parameters.atomic_gaussian_width_by_species.clone(),
+            density: parameters.density,
             center_atom_weight: parameters.center_atom_weight,
             radial_basis: parameters.radial_basis.clone(),
             cutoff_function: parameters.cutoff_function,
-            radial_scaling: parameters.radial_scaling,
+            inner_cutoff: parameters.inner_cutoff,
+            radial_scaling: parameters.radial_scaling.clone(),
+            density_weight_by_species: parameters.density_weight_by_species.clone(),
+            use_system_density_scaling: parameters.use_system_density_scaling,
+            use_system_atomic_gaussian_width: parameters.use_system_atomic_gaussian_width,
+            excluded_neighbor_species: parameters.excluded_neighbor_species.clone(),
         };
 
         let spherical_expansion = SphericalExpansion::new(expansion_parameters)?;
@@ -125,10 +160,16 @@ impl CalculatorBase for SoapRadialSpectrum {
         serde_json::to_string(&self.parameters).expect("failed to serialize to JSON")
     }
 
+    fn cutoff(&self) -> Option<f64> {
+        Some(self.parameters.cutoff)
+    }
+
     fn keys(&self, systems: &mut [Box<dyn System>]) -> Result<equistore::Labels, Error> {
         let builder = CenterSingleNeighborsSpeciesKeys {
             cutoff: self.parameters.cutoff,
             self_pairs: true,
+            species_center: SpeciesFilter::Any,
+            species_neighbor: SpeciesFilter::NoneOf(self.parameters.excluded_neighbor_species.clone()),
         };
         return builder.keys(systems);
     }
@@ -202,7 +243,7 @@ impl CalculatorBase for SoapRadialSpectrum {
         return vec![properties; keys.count()];
     }
 
-    #[time_graph::instrument(name = "SoapRadialSpectrum::compute")]
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "SoapRadialSpectrum::compute"))]
     fn compute(&mut self, systems: &mut [Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
         assert_eq!(descriptor.keys().names(), ["species_center", "species_neighbor"]);
         let mut gradients = Vec::new();
@@ -242,9 +283,9 @@ impl CalculatorBase for SoapRadialSpectrum {
 
             if let Some(mut gradient) = block.gradient_mut("positions") {
                 let gradient_spx = block_spx.gradient("positions").expect("missing spherical expansion gradients");
-                debug_assert_eq!(gradient.samples(), gradient_spx.samples());
+                let gradient = gradient.data_mut();
 
-                let array = gradient.values_mut().to_array_mut();
+                let array = gradient.values.to_array_mut();
                 let array_spx = gradient_spx.values().to_array();
                 let shape = array_spx.shape();
                 // shape[2] is the m component
@@ -253,7 +294,20 @@ impl CalculatorBase for SoapRadialSpectrum {
                 let array_spx_reshaped = array_spx.view().into_shape(
                     (shape[0], shape[1], shape[3])
                 ).expect("wrong shape");
-                array.assign(&array_spx_reshaped);
+
+                let samples_spx = gradient_spx.samples();
+                if *gradient.samples == samples_spx {
+                    array.assign(&array_spx_reshaped);
+                } else {
+                    // the gradients were restricted to some atoms, and the
+                    // spherical expansion contains more gradient samples
+                    for (grad_sample_i, sample) in gradient.samples.iter().enumerate() {
+                        let grad_sample_spx_i = samples_spx.position(sample).expect("missing spherical expansion gradient sample");
+                        array.index_axis_mut(ndarray::Axis(0), grad_sample_i).assign(
+                            &array_spx_reshaped.index_axis(ndarray::Axis(0), grad_sample_spx_i)
+                        );
+                    }
+                }
             }
 
             if let Some(mut gradient) = block.gradient_mut("cell") {
@@ -292,10 +346,17 @@ mod tests {
             cutoff: 3.5,
             max_radial: 6,
             atomic_gaussian_width: 0.3,
+            atomic_gaussian_width_by_species: None,
+            density: AtomicDensity::Gaussian {},
             center_atom_weight: 1.0,
             radial_basis: RadialBasis::splined_gto(1e-8),
             radial_scaling: RadialScaling::None {},
             cutoff_function: CutoffFunction::ShiftedCosine { width: 0.5 },
+            inner_cutoff: None,
+            density_weight_by_species: None,
+            use_system_density_scaling: false,
+            use_system_atomic_gaussian_width: false,
+            excluded_neighbor_species: Vec::new(),
         }
     }
 
@@ -322,7 +383,7 @@ mod tests {
         ) as Box<dyn CalculatorBase>);
 
         let system = test_system("water");
-        let options = crate::calculators::tests_utils::FinalDifferenceOptions {
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
             displacement: 1e-6,
             max_relative: 5e-5,
             epsilon: 1e-16,
@@ -337,7 +398,7 @@ mod tests {
         ) as Box<dyn CalculatorBase>);
 
         let system = test_system("water");
-        let options = crate::calculators::tests_utils::FinalDifferenceOptions {
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
             displacement: 1e-6,
             max_relative: 5e-5,
             epsilon: 1e-16,
@@ -1,7 +1,9 @@
+use std::cell::RefCell;
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use ndarray::s;
 use rayon::prelude::*;
+use thread_local::ThreadLocal;
 
 use equistore::{LabelsBuilder, Labels, LabelValue, TensorBlockRefMut};
 use equistore::TensorMap;
@@ -20,6 +22,10 @@ use super::spherical_expansion_pair::{GradientsOptions, PairContribution};
 use super::super::{split_tensor_map_by_system, array_mut_for_system};
 
 
+/// Displacement used to compute the second derivatives of the spherical
+/// expansion by finite differences of the analytical gradients
+const HESSIAN_DISPLACEMENT: f64 = 1e-5;
+
 /// The actual calculator used to compute SOAP spherical expansion coefficients
 #[derive(Debug)]
 pub struct SphericalExpansion {
@@ -27,6 +33,8 @@ pub struct SphericalExpansion {
     by_pair: SphericalExpansionByPair,
     /// Cache for (-1)^l values
     m_1_pow_l: Vec<f64>,
+    /// Per-thread memory buffers, re-used between systems
+    scratch: ThreadLocal<RefCell<ScratchBuffers>>,
 }
 
 impl SphericalExpansion {
@@ -39,6 +47,7 @@ impl SphericalExpansion {
         return Ok(SphericalExpansion {
             by_pair: SphericalExpansionByPair::new(parameters)?,
             m_1_pow_l,
+            scratch: ThreadLocal::new(),
         });
     }
 
@@ -48,8 +57,14 @@ impl SphericalExpansion {
     fn do_self_contributions(&mut self, systems: &[Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
         debug_assert_eq!(descriptor.keys().names(), ["spherical_harmonics_l", "species_center", "species_neighbor"]);
 
-        let self_contribution = self.by_pair.self_contribution();
+        let density_weights = systems.iter()
+            .map(|system| self.by_pair.density_weights(&**system))
+            .collect::<Result<Vec<_>, _>>()?;
+        let atomic_gaussian_widths = systems.iter()
+            .map(|system| self.by_pair.atomic_gaussian_widths(&**system))
+            .collect::<Result<Vec<_>, _>>()?;
 
+        let mut self_contributions = BTreeMap::new();
         for (key, mut block) in descriptor.iter_mut() {
             let spherical_harmonics_l = key[0];
             let species_center = key[1];
@@ -82,8 +97,14 @@ impl SphericalExpansion {
                     continue;
                 }
 
+                let width = atomic_gaussian_widths[structure.usize()][center.usize()];
+                let self_contribution = self_contributions.entry((species_center, width.to_bits())).or_insert_with(|| {
+                    self.by_pair.self_contribution(species_center, width)
+                });
+
+                let weight = density_weights[structure.usize()][center.usize()];
                 for (property_i, &[n]) in block.properties.iter_fixed_size().enumerate() {
-                    array[[sample_i, 0, property_i]] += self_contribution.values[[0, n.usize()]];
+                    array[[sample_i, 0, property_i]] += weight * self_contribution.values[[0, n.usize()]];
                 }
             }
         }
@@ -99,6 +120,7 @@ impl SphericalExpansion {
         system: &dyn System,
         do_gradients: GradientsOptions,
         requested_centers: &BTreeSet<usize>,
+        scratch: &mut ScratchBuffers,
     ) -> Result<PairAccumulationResult, Error> {
         // pre-filter pairs to only include the ones containing at least one of
         // the requested atoms
@@ -111,6 +133,8 @@ impl SphericalExpansion {
 
         let system_size = system.size()?;
         let species = system.species()?;
+        let density_weights = self.by_pair.density_weights(system)?;
+        let atomic_gaussian_widths = self.by_pair.atomic_gaussian_widths(system)?;
 
         let mut species_mapping = BTreeMap::new();
         for &s in species {
@@ -118,6 +142,16 @@ impl SphericalExpansion {
             species_mapping.entry(s).or_insert(next_idx);
         }
 
+        // can we get the contribution of all reversed pairs from the
+        // contribution of the corresponding pair? This is the case if all
+        // atoms share the same atomic density, and if the contribution of the
+        // pair is always computed (i.e. no neighbor species are excluded).
+        let parameters = self.by_pair.parameters();
+        let all_pairs_symmetric = parameters.excluded_neighbor_species.is_empty()
+            && species.iter().zip(&atomic_gaussian_widths).all(|(&s, &width)| {
+                self.by_pair.same_density_for_pair(s, width, species[0], atomic_gaussian_widths[0])
+            });
+
         let inverse_cell = if do_gradients.cell {
             let cell = system.cell()?;
             if cell.shape() == CellShape::Infinite {
@@ -137,45 +171,72 @@ impl SphericalExpansion {
 
         let max_angular = self.by_pair.parameters().max_angular;
         let max_radial = self.by_pair.parameters().max_radial;
-        let mut contribution = PairContribution::new(max_radial, max_angular, do_gradients.either());
 
         // total number of joined (l, m) indices
         let lm_shape = (max_angular + 1) * (max_angular + 1);
         let mut result = PairAccumulationResult {
-            values: ndarray::Array4::from_elem(
+            values: zeros(
+                &mut scratch.values,
                 (species_mapping.len(), requested_centers.len(), lm_shape, max_radial),
-                0.0
             ),
             positions_gradients_by_pair: if do_gradients.positions {
                 let shape = (pairs_count, 3, lm_shape, max_radial);
-                Some(ndarray::Array4::from_elem(shape, 0.0))
+                Some(zeros(&mut scratch.positions_gradients_by_pair, shape))
+            } else {
+                None
+            },
+            positions_gradients_by_reversed_pair: if do_gradients.positions && !all_pairs_symmetric {
+                let shape = (pairs_count, 3, lm_shape, max_radial);
+                Some(zeros(&mut scratch.positions_gradients_by_reversed_pair, shape))
             } else {
                 None
             },
             positions_gradients_self: if do_gradients.positions {
                 let shape = (species_mapping.len(), requested_centers.len(), 3, lm_shape, max_radial);
-                Some(ndarray::Array5::from_elem(shape, 0.0))
+                Some(zeros(&mut scratch.positions_gradients_self, shape))
             } else {
                 None
             },
             cell_gradients: if do_gradients.cell {
-                Some(ndarray::Array6::from_elem(
+                Some(zeros(
+                    &mut scratch.cell_gradients,
                     (species_mapping.len(), requested_centers.len(), 3, 3, lm_shape, max_radial),
-                    0.0)
-                )
+                ))
             } else {
                 None
             },
             species_mapping,
             centers_mapping,
-            pair_to_pair_ids: HashMap::new(),
+            pair_to_pair_ids: std::mem::take(&mut scratch.pair_to_pair_ids),
+            density_weights,
         };
+        result.pair_to_pair_ids.clear();
+
+        let contribution = scratch.pair_contribution(max_radial, max_angular, do_gradients.either());
 
         for (pair_id, pair) in pairs.iter().filter(pair_should_contribute).enumerate() {
             debug_assert!(requested_centers.contains(&pair.first) || requested_centers.contains(&pair.second));
 
+            // atoms with an excluded species do not contribute to the density
+            // around the other atom in the pair, so we skip the corresponding
+            // calculations entirely
+            let first_is_neighbor = parameters.is_neighbor_species_included(species[pair.first]);
+            let second_is_neighbor = parameters.is_neighbor_species_included(species[pair.second]);
+            if !first_is_neighbor && !second_is_neighbor {
+                continue;
+            }
+
             let direction = pair.vector / pair.distance;
-            self.by_pair.compute_for_pair(pair.distance, direction, do_gradients, &mut contribution);
+            if second_is_neighbor {
+                self.by_pair.compute_for_pair(
+                    pair.distance,
+                    direction,
+                    species[pair.second],
+                    atomic_gaussian_widths[pair.second],
+                    do_gradients,
+                    contribution
+                );
+            }
 
             let inverse_cell_pair_vector = Vector3D::new(
                 pair.vector[0] * inverse_cell[0][0] + pair.vector[1] * inverse_cell[1][0] + pair.vector[2] * inverse_cell[2][0],
@@ -183,7 +244,7 @@ impl SphericalExpansion {
                 pair.vector[0] * inverse_cell[0][2] + pair.vector[1] * inverse_cell[1][2] + pair.vector[2] * inverse_cell[2][2],
             );
 
-            if let Some(mapped_center) = result.centers_mapping[pair.first] {
+            if let Some(mapped_center) = result.centers_mapping[pair.first].filter(|_| second_is_neighbor) {
                 // add the pair contribution to the atomic environnement
                 // corresponding to the **first** atom in the pair
                 let neighbor_i = pair.second;
@@ -193,19 +254,23 @@ impl SphericalExpansion {
                     .push(pair_id);
 
                 let species_neighbor_i = result.species_mapping[&species[neighbor_i]];
+                let weight = result.density_weights[neighbor_i];
                 let mut values = result.values.slice_mut(s![species_neighbor_i, mapped_center, .., ..]);
-                values += &contribution.values;
+                values.scaled_add(weight, &contribution.values);
 
 
                 if let Some(ref contribution_gradients) = contribution.gradients {
                     if let Some(ref mut positions_gradients) = result.positions_gradients_by_pair {
+                        // the density weight of the neighbor is applied in
+                        // `position_gradients_to_equistore`, since this
+                        // array is shared between both directions of the pair
                         let gradients = &mut positions_gradients.slice_mut(s![pair_id, .., .., ..]);
                         gradients.assign(contribution_gradients);
                     }
 
                     if let Some(ref mut positions_gradients) = result.positions_gradients_self {
                         let mut gradients = positions_gradients.slice_mut(s![species_neighbor_i, mapped_center, .., .., ..]);
-                        gradients -= contribution_gradients;
+                        gradients.scaled_add(-weight, contribution_gradients);
                     }
 
                     if let Some(ref mut cell_gradients) = result.cell_gradients {
@@ -215,7 +280,7 @@ impl SphericalExpansion {
 
                         for spatial_1 in 0..3 {
                             for spatial_2 in 0..3 {
-                                let inverse_cell_pair_vector_2 = inverse_cell_pair_vector[spatial_2];
+                                let inverse_cell_pair_vector_2 = weight * inverse_cell_pair_vector[spatial_2];
 
                                 let mut lm_index = 0;
                                 for spherical_harmonics_l in 0..=max_angular {
@@ -241,9 +306,10 @@ impl SphericalExpansion {
                 }
             }
 
-            if pair.first == pair.second {
+            if pair.first == pair.second || !first_is_neighbor {
                 // do not compute for the reversed pair if the pair is
-                // between an atom and its image
+                // between an atom and its image, or if the first atom does
+                // not contribute to the density
                 continue;
             }
 
@@ -257,20 +323,40 @@ impl SphericalExpansion {
                     .push(pair_id);
 
 
-                contribution.inverse_pair(&self.m_1_pow_l);
+                let width_first = atomic_gaussian_widths[pair.first];
+                let width_second = atomic_gaussian_widths[pair.second];
+                if second_is_neighbor && self.by_pair.same_density_for_pair(species[pair.first], width_first, species[pair.second], width_second) {
+                    contribution.inverse_pair(&self.m_1_pow_l);
+                } else {
+                    self.by_pair.compute_for_pair(
+                        pair.distance,
+                        -direction,
+                        species[pair.first],
+                        width_first,
+                        do_gradients,
+                        contribution
+                    );
+                }
 
                 let species_neighbor_i = result.species_mapping[&species[neighbor_i]];
+                let weight = result.density_weights[neighbor_i];
 
                 let mut values = result.values.slice_mut(s![species_neighbor_i, mapped_center, .., ..]);
-                values += &contribution.values;
+                values.scaled_add(weight, &contribution.values);
 
                 if let Some(ref contribution_gradients) = contribution.gradients {
                     // we don't add second->first pair to positions_gradient_by_pair,
-                    // instead handling this in position_gradients_to_equistore
+                    // instead handling this in position_gradients_to_equistore.
+                    // If the reversed pair is not symmetric, we need to store
+                    // its gradients separately.
+                    if let Some(ref mut positions_gradients) = result.positions_gradients_by_reversed_pair {
+                        let gradients = &mut positions_gradients.slice_mut(s![pair_id, .., .., ..]);
+                        gradients.assign(contribution_gradients);
+                    }
 
                     if let Some(ref mut positions_gradients) = result.positions_gradients_self {
                         let mut gradients = positions_gradients.slice_mut(s![species_neighbor_i, mapped_center, .., .., ..]);
-                        gradients -= contribution_gradients;
+                        gradients.scaled_add(-weight, contribution_gradients);
                     }
 
                     if let Some(ref mut cell_gradients) = result.cell_gradients {
@@ -282,7 +368,7 @@ impl SphericalExpansion {
 
                         for spatial_1 in 0..3 {
                             for spatial_2 in 0..3 {
-                                let inverse_cell_pair_vector_2 = inverse_cell_pair_vector[spatial_2];
+                                let inverse_cell_pair_vector_2 = weight * inverse_cell_pair_vector[spatial_2];
 
                                 let mut lm_index = 0;
                                 for spherical_harmonics_l in 0..=max_angular {
@@ -307,6 +393,167 @@ impl SphericalExpansion {
         return Ok(result);
     }
 
+    /// For one system, compute the second derivatives of the contribution of
+    /// each pair to the spherical expansion with respect to the pair vector.
+    ///
+    /// The second derivatives are obtained with central finite differences of
+    /// the analytical gradients of each pair contribution.
+    fn accumulate_positions_hessian(
+        &self,
+        system: &dyn System,
+        requested_centers: &BTreeSet<usize>,
+    ) -> Result<PositionsHessianResult, Error> {
+        let species = system.species()?;
+        let density_weights = self.by_pair.density_weights(system)?;
+        let atomic_gaussian_widths = self.by_pair.atomic_gaussian_widths(system)?;
+
+        let max_angular = self.by_pair.parameters().max_angular;
+        let max_radial = self.by_pair.parameters().max_radial;
+        let lm_shape = (max_angular + 1) * (max_angular + 1);
+
+        let do_gradients = GradientsOptions {
+            positions: true,
+            cell: false,
+        };
+        let mut contribution = PairContribution::new(max_radial, max_angular, true);
+        let mut compute_gradients = |vector: Vector3D, neighbor: usize| {
+            let distance = vector.norm();
+            self.by_pair.compute_for_pair(
+                distance,
+                vector / distance,
+                species[neighbor],
+                atomic_gaussian_widths[neighbor],
+                do_gradients,
+                &mut contribution
+            );
+            return contribution.gradients.as_ref().expect("missing gradients").clone();
+        };
+
+        let mut result = PositionsHessianResult {
+            by_pair: HashMap::new(),
+            by_center: HashMap::new(),
+        };
+
+        let mut pair_hessian = ndarray::Array4::from_elem((3, 3, lm_shape, max_radial), 0.0);
+        for pair in system.pairs()? {
+            if pair.first == pair.second {
+                // the pair between an atom and its own periodic image does
+                // not depend on the positions
+                continue;
+            }
+
+            for (center_i, neighbor_i, vector) in [(pair.first, pair.second, pair.vector), (pair.second, pair.first, -pair.vector)] {
+                if !requested_centers.contains(&center_i) {
+                    continue;
+                }
+
+                if !self.by_pair.parameters().is_neighbor_species_included(species[neighbor_i]) {
+                    continue;
+                }
+
+                for spatial_2 in 0..3 {
+                    let mut displaced = vector;
+                    displaced[spatial_2] += HESSIAN_DISPLACEMENT;
+                    let gradients_plus = compute_gradients(displaced, neighbor_i);
+
+                    displaced[spatial_2] -= 2.0 * HESSIAN_DISPLACEMENT;
+                    let gradients_minus = compute_gradients(displaced, neighbor_i);
+
+                    let finite_difference = (gradients_plus - gradients_minus) / (2.0 * HESSIAN_DISPLACEMENT);
+                    pair_hessian.slice_mut(s![.., spatial_2, .., ..]).assign(&finite_difference);
+                }
+
+                // the exact second derivatives are symmetric, remove the
+                // asymmetry coming from the finite differences
+                for spatial_1 in 0..3 {
+                    for spatial_2 in (spatial_1 + 1)..3 {
+                        let symmetric = 0.5 * (
+                            &pair_hessian.slice(s![spatial_1, spatial_2, .., ..])
+                            + &pair_hessian.slice(s![spatial_2, spatial_1, .., ..])
+                        );
+                        pair_hessian.slice_mut(s![spatial_1, spatial_2, .., ..]).assign(&symmetric);
+                        pair_hessian.slice_mut(s![spatial_2, spatial_1, .., ..]).assign(&symmetric);
+                    }
+                }
+
+                let weight = density_weights[neighbor_i];
+                result.by_pair.entry((center_i, neighbor_i))
+                    .or_insert_with(|| ndarray::Array4::from_elem(pair_hessian.raw_dim(), 0.0))
+                    .scaled_add(weight, &pair_hessian);
+
+                result.by_center.entry((center_i, species[neighbor_i]))
+                    .or_insert_with(|| ndarray::Array4::from_elem(pair_hessian.raw_dim(), 0.0))
+                    .scaled_add(weight, &pair_hessian);
+            }
+        }
+
+        return Ok(result);
+    }
+
+    /// For one system, compute the contribution of each neighbor to the
+    /// spherical expansion around the requested centers, summed over periodic
+    /// images and without the density scaling of the neighbor. These are the
+    /// gradients of the spherical expansion w.r.t. the density scaling of the
+    /// neighbor.
+    ///
+    /// The result is indexed by `(center, neighbor)`, and the shape of each
+    /// array is [lm_index, n].
+    fn accumulate_density_scaling_gradients(
+        &self,
+        system: &dyn System,
+        requested_centers: &BTreeSet<usize>,
+    ) -> Result<HashMap<(usize, usize), ndarray::Array2<f64>>, Error> {
+        let species = system.species()?;
+        let atomic_gaussian_widths = self.by_pair.atomic_gaussian_widths(system)?;
+
+        let max_angular = self.by_pair.parameters().max_angular;
+        let max_radial = self.by_pair.parameters().max_radial;
+
+        let do_gradients = GradientsOptions {
+            positions: false,
+            cell: false,
+        };
+        let mut contribution = PairContribution::new(max_radial, max_angular, false);
+
+        let mut result = HashMap::new();
+        for pair in system.pairs()? {
+            let direction = pair.vector / pair.distance;
+
+            let mut directions = vec![(pair.first, pair.second, direction)];
+            if pair.first != pair.second {
+                // the pair between an atom and its own periodic image is only
+                // counted once, as in `accumulate_all_pairs`
+                directions.push((pair.second, pair.first, -direction));
+            }
+
+            for (center_i, neighbor_i, direction) in directions {
+                if !requested_centers.contains(&center_i) {
+                    continue;
+                }
+
+                if !self.by_pair.parameters().is_neighbor_species_included(species[neighbor_i]) {
+                    continue;
+                }
+
+                self.by_pair.compute_for_pair(
+                    pair.distance,
+                    direction,
+                    species[neighbor_i],
+                    atomic_gaussian_widths[neighbor_i],
+                    do_gradients,
+                    &mut contribution
+                );
+
+                let weight = self.by_pair.species_density_weight(species[neighbor_i]);
+                result.entry((center_i, neighbor_i))
+                    .or_insert_with(|| ndarray::Array2::from_elem(contribution.values.raw_dim(), 0.0))
+                    .scaled_add(weight, &contribution.values);
+            }
+        }
+
+        return Ok(result);
+    }
+
     /// Move the pre-computed spherical expansion data to a single equistore
     /// block
     #[allow(clippy::unused_self)]
@@ -450,14 +697,19 @@ impl SphericalExpansion {
 
                 for &pair_id in &result.pair_to_pair_ids[&(center_i.usize(), neighbor_i)] {
                     let pair = pairs[pair_id];
-                    let factor = if pair.first == center_i.usize() {
+                    let (factor, positions_gradients_by_pair) = if pair.first == center_i.usize() {
                         debug_assert_eq!(pair.second, neighbor_i);
-                        1.0
+                        (1.0, positions_gradients_by_pair)
                     } else {
                         debug_assert!(pair.second == center_i.usize());
                         debug_assert_eq!(pair.first, neighbor_i);
-                        -m_1_pow_l
+                        if let Some(ref reversed) = result.positions_gradients_by_reversed_pair {
+                            (1.0, reversed)
+                        } else {
+                            (-m_1_pow_l, positions_gradients_by_pair)
+                        }
                     };
+                    let factor = factor * result.density_weights[neighbor_i];
 
                     for spatial in 0..3 {
                         for m in 0..(2 * spherical_harmonics_l + 1) {
@@ -541,6 +793,129 @@ impl SphericalExpansion {
 
         return Ok(());
     }
+
+    /// Fill the gradients w.r.t. density scaling of a single equistore block,
+    /// from the contributions of each pair and the self contribution of the
+    /// center.
+    fn density_scaling_gradients_to_equistore(
+        &self,
+        key: &[LabelValue],
+        block: &mut TensorBlockRefMut,
+        system: &dyn System,
+        contributions: &HashMap<(usize, usize), ndarray::Array2<f64>>,
+    ) -> Result<(), Error> {
+        let atomic_gaussian_widths = self.by_pair.atomic_gaussian_widths(system)?;
+
+        let spherical_harmonics_l = key[0].usize();
+        let species_center = key[1];
+        let species_neighbor = key[2];
+        let lm_start = spherical_harmonics_l * spherical_harmonics_l;
+
+        let values_samples = block.samples();
+        let mut gradient = block.gradient_mut("density_scaling").expect("missing density scaling gradients");
+        let gradient = gradient.data_mut();
+        let mut array = array_mut_for_system(gradient.values);
+
+        for (grad_sample_i, &[sample_i, _, atom_i]) in gradient.samples.iter_fixed_size().enumerate() {
+            let center_i = values_samples[sample_i.usize()][1].usize();
+            let atom_i = atom_i.usize();
+
+            if let Some(contribution) = contributions.get(&(center_i, atom_i)) {
+                for m in 0..(2 * spherical_harmonics_l + 1) {
+                    for (property_i, [n]) in gradient.properties.iter_fixed_size().enumerate() {
+                        array[[grad_sample_i, m, property_i]] += contribution[[lm_start + m, n.usize()]];
+                    }
+                }
+            }
+
+            if atom_i == center_i && spherical_harmonics_l == 0 && species_center == species_neighbor {
+                // gradient of the center contribution, see `do_self_contributions`
+                let self_contribution = self.by_pair.self_contribution(
+                    species_center.i32(),
+                    atomic_gaussian_widths[center_i],
+                );
+                let weight = self.by_pair.species_density_weight(species_center.i32());
+
+                for (property_i, [n]) in gradient.properties.iter_fixed_size().enumerate() {
+                    array[[grad_sample_i, 0, property_i]] += weight * self_contribution.values[[0, n.usize()]];
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Fill the second derivatives w.r.t. positions of a single equistore
+    /// block from the per-pair second derivatives.
+    ///
+    /// Since the contribution of the `i-j` pair only depends on `r_j - r_i`,
+    /// the second derivatives w.r.t. `(i, i)` and `(j, j)` are equal to the
+    /// pair second derivatives, and the ones w.r.t. `(i, j)` and `(j, i)` are
+    /// the opposite of the pair second derivatives.
+    #[allow(clippy::unused_self)]
+    fn positions_hessian_to_equistore(
+        &self,
+        key: &[LabelValue],
+        block: &mut TensorBlockRefMut,
+        hessian: &PositionsHessianResult,
+    ) {
+        let spherical_harmonics_l = key[0].usize();
+        let species_neighbor = key[2].i32();
+        let lm_start = spherical_harmonics_l * spherical_harmonics_l;
+
+        let values_samples = block.samples();
+        let mut gradient = block.gradient_mut("positions_hessian").expect("missing positions hessian");
+        let gradient = gradient.data_mut();
+        let mut array = array_mut_for_system(gradient.values);
+
+        for (grad_sample_i, &[sample_i, _, atom_1, atom_2]) in gradient.samples.iter_fixed_size().enumerate() {
+            let center_i = values_samples[sample_i.usize()][1].usize();
+            let atom_1 = atom_1.usize();
+            let atom_2 = atom_2.usize();
+
+            let (factor, pair_hessian) = if atom_1 == center_i && atom_2 == center_i {
+                (1.0, hessian.by_center.get(&(center_i, species_neighbor)))
+            } else if atom_1 == atom_2 {
+                (1.0, hessian.by_pair.get(&(center_i, atom_1)))
+            } else if atom_1 == center_i {
+                (-1.0, hessian.by_pair.get(&(center_i, atom_2)))
+            } else if atom_2 == center_i {
+                (-1.0, hessian.by_pair.get(&(center_i, atom_1)))
+            } else {
+                // two different neighbors do not interact in the density
+                continue;
+            };
+
+            let pair_hessian = if let Some(pair_hessian) = pair_hessian {
+                pair_hessian
+            } else {
+                continue;
+            };
+
+            for spatial_1 in 0..3 {
+                for spatial_2 in 0..3 {
+                    for m in 0..(2 * spherical_harmonics_l + 1) {
+                        for (property_i, [n]) in gradient.properties.iter_fixed_size().enumerate() {
+                            array[[grad_sample_i, spatial_1, spatial_2, m, property_i]] += factor * pair_hessian[[spatial_1, spatial_2, lm_start + m, n.usize()]];
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Result of `accumulate_positions_hessian`, containing the second derivatives
+/// of the spherical expansion w.r.t. the pair vector, including the density
+/// weight of the neighbor. The shape of all arrays is [spatial_1, spatial_2,
+/// lm_index, n].
+struct PositionsHessianResult {
+    /// Second derivatives summed over all the pairs (including periodic
+    /// images) between a given `(center, neighbor)` couple of atoms
+    by_pair: HashMap<(usize, usize), ndarray::Array4<f64>>,
+    /// Second derivatives summed over all the pairs between a given center
+    /// and neighbors of a given species, indexed by `(center, species_neighbor)`
+    by_center: HashMap<(usize, i32), ndarray::Array4<f64>>,
 }
 
 /// Result of `accumulate_all_pairs`, summing over all pairs in a system
@@ -555,6 +930,13 @@ struct PairAccumulationResult {
     ///
     /// the shape is [pair_id, spatial, lm_index, n]
     positions_gradients_by_pair: Option<ndarray::Array4<f64>>,
+    /// gradients w.r.t. positions associated with each reversed pair (with
+    /// `pair.second` as the center). This is only used when some species have
+    /// different atomic densities, otherwise the gradients of the reversed
+    /// pair are obtained from `positions_gradients_by_pair`.
+    ///
+    /// the shape is [pair_id, spatial, lm_index, n]
+    positions_gradients_by_reversed_pair: Option<ndarray::Array4<f64>>,
     /// gradient of spherical expansion w.r.t. the position of the central atom
     ///
     /// this is separate from `positions_gradients_by_pair` because it can be
@@ -577,6 +959,70 @@ struct PairAccumulationResult {
     /// Two atoms can have more than one pair between them, so we need to be
     /// able store more than one pair id.
     pair_to_pair_ids: HashMap<(usize, usize), Vec<usize>>,
+    /// Scaling factor for the density of each atom in the system
+    density_weights: Vec<f64>,
+}
+
+impl PairAccumulationResult {
+    /// Give the memory used by this result back to `scratch`, to be re-used
+    /// for the next system
+    fn recycle(self, scratch: &mut ScratchBuffers) {
+        scratch.values = self.values.into_raw_vec();
+        if let Some(array) = self.positions_gradients_by_pair {
+            scratch.positions_gradients_by_pair = array.into_raw_vec();
+        }
+        if let Some(array) = self.positions_gradients_by_reversed_pair {
+            scratch.positions_gradients_by_reversed_pair = array.into_raw_vec();
+        }
+        if let Some(array) = self.positions_gradients_self {
+            scratch.positions_gradients_self = array.into_raw_vec();
+        }
+        if let Some(array) = self.cell_gradients {
+            scratch.cell_gradients = array.into_raw_vec();
+        }
+        scratch.pair_to_pair_ids = self.pair_to_pair_ids;
+    }
+}
+
+/// Memory buffers used by `accumulate_all_pairs`, kept around between the
+/// different systems computed on the same thread to reduce the number of
+/// allocations.
+#[derive(Debug, Default)]
+struct ScratchBuffers {
+    values: Vec<f64>,
+    positions_gradients_by_pair: Vec<f64>,
+    positions_gradients_by_reversed_pair: Vec<f64>,
+    positions_gradients_self: Vec<f64>,
+    cell_gradients: Vec<f64>,
+    pair_to_pair_ids: HashMap<(usize, usize), Vec<usize>>,
+    contribution: Option<PairContribution>,
+}
+
+impl ScratchBuffers {
+    /// Get a `PairContribution` with the right shape, re-using the existing
+    /// one if possible
+    fn pair_contribution(&mut self, max_radial: usize, max_angular: usize, do_gradients: bool) -> &mut PairContribution {
+        let lm_shape = (max_angular + 1) * (max_angular + 1);
+        let reusable = self.contribution.as_ref().map_or(false, |contribution| {
+            contribution.values.dim() == (lm_shape, max_radial) && contribution.gradients.is_some() == do_gradients
+        });
+
+        if !reusable {
+            self.contribution = Some(PairContribution::new(max_radial, max_angular, do_gradients));
+        }
+
+        return self.contribution.as_mut().expect("the contribution was just created");
+    }
+}
+
+/// Create an array filled with zeros with the given `shape`, using the memory
+/// from `buffer`
+fn zeros<Sh: ndarray::ShapeBuilder>(buffer: &mut Vec<f64>, shape: Sh) -> ndarray::Array<f64, Sh::Dim> {
+    let shape = shape.into_shape();
+    let mut buffer = std::mem::take(buffer);
+    buffer.clear();
+    buffer.resize(shape.size(), 0.0);
+    return ndarray::Array::from_shape_vec(shape, buffer).expect("the buffer has the right size");
 }
 
 impl CalculatorBase for SphericalExpansion {
@@ -588,10 +1034,32 @@ impl CalculatorBase for SphericalExpansion {
         serde_json::to_string(self.by_pair.parameters()).expect("failed to serialize to JSON")
     }
 
+    fn cutoff(&self) -> Option<f64> {
+        Some(self.by_pair.parameters().cutoff)
+    }
+
+    fn work_estimate(&self, systems: &mut [Box<dyn System>]) -> Result<Option<usize>, Error> {
+        let parameters = self.by_pair.parameters();
+        // each pair contributes to all the (n, l, m) coefficients
+        let basis_size = (0..=parameters.max_angular)
+            .map(|l| (2 * l + 1) * parameters.max_radial_for_angular(l))
+            .sum::<usize>();
+
+        let mut n_pairs = 0;
+        for system in systems {
+            system.compute_neighbors(parameters.cutoff)?;
+            n_pairs += system.pairs()?.len();
+        }
+
+        return Ok(Some(n_pairs * basis_size));
+    }
+
     fn keys(&self, systems: &mut [Box<dyn System>]) -> Result<Labels, Error> {
         let builder = CenterSingleNeighborsSpeciesKeys {
             cutoff: self.by_pair.parameters().cutoff,
             self_pairs: true,
+            species_center: SpeciesFilter::Any,
+            species_neighbor: SpeciesFilter::NoneOf(self.by_pair.parameters().excluded_neighbor_species.clone()),
         };
         let keys = builder.keys(systems)?;
 
@@ -646,6 +1114,10 @@ impl CalculatorBase for SphericalExpansion {
         match parameter {
             "positions" => true,
             "cell" => true,
+            // computed with finite differences of the analytical gradients
+            "positions_hessian" => true,
+            // the density scaling is only used if requested
+            "density_scaling" => self.by_pair.parameters().use_system_density_scaling,
             _ => false,
         }
     }
@@ -704,23 +1176,20 @@ impl CalculatorBase for SphericalExpansion {
     }
 
     fn properties(&self, keys: &Labels) -> Vec<Labels> {
-        let mut properties = LabelsBuilder::new(self.properties_names());
-        for n in 0..self.by_pair.parameters().max_radial {
-            properties.add(&[n]);
-        }
-        let properties = properties.finish();
-
-        return vec![properties; keys.count()];
+        return self.by_pair.parameters().properties_by_angular(keys);
     }
 
-    #[time_graph::instrument(name = "SphericalExpansion::compute")]
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "SphericalExpansion::compute"))]
     fn compute(&mut self, systems: &mut [Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
         assert_eq!(descriptor.keys().names(), ["spherical_harmonics_l", "species_center", "species_neighbor"]);
+        self.by_pair.clear_radial_integrals();
 
         let do_gradients = GradientsOptions {
             positions: descriptor.block_by_id(0).gradient("positions").is_some(),
             cell: descriptor.block_by_id(0).gradient("cell").is_some(),
         };
+        let do_positions_hessian = descriptor.block_by_id(0).gradient("positions_hessian").is_some();
+        let do_density_scaling = descriptor.block_by_id(0).gradient("density_scaling").is_some();
         self.do_self_contributions(systems, descriptor)?;
         let mut descriptors_by_system = split_tensor_map_by_system(descriptor, systems.len());
 
@@ -736,18 +1205,37 @@ impl CalculatorBase for SphericalExpansion {
                     block.samples().iter().map(|sample| sample[1].usize()).collect::<Vec<_>>()
                 }).collect::<BTreeSet<_>>();
 
+                let mut scratch = self.scratch.get_or_default().borrow_mut();
                 let accumulated = self.accumulate_all_pairs(
                     system,
                     do_gradients,
                     &requested_centers,
+                    &mut scratch,
                 )?;
 
                 // all pairs are done, copy the data into equistore, handling
-                // any property selection made by the user
-                for (key, mut block) in descriptor.iter_mut() {
+                // any property selection made by the user. Different blocks
+                // are independent, and filled in parallel.
+                descriptor.par_iter_mut().try_for_each(|(key, mut block)| {
                     self.values_to_equistore(key, &mut block, system, &accumulated)?;
                     self.position_gradients_to_equistore(key, &mut block, system, &accumulated)?;
                     self.cell_gradients_to_equistore(key, &mut block, system, &accumulated)?;
+                    Ok::<_, Error>(())
+                })?;
+                accumulated.recycle(&mut scratch);
+
+                if do_positions_hessian {
+                    let hessian = self.accumulate_positions_hessian(system, &requested_centers)?;
+                    descriptor.par_iter_mut().for_each(|(key, mut block)| {
+                        self.positions_hessian_to_equistore(key, &mut block, &hessian);
+                    });
+                }
+
+                if do_density_scaling {
+                    let contributions = self.accumulate_density_scaling_gradients(system, &requested_centers)?;
+                    descriptor.par_iter_mut().try_for_each(|(key, mut block)| {
+                        self.density_scaling_gradients_to_equistore(key, &mut block, system, &contributions)
+                    })?;
                 }
 
                 Ok::<_, Error>(())
@@ -761,14 +1249,15 @@ impl CalculatorBase for SphericalExpansion {
 #[cfg(test)]
 mod tests {
     use ndarray::ArrayD;
+    use approx::assert_relative_eq;
     use equistore::{Labels, TensorBlock, EmptyArray, LabelsBuilder, TensorMap};
 
     use crate::systems::test_utils::{test_systems, test_system};
-    use crate::{Calculator, CalculationOptions, LabelsSelection};
+    use crate::{Calculator, CalculationOptions, LabelsSelection, System};
     use crate::calculators::CalculatorBase;
 
     use super::{SphericalExpansion, SphericalExpansionParameters};
-    use super::super::{CutoffFunction, RadialScaling};
+    use super::super::{AtomicDensity, CutoffFunction, InnerCutoff, RadialScaling};
     use crate::calculators::radial_basis::RadialBasis;
 
 
@@ -777,11 +1266,19 @@ mod tests {
             cutoff: 3.5,
             max_radial: 6,
             max_angular: 6,
+            max_radial_by_angular: None,
             atomic_gaussian_width: 0.3,
+            atomic_gaussian_width_by_species: None,
+            density: AtomicDensity::Gaussian {},
             center_atom_weight: 1.0,
             radial_basis: RadialBasis::splined_gto(1e-8),
-            radial_scaling: RadialScaling::Willatt2018 { scale: 1.5, rate: 0.8, exponent: 2},
+            radial_scaling: RadialScaling::Willatt2018 { scale: 1.5, rate: 0.8, exponent: 2.0, scale_by_species: None },
             cutoff_function: CutoffFunction::ShiftedCosine { width: 0.5 },
+            inner_cutoff: None,
+            density_weight_by_species: None,
+            use_system_density_scaling: false,
+            use_system_atomic_gaussian_width: false,
+            excluded_neighbor_species: Vec::new(),
         }
     }
 
@@ -813,6 +1310,24 @@ mod tests {
         // `rascaline/tests/spherical-expansion.rs`
     }
 
+    #[test]
+    fn work_estimate() {
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water", "methane"]);
+        let estimate = calculator.estimate(&mut systems, Default::default()).unwrap();
+
+        let mut n_pairs = 0;
+        for system in &mut systems {
+            system.compute_neighbors(3.5).unwrap();
+            n_pairs += system.pairs().unwrap().len();
+        }
+        // 6 radial functions for each of the 7 * 7 (l, m) values
+        assert_eq!(estimate.work, Some(n_pairs * 6 * 49));
+    }
+
     #[test]
     fn finite_differences_positions() {
         let calculator = Calculator::from(Box::new(SphericalExpansion::new(
@@ -820,7 +1335,7 @@ mod tests {
         ).unwrap()) as Box<dyn CalculatorBase>);
 
         let system = test_system("water");
-        let options = crate::calculators::tests_utils::FinalDifferenceOptions {
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
             displacement: 1e-6,
             max_relative: 1e-5,
             epsilon: 1e-16,
@@ -829,77 +1344,585 @@ mod tests {
     }
 
     #[test]
-    fn finite_differences_cell() {
+    fn finite_differences_positions_hessian() {
         let calculator = Calculator::from(Box::new(SphericalExpansion::new(
             parameters()
         ).unwrap()) as Box<dyn CalculatorBase>);
 
         let system = test_system("water");
-        let options = crate::calculators::tests_utils::FinalDifferenceOptions {
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
             displacement: 1e-6,
             max_relative: 1e-5,
-            epsilon: 1e-16,
+            epsilon: 1e-8,
         };
-        crate::calculators::tests_utils::finite_differences_cell(calculator, &system, options);
+        crate::calculators::tests_utils::finite_differences_positions_hessian(calculator, &system, options);
     }
 
     #[test]
-    fn compute_partial() {
-        let calculator = Calculator::from(Box::new(SphericalExpansion::new(
-            SphericalExpansionParameters {
-                max_angular: 2,
-                ..parameters()
-            }
+    fn remove_empty_blocks() {
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters()
         ).unwrap()) as Box<dyn CalculatorBase>);
 
-        let mut systems = test_systems(&["water"]);
-
-        let properties = Labels::new(["n"], &[
-            [0],
-            [3],
-            [2],
-        ]);
+        // the two atoms are outside of each other cutoff
+        let mut system = crate::systems::SimpleSystem::new(crate::systems::UnitCell::infinite());
+        system.add_atom(8, crate::Vector3D::new(0.0, 0.0, 0.0));
+        system.add_atom(1, crate::Vector3D::new(0.0, 0.0, 10.0));
+        let mut systems = vec![Box::new(system) as Box<dyn System>];
 
-        let samples = Labels::new(["structure", "center"], &[
-            [0, 2],
-            [0, 1],
-        ]);
+        // use the same keys as in a dataset where O and H are neighbors
+        let mut keys = LabelsBuilder::new(vec!["spherical_harmonics_l", "species_center", "species_neighbor"]);
+        for l in 0..=6 {
+            for species_center in [1, 8] {
+                for species_neighbor in [1, 8] {
+                    keys.add(&[l, species_center, species_neighbor]);
+                }
+            }
+        }
+        let keys = keys.finish();
 
-        let keys = Labels::new(["spherical_harmonics_l", "species_center", "species_neighbor"], &[
-            [0, -42, -42],
-            [0, 6, 1], // not part of the default keys
-            [2, -42, -42],
-            [1, -42, -42],
-            [1, -42, 1],
-            [1, 1, -42],
-            [0, -42, 1],
-            [2, -42, 1],
-            [0, 1, 1],
-            [1, 1, 1],
-            [0, 1, -42],
-            [2, 1, -42],
-            [2, 1, 1],
-        ]);
+        let options = CalculationOptions {
+            selected_keys: Some(&keys),
+            ..Default::default()
+        };
+        let descriptor = calculator.compute(&mut systems, options).unwrap();
+        assert_eq!(descriptor.keys().count(), 7 * 4);
+        assert_eq!(descriptor.block_by_id(1).samples().count(), 0);
 
-        crate::calculators::tests_utils::compute_partial(
-            calculator, &mut systems, &keys, &samples, &properties
-        );
+        let options = CalculationOptions {
+            selected_keys: Some(&keys),
+            remove_empty_blocks: true,
+            ..Default::default()
+        };
+        let descriptor = calculator.compute(&mut systems, options).unwrap();
+        assert_eq!(descriptor.keys().count(), 7 * 2);
+        for (key, block) in descriptor.iter() {
+            assert_eq!(key[1], key[2]);
+            assert_eq!(block.samples().count(), 1);
+        }
     }
 
     #[test]
-    fn non_existing_samples() {
+    fn scratch_buffers() {
+        // the per-thread buffers are re-used between calculations with
+        // different systems & gradients, this should not change the results
         let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
             parameters()
         ).unwrap()) as Box<dyn CalculatorBase>);
 
-        let mut systems = test_systems(&["water"]);
+        let mut systems = test_systems(&["water", "methane"]);
+        calculator.compute(&mut systems, Default::default()).unwrap();
 
-        // include the three atoms in all blocks, regardless of the
-        // species_center key.
-        let block = TensorBlock::new(
-            EmptyArray::new(vec![3, 1]),
-            &Labels::new(["structure", "center"], &[[0, 0], [0, 1], [0, 2]]),
-            &[],
+        let mut systems = test_systems(&["methane"]);
+        let options = CalculationOptions {
+            gradients: &["positions", "cell"],
+            ..Default::default()
+        };
+        let descriptor = calculator.compute(&mut systems, options).unwrap();
+
+        let mut reference = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let expected = reference.compute(&mut systems, options).unwrap();
+
+        for (block, expected) in descriptor.blocks().iter().zip(expected.blocks()) {
+            assert_eq!(block.values().to_array(), expected.values().to_array());
+            for parameter in ["positions", "cell"] {
+                assert_eq!(
+                    block.gradient(parameter).unwrap().values().to_array(),
+                    expected.gradient(parameter).unwrap().values().to_array(),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn update() {
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+
+        // a chain of hydrogen atoms, only the atoms at the end of the chain
+        // are affected when moving the last one
+        let mut chain = crate::systems::SimpleSystem::new(crate::systems::UnitCell::infinite());
+        for i in 0..5 {
+            chain.add_atom(1, crate::Vector3D::new(0.0, 0.0, 2.0 * i as f64));
+        }
+        let mut systems = vec![Box::new(test_system("water")) as Box<dyn System>, Box::new(chain)];
+
+        let mut descriptor = calculator.compute(&mut systems, Default::default()).unwrap();
+
+        let mut moved = crate::systems::SimpleSystem::try_from(&*systems[1]).unwrap();
+        moved.positions_mut()[4][2] += 0.1;
+        moved.positions_mut()[4][0] -= 0.05;
+        systems[1] = Box::new(moved);
+
+        let moved_atoms = Labels::new(["structure", "atom"], &[[1, 4]]);
+        calculator.update(&mut systems, &mut descriptor, &moved_atoms, 0.2).unwrap();
+
+        let expected = calculator.compute(&mut systems, Default::default()).unwrap();
+        assert_eq!(descriptor.keys(), expected.keys());
+        for (block, expected) in descriptor.blocks().iter().zip(expected.blocks()) {
+            assert_eq!(block.samples(), expected.samples());
+            let values = block.values().to_array();
+            let expected = expected.values().to_array();
+            for (value, expected) in values.iter().zip(expected) {
+                assert_relative_eq!(value, expected, max_relative=1e-12);
+            }
+        }
+
+        let options = CalculationOptions {
+            gradients: &["positions"],
+            ..Default::default()
+        };
+        let mut descriptor = calculator.compute(&mut systems, options).unwrap();
+        let error = calculator.update(&mut systems, &mut descriptor, &moved_atoms, 0.2).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: descriptors containing gradients can not be updated in place");
+
+        let options = CalculationOptions {
+            gradients: &["positions_hessian"],
+            ..Default::default()
+        };
+        let mut descriptor = calculator.compute(&mut systems, options).unwrap();
+        let error = calculator.update(&mut systems, &mut descriptor, &moved_atoms, 0.2).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: descriptors containing gradients can not be updated in place");
+    }
+
+    #[test]
+    fn positions_hessian_unsupported() {
+        let mut calculator = Calculator::from(Box::new(
+            crate::calculators::SphericalExpansionByPair::new(parameters()).unwrap()
+        ) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water"]);
+        let options = CalculationOptions {
+            gradients: &["positions_hessian"],
+            ..Default::default()
+        };
+        let error = calculator.compute(&mut systems, options).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: the spherical expansion by pair calculator does not support second derivatives with respect to positions"
+        );
+    }
+
+    #[test]
+    fn finite_differences_cell() {
+        let calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+
+        let system = test_system("water");
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
+            displacement: 1e-6,
+            max_relative: 1e-5,
+            epsilon: 1e-16,
+        };
+        crate::calculators::tests_utils::finite_differences_cell(calculator, &system, options);
+    }
+
+    #[test]
+    fn cell_gradients_only() {
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+
+        let options = CalculationOptions {
+            gradients: &["positions", "cell"],
+            ..Default::default()
+        };
+        let reference = calculator.compute(&mut test_systems(&["water"]), options).unwrap();
+
+        let options = CalculationOptions {
+            gradients: &["cell"],
+            ..Default::default()
+        };
+        let descriptor = calculator.compute(&mut test_systems(&["water"]), options).unwrap();
+
+        for (block, reference) in descriptor.blocks().iter().zip(reference.blocks()) {
+            assert!(block.gradient("positions").is_none());
+
+            let gradient = block.gradient("cell").unwrap();
+            let reference = reference.gradient("cell").unwrap();
+            assert_eq!(gradient.samples(), reference.samples());
+            assert_relative_eq!(gradient.values().to_array(), reference.values().to_array(), max_relative=1e-12);
+        }
+    }
+
+    #[test]
+    fn selected_gradient_atoms() {
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+
+        let options = CalculationOptions {
+            gradients: &["positions"],
+            ..Default::default()
+        };
+        let reference = calculator.compute(&mut test_systems(&["water"]), options).unwrap();
+
+        let selected_atoms = Labels::new(["structure", "atom"], &[[0, 1]]);
+        let options = CalculationOptions {
+            gradients: &["positions"],
+            selected_gradient_atoms: Some(&selected_atoms),
+            ..Default::default()
+        };
+        let descriptor = calculator.compute(&mut test_systems(&["water"]), options).unwrap();
+
+        for (block, reference) in descriptor.blocks().iter().zip(reference.blocks()) {
+            assert_eq!(block.values().to_array(), reference.values().to_array());
+
+            let gradient = block.gradient("positions").unwrap();
+            let reference = reference.gradient("positions").unwrap();
+
+            let reference_samples = reference.samples();
+            let expected_count = reference_samples.iter().filter(|sample| sample[2] == 1).count();
+            assert_eq!(gradient.samples().count(), expected_count);
+
+            for (grad_sample_i, sample) in gradient.samples().iter().enumerate() {
+                assert_eq!(sample[2], 1);
+
+                let reference_i = reference_samples.position(sample).unwrap();
+                assert_relative_eq!(
+                    gradient.values().to_array().index_axis(ndarray::Axis(0), grad_sample_i),
+                    reference.values().to_array().index_axis(ndarray::Axis(0), reference_i),
+                    max_relative=1e-12
+                );
+            }
+        }
+
+        let selected_atoms = Labels::new(["structure", "center"], &[[0, 1]]);
+        let options = CalculationOptions {
+            gradients: &["positions"],
+            selected_gradient_atoms: Some(&selected_atoms),
+            ..Default::default()
+        };
+        let error = calculator.compute(&mut test_systems(&["water"]), options).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: expected [structure, atom] names for the selected gradient atoms, got [structure, center]"
+        );
+    }
+
+    #[test]
+    fn finite_differences_laguerre() {
+        let calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            SphericalExpansionParameters {
+                radial_basis: RadialBasis::laguerre(),
+                ..parameters()
+            }
+        ).unwrap()) as Box<dyn CalculatorBase>);
+
+        let system = test_system("water");
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
+            displacement: 1e-6,
+            max_relative: 1e-5,
+            epsilon: 1e-16,
+        };
+        crate::calculators::tests_utils::finite_differences_positions(calculator, &system, options);
+    }
+
+    #[test]
+    fn atomic_density() {
+        let densities = [
+            AtomicDensity::Slater { decay_length: 0.4 },
+            AtomicDensity::Lorentzian { width: 0.4 },
+            AtomicDensity::Delta {},
+        ];
+
+        for density in densities {
+            let calculator = Calculator::from(Box::new(SphericalExpansion::new(
+                SphericalExpansionParameters {
+                    max_radial: 4,
+                    max_angular: 4,
+                    density: density,
+                    ..parameters()
+                }
+            ).unwrap()) as Box<dyn CalculatorBase>);
+
+            let system = test_system("water");
+            let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
+                displacement: 1e-6,
+                max_relative: 1e-5,
+                epsilon: 1e-16,
+            };
+            crate::calculators::tests_utils::finite_differences_positions(calculator, &system, options);
+        }
+
+        let error = SphericalExpansion::new(SphericalExpansionParameters {
+            density: AtomicDensity::Slater { decay_length: -0.4 },
+            ..parameters()
+        }).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: expected positive decay_length for Slater atomic density, got -0.4"
+        );
+
+        let error = SphericalExpansion::new(SphericalExpansionParameters {
+            density: AtomicDensity::Lorentzian { width: 0.4 },
+            atomic_gaussian_width_by_species: Some([(1, 0.5)].into_iter().collect()),
+            ..parameters()
+        }).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: atomic_gaussian_width_by_species and use_system_atomic_gaussian_width \
+            can only be used with a gaussian atomic density"
+        );
+    }
+
+    #[test]
+    fn inner_cutoff() {
+        let calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            SphericalExpansionParameters {
+                inner_cutoff: Some(InnerCutoff { radius: 0.8, width: 0.3 }),
+                ..parameters()
+            }
+        ).unwrap()) as Box<dyn CalculatorBase>);
+
+        let system = test_system("water");
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
+            displacement: 1e-6,
+            max_relative: 1e-5,
+            epsilon: 1e-16,
+        };
+        crate::calculators::tests_utils::finite_differences_positions(calculator, &system, options);
+
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let reference = calculator.compute(&mut test_systems(&["water"]), Default::default()).unwrap();
+
+        // O-H pairs are inside the inner cutoff, H-H pairs are outside
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            SphericalExpansionParameters {
+                inner_cutoff: Some(InnerCutoff { radius: 1.0, width: 0.2 }),
+                ..parameters()
+            }
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let descriptor = calculator.compute(&mut test_systems(&["water"]), Default::default()).unwrap();
+
+        for (key, block) in descriptor.keys().iter().zip(descriptor.blocks()) {
+            let values = block.values().to_array();
+            if key[1] == key[2] {
+                // central atom and H-H contributions are unchanged
+                let reference = reference.block_by_id(reference.keys().position(key).unwrap());
+                assert_relative_eq!(values, reference.values().to_array(), max_relative=1e-12);
+            } else {
+                assert!(values.iter().all(|&v| v == 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn excluded_neighbor_species() {
+        let calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            SphericalExpansionParameters {
+                excluded_neighbor_species: vec![1],
+                ..parameters()
+            }
+        ).unwrap()) as Box<dyn CalculatorBase>);
+
+        let system = test_system("water");
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
+            displacement: 1e-6,
+            max_relative: 1e-5,
+            epsilon: 1e-16,
+        };
+        crate::calculators::tests_utils::finite_differences_positions(calculator, &system, options);
+
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let reference = calculator.compute(&mut test_systems(&["water"]), Default::default()).unwrap();
+
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            SphericalExpansionParameters {
+                excluded_neighbor_species: vec![1],
+                ..parameters()
+            }
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let descriptor = calculator.compute(&mut test_systems(&["water"]), Default::default()).unwrap();
+
+        // H are still centers, but there are no blocks for H neighbors
+        assert_eq!(descriptor.keys().count(), 7 * 2);
+        for (key, block) in descriptor.keys().iter().zip(descriptor.blocks()) {
+            assert_eq!(key[2].i32(), -42);
+
+            // the density of O atoms is not affected by the H atoms
+            let reference = reference.block_by_id(reference.keys().position(key).unwrap());
+            assert_eq!(block.samples(), reference.samples());
+            assert_relative_eq!(block.values().to_array(), reference.values().to_array(), max_relative=1e-12);
+        }
+    }
+
+    #[test]
+    fn radial_scaling_by_species() {
+        let calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            SphericalExpansionParameters {
+                radial_scaling: RadialScaling::Willatt2018 {
+                    scale: 1.5,
+                    rate: 0.8,
+                    exponent: 2.5,
+                    scale_by_species: Some([(-42, 2.5)].into_iter().collect()),
+                },
+                ..parameters()
+            }
+        ).unwrap()) as Box<dyn CalculatorBase>);
+
+        let system = test_system("water");
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
+            displacement: 1e-6,
+            max_relative: 1e-5,
+            epsilon: 1e-16,
+        };
+        crate::calculators::tests_utils::finite_differences_positions(calculator, &system, options);
+
+        // using the same per-species scale for all species is the same as
+        // changing the global scale
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            SphericalExpansionParameters {
+                radial_scaling: RadialScaling::Willatt2018 {
+                    scale: 2.5,
+                    rate: 0.8,
+                    exponent: 2.5,
+                    scale_by_species: None,
+                },
+                ..parameters()
+            }
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let reference = calculator.compute(&mut test_systems(&["water"]), Default::default()).unwrap();
+
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            SphericalExpansionParameters {
+                radial_scaling: RadialScaling::Willatt2018 {
+                    scale: 1.5,
+                    rate: 0.8,
+                    exponent: 2.5,
+                    scale_by_species: Some([(-42, 2.5), (1, 2.5)].into_iter().collect()),
+                },
+                ..parameters()
+            }
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let descriptor = calculator.compute(&mut test_systems(&["water"]), Default::default()).unwrap();
+
+        for (block, reference) in descriptor.blocks().iter().zip(reference.blocks()) {
+            assert_relative_eq!(
+                block.values().to_array(), reference.values().to_array(), max_relative=1e-12
+            );
+        }
+    }
+
+    #[test]
+    fn compute_partial() {
+        let calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            SphericalExpansionParameters {
+                max_angular: 2,
+                ..parameters()
+            }
+        ).unwrap()) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water"]);
+
+        let properties = Labels::new(["n"], &[
+            [0],
+            [3],
+            [2],
+        ]);
+
+        let samples = Labels::new(["structure", "center"], &[
+            [0, 2],
+            [0, 1],
+        ]);
+
+        let keys = Labels::new(["spherical_harmonics_l", "species_center", "species_neighbor"], &[
+            [0, -42, -42],
+            [0, 6, 1], // not part of the default keys
+            [2, -42, -42],
+            [1, -42, -42],
+            [1, -42, 1],
+            [1, 1, -42],
+            [0, -42, 1],
+            [2, -42, 1],
+            [0, 1, 1],
+            [1, 1, 1],
+            [0, 1, -42],
+            [2, 1, -42],
+            [2, 1, 1],
+        ]);
+
+        crate::calculators::tests_utils::compute_partial(
+            calculator, &mut systems, &keys, &samples, &properties
+        );
+    }
+
+    #[test]
+    fn max_radial_by_angular() {
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            SphericalExpansionParameters {
+                max_angular: 2,
+                ..parameters()
+            }
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let reference = calculator.compute(&mut test_systems(&["water"]), Default::default()).unwrap();
+
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            SphericalExpansionParameters {
+                max_angular: 2,
+                max_radial_by_angular: Some(vec![6, 4, 2]),
+                ..parameters()
+            }
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let descriptor = calculator.compute(&mut test_systems(&["water"]), Default::default()).unwrap();
+
+        assert_eq!(descriptor.keys(), reference.keys());
+        let blocks = descriptor.keys().iter().zip(descriptor.blocks()).zip(reference.blocks());
+        for ((key, block), reference) in blocks {
+            let max_radial = [6, 4, 2][key[0].usize()];
+            assert_eq!(block.properties().count(), max_radial);
+
+            // the remaining basis functions are the same as without
+            // max_radial_by_angular
+            let expected = reference.values().to_array().slice_axis(
+                ndarray::Axis(2), ndarray::Slice::from(0..max_radial)
+            ).to_owned();
+            assert_relative_eq!(block.values().to_array(), expected, max_relative=1e-12);
+        }
+
+        let error = SphericalExpansion::new(SphericalExpansionParameters {
+            max_angular: 2,
+            max_radial_by_angular: Some(vec![6, 4]),
+            ..parameters()
+        }).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: expected 3 values in max_radial_by_angular (one for each angular channel), got 2"
+        );
+
+        let error = SphericalExpansion::new(SphericalExpansionParameters {
+            max_angular: 2,
+            max_radial_by_angular: Some(vec![6, 4, 8]),
+            ..parameters()
+        }).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: values in max_radial_by_angular must be between 1 and max_radial (6), got 8"
+        );
+    }
+
+    #[test]
+    fn non_existing_samples() {
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+
+        let mut systems = test_systems(&["water"]);
+
+        // include the three atoms in all blocks, regardless of the
+        // species_center key.
+        let block = TensorBlock::new(
+            EmptyArray::new(vec![3, 1]),
+            &Labels::new(["structure", "center"], &[[0, 0], [0, 1], [0, 2]]),
+            &[],
             &Labels::single(),
         ).unwrap();
 
@@ -946,4 +1969,275 @@ mod tests {
         let array = block.values.as_array();
         assert_eq!(array.index_axis(ndarray::Axis(0), 0), ArrayD::from_elem(vec![1, 6], 0.0));
     }
+
+    #[test]
+    fn density_scaling() {
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let reference = calculator.compute(&mut test_systems(&["water"]), Default::default()).unwrap();
+
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            SphericalExpansionParameters {
+                use_system_density_scaling: true,
+                ..parameters()
+            }
+        ).unwrap()) as Box<dyn CalculatorBase>);
+
+        // systems without density scaling are rejected
+        let error = calculator.compute(&mut test_systems(&["water"]), Default::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: use_system_density_scaling is set, but the system does not define per-atom density scaling"
+        );
+
+        // scaling all atoms by the same factor scales the whole expansion
+        let mut system = test_system("water");
+        system.set_density_scaling(vec![2.5; 3]).unwrap();
+        let scaled = calculator.compute(&mut [Box::new(system) as Box<dyn System>], Default::default()).unwrap();
+
+        for (block, reference) in scaled.blocks().iter().zip(reference.blocks()) {
+            let expected = 2.5 * &reference.values().to_array();
+            assert_relative_eq!(block.values().to_array(), expected, max_relative=1e-12);
+        }
+    }
+
+    #[test]
+    fn density_scaling_finite_differences() {
+        let parameters = SphericalExpansionParameters {
+            use_system_density_scaling: true,
+            ..parameters()
+        };
+
+        let mut system = test_system("water");
+        system.set_density_scaling(vec![1.5, 0.8, 1.2]).unwrap();
+
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
+            displacement: 1e-6,
+            max_relative: 1e-5,
+            epsilon: 1e-16,
+        };
+
+        let calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters.clone()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        crate::calculators::tests_utils::finite_differences_positions(calculator, &system, options);
+
+        let calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        crate::calculators::tests_utils::finite_differences_cell(calculator, &system, options);
+    }
+
+    #[test]
+    fn density_scaling_gradients() {
+        let parameters = SphericalExpansionParameters {
+            use_system_density_scaling: true,
+            density_weight_by_species: Some([(1, 0.6)].into_iter().collect()),
+            ..parameters()
+        };
+
+        let mut system = test_system("water");
+        system.set_density_scaling(vec![1.5, 0.8, 1.2]).unwrap();
+
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
+            displacement: 1e-6,
+            max_relative: 1e-5,
+            epsilon: 1e-16,
+        };
+
+        let calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        crate::calculators::tests_utils::finite_differences_density_scaling(calculator, &system, options);
+
+        // density scaling gradients are only available if the density
+        // scaling is used
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let options = CalculationOptions {
+            gradients: &["density_scaling"],
+            ..Default::default()
+        };
+        let error = calculator.compute(&mut test_systems(&["water"]), options).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: the spherical expansion calculator does not support gradients with respect to the density scaling"
+        );
+    }
+
+    #[test]
+    fn density_weight_by_species() {
+        // species weights are equivalent to per-atom density scaling
+        let mut system = test_system("water");
+        system.set_density_scaling(vec![1.0, 0.4, 0.4]).unwrap();
+
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            SphericalExpansionParameters {
+                use_system_density_scaling: true,
+                ..parameters()
+            }
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let options = CalculationOptions {
+            gradients: &["positions"],
+            ..Default::default()
+        };
+        let reference = calculator.compute(&mut [Box::new(system) as Box<dyn System>], options).unwrap();
+
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            SphericalExpansionParameters {
+                density_weight_by_species: Some([(1, 0.4)].into_iter().collect()),
+                ..parameters()
+            }
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let descriptor = calculator.compute(&mut test_systems(&["water"]), options).unwrap();
+
+        for (block, reference) in descriptor.blocks().iter().zip(reference.blocks()) {
+            assert_relative_eq!(block.values().to_array(), reference.values().to_array(), max_relative=1e-12);
+
+            let gradient = block.gradient("positions").unwrap();
+            let reference = reference.gradient("positions").unwrap();
+            assert_relative_eq!(gradient.values().to_array(), reference.values().to_array(), max_relative=1e-12);
+        }
+
+        let error = SphericalExpansion::new(SphericalExpansionParameters {
+            density_weight_by_species: Some([(1, f64::NAN)].into_iter().collect()),
+            ..parameters()
+        }).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: density weight for species 1 must be a finite number, got NaN"
+        );
+    }
+
+    #[test]
+    fn atomic_gaussian_width_by_species() {
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let reference = calculator.compute(&mut test_systems(&["water"]), Default::default()).unwrap();
+
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            SphericalExpansionParameters {
+                atomic_gaussian_width: 0.5,
+                ..parameters()
+            }
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let reference_wide = calculator.compute(&mut test_systems(&["water"]), Default::default()).unwrap();
+
+        let error = SphericalExpansion::new(SphericalExpansionParameters {
+            atomic_gaussian_width_by_species: Some([(1, -0.5)].into_iter().collect()),
+            ..parameters()
+        }).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: atomic_gaussian_width for species 1 must be a positive number, got -0.5"
+        );
+
+        let parameters = SphericalExpansionParameters {
+            atomic_gaussian_width_by_species: Some([(1, 0.5)].into_iter().collect()),
+            ..parameters()
+        };
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters.clone()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let descriptor = calculator.compute(&mut test_systems(&["water"]), Default::default()).unwrap();
+
+        assert_eq!(descriptor.keys(), reference.keys());
+        let blocks = descriptor.keys().iter().zip(descriptor.blocks());
+        for (key, block) in blocks {
+            // the density of hydrogen neighbors uses the width given for this
+            // species, oxygen neighbors use the default width
+            let expected = if key[2].i32() == 1 {
+                reference_wide.block_by_id(reference_wide.keys().position(key).unwrap())
+            } else {
+                reference.block_by_id(reference.keys().position(key).unwrap())
+            };
+            assert_relative_eq!(
+                block.values().to_array(),
+                expected.values().to_array(),
+                max_relative=1e-12,
+            );
+        }
+
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
+            displacement: 1e-6,
+            max_relative: 1e-5,
+            epsilon: 1e-16,
+        };
+        let system = test_system("water");
+
+        let calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters.clone()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        crate::calculators::tests_utils::finite_differences_positions(calculator, &system, options);
+
+        let calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        crate::calculators::tests_utils::finite_differences_cell(calculator, &system, options);
+    }
+
+    #[test]
+    fn system_atomic_gaussian_width() {
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            SphericalExpansionParameters {
+                radial_basis: RadialBasis::gto(),
+                atomic_gaussian_width_by_species: Some([(1, 0.5)].into_iter().collect()),
+                ..parameters()
+            }
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        let reference = calculator.compute(&mut test_systems(&["water"]), Default::default()).unwrap();
+
+        let parameters = SphericalExpansionParameters {
+            use_system_atomic_gaussian_width: true,
+            ..parameters()
+        };
+        let mut calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters.clone()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+
+        // systems without per-atom widths are rejected
+        let error = calculator.compute(&mut test_systems(&["water"]), Default::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: use_system_atomic_gaussian_width is set, but the system does not define per-atom gaussian widths"
+        );
+
+        // using the same width for all atoms of a given species is the same as
+        // using per-species widths
+        let mut system = test_system("water");
+        system.set_atomic_gaussian_width(vec![0.3, 0.5, 0.5]).unwrap();
+        let descriptor = calculator.compute(&mut [Box::new(system) as Box<dyn System>], Default::default()).unwrap();
+
+        assert_eq!(descriptor.keys(), reference.keys());
+        for (block, reference) in descriptor.blocks().iter().zip(reference.blocks()) {
+            assert_relative_eq!(
+                block.values().to_array(),
+                reference.values().to_array(),
+                max_relative=1e-12,
+            );
+        }
+
+        // check gradients with a different width for each atom
+        let mut system = test_system("water");
+        system.set_atomic_gaussian_width(vec![0.3, 0.4, 0.5]).unwrap();
+
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
+            displacement: 1e-6,
+            max_relative: 1e-5,
+            epsilon: 1e-16,
+        };
+
+        let calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters.clone()
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        crate::calculators::tests_utils::finite_differences_positions(calculator, &system, options);
+
+        let calculator = Calculator::from(Box::new(SphericalExpansion::new(
+            parameters
+        ).unwrap()) as Box<dyn CalculatorBase>);
+        crate::calculators::tests_utils::finite_differences_cell(calculator, &system, options);
+    }
 }
@@ -15,7 +15,7 @@ use crate::math::SphericalHarmonicsCache;
 use super::super::CalculatorBase;
 use super::super::neighbor_list::FullNeighborList;
 
-use super::{CutoffFunction, RadialScaling};
+use super::{AtomicDensity, CutoffFunction, InnerCutoff, RadialScaling};
 
 use crate::calculators::radial_basis::RadialBasis;
 use super::SoapRadialIntegralCache;
@@ -38,8 +38,26 @@ pub struct SphericalExpansionParameters {
     pub max_radial: usize,
     /// Number of spherical harmonics to use in the expansion
     pub max_angular: usize,
+    /// Number of radial basis function to use for each angular channel `l`,
+    /// overriding `max_radial`. If given, this must contain `max_angular + 1`
+    /// values, all of them between 1 and `max_radial`. This can be used to
+    /// reduce the number of features at high `l`.
+    #[serde(default)]
+    pub max_radial_by_angular: Option<Vec<usize>>,
     /// Width of the atom-centered gaussian used to create the atomic density
     pub atomic_gaussian_width: f64,
+    /// Width of the atom-centered gaussian to use for specific neighbor
+    /// species, overriding `atomic_gaussian_width`. This can be used to give a
+    /// different smearing to heavy and light elements.
+    #[serde(default)]
+    pub atomic_gaussian_width_by_species: Option<BTreeMap<i32, f64>>,
+    /// Shape of the atomic density placed on each neighbor. Non-gaussian
+    /// densities do not use `atomic_gaussian_width`. The radial integral of
+    /// Slater and Lorentzian densities is computed numerically and always
+    /// splined, while the radial integral of a delta density is the radial
+    /// basis evaluated at the neighbor distance.
+    #[serde(default)]
+    pub density: AtomicDensity,
     /// Weight of the central atom contribution to the
     /// features. If `1` the center atom contribution is weighted the same
     /// as any other contribution. If `0` the central atom does not
@@ -49,11 +67,43 @@ pub struct SphericalExpansionParameters {
     pub radial_basis: RadialBasis,
     /// Cutoff function used to smooth the behavior around the cutoff radius
     pub cutoff_function: CutoffFunction,
+    /// Smooth inner cutoff, removing the contribution of neighbors closer than
+    /// a given distance to the central atom. This does not affect the central
+    /// atom contribution.
+    #[serde(default)]
+    pub inner_cutoff: Option<InnerCutoff>,
     /// radial scaling can be used to reduce the importance of neighbor atoms
     /// further away from the center, usually improving the performance of the
     /// model
     #[serde(default)]
     pub radial_scaling: RadialScaling,
+    /// Weight multiplying the density of atoms with the given species, for
+    /// example `{1: 0.4, 6: 1.0}`. Species missing from this map use a weight
+    /// of 1. This applies to both neighbors and the central atom contribution,
+    /// on top of `center_atom_weight` and `use_system_density_scaling`.
+    #[serde(default)]
+    pub density_weight_by_species: Option<BTreeMap<i32, f64>>,
+    /// Scale the density of each atom by the per-atom factor given by the
+    /// system (see `System::density_scaling`), for example an effective atomic
+    /// volume. This affects both neighbors and the central atom contribution.
+    /// Only the amplitude of the density is scaled, per-atom widths are set
+    /// with `use_system_atomic_gaussian_width`.
+    #[serde(default)]
+    pub use_system_density_scaling: bool,
+    /// Use the per-atom width of the gaussian density given by the system (see
+    /// `System::atomic_gaussian_width`) instead of `atomic_gaussian_width` and
+    /// `atomic_gaussian_width_by_species`. Since every atom can have a
+    /// different width, the radial integral is computed directly for each
+    /// width instead of using splines.
+    #[serde(default)]
+    pub use_system_atomic_gaussian_width: bool,
+    /// Species of the neighbors to remove from the density, for example `[1]`
+    /// to ignore all hydrogen atoms. Atoms with these species are still used
+    /// as centers, but they do not contribute to the density around any
+    /// center (including themselves), and the corresponding blocks are not
+    /// part of the output.
+    #[serde(default)]
+    pub excluded_neighbor_species: Vec<i32>,
 }
 
 impl SphericalExpansionParameters {
@@ -61,6 +111,38 @@ impl SphericalExpansionParameters {
     pub fn validate(&self) -> Result<(), Error> {
         self.cutoff_function.validate()?;
         self.radial_scaling.validate()?;
+        self.radial_scaling.validate_range(self.cutoff)?;
+        if let Some(inner_cutoff) = self.inner_cutoff {
+            inner_cutoff.validate(self.cutoff)?;
+        }
+        self.density.validate()?;
+
+        let per_atom_widths = self.atomic_gaussian_width_by_species.is_some() || self.use_system_atomic_gaussian_width;
+        if per_atom_widths && !matches!(self.density, AtomicDensity::Gaussian {}) {
+            return Err(Error::InvalidParameter(
+                "atomic_gaussian_width_by_species and use_system_atomic_gaussian_width \
+                can only be used with a gaussian atomic density".into()
+            ));
+        }
+
+        if let Some(ref max_radial_by_angular) = self.max_radial_by_angular {
+            if max_radial_by_angular.len() != self.max_angular + 1 {
+                return Err(Error::InvalidParameter(format!(
+                    "expected {} values in max_radial_by_angular (one for each \
+                    angular channel), got {}",
+                    self.max_angular + 1, max_radial_by_angular.len()
+                )));
+            }
+
+            for &max_radial in max_radial_by_angular {
+                if max_radial == 0 || max_radial > self.max_radial {
+                    return Err(Error::InvalidParameter(format!(
+                        "values in max_radial_by_angular must be between 1 and \
+                        max_radial ({}), got {}", self.max_radial, max_radial
+                    )));
+                }
+            }
+        }
 
         // try constructing a radial integral
         SoapRadialIntegralCache::new(self.radial_basis.clone(), SoapRadialIntegralParameters {
@@ -68,18 +150,106 @@ impl SphericalExpansionParameters {
             max_angular: self.max_angular,
             atomic_gaussian_width: self.atomic_gaussian_width,
             cutoff: self.cutoff,
+            density: self.density,
         })?;
 
+        if self.use_system_atomic_gaussian_width {
+            if let RadialBasis::TabulatedRadialIntegral { .. } = self.radial_basis {
+                return Err(Error::InvalidParameter(
+                    "use_system_atomic_gaussian_width can not be used with a \
+                    tabulated radial integral".into()
+                ));
+            }
+        }
+
+        if let Some(ref atomic_gaussian_width_by_species) = self.atomic_gaussian_width_by_species {
+            for (&species, &atomic_gaussian_width) in atomic_gaussian_width_by_species {
+                if !(atomic_gaussian_width > 0.0 && atomic_gaussian_width.is_finite()) {
+                    return Err(Error::InvalidParameter(format!(
+                        "atomic_gaussian_width for species {} must be a \
+                        positive number, got {}", species, atomic_gaussian_width
+                    )));
+                }
+
+                SoapRadialIntegralCache::new(self.radial_basis.clone(), SoapRadialIntegralParameters {
+                    max_radial: self.max_radial,
+                    max_angular: self.max_angular,
+                    atomic_gaussian_width: atomic_gaussian_width,
+                    cutoff: self.cutoff,
+                    density: self.density,
+                })?;
+            }
+        }
+
+        if let Some(ref density_weight_by_species) = self.density_weight_by_species {
+            for (&species, &weight) in density_weight_by_species {
+                if !weight.is_finite() {
+                    return Err(Error::InvalidParameter(format!(
+                        "density weight for species {} must be a finite number, got {}",
+                        species, weight
+                    )));
+                }
+            }
+        }
+
         return Ok(());
     }
+
+    /// Get the width of the atom-centered gaussian used for the density of
+    /// atoms with the given `species`
+    pub fn atomic_gaussian_width_for_species(&self, species: i32) -> f64 {
+        return self.atomic_gaussian_width_by_species.as_ref()
+            .and_then(|widths| widths.get(&species))
+            .copied()
+            .unwrap_or(self.atomic_gaussian_width);
+    }
+
+    /// Check if atoms with the given `species` contribute to the density
+    /// around the centers, i.e. if they are not part of
+    /// `excluded_neighbor_species`
+    pub fn is_neighbor_species_included(&self, species: i32) -> bool {
+        return !self.excluded_neighbor_species.contains(&species);
+    }
+
+    /// Get the number of radial basis function used for the angular channel
+    /// `spherical_harmonics_l`
+    pub fn max_radial_for_angular(&self, spherical_harmonics_l: usize) -> usize {
+        match self.max_radial_by_angular {
+            Some(ref max_radial_by_angular) => max_radial_by_angular[spherical_harmonics_l],
+            None => self.max_radial,
+        }
+    }
+
+    /// Get the radial properties (`n`) to use for each of the `keys`, taking
+    /// into account `max_radial_by_angular`. The first dimension of the keys
+    /// should be `spherical_harmonics_l`.
+    pub(super) fn properties_by_angular(&self, keys: &Labels) -> Vec<Labels> {
+        let mut cache: BTreeMap<_, Labels> = BTreeMap::new();
+        let mut result = Vec::new();
+        for key in keys.iter() {
+            let spherical_harmonics_l = key[0].usize();
+            let properties = cache.entry(spherical_harmonics_l).or_insert_with(|| {
+                let mut properties = LabelsBuilder::new(vec!["n"]);
+                for n in 0..self.max_radial_for_angular(spherical_harmonics_l) {
+                    properties.add(&[n]);
+                }
+                properties.finish()
+            });
+
+            result.push(properties.clone());
+        }
+
+        return result;
+    }
 }
 
 /// The actual calculator used to compute spherical expansion pair-by-pair
 pub struct SphericalExpansionByPair {
     pub(crate) parameters: SphericalExpansionParameters,
     /// implementation + cached allocation to compute the radial integral for a
-    /// single pair
-    radial_integral: ThreadLocal<RefCell<SoapRadialIntegralCache>>,
+    /// single pair, for each of the atomic gaussian widths used by this
+    /// calculator. The key of the map is the bit representation of the width.
+    radial_integral: ThreadLocal<RefCell<BTreeMap<u64, SoapRadialIntegralCache>>>,
     /// implementation + cached allocation to compute the spherical harmonics
     /// for a single pair
     spherical_harmonics: ThreadLocal<RefCell<SphericalHarmonicsCache>>,
@@ -109,6 +279,7 @@ impl GradientsOptions {
 
 
 /// Contribution of a single pair to the spherical expansion
+#[derive(Debug)]
 pub(super) struct PairContribution {
     /// Values of the contribution. The shape is (lm, n), where the lm index
     /// runs over both l and m
@@ -192,47 +363,179 @@ impl SphericalExpansionByPair {
         &self.parameters
     }
 
-    /// Compute the product of radial scaling & cutoff smoothing functions
-    fn scaling_functions(&self, r: f64) -> f64 {
+    /// Get the factor by which the density of each atom in the `system` should
+    /// be scaled. This uses `System::density_scaling` if requested in the
+    /// parameters, and 1 for all atoms otherwise, multiplied by the weight
+    /// associated with the atomic species (if any).
+    pub(super) fn density_weights(&self, system: &dyn System) -> Result<Vec<f64>, Error> {
+        let mut weights = self.system_density_weights(system)?;
+        for (weight, &species) in weights.iter_mut().zip(system.species()?) {
+            *weight *= self.species_density_weight(species);
+        }
+
+        return Ok(weights);
+    }
+
+    /// Get the weight associated with the given atomic `species`, or 1 if
+    /// there are no weights for this species.
+    pub(super) fn species_density_weight(&self, species: i32) -> f64 {
+        if let Some(ref density_weight_by_species) = self.parameters.density_weight_by_species {
+            if let Some(&species_weight) = density_weight_by_species.get(&species) {
+                return species_weight;
+            }
+        }
+        return 1.0;
+    }
+
+    /// Get the per-atom density scaling from `System::density_scaling` if
+    /// requested in the parameters, and 1 for all atoms otherwise.
+    fn system_density_weights(&self, system: &dyn System) -> Result<Vec<f64>, Error> {
+        let size = system.size()?;
+        if !self.parameters.use_system_density_scaling {
+            return Ok(vec![1.0; size]);
+        }
+
+        let scaling = system.density_scaling()?.ok_or_else(|| Error::InvalidParameter(
+            "use_system_density_scaling is set, but the system does not define per-atom density scaling".into()
+        ))?;
+
+        if scaling.len() != size {
+            return Err(Error::InvalidParameter(format!(
+                "expected {} density scaling factors for this system, got {}",
+                size, scaling.len()
+            )));
+        }
+
+        return Ok(scaling.to_vec());
+    }
+
+    /// Get the width of the gaussian density of each atom in the `system`.
+    /// This uses `System::atomic_gaussian_width` if requested in the
+    /// parameters, and the width associated with the atomic species otherwise.
+    pub(super) fn atomic_gaussian_widths(&self, system: &dyn System) -> Result<Vec<f64>, Error> {
+        let species = system.species()?;
+        if !self.parameters.use_system_atomic_gaussian_width {
+            return Ok(species.iter()
+                .map(|&s| self.parameters.atomic_gaussian_width_for_species(s))
+                .collect()
+            );
+        }
+
+        let widths = system.atomic_gaussian_width()?.ok_or_else(|| Error::InvalidParameter(
+            "use_system_atomic_gaussian_width is set, but the system does not define per-atom gaussian widths".into()
+        ))?;
+
+        if widths.len() != species.len() {
+            return Err(Error::InvalidParameter(format!(
+                "expected {} atomic gaussian widths for this system, got {}",
+                species.len(), widths.len()
+            )));
+        }
+
+        if widths.iter().any(|&w| !(w > 0.0 && w.is_finite())) {
+            return Err(Error::InvalidParameter(
+                "atomic gaussian widths given by the system must be strictly positive".into()
+            ));
+        }
+
+        return Ok(widths.to_vec());
+    }
+
+    /// Get the radial integral for the given `atomic_gaussian_width` from the
+    /// `radial_integrals` cache, creating it if it does not exist yet.
+    fn radial_integral_for_width<'a>(
+        &self,
+        radial_integrals: &'a mut BTreeMap<u64, SoapRadialIntegralCache>,
+        atomic_gaussian_width: f64,
+    ) -> &'a mut SoapRadialIntegralCache {
+        return radial_integrals.entry(atomic_gaussian_width.to_bits()).or_insert_with(|| {
+            // creating splines for each per-atom width would be more expensive
+            // than directly computing the radial integral
+            let radial_basis = if self.parameters.use_system_atomic_gaussian_width {
+                self.parameters.radial_basis.without_splines()
+            } else {
+                self.parameters.radial_basis.clone()
+            };
+
+            SoapRadialIntegralCache::new(
+                radial_basis,
+                SoapRadialIntegralParameters {
+                    max_radial: self.parameters.max_radial,
+                    max_angular: self.parameters.max_angular,
+                    atomic_gaussian_width: atomic_gaussian_width,
+                    cutoff: self.parameters.cutoff,
+                    density: self.parameters.density,
+                }
+            ).expect("invalid radial integral parameters")
+        });
+    }
+
+    /// Remove all the radial integrals created for per-atom gaussian widths.
+    /// These can not be re-used across systems, and would otherwise accumulate
+    /// in the cache.
+    pub(super) fn clear_radial_integrals(&mut self) {
+        if self.parameters.use_system_atomic_gaussian_width {
+            for radial_integrals in self.radial_integral.iter_mut() {
+                radial_integrals.get_mut().clear();
+            }
+        }
+    }
+
+    /// Compute the product of radial scaling & cutoff smoothing functions, for
+    /// a neighbor with the given `species`
+    fn scaling_functions(&self, r: f64, species: i32) -> f64 {
         let cutoff = self.parameters.cutoff_function.compute(r, self.parameters.cutoff);
-        let scaling = self.parameters.radial_scaling.compute(r);
-        return cutoff * scaling;
+        let scaling = self.parameters.radial_scaling.compute(r, species);
+        let inner = self.parameters.inner_cutoff.map_or(1.0, |inner| inner.compute(r));
+        return cutoff * scaling * inner;
     }
 
-    /// Compute the gradient of the product of radial scaling & cutoff smoothing functions
-    fn scaling_functions_gradient(&self, r: f64) -> f64 {
+    /// Compute the gradient of the product of radial scaling & cutoff smoothing
+    /// functions, for a neighbor with the given `species`
+    fn scaling_functions_gradient(&self, r: f64, species: i32) -> f64 {
         let cutoff = self.parameters.cutoff_function.compute(r, self.parameters.cutoff);
         let cutoff_grad = self.parameters.cutoff_function.derivative(r, self.parameters.cutoff);
 
-        let scaling = self.parameters.radial_scaling.compute(r);
-        let scaling_grad = self.parameters.radial_scaling.derivative(r);
+        let scaling = self.parameters.radial_scaling.compute(r, species);
+        let scaling_grad = self.parameters.radial_scaling.derivative(r, species);
 
-        return cutoff_grad * scaling + cutoff * scaling_grad;
+        let inner = self.parameters.inner_cutoff.map_or(1.0, |inner| inner.compute(r));
+        let inner_grad = self.parameters.inner_cutoff.map_or(0.0, |inner| inner.derivative(r));
+
+        return cutoff_grad * scaling * inner + cutoff * scaling_grad * inner + cutoff * scaling * inner_grad;
+    }
+
+    /// Check if the densities around two atoms with the given species and
+    /// atomic gaussian widths are the same, i.e. if both the width and the
+    /// radial scaling function are the same for both atoms.
+    pub(super) fn same_density_for_pair(
+        &self,
+        species_first: i32,
+        width_first: f64,
+        species_second: i32,
+        width_second: f64,
+    ) -> bool {
+        return width_first.to_bits() == width_second.to_bits()
+            && self.parameters.radial_scaling.same_for_species(species_first, species_second);
     }
 
     /// Compute the self-contribution (contribution coming from an atom "seeing"
     /// it's own density). This is equivalent to a normal pair contribution,
     /// with a distance of 0.
     ///
-    /// For now, the same density is used for all atoms, so this function can be
-    /// called only once and re-used for all atoms (see `do_self_contributions`
-    /// below).
+    /// The density shape only depends on the `species` of the atom (through
+    /// the radial scaling) and the `atomic_gaussian_width`, so this function
+    /// can be called only once per species/width and re-used for all atoms,
+    /// multiplying by the density weight of each atom (see
+    /// `do_self_contributions` below).
     ///
     /// By symmetry, the self-contribution is only non-zero for `L=0`, and does
     /// not contributes to the gradients.
-    pub(super) fn self_contribution(&self) -> PairContribution {
-        let mut radial_integral = self.radial_integral.get_or(|| {
-            let radial_integral = SoapRadialIntegralCache::new(
-                self.parameters.radial_basis.clone(),
-                SoapRadialIntegralParameters {
-                    max_radial: self.parameters.max_radial,
-                    max_angular: self.parameters.max_angular,
-                    atomic_gaussian_width: self.parameters.atomic_gaussian_width,
-                    cutoff: self.parameters.cutoff,
-                }
-            ).expect("invalid radial integral parameters");
-            return RefCell::new(radial_integral);
+    pub(super) fn self_contribution(&self, species: i32, atomic_gaussian_width: f64) -> PairContribution {
+        let mut radial_integrals = self.radial_integral.get_or(|| {
+            RefCell::new(BTreeMap::new())
         }).borrow_mut();
+        let radial_integral = self.radial_integral_for_width(&mut radial_integrals, atomic_gaussian_width);
 
         let mut spherical_harmonics = self.spherical_harmonics.get_or(|| {
             RefCell::new(SphericalHarmonicsCache::new(self.parameters.max_angular))
@@ -243,7 +546,10 @@ impl SphericalExpansionByPair {
         // case where the pair distance is zero.
         radial_integral.compute(0.0, false);
         spherical_harmonics.compute(Vector3D::new(0.0, 0.0, 1.0), false);
-        let f_scaling = self.scaling_functions(0.0);
+        // the inner cutoff only applies to neighbors, and not to the central
+        // atom contribution
+        let f_scaling = self.parameters.cutoff_function.compute(0.0, self.parameters.cutoff)
+            * self.parameters.radial_scaling.compute(0.0, species);
 
         let factor = self.parameters.center_atom_weight
             * f_scaling
@@ -264,8 +570,14 @@ impl SphericalExpansionByPair {
     /// (-1) to store the data associated with self-pairs.
     fn do_self_contributions(&self, systems: &[Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
         debug_assert_eq!(descriptor.keys().names(), ["spherical_harmonics_l", "species_atom_1", "species_atom_2"]);
-        let self_contribution = self.self_contribution();
-
+        let density_weights = systems.iter()
+            .map(|system| self.density_weights(&**system))
+            .collect::<Result<Vec<_>, _>>()?;
+        let atomic_gaussian_widths = systems.iter()
+            .map(|system| self.atomic_gaussian_widths(&**system))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut self_contributions = BTreeMap::new();
         for (key, mut block) in descriptor.iter_mut() {
             let spherical_harmonics_l = key[0];
             let species_atom_1 = key[1];
@@ -302,8 +614,14 @@ impl SphericalExpansionByPair {
                     continue;
                 }
 
+                let width = atomic_gaussian_widths[structure.usize()][atom_1.usize()];
+                let self_contribution = self_contributions.entry((species_atom_1, width.to_bits())).or_insert_with(|| {
+                    self.self_contribution(species_atom_1, width)
+                });
+
+                let weight = density_weights[structure.usize()][atom_1.usize()];
                 for (property_i, &[n]) in data.properties.iter_fixed_size().enumerate() {
-                    array[[sample_i, 0, property_i]] = self_contribution.values[[0, n.usize()]];
+                    array[[sample_i, 0, property_i]] = weight * self_contribution.values[[0, n.usize()]];
                 }
             }
         }
@@ -311,17 +629,20 @@ impl SphericalExpansionByPair {
         return Ok(());
     }
 
-    /// Compute the contribution of a single pair and store the corresponding
-    /// data inside the given descriptor.
+    /// Compute the contribution of a single pair, where the neighbor has the
+    /// given `species` and its density has the given `atomic_gaussian_width`,
+    /// and store the corresponding data inside the given `contribution`.
     ///
-    /// This will store data both for the spherical expansion with `pair.first`
-    /// as the center and `pair.second` as the neighbor, and for the spherical
-    /// expansion with `pair.second` as the center and `pair.first` as the
-    /// neighbor.
+    /// If both atoms in the pair have the same density (see
+    /// `same_density_for_pair`), the contribution for the spherical expansion
+    /// with the neighbor as the center can be obtained with
+    /// `PairContribution::inverse_pair`.
     pub(super) fn compute_for_pair(
         &self,
         distance: f64,
         mut direction: Vector3D,
+        species: i32,
+        atomic_gaussian_width: f64,
         do_gradients: GradientsOptions,
         contribution: &mut PairContribution,
     ) {
@@ -336,18 +657,10 @@ impl SphericalExpansionByPair {
             direction = Vector3D::new(0.0, 0.0, 1.0);
         }
 
-        let mut radial_integral = self.radial_integral.get_or(|| {
-            let radial_integral = SoapRadialIntegralCache::new(
-                self.parameters.radial_basis.clone(),
-                SoapRadialIntegralParameters {
-                    max_radial: self.parameters.max_radial,
-                    max_angular: self.parameters.max_angular,
-                    atomic_gaussian_width: self.parameters.atomic_gaussian_width,
-                    cutoff: self.parameters.cutoff,
-                }
-            ).expect("invalid parameters");
-            return RefCell::new(radial_integral);
+        let mut radial_integrals = self.radial_integral.get_or(|| {
+            RefCell::new(BTreeMap::new())
         }).borrow_mut();
+        let radial_integral = self.radial_integral_for_width(&mut radial_integrals, atomic_gaussian_width);
 
         let mut spherical_harmonics = self.spherical_harmonics.get_or(|| {
             RefCell::new(SphericalHarmonicsCache::new(self.parameters.max_angular))
@@ -356,8 +669,8 @@ impl SphericalExpansionByPair {
         radial_integral.compute(distance, do_gradients.either());
         spherical_harmonics.compute(direction, do_gradients.either());
 
-        let f_scaling = self.scaling_functions(distance);
-        let f_scaling_grad = self.scaling_functions_gradient(distance);
+        let f_scaling = self.scaling_functions(distance, species);
+        let f_scaling_grad = self.scaling_functions_gradient(distance, species);
 
         let mut lm_index = 0;
         let mut lm_index_grad = 0;
@@ -415,12 +728,15 @@ impl SphericalExpansionByPair {
         }
     }
 
-    /// Accumulate a single pair `contribution` in the right block.
+    /// Accumulate a single pair `contribution` in the right block, scaling it
+    /// by the density `weight` of the neighbor atom.
+    #[allow(clippy::too_many_arguments)]
     fn accumulate_in_block(
         spherical_harmonics_l: usize,
         mut block: TensorBlockRefMut,
         sample: &[LabelValue],
         contribution: &PairContribution,
+        weight: f64,
         do_gradients: GradientsOptions,
         inverse_cell_pair_vector: Vector3D,
     ) {
@@ -436,7 +752,7 @@ impl SphericalExpansionByPair {
                 for (property_i, [n]) in data.properties.iter_fixed_size().enumerate() {
                     unsafe {
                         let out = array.uget_mut([sample_i, m, property_i]);
-                        *out += *contribution.values.uget([lm_start + m, n.usize()]);
+                        *out += weight * *contribution.values.uget([lm_start + m, n.usize()]);
                     }
                 }
             }
@@ -450,17 +766,20 @@ impl SphericalExpansionByPair {
                     debug_assert_eq!(gradient.samples.names(), ["sample", "structure", "atom"]);
 
                     // gradient of the pair contribution w.r.t. the position of
-                    // the first atom
+                    // the first atom. Gradient samples might be missing if the
+                    // user restricted the gradients to some of the atoms.
                     let first_grad_sample_i = gradient.samples.position(&[
                         sample_i.into(), /* structure */ sample[0], /* pair.first */ sample[2]
-                    ]).expect("missing first gradient sample");
-
-                    for spatial in 0..3 {
-                        for m in 0..(2 * spherical_harmonics_l + 1) {
-                            for (property_i, [n]) in gradient.properties.iter_fixed_size().enumerate() {
-                                unsafe {
-                                    let out = array.uget_mut([first_grad_sample_i, spatial, m, property_i]);
-                                    *out -= contribution_gradients.uget([spatial, lm_start + m, n.usize()]);
+                    ]);
+
+                    if let Some(first_grad_sample_i) = first_grad_sample_i {
+                        for spatial in 0..3 {
+                            for m in 0..(2 * spherical_harmonics_l + 1) {
+                                for (property_i, [n]) in gradient.properties.iter_fixed_size().enumerate() {
+                                    unsafe {
+                                        let out = array.uget_mut([first_grad_sample_i, spatial, m, property_i]);
+                                        *out -= weight * contribution_gradients.uget([spatial, lm_start + m, n.usize()]);
+                                    }
                                 }
                             }
                         }
@@ -470,14 +789,16 @@ impl SphericalExpansionByPair {
                     // the second atom
                     let second_grad_sample_i = gradient.samples.position(&[
                         sample_i.into(), /* structure */ sample[0], /* pair.second */ sample[3]
-                    ]).expect("missing second gradient sample");
-
-                    for spatial in 0..3 {
-                        for m in 0..(2 * spherical_harmonics_l + 1) {
-                            for (property_i, [n]) in gradient.properties.iter_fixed_size().enumerate() {
-                                unsafe {
-                                    let out = array.uget_mut([second_grad_sample_i, spatial, m, property_i]);
-                                    *out += contribution_gradients.uget([spatial, lm_start + m, n.usize()]);
+                    ]);
+
+                    if let Some(second_grad_sample_i) = second_grad_sample_i {
+                        for spatial in 0..3 {
+                            for m in 0..(2 * spherical_harmonics_l + 1) {
+                                for (property_i, [n]) in gradient.properties.iter_fixed_size().enumerate() {
+                                    unsafe {
+                                        let out = array.uget_mut([second_grad_sample_i, spatial, m, property_i]);
+                                        *out += weight * contribution_gradients.uget([spatial, lm_start + m, n.usize()]);
+                                    }
                                 }
                             }
                         }
@@ -500,7 +821,7 @@ impl SphericalExpansionByPair {
                                 for (property_i, [n]) in gradient.properties.iter_fixed_size().enumerate() {
                                     unsafe {
                                         let out = array.uget_mut([sample_i, spatial_1, spatial_2, m, property_i]);
-                                        *out += inverse_cell_pair_vector_2 * contribution_gradients.uget([spatial_1, lm_start + m, n.usize()]);
+                                        *out += weight * inverse_cell_pair_vector_2 * contribution_gradients.uget([spatial_1, lm_start + m, n.usize()]);
                                     }
                                 }
                             }
@@ -522,6 +843,10 @@ impl CalculatorBase for SphericalExpansionByPair {
         serde_json::to_string(&self.parameters).expect("failed to serialize to JSON")
     }
 
+    fn cutoff(&self) -> Option<f64> {
+        Some(self.parameters.cutoff)
+    }
+
     fn keys(&self, systems: &mut [Box<dyn System>]) -> Result<Labels, Error> {
         // the species part of the keys is the same for all l
         let species_keys = FullNeighborList { cutoff: self.parameters.cutoff, self_pairs: false }.keys(systems)?;
@@ -540,6 +865,8 @@ impl CalculatorBase for SphericalExpansionByPair {
             all_species_pairs.insert((species.into(), species.into()));
         }
 
+        all_species_pairs.retain(|(_, s2)| self.parameters.is_neighbor_species_included(s2.i32()));
+
         let mut keys = LabelsBuilder::new(vec![
             "spherical_harmonics_l",
             "species_atom_1",
@@ -688,17 +1015,13 @@ impl CalculatorBase for SphericalExpansionByPair {
     }
 
     fn properties(&self, keys: &Labels) -> Vec<Labels> {
-        let mut properties = LabelsBuilder::new(self.properties_names());
-        for n in 0..self.parameters.max_radial {
-            properties.add(&[n]);
-        }
-
-        return vec![properties.finish(); keys.count()];
+        return self.parameters.properties_by_angular(keys);
     }
 
-    #[time_graph::instrument(name = "SphericalExpansionByPair::compute")]
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "SphericalExpansionByPair::compute"))]
     fn compute(&mut self, systems: &mut [Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
         assert_eq!(descriptor.keys().names(), ["spherical_harmonics_l", "species_atom_1", "species_atom_2"]);
+        self.clear_radial_integrals();
 
         let do_gradients = GradientsOptions {
             positions: descriptor.block_by_id(0).gradient("positions").is_some(),
@@ -716,6 +1039,8 @@ impl CalculatorBase for SphericalExpansionByPair {
         for (system_i, system) in systems.iter_mut().enumerate() {
             system.compute_neighbors(self.parameters.cutoff)?;
             let species = system.species()?;
+            let density_weights = self.density_weights(&**system)?;
+            let atomic_gaussian_widths = self.atomic_gaussian_widths(&**system)?;
 
             let inverse_cell = if do_gradients.cell {
                 let cell = system.cell()?;
@@ -730,51 +1055,78 @@ impl CalculatorBase for SphericalExpansionByPair {
             };
 
             for (pair_id, pair) in system.pairs()?.iter().enumerate() {
-                let direction = pair.vector / pair.distance;
-                self.compute_for_pair(pair.distance, direction, do_gradients, &mut contribution);
+                let species_first = species[pair.first];
+                let species_second = species[pair.second];
 
+                let direction = pair.vector / pair.distance;
                 let inverse_cell_pair_vector = Vector3D::new(
                     pair.vector[0] * inverse_cell[0][0] + pair.vector[1] * inverse_cell[1][0] + pair.vector[2] * inverse_cell[2][0],
                     pair.vector[0] * inverse_cell[0][1] + pair.vector[1] * inverse_cell[1][1] + pair.vector[2] * inverse_cell[2][1],
                     pair.vector[0] * inverse_cell[0][2] + pair.vector[1] * inverse_cell[1][2] + pair.vector[2] * inverse_cell[2][2],
                 );
 
-                let species_first = species[pair.first];
-                let species_second = species[pair.second];
-                for spherical_harmonics_l in 0..=self.parameters.max_angular {
-                    let block_i = keys.position(&[
-                        spherical_harmonics_l.into(),
-                        species_first.into(),
-                        species_second.into(),
-                    ]);
-
-                    if let Some(block_i) = block_i {
-                        let sample = &[
-                            system_i.into(),
-                            pair_id.into(),
-                            pair.first.into(),
-                            pair.second.into(),
-                        ];
-
-                        SphericalExpansionByPair::accumulate_in_block(
-                            spherical_harmonics_l,
-                            descriptor.block_mut_by_id(block_i),
-                            sample,
-                            &contribution,
-                            do_gradients,
-                            inverse_cell_pair_vector,
-                        );
+                // the second atom only contributes to the density around the
+                // first one if its species is not excluded
+                let second_is_neighbor = self.parameters.is_neighbor_species_included(species_second);
+                if second_is_neighbor {
+                    self.compute_for_pair(
+                        pair.distance,
+                        direction,
+                        species_second,
+                        atomic_gaussian_widths[pair.second],
+                        do_gradients,
+                        &mut contribution
+                    );
+
+                    for spherical_harmonics_l in 0..=self.parameters.max_angular {
+                        let block_i = keys.position(&[
+                            spherical_harmonics_l.into(),
+                            species_first.into(),
+                            species_second.into(),
+                        ]);
+
+                        if let Some(block_i) = block_i {
+                            let sample = &[
+                                system_i.into(),
+                                pair_id.into(),
+                                pair.first.into(),
+                                pair.second.into(),
+                            ];
+
+                            SphericalExpansionByPair::accumulate_in_block(
+                                spherical_harmonics_l,
+                                descriptor.block_mut_by_id(block_i),
+                                sample,
+                                &contribution,
+                                density_weights[pair.second],
+                                do_gradients,
+                                inverse_cell_pair_vector,
+                            );
+                        }
                     }
                 }
 
                 // also check for the block with a reversed pair, except if
                 // we are handling a pair between an atom and it's own
-                // periodic image
-                if pair.first == pair.second {
+                // periodic image, or if the first atom is not a neighbor
+                if pair.first == pair.second || !self.parameters.is_neighbor_species_included(species_first) {
                     continue;
                 }
 
-                contribution.inverse_pair(&self.m_1_pow_l);
+                let width_first = atomic_gaussian_widths[pair.first];
+                let width_second = atomic_gaussian_widths[pair.second];
+                if second_is_neighbor && self.same_density_for_pair(species_first, width_first, species_second, width_second) {
+                    contribution.inverse_pair(&self.m_1_pow_l);
+                } else {
+                    self.compute_for_pair(
+                        pair.distance,
+                        -direction,
+                        species_first,
+                        width_first,
+                        do_gradients,
+                        &mut contribution
+                    );
+                }
 
                 for spherical_harmonics_l in 0..=self.parameters.max_angular {
                     let block_i = keys.position(&[
@@ -796,6 +1148,7 @@ impl CalculatorBase for SphericalExpansionByPair {
                             descriptor.block_mut_by_id(block_i),
                             sample,
                             &contribution,
+                            density_weights[pair.first],
                             do_gradients,
                             -inverse_cell_pair_vector,
                         );
@@ -821,7 +1174,7 @@ mod tests {
     use crate::calculators::{CalculatorBase, SphericalExpansion};
 
     use super::{SphericalExpansionByPair, SphericalExpansionParameters};
-    use super::super::{CutoffFunction, RadialScaling};
+    use super::super::{AtomicDensity, CutoffFunction, RadialScaling};
     use crate::calculators::radial_basis::RadialBasis;
 
 
@@ -830,11 +1183,19 @@ mod tests {
             cutoff: 3.5,
             max_radial: 6,
             max_angular: 6,
+            max_radial_by_angular: None,
             atomic_gaussian_width: 0.3,
+            atomic_gaussian_width_by_species: None,
+            density: AtomicDensity::Gaussian {},
             center_atom_weight: 1.0,
             radial_basis: RadialBasis::splined_gto(1e-8),
-            radial_scaling: RadialScaling::Willatt2018 { scale: 1.5, rate: 0.8, exponent: 2},
+            radial_scaling: RadialScaling::Willatt2018 { scale: 1.5, rate: 0.8, exponent: 2.0, scale_by_species: None },
             cutoff_function: CutoffFunction::ShiftedCosine { width: 0.5 },
+            inner_cutoff: None,
+            density_weight_by_species: None,
+            use_system_density_scaling: false,
+            use_system_atomic_gaussian_width: false,
+            excluded_neighbor_species: Vec::new(),
         }
     }
 
@@ -845,7 +1206,7 @@ mod tests {
         ).unwrap()) as Box<dyn CalculatorBase>);
 
         let system = test_system("water");
-        let options = crate::calculators::tests_utils::FinalDifferenceOptions {
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
             displacement: 1e-6,
             max_relative: 1e-5,
             epsilon: 1e-16,
@@ -860,7 +1221,7 @@ mod tests {
         ).unwrap()) as Box<dyn CalculatorBase>);
 
         let system = test_system("water");
-        let options = crate::calculators::tests_utils::FinalDifferenceOptions {
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
             displacement: 1e-6,
             max_relative: 1e-5,
             epsilon: 1e-16,
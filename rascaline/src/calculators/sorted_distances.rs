@@ -2,7 +2,7 @@ use equistore::{Labels, LabelsBuilder, TensorMap};
 
 use super::CalculatorBase;
 
-use crate::{Error, System};
+use crate::{Error, System, Vector3D};
 use crate::labels::{SpeciesFilter, SamplesBuilder};
 use crate::labels::AtomCenteredSamples;
 use crate::labels::{KeysBuilder, CenterSpeciesKeys, CenterSingleNeighborsSpeciesKeys};
@@ -37,11 +37,17 @@ impl CalculatorBase for SortedDistances {
         serde_json::to_string(self).expect("failed to serialize to JSON")
     }
 
+    fn cutoff(&self) -> Option<f64> {
+        Some(self.cutoff)
+    }
+
     fn keys(&self, systems: &mut [Box<dyn System>]) -> Result<Labels, Error> {
         if self.separate_neighbor_species {
             let builder = CenterSingleNeighborsSpeciesKeys {
                 cutoff: self.cutoff,
                 self_pairs: false,
+                species_center: SpeciesFilter::Any,
+                species_neighbor: SpeciesFilter::Any,
             };
             return builder.keys(systems);
         }
@@ -55,41 +61,29 @@ impl CalculatorBase for SortedDistances {
 
     fn samples(&self, keys: &Labels, systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
         let mut samples = Vec::new();
-        if self.separate_neighbor_species {
-            assert_eq!(keys.names(), ["species_center", "species_neighbor"]);
-            for [species_center, species_neighbor] in keys.iter_fixed_size() {
-                let builder = AtomCenteredSamples {
-                    cutoff: self.cutoff,
-                    species_center: SpeciesFilter::Single(species_center.i32()),
-                    species_neighbor: SpeciesFilter::Single(species_neighbor.i32()),
-                    self_pairs: false,
-                };
-
-                samples.push(builder.samples(systems)?);
-            }
-        } else {
-            assert_eq!(keys.names(), ["species_center"]);
-            for [species_center] in keys.iter_fixed_size() {
-                let builder = AtomCenteredSamples {
-                    cutoff: self.cutoff,
-                    species_center: SpeciesFilter::Single(species_center.i32()),
-                    species_neighbor: SpeciesFilter::Any,
-                    self_pairs: false,
-                };
-
-                samples.push(builder.samples(systems)?);
-            }
+        for builder in self.samples_builders(keys) {
+            samples.push(builder.samples(systems)?);
         }
 
         return Ok(samples);
     }
 
-    fn supports_gradient(&self, _parameter: &str) -> bool {
-        return false;
+    fn supports_gradient(&self, parameter: &str) -> bool {
+        match parameter {
+            "positions" | "positions_hessian" => true,
+            _ => false,
+        }
     }
 
-    fn positions_gradient_samples(&self, _: &Labels, _: &[Labels], _: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
-        unimplemented!()
+    fn positions_gradient_samples(&self, keys: &Labels, samples: &[Labels], systems: &mut [Box<dyn System>]) -> Result<Vec<Labels>, Error> {
+        assert_eq!(keys.count(), samples.len());
+
+        let mut gradient_samples = Vec::new();
+        for (builder, samples) in self.samples_builders(keys).into_iter().zip(samples) {
+            gradient_samples.push(builder.gradients_for(systems, samples)?);
+        }
+
+        return Ok(gradient_samples);
     }
 
     fn components(&self, keys: &Labels) -> Vec<Vec<Labels>> {
@@ -110,7 +104,7 @@ impl CalculatorBase for SortedDistances {
         return vec![properties; keys.count()];
     }
 
-    #[time_graph::instrument(name = "SortedDistances::compute")]
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "SortedDistances::compute"))]
     fn compute(&mut self, systems: &mut [Box<dyn System>], descriptor: &mut TensorMap) -> Result<(), Error> {
         if self.separate_neighbor_species {
             assert_eq!(descriptor.keys().names(), ["species_center", "species_neighbor"]);
@@ -125,41 +119,119 @@ impl CalculatorBase for SortedDistances {
                 None
             };
 
-            let block_data = block.data_mut();
-            let array = block_data.values.to_array_mut();
+            let samples = block.samples();
+            let properties = block.data_mut().properties.clone();
 
-            for (sample_i, [structure_i, center_i]) in block_data.samples.iter_fixed_size().enumerate() {
+            for (sample_i, &[structure_i, center_i]) in samples.iter_fixed_size().enumerate() {
                 let center_i = center_i.usize();
 
                 let system = &mut systems[structure_i.usize()];
                 system.compute_neighbors(self.cutoff)?;
                 let species = system.species()?;
 
-                let mut distances = Vec::new();
+                let mut neighbors = Vec::new();
                 for pair in system.pairs_containing(center_i)? {
+                    let (neighbor_i, vector) = if pair.first == center_i {
+                        (pair.second, pair.vector)
+                    } else {
+                        debug_assert_eq!(pair.second, center_i);
+                        (pair.first, -pair.vector)
+                    };
+
                     if let Some(species_neighbor) = species_neighbor {
-                        let neighbor_i = if pair.first == center_i {
-                            pair.second
-                        } else {
-                            debug_assert_eq!(pair.second, center_i);
-                            pair.first
+                        if species[neighbor_i] != species_neighbor {
+                            continue;
+                        }
+                    }
+
+                    neighbors.push(SortedNeighbor {
+                        atom: neighbor_i,
+                        distance: pair.distance,
+                        vector: vector,
+                    });
+                }
+
+                // Sort the neighbors, the missing ones are replaced by
+                // `self.cutoff` below
+                if !system.pairs_sorted_by_distance()? {
+                    neighbors.sort_unstable_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap());
+                }
+
+                let block_data = block.data_mut();
+                let array = block_data.values.to_array_mut();
+                for (property_i, [neighbor]) in properties.iter_fixed_size().enumerate() {
+                    array[[sample_i, property_i]] = neighbors.get(neighbor.usize()).map_or(self.cutoff, |n| n.distance);
+                }
+
+                if let Some(mut gradient) = block.gradient_mut("positions") {
+                    let gradient = gradient.data_mut();
+                    let array = gradient.values.to_array_mut();
+
+                    for (property_i, [neighbor]) in properties.iter_fixed_size().enumerate() {
+                        let neighbor = match neighbors.get(neighbor.usize()) {
+                            // pairs between an atom and its own periodic
+                            // image do not depend on positions
+                            Some(neighbor) if neighbor.atom != center_i => neighbor,
+                            _ => continue,
                         };
 
-                        if species[neighbor_i] == species_neighbor {
-                            distances.push(pair.distance);
+                        // gradient samples might be missing if the user
+                        // restricted the gradients to some of the atoms
+                        let direction = neighbor.vector / neighbor.distance;
+                        let atoms = [(center_i, -1.0), (neighbor.atom, 1.0)];
+                        for &(atom, sign) in &atoms {
+                            let grad_sample_i = gradient.samples.position(&[
+                                sample_i.into(), structure_i, atom.into()
+                            ]);
+
+                            if let Some(grad_sample_i) = grad_sample_i {
+                                for spatial in 0..3 {
+                                    array[[grad_sample_i, spatial, property_i]] = sign * direction[spatial];
+                                }
+                            }
                         }
-                    } else {
-                        distances.push(pair.distance);
                     }
                 }
 
-                // Sort, resize to limit to at most `self.max_neighbors` values
-                // and pad the distance vectors as needed
-                distances.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
-                distances.resize(self.max_neighbors, self.cutoff);
+                if let Some(mut hessian) = block.gradient_mut("positions_hessian") {
+                    let hessian = hessian.data_mut();
+                    let array = hessian.values.to_array_mut();
 
-                for (property_i, [neighbor]) in block_data.properties.iter_fixed_size().enumerate() {
-                    array[[sample_i, property_i]] = distances[neighbor.usize()];
+                    for (property_i, [neighbor]) in properties.iter_fixed_size().enumerate() {
+                        let neighbor = match neighbors.get(neighbor.usize()) {
+                            Some(neighbor) if neighbor.atom != center_i => neighbor,
+                            _ => continue,
+                        };
+
+                        // the second derivative of the distance w.r.t. the
+                        // pair vector is `(I - r r^T / |r|^2) / |r|`, and the
+                        // pair vector depends on `+neighbor - center`
+                        let direction = neighbor.vector / neighbor.distance;
+                        let atoms = [(center_i, -1.0), (neighbor.atom, 1.0)];
+                        for &(atom_1, sign_1) in &atoms {
+                            for &(atom_2, sign_2) in &atoms {
+                                let hessian_sample_i = hessian.samples.position(&[
+                                    sample_i.into(), structure_i, atom_1.into(), atom_2.into()
+                                ]);
+
+                                let hessian_sample_i = if let Some(hessian_sample_i) = hessian_sample_i {
+                                    hessian_sample_i
+                                } else {
+                                    // the user restricted the gradients to
+                                    // other atoms
+                                    continue;
+                                };
+
+                                for spatial_1 in 0..3 {
+                                    for spatial_2 in 0..3 {
+                                        let identity = if spatial_1 == spatial_2 { 1.0 } else { 0.0 };
+                                        let value = (identity - direction[spatial_1] * direction[spatial_2]) / neighbor.distance;
+                                        array[[hessian_sample_i, spatial_1, spatial_2, property_i]] = sign_1 * sign_2 * value;
+                                    }
+                                }
+                            }
+                        }
+                    }
                 }
             }
         }
@@ -168,13 +240,54 @@ impl CalculatorBase for SortedDistances {
     }
 }
 
+impl SortedDistances {
+    /// Get the samples builders corresponding to each one of the `keys`
+    fn samples_builders(&self, keys: &Labels) -> Vec<AtomCenteredSamples> {
+        let mut builders = Vec::new();
+        if self.separate_neighbor_species {
+            assert_eq!(keys.names(), ["species_center", "species_neighbor"]);
+            for [species_center, species_neighbor] in keys.iter_fixed_size() {
+                builders.push(AtomCenteredSamples {
+                    cutoff: self.cutoff,
+                    species_center: SpeciesFilter::Single(species_center.i32()),
+                    species_neighbor: SpeciesFilter::Single(species_neighbor.i32()),
+                    self_pairs: false,
+                });
+            }
+        } else {
+            assert_eq!(keys.names(), ["species_center"]);
+            for [species_center] in keys.iter_fixed_size() {
+                builders.push(AtomCenteredSamples {
+                    cutoff: self.cutoff,
+                    species_center: SpeciesFilter::Single(species_center.i32()),
+                    species_neighbor: SpeciesFilter::Any,
+                    self_pairs: false,
+                });
+            }
+        }
+
+        return builders;
+    }
+}
+
+/// A neighbor of the central atom, used to sort neighbors by distance
+struct SortedNeighbor {
+    /// index of the neighbor in the system
+    atom: usize,
+    /// distance between the central atom and this neighbor
+    distance: f64,
+    /// vector from the central atom to this neighbor
+    vector: Vector3D,
+}
+
 #[cfg(test)]
 mod tests {
     use ndarray::{s, aview1};
     use equistore::Labels;
 
     use crate::systems::test_utils::test_systems;
-    use crate::Calculator;
+    use crate::systems::UnitCell;
+    use crate::{Calculator, SimpleSystem, Vector3D};
 
     use super::super::CalculatorBase;
     use super::SortedDistances;
@@ -233,4 +346,47 @@ mod tests {
             calculator, &mut systems, &keys, &samples, &properties
         );
     }
+
+    /// System where all the distances are different, to prevent neighbors
+    /// from being re-ordered when computing finite differences
+    fn non_degenerate_system() -> SimpleSystem {
+        let mut system = SimpleSystem::new(UnitCell::infinite());
+        system.add_atom(8, Vector3D::new(0.0, 0.0, 0.0));
+        system.add_atom(1, Vector3D::new(0.9, 0.1, -0.2));
+        system.add_atom(1, Vector3D::new(-0.3, 1.1, 0.2));
+        system.add_atom(6, Vector3D::new(0.4, -0.3, 1.3));
+        return system;
+    }
+
+    #[test]
+    fn finite_differences_positions() {
+        let calculator = Calculator::from(Box::new(SortedDistances{
+            cutoff: 2.0,
+            max_neighbors: 4,
+            separate_neighbor_species: false,
+        }) as Box<dyn CalculatorBase>);
+
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
+            displacement: 1e-6,
+            max_relative: 1e-6,
+            epsilon: 1e-16,
+        };
+        crate::calculators::tests_utils::finite_differences_positions(calculator, &non_degenerate_system(), options);
+    }
+
+    #[test]
+    fn finite_differences_positions_hessian() {
+        let calculator = Calculator::from(Box::new(SortedDistances{
+            cutoff: 2.0,
+            max_neighbors: 4,
+            separate_neighbor_species: true,
+        }) as Box<dyn CalculatorBase>);
+
+        let options = crate::calculators::tests_utils::FiniteDifferenceOptions {
+            displacement: 1e-6,
+            max_relative: 1e-5,
+            epsilon: 1e-9,
+        };
+        crate::calculators::tests_utils::finite_differences_positions_hessian(calculator, &non_degenerate_system(), options);
+    }
 }
@@ -1,11 +1,14 @@
 use ndarray::Axis;
-use approx::{assert_relative_eq, assert_ulps_eq};
+use approx::assert_ulps_eq;
 
 use equistore::{Labels, TensorMap, LabelsBuilder};
 
 use crate::calculator::LabelsSelection;
 use crate::{CalculationOptions, Calculator};
-use crate::systems::{System, SimpleSystem, UnitCell};
+use crate::systems::System;
+
+pub use crate::testing::{FiniteDifferenceOptions, finite_differences_positions, finite_differences_cell};
+pub use crate::testing::{finite_differences_positions_hessian, finite_differences_density_scaling};
 
 /// Check that computing a partial subset of features/samples works as intended
 /// for the given `calculator` and `systems`.
@@ -232,145 +235,3 @@ fn check_compute_partial_both(
         }
     }
 }
-
-#[derive(Debug, Clone, Copy)]
-pub struct FinalDifferenceOptions {
-    /// distance each atom will be displaced in each direction when computing
-    /// finite differences
-    pub displacement: f64,
-    /// Maximal relative error. 10 * displacement is a good starting point
-    pub max_relative: f64,
-    /// Threshold below which all values are considered zero. This should be
-    /// very small (1e-16) to prevent false positives (if all values & gradients
-    /// are below that threshold, tests will pass even with wrong gradients)
-    pub epsilon: f64,
-}
-
-/// Check that analytical gradients with respect to positions agree with a
-/// finite difference calculation of the gradients.
-pub fn finite_differences_positions(mut calculator: Calculator, system: &SimpleSystem, options: FinalDifferenceOptions) {
-    let calculation_options = CalculationOptions {
-        gradients: &["positions"],
-        ..Default::default()
-    };
-    let reference = calculator.compute(&mut [Box::new(system.clone())], calculation_options).unwrap();
-
-    for atom_i in 0..system.size().unwrap() {
-        for spatial in 0..3 {
-            let mut system_pos = system.clone();
-            system_pos.positions_mut()[atom_i][spatial] += options.displacement / 2.0;
-            let updated_pos = calculator.compute(&mut [Box::new(system_pos)], Default::default()).unwrap();
-
-            let mut system_neg = system.clone();
-            system_neg.positions_mut()[atom_i][spatial] -= options.displacement / 2.0;
-            let updated_neg = calculator.compute(&mut [Box::new(system_neg)], Default::default()).unwrap();
-
-            assert_eq!(updated_pos.keys(), reference.keys());
-            assert_eq!(updated_neg.keys(), reference.keys());
-
-            for (block_i, (_, block)) in reference.iter().enumerate() {
-                let gradients = &block.gradient("positions").unwrap();
-                let block_pos = &updated_pos.block_by_id(block_i);
-                let block_neg = &updated_neg.block_by_id(block_i);
-
-                for (gradient_i, [sample_i, _, atom]) in gradients.samples().iter_fixed_size().enumerate() {
-                    if atom.usize() != atom_i {
-                        continue;
-                    }
-                    let sample_i = sample_i.usize();
-
-                    // check that the same sample is here in both descriptors
-                    assert_eq!(block_pos.samples()[sample_i], block.samples()[sample_i]);
-                    assert_eq!(block_neg.samples()[sample_i], block.samples()[sample_i]);
-
-                    let value_pos = block_pos.values().to_array().index_axis(Axis(0), sample_i);
-                    let value_neg = block_neg.values().to_array().index_axis(Axis(0), sample_i);
-                    let gradient = gradients.values().to_array().index_axis(Axis(0), gradient_i);
-                    let gradient = gradient.index_axis(Axis(0), spatial);
-
-                    assert_eq!(value_pos.shape(), gradient.shape());
-                    assert_eq!(value_neg.shape(), gradient.shape());
-
-                    let mut finite_difference = value_pos.to_owned().clone();
-                    finite_difference -= &value_neg;
-                    finite_difference /= options.displacement;
-
-                    assert_relative_eq!(
-                        finite_difference, gradient,
-                        epsilon=options.epsilon,
-                        max_relative=options.max_relative,
-                    );
-                }
-            }
-        }
-    }
-}
-
-
-/// Check that analytical gradients with respect to cell agree with a
-/// finite difference calculation of the gradients.
-pub fn finite_differences_cell(mut calculator: Calculator, system: &SimpleSystem, options: FinalDifferenceOptions) {
-    let calculation_options = CalculationOptions {
-        gradients: &["cell"],
-        ..Default::default()
-    };
-    let reference = calculator.compute(&mut [Box::new(system.clone())], calculation_options).unwrap();
-    let original_cell = system.cell().unwrap().matrix();
-    let original_cell_inverse = original_cell.inverse();
-
-    for spatial_1 in 0..3 {
-        for spatial_2 in 0..3 {
-            let mut deformed_cell = original_cell;
-            deformed_cell[spatial_1][spatial_2] += options.displacement / 2.0;
-
-            let mut system_pos = system.clone();
-            system_pos.set_cell(UnitCell::from(deformed_cell));
-            for position in system_pos.positions_mut() {
-                *position = deformed_cell * (original_cell_inverse * *position);
-            }
-            let updated_pos = calculator.compute(&mut [Box::new(system_pos)], Default::default()).unwrap();
-
-            deformed_cell[spatial_1][spatial_2] -= options.displacement;
-
-            let mut system_neg = system.clone();
-            system_neg.set_cell(UnitCell::from(deformed_cell));
-            for position in system_neg.positions_mut() {
-                *position = deformed_cell * (original_cell_inverse * *position);
-            }
-            let updated_neg = calculator.compute(&mut [Box::new(system_neg)], Default::default()).unwrap();
-
-            for (block_i, (_, block)) in reference.iter().enumerate() {
-                let gradients = &block.gradient("cell").unwrap();
-                let block_pos = &updated_pos.block_by_id(block_i);
-                let block_neg = &updated_neg.block_by_id(block_i);
-
-                for (gradient_i, [sample_i]) in gradients.samples().iter_fixed_size().enumerate() {
-                    let sample_i = sample_i.usize();
-
-                    // check that the same sample is here in both descriptors
-                    assert_eq!(block_pos.samples()[sample_i], block.samples()[sample_i]);
-                    assert_eq!(block_neg.samples()[sample_i], block.samples()[sample_i]);
-
-                    let value_pos = block_pos.values().to_array().index_axis(Axis(0), sample_i);
-                    let value_neg = block_neg.values().to_array().index_axis(Axis(0), sample_i);
-                    let gradient = gradients.values().to_array().index_axis(Axis(0), gradient_i);
-                    let gradient = gradient.index_axis(Axis(0), spatial_1);
-                    let gradient = gradient.index_axis(Axis(0), spatial_2);
-
-                    assert_eq!(value_pos.shape(), gradient.shape());
-                    assert_eq!(value_neg.shape(), gradient.shape());
-
-                    let mut finite_difference = value_pos.to_owned().clone();
-                    finite_difference -= &value_neg;
-                    finite_difference /= options.displacement;
-
-                    assert_relative_eq!(
-                        finite_difference, gradient,
-                        epsilon=options.epsilon,
-                        max_relative=options.max_relative,
-                    );
-                }
-            }
-        }
-    }
-}
@@ -1,10 +1,14 @@
 use std::str::Utf8Error;
 
+use crate::systems::InvalidSystem;
+
 #[non_exhaustive]
 #[derive(Debug)]
 pub enum Error {
     /// Got an invalid parameter value in a function
     InvalidParameter(String),
+    /// A system failed validation, see `System::validate`
+    InvalidSystem(InvalidSystem),
     /// Error while serializing/deserializing data
     Json(serde_json::Error),
     /// Error due to C strings containing non-utf8 data
@@ -31,6 +35,7 @@ impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::InvalidParameter(e) => write!(f, "invalid parameter: {}", e),
+            Error::InvalidSystem(e) => write!(f, "invalid system: {}", e),
             Error::Json(e) => write!(f, "json error: {}", e),
             Error::Utf8(e) => write!(f, "utf8 decoding error: {}", e),
             Error::Chemfiles(e) => write!(f, "chemfiles error: {}", e),
@@ -46,6 +51,7 @@ impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Error::InvalidParameter(_) |
+            Error::InvalidSystem(_) |
             Error::Internal(_) |
             Error::Chemfiles(_) |
             Error::BufferSize(_) |
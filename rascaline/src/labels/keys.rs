@@ -3,6 +3,9 @@ use std::collections::BTreeSet;
 use equistore::{Labels, LabelsBuilder};
 
 use crate::{System, Error};
+use crate::systems::center_atoms;
+
+use super::SpeciesFilter;
 
 
 /// Common interface to create a set of equistore's `TensorMap` keys from systems
@@ -18,8 +21,11 @@ impl KeysBuilder for CenterSpeciesKeys {
     fn keys(&self, systems: &mut [Box<dyn System>]) -> Result<Labels, Error> {
         let mut all_species = BTreeSet::new();
         for system in systems {
-            for &species in system.species()? {
-                all_species.insert(species);
+            let centers = center_atoms(&**system)?;
+            for (&species, &is_center) in system.species()?.iter().zip(&centers) {
+                if is_center {
+                    all_species.insert(species);
+                }
             }
         }
 
@@ -63,6 +69,12 @@ pub struct CenterSingleNeighborsSpeciesKeys {
     pub cutoff: f64,
     /// Should we consider an atom to be it's own neighbor or not?
     pub self_pairs: bool,
+    /// Filter for the central atom species. This can not be
+    /// `SpeciesFilter::AllOf`.
+    pub species_center: SpeciesFilter,
+    /// Filter for the neighbor atom species. This can not be
+    /// `SpeciesFilter::AllOf`.
+    pub species_neighbor: SpeciesFilter,
 }
 
 impl KeysBuilder for CenterSingleNeighborsSpeciesKeys {
@@ -74,18 +86,32 @@ impl KeysBuilder for CenterSingleNeighborsSpeciesKeys {
             system.compute_neighbors(self.cutoff)?;
 
             let species = system.species()?;
+            // ghost atoms and atoms outside of the centers mask are only used
+            // as neighbors, never as centers
+            let centers = center_atoms(&**system)?;
             for pair in system.pairs()? {
-                all_species_pairs.insert((species[pair.first], species[pair.second]));
-                all_species_pairs.insert((species[pair.second], species[pair.first]));
+                if centers[pair.first] {
+                    all_species_pairs.insert((species[pair.first], species[pair.second]));
+                }
+
+                if centers[pair.second] {
+                    all_species_pairs.insert((species[pair.second], species[pair.first]));
+                }
             }
 
             if self.self_pairs {
-                for &species in species {
-                    all_species_pairs.insert((species, species));
+                for (&species, &is_center) in species.iter().zip(&centers) {
+                    if is_center {
+                        all_species_pairs.insert((species, species));
+                    }
                 }
             }
         }
 
+        all_species_pairs.retain(|&(center, neighbor)| {
+            self.species_center.matches(center) && self.species_neighbor.matches(neighbor)
+        });
+
         let mut keys = LabelsBuilder::new(vec!["species_center", "species_neighbor"]);
         for (center, neighbor) in all_species_pairs {
             keys.add(&[center, neighbor]);
@@ -105,6 +131,12 @@ pub struct CenterTwoNeighborsSpeciesKeys {
     pub self_pairs: bool,
     /// Are neighbor atoms keys symmetric with respect to exchange or not?
     pub symmetric: bool,
+    /// Filter for the central atom species. This can not be
+    /// `SpeciesFilter::AllOf`.
+    pub species_center: SpeciesFilter,
+    /// Filter for both neighbor atom species. This can not be
+    /// `SpeciesFilter::AllOf`.
+    pub species_neighbor: SpeciesFilter,
 }
 
 impl KeysBuilder for CenterTwoNeighborsSpeciesKeys {
@@ -115,9 +147,17 @@ impl KeysBuilder for CenterTwoNeighborsSpeciesKeys {
         for system in systems {
             system.compute_neighbors(self.cutoff)?;
             let species = system.species()?;
+            let centers = center_atoms(&**system)?;
 
             for center in 0..system.size()? {
+                if !centers[center] {
+                    continue;
+                }
+
                 let species_center = species[center];
+                if !self.species_center.matches(species_center) {
+                    continue;
+                }
 
                 // all neighbor species around the current center
                 let mut neighbor_species = BTreeSet::new();
@@ -136,6 +176,8 @@ impl KeysBuilder for CenterTwoNeighborsSpeciesKeys {
                     neighbor_species.insert(species_center);
                 }
 
+                neighbor_species.retain(|&species| self.species_neighbor.matches(species));
+
                 // create keys
                 for &species_neighbor_1 in &neighbor_species {
                     for &species_neighbor_2 in &neighbor_species {
@@ -157,3 +199,47 @@ impl KeysBuilder for CenterTwoNeighborsSpeciesKeys {
         return Ok(keys_builder.finish());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::systems::test_utils::test_systems;
+
+    use super::*;
+
+    #[test]
+    fn species_filters() {
+        let mut systems = test_systems(&["CH", "water"]);
+
+        let builder = CenterSingleNeighborsSpeciesKeys {
+            cutoff: 2.0,
+            self_pairs: true,
+            species_center: SpeciesFilter::Any,
+            species_neighbor: SpeciesFilter::Any,
+        };
+        let keys = builder.keys(&mut systems).unwrap();
+        assert_eq!(keys.count(), 7);
+
+        let builder = CenterSingleNeighborsSpeciesKeys {
+            cutoff: 2.0,
+            self_pairs: true,
+            species_center: SpeciesFilter::Range(2..=10),
+            species_neighbor: SpeciesFilter::NoneOf(vec![1]),
+        };
+        let keys = builder.keys(&mut systems).unwrap();
+        assert_eq!(keys.count(), 1);
+        assert_eq!(keys[0], [6, 6]);
+
+        let builder = CenterTwoNeighborsSpeciesKeys {
+            cutoff: 2.0,
+            self_pairs: true,
+            symmetric: true,
+            species_center: SpeciesFilter::Any,
+            species_neighbor: SpeciesFilter::NoneOf(vec![1]),
+        };
+        let keys = builder.keys(&mut systems).unwrap();
+        for &[_, neighbor_1, neighbor_2] in keys.iter_fixed_size() {
+            assert_ne!(neighbor_1.i32(), 1);
+            assert_ne!(neighbor_2.i32(), 1);
+        }
+    }
+}
@@ -1,6 +1,8 @@
 mod samples;
 
 pub use self::samples::{SpeciesFilter, SamplesBuilder};
+pub use self::samples::hessian_samples_from_gradients;
+pub use self::samples::{gradient_samples_mapping, GradientSamplesMapping};
 pub use self::samples::AtomCenteredSamples;
 pub use self::samples::LongRangeSamplesPerAtom;
 
@@ -9,3 +11,7 @@ pub use self::keys::KeysBuilder;
 pub use self::keys::CenterSpeciesKeys;
 pub use self::keys::{CenterSingleNeighborsSpeciesKeys, AllSpeciesPairsKeys};
 pub use self::keys::{CenterTwoNeighborsSpeciesKeys};
+
+mod operations;
+pub use self::operations::{labels_union, labels_intersection, labels_difference};
+pub use self::operations::labels_mapping;
@@ -0,0 +1,116 @@
+use equistore::{Labels, LabelsBuilder};
+
+use crate::Error;
+
+/// Check that `first` and `second` have the same names before running the
+/// `operation` on them
+fn check_names(operation: &str, first: &Labels, second: &Labels) -> Result<(), Error> {
+    if first.names() != second.names() {
+        return Err(Error::InvalidParameter(format!(
+            "can not compute the {} of labels with different names: [{}] and [{}]",
+            operation, first.names().join(", "), second.names().join(", ")
+        )));
+    }
+    return Ok(());
+}
+
+/// Get the union of `first` and `second`, containing all the entries of
+/// `first` (in the same order), followed by the entries of `second` which are
+/// not in `first`.
+///
+/// Both labels must have the same names.
+pub fn labels_union(first: &Labels, second: &Labels) -> Result<Labels, Error> {
+    check_names("union", first, second)?;
+
+    let mut builder = LabelsBuilder::new(first.names());
+    for entry in first.iter() {
+        builder.add(entry);
+    }
+
+    for entry in second.iter() {
+        if !first.contains(entry) {
+            builder.add(entry);
+        }
+    }
+
+    return Ok(builder.finish());
+}
+
+/// Get the intersection of `first` and `second`, containing the entries of
+/// `first` which are also in `second`, in the same order as in `first`.
+///
+/// Both labels must have the same names.
+pub fn labels_intersection(first: &Labels, second: &Labels) -> Result<Labels, Error> {
+    check_names("intersection", first, second)?;
+
+    let mut builder = LabelsBuilder::new(first.names());
+    for entry in first.iter() {
+        if second.contains(entry) {
+            builder.add(entry);
+        }
+    }
+
+    return Ok(builder.finish());
+}
+
+/// Get the difference of `first` and `second`, containing the entries of
+/// `first` which are not in `second`, in the same order as in `first`.
+///
+/// Both labels must have the same names.
+pub fn labels_difference(first: &Labels, second: &Labels) -> Result<Labels, Error> {
+    check_names("difference", first, second)?;
+
+    let mut builder = LabelsBuilder::new(first.names());
+    for entry in first.iter() {
+        if !second.contains(entry) {
+            builder.add(entry);
+        }
+    }
+
+    return Ok(builder.finish());
+}
+
+/// Get the position in `to` of each entry in `from`, or `None` if the entry
+/// is not part of `to`. The resulting vector has one element for each entry
+/// in `from`.
+///
+/// This can be used to move data associated with one set of labels (for
+/// example the samples of a block) to another set of labels.
+///
+/// Both labels must have the same names.
+pub fn labels_mapping(from: &Labels, to: &Labels) -> Result<Vec<Option<usize>>, Error> {
+    check_names("mapping", from, to)?;
+
+    return Ok(from.iter().map(|entry| to.position(entry)).collect());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_operations() {
+        let first = Labels::new(["structure", "center"], &[[0, 1], [0, 0], [1, 2]]);
+        let second = Labels::new(["structure", "center"], &[[1, 2], [2, 0], [0, 1]]);
+
+        let union = labels_union(&first, &second).unwrap();
+        assert_eq!(union, Labels::new(["structure", "center"], &[[0, 1], [0, 0], [1, 2], [2, 0]]));
+
+        let intersection = labels_intersection(&first, &second).unwrap();
+        assert_eq!(intersection, Labels::new(["structure", "center"], &[[0, 1], [1, 2]]));
+
+        let difference = labels_difference(&first, &second).unwrap();
+        assert_eq!(difference, Labels::new(["structure", "center"], &[[0, 0]]));
+
+        let difference = labels_difference(&first, &first).unwrap();
+        assert_eq!(difference.count(), 0);
+        assert_eq!(difference.names(), ["structure", "center"]);
+
+        let mapping = labels_mapping(&first, &second).unwrap();
+        assert_eq!(mapping, [Some(2), None, Some(0)]);
+
+        let other = Labels::new(["structure", "atom"], &[[0, 1]]);
+        let error = labels_union(&first, &other).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: can not compute the union of labels with different names: [structure, center] and [structure, atom]");
+    }
+}
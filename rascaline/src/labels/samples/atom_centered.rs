@@ -3,6 +3,7 @@ use std::collections::BTreeSet;
 use equistore::{Labels, LabelsBuilder};
 
 use crate::{Error, System};
+use crate::systems::center_atoms;
 use super::{SamplesBuilder, SpeciesFilter};
 
 
@@ -34,11 +35,14 @@ impl SamplesBuilder for AtomCenteredSamples {
         for (system_i, system) in systems.iter_mut().enumerate() {
             system.compute_neighbors(self.cutoff)?;
             let species = system.species()?;
+            // ghost atoms and atoms outside of the centers mask are only used
+            // as neighbors, never as centers
+            let centers = center_atoms(&**system)?;
 
             match &self.species_neighbor {
                 SpeciesFilter::Any => {
                     for (center_i, &species_center) in species.iter().enumerate() {
-                        if self.species_center.matches(species_center) {
+                        if centers[center_i] && self.species_center.matches(species_center) {
                             builder.add(&[system_i, center_i]);
                         }
                     }
@@ -46,7 +50,7 @@ impl SamplesBuilder for AtomCenteredSamples {
                 SpeciesFilter::AllOf(requested_species) => {
                     let mut neighbor_species = BTreeSet::new();
                     for (center_i, &species_center) in species.iter().enumerate() {
-                        if self.species_center.matches(species_center) {
+                        if centers[center_i] && self.species_center.matches(species_center) {
                             for pair in system.pairs_containing(center_i)? {
                                 let neighbor = if pair.first == center_i {
                                     pair.second
@@ -71,7 +75,7 @@ impl SamplesBuilder for AtomCenteredSamples {
                 selection => {
                     let mut matching_centers = BTreeSet::new();
                     for (center_i, &species_center) in species.iter().enumerate() {
-                        if self.species_center.matches(species_center) {
+                        if centers[center_i] && self.species_center.matches(species_center) {
                             if self.self_pairs && selection.matches(species_center) {
                                 matching_centers.insert(center_i);
                             }
@@ -307,4 +311,76 @@ mod tests {
             ]
         ));
     }
+
+    #[test]
+    fn ghost_atoms() {
+        use crate::Vector3D;
+        use crate::systems::{SimpleSystem, UnitCell, GhostAtom};
+
+        let mut system = SimpleSystem::new(UnitCell::infinite());
+        system.add_atom(8, Vector3D::new(0.0, 0.0, 0.0));
+        system.add_atom(1, Vector3D::new(0.0, 0.75545, -0.58895));
+        system.add_ghost_atom(1, Vector3D::new(0.0, -0.75545, -0.58895), GhostAtom {
+            rank: 1,
+            local_index: 0,
+        });
+        assert_eq!(system.local_size().unwrap(), 2);
+
+        let mut systems = vec![Box::new(system) as Box<dyn System>];
+        let builder = AtomCenteredSamples {
+            cutoff: 2.0,
+            species_center: SpeciesFilter::Any,
+            species_neighbor: SpeciesFilter::Any,
+            self_pairs: true,
+        };
+
+        // the ghost atom is not used as a center
+        let samples = builder.samples(&mut systems).unwrap();
+        assert_eq!(samples, Labels::new(["structure", "center"], &[[0, 0], [0, 1]]));
+
+        // but it is used as a neighbor
+        let gradient_samples = builder.gradients_for(&mut systems, &samples).unwrap();
+        assert_eq!(gradient_samples, Labels::new(
+            ["sample", "structure", "atom"],
+            &[
+                [0, 0, 0], [0, 0, 1], [0, 0, 2],
+                [1, 0, 0], [1, 0, 1], [1, 0, 2],
+            ]
+        ));
+    }
+
+    #[test]
+    fn centers_mask() {
+        use crate::Vector3D;
+        use crate::systems::{SimpleSystem, UnitCell};
+        use crate::labels::{KeysBuilder, CenterSpeciesKeys};
+
+        let mut system = SimpleSystem::new(UnitCell::infinite());
+        system.add_atom(8, Vector3D::new(0.0, 0.0, 0.0));
+        system.add_atom(1, Vector3D::new(0.0, 0.75545, -0.58895));
+        system.add_atom(1, Vector3D::new(0.0, -0.75545, -0.58895));
+        system.set_centers(vec![true, false, false]).unwrap();
+
+        let mut systems = vec![Box::new(system) as Box<dyn System>];
+        let builder = AtomCenteredSamples {
+            cutoff: 2.0,
+            species_center: SpeciesFilter::Any,
+            species_neighbor: SpeciesFilter::Single(1),
+            self_pairs: true,
+        };
+
+        // only the oxygen is used as a center
+        let samples = builder.samples(&mut systems).unwrap();
+        assert_eq!(samples, Labels::new(["structure", "center"], &[[0, 0]]));
+
+        let keys = CenterSpeciesKeys.keys(&mut systems).unwrap();
+        assert_eq!(keys, Labels::new(["species_center"], &[[8]]));
+
+        // but the hydrogen atoms are still used as neighbors
+        let gradient_samples = builder.gradients_for(&mut systems, &samples).unwrap();
+        assert_eq!(gradient_samples, Labels::new(
+            ["sample", "structure", "atom"],
+            &[[0, 0, 0], [0, 0, 1], [0, 0, 2]]
+        ));
+    }
 }
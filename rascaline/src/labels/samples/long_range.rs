@@ -1,6 +1,7 @@
 use equistore::{Labels, LabelsBuilder};
 
 use crate::{Error, System};
+use crate::systems::center_atoms;
 use super::{SamplesBuilder, SpeciesFilter};
 
 
@@ -41,8 +42,9 @@ impl SamplesBuilder for LongRangeSamplesPerAtom {
             }
 
             if has_matching_neighbor {
+                let centers = center_atoms(&**system)?;
                 for (center_i, &species_center) in species.iter().enumerate() {
-                    if self.species_center.matches(species_center) {
+                    if centers[center_i] && self.species_center.matches(species_center) {
                         builder.add(&[system_i, center_i]);
                     }
                 }
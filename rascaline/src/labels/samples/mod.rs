@@ -1,10 +1,13 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::RangeInclusive;
 
-use equistore::Labels;
+use equistore::{Labels, LabelsBuilder};
 
 use crate::{Error, System};
 
-/// Atomic species filters to be used when building samples and gradient sample
+/// Atomic species filters to be used when building keys, samples and gradient
+/// samples
+#[derive(Debug, Clone, PartialEq)]
 pub enum SpeciesFilter {
     /// Any atomic species is fine
     Any,
@@ -12,6 +15,10 @@ pub enum SpeciesFilter {
     Single(i32),
     /// Any of the given atomic species is fine
     OneOf(Vec<i32>),
+    /// Any atomic species except the given ones is fine
+    NoneOf(Vec<i32>),
+    /// Any atomic species in the given range (including both ends) is fine
+    Range(RangeInclusive<i32>),
     /// All of the given atoms species must be present. This can only be used
     /// for neighbor species selection.
     AllOf(BTreeSet<i32>),
@@ -24,6 +31,8 @@ impl SpeciesFilter {
             SpeciesFilter::Any => true,
             SpeciesFilter::Single(selected) => species == *selected,
             SpeciesFilter::OneOf(selected) => selected.contains(&species),
+            SpeciesFilter::NoneOf(excluded) => !excluded.contains(&species),
+            SpeciesFilter::Range(range) => range.contains(&species),
             SpeciesFilter::AllOf(_) => panic!("internal error: can not call `matches` on a `SpeciesFilter::AllOf`"),
         }
     }
@@ -51,9 +60,221 @@ pub trait SamplesBuilder {
     fn gradients_for(&self, systems: &mut [Box<dyn System>], samples: &Labels) -> Result<Labels, Error>;
 }
 
+/// Create the samples for second derivatives with respect to positions from
+/// the corresponding positions `gradient_samples`. The resulting samples
+/// contain all couples of atoms appearing in the gradients of the same
+/// sample, with names `["sample", "structure", "atom_1", "atom_2"]`.
+pub fn hessian_samples_from_gradients(gradient_samples: &Labels) -> Labels {
+    assert_eq!(gradient_samples.names(), ["sample", "structure", "atom"]);
+    let mut builder = LabelsBuilder::new(vec!["sample", "structure", "atom_1", "atom_2"]);
+
+    let gradient_samples = gradient_samples.iter_fixed_size().collect::<Vec<_>>();
+    // gradient samples are grouped by sample, so we can iterate over chunks
+    // with the same sample
+    let mut start = 0;
+    while start < gradient_samples.len() {
+        let &[sample, structure, _] = gradient_samples[start];
+        let mut end = start;
+        while end < gradient_samples.len() && gradient_samples[end][0] == sample {
+            end += 1;
+        }
+
+        for &[_, _, atom_1] in &gradient_samples[start..end] {
+            for &[_, _, atom_2] in &gradient_samples[start..end] {
+                builder.add(&[sample, structure, *atom_1, *atom_2]);
+            }
+        }
+
+        start = end;
+    }
+
+    return builder.finish();
+}
+
+/// Mapping between the rows of a gradient block and the values samples, atoms
+/// and pairs they originate from, as returned by [`gradient_samples_mapping`].
+///
+/// All the vectors have one entry for each row of the gradient block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradientSamplesMapping {
+    /// Index of the corresponding row in the values
+    pub sample: Vec<usize>,
+    /// Index of the structure
+    pub structure: Vec<usize>,
+    /// Index of the central atom of the values sample
+    pub center: Vec<usize>,
+    /// Index of the atom with respect to which the gradient is taken
+    pub atom: Vec<usize>,
+    /// Indexes in `System::pairs` of the pairs between `center` and `atom`
+    /// contributing to this gradient. There can be multiple pairs between the
+    /// same atoms in periodic systems, and no pair for the gradient of an atom
+    /// with respect to its own position.
+    pub pairs: Vec<Vec<usize>>,
+}
+
+/// Get the mapping between the positions `gradient_samples` of an
+/// atom-centered representation and the values `samples`, atoms and pairs
+/// they originate from. `samples` must contain `"structure"` and `"center"`.
+///
+/// This can be used by code wrapping rascaline in an automatic differentiation
+/// framework to implement the double-backward pass. The neighbors lists of the
+/// `systems` are computed with the given `cutoff`, which should match the one
+/// used to compute the representation.
+pub fn gradient_samples_mapping(
+    systems: &mut [Box<dyn System>],
+    cutoff: f64,
+    samples: &Labels,
+    gradient_samples: &Labels,
+) -> Result<GradientSamplesMapping, Error> {
+    if gradient_samples.names() != ["sample", "structure", "atom"] {
+        return Err(Error::InvalidParameter(format!(
+            "expected [sample, structure, atom] names for the gradient samples, got [{}]",
+            gradient_samples.names().join(", ")
+        )));
+    }
+
+    let center_column = samples.names().iter().position(|&name| name == "center").ok_or_else(|| Error::InvalidParameter(
+        "samples must contain a \"center\" variable to map gradient samples to pairs".into()
+    ))?;
+
+    // all the pairs between two atoms in each system, indexed by the atoms
+    // (smallest index first)
+    let mut all_pairs = Vec::new();
+    for system in systems.iter_mut() {
+        system.compute_neighbors(cutoff)?;
+
+        let mut pairs_by_atoms = BTreeMap::<(usize, usize), Vec<usize>>::new();
+        for (pair_i, pair) in system.pairs()?.iter().enumerate() {
+            let atoms = (usize::min(pair.first, pair.second), usize::max(pair.first, pair.second));
+            pairs_by_atoms.entry(atoms).or_default().push(pair_i);
+        }
+        all_pairs.push(pairs_by_atoms);
+    }
+
+    let mut mapping = GradientSamplesMapping {
+        sample: Vec::with_capacity(gradient_samples.count()),
+        structure: Vec::with_capacity(gradient_samples.count()),
+        center: Vec::with_capacity(gradient_samples.count()),
+        atom: Vec::with_capacity(gradient_samples.count()),
+        pairs: Vec::with_capacity(gradient_samples.count()),
+    };
+
+    for &[sample, structure, atom] in gradient_samples.iter_fixed_size() {
+        let sample = sample.usize();
+        let structure = structure.usize();
+        let atom = atom.usize();
+        let center = samples[sample][center_column].usize();
+
+        let atoms = (usize::min(center, atom), usize::max(center, atom));
+        let pairs = if center == atom {
+            Vec::new()
+        } else {
+            all_pairs[structure].get(&atoms).cloned().unwrap_or_default()
+        };
+
+        mapping.sample.push(sample);
+        mapping.structure.push(structure);
+        mapping.center.push(center);
+        mapping.atom.push(atom);
+        mapping.pairs.push(pairs);
+    }
+
+    return Ok(mapping);
+}
 
 mod atom_centered;
 pub use self::atom_centered::AtomCenteredSamples;
 
 mod long_range;
 pub use self::long_range::LongRangeSamplesPerAtom;
+
+#[cfg(test)]
+mod tests {
+    use crate::systems::test_utils::test_systems;
+
+    use super::*;
+
+    #[test]
+    fn species_filter() {
+        assert!(SpeciesFilter::Any.matches(42));
+
+        assert!(SpeciesFilter::Single(6).matches(6));
+        assert!(!SpeciesFilter::Single(6).matches(1));
+
+        let filter = SpeciesFilter::OneOf(vec![1, 8]);
+        assert!(filter.matches(8));
+        assert!(!filter.matches(6));
+
+        let filter = SpeciesFilter::NoneOf(vec![1]);
+        assert!(filter.matches(6));
+        assert!(!filter.matches(1));
+
+        let filter = SpeciesFilter::Range(21..=30);
+        assert!(filter.matches(21));
+        assert!(filter.matches(30));
+        assert!(!filter.matches(31));
+        assert!(!filter.matches(8));
+    }
+
+    #[test]
+    fn gradient_mapping() {
+        let mut systems = test_systems(&["water"]);
+        let builder = AtomCenteredSamples {
+            cutoff: 2.0,
+            species_center: SpeciesFilter::Any,
+            species_neighbor: SpeciesFilter::Any,
+            self_pairs: true,
+        };
+        let samples = builder.samples(&mut systems).unwrap();
+        let gradient_samples = builder.gradients_for(&mut systems, &samples).unwrap();
+
+        let mapping = gradient_samples_mapping(&mut systems, 2.0, &samples, &gradient_samples).unwrap();
+        assert_eq!(mapping.sample.len(), gradient_samples.count());
+
+        let pairs = systems[0].pairs().unwrap();
+        for (grad_sample_i, &[sample, structure, atom]) in gradient_samples.iter_fixed_size().enumerate() {
+            assert_eq!(mapping.sample[grad_sample_i], sample.usize());
+            assert_eq!(mapping.structure[grad_sample_i], structure.usize());
+            assert_eq!(mapping.center[grad_sample_i], samples[sample.usize()][1].usize());
+            assert_eq!(mapping.atom[grad_sample_i], atom.usize());
+
+            let center = mapping.center[grad_sample_i];
+            if center == atom.usize() {
+                assert!(mapping.pairs[grad_sample_i].is_empty());
+            } else {
+                assert_eq!(mapping.pairs[grad_sample_i].len(), 1);
+                let pair = &pairs[mapping.pairs[grad_sample_i][0]];
+                let mut pair_atoms = [pair.first, pair.second];
+                pair_atoms.sort_unstable();
+                let mut expected = [center, atom.usize()];
+                expected.sort_unstable();
+                assert_eq!(pair_atoms, expected);
+            }
+        }
+
+        let error = gradient_samples_mapping(&mut systems, 2.0, &samples, &samples).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: expected [sample, structure, atom] names for the gradient samples, got [structure, center]"
+        );
+    }
+
+    #[test]
+    fn hessian_samples() {
+        let gradient_samples = Labels::new(["sample", "structure", "atom"], &[
+            [0, 0, 0], [0, 0, 2],
+            [1, 0, 1],
+            [2, 1, 0], [2, 1, 3],
+        ]);
+
+        let hessian_samples = hessian_samples_from_gradients(&gradient_samples);
+        assert_eq!(hessian_samples, Labels::new(
+            ["sample", "structure", "atom_1", "atom_2"],
+            &[
+                [0, 0, 0, 0], [0, 0, 0, 2], [0, 0, 2, 0], [0, 0, 2, 2],
+                [1, 0, 1, 1],
+                [2, 1, 0, 0], [2, 1, 0, 3], [2, 1, 3, 0], [2, 1, 3, 3],
+            ]
+        ));
+    }
+}
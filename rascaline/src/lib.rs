@@ -29,10 +29,19 @@ pub use self::systems::{System, SimpleSystem};
 pub mod labels;
 
 mod calculator;
-pub use self::calculator::{Calculator, CalculationOptions, LabelsSelection};
+pub use self::calculator::{Calculator, CalculationOptions, LabelsSelection, CostEstimate};
+
+mod threads;
+pub use self::threads::{set_max_threads, max_threads};
+
+pub mod profiling;
 
 pub mod calculators;
 
+pub mod postprocessing;
+
+pub mod testing;
+
 // only try to build the tutorials in test mode
 #[cfg(test)]
 mod tutorials;
@@ -23,7 +23,8 @@ mod hyp2f1;
 pub (crate) use self::hyp2f1::hyp2f1;
 
 mod splines;
-pub(crate) use self::splines::{HermitSplinePoint, HermitCubicSpline, SplineParameters};
+pub(crate) use self::splines::{HermitSplinePoint, HermitCubicSpline, SplineParameters, cached_spline};
+pub use self::splines::SplineAccuracy;
 
 mod spherical_harmonics;
 pub use self::spherical_harmonics::{SphericalHarmonics, SphericalHarmonicsArray};
@@ -1,11 +1,17 @@
-use ndarray::{Array, ArrayViewMut, azip};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, Weak};
+
+use ndarray::{Array, ArrayViewMut, Ix2, azip};
 use log::info;
+use once_cell::sync::Lazy;
 
 use crate::Error;
 
 
-/// Maximal number of points in the splines
+/// Default maximal number of points in the splines
 const MAX_SPLINE_SIZE: usize = 10_000;
+/// Number of uniformly spaced points used to start the spline refinement
+const INITIAL_GRID_SIZE: usize = 11;
 
 /// [Hermit cubit spline][splines-wiki] implementation.
 ///
@@ -33,6 +39,106 @@ pub struct SplineParameters {
     pub shape: Vec<usize>,
 }
 
+/// Accuracy targets used when creating a `HermitCubicSpline` with
+/// `HermitCubicSpline::with_accuracy`
+#[derive(Debug, Clone, Copy)]
+pub struct SplineAccuracy {
+    /// Target for the mean absolute error of the spline
+    pub absolute: f64,
+    /// Target for the mean relative error of the spline
+    pub relative: f64,
+    /// Maximal number of control points in the spline
+    pub max_points: usize,
+}
+
+impl SplineAccuracy {
+    fn validate(&self) -> Result<(), Error> {
+        if self.absolute < 0.0 {
+            return Err(Error::InvalidParameter(format!(
+                "got invalid accuracy in spline ({}), it must be positive", self.absolute
+            )));
+        }
+
+        if self.relative < 0.0 {
+            return Err(Error::InvalidParameter(format!(
+                "got invalid relative accuracy in spline ({}), it must be positive", self.relative
+            )));
+        }
+
+        if self.max_points < INITIAL_GRID_SIZE {
+            return Err(Error::InvalidParameter(format!(
+                "got invalid maximal number of points in spline ({}), it must be at least {}",
+                self.max_points, INITIAL_GRID_SIZE
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Check if the given absolute or relative error reach the targets
+    fn reached(&self, absolute_error: f64, relative_error: f64) -> bool {
+        absolute_error < self.absolute || relative_error < self.relative
+    }
+}
+
+impl From<f64> for SplineAccuracy {
+    /// Use the same target for the absolute and relative error, and the
+    /// default maximal number of points
+    fn from(accuracy: f64) -> SplineAccuracy {
+        SplineAccuracy {
+            absolute: accuracy,
+            relative: accuracy,
+            max_points: MAX_SPLINE_SIZE,
+        }
+    }
+}
+
+/// Process-wide cache of splines, used to share the splined radial integrals
+/// between all calculators (and all threads) using the same hyper-parameters.
+///
+/// This only stores weak references, so the splines are freed once all the
+/// calculators using them are dropped.
+static SPLINES_CACHE: Lazy<Mutex<BTreeMap<String, Weak<HermitCubicSpline<Ix2>>>>> = Lazy::new(Default::default);
+
+/// Get the spline identified by `key` from the process-wide cache of splines,
+/// or create it with `create` and add it to the cache.
+///
+/// The `key` must contain all the parameters influencing the spline, including
+/// the spline accuracy.
+pub(crate) fn cached_spline<F>(key: String, create: F) -> Result<Arc<HermitCubicSpline<Ix2>>, Error>
+    where F: FnOnce() -> Result<HermitCubicSpline<Ix2>, Error>
+{
+    // the lock is held while creating the spline, so that multiple threads
+    // requesting the same spline only create it once
+    let mut cache = SPLINES_CACHE.lock().expect("mutex was poisoned");
+    if let Some(spline) = cache.get(&key).and_then(Weak::upgrade) {
+        return Ok(spline);
+    }
+
+    // remove splines which have been freed since the last call
+    cache.retain(|_, spline| spline.strong_count() > 0);
+
+    let spline = Arc::new(create()?);
+    cache.insert(key, Arc::downgrade(&spline));
+    return Ok(spline);
+}
+
+/// Function value and spline error in the middle of an interval between two
+/// control points
+struct SplineMidpoint<D: ndarray::Dimension> {
+    point: HermitSplinePoint<D>,
+    max_absolute_error: f64,
+    mean_absolute_error: f64,
+    mean_relative_error: f64,
+}
+
+impl<D: ndarray::Dimension> SplineMidpoint<D> {
+    /// Is the spline accurate enough in the interval containing this midpoint?
+    fn converged(&self, accuracy: SplineAccuracy) -> bool {
+        accuracy.reached(self.mean_absolute_error, self.mean_relative_error)
+    }
+}
+
 /// A single control point/knot in the Hermit cubic spline
 #[derive(Debug, Clone)]
 pub struct HermitSplinePoint<D: ndarray::Dimension> {
@@ -75,19 +181,20 @@ impl<D: ndarray::Dimension> HermitCubicSpline<D> {
     ///
     /// Points are added to the spline until the requested accuracy is reached.
     /// We consider that the accuracy is reached when either the mean absolute
-    /// error or the mean relative error gets below the `accuracy` threshold.
+    /// error or the mean relative error gets below the corresponding target in
+    /// `accuracy`. Points are only added in the intervals where the error is
+    /// still above the targets, giving a non-uniform grid with more points
+    /// where the function varies the fastest.
+    #[allow(clippy::too_many_lines)]
     pub fn with_accuracy<F>(
-        accuracy: f64,
+        accuracy: impl Into<SplineAccuracy>,
         parameters: SplineParameters,
         function: F,
     ) -> Result<HermitCubicSpline<D>, Error> where
             F: Fn(f64) -> (Array<f64, D>, Array<f64, D>),
     {
-        if accuracy < 0.0 {
-            return Err(Error::InvalidParameter(format!(
-                "got invalid accuracy in spline ({}), it must be positive", accuracy
-            )));
-        }
+        let accuracy = accuracy.into();
+        accuracy.validate()?;
 
         let interpolated = Array::from_elem(parameters.shape.clone(), 0.0);
         let mut interpolated = match interpolated.into_dimensionality::<D>() {
@@ -99,11 +206,10 @@ impl<D: ndarray::Dimension> HermitCubicSpline<D> {
             }
         };
 
-        let initial_grid_size = 11;
-        let grid_step = (parameters.stop - parameters.start) / (initial_grid_size - 1) as f64;
+        let grid_step = (parameters.stop - parameters.start) / (INITIAL_GRID_SIZE - 1) as f64;
 
         let mut points = Vec::new();
-        for k in 0..initial_grid_size {
+        for k in 0..INITIAL_GRID_SIZE {
             let position = parameters.start + k as f64 * grid_step;
             let (value, derivative) = function(position);
 
@@ -119,27 +225,31 @@ impl<D: ndarray::Dimension> HermitCubicSpline<D> {
 
         let mut spline = HermitCubicSpline::new(parameters, points);
 
+        // `midpoints[k]` contains the function evaluated in the middle of the
+        // interval between control points `k` and `k + 1`, together with the
+        // spline error at this point. This is only computed once per interval.
+        let mut midpoints: Vec<Option<SplineMidpoint<D>>> = (0..(spline.len() - 1)).map(|_| None).collect();
+
         // add more points as required to reach the requested accuracy
         loop {
-            let mut max_absolute_error = 0.0;
-            let mut mean_absolute_error = 0.0;
-            let mut mean_relative_error = 0.0;
-            let mut error_count = 0;
-
             let positions = spline.positions();
 
             // evaluate the error at points in between grid points, since these
             // should have the highest error in average.
-            let mut new_points = Vec::new();
-            for k in 0..(spline.len() - 1) {
-                let position = (positions[k] + positions[k + 1]) / 2.0;
+            for (k, midpoint) in midpoints.iter_mut().enumerate() {
+                if midpoint.is_some() {
+                    continue;
+                }
 
+                let position = (positions[k] + positions[k + 1]) / 2.0;
                 let (value, derivative) = function(position);
 
                 interpolated.fill(0.0);
                 spline.compute(position, interpolated.view_mut(), None);
 
-                // get the error across all values in the arrays
+                let mut max_absolute_error = 0.0;
+                let mut mean_absolute_error = 0.0;
+                let mut mean_relative_error = 0.0;
                 azip!((interpolated in &interpolated, value in &value) {
                     let absolute_error = f64::abs(interpolated - value);
                     if absolute_error > max_absolute_error {
@@ -148,50 +258,92 @@ impl<D: ndarray::Dimension> HermitCubicSpline<D> {
 
                     mean_absolute_error += absolute_error;
                     mean_relative_error += f64::abs((interpolated - value) / value);
-                    error_count += 1;
                 });
+                mean_absolute_error /= value.len() as f64;
+                mean_relative_error /= value.len() as f64;
+
+                *midpoint = Some(SplineMidpoint {
+                    point: HermitSplinePoint { position, value, derivative },
+                    max_absolute_error,
+                    mean_absolute_error,
+                    mean_relative_error,
+                });
+            }
+
+            let midpoints_ref = midpoints.iter()
+                .map(|m| m.as_ref().expect("missing spline midpoint"))
+                .collect::<Vec<_>>();
 
-                new_points.push(HermitSplinePoint { position, value, derivative });
+            // get the error across all intervals
+            let mut max_absolute_error = 0.0;
+            let mut mean_absolute_error = 0.0;
+            let mut mean_relative_error = 0.0;
+            for midpoint in &midpoints_ref {
+                max_absolute_error = f64::max(max_absolute_error, midpoint.max_absolute_error);
+                mean_absolute_error += midpoint.mean_absolute_error;
+                mean_relative_error += midpoint.mean_relative_error;
             }
-            mean_absolute_error /= error_count as f64;
-            mean_relative_error /= error_count as f64;
+            mean_absolute_error /= midpoints_ref.len() as f64;
+            mean_relative_error /= midpoints_ref.len() as f64;
 
-            if mean_absolute_error < accuracy || mean_relative_error < accuracy {
+            if accuracy.reached(mean_absolute_error, mean_relative_error) {
                 info!(
-                    "spline reached requested accuracy ({:.3e}) with {} reference points (max absolute error is {:.3e})",
-                    accuracy, spline.len(), max_absolute_error,
+                    "spline reached requested accuracy ({:.3e} absolute, {:.3e} relative) with {} reference points (max absolute error is {:.3e})",
+                    accuracy.absolute, accuracy.relative, spline.len(), max_absolute_error,
                 );
                 break;
             }
 
-            if spline.len() + new_points.len() > MAX_SPLINE_SIZE {
+            // only refine the intervals where neither of the accuracy targets
+            // is reached. If all intervals are individually converged but the
+            // overall error is still too large, refine the intervals with the
+            // largest absolute errors.
+            let mut refine = midpoints_ref.iter()
+                .map(|m| !m.converged(accuracy))
+                .collect::<Vec<_>>();
+
+            if !refine.iter().any(|&r| r) {
+                refine = midpoints_ref.iter()
+                    .map(|m| m.mean_absolute_error >= accuracy.absolute)
+                    .collect();
+            }
+
+            let n_new_points = refine.iter().filter(|&&r| r).count();
+            if spline.len() + n_new_points > accuracy.max_points {
                 return Err(Error::Internal(format!(
-                    "failed to reach requested accuracy ({:e}) in spline interpolation, \
-                    mean absolute error is {:e} and mean relative error is {:e}",
-                    accuracy, mean_absolute_error, mean_relative_error
+                    "failed to reach requested accuracy ({:e} absolute, {:e} relative) \
+                    in spline interpolation with at most {} points, mean absolute \
+                    error is {:e} and mean relative error is {:e}",
+                    accuracy.absolute, accuracy.relative, accuracy.max_points,
+                    mean_absolute_error, mean_relative_error
                 )));
             }
 
             // add more points and continue
-            for point in new_points {
-                spline.add_point(point);
+            let old_points = std::mem::take(&mut spline.points);
+            let mut old_midpoints = std::mem::take(&mut midpoints);
+            for (k, point) in old_points.into_iter().enumerate() {
+                spline.points.push(point);
+
+                if k < old_midpoints.len() {
+                    let midpoint = old_midpoints[k].take().expect("missing spline midpoint");
+                    if refine[k] {
+                        spline.points.push(midpoint.point);
+                        midpoints.push(None);
+                        midpoints.push(None);
+                    } else {
+                        midpoints.push(Some(midpoint));
+                    }
+                }
             }
         }
 
         return Ok(spline);
     }
 
-    /// Add a new control points to this spline. The new point must be between
-    /// `self.start` and `self.stop`.
-    fn add_point(&mut self, point: HermitSplinePoint<D>) {
-        debug_assert!(point.position > self.parameters.start);
-        debug_assert!(point.position < self.parameters.stop);
-        match self.points.binary_search_by(
-            |v| v.position.partial_cmp(&point.position).expect("got NaN")
-        ) {
-            Ok(_) => panic!("trying to add the same point twice to the spline"),
-            Err(k) => self.points.insert(k, point)
-        }
+    /// Get the control points of this spline
+    pub(crate) fn points(&self) -> &[HermitSplinePoint<D>] {
+        &self.points
     }
 
     /// Get the number of control points in this spline
@@ -325,4 +477,74 @@ mod tests {
             |x| (ndarray::arr1(&[f64::sin(x)]), ndarray::arr1(&[f64::cos(x)])),
         ).unwrap();
     }
+
+    #[test]
+    fn adaptive_refinement() {
+        let parameters = SplineParameters {
+            start: 0.0,
+            stop: 5.0,
+            shape: vec![1],
+        };
+
+        // function varying quickly close to 0 and slowly after
+        let function = |x: f64| {
+            let value = f64::exp(-10.0 * x * x) + 0.1 * x;
+            let gradient = -20.0 * x * f64::exp(-10.0 * x * x) + 0.1;
+            (ndarray::arr1(&[value]), ndarray::arr1(&[gradient]))
+        };
+
+        let accuracy = SplineAccuracy {
+            absolute: 1e-8,
+            relative: 0.0,
+            max_points: 1000,
+        };
+        let spline = HermitCubicSpline::with_accuracy(accuracy, parameters.clone(), function).unwrap();
+
+        let positions = spline.positions();
+        let close = positions.iter().filter(|&&x| x < 1.0).count();
+        let far = positions.iter().filter(|&&x| x >= 4.0).count();
+        assert!(close > 5 * far);
+
+        let mut values = ndarray::Array1::from_elem((1,), 0.0);
+        for &x in &[0.0, 0.013, 0.21, 0.5, 0.77, 1.3, 2.4, 4.9] {
+            spline.compute(x, values.view_mut(), None);
+            assert_relative_eq!(values[0], function(x).0[0], epsilon=1e-6);
+        }
+
+        // limit the number of points
+        let accuracy = SplineAccuracy {
+            absolute: 1e-14,
+            relative: 1e-14,
+            max_points: 20,
+        };
+        let error = HermitCubicSpline::with_accuracy(accuracy, parameters, function).unwrap_err();
+        assert!(error.to_string().contains("in spline interpolation with at most 20 points"));
+    }
+
+    #[test]
+    fn cache() {
+        let parameters = SplineParameters {
+            start: 0.0,
+            stop: 1.0,
+            shape: vec![1, 1],
+        };
+
+        let create = || {
+            HermitCubicSpline::with_accuracy(1e-6, parameters.clone(), |x| {
+                let values = Array::from_elem((1, 1), f64::sin(x));
+                let derivatives = Array::from_elem((1, 1), f64::cos(x));
+                (values, derivatives)
+            })
+        };
+
+        let first = cached_spline("test-cache".into(), create).unwrap();
+        let second = cached_spline("test-cache".into(), || panic!("should not be called")).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+
+        // the spline is created again once all references are dropped
+        std::mem::drop(first);
+        std::mem::drop(second);
+        let error = cached_spline("test-cache".into(), || Err(Error::InvalidParameter("created".into()))).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: created");
+    }
 }
@@ -0,0 +1,151 @@
+//! Utilities to post-process the descriptors created by the calculators, for
+//! example to use them with other libraries.
+
+use std::collections::BTreeMap;
+
+use equistore::{Labels, TensorBlockRef, TensorMap};
+
+use crate::Error;
+
+/// Gradients that can be present in the descriptors created by rascaline
+pub(crate) const GRADIENTS: [&str; 4] = ["positions", "positions_hessian", "density_scaling", "cell"];
+
+/// Format the values of a key for error messages
+fn key_to_string(key: &[i32]) -> String {
+    return key.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(", ");
+}
+
+/// Get the values of all entries in `labels`
+fn labels_values(labels: &Labels) -> Vec<Vec<i32>> {
+    return labels.iter()
+        .map(|entry| entry.iter().map(|value| value.i32()).collect())
+        .collect();
+}
+
+/// Key and properties of a block seen when fitting a post-processing step
+/// (`Standardizer` or `Pca`) to descriptors
+#[derive(Debug, Clone, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct FittedBlock {
+    /// values of the key for this block
+    key: Vec<i32>,
+    /// names of the properties of this block
+    properties_names: Vec<String>,
+    /// values of the properties of this block
+    properties: Vec<Vec<i32>>,
+}
+
+impl FittedBlock {
+    fn new(key: Vec<i32>, properties: &Labels) -> FittedBlock {
+        return FittedBlock {
+            key: key,
+            properties_names: properties.names().iter().map(|&name| name.to_owned()).collect(),
+            properties: labels_values(properties),
+        };
+    }
+
+    fn same_properties(&self, properties: &Labels) -> bool {
+        return properties.names() == self.properties_names && labels_values(properties) == self.properties;
+    }
+
+    /// Check that `properties` are the same as the ones used when fitting
+    /// this block. `name` is the name of the post-processing step, used in
+    /// error messages.
+    fn check_properties(&self, properties: &Labels, name: &str) -> Result<(), Error> {
+        if !self.same_properties(properties) {
+            return Err(Error::InvalidParameter(format!(
+                "the properties of the block for key [{}] are different from the ones used to fit this {}",
+                key_to_string(&self.key), name
+            )));
+        }
+
+        return Ok(());
+    }
+}
+
+/// Accumulate some data over the blocks of all `descriptors`, grouping
+/// together the blocks with the same key. `init` creates the data for a new
+/// key from the properties of the block, and `update` adds a block to the
+/// corresponding data. `name` is the name of the post-processing step, used
+/// in error messages.
+///
+/// All descriptors must have the same keys names, and blocks with the same
+/// key must have the same properties. This returns the keys names and the
+/// accumulated data for each key, sorted by key.
+fn fit_blocks<T, I, U>(
+    descriptors: &[TensorMap],
+    name: &str,
+    mut init: I,
+    mut update: U,
+) -> Result<(Vec<String>, Vec<(FittedBlock, T)>), Error>
+    where I: FnMut(&Labels) -> T,
+          U: FnMut(&mut T, &TensorBlockRef),
+{
+    let keys_names = match descriptors.first() {
+        Some(first) => first.keys().names(),
+        None => return Err(Error::InvalidParameter(format!(
+            "can not fit a {} without any descriptor", name
+        ))),
+    };
+
+    let mut all_blocks = BTreeMap::new();
+    for descriptor in descriptors {
+        if descriptor.keys().names() != keys_names {
+            return Err(Error::InvalidParameter(format!(
+                "all descriptors must have the same keys names, got [{}] and [{}]",
+                keys_names.join(", "), descriptor.keys().names().join(", ")
+            )));
+        }
+
+        for (key, block) in descriptor.iter() {
+            let key = key.iter().map(|value| value.i32()).collect::<Vec<_>>();
+            let properties = block.properties();
+
+            let (fitted, data) = all_blocks.entry(key.clone()).or_insert_with(|| {
+                (FittedBlock::new(key.clone(), &properties), init(&properties))
+            });
+
+            if !fitted.same_properties(&properties) {
+                return Err(Error::InvalidParameter(format!(
+                    "the block for key [{}] has different properties in different descriptors",
+                    key_to_string(&key)
+                )));
+            }
+
+            update(data, &block);
+        }
+    }
+
+    let keys_names = keys_names.iter().map(|&name| name.to_owned()).collect();
+    return Ok((keys_names, all_blocks.into_values().collect()));
+}
+
+/// Check that the keys of `descriptor` have the names used during a fit
+fn check_keys_names(descriptor: &TensorMap, keys_names: &[String]) -> Result<(), Error> {
+    if descriptor.keys().names() != keys_names {
+        return Err(Error::InvalidParameter(format!(
+            "expected keys names [{}] in this descriptor, got [{}]",
+            keys_names.join(", "), descriptor.keys().names().join(", ")
+        )));
+    }
+
+    return Ok(());
+}
+
+mod rename;
+pub use self::rename::rename_dimensions;
+
+mod union;
+pub use self::union::union_keys;
+
+mod reduce;
+pub use self::reduce::{sum_over_samples, mean_over_samples, SampleWeights};
+
+mod normalize;
+pub use self::normalize::{normalize_l2, NormalizationScope};
+
+mod standardize;
+pub use self::standardize::Standardizer;
+
+mod pca;
+pub use self::pca::Pca;
@@ -0,0 +1,394 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use ndarray::{ArrayD, Axis, Slice};
+
+use equistore::{LabelsBuilder, LabelValue, TensorBlock, TensorBlockRef, TensorMap};
+
+use crate::Error;
+
+use super::GRADIENTS;
+
+/// Which entries are used to compute the norm of a sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalizationScope {
+    /// Normalize each sample separately in each block
+    PerBlock,
+    /// Normalize each sample over all the blocks, as if the blocks were joined
+    /// along the properties. The same sample can appear in multiple blocks,
+    /// and is identified by the values of its samples labels.
+    AcrossBlocks,
+}
+
+/// Normalize the feature vector of each sample in the `descriptor` to have an
+/// L2 norm of 1, and update the gradients accordingly.
+///
+/// The feature vector of a sample contains all the components and properties
+/// of the corresponding row in a block, and can be joined over all the blocks
+/// depending on the `scope`. The gradients of the normalized values
+/// `x / |x|` are computed with the chain rule, and require the descriptor to
+/// contain gradients for all the samples. When normalizing across blocks, the
+/// gradients of a block can contain additional gradient samples, since the
+/// normalized values also depend on the gradients of the other blocks. Samples
+/// with a norm of zero are left unchanged.
+///
+/// Hessian gradients (`"positions_hessian"`) are not supported.
+pub fn normalize_l2(descriptor: &TensorMap, scope: NormalizationScope) -> Result<TensorMap, Error> {
+    let blocks = descriptor.iter().map(|(_, block)| block).collect::<Vec<_>>();
+
+    if let Some(first) = blocks.first() {
+        if first.gradient("positions_hessian").is_some() {
+            return Err(Error::InvalidParameter(
+                "L2 normalization does not support \"positions_hessian\" gradients".into()
+            ));
+        }
+
+        if scope == NormalizationScope::AcrossBlocks {
+            let first_samples = first.samples();
+            let names = first_samples.names();
+            for block in &blocks {
+                if block.samples().names() != names {
+                    return Err(Error::InvalidParameter(format!(
+                        "all blocks must have the same samples names to normalize across blocks, got [{}] and [{}]",
+                        names.join(", "), block.samples().names().join(", ")
+                    )));
+                }
+            }
+        }
+    }
+
+    let mut new_blocks = Vec::with_capacity(blocks.len());
+    match scope {
+        NormalizationScope::PerBlock => {
+            for block in &blocks {
+                let mut norms = Norms::default();
+                norms.accumulate(block);
+                new_blocks.push(norms.normalize(block)?);
+            }
+        }
+        NormalizationScope::AcrossBlocks => {
+            let mut norms = Norms::default();
+            for block in &blocks {
+                norms.accumulate(block);
+            }
+
+            for block in &blocks {
+                new_blocks.push(norms.normalize(block)?);
+            }
+        }
+    }
+
+    return Ok(TensorMap::new(descriptor.keys().clone(), new_blocks)?);
+}
+
+/// Squared norms and gradients of the squared norms of samples, accumulated
+/// over one or more blocks
+#[derive(Default)]
+struct Norms {
+    /// Squared norm of each sample, indexed by the sample values
+    squared: BTreeMap<Vec<i32>, f64>,
+    /// For each gradient, dot product between the values and the gradients of
+    /// the values, indexed by the sample values followed by the gradient
+    /// sample values (without `"sample"`). This contains one entry for each
+    /// of the gradient components.
+    dots: BTreeMap<(&'static str, Vec<i32>), Vec<f64>>,
+}
+
+impl Norms {
+    fn accumulate(&mut self, block: &TensorBlockRef) {
+        let samples = block.samples();
+        let values = block.values().to_array();
+
+        let mut rows = Vec::with_capacity(samples.count());
+        for (sample, row) in samples.iter().zip(values.axis_iter(Axis(0))) {
+            let key = sample.iter().map(|value| value.i32()).collect::<Vec<_>>();
+            *self.squared.entry(key).or_insert(0.0) += row.iter().map(|x| x * x).sum::<f64>();
+            rows.push(row.iter().copied().collect::<Vec<_>>());
+        }
+
+        for parameter in GRADIENTS {
+            if let Some(gradient) = block.gradient(parameter) {
+                let gradient_values = gradient.values().to_array();
+                for (gradient_sample, gradient_row) in gradient.samples().iter().zip(gradient_values.axis_iter(Axis(0))) {
+                    let sample_i = gradient_sample[0].usize();
+                    let row = &rows[sample_i];
+
+                    let dots = self.dots.entry((parameter, gradient_key(&samples[sample_i], gradient_sample)))
+                        .or_insert_with(|| vec![0.0; gradient_row.len() / row.len().max(1)]);
+
+                    // the gradient row contains the gradient components (e.g.
+                    // the x/y/z directions) followed by the same entries as
+                    // the values row, in the same order
+                    for (i, gradient) in gradient_row.iter().enumerate() {
+                        dots[i / row.len()] += gradient * row[i % row.len()];
+                    }
+                }
+            }
+        }
+    }
+
+    fn normalize(&self, block: &TensorBlockRef) -> Result<TensorBlock, Error> {
+        let samples = block.samples();
+        let values = block.values().to_array();
+
+        let mut sample_norms = Vec::with_capacity(samples.count());
+        let mut new_values = values.clone();
+        for (sample, mut row) in samples.iter().zip(new_values.axis_iter_mut(Axis(0))) {
+            let key = sample.iter().map(|value| value.i32()).collect::<Vec<_>>();
+            let norm = f64::sqrt(self.squared[&key]);
+            if norm != 0.0 {
+                row.mapv_inplace(|x| x / norm);
+            }
+            sample_norms.push(norm);
+        }
+
+        let properties = block.properties();
+        let mut new_block = TensorBlock::new(
+            new_values,
+            &samples,
+            &block.components(),
+            &properties,
+        )?;
+
+        for parameter in GRADIENTS {
+            if let Some(gradient) = block.gradient(parameter) {
+                let gradient_samples = gradient.samples();
+                let gradient_values = gradient.values().to_array();
+
+                // when normalizing across blocks, the normalized values depend
+                // on the gradients from other blocks, and we need to add the
+                // gradient samples missing from this block
+                let existing = gradient_samples.iter()
+                    .map(|gradient_sample| gradient_sample.iter().map(|value| value.i32()).collect::<Vec<_>>())
+                    .collect::<BTreeSet<_>>();
+
+                let mut missing = Vec::new();
+                for (sample_i, sample) in samples.iter().enumerate() {
+                    let sample = sample.iter().map(|value| value.i32()).collect::<Vec<_>>();
+                    let all_gradient_keys = self.dots.range((parameter, sample.clone())..)
+                        .take_while(|((other, key), _)| *other == parameter && key.starts_with(&sample));
+
+                    for ((_, key), _) in all_gradient_keys {
+                        let mut gradient_sample = vec![sample_i as i32];
+                        gradient_sample.extend_from_slice(&key[sample.len()..]);
+                        if !existing.contains(&gradient_sample) {
+                            missing.push(gradient_sample);
+                        }
+                    }
+                }
+
+                let mut new_gradient_samples = LabelsBuilder::new(gradient_samples.names());
+                for gradient_sample in gradient_samples.iter() {
+                    new_gradient_samples.add(gradient_sample);
+                }
+                for gradient_sample in &missing {
+                    new_gradient_samples.add(gradient_sample);
+                }
+                let new_gradient_samples = new_gradient_samples.finish();
+
+                let mut shape = gradient_values.shape().to_vec();
+                shape[0] += missing.len();
+                let mut new_gradient = ArrayD::from_elem(shape, 0.0);
+                new_gradient.slice_axis_mut(Axis(0), Slice::from(0..gradient_samples.count())).assign(gradient_values);
+
+                for (gradient_sample, mut gradient_row) in new_gradient_samples.iter().zip(new_gradient.axis_iter_mut(Axis(0))) {
+                    let sample_i = gradient_sample[0].usize();
+                    let norm = sample_norms[sample_i];
+                    if norm == 0.0 {
+                        continue;
+                    }
+
+                    let dots = &self.dots[&(parameter, gradient_key(&samples[sample_i], gradient_sample))];
+                    let row = values.index_axis(Axis(0), sample_i);
+                    let row_size = row.len();
+
+                    // d(x / |x|) = dx / |x| - x (x · dx) / |x|^3
+                    let norm_3 = norm * norm * norm;
+                    for (i, (gradient, x)) in gradient_row.iter_mut().zip(row.iter().cycle()).enumerate() {
+                        *gradient = *gradient / norm - x * dots[i / row_size] / norm_3;
+                    }
+                }
+
+                new_block.add_gradient(parameter, TensorBlock::new(
+                    new_gradient,
+                    &new_gradient_samples,
+                    &gradient.components(),
+                    &properties,
+                )?)?;
+            }
+        }
+
+        return Ok(new_block);
+    }
+}
+
+/// Get the key identifying a gradient sample across blocks, i.e. the values of
+/// the corresponding `sample` followed by the values of the `gradient_sample`
+/// without the first `"sample"` dimension
+fn gradient_key(sample: &[LabelValue], gradient_sample: &[LabelValue]) -> Vec<i32> {
+    return sample.iter()
+        .chain(&gradient_sample[1..])
+        .map(|value| value.i32())
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use equistore::Labels;
+
+    use crate::{Calculator, CalculationOptions};
+    use crate::systems::test_utils::test_systems;
+
+    use super::*;
+
+    fn descriptor() -> TensorMap {
+        let mut calculator = Calculator::new("dummy_calculator", r#"{
+            "cutoff": 1.0,
+            "delta": 9,
+            "name": ""
+        }"#.to_owned()).unwrap();
+
+        let options = CalculationOptions {
+            gradients: &["positions"],
+            ..Default::default()
+        };
+        return calculator.compute(&mut test_systems(&["water", "methane"]), options).unwrap();
+    }
+
+    fn spherical_expansion(gradients: &[&str]) -> Result<TensorMap, Error> {
+        let mut calculator = Calculator::new("spherical_expansion", r#"{
+            "max_radial": 3,
+            "max_angular": 2,
+            "cutoff": 3.5,
+            "atomic_gaussian_width": 0.3,
+            "center_atom_weight": 1.0,
+            "radial_basis": { "Gto": {} },
+            "cutoff_function": { "ShiftedCosine": { "width": 0.5 } }
+        }"#.to_owned()).unwrap();
+
+        let options = CalculationOptions {
+            gradients: gradients,
+            ..Default::default()
+        };
+        return calculator.compute(&mut test_systems(&["water", "methane"]), options);
+    }
+
+    /// Check that the normalized values have a norm of 1, and that the
+    /// gradients are orthogonal to the values (since `d(x·x) = 2 x·dx = 0`)
+    fn check_normalized(descriptor: &TensorMap, scope: NormalizationScope) {
+        let mut squared = BTreeMap::new();
+        let mut dots = BTreeMap::new();
+        for (_, block) in descriptor.iter() {
+            let samples = block.samples();
+            let values = block.values().to_array();
+            for (sample, row) in samples.iter().zip(values.axis_iter(Axis(0))) {
+                let key = sample.iter().map(|v| v.i32()).collect::<Vec<_>>();
+                *squared.entry(key).or_insert(0.0) += row.iter().map(|x| x * x).sum::<f64>();
+            }
+
+            for parameter in GRADIENTS {
+                let gradient = match block.gradient(parameter) {
+                    Some(gradient) => gradient,
+                    None => continue,
+                };
+                let gradient_values = gradient.values().to_array();
+                for (gradient_sample, gradient_row) in gradient.samples().iter().zip(gradient_values.axis_iter(Axis(0))) {
+                    let sample_i = gradient_sample[0].usize();
+                    let row = values.index_axis(Axis(0), sample_i).iter().copied().collect::<Vec<_>>();
+                    let key = gradient_key(&samples[sample_i], gradient_sample);
+
+                    let gradient_row = gradient_row.iter().copied().collect::<Vec<_>>();
+                    for (direction, gradient) in gradient_row.chunks(row.len()).enumerate() {
+                        let dot = gradient.iter().zip(&row).map(|(g, x)| g * x).sum::<f64>();
+                        *dots.entry((parameter, key.clone(), direction)).or_insert(0.0) += dot;
+                    }
+                }
+            }
+
+            if scope == NormalizationScope::PerBlock {
+                for norm in squared.values() {
+                    assert_relative_eq!(*norm, 1.0, max_relative=1e-12);
+                }
+                for dot in dots.values() {
+                    assert_relative_eq!(*dot, 0.0, epsilon=1e-12);
+                }
+                squared.clear();
+                dots.clear();
+            }
+        }
+
+        for norm in squared.values() {
+            assert_relative_eq!(*norm, 1.0, max_relative=1e-12);
+        }
+        for dot in dots.values() {
+            assert_relative_eq!(*dot, 0.0, epsilon=1e-12);
+        }
+    }
+
+    #[test]
+    fn per_block() {
+        let descriptor = descriptor();
+        let normalized = normalize_l2(&descriptor, NormalizationScope::PerBlock).unwrap();
+        assert_eq!(normalized.keys(), descriptor.keys());
+        check_normalized(&normalized, NormalizationScope::PerBlock);
+
+        // check the values of the first sample
+        let values = descriptor.block_by_id(0).values().to_array().index_axis(Axis(0), 0).to_owned();
+        let expected = &values / f64::sqrt((&values * &values).sum());
+        let normalized_block = normalized.block_by_id(0);
+        assert_relative_eq!(normalized_block.values().to_array().index_axis(Axis(0), 0), expected);
+    }
+
+    #[test]
+    fn across_blocks() {
+        let descriptor = spherical_expansion(&["positions", "cell"]).unwrap();
+        let normalized = normalize_l2(&descriptor, NormalizationScope::AcrossBlocks).unwrap();
+        check_normalized(&normalized, NormalizationScope::AcrossBlocks);
+
+        // normalizing across blocks is the same as normalizing the joined
+        // blocks
+        let join = |descriptor: TensorMap| {
+            let keys_to_move = Labels::empty(vec!["species_center"]);
+            let descriptor = descriptor.keys_to_samples(&keys_to_move, true).unwrap();
+
+            let keys_to_move = Labels::empty(vec!["species_neighbor"]);
+            let descriptor = descriptor.keys_to_properties(&keys_to_move, true).unwrap();
+            let descriptor = descriptor.components_to_properties(&["spherical_harmonics_m"]).unwrap();
+
+            let keys_to_move = Labels::empty(vec!["spherical_harmonics_l"]);
+            return descriptor.keys_to_properties(&keys_to_move, true).unwrap();
+        };
+
+        let normalized = join(normalized);
+        let expected = normalize_l2(&join(descriptor), NormalizationScope::PerBlock).unwrap();
+
+        let block = normalized.block_by_id(0);
+        let expected_block = expected.block_by_id(0);
+        assert_relative_eq!(block.values().to_array(), expected_block.values().to_array(), max_relative=1e-12);
+
+        for parameter in ["positions", "cell"] {
+            let gradient = block.gradient(parameter).unwrap();
+            let gradient_samples = gradient.samples();
+            let gradient_values = gradient.values().to_array();
+
+            let expected_gradient = expected_block.gradient(parameter).unwrap();
+            let expected_values = expected_gradient.values().to_array();
+            for (gradient_sample, expected_row) in expected_gradient.samples().iter().zip(expected_values.axis_iter(Axis(0))) {
+                match gradient_samples.position(gradient_sample) {
+                    Some(position) => {
+                        let row = gradient_values.index_axis(Axis(0), position);
+                        assert_relative_eq!(row, expected_row, epsilon=1e-12, max_relative=1e-12);
+                    }
+                    None => assert!(expected_row.iter().all(|&g| g == 0.0)),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hessian() {
+        let descriptor = spherical_expansion(&["positions", "positions_hessian"]).unwrap();
+        let error = normalize_l2(&descriptor, NormalizationScope::PerBlock).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: L2 normalization does not support \"positions_hessian\" gradients");
+    }
+}
@@ -0,0 +1,341 @@
+use ndarray::{Array1, Array2, ArrayD, Axis, IxDyn};
+
+use equistore::{LabelsBuilder, TensorBlock, TensorBlockRef, TensorMap};
+
+use crate::Error;
+use crate::math::SymmetricEigen;
+
+use super::{GRADIENTS, key_to_string, check_keys_names, fit_blocks, FittedBlock};
+
+/// Principal component analysis (PCA) of the properties of descriptors, used
+/// to compress descriptors with many properties such as the SOAP power
+/// spectrum.
+///
+/// The principal components of each block are computed over a dataset with
+/// `Pca::fit`, and the descriptors can then be projected onto them with
+/// `Pca::transform`. The principal components depend on the fitted dataset,
+/// so descriptors of new structures should be projected with a PCA loaded
+/// with `Pca::from_json` instead of a newly fitted one.
+///
+/// All the samples and components of a block are used to compute the
+/// covariance between properties. The values are centered before the
+/// projection, while gradients are only projected. The properties of the
+/// transformed blocks are named `"pca_component"`, sorted by decreasing
+/// explained variance.
+#[derive(Debug, Clone, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Pca {
+    /// names of the keys in the fitted descriptors
+    keys_names: Vec<String>,
+    /// projection for each block in the fitted descriptors
+    blocks: Vec<BlockProjection>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct BlockProjection {
+    /// key and properties of this block
+    #[serde(flatten)]
+    fitted: FittedBlock,
+    /// mean of each property
+    mean: Array1<f64>,
+    /// projection matrix, with shape `(n_properties, n_components)`
+    projection: Array2<f64>,
+    /// variance of the data along each principal component
+    explained_variance: Array1<f64>,
+}
+
+/// Running sums used to compute the covariance of the properties in a block
+struct CovarianceSums {
+    count: usize,
+    sum: Array1<f64>,
+    sum_products: Array2<f64>,
+}
+
+impl Pca {
+    /// Compute the `n_components` first principal components of each block
+    /// over all the given `descriptors`. Blocks with fewer properties than
+    /// `n_components` keep as many components as they have properties.
+    ///
+    /// The `descriptors` must follow the same rules as for
+    /// `Standardizer::fit`: same keys names, and same properties for all the
+    /// blocks with a given key.
+    pub fn fit(descriptors: &[TensorMap], n_components: usize) -> Result<Pca, Error> {
+        if n_components == 0 {
+            return Err(Error::InvalidParameter(
+                "the number of PCA components must be at least 1".into()
+            ));
+        }
+
+        let (keys_names, all_sums) = fit_blocks(descriptors, "PCA",
+            |properties| {
+                let n_properties = properties.count();
+                CovarianceSums {
+                    count: 0,
+                    sum: Array1::zeros(n_properties),
+                    sum_products: Array2::zeros((n_properties, n_properties)),
+                }
+            },
+            |sums, block| {
+                let values = as_matrix(block.values().to_array());
+                sums.count += values.nrows();
+                sums.sum += &values.sum_axis(Axis(0));
+                sums.sum_products += &values.t().dot(&values);
+            },
+        )?;
+
+        let blocks = all_sums.into_iter().map(|(fitted, sums)| {
+            let count = sums.count.max(1) as f64;
+            let mean = sums.sum / count;
+
+            let n_properties = mean.len();
+            let mut covariance = sums.sum_products / count;
+            for i in 0..n_properties {
+                for j in 0..n_properties {
+                    covariance[[i, j]] -= mean[i] * mean[j];
+                }
+            }
+            // make sure the matrix is exactly symmetric
+            let covariance = (&covariance + &covariance.t()) / 2.0;
+
+            // eigenvalues are sorted in increasing order, we want the largest
+            // ones first
+            let eigen = SymmetricEigen::new(covariance);
+            let n_kept = usize::min(n_components, n_properties);
+            let mut projection = Array2::zeros((n_properties, n_kept));
+            let mut explained_variance = Array1::zeros(n_kept);
+            for component in 0..n_kept {
+                let eigen_i = n_properties - 1 - component;
+                projection.column_mut(component).assign(&eigen.eigenvectors.column(eigen_i));
+                explained_variance[component] = f64::max(eigen.eigenvalues[eigen_i], 0.0);
+            }
+
+            BlockProjection {
+                fitted: fitted,
+                mean: mean,
+                projection: projection,
+                explained_variance: explained_variance,
+            }
+        }).collect();
+
+        return Ok(Pca {
+            keys_names: keys_names,
+            blocks: blocks,
+        });
+    }
+
+    /// Project the given `descriptor` onto the principal components computed
+    /// in `Pca::fit`.
+    ///
+    /// All the keys in the `descriptor` must have been seen during the fit,
+    /// with the same properties.
+    pub fn transform(&self, descriptor: &TensorMap) -> Result<TensorMap, Error> {
+        check_keys_names(descriptor, &self.keys_names)?;
+
+        let mut blocks = Vec::new();
+        for (key, block) in descriptor.iter() {
+            let key = key.iter().map(|value| value.i32()).collect::<Vec<_>>();
+            let projection = self.block_projection(&key).ok_or_else(|| {
+                Error::InvalidParameter(format!(
+                    "the key [{}] was not present when fitting this PCA",
+                    key_to_string(&key)
+                ))
+            })?;
+
+            blocks.push(projection.transform(&block)?);
+        }
+
+        return Ok(TensorMap::new(descriptor.keys().clone(), blocks)?);
+    }
+
+    /// Get the variance of the fitted data along each of the principal
+    /// components for the block with the given `key`, or `None` if this key
+    /// was not present when fitting.
+    pub fn explained_variance(&self, key: &[i32]) -> Option<&Array1<f64>> {
+        return self.block_projection(key).map(|projection| &projection.explained_variance);
+    }
+
+    /// Serialize the fitted projections to JSON
+    pub fn to_json(&self) -> Result<String, Error> {
+        return Ok(serde_json::to_string(self)?);
+    }
+
+    /// Load a PCA previously serialized to JSON with `to_json`
+    pub fn from_json(json: &str) -> Result<Pca, Error> {
+        return Ok(serde_json::from_str(json)?);
+    }
+
+    fn block_projection(&self, key: &[i32]) -> Option<&BlockProjection> {
+        return self.blocks.iter().find(|projection| projection.fitted.key == key);
+    }
+}
+
+impl BlockProjection {
+    fn transform(&self, block: &TensorBlockRef) -> Result<TensorBlock, Error> {
+        self.fitted.check_properties(&block.properties(), "PCA")?;
+
+        let n_components = self.projection.ncols();
+        let mut new_properties = LabelsBuilder::new(vec!["pca_component"]);
+        for component in 0..n_components {
+            new_properties.add(&[component]);
+        }
+        let new_properties = new_properties.finish();
+
+        let values = block.values().to_array();
+        let centered = as_matrix(values) - &self.mean;
+        let new_values = from_matrix(centered.dot(&self.projection), values.shape());
+
+        let mut new_block = TensorBlock::new(
+            new_values,
+            &block.samples(),
+            &block.components(),
+            &new_properties,
+        )?;
+
+        for parameter in GRADIENTS {
+            if let Some(gradient) = block.gradient(parameter) {
+                let gradient_values = gradient.values().to_array();
+                let new_gradient = from_matrix(
+                    as_matrix(gradient_values).dot(&self.projection),
+                    gradient_values.shape(),
+                );
+
+                new_block.add_gradient(parameter, TensorBlock::new(
+                    new_gradient,
+                    &gradient.samples(),
+                    &gradient.components(),
+                    &new_properties,
+                )?)?;
+            }
+        }
+
+        return Ok(new_block);
+    }
+}
+
+/// Reshape an array with properties as the last dimension to a 2D matrix,
+/// with all the other dimensions merged in the rows
+fn as_matrix(array: &ArrayD<f64>) -> Array2<f64> {
+    let n_properties = array.shape().last().copied().unwrap_or(1);
+    let n_rows = if n_properties == 0 { 0 } else { array.len() / n_properties };
+
+    return array.as_standard_layout()
+        .into_owned()
+        .into_shape((n_rows, n_properties))
+        .expect("invalid shape");
+}
+
+/// Reshape a 2D `matrix` to the given `shape`, replacing the last dimension
+/// with the number of columns of the matrix
+fn from_matrix(matrix: Array2<f64>, shape: &[usize]) -> ArrayD<f64> {
+    let mut shape = shape.to_vec();
+    *shape.last_mut().expect("arrays should have at least one dimension") = matrix.ncols();
+
+    return matrix.into_shape(IxDyn(&shape)).expect("invalid shape");
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use equistore::Labels;
+
+    use crate::{Calculator, CalculationOptions};
+    use crate::systems::test_utils::test_systems;
+
+    use super::*;
+
+    fn spherical_expansion(systems: &[&str]) -> TensorMap {
+        let mut calculator = Calculator::new("spherical_expansion", r#"{
+            "max_radial": 5,
+            "max_angular": 2,
+            "cutoff": 3.5,
+            "atomic_gaussian_width": 0.3,
+            "center_atom_weight": 1.0,
+            "radial_basis": { "Gto": {} },
+            "cutoff_function": { "ShiftedCosine": { "width": 0.5 } }
+        }"#.to_owned()).unwrap();
+
+        let options = CalculationOptions {
+            gradients: &["positions"],
+            ..Default::default()
+        };
+        return calculator.compute(&mut test_systems(systems), options).unwrap();
+    }
+
+    #[test]
+    fn full_rotation() {
+        let descriptor = spherical_expansion(&["water", "methane"]);
+        let pca = Pca::fit(&[spherical_expansion(&["water", "methane"])], 5).unwrap();
+        let transformed = pca.transform(&descriptor).unwrap();
+
+        for ((key, block), transformed_block) in descriptor.iter().zip(transformed.blocks().iter()) {
+            assert_eq!(transformed_block.properties(), Labels::new(["pca_component"], &[[0], [1], [2], [3], [4]]));
+
+            let key = key.iter().map(|value| value.i32()).collect::<Vec<_>>();
+            let variance = pca.explained_variance(&key).unwrap();
+            for i in 1..variance.len() {
+                assert!(variance[i - 1] >= variance[i]);
+            }
+
+            // using all the components is a rotation of the centered values
+            let values = as_matrix(block.values().to_array());
+            let mean = values.mean_axis(Axis(0)).unwrap();
+            let centered = &values - &mean;
+
+            let transformed_values = as_matrix(transformed_block.values().to_array());
+            for (row, transformed_row) in centered.outer_iter().zip(transformed_values.outer_iter()) {
+                assert_relative_eq!(row.dot(&row), transformed_row.dot(&transformed_row), epsilon=1e-10, max_relative=1e-10);
+            }
+
+            // the transformed values are uncorrelated, with variance given by
+            // the explained variance
+            let n_rows = transformed_values.nrows() as f64;
+            let covariance = transformed_values.t().dot(&transformed_values) / n_rows;
+            assert_relative_eq!(covariance, Array2::from_diag(variance), epsilon=1e-10, max_relative=1e-8);
+        }
+    }
+
+    #[test]
+    fn compress() {
+        let descriptor = spherical_expansion(&["water", "methane"]);
+        let pca = Pca::fit(&[spherical_expansion(&["water"]), spherical_expansion(&["methane"])], 2).unwrap();
+        let transformed = pca.transform(&descriptor).unwrap();
+        assert_eq!(transformed.keys(), descriptor.keys());
+
+        for ((key, block), transformed_block) in descriptor.iter().zip(transformed.blocks().iter()) {
+            let key = key.iter().map(|value| value.i32()).collect::<Vec<_>>();
+            let projection = pca.block_projection(&key).unwrap();
+
+            let values = block.values().to_array();
+            let transformed_values = transformed_block.values().to_array();
+            assert_eq!(transformed_values.shape()[..values.ndim() - 1], values.shape()[..values.ndim() - 1]);
+            assert_eq!(transformed_values.shape()[values.ndim() - 1], 2);
+
+            // gradients are projected without centering
+            let gradient = block.gradient("positions").unwrap();
+            let transformed_gradient = transformed_block.gradient("positions").unwrap();
+            assert_eq!(gradient.samples(), transformed_gradient.samples());
+
+            let expected = as_matrix(gradient.values().to_array()).dot(&projection.projection);
+            assert_relative_eq!(as_matrix(transformed_gradient.values().to_array()), expected, epsilon=1e-12, max_relative=1e-12);
+        }
+
+        // serialization round-trip
+        let json = pca.to_json().unwrap();
+        let loaded = Pca::from_json(&json).unwrap().transform(&descriptor).unwrap();
+        for (block, expected) in loaded.blocks().iter().zip(transformed.blocks().iter()) {
+            assert_relative_eq!(block.values().to_array(), expected.values().to_array(), epsilon=1e-12, max_relative=1e-12);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let error = Pca::fit(&[spherical_expansion(&["water"])], 0).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: the number of PCA components must be at least 1");
+
+        let pca = Pca::fit(&[spherical_expansion(&["water"])], 2).unwrap();
+        let error = pca.transform(&spherical_expansion(&["methane"])).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: the key [0, 1, 6] was not present when fitting this PCA");
+    }
+}
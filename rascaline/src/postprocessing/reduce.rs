@@ -0,0 +1,338 @@
+use std::collections::BTreeMap;
+
+use ndarray::{ArrayD, Axis};
+
+use equistore::{LabelsBuilder, LabelValue, TensorBlock, TensorMap};
+
+use crate::Error;
+
+use super::GRADIENTS;
+
+/// Weights associated with the samples of a descriptor, used when summing or
+/// averaging over samples.
+///
+/// The weight of a sample is the product of the weight of the corresponding
+/// structure and the weight of the corresponding atom. Missing weights are
+/// taken to be 1.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SampleWeights<'a> {
+    /// Weight of each structure (for example thermodynamic weights), indexed
+    /// by the `"structure"` dimension of the samples.
+    pub structures: Option<&'a [f64]>,
+    /// Weight of each atom (for example a per-atom confidence) for each
+    /// structure, indexed by the `"structure"` and `"center"` dimensions of
+    /// the samples.
+    pub atoms: Option<&'a [Vec<f64>]>,
+}
+
+impl<'a> SampleWeights<'a> {
+    /// Get the weight of the given `sample`, where `structure_i` and
+    /// `center_i` are the positions of the `"structure"` and `"center"`
+    /// dimensions in the samples
+    fn weight(&self, sample: &[LabelValue], structure_i: Option<usize>, center_i: Option<usize>) -> Result<f64, Error> {
+        if self.structures.is_none() && self.atoms.is_none() {
+            return Ok(1.0);
+        }
+
+        let structure = match structure_i {
+            Some(structure_i) => sample[structure_i].usize(),
+            None => return Err(Error::InvalidParameter(
+                "sample weights require a \"structure\" dimension in the samples".into()
+            )),
+        };
+
+        let mut weight = 1.0;
+        if let Some(structures) = self.structures {
+            weight *= *structures.get(structure).ok_or_else(|| Error::InvalidParameter(format!(
+                "missing weight for structure {}, we only have {} structure weights",
+                structure, structures.len()
+            )))?;
+        }
+
+        if let Some(atoms) = self.atoms {
+            let center = match center_i {
+                Some(center_i) => sample[center_i].usize(),
+                None => return Err(Error::InvalidParameter(
+                    "atom weights require a \"center\" dimension in the samples".into()
+                )),
+            };
+
+            weight *= *atoms.get(structure).and_then(|atoms| atoms.get(center)).ok_or_else(|| {
+                Error::InvalidParameter(format!(
+                    "missing weight for atom {} in structure {}", center, structure
+                ))
+            })?;
+        }
+
+        return Ok(weight);
+    }
+}
+
+/// Sum the `descriptor` over the sample dimensions in `sample_names`, using
+/// the given `weights` for each sample.
+///
+/// For example, summing over `"center"` creates a descriptor for each
+/// structure from an atom-centered descriptor. The remaining sample
+/// dimensions are sorted in the output. Gradients are summed in the same way
+/// as the values, using the weight of the corresponding sample.
+pub fn sum_over_samples(descriptor: &TensorMap, sample_names: &[&str], weights: SampleWeights) -> Result<TensorMap, Error> {
+    return reduce_over_samples(descriptor, sample_names, weights, false);
+}
+
+/// Compute the weighted average of the `descriptor` over the sample
+/// dimensions in `sample_names`, using the given `weights` for each sample.
+///
+/// This is the same as `sum_over_samples`, with the result divided by the
+/// sum of the weights of the samples in each group. Groups with a total
+/// weight of zero are set to zero.
+pub fn mean_over_samples(descriptor: &TensorMap, sample_names: &[&str], weights: SampleWeights) -> Result<TensorMap, Error> {
+    return reduce_over_samples(descriptor, sample_names, weights, true);
+}
+
+#[allow(clippy::too_many_lines)]
+fn reduce_over_samples(
+    descriptor: &TensorMap,
+    sample_names: &[&str],
+    weights: SampleWeights,
+    mean: bool,
+) -> Result<TensorMap, Error> {
+    let mut blocks = Vec::new();
+    for (_, block) in descriptor.iter() {
+        let samples = block.samples();
+        let names = samples.names();
+        for name in sample_names {
+            if !names.contains(name) {
+                return Err(Error::InvalidParameter(format!(
+                    "'{}' is not one of the samples of this descriptor: [{}]",
+                    name, names.join(", ")
+                )));
+            }
+        }
+
+        let kept = (0..names.len())
+            .filter(|&i| !sample_names.contains(&names[i]))
+            .collect::<Vec<_>>();
+        if kept.is_empty() {
+            return Err(Error::InvalidParameter(
+                "at least one sample dimension must remain after the reduction".into()
+            ));
+        }
+
+        let structure_i = names.iter().position(|&name| name == "structure");
+        let center_i = names.iter().position(|&name| name == "center");
+
+        let mut new_samples = BTreeMap::new();
+        for sample in samples.iter() {
+            let new_sample = kept.iter().map(|&i| sample[i].i32()).collect::<Vec<_>>();
+            new_samples.insert(new_sample, 0);
+        }
+        for (i, position) in new_samples.values_mut().enumerate() {
+            *position = i;
+        }
+
+        let mut mapping = Vec::with_capacity(samples.count());
+        let mut sample_weights = Vec::with_capacity(samples.count());
+        let mut total_weights = vec![0.0; new_samples.len()];
+        for sample in samples.iter() {
+            let new_sample = kept.iter().map(|&i| sample[i].i32()).collect::<Vec<_>>();
+            let new_sample_i = new_samples[&new_sample];
+            let weight = weights.weight(sample, structure_i, center_i)?;
+
+            mapping.push(new_sample_i);
+            sample_weights.push(weight);
+            total_weights[new_sample_i] += weight;
+        }
+
+        // weight to use for the contribution of each sample, including the
+        // normalization for averages
+        let factors = mapping.iter().zip(&sample_weights).map(|(&new_sample_i, &weight)| {
+            if mean {
+                let total = total_weights[new_sample_i];
+                if total == 0.0 { 0.0 } else { weight / total }
+            } else {
+                weight
+            }
+        }).collect::<Vec<_>>();
+
+        let mut new_samples_labels = LabelsBuilder::new(kept.iter().map(|&i| names[i]).collect());
+        for sample in new_samples.keys() {
+            new_samples_labels.add(sample);
+        }
+        let new_samples_labels = new_samples_labels.finish();
+
+        let properties = block.properties();
+        let new_values = reduce_array(block.values().to_array(), &mapping, &factors, new_samples.len());
+        let mut new_block = TensorBlock::new(
+            new_values,
+            &new_samples_labels,
+            &block.components(),
+            &properties,
+        )?;
+
+        for parameter in GRADIENTS {
+            if let Some(gradient) = block.gradient(parameter) {
+                let gradient_samples = gradient.samples();
+
+                // the first dimension of gradient samples is the "sample",
+                // which is updated to the new samples
+                let mut new_gradient_samples = BTreeMap::new();
+                let mut gradient_mapping = Vec::with_capacity(gradient_samples.count());
+                let mut gradient_factors = Vec::with_capacity(gradient_samples.count());
+                for gradient_sample in gradient_samples.iter() {
+                    let sample_i = gradient_sample[0].usize();
+
+                    let mut new_gradient_sample = vec![mapping[sample_i] as i32];
+                    new_gradient_sample.extend(gradient_sample[1..].iter().map(|value| value.i32()));
+                    new_gradient_samples.insert(new_gradient_sample.clone(), 0);
+
+                    gradient_mapping.push(new_gradient_sample);
+                    gradient_factors.push(factors[sample_i]);
+                }
+
+                for (i, position) in new_gradient_samples.values_mut().enumerate() {
+                    *position = i;
+                }
+
+                let gradient_mapping = gradient_mapping.iter()
+                    .map(|new_gradient_sample| new_gradient_samples[new_gradient_sample])
+                    .collect::<Vec<_>>();
+
+                let mut new_gradient_samples_labels = LabelsBuilder::new(gradient_samples.names());
+                for gradient_sample in new_gradient_samples.keys() {
+                    new_gradient_samples_labels.add(gradient_sample);
+                }
+
+                let new_gradient = reduce_array(
+                    gradient.values().to_array(),
+                    &gradient_mapping,
+                    &gradient_factors,
+                    new_gradient_samples.len(),
+                );
+
+                new_block.add_gradient(parameter, TensorBlock::new(
+                    new_gradient,
+                    &new_gradient_samples_labels.finish(),
+                    &gradient.components(),
+                    &properties,
+                )?)?;
+            }
+        }
+
+        blocks.push(new_block);
+    }
+
+    return Ok(TensorMap::new(descriptor.keys().clone(), blocks)?);
+}
+
+/// Sum the rows of `array` into a new array with `n_rows` rows, where the row
+/// `i` of `array` is multiplied by `factors[i]` and added to the row
+/// `mapping[i]` of the new array.
+fn reduce_array(array: &ArrayD<f64>, mapping: &[usize], factors: &[f64], n_rows: usize) -> ArrayD<f64> {
+    let mut shape = array.shape().to_vec();
+    shape[0] = n_rows;
+
+    let mut result = ArrayD::from_elem(shape, 0.0);
+    for (row_i, row) in array.axis_iter(Axis(0)).enumerate() {
+        result.index_axis_mut(Axis(0), mapping[row_i]).scaled_add(factors[row_i], &row);
+    }
+
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use equistore::Labels;
+
+    use crate::{Calculator, CalculationOptions};
+    use crate::systems::test_utils::test_systems;
+
+    use super::*;
+
+    fn descriptor() -> TensorMap {
+        let mut calculator = Calculator::new("dummy_calculator", r#"{
+            "cutoff": 1.0,
+            "delta": 9,
+            "name": ""
+        }"#.to_owned()).unwrap();
+
+        let options = CalculationOptions {
+            gradients: &["positions"],
+            ..Default::default()
+        };
+        return calculator.compute(&mut test_systems(&["water", "water"]), options).unwrap();
+    }
+
+    #[test]
+    fn sum() {
+        let descriptor = descriptor();
+        let summed = sum_over_samples(&descriptor, &["center"], SampleWeights::default()).unwrap();
+        assert_eq!(summed.keys(), descriptor.keys());
+
+        // H block
+        let block = descriptor.block_by_id(1);
+        let values = block.values().to_array();
+        let expected = &values.index_axis(Axis(0), 0) + &values.index_axis(Axis(0), 1);
+
+        let summed_block = summed.block_by_id(1);
+        assert_eq!(summed_block.samples(), Labels::new(["structure"], &[[0], [1]]));
+        let summed_values = summed_block.values().to_array();
+        assert_relative_eq!(summed_values.index_axis(Axis(0), 0), expected);
+        assert_relative_eq!(summed_values.index_axis(Axis(0), 1), expected);
+
+        let gradient = summed_block.gradient("positions").unwrap();
+        assert_eq!(gradient.samples().names(), ["sample", "structure", "atom"]);
+        for gradient_sample in gradient.samples().iter() {
+            assert_eq!(gradient_sample[0].i32(), gradient_sample[1].i32());
+        }
+
+        let error = sum_over_samples(&descriptor, &["atom"], SampleWeights::default()).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: 'atom' is not one of the samples of this descriptor: [structure, center]");
+
+        let error = sum_over_samples(&descriptor, &["structure", "center"], SampleWeights::default()).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: at least one sample dimension must remain after the reduction");
+    }
+
+    #[test]
+    fn weighted() {
+        let descriptor = descriptor();
+        let block = descriptor.block_by_id(1);
+        let values = block.values().to_array();
+        let first = values.index_axis(Axis(0), 0);
+        let second = values.index_axis(Axis(0), 1);
+
+        let atoms = [vec![1.0, 2.0, 0.5], vec![1.0, 1.0, 1.0]];
+        let weights = SampleWeights {
+            structures: Some(&[3.0, 1.0]),
+            atoms: Some(&atoms),
+        };
+
+        let summed = sum_over_samples(&descriptor, &["center"], weights).unwrap();
+        let summed_values = summed.block_by_id(1).values().to_array();
+        assert_relative_eq!(summed_values.index_axis(Axis(0), 0), 6.0 * &first + 1.5 * &second);
+        assert_relative_eq!(summed_values.index_axis(Axis(0), 1), &first + &second);
+
+        let mean = mean_over_samples(&descriptor, &["center"], weights).unwrap();
+        let mean_values = mean.block_by_id(1).values().to_array();
+        assert_relative_eq!(mean_values.index_axis(Axis(0), 0), (6.0 * &first + 1.5 * &second) / 7.5);
+        assert_relative_eq!(mean_values.index_axis(Axis(0), 1), (&first + &second) / 2.0);
+
+        // weighted average over structures
+        let weights = SampleWeights {
+            structures: Some(&[3.0, 1.0]),
+            atoms: None,
+        };
+        let mean = mean_over_samples(&descriptor, &["structure"], weights).unwrap();
+        let mean_block = mean.block_by_id(1);
+        assert_eq!(mean_block.samples(), Labels::new(["center"], &[[1], [2]]));
+        // both structures are the same
+        assert_relative_eq!(mean_block.values().to_array().index_axis(Axis(0), 0), first);
+
+        let weights = SampleWeights {
+            structures: Some(&[3.0]),
+            atoms: None,
+        };
+        let error = sum_over_samples(&descriptor, &["center"], weights).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: missing weight for structure 1, we only have 1 structure weights");
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::BTreeSet;
+
+use equistore::{Labels, LabelsBuilder, TensorBlock, TensorMap};
+
+use crate::Error;
+
+use super::GRADIENTS;
+
+/// Create a copy of `descriptor` where the dimensions of the keys, samples,
+/// components and properties are renamed according to `renames`, which
+/// contains `(old_name, new_name)` pairs.
+///
+/// The gradients are renamed in the same way, making sure that for example
+/// the `"structure"` dimension of the samples and of the gradient samples
+/// stays consistent. This is useful to use rascaline descriptors with
+/// libraries expecting different naming conventions (e.g. `"center_type"`
+/// instead of `"species_center"`).
+///
+/// ```
+/// # use rascaline::{Calculator, System, SimpleSystem, Vector3D};
+/// # use rascaline::systems::UnitCell;
+/// # use rascaline::postprocessing::rename_dimensions;
+/// let mut calculator = Calculator::new("dummy_calculator", r#"{
+///     "cutoff": 1.0, "delta": 0, "name": ""
+/// }"#.to_owned()).unwrap();
+///
+/// let mut system = SimpleSystem::new(UnitCell::infinite());
+/// system.add_atom(1, Vector3D::new(0.0, 0.0, 0.0));
+/// let mut systems = vec![Box::new(system) as Box<dyn System>];
+/// let descriptor = calculator.compute(&mut systems, Default::default()).unwrap();
+///
+/// let renamed = rename_dimensions(&descriptor, &[("species_center", "center_type")]).unwrap();
+/// assert_eq!(renamed.keys().names(), ["center_type"]);
+/// ```
+pub fn rename_dimensions(descriptor: &TensorMap, renames: &[(&str, &str)]) -> Result<TensorMap, Error> {
+    let mut old_names = BTreeSet::new();
+    for &(old, _) in renames {
+        if !old_names.insert(old) {
+            return Err(Error::InvalidParameter(format!(
+                "the '{}' dimension is renamed multiple times", old
+            )));
+        }
+    }
+
+    let mut renamer = Renamer {
+        renames: renames,
+        used: vec![false; renames.len()],
+    };
+
+    let keys = renamer.rename(descriptor.keys())?;
+
+    let mut blocks = Vec::new();
+    for (_, block) in descriptor.iter() {
+        let components = block.components().iter()
+            .map(|component| renamer.rename(component))
+            .collect::<Result<Vec<_>, _>>()?;
+        let properties = renamer.rename(&block.properties())?;
+
+        let mut new_block = TensorBlock::new(
+            block.values().to_array().clone(),
+            &renamer.rename(&block.samples())?,
+            &components,
+            &properties,
+        )?;
+
+        for parameter in GRADIENTS {
+            if let Some(gradient) = block.gradient(parameter) {
+                let gradient_components = gradient.components().iter()
+                    .map(|component| renamer.rename(component))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                new_block.add_gradient(parameter, TensorBlock::new(
+                    gradient.values().to_array().clone(),
+                    &renamer.rename(&gradient.samples())?,
+                    &gradient_components,
+                    &properties,
+                )?)?;
+            }
+        }
+
+        blocks.push(new_block);
+    }
+
+    if let Some(i) = renamer.used.iter().position(|&used| !used) {
+        return Err(Error::InvalidParameter(format!(
+            "the '{}' dimension does not exist in this descriptor", renames[i].0
+        )));
+    }
+
+    return Ok(TensorMap::new(keys, blocks)?);
+}
+
+/// Rename the dimensions of multiple labels, keeping track of which renames
+/// have been used
+struct Renamer<'a> {
+    renames: &'a [(&'a str, &'a str)],
+    used: Vec<bool>,
+}
+
+impl<'a> Renamer<'a> {
+    fn rename(&mut self, labels: &Labels) -> Result<Labels, Error> {
+        let mut names = Vec::new();
+        for name in labels.names() {
+            if let Some(i) = self.renames.iter().position(|&(old, _)| old == name) {
+                self.used[i] = true;
+                names.push(self.renames[i].1);
+            } else {
+                names.push(name);
+            }
+        }
+
+        if names.is_empty() {
+            return Ok(labels.clone());
+        }
+
+        let unique_names = names.iter().collect::<BTreeSet<_>>();
+        if unique_names.len() != names.len() {
+            return Err(Error::InvalidParameter(format!(
+                "renaming dimensions would create duplicated names: [{}]",
+                names.iter().map(|name| format!("\"{}\"", name)).collect::<Vec<_>>().join(", ")
+            )));
+        }
+
+        let mut builder = LabelsBuilder::new(names);
+        for entry in labels.iter() {
+            builder.add(entry);
+        }
+
+        return Ok(builder.finish());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Calculator;
+    use crate::systems::test_utils::test_systems;
+
+    use super::*;
+
+    #[test]
+    fn rename() {
+        let mut calculator = Calculator::new("dummy_calculator", r#"{
+            "cutoff": 1.0,
+            "delta": 9,
+            "name": ""
+        }"#.to_owned()).unwrap();
+
+        let mut systems = test_systems(&["water", "methane"]);
+        let options = crate::CalculationOptions {
+            gradients: &["positions", "cell"],
+            ..Default::default()
+        };
+        let descriptor = calculator.compute(&mut systems, options).unwrap();
+
+        let renamed = rename_dimensions(&descriptor, &[
+            ("species_center", "center_type"),
+            ("structure", "system"),
+        ]).unwrap();
+
+        assert_eq!(renamed.keys().names(), ["center_type"]);
+        assert_eq!(renamed.keys().count(), descriptor.keys().count());
+        for ((_, block), (_, expected)) in renamed.iter().zip(descriptor.iter()) {
+            assert_eq!(block.samples().names(), ["system", "center"]);
+            assert_eq!(block.properties(), expected.properties());
+            assert_eq!(block.values().to_array(), expected.values().to_array());
+
+            let gradient = block.gradient("positions").unwrap();
+            assert_eq!(gradient.samples().names(), ["sample", "system", "atom"]);
+            assert_eq!(gradient.values().to_array(), expected.gradient("positions").unwrap().values().to_array());
+
+            let gradient = block.gradient("cell").unwrap();
+            assert_eq!(gradient.samples(), expected.gradient("cell").unwrap().samples());
+        }
+
+        let error = rename_dimensions(&descriptor, &[("not_there", "foo")]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: the 'not_there' dimension does not exist in this descriptor");
+
+        let error = rename_dimensions(&descriptor, &[("center", "structure")]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: renaming dimensions would create duplicated names: [\"structure\", \"structure\"]");
+
+        let error = rename_dimensions(&descriptor, &[("center", "a"), ("center", "b")]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: the 'center' dimension is renamed multiple times");
+    }
+}
@@ -0,0 +1,260 @@
+use ndarray::{ArrayD, Axis};
+
+use equistore::{TensorBlock, TensorBlockRef, TensorMap};
+
+use crate::Error;
+
+use super::{GRADIENTS, key_to_string, check_keys_names, fit_blocks, FittedBlock};
+
+/// Standardization of the properties of descriptors, removing the mean and
+/// dividing by the standard deviation of each property.
+///
+/// The mean and standard deviation of each property in each block are
+/// computed over a dataset with `Standardizer::fit`, and can then be applied
+/// to other descriptors with `Standardizer::transform`. The fitted statistics
+/// can be saved with `Standardizer::to_json`, so that new structures are
+/// scaled with the mean and standard deviation of the training set.
+///
+/// The statistics of a property are computed over all the samples and all the
+/// components of the corresponding block. Gradients are divided by the
+/// standard deviation. Properties with a standard deviation of zero are only
+/// centered, and not scaled.
+#[derive(Debug, Clone, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Standardizer {
+    /// names of the keys in the fitted descriptors
+    keys_names: Vec<String>,
+    /// statistics for each block in the fitted descriptors
+    blocks: Vec<BlockStatistics>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct BlockStatistics {
+    /// key and properties of this block
+    #[serde(flatten)]
+    fitted: FittedBlock,
+    /// mean of each property
+    mean: Vec<f64>,
+    /// standard deviation of each property
+    std: Vec<f64>,
+}
+
+/// Running mean and variance of each property in a block, updated with
+/// Welford's algorithm
+struct RunningStatistics {
+    count: usize,
+    mean: Vec<f64>,
+    /// sum of squared differences from the mean
+    m2: Vec<f64>,
+}
+
+impl Standardizer {
+    /// Compute the mean and standard deviation of each property in each block
+    /// over all the given `descriptors`.
+    ///
+    /// All descriptors must have the same keys names, and blocks with the
+    /// same key must have the same properties. Keys can be missing from some
+    /// of the descriptors.
+    pub fn fit(descriptors: &[TensorMap]) -> Result<Standardizer, Error> {
+        let (keys_names, all_statistics) = fit_blocks(descriptors, "standardizer",
+            |properties| RunningStatistics {
+                count: 0,
+                mean: vec![0.0; properties.count()],
+                m2: vec![0.0; properties.count()],
+            },
+            |statistics, block| {
+                // each lane along the last axis contains all the properties
+                // for a single sample and component
+                let values = block.values().to_array();
+                for row in values.lanes(Axis(values.ndim() - 1)) {
+                    statistics.count += 1;
+                    let count = statistics.count as f64;
+                    for ((value, mean), m2) in row.iter().zip(&mut statistics.mean).zip(&mut statistics.m2) {
+                        let delta = value - *mean;
+                        *mean += delta / count;
+                        *m2 += delta * (value - *mean);
+                    }
+                }
+            },
+        )?;
+
+        let blocks = all_statistics.into_iter().map(|(fitted, statistics)| {
+            let count = statistics.count.max(1) as f64;
+            let std = statistics.m2.iter().map(|m2| f64::sqrt(m2 / count)).collect();
+
+            BlockStatistics {
+                fitted: fitted,
+                mean: statistics.mean,
+                std: std,
+            }
+        }).collect();
+
+        return Ok(Standardizer {
+            keys_names: keys_names,
+            blocks: blocks,
+        });
+    }
+
+    /// Standardize the given `descriptor`, using the statistics computed in
+    /// `Standardizer::fit`.
+    ///
+    /// All the keys in the `descriptor` must have been seen during the fit,
+    /// with the same properties.
+    pub fn transform(&self, descriptor: &TensorMap) -> Result<TensorMap, Error> {
+        check_keys_names(descriptor, &self.keys_names)?;
+
+        let mut blocks = Vec::new();
+        for (key, block) in descriptor.iter() {
+            let key = key.iter().map(|value| value.i32()).collect::<Vec<_>>();
+            let statistics = self.blocks.iter().find(|statistics| statistics.fitted.key == key).ok_or_else(|| {
+                Error::InvalidParameter(format!(
+                    "the key [{}] was not present when fitting this standardizer",
+                    key_to_string(&key)
+                ))
+            })?;
+
+            blocks.push(statistics.transform(&block)?);
+        }
+
+        return Ok(TensorMap::new(descriptor.keys().clone(), blocks)?);
+    }
+
+    /// Serialize the fitted means and standard deviations to JSON
+    pub fn to_json(&self) -> Result<String, Error> {
+        return Ok(serde_json::to_string(self)?);
+    }
+
+    /// Load a standardizer previously serialized to JSON with `to_json`
+    pub fn from_json(json: &str) -> Result<Standardizer, Error> {
+        return Ok(serde_json::from_str(json)?);
+    }
+}
+
+impl BlockStatistics {
+    fn transform(&self, block: &TensorBlockRef) -> Result<TensorBlock, Error> {
+        let properties = block.properties();
+        self.fitted.check_properties(&properties, "standardizer")?;
+
+        // properties with zero standard deviation are constant, and only
+        // need to be centered
+        let scale = self.std.iter()
+            .map(|&std| if std == 0.0 { 1.0 } else { 1.0 / std })
+            .collect::<Vec<_>>();
+
+        let mut values = block.values().to_array().clone();
+        for mut row in values.lanes_mut(Axis(values.ndim() - 1)) {
+            for ((value, mean), scale) in row.iter_mut().zip(&self.mean).zip(&scale) {
+                *value = (*value - mean) * scale;
+            }
+        }
+
+        let mut new_block = TensorBlock::new(
+            values,
+            &block.samples(),
+            &block.components(),
+            &properties,
+        )?;
+
+        for parameter in GRADIENTS {
+            if let Some(gradient) = block.gradient(parameter) {
+                let mut gradient_values: ArrayD<f64> = gradient.values().to_array().clone();
+                for mut row in gradient_values.lanes_mut(Axis(gradient_values.ndim() - 1)) {
+                    for (value, scale) in row.iter_mut().zip(&scale) {
+                        *value *= scale;
+                    }
+                }
+
+                new_block.add_gradient(parameter, TensorBlock::new(
+                    gradient_values,
+                    &gradient.samples(),
+                    &gradient.components(),
+                    &properties,
+                )?)?;
+            }
+        }
+
+        return Ok(new_block);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use crate::{Calculator, CalculationOptions};
+    use crate::systems::test_utils::test_systems;
+
+    use super::*;
+
+    fn compute(systems: &[&str]) -> TensorMap {
+        let mut calculator = Calculator::new("dummy_calculator", r#"{
+            "cutoff": 1.0,
+            "delta": 9,
+            "name": ""
+        }"#.to_owned()).unwrap();
+
+        let options = CalculationOptions {
+            gradients: &["positions"],
+            ..Default::default()
+        };
+        return calculator.compute(&mut test_systems(systems), options).unwrap();
+    }
+
+    #[test]
+    fn standardize() {
+        let descriptors = [compute(&["water"]), compute(&["methane", "water"])];
+        let standardizer = Standardizer::fit(&descriptors).unwrap();
+
+        // the standardized descriptor for the whole dataset has zero mean and
+        // unit variance
+        let all = compute(&["water", "methane", "water"]);
+        let standardized = standardizer.transform(&all).unwrap();
+        assert_eq!(standardized.keys(), all.keys());
+
+        for (block, standardized_block) in all.blocks().iter().zip(standardized.blocks().iter()) {
+            let values = standardized_block.values().to_array();
+            let mean = values.mean_axis(Axis(0)).unwrap();
+            let std = values.std_axis(Axis(0), 0.0);
+
+            let original_std = block.values().to_array().std_axis(Axis(0), 0.0);
+            for property_i in 0..mean.len() {
+                assert_relative_eq!(mean[property_i], 0.0, epsilon=1e-12);
+                if original_std[property_i] == 0.0 {
+                    assert_eq!(std[property_i], 0.0);
+                } else {
+                    assert_relative_eq!(std[property_i], 1.0, max_relative=1e-12);
+                }
+            }
+
+            let gradient = block.gradient("positions").unwrap().values().to_array();
+            let standardized_gradient = standardized_block.gradient("positions").unwrap().values().to_array();
+            for (property_i, &std) in original_std.iter().enumerate() {
+                let expected = if std == 0.0 {
+                    gradient.index_axis(Axis(2), property_i).to_owned()
+                } else {
+                    gradient.index_axis(Axis(2), property_i).to_owned() / std
+                };
+                assert_relative_eq!(standardized_gradient.index_axis(Axis(2), property_i), expected, max_relative=1e-12);
+            }
+        }
+
+        // serialization round-trip
+        let json = standardizer.to_json().unwrap();
+        let loaded = Standardizer::from_json(&json).unwrap().transform(&all).unwrap();
+        for (block, expected) in loaded.blocks().iter().zip(standardized.blocks().iter()) {
+            assert_relative_eq!(block.values().to_array(), expected.values().to_array(), epsilon=1e-12, max_relative=1e-12);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let standardizer = Standardizer::fit(&[compute(&["water"])]).unwrap();
+
+        let error = standardizer.transform(&compute(&["methane"])).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: the key [6] was not present when fitting this standardizer");
+
+        let error = Standardizer::fit(&[]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: can not fit a standardizer without any descriptor");
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeSet;
+
+use ndarray::ArrayD;
+
+use equistore::{Labels, LabelsBuilder, TensorBlock, TensorBlockRef, TensorMap};
+
+use crate::Error;
+
+use super::GRADIENTS;
+
+/// Re-block all the `descriptors` onto the union of their keys, adding empty
+/// blocks for the keys missing from some of the descriptors.
+///
+/// This is useful when computing a descriptor batch-by-batch over a dataset,
+/// where some keys (i.e. some species or combination of species) are only
+/// present in some of the batches. The resulting descriptors all have the
+/// same keys, in the same (sorted) order. The empty blocks have no samples,
+/// and take their components, properties and gradients from one of the
+/// descriptors containing the corresponding key.
+///
+/// All the descriptors must have the same keys names and the same set of
+/// gradients.
+pub fn union_keys(descriptors: &[TensorMap]) -> Result<Vec<TensorMap>, Error> {
+    let first = match descriptors.first() {
+        Some(first) => first,
+        None => return Ok(Vec::new()),
+    };
+
+    let names = first.keys().names();
+    let mut all_keys = BTreeSet::new();
+    for descriptor in descriptors {
+        if descriptor.keys().names() != names {
+            return Err(Error::InvalidParameter(format!(
+                "all descriptors must have the same keys names, got [{}] and [{}]",
+                names.join(", "), descriptor.keys().names().join(", ")
+            )));
+        }
+
+        for key in descriptor.keys().iter() {
+            all_keys.insert(key.iter().map(|value| value.i32()).collect::<Vec<_>>());
+        }
+    }
+
+    let mut keys = LabelsBuilder::new(names);
+    for key in &all_keys {
+        keys.add(key);
+    }
+    let keys = keys.finish();
+
+    let mut result = Vec::new();
+    for descriptor in descriptors {
+        let mut blocks = Vec::new();
+        for key in keys.iter() {
+            if let Some(block_i) = descriptor.keys().position(key) {
+                blocks.push(copy_block(&descriptor.block_by_id(block_i))?);
+                continue;
+            }
+
+            // find another descriptor with this key to use as a template
+            let template = descriptors.iter()
+                .find_map(|other| other.keys().position(key).map(|block_i| other.block_by_id(block_i)))
+                .expect("all keys should be in at least one descriptor");
+
+            blocks.push(empty_block(&template)?);
+        }
+
+        result.push(TensorMap::new(keys.clone(), blocks)?);
+    }
+
+    return Ok(result);
+}
+
+/// Create a copy of `block`, including all its gradients
+fn copy_block(block: &TensorBlockRef) -> Result<TensorBlock, Error> {
+    let properties = block.properties();
+    let mut new_block = TensorBlock::new(
+        block.values().to_array().clone(),
+        &block.samples(),
+        &block.components(),
+        &properties,
+    )?;
+
+    for parameter in GRADIENTS {
+        if let Some(gradient) = block.gradient(parameter) {
+            new_block.add_gradient(parameter, TensorBlock::new(
+                gradient.values().to_array().clone(),
+                &gradient.samples(),
+                &gradient.components(),
+                &properties,
+            )?)?;
+        }
+    }
+
+    return Ok(new_block);
+}
+
+/// Create a block with the same metadata and gradients as `template`, but
+/// without any samples
+fn empty_block(template: &TensorBlockRef) -> Result<TensorBlock, Error> {
+    let properties = template.properties();
+    let mut new_block = TensorBlock::new(
+        zero_samples_array(template),
+        &empty_labels(&template.samples()),
+        &template.components(),
+        &properties,
+    )?;
+
+    for parameter in GRADIENTS {
+        if let Some(gradient) = template.gradient(parameter) {
+            new_block.add_gradient(parameter, TensorBlock::new(
+                zero_samples_array(&gradient),
+                &empty_labels(&gradient.samples()),
+                &gradient.components(),
+                &properties,
+            )?)?;
+        }
+    }
+
+    return Ok(new_block);
+}
+
+/// Create an array with the same shape as the values in `block`, except for
+/// the samples dimension which is set to 0
+fn zero_samples_array(block: &TensorBlockRef) -> ArrayD<f64> {
+    let mut shape = block.values().to_array().shape().to_vec();
+    shape[0] = 0;
+    return ArrayD::from_elem(shape, 0.0);
+}
+
+/// Create labels with the same names as `labels`, but no entries
+fn empty_labels(labels: &Labels) -> Labels {
+    return LabelsBuilder::new(labels.names()).finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Calculator, CalculationOptions};
+    use crate::systems::test_utils::test_systems;
+
+    use super::*;
+
+    #[test]
+    fn union() {
+        let mut calculator = Calculator::new("dummy_calculator", r#"{
+            "cutoff": 1.0,
+            "delta": 9,
+            "name": ""
+        }"#.to_owned()).unwrap();
+
+        let options = CalculationOptions {
+            gradients: &["positions"],
+            ..Default::default()
+        };
+        let water = calculator.compute(&mut test_systems(&["water"]), options).unwrap();
+        let methane = calculator.compute(&mut test_systems(&["methane"]), options).unwrap();
+
+        let descriptors = union_keys(&[water, methane]).unwrap();
+        assert_eq!(descriptors.len(), 2);
+
+        let expected = Labels::new(["species_center"], &[[-42], [1], [6]]);
+        assert_eq!(*descriptors[0].keys(), expected);
+        assert_eq!(*descriptors[1].keys(), expected);
+
+        // water does not contain carbon
+        let block = descriptors[0].block_by_id(2);
+        assert_eq!(block.samples().count(), 0);
+        assert_eq!(block.values().to_array().shape(), [0, 2]);
+        let gradient = block.gradient("positions").unwrap();
+        assert_eq!(gradient.samples().names(), ["sample", "structure", "atom"]);
+        assert_eq!(gradient.values().to_array().shape(), [0, 3, 2]);
+
+        // methane does not contain oxygen
+        let block = descriptors[1].block_by_id(0);
+        assert_eq!(block.samples().count(), 0);
+
+        // existing blocks are copied
+        let reference = calculator.compute(&mut test_systems(&["methane"]), options).unwrap();
+        let block = descriptors[1].block_by_id(2);
+        let expected = reference.block_by_id(1);
+        assert_eq!(block.samples(), expected.samples());
+        assert_eq!(block.values().to_array(), expected.values().to_array());
+
+        let gradient = block.gradient("positions").unwrap();
+        let expected = expected.gradient("positions").unwrap();
+        assert_eq!(gradient.samples(), expected.samples());
+        assert_eq!(gradient.values().to_array(), expected.values().to_array());
+
+        assert!(union_keys(&[]).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+//! Access to the profiling data collected during calculations.
+//!
+//! Rascaline uses the [`time_graph`](https://docs.rs/time-graph/) crate to
+//! collect timing information on the calculations. This profiling code
+//! collects the total time spent inside the most important functions, as well
+//! as the function call graph (which function called which other function).
+//!
+//! Data collection is disabled by default. It can be enabled for all
+//! calculations with [`enable`], or only for the calculations of a given
+//! calculator with [`Calculator::set_profiling`](crate::Calculator::set_profiling).
+//!
+//! The instrumentation code is only compiled when the `profiling` cargo
+//! feature is enabled. Without it, no data is collected and [`report`] returns
+//! an error.
+//!
+//! ```no_run
+//! rascaline::profiling::enable(true);
+//! rascaline::profiling::clear();
+//! // run some calculations
+//! let report = rascaline::profiling::report("short_table").unwrap();
+//! rascaline::profiling::enable(false);
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::Error;
+
+/// Is profiling data collection enabled for all calculations?
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enable or disable profiling data collection for all calculations
+pub fn enable(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+    #[cfg(feature = "profiling")]
+    time_graph::enable_data_collection(enabled);
+}
+
+/// Check if profiling data collection was enabled for all calculations with
+/// [`enable`]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Clear all collected profiling data
+pub fn clear() {
+    #[cfg(feature = "profiling")]
+    time_graph::clear_collected_data();
+}
+
+/// Get the profiling data collected so far in the given `format`. The
+/// supported formats are `"table"`, `"short_table"` (using short function
+/// names) and `"json"`.
+#[cfg(feature = "profiling")]
+pub fn report(format: &str) -> Result<String, Error> {
+    let graph = time_graph::get_full_graph();
+    match format {
+        "table" => Ok(graph.as_table()),
+        "short_table" => Ok(graph.as_short_table()),
+        "json" => Ok(graph.as_json()),
+        format => Err(Error::InvalidParameter(format!(
+            "invalid profiling data format: {}, expected 'table', 'short_table' or 'json'",
+            format
+        ))),
+    }
+}
+
+/// Get the profiling data collected so far, see the documentation of this
+/// function when the `profiling` feature is enabled.
+#[cfg(not(feature = "profiling"))]
+pub fn report(_format: &str) -> Result<String, Error> {
+    return Err(Error::InvalidParameter(
+        "rascaline was compiled without the 'profiling' feature, no profiling data is available".into()
+    ));
+}
+
+/// Run `function` with profiling data collection enabled, restoring the
+/// global setting afterward
+#[cfg(feature = "profiling")]
+pub(crate) fn collect<T>(function: impl FnOnce() -> T) -> T {
+    if is_enabled() {
+        return function();
+    }
+
+    time_graph::enable_data_collection(true);
+    let result = function();
+    time_graph::enable_data_collection(is_enabled());
+
+    return result;
+}
+
+#[cfg(not(feature = "profiling"))]
+pub(crate) fn collect<T>(function: impl FnOnce() -> T) -> T {
+    return function();
+}
+
+#[cfg(all(test, feature = "profiling"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_format() {
+        assert!(report("json").is_ok());
+
+        let error = report("csv").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid parameter: invalid profiling data format: csv, expected 'table', 'short_table' or 'json'"
+        );
+    }
+}
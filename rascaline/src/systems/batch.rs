@@ -0,0 +1,240 @@
+use equistore::TensorMap;
+
+use crate::{Error, Vector3D};
+use crate::{Calculator, CalculationOptions};
+
+use super::{System, SimpleSystem, UnitCell};
+
+/// A batch of systems which can be re-filled in place with new frames, for
+/// example at every step of a molecular dynamics simulation or when reading
+/// a trajectory in chunks.
+///
+/// The batch owns the per-frame buffers, and re-uses them (as well as the
+/// neighbors list when using a Verlet skin) for the next frames. Systems are
+/// never de-allocated when the batch shrinks, so re-filling a batch with the
+/// same number of atoms in each frame does not allocate any memory.
+///
+/// ```
+/// # use rascaline::systems::{SystemsBatch, UnitCell};
+/// # use rascaline::Vector3D;
+/// let mut batch = SystemsBatch::new();
+/// for step in 0..10 {
+///     batch.clear();
+///     let positions = [Vector3D::new(0.0, 0.0, 0.0), Vector3D::new(0.0, 0.0, 1.0 + 0.01 * step as f64)];
+///     batch.push_frame(UnitCell::infinite(), &[1, 1], &positions).unwrap();
+///     // call batch.compute(&mut calculator, Default::default())
+/// }
+/// assert_eq!(batch.len(), 1);
+/// ```
+pub struct SystemsBatch {
+    /// All the systems in this batch, including the currently unused ones.
+    ///
+    /// All the systems in this vector are `SimpleSystem`, stored as
+    /// `Box<dyn System>` to be directly usable with `Calculator::compute`.
+    /// Systems replaced by the calculator during `compute` are converted
+    /// back to `SimpleSystem` afterwards.
+    systems: Vec<Box<dyn System>>,
+    /// number of systems currently used in this batch
+    len: usize,
+    /// skin distance for the Verlet neighbor list of new systems
+    neighbors_skin: f64,
+}
+
+impl Default for SystemsBatch {
+    fn default() -> SystemsBatch {
+        SystemsBatch::new()
+    }
+}
+
+impl std::fmt::Debug for SystemsBatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SystemsBatch")
+            .field("len", &self.len)
+            .field("capacity", &self.systems.len())
+            .field("neighbors_skin", &self.neighbors_skin)
+            .finish()
+    }
+}
+
+impl SystemsBatch {
+    /// Create a new empty batch
+    pub fn new() -> SystemsBatch {
+        SystemsBatch {
+            systems: Vec::new(),
+            len: 0,
+            neighbors_skin: 0.0,
+        }
+    }
+
+    /// Use a Verlet neighbor list with the given `skin` distance for all the
+    /// systems in this batch, see `SimpleSystem::set_neighbors_skin`.
+    pub fn set_neighbors_skin(&mut self, skin: f64) -> Result<(), Error> {
+        for i in 0..self.systems.len() {
+            self.system_mut(i).set_neighbors_skin(skin)?;
+        }
+        self.neighbors_skin = skin;
+        return Ok(());
+    }
+
+    /// Get the number of systems in this batch
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if this batch is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Remove all systems from this batch, keeping the corresponding memory
+    /// allocated to be re-used by the next frames.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Add a new frame with the given `cell`, `species` and `positions` to
+    /// this batch, re-using the memory of a previous frame if possible.
+    ///
+    /// When the species and cell are the same as the previous frame at the
+    /// same index, the other data set on this system (charges, bonds, …) is
+    /// kept and the Verlet neighbor list candidates can be re-used. Otherwise,
+    /// this data is removed and should be set again with `get_mut`.
+    pub fn push_frame(&mut self, cell: UnitCell, species: &[i32], positions: &[Vector3D]) -> Result<(), Error> {
+        if self.len == self.systems.len() {
+            let mut system = SimpleSystem::new(cell);
+            system.set_neighbors_skin(self.neighbors_skin)?;
+            self.systems.push(Box::new(system));
+        }
+
+        self.system_mut(self.len).refill(cell, species, positions)?;
+        self.len += 1;
+        return Ok(());
+    }
+
+    /// Add a copy of the cell, species and positions of `system` (for
+    /// example a frame read from a trajectory) to this batch. See
+    /// `SystemsBatch::push_frame` for more information.
+    pub fn push_system(&mut self, system: &dyn System) -> Result<(), Error> {
+        return self.push_frame(system.cell()?, system.species()?, system.positions()?);
+    }
+
+    /// Get the system at index `i` in this batch, if any
+    pub fn get(&self, i: usize) -> Option<&dyn System> {
+        if i < self.len {
+            Some(&*self.systems[i])
+        } else {
+            None
+        }
+    }
+
+    /// Get a mutable reference to the system at index `i` in this batch, if
+    /// any. This can be used to set additional per-atom data on the system.
+    pub fn get_mut(&mut self, i: usize) -> Option<&mut SimpleSystem> {
+        if i < self.len {
+            Some(self.system_mut(i))
+        } else {
+            None
+        }
+    }
+
+    /// Run the given `calculator` on all the systems currently in this batch
+    pub fn compute(&mut self, calculator: &mut Calculator, options: CalculationOptions) -> Result<TensorMap, Error> {
+        let descriptor = calculator.compute(&mut self.systems[..self.len], options);
+
+        // the calculator gets mutable access to the boxes, and could replace
+        // some of them with another type of system
+        for system in &mut self.systems[..self.len] {
+            let is_simple = system.as_any_mut().map_or(false, |any| any.is::<SimpleSystem>());
+            if !is_simple {
+                let mut simple = SimpleSystem::try_from(&**system)?;
+                simple.set_neighbors_skin(self.neighbors_skin)?;
+                *system = Box::new(simple);
+            }
+        }
+
+        return descriptor;
+    }
+
+    fn system_mut(&mut self, i: usize) -> &mut SimpleSystem {
+        return self.systems[i].as_any_mut()
+            .and_then(|any| any.downcast_mut::<SimpleSystem>())
+            .expect("all systems in a batch should be SimpleSystem");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::systems::test_utils::test_systems;
+
+    use super::*;
+
+    #[test]
+    fn refill() {
+        let mut batch = SystemsBatch::new();
+        batch.set_neighbors_skin(0.5).unwrap();
+        assert!(batch.is_empty());
+
+        let systems = test_systems(&["water", "methane"]);
+        for system in &systems {
+            batch.push_system(&**system).unwrap();
+        }
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.get(1).unwrap().species().unwrap(), systems[1].species().unwrap());
+
+        batch.get_mut(0).unwrap().set_charges(vec![-0.8, 0.4, 0.4]).unwrap();
+
+        // same species & cell: the additional data is kept
+        batch.clear();
+        let mut positions = systems[0].positions().unwrap().to_vec();
+        positions[0][2] += 0.1;
+        batch.push_frame(systems[0].cell().unwrap(), systems[0].species().unwrap(), &positions).unwrap();
+        assert_eq!(batch.len(), 1);
+        assert!(batch.get(1).is_none());
+
+        let system = batch.get(0).unwrap();
+        assert_eq!(system.positions().unwrap(), positions);
+        assert_eq!(system.charges().unwrap(), Some(&[-0.8, 0.4, 0.4][..]));
+
+        // different species: the additional data is removed
+        batch.clear();
+        batch.push_system(&*systems[1]).unwrap();
+        let system = batch.get_mut(0).unwrap();
+        assert_eq!(system.species().unwrap(), systems[1].species().unwrap());
+        assert_eq!(system.charges().unwrap(), None);
+
+        system.compute_neighbors(3.0).unwrap();
+        let mut reference = SimpleSystem::try_from(&*systems[1]).unwrap();
+        reference.compute_neighbors(3.0).unwrap();
+        assert_eq!(system.pairs().unwrap().len(), reference.pairs().unwrap().len());
+
+        let error = batch.push_frame(UnitCell::infinite(), &[1, 1], &positions).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: expected 2 positions, got 3");
+    }
+
+    #[test]
+    fn compute() {
+        let mut calculator = Calculator::new("dummy_calculator", r#"{
+            "cutoff": 1.0,
+            "delta": 0,
+            "name": ""
+        }"#.to_owned()).unwrap();
+
+        let mut batch = SystemsBatch::new();
+        for system in &test_systems(&["water", "methane"]) {
+            batch.push_system(&**system).unwrap();
+        }
+
+        let descriptor = batch.compute(&mut calculator, Default::default()).unwrap();
+        let samples = descriptor.block_by_id(0).samples();
+        assert!(samples.iter().any(|sample| sample[0].i32() == 1));
+
+        batch.clear();
+        batch.push_system(&*test_systems(&["water"])[0]).unwrap();
+        let descriptor = batch.compute(&mut calculator, Default::default()).unwrap();
+        for (_, block) in descriptor.iter() {
+            for sample in block.samples().iter() {
+                assert_eq!(sample[0].i32(), 0);
+            }
+        }
+    }
+}
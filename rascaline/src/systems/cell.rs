@@ -31,6 +31,9 @@ pub struct UnitCell {
     inverse: Matrix3,
     /// Unit cell shape
     shape: CellShape,
+    /// Are the periodic boundary conditions enabled along each of the cell
+    /// vectors?
+    periodic: [bool; 3],
 }
 
 impl From<Matrix3> for UnitCell {
@@ -58,7 +61,8 @@ impl From<Matrix3> for UnitCell {
             matrix: matrix,
             transpose: matrix.transposed(),
             inverse: matrix.transposed().inverse(),
-            shape: shape
+            shape: shape,
+            periodic: [true; 3],
         }
     }
 }
@@ -71,6 +75,7 @@ impl UnitCell {
             transpose: Matrix3::zero(),
             inverse: Matrix3::zero(),
             shape: CellShape::Infinite,
+            periodic: [false; 3],
         }
     }
 
@@ -87,6 +92,7 @@ impl UnitCell {
             transpose: matrix,
             inverse: matrix.inverse(),
             shape: CellShape::Orthorhombic,
+            periodic: [true; 3],
         }
     }
 
@@ -128,6 +134,30 @@ impl UnitCell {
         self.shape() == CellShape::Infinite
     }
 
+    /// Set which of the cell vectors have periodic boundary conditions, to
+    /// describe wires (periodic along a single vector) or slabs (periodic
+    /// along two vectors). Non-periodic directions are treated as open
+    /// boundaries. The cell vectors are still required along non-periodic
+    /// directions. This has no effect on infinite cells.
+    pub fn with_periodicity(mut self, periodic: [bool; 3]) -> UnitCell {
+        if !self.is_infinite() {
+            self.periodic = periodic;
+        }
+        return self;
+    }
+
+    /// Get which of the cell vectors have periodic boundary conditions. This
+    /// is `[false, false, false]` for infinite cells.
+    pub fn periodic(&self) -> [bool; 3] {
+        self.periodic
+    }
+
+    /// Check if this unit cell has periodic boundary conditions along all
+    /// three cell vectors
+    pub fn is_fully_periodic(&self) -> bool {
+        self.periodic == [true; 3]
+    }
+
     /// Get the first length of the cell (i.e. the norm of the first vector of
     /// the cell)
     pub fn a(&self) -> f64 {
@@ -235,6 +265,72 @@ impl UnitCell {
         // we only have code to multiply a vector by a matrix on the left
         return self.transpose * fractional;
     }
+
+    /// Wrap the `position` inside this unit cell, i.e. get the periodic image
+    /// of the position with fractional coordinates between 0 and 1 along all
+    /// periodic directions. Non-periodic directions and infinite cells are
+    /// left unchanged.
+    pub fn wrap(&self, position: Vector3D) -> Vector3D {
+        if self.is_infinite() {
+            return position;
+        }
+
+        let mut fractional = self.fractional(position);
+        for i in 0..3 {
+            if self.periodic[i] {
+                fractional[i] -= f64::floor(fractional[i]);
+            }
+        }
+        return self.cartesian(fractional);
+    }
+
+    /// Get the shortest periodic image of the `vector`, following the minimum
+    /// image convention along all periodic directions.
+    pub fn vector_image(&self, vector: Vector3D) -> Vector3D {
+        if self.is_infinite() {
+            return vector;
+        }
+
+        let mut fractional = self.fractional(vector);
+        for i in 0..3 {
+            if self.periodic[i] {
+                fractional[i] -= f64::round(fractional[i]);
+            }
+        }
+        let image = self.cartesian(fractional);
+
+        if self.shape != CellShape::Triclinic {
+            return image;
+        }
+
+        // in triclinic cells, rounding the fractional coordinates does not
+        // always give the shortest image, so we also check the neighboring
+        // images
+        let mut best = image;
+        for i in -1..=1 {
+            for j in -1..=1 {
+                for k in -1..=1 {
+                    let shift = [i, j, k];
+                    if (0..3).any(|d| shift[d] != 0 && !self.periodic[d]) {
+                        continue;
+                    }
+
+                    let candidate = image + self.cartesian(Vector3D::new(i as f64, j as f64, k as f64));
+                    if candidate.norm2() < best.norm2() {
+                        best = candidate;
+                    }
+                }
+            }
+        }
+
+        return best;
+    }
+
+    /// Get the distance between the points `first` and `second` in this cell,
+    /// using the minimum image convention.
+    pub fn distance(&self, first: Vector3D, second: Vector3D) -> f64 {
+        return self.vector_image(second - first).norm();
+    }
 }
 
 /// Get the angles between the vectors `u` and `v`.
@@ -372,4 +468,46 @@ mod tests {
             assert_ulps_eq!(test, transformed, epsilon = 1e-15);
         }
     }
+
+    #[test]
+    fn wrap() {
+        let cell = UnitCell::cubic(5.0);
+        assert_relative_eq!(cell.wrap(Vector3D::new(6.0, -1.0, 2.0)), Vector3D::new(1.0, 4.0, 2.0), epsilon = 1e-12);
+
+        let cell = UnitCell::cubic(5.0).with_periodicity([true, false, true]);
+        assert_relative_eq!(cell.wrap(Vector3D::new(6.0, -1.0, 12.0)), Vector3D::new(1.0, -1.0, 2.0), epsilon = 1e-12);
+
+        let cell = UnitCell::infinite();
+        assert_eq!(cell.wrap(Vector3D::new(6.0, -1.0, 12.0)), Vector3D::new(6.0, -1.0, 12.0));
+
+        let cell = UnitCell::triclinic(5.0, 6.0, 3.6, 90.0, 53.0, 77.0);
+        let wrapped = cell.wrap(Vector3D::new(-5.0, 12.0, 4.9));
+        let fractional = cell.fractional(wrapped);
+        for i in 0..3 {
+            assert!(fractional[i] >= 0.0 && fractional[i] < 1.0);
+        }
+    }
+
+    #[test]
+    fn distance() {
+        let cell = UnitCell::cubic(5.0);
+        assert_relative_eq!(cell.vector_image(Vector3D::new(4.0, -3.0, 2.0)), Vector3D::new(-1.0, 2.0, 2.0), epsilon = 1e-12);
+        assert_relative_eq!(cell.distance(Vector3D::new(0.5, 0.0, 0.0), Vector3D::new(4.5, 0.0, 0.0)), 1.0, epsilon = 1e-12);
+
+        let cell = UnitCell::cubic(5.0).with_periodicity([false, true, true]);
+        assert_relative_eq!(cell.distance(Vector3D::new(0.5, 0.0, 0.0), Vector3D::new(4.5, 0.0, 0.0)), 4.0, epsilon = 1e-12);
+
+        let cell = UnitCell::infinite();
+        assert_relative_eq!(cell.distance(Vector3D::new(0.5, 0.0, 0.0), Vector3D::new(4.5, 0.0, 0.0)), 4.0, epsilon = 1e-12);
+
+        // in this strongly tilted cell, rounding the fractional coordinates
+        // does not give the shortest vector
+        let cell = UnitCell::from(Matrix3::new([
+            [1.0, 0.0, 0.0],
+            [0.9, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+        ]));
+        let image = cell.vector_image(Vector3D::new(0.2, 0.7, 0.0));
+        assert_relative_eq!(image, Vector3D::new(0.3, -0.3, 0.0), epsilon = 1e-12);
+    }
 }
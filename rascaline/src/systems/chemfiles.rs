@@ -1,6 +1,6 @@
 use std::path::Path;
 
-use super::SimpleSystem;
+use super::{System, SimpleSystem, SpeciesMap, UnitCell};
 use crate::Error;
 
 #[cfg(feature = "chemfiles")]
@@ -10,34 +10,122 @@ impl From<chemfiles::Error> for Error {
     }
 }
 
-/// Read all structures in the file at the given `path` using
-/// [chemfiles](https://chemfiles.org/), and convert them to `SimpleSystem`s.
+/// Options for [`read_from_file_with_options`], allowing to fix the metadata
+/// of the systems when reading them from files.
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    /// Name of the format of the file (e.g. `"XYZ"`, `"PDB"`, `"POSCAR"`),
+    /// following the naming used by chemfiles. If this is `None` (the
+    /// default), the format is guessed from the file extension.
+    pub format: Option<String>,
+    /// Unit cell to use for all the systems, replacing the one read from the
+    /// file. This is useful for formats which do not store the unit cell.
+    pub cell: Option<UnitCell>,
+    /// Species to use for the atoms, based on their name in the file. Atoms
+    /// with a name in this mapping use the corresponding species, while all
+    /// other atoms use the default species (the atomic number for elements).
+    pub species: Option<SpeciesMap>,
+}
+
+/// Read all structures in the file at the given `path`, and convert them to
+/// `SimpleSystem`s.
+///
+/// With the chemfiles feature, this function can read all [formats supported
+/// by chemfiles](https://chemfiles.org/chemfiles/latest/formats.html). The
+/// bonds defined in the file (for example the `CONECT` records in PDB files)
+/// are available through `System::bonds`.
+///
+/// Without the chemfiles feature, only XYZ and extended XYZ files (with the
+/// `.xyz` or `.extxyz` extension) and VASP files (named `POSCAR*` or
+/// `CONTCAR*`, or with the `.vasp` extension) can be read, using
+/// [`read_xyz`](crate::systems::read_xyz) and
+/// [`read_poscar`](crate::systems::read_poscar) respectively.
 ///
-/// This function can read all [formats supported by
-/// chemfiles](https://chemfiles.org/chemfiles/latest/formats.html).
+/// In both cases, files compressed with gzip (e.g. `structures.xyz.gz`) are
+/// decompressed on the fly, and their format is guessed from the file name
+/// without the `.gz` extension.
+pub fn read_from_file(path: impl AsRef<Path>) -> Result<Vec<SimpleSystem>, Error> {
+    return read_from_file_with_options(path, &ReadOptions::default());
+}
+
+/// Read all structures in the file at the given `path` like
+/// [`read_from_file`], using the given `options` to set the file format and
+/// override the unit cell or species of the systems.
+pub fn read_from_file_with_options(path: impl AsRef<Path>, options: &ReadOptions) -> Result<Vec<SimpleSystem>, Error> {
+    let mut systems = read_systems(path.as_ref(), options.format.as_deref())?;
+
+    for system in &mut systems {
+        if let Some(cell) = options.cell {
+            system.set_cell(cell);
+        }
+
+        if let Some(ref species) = options.species {
+            override_species(system, species)?;
+        }
+    }
+
+    return Ok(systems);
+}
+
+/// Change the species of the atoms in `system` according to their name in
+/// the species map of the system and the `overrides`
+fn override_species(system: &mut SimpleSystem, overrides: &SpeciesMap) -> Result<(), Error> {
+    let previous = match system.species_map()? {
+        Some(map) => map.clone(),
+        None => return Ok(()),
+    };
+
+    let new_species = |species: i32| {
+        previous.symbol(species)
+            .and_then(|symbol| overrides.species(symbol))
+            .unwrap_or(species)
+    };
+
+    let mut species_map = SpeciesMap::new();
+    for (species, symbol) in previous.iter() {
+        species_map.insert(new_species(species), symbol)?;
+    }
+
+    for species in system.species_mut() {
+        *species = new_species(*species);
+    }
+    system.set_species_map(species_map);
+
+    return Ok(());
+}
+
 #[cfg(feature = "chemfiles")]
 #[allow(clippy::needless_range_loop)]
-pub fn read_from_file(path: impl AsRef<Path>) -> Result<Vec<SimpleSystem>, Error> {
+fn read_systems(path: &Path, format: Option<&str>) -> Result<Vec<SimpleSystem>, Error> {
     use std::collections::HashMap;
     use crate::Matrix3;
-    use crate::systems::UnitCell;
+    use super::elements::element_symbol;
 
     let mut systems = Vec::new();
 
-    let mut trajectory = chemfiles::Trajectory::open(path, 'r')?;
+    let mut trajectory = match format {
+        Some(format) => chemfiles::Trajectory::open_with_format(path, 'r', format)?,
+        None => chemfiles::Trajectory::open(path, 'r')?,
+    };
     let mut frame = chemfiles::Frame::new();
-
     let mut assigned_species = HashMap::new();
-    let mut get_species = |atom: chemfiles::AtomRef| {
+    let mut species_map = SpeciesMap::new();
+    let mut get_species = |atom: chemfiles::AtomRef, species_map: &mut SpeciesMap| -> Result<i32, Error> {
         let atomic_number = atom.atomic_number();
+        let atomic_type = atom.atomic_type();
         if atomic_number == 0 {
             // use number assigned from the the atomic type, starting at 120
             // since that's larger than the number of elements in the periodic
             // table
             let new_species = 120 + assigned_species.len() as i32;
-            *assigned_species.entry(atom.atomic_type()).or_insert(new_species)
+            let species = *assigned_species.entry(atomic_type.clone()).or_insert(new_species);
+            species_map.insert(species, &atomic_type)?;
+            Ok(species)
         } else {
-            atomic_number as i32
+            let species = atomic_number as i32;
+            let symbol = element_symbol(species).unwrap_or(atomic_type.as_str());
+            species_map.insert(species, symbol)?;
+            Ok(species)
         }
     };
 
@@ -54,30 +142,69 @@ pub fn read_from_file(path: impl AsRef<Path>) -> Result<Vec<SimpleSystem>, Error
             UnitCell::from(Matrix3::from(frame.cell().matrix()).transposed())
         };
         let mut system = SimpleSystem::new(cell);
+        let mut masses = Vec::with_capacity(frame.size());
         for i in 0..frame.size() {
             let atom = frame.atom(i);
-            system.add_atom(get_species(atom), positions[i].into());
+            masses.push(atom.mass());
+            system.add_atom(get_species(atom, &mut species_map)?, positions[i].into());
+        }
+
+        // atoms with unknown types have a mass of 0 in chemfiles
+        if masses.iter().all(|&mass| mass > 0.0) {
+            system.set_masses(masses)?;
+        }
+
+        if let Some(velocities) = frame.velocities() {
+            system.set_velocities(velocities.iter().map(|&v| v.into()).collect())?;
+        }
+
+        for [i, j] in frame.topology().bonds() {
+            system.add_bond(i, j)?;
         }
 
+        system.set_species_map(species_map.clone());
         systems.push(system);
     }
 
     return Ok(systems);
 }
 
-/// Read all structures in the file at the given `path` using
-/// [chemfiles](https://chemfiles.org/), and convert them to `SimpleSystem`s.
-///
-/// This function can read all [formats supported by
-/// chemfiles](https://chemfiles.org/chemfiles/latest/formats.html).
 #[cfg(not(feature = "chemfiles"))]
-pub fn read_from_file(_: impl AsRef<Path>) -> Result<Vec<SimpleSystem>, Error> {
+fn read_systems(path: &Path, format: Option<&str>) -> Result<Vec<SimpleSystem>, Error> {
+    // guess the format of compressed files from the name without `.gz`, the
+    // decompression itself happens when reading the file
+    let mut uncompressed = path;
+    if path.extension().map_or(false, |e| e.eq_ignore_ascii_case("gz")) {
+        if let Some(stem) = path.file_stem() {
+            uncompressed = Path::new(stem);
+        }
+    }
+
+    let extension = uncompressed.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let name = uncompressed.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
+    let is_xyz = match format {
+        Some(format) => format.eq_ignore_ascii_case("xyz") || format.eq_ignore_ascii_case("extxyz"),
+        None => extension.eq_ignore_ascii_case("xyz") || extension.eq_ignore_ascii_case("extxyz"),
+    };
+    if is_xyz {
+        return super::read_xyz(path);
+    }
+
+    let is_poscar = match format {
+        Some(format) => format.eq_ignore_ascii_case("poscar"),
+        None => name.starts_with("POSCAR") || name.starts_with("CONTCAR") || extension.eq_ignore_ascii_case("vasp"),
+    };
+    if is_poscar {
+        return super::read_poscar(path).map(|system| vec![system]);
+    }
+
     Err(Error::Chemfiles(
-        "read_from_file is only available with the chemfiles feature enabled".into()
+        "read_from_file is only available for XYZ and VASP files without the chemfiles feature".into()
     ))
 }
 
-#[cfg(all(test, feature = "chemfiles"))]
+#[cfg(test)]
 mod tests {
     use std::path::PathBuf;
     use approx::assert_relative_eq;
@@ -86,6 +213,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[cfg(feature = "chemfiles")]
     fn read() -> Result<(), Box<dyn std::error::Error>> {
         let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         path.push("benches");
@@ -103,6 +231,15 @@ mod tests {
             Vector3D::from([7.8554, 7.84887, 0.0188612])
         );
 
+        let masses = systems[0].masses()?.expect("missing masses");
+        assert_eq!(masses.len(), 54);
+        assert_relative_eq!(masses[0], 28.0855, epsilon=1e-3);
+        assert!(systems[0].velocities()?.is_none());
+        assert!(systems[0].bonds()?.is_empty());
+
+        let species_map = systems[0].species_map()?.expect("missing species map");
+        assert_eq!(species_map.symbol(14), Some("Si"));
+
         let cell = systems[0].cell()?;
         assert_relative_eq!(cell.a(), 11.098535905469692);
         assert_relative_eq!(cell.b(), 11.098535905469692);
@@ -119,4 +256,34 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn read_with_options() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("benches");
+        path.push("data");
+        path.push("silicon_bulk.xyz");
+
+        let mut species = SpeciesMap::new();
+        species.insert(3, "Si").unwrap();
+        let options = ReadOptions {
+            format: Some("XYZ".into()),
+            cell: Some(UnitCell::cubic(20.0)),
+            species: Some(species),
+        };
+
+        let systems = read_from_file_with_options(&path, &options).unwrap();
+        assert_eq!(systems.len(), 30);
+        assert_eq!(systems[0].species().unwrap(), [3; 54].as_ref());
+        assert_eq!(systems[0].cell().unwrap(), UnitCell::cubic(20.0));
+
+        let species_map = systems[0].species_map().unwrap().expect("missing species map");
+        assert_eq!(species_map.symbol(3), Some("Si"));
+        assert_eq!(species_map.symbol(14), None);
+
+        assert_relative_eq!(
+            systems[0].positions().unwrap()[0],
+            Vector3D::from([7.8554, 7.84887, 0.0188612])
+        );
+    }
 }
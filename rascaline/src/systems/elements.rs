@@ -0,0 +1,99 @@
+use super::SpeciesMap;
+
+/// Symbols of all the elements in the periodic table, ordered by atomic number
+const ELEMENTS: [&str; 118] = [
+    "H", "He", "Li", "Be", "B", "C", "N", "O", "F", "Ne", "Na", "Mg", "Al", "Si",
+    "P", "S", "Cl", "Ar", "K", "Ca", "Sc", "Ti", "V", "Cr", "Mn", "Fe", "Co",
+    "Ni", "Cu", "Zn", "Ga", "Ge", "As", "Se", "Br", "Kr", "Rb", "Sr", "Y", "Zr",
+    "Nb", "Mo", "Tc", "Ru", "Rh", "Pd", "Ag", "Cd", "In", "Sn", "Sb", "Te", "I",
+    "Xe", "Cs", "Ba", "La", "Ce", "Pr", "Nd", "Pm", "Sm", "Eu", "Gd", "Tb", "Dy",
+    "Ho", "Er", "Tm", "Yb", "Lu", "Hf", "Ta", "W", "Re", "Os", "Ir", "Pt", "Au",
+    "Hg", "Tl", "Pb", "Bi", "Po", "At", "Rn", "Fr", "Ra", "Ac", "Th", "Pa", "U",
+    "Np", "Pu", "Am", "Cm", "Bk", "Cf", "Es", "Fm", "Md", "No", "Lr", "Rf", "Db",
+    "Sg", "Bh", "Hs", "Mt", "Ds", "Rg", "Cn", "Nh", "Fl", "Mc", "Lv", "Ts", "Og",
+];
+
+/// Get the atomic number of the element with the given `symbol`, or `None` if
+/// the symbol does not correspond to any element. The comparison is case
+/// insensitive.
+pub(crate) fn atomic_number(symbol: &str) -> Option<i32> {
+    ELEMENTS.iter()
+        .position(|element| element.eq_ignore_ascii_case(symbol))
+        .map(|index| index as i32 + 1)
+}
+
+/// Get the symbol of the element with the given atomic `number`, or `None` if
+/// there is no such element.
+pub(crate) fn element_symbol(number: i32) -> Option<&'static str> {
+    if number < 1 {
+        return None;
+    }
+    ELEMENTS.get(number as usize - 1).copied()
+}
+
+/// Assign species to atomic types, using the atomic number for types
+/// corresponding to actual elements, and new numbers starting at 120 (i.e.
+/// larger than the number of elements in the periodic table) for all other
+/// types. All the assigned species are recorded in a `SpeciesMap`.
+#[derive(Debug, Default)]
+pub(crate) struct SpeciesAssigner {
+    assigned: Vec<String>,
+    map: SpeciesMap,
+}
+
+impl SpeciesAssigner {
+    pub fn get(&mut self, atomic_type: &str) -> i32 {
+        if let Some(number) = atomic_number(atomic_type) {
+            let symbol = element_symbol(number).expect("invalid atomic number");
+            self.map.insert(number, symbol).expect("inconsistent species map");
+            return number;
+        }
+
+        let index = match self.assigned.iter().position(|t| t == atomic_type) {
+            Some(index) => index,
+            None => {
+                self.assigned.push(atomic_type.into());
+                self.assigned.len() - 1
+            }
+        };
+
+        let species = 120 + index as i32;
+        self.map.insert(species, atomic_type).expect("inconsistent species map");
+        return species;
+    }
+
+    /// Get the mapping between all the species assigned so far and the
+    /// corresponding atomic types
+    pub fn map(&self) -> &SpeciesMap {
+        &self.map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn species() {
+        assert_eq!(atomic_number("H"), Some(1));
+        assert_eq!(atomic_number("si"), Some(14));
+        assert_eq!(atomic_number("Og"), Some(118));
+        assert_eq!(atomic_number("Xx"), None);
+
+        let mut assigner = SpeciesAssigner::default();
+        assert_eq!(assigner.get("O"), 8);
+        assert_eq!(assigner.get("CH3"), 120);
+        assert_eq!(assigner.get("Wat"), 121);
+        assert_eq!(assigner.get("CH3"), 120);
+        assert_eq!(assigner.get("si"), 14);
+
+        assert_eq!(element_symbol(14), Some("Si"));
+        assert_eq!(element_symbol(0), None);
+        assert_eq!(element_symbol(119), None);
+
+        let map = assigner.map();
+        assert_eq!(map.symbol(14), Some("Si"));
+        assert_eq!(map.symbol(120), Some("CH3"));
+        assert_eq!(map.species("Wat"), Some(121));
+    }
+}
@@ -0,0 +1,60 @@
+use std::io::Read;
+use std::path::Path;
+
+use crate::Error;
+
+/// Magic bytes at the start of gzip-compressed files
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Read the whole content of the file at `path` as a string. Files compressed
+/// with gzip (for example `structures.xyz.gz`) are decompressed on the fly.
+pub(crate) fn read_to_string(path: &Path) -> Result<String, Error> {
+    let io_error = |e: std::io::Error| Error::InvalidParameter(format!(
+        "failed to read '{}': {}", path.display(), e
+    ));
+
+    let bytes = std::fs::read(path).map_err(io_error)?;
+    if bytes.starts_with(&GZIP_MAGIC) {
+        let mut content = String::new();
+        // use a multi-member decoder, to support files created by
+        // concatenating multiple gzip files
+        flate2::read::MultiGzDecoder::new(&*bytes).read_to_string(&mut content).map_err(io_error)?;
+        return Ok(content);
+    }
+
+    return String::from_utf8(bytes).map_err(|e| io_error(
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use flate2::write::GzEncoder;
+
+    use super::*;
+
+    #[test]
+    fn gzip() {
+        let content = "2\n\nH 0 0 0\nH 0 0 1\n";
+
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(content.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut path = std::env::temp_dir();
+        path.push(format!("rascaline-test-{}.xyz.gz", std::process::id()));
+        std::fs::write(&path, compressed).unwrap();
+        let decompressed = read_to_string(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(decompressed.unwrap(), content);
+
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+        assert!(read_to_string(&path).unwrap().contains("[package]"));
+
+        let error = read_to_string(Path::new("not-there.xyz")).unwrap_err();
+        assert!(error.to_string().starts_with("invalid parameter: failed to read 'not-there.xyz'"));
+    }
+}
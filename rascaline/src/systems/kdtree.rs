@@ -0,0 +1,211 @@
+use std::cmp::Ordering;
+
+use rayon::prelude::*;
+
+use crate::Vector3D;
+use super::neighbors::{CellPair, CellShift};
+
+/// Maximal number of atoms in the leaves of the tree
+const LEAF_SIZE: usize = 16;
+
+/// A node in the kd-tree, containing all the atoms in
+/// `KdTree::indexes[start..end]`
+#[derive(Debug, Clone)]
+struct Node {
+    start: usize,
+    end: usize,
+    /// lower corner of the bounding box of the atoms in this node
+    min: Vector3D,
+    /// upper corner of the bounding box of the atoms in this node
+    max: Vector3D,
+    /// index of the two children of this node, or `None` for leaves
+    children: Option<(usize, usize)>,
+}
+
+/// A kd-tree used to search for neighbors in systems without periodic
+/// boundary conditions.
+///
+/// Contrary to the cell list, the tree adapts to the distribution of the atoms,
+/// which makes it a lot more efficient for large and sparse systems such as
+/// isolated clusters or biomolecules in vacuum.
+#[derive(Debug, Clone)]
+pub struct KdTree<'a> {
+    positions: &'a [Vector3D],
+    /// indexes of the atoms, ordered such that each node contains a
+    /// contiguous range of this array
+    indexes: Vec<usize>,
+    /// all the nodes in the tree, the first one being the root
+    nodes: Vec<Node>,
+}
+
+impl<'a> KdTree<'a> {
+    /// Build a new kd-tree containing the atoms at the given `positions`
+    pub fn new(positions: &'a [Vector3D]) -> KdTree<'a> {
+        let mut tree = KdTree {
+            positions: positions,
+            indexes: (0..positions.len()).collect(),
+            nodes: Vec::new(),
+        };
+
+        if !positions.is_empty() {
+            tree.build(0, positions.len());
+        }
+
+        return tree;
+    }
+
+    /// Recursively build the node containing `self.indexes[start..end]`,
+    /// returning the index of this node
+    fn build(&mut self, start: usize, end: usize) -> usize {
+        let positions = self.positions;
+
+        let mut min = positions[self.indexes[start]];
+        let mut max = min;
+        for &atom in &self.indexes[start..end] {
+            for spatial in 0..3 {
+                min[spatial] = f64::min(min[spatial], positions[atom][spatial]);
+                max[spatial] = f64::max(max[spatial], positions[atom][spatial]);
+            }
+        }
+
+        let node_i = self.nodes.len();
+        self.nodes.push(Node {
+            start: start,
+            end: end,
+            min: min,
+            max: max,
+            children: None,
+        });
+
+        if end - start > LEAF_SIZE {
+            // split the atoms in two halves along the largest dimension of the
+            // bounding box
+            let extent = max - min;
+            let mut axis = 0;
+            for spatial in 1..3 {
+                if extent[spatial] > extent[axis] {
+                    axis = spatial;
+                }
+            }
+
+            let middle = start + (end - start) / 2;
+            self.indexes[start..end].select_nth_unstable_by(middle - start, |&i, &j| {
+                positions[i][axis].partial_cmp(&positions[j][axis]).unwrap_or(Ordering::Equal)
+            });
+
+            let left = self.build(start, middle);
+            let right = self.build(middle, end);
+            self.nodes[node_i].children = Some((left, right));
+        }
+
+        return node_i;
+    }
+
+    /// Get all the pairs of atoms separated by less than `cutoff`. This
+    /// produces a "half" neighbors list, where each pair is only included
+    /// once, with the first atom having a smaller index than the second one.
+    ///
+    /// The search for the neighbors of the different atoms runs in parallel.
+    pub fn pairs(&self, cutoff: f64) -> Vec<CellPair> {
+        if self.nodes.is_empty() {
+            return Vec::new();
+        }
+
+        let cutoff2 = cutoff * cutoff;
+        return (0..self.positions.len()).into_par_iter().flat_map_iter(|atom| {
+            let mut pairs = Vec::new();
+            self.search(0, atom, cutoff2, &mut pairs);
+            pairs
+        }).collect();
+    }
+
+    /// Add all the pairs between `atom` and atoms with a larger index in the
+    /// node at `node_i` to `pairs`
+    fn search(&self, node_i: usize, atom: usize, cutoff2: f64, pairs: &mut Vec<CellPair>) {
+        let node = &self.nodes[node_i];
+        let position = self.positions[atom];
+
+        // skip this node if its bounding box is too far away
+        let mut distance2 = 0.0;
+        for spatial in 0..3 {
+            let delta = if position[spatial] < node.min[spatial] {
+                node.min[spatial] - position[spatial]
+            } else if position[spatial] > node.max[spatial] {
+                position[spatial] - node.max[spatial]
+            } else {
+                0.0
+            };
+            distance2 += delta * delta;
+        }
+
+        if distance2 >= cutoff2 {
+            return;
+        }
+
+        if let Some((left, right)) = node.children {
+            self.search(left, atom, cutoff2, pairs);
+            self.search(right, atom, cutoff2, pairs);
+        } else {
+            for &neighbor in &self.indexes[node.start..node.end] {
+                if neighbor <= atom {
+                    continue;
+                }
+
+                let vector = self.positions[neighbor] - position;
+                if vector * vector < cutoff2 {
+                    pairs.push(CellPair {
+                        first: atom,
+                        second: neighbor,
+                        shift: CellShift::default(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairs() {
+        // pseudo-random positions, with a dense cluster and a few isolated
+        // atoms far away
+        let mut positions = (0..500).map(|i| {
+            let i = i as f64;
+            Vector3D::new(
+                5.0 * f64::sin(1.3 * i),
+                5.0 * f64::sin(2.1 * i + 0.3),
+                5.0 * f64::sin(0.7 * i + 1.1),
+            )
+        }).collect::<Vec<_>>();
+        positions.push(Vector3D::new(1000.0, 0.0, 0.0));
+        positions.push(Vector3D::new(1001.5, 0.0, 0.0));
+        positions.push(Vector3D::new(-1000.0, 0.0, 300.0));
+
+        let cutoff = 1.6;
+        let mut expected = Vec::new();
+        for i in 0..positions.len() {
+            for j in (i + 1)..positions.len() {
+                if (positions[j] - positions[i]).norm() < cutoff {
+                    expected.push((i, j));
+                }
+            }
+        }
+        assert!(expected.len() > 100);
+
+        let mut actual = KdTree::new(&positions).pairs(cutoff).iter()
+            .map(|pair| {
+                assert_eq!(pair.shift, CellShift::default());
+                (pair.first, pair.second)
+            })
+            .collect::<Vec<_>>();
+        actual.sort_unstable();
+
+        assert_eq!(actual, expected);
+        assert!(actual.contains(&(500, 501)));
+
+        assert!(KdTree::new(&[]).pairs(cutoff).is_empty());
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::{Error, Matrix3, Vector3D};
+
+use super::{SimpleSystem, UnitCell};
+use super::files::read_to_string;
+
+/// Read all frames in the LAMMPS text dump file at the given `path`, and
+/// convert them to `SimpleSystem`s.
+///
+/// LAMMPS only knows about numeric atom types, so the caller must provide the
+/// `species` to use for each atom type. The file must contain the `type`
+/// column and either unscaled (`x y z` or `xu yu zu`) or scaled (`xs ys zs` or
+/// `xsu ysu zsu`) coordinates. Velocities (`vx vy vz`), charges (`q`) and
+/// masses (`mass`) are also read if present. Atoms are sorted by their `id` if
+/// this column is present, to get a consistent order between frames.
+pub fn read_lammps_dump(path: impl AsRef<Path>, species: &BTreeMap<i32, i32>) -> Result<Vec<SimpleSystem>, Error> {
+    let content = read_to_string(path.as_ref())?;
+
+    return parse_lammps_dump(&content, species);
+}
+
+/// Parse all frames in the given LAMMPS text dump `content`, see
+/// [`read_lammps_dump`] for more information.
+pub fn parse_lammps_dump(content: &str, species: &BTreeMap<i32, i32>) -> Result<Vec<SimpleSystem>, Error> {
+    let mut systems = Vec::new();
+
+    let mut lines = content.lines().enumerate().peekable();
+    let mut n_atoms = None;
+    let mut bounds = None;
+
+    while let Some((line_i, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let item = line.strip_prefix("ITEM:").ok_or_else(|| dump_error(
+            line_i, format!("expected an ITEM line, got '{}'", line)
+        ))?.trim();
+
+        if item.starts_with("NUMBER OF ATOMS") {
+            let (value_i, value) = next_line(&mut lines, line_i)?;
+            n_atoms = Some(value.trim().parse::<usize>().map_err(|_| dump_error(
+                value_i, format!("invalid number of atoms '{}'", value.trim())
+            ))?);
+        } else if let Some(flags) = item.strip_prefix("BOX BOUNDS") {
+            let mut rows = Vec::new();
+            for _ in 0..3 {
+                let (value_i, value) = next_line(&mut lines, line_i)?;
+                let row = value.split_whitespace()
+                    .map(|v| v.parse::<f64>().map_err(|_| dump_error(value_i, format!("invalid number '{}' in box bounds", v))))
+                    .collect::<Result<Vec<_>, _>>()?;
+                rows.push(row);
+            }
+            bounds = Some(BoxBounds::new(flags, &rows).map_err(|e| dump_error(line_i, e))?);
+        } else if let Some(columns) = item.strip_prefix("ATOMS") {
+            let n_atoms = n_atoms.ok_or_else(|| dump_error(line_i, "missing NUMBER OF ATOMS before ATOMS"))?;
+            let bounds = bounds.as_ref().ok_or_else(|| dump_error(line_i, "missing BOX BOUNDS before ATOMS"))?;
+
+            let columns = AtomColumns::new(columns).map_err(|e| dump_error(line_i, e))?;
+            let mut atoms = Vec::with_capacity(n_atoms);
+            for _ in 0..n_atoms {
+                let (atom_i, atom_line) = next_line(&mut lines, line_i)?;
+                atoms.push(columns.parse(atom_line, bounds, species).map_err(|e| dump_error(atom_i, e))?);
+            }
+            atoms.sort_by_key(|atom| atom.id);
+
+            systems.push(bounds.system(&atoms)?);
+        } else {
+            // skip the content of unknown items (TIMESTEP, UNITS, TIME, ...)
+            while let Some((_, next)) = lines.peek() {
+                if next.trim_start().starts_with("ITEM:") {
+                    break;
+                }
+                lines.next();
+            }
+        }
+    }
+
+    return Ok(systems);
+}
+
+fn dump_error(line_i: usize, message: impl Into<String>) -> Error {
+    Error::InvalidParameter(format!(
+        "invalid LAMMPS dump file at line {}: {}", line_i + 1, message.into()
+    ))
+}
+
+fn next_line<'a>(lines: &mut impl Iterator<Item=(usize, &'a str)>, item_i: usize) -> Result<(usize, &'a str), Error> {
+    lines.next().ok_or_else(|| dump_error(item_i, "unexpected end of file"))
+}
+
+/// Simulation box of a single frame
+struct BoxBounds {
+    origin: Vector3D,
+    matrix: Matrix3,
+    periodic: [bool; 3],
+}
+
+impl BoxBounds {
+    fn new(flags: &str, rows: &[Vec<f64>]) -> Result<BoxBounds, String> {
+        let flags = flags.split_whitespace().collect::<Vec<_>>();
+        let triclinic = flags.len() >= 3 && flags[..3] == ["xy", "xz", "yz"];
+        let boundaries = if triclinic { &flags[3..] } else { &flags[..] };
+
+        let expected = if triclinic { 3 } else { 2 };
+        if rows.iter().any(|row| row.len() != expected) {
+            return Err(format!("expected {} values on each line of BOX BOUNDS", expected));
+        }
+
+        // non periodic boundaries are fixed (f), shrink-wrapped (s), or
+        // shrink-wrapped with a minimum (m)
+        let mut periodic = [true; 3];
+        if !boundaries.is_empty() {
+            if boundaries.len() != 3 {
+                return Err(format!("expected 3 boundary flags in BOX BOUNDS, got {}", boundaries.len()));
+            }
+
+            for (periodic, flag) in periodic.iter_mut().zip(boundaries) {
+                *periodic = *flag == "pp";
+            }
+        }
+
+        let (xy, xz, yz) = if triclinic {
+            (rows[0][2], rows[1][2], rows[2][2])
+        } else {
+            (0.0, 0.0, 0.0)
+        };
+
+        // the file contains the bounding box of the (possibly tilted) cell
+        let xlo = rows[0][0] - f64::min(f64::min(0.0, xy), f64::min(xz, xy + xz));
+        let xhi = rows[0][1] - f64::max(f64::max(0.0, xy), f64::max(xz, xy + xz));
+        let ylo = rows[1][0] - f64::min(0.0, yz);
+        let yhi = rows[1][1] - f64::max(0.0, yz);
+        let zlo = rows[2][0];
+        let zhi = rows[2][1];
+
+        return Ok(BoxBounds {
+            origin: Vector3D::new(xlo, ylo, zlo),
+            matrix: Matrix3::new([
+                [xhi - xlo, 0.0, 0.0],
+                [xy, yhi - ylo, 0.0],
+                [xz, yz, zhi - zlo],
+            ]),
+            periodic: periodic,
+        });
+    }
+
+    fn system(&self, atoms: &[Atom]) -> Result<SimpleSystem, Error> {
+        let cell = if self.periodic == [false; 3] {
+            UnitCell::infinite()
+        } else {
+            if self.matrix.determinant() <= 1e-6 {
+                return Err(Error::InvalidParameter(
+                    "invalid LAMMPS dump file: the simulation box is degenerate".into()
+                ));
+            }
+            UnitCell::from(self.matrix).with_periodicity(self.periodic)
+        };
+
+        let mut system = SimpleSystem::new(cell);
+        for atom in atoms {
+            system.add_atom(atom.species, atom.position);
+        }
+
+        if !atoms.is_empty() && atoms.iter().all(|atom| atom.velocity.is_some()) {
+            system.set_velocities(atoms.iter().map(|atom| atom.velocity.expect("missing velocity")).collect())?;
+        }
+
+        if !atoms.is_empty() && atoms.iter().all(|atom| atom.charge.is_some()) {
+            system.set_charges(atoms.iter().map(|atom| atom.charge.expect("missing charge")).collect())?;
+        }
+
+        if !atoms.is_empty() && atoms.iter().all(|atom| atom.mass.is_some()) {
+            system.set_masses(atoms.iter().map(|atom| atom.mass.expect("missing mass")).collect())?;
+        }
+
+        return Ok(system);
+    }
+}
+
+/// Data for a single atom in a frame
+struct Atom {
+    id: usize,
+    species: i32,
+    position: Vector3D,
+    velocity: Option<Vector3D>,
+    charge: Option<f64>,
+    mass: Option<f64>,
+}
+
+/// Indexes of the columns we know how to use in the ATOMS section
+struct AtomColumns {
+    count: usize,
+    id: Option<usize>,
+    atom_type: usize,
+    positions: [usize; 3],
+    scaled: bool,
+    velocities: Option<[usize; 3]>,
+    charge: Option<usize>,
+    mass: Option<usize>,
+}
+
+impl AtomColumns {
+    fn new(columns: &str) -> Result<AtomColumns, String> {
+        let columns = columns.split_whitespace().collect::<Vec<_>>();
+        let find = |name: &str| columns.iter().position(|&c| c == name);
+        let find_all = |names: [&str; 3]| -> Option<[usize; 3]> {
+            Some([find(names[0])?, find(names[1])?, find(names[2])?])
+        };
+
+        let atom_type = find("type").ok_or_else(|| "missing 'type' column in ATOMS".to_string())?;
+
+        let (positions, scaled) = if let Some(positions) = find_all(["x", "y", "z"]) {
+            (positions, false)
+        } else if let Some(positions) = find_all(["xu", "yu", "zu"]) {
+            (positions, false)
+        } else if let Some(positions) = find_all(["xs", "ys", "zs"]) {
+            (positions, true)
+        } else if let Some(positions) = find_all(["xsu", "ysu", "zsu"]) {
+            (positions, true)
+        } else {
+            return Err("missing positions columns in ATOMS".into());
+        };
+
+        return Ok(AtomColumns {
+            count: columns.len(),
+            id: find("id"),
+            atom_type: atom_type,
+            positions: positions,
+            scaled: scaled,
+            velocities: find_all(["vx", "vy", "vz"]),
+            charge: find("q"),
+            mass: find("mass"),
+        });
+    }
+
+    fn parse(&self, line: &str, bounds: &BoxBounds, species: &BTreeMap<i32, i32>) -> Result<Atom, String> {
+        let values = line.split_whitespace().collect::<Vec<_>>();
+        if values.len() != self.count {
+            return Err(format!("expected {} values for this atom, got {}", self.count, values.len()));
+        }
+
+        let real = |i: usize| values[i].parse::<f64>().map_err(|_| format!("invalid number '{}'", values[i]));
+        let vector = |indexes: [usize; 3]| -> Result<Vector3D, String> {
+            Ok(Vector3D::new(real(indexes[0])?, real(indexes[1])?, real(indexes[2])?))
+        };
+
+        let atom_type = values[self.atom_type].parse::<i32>().map_err(|_| format!(
+            "invalid atom type '{}'", values[self.atom_type]
+        ))?;
+        let atom_species = *species.get(&atom_type).ok_or_else(|| format!(
+            "no species given for atom type {}", atom_type
+        ))?;
+
+        let mut position = vector(self.positions)?;
+        if self.scaled {
+            position = bounds.origin + bounds.matrix.transposed() * position;
+        }
+
+        let id = match self.id {
+            Some(id) => values[id].parse::<usize>().map_err(|_| format!("invalid atom id '{}'", values[id]))?,
+            None => 0,
+        };
+
+        return Ok(Atom {
+            id: id,
+            species: atom_species,
+            position: position,
+            velocity: self.velocities.map(vector).transpose()?,
+            charge: self.charge.map(real).transpose()?,
+            mass: self.mass.map(real).transpose()?,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use crate::System;
+    use super::*;
+
+    const DUMP: &str = "ITEM: TIMESTEP
+0
+ITEM: NUMBER OF ATOMS
+2
+ITEM: BOX BOUNDS pp pp ff
+0.0 10.0
+0.0 10.0
+0.0 12.0
+ITEM: ATOMS id type x y z vx vy vz
+2 2 1.0 0.0 0.0 0.1 0.0 0.0
+1 1 0.0 0.0 0.0 0.0 0.0 0.0
+ITEM: TIMESTEP
+10
+ITEM: NUMBER OF ATOMS
+2
+ITEM: BOX BOUNDS xy xz yz pp pp pp
+0.0 12.0 2.0
+0.0 10.0 0.0
+0.0 10.0 0.0
+ITEM: ATOMS id type xs ys zs
+1 1 0.5 0.5 0.5
+2 1 0.0 0.5 0.0
+";
+
+    #[test]
+    fn read_dump() {
+        let species = BTreeMap::from([(1, 8), (2, 1)]);
+        let systems = parse_lammps_dump(DUMP, &species).unwrap();
+        assert_eq!(systems.len(), 2);
+
+        // atoms are sorted by id
+        let system = &systems[0];
+        assert_eq!(system.species().unwrap(), [8, 1].as_ref());
+        assert_eq!(system.positions().unwrap()[1], Vector3D::new(1.0, 0.0, 0.0));
+        assert_eq!(system.velocities().unwrap().unwrap()[1], Vector3D::new(0.1, 0.0, 0.0));
+
+        let cell = system.cell().unwrap();
+        assert_eq!(cell.periodic(), [true, true, false]);
+        assert_eq!(cell.matrix()[2], [0.0, 0.0, 12.0]);
+
+        // triclinic box with scaled coordinates
+        let system = &systems[1];
+        let matrix = system.cell().unwrap().matrix();
+        assert_eq!(matrix[0], [10.0, 0.0, 0.0]);
+        assert_eq!(matrix[1], [2.0, 10.0, 0.0]);
+        assert_eq!(matrix[2], [0.0, 0.0, 10.0]);
+
+        let positions = system.positions().unwrap();
+        assert_relative_eq!(positions[0], Vector3D::new(6.0, 5.0, 5.0));
+        assert_relative_eq!(positions[1], Vector3D::new(1.0, 5.0, 0.0));
+    }
+
+    #[test]
+    fn errors() {
+        let species = BTreeMap::from([(1, 8)]);
+        let error = parse_lammps_dump(DUMP, &species).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: invalid LAMMPS dump file at line 10: no species given for atom type 2");
+
+        let error = parse_lammps_dump("ITEM: NUMBER OF ATOMS\n1\nITEM: ATOMS id type x y z\n", &species).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: invalid LAMMPS dump file at line 3: missing BOX BOUNDS before ATOMS");
+
+        let error = parse_lammps_dump("1\n", &species).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: invalid LAMMPS dump file at line 1: expected an ITEM line, got '1'");
+    }
+}
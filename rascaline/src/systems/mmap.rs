@@ -0,0 +1,373 @@
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use memmap2::Mmap;
+
+use crate::{Error, Matrix3, Vector3D};
+
+use super::{System, Pair, UnitCell};
+use super::neighbors::{NeighborsList, NeighborsCache};
+
+/// Magic bytes at the start of all memory-mapped system files
+const MAGIC: &[u8; 8] = b"RASCMMAP";
+/// Version of the file format
+const VERSION: u64 = 1;
+
+/// Size of the data for the cell of each system: 9 `f64` for the matrix, then
+/// one byte marking infinite cells, three bytes for the periodicity and 4
+/// bytes of padding.
+const CELL_SIZE: usize = 9 * 8 + 8;
+
+/// Size of the data for a system with `n_atoms` atoms, padded to a multiple
+/// of 8 bytes to keep all positions aligned. This returns `None` if the size
+/// does not fit in an `usize`.
+fn system_size(n_atoms: usize) -> Option<usize> {
+    let size = n_atoms.checked_mul(3 * 8 + 4)?.checked_add(CELL_SIZE + 7)?;
+    return Some(size / 8 * 8);
+}
+
+/// Write the `systems` to `path` in the binary format used by
+/// [`MmapSystems`].
+///
+/// Only the species, positions and unit cell of the systems are stored; all
+/// other per-atom data (charges, masses, ghost atoms, …) is not part of this
+/// format. The data is always stored in little-endian order.
+pub fn write_mmap_systems(path: impl AsRef<Path>, systems: &[Box<dyn System>]) -> Result<(), Error> {
+    let path = path.as_ref();
+    let io_error = |e: std::io::Error| Error::InvalidParameter(format!(
+        "failed to write '{}': {}", path.display(), e
+    ));
+
+    let mut data = Vec::new();
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&VERSION.to_le_bytes());
+    data.extend_from_slice(&(systems.len() as u64).to_le_bytes());
+
+    // index with the offset and number of atoms of each system
+    let mut offset = 24 + systems.len() * 16;
+    for system in systems {
+        let n_atoms = system.size()?;
+        data.extend_from_slice(&(offset as u64).to_le_bytes());
+        data.extend_from_slice(&(n_atoms as u64).to_le_bytes());
+        offset += system_size(n_atoms).expect("system is too large");
+    }
+
+    for system in systems {
+        let start = data.len();
+
+        let cell = system.cell()?;
+        for row in cell.matrix().iter() {
+            for value in row {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        let periodic = cell.periodic();
+        data.extend_from_slice(&[
+            u8::from(cell.is_infinite()), u8::from(periodic[0]), u8::from(periodic[1]), u8::from(periodic[2]),
+            0, 0, 0, 0
+        ]);
+
+        for position in system.positions()? {
+            for value in position.iter() {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        for species in system.species()? {
+            data.extend_from_slice(&species.to_le_bytes());
+        }
+
+        data.resize(start + system_size(system.size()?).expect("system is too large"), 0);
+    }
+
+    let mut file = std::fs::File::create(path).map_err(io_error)?;
+    file.write_all(&data).map_err(io_error)?;
+
+    return Ok(());
+}
+
+/// Collection of systems stored in a memory-mapped file, created with
+/// [`write_mmap_systems`].
+///
+/// Opening the file only reads a small index, and the systems created from
+/// this collection directly use the mapped memory for their positions and
+/// species without copying them, making it very cheap to load large datasets
+/// again and again.
+pub struct MmapSystems {
+    mmap: Arc<Mmap>,
+    /// offset in the file and number of atoms of each system
+    entries: Vec<(usize, usize)>,
+}
+
+impl MmapSystems {
+    /// Open the memory-mapped system file at the given `path`.
+    ///
+    /// The file must not be modified while the mapping is alive.
+    pub fn open(path: impl AsRef<Path>) -> Result<MmapSystems, Error> {
+        if cfg!(target_endian = "big") {
+            return Err(Error::InvalidParameter(
+                "memory-mapped systems are only supported on little-endian platforms".into()
+            ));
+        }
+
+        let path = path.as_ref();
+        let file = std::fs::File::open(path).map_err(|e| Error::InvalidParameter(format!(
+            "failed to read '{}': {}", path.display(), e
+        )))?;
+
+        // SAFETY: this is only unsafe if the file is modified by someone else
+        // while we have it mapped, which is documented as forbidden above
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|e| Error::InvalidParameter(format!(
+            "failed to map '{}' in memory: {}", path.display(), e
+        )))?;
+
+        let format_error = |message: &str| Error::InvalidParameter(format!(
+            "invalid memory-mapped systems file '{}': {}", path.display(), message
+        ));
+
+        let read_u64 = |offset: usize| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&mmap[offset..offset + 8]);
+            u64::from_le_bytes(bytes) as usize
+        };
+
+        if mmap.len() < 24 || mmap[..8] != *MAGIC {
+            return Err(format_error("missing header"));
+        }
+
+        if read_u64(8) != VERSION as usize {
+            return Err(format_error(&format!("unsupported version {}", read_u64(8))));
+        }
+
+        let n_systems = read_u64(16);
+        let index_end = n_systems.checked_mul(16).and_then(|size| size.checked_add(24));
+        if !matches!(index_end, Some(end) if end <= mmap.len()) {
+            return Err(format_error("the file is too small"));
+        }
+
+        let mut entries = Vec::with_capacity(n_systems);
+        for i in 0..n_systems {
+            let offset = read_u64(24 + 16 * i);
+            let n_atoms = read_u64(24 + 16 * i + 8);
+
+            // the mapping itself is page-aligned, so this is enough to ensure
+            // that the positions are correctly aligned
+            if offset % 8 != 0 {
+                return Err(format_error("misaligned system data"));
+            }
+
+            let system_end = system_size(n_atoms).and_then(|size| offset.checked_add(size));
+            if !matches!(system_end, Some(end) if end <= mmap.len()) {
+                return Err(format_error("the file is too small"));
+            }
+
+            entries.push((offset, n_atoms));
+        }
+
+        let systems = MmapSystems {
+            mmap: Arc::new(mmap),
+            entries: entries,
+        };
+
+        for i in 0..systems.len() {
+            let matrix = systems.cell_matrix(i);
+            let infinite = systems.mmap[systems.entries[i].0 + 72] != 0;
+            if !infinite && matrix.determinant() <= 1e-6 {
+                return Err(format_error("degenerate unit cell"));
+            }
+        }
+
+        return Ok(systems);
+    }
+
+    /// Get the number of systems in this file
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if this file contains no systems
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn cell_matrix(&self, i: usize) -> Matrix3 {
+        let offset = self.entries[i].0;
+        let mut matrix = Matrix3::zero();
+        for row in 0..3 {
+            for column in 0..3 {
+                let start = offset + 8 * (3 * row + column);
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(&self.mmap[start..start + 8]);
+                matrix[row][column] = f64::from_le_bytes(bytes);
+            }
+        }
+        return matrix;
+    }
+
+    /// Get the system at index `i` in this file
+    pub fn system(&self, i: usize) -> MmapSystem {
+        let (offset, n_atoms) = self.entries[i];
+
+        let flags = &self.mmap[offset + 72..offset + 76];
+        let cell = if flags[0] != 0 {
+            UnitCell::infinite()
+        } else {
+            UnitCell::from(self.cell_matrix(i)).with_periodicity([flags[1] != 0, flags[2] != 0, flags[3] != 0])
+        };
+
+        MmapSystem {
+            mmap: Arc::clone(&self.mmap),
+            offset: offset,
+            n_atoms: n_atoms,
+            cell: cell,
+            neighbors: NeighborsCache::default(),
+        }
+    }
+
+    /// Get all the systems in this file, ready to be used with
+    /// `Calculator::compute`
+    pub fn systems(&self) -> Vec<Box<dyn System>> {
+        (0..self.len()).map(|i| Box::new(self.system(i)) as Box<dyn System>).collect()
+    }
+}
+
+/// Get the positions of the `n_atoms` atoms of the system stored at `offset`
+/// in the `mmap`
+#[allow(clippy::cast_ptr_alignment)]
+fn mmap_positions(mmap: &Mmap, offset: usize, n_atoms: usize) -> &[Vector3D] {
+    let start = offset + CELL_SIZE;
+    let bytes = &mmap[start..start + n_atoms * 3 * 8];
+    // SAFETY: the data is aligned to 8 bytes (this is checked when opening
+    // the file), the length was checked above, `Vector3D` has the same
+    // layout as `[f64; 3]` and the file contains little-endian data.
+    unsafe {
+        std::slice::from_raw_parts(bytes.as_ptr().cast::<Vector3D>(), n_atoms)
+    }
+}
+
+/// A single system in a memory-mapped file, see [`MmapSystems`].
+pub struct MmapSystem {
+    mmap: Arc<Mmap>,
+    offset: usize,
+    n_atoms: usize,
+    cell: UnitCell,
+    neighbors: NeighborsCache,
+}
+
+impl System for MmapSystem {
+    fn size(&self) -> Result<usize, Error> {
+        Ok(self.n_atoms)
+    }
+
+    fn positions(&self) -> Result<&[Vector3D], Error> {
+        Ok(mmap_positions(&self.mmap, self.offset, self.n_atoms))
+    }
+
+    #[allow(clippy::cast_ptr_alignment)]
+    fn species(&self) -> Result<&[i32], Error> {
+        let start = self.offset + CELL_SIZE + self.n_atoms * 3 * 8;
+        let bytes = &self.mmap[start..start + self.n_atoms * 4];
+        // SAFETY: same as for the positions, the species come right after the
+        // positions and are aligned to 8 bytes.
+        let species = unsafe {
+            std::slice::from_raw_parts(bytes.as_ptr().cast::<i32>(), self.n_atoms)
+        };
+        Ok(species)
+    }
+
+    fn cell(&self) -> Result<UnitCell, Error> {
+        Ok(self.cell)
+    }
+
+    fn compute_neighbors(&mut self, cutoff: f64) -> Result<(), Error> {
+        let mmap = Arc::clone(&self.mmap);
+        let positions = mmap_positions(&mmap, self.offset, self.n_atoms);
+        let cell = self.cell;
+        // re-use already computed NL if possible
+        self.neighbors.select(cutoff, || Ok(NeighborsList::new(positions, cell, cutoff)))
+    }
+
+    fn pairs(&self) -> Result<&[Pair], Error> {
+        let neighbors = self.neighbors.current().ok_or_else(|| Error::Internal(
+            "neighbor list is not initialized".into()
+        ))?;
+        Ok(&neighbors.pairs)
+    }
+
+    fn pairs_containing(&self, center: usize) -> Result<&[Pair], Error> {
+        let neighbors = self.neighbors.current().ok_or_else(|| Error::Internal(
+            "neighbor list is not initialized".into()
+        ))?;
+        Ok(&neighbors.pairs_by_center[center])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::systems::test_utils::test_systems;
+    use crate::systems::CellShape;
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut systems = test_systems(&["water", "methane"]);
+        let mut infinite = crate::SimpleSystem::new(UnitCell::infinite());
+        infinite.add_atom(3, Vector3D::new(1.0, 2.0, 3.0));
+        systems.push(Box::new(infinite));
+
+        let path = std::env::temp_dir().join(format!("rascaline-mmap-{}.bin", std::process::id()));
+        write_mmap_systems(&path, &systems).unwrap();
+
+        let mmap = MmapSystems::open(&path).unwrap();
+        assert_eq!(mmap.len(), 3);
+
+        let loaded = mmap.systems();
+        for (expected, actual) in systems.iter().zip(&loaded) {
+            assert_eq!(expected.size().unwrap(), actual.size().unwrap());
+            assert_eq!(expected.species().unwrap(), actual.species().unwrap());
+            assert_eq!(expected.positions().unwrap(), actual.positions().unwrap());
+            assert_eq!(expected.cell().unwrap().matrix(), actual.cell().unwrap().matrix());
+        }
+        assert_eq!(loaded[2].cell().unwrap().shape(), CellShape::Infinite);
+
+        let mut system = mmap.system(1);
+        system.compute_neighbors(3.0).unwrap();
+        assert!(!system.pairs().unwrap().is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_file() {
+        let path = std::env::temp_dir().join(format!("rascaline-mmap-invalid-{}.bin", std::process::id()));
+        std::fs::write(&path, b"not a valid file").unwrap();
+
+        let error = MmapSystems::open(&path).err().unwrap();
+        assert!(error.to_string().ends_with("missing header"));
+
+        // offsets and sizes which overflow when computing the end of a system
+        write_mmap_systems(&path, &test_systems(&["water"])).unwrap();
+        let valid = std::fs::read(&path).unwrap();
+
+        let mut data = valid.clone();
+        data[24..32].copy_from_slice(&(u64::MAX - 7).to_le_bytes());
+        std::fs::write(&path, &data).unwrap();
+        let error = MmapSystems::open(&path).err().unwrap();
+        assert!(error.to_string().ends_with("the file is too small"));
+
+        let mut data = valid.clone();
+        data[32..40].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(&path, &data).unwrap();
+        let error = MmapSystems::open(&path).err().unwrap();
+        assert!(error.to_string().ends_with("the file is too small"));
+
+        let mut data = valid;
+        data[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(&path, &data).unwrap();
+        let error = MmapSystems::open(&path).err().unwrap();
+        assert!(error.to_string().ends_with("the file is too small"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -6,11 +6,38 @@ pub use self::cell::{UnitCell, CellShape};
 mod neighbors;
 pub use self::neighbors::NeighborsList;
 
+mod kdtree;
+
 mod simple_system;
 pub use self::simple_system::SimpleSystem;
 
+mod batch;
+pub use self::batch::SystemsBatch;
+
+mod elements;
+
+mod species;
+pub use self::species::SpeciesMap;
+
+mod validation;
+pub use self::validation::InvalidSystem;
+
+mod files;
+
 mod chemfiles;
-pub use self::chemfiles::read_from_file;
+pub use self::chemfiles::{read_from_file, read_from_file_with_options, ReadOptions};
+
+mod xyz;
+pub use self::xyz::{read_xyz, parse_xyz};
+
+mod lammps;
+pub use self::lammps::{read_lammps_dump, parse_lammps_dump};
+
+mod poscar;
+pub use self::poscar::{read_poscar, parse_poscar};
+
+mod mmap;
+pub use self::mmap::{write_mmap_systems, MmapSystems, MmapSystem};
 
 #[cfg(test)]
 pub(crate) mod test_utils;
@@ -30,6 +57,26 @@ pub struct Pair {
     /// vector from the first atom to the second atom, wrapped inside the unit
     /// cell as required
     pub vector: Vector3D,
+    /// number of unit cell vectors to add to the position of the second atom
+    /// to get the periodic image used in this pair, i.e. `vector` is equal to
+    /// `positions[second] - positions[first] + cell_shift[0] * a +
+    /// cell_shift[1] * b + cell_shift[2] * c`. This is used to distinguish
+    /// different periodic images of the same atom.
+    pub cell_shift: [i32; 3],
+}
+
+/// Information about a ghost atom, i.e. a copy of an atom owned by another
+/// domain in a domain-decomposed simulation (or a periodic image of a local
+/// atom).
+// WARNING: any change to this definition MUST be reflected in
+// rascal_ghost_atom_t as well
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GhostAtom {
+    /// index of the domain (e.g. the MPI rank) owning the original atom
+    pub rank: usize,
+    /// index of the original atom in the local atoms of the owning domain
+    pub local_index: usize,
 }
 
 /// A `System` deals with the storage of atoms and related information, as well
@@ -41,6 +88,15 @@ pub trait System: Send + Sync {
     /// Get the number of atoms in this system
     fn size(&self) -> Result<usize, Error>;
 
+    /// Get the number of local atoms in this system. Local atoms are stored
+    /// first, and all atoms with an index larger than or equal to
+    /// `local_size()` are ghost atoms: they are used as neighbors of the local
+    /// atoms, but calculators never use them as centers. The default
+    /// implementation returns `self.size()`, i.e. there are no ghost atoms.
+    fn local_size(&self) -> Result<usize, Error> {
+        return self.size();
+    }
+
     /// Get the atomic species for all atoms in this system. The returned value
     /// must be a slice of length `self.size()`, where each different atomic
     /// species is identified with a different integer value. These values are
@@ -62,6 +118,9 @@ pub trait System: Send + Sync {
     /// distance between atoms is actually bellow the cutoff passed in the last
     /// call to `compute_neighbors`. This function is only valid to call after a
     /// call to `compute_neighbors`.
+    ///
+    /// If `pairs_sorted_by_distance` returns `true`, the pairs must be sorted
+    /// by increasing distance. Otherwise, no specific order is required.
     fn pairs(&self) -> Result<&[Pair], Error>;
 
     /// Get the list of pairs in this system which include the atom at index
@@ -70,4 +129,158 @@ pub trait System: Send + Sync {
     /// included both in the return of `pairs_containing(i)` and
     /// `pairs_containing(j)`.
     fn pairs_containing(&self, center: usize) -> Result<&[Pair], Error>;
+
+    /// Are the pairs returned by `pairs` and `pairs_containing` sorted by
+    /// increasing distance? Calculators that only use the nearest neighbors of
+    /// each center can rely on this to avoid sorting the pairs again. The
+    /// default implementation returns `false`.
+    fn pairs_sorted_by_distance(&self) -> Result<bool, Error> {
+        return Ok(false);
+    }
+
+    /// Get the list of chemical bonds in this system, if any. Each bond is
+    /// given as a pair of atomic indexes `[i, j]`, and should only appear
+    /// once. The default implementation returns an empty list, i.e. systems
+    /// do not carry any topology information by default.
+    fn bonds(&self) -> Result<&[[usize; 2]], Error> {
+        return Ok(&[]);
+    }
+
+    /// Get a per-atom scaling factor for the atomic density, if any. This can
+    /// be used to make the density of each atom depend on some atomic property
+    /// such as an effective (Hirshfeld) volume or a covalent radius. When
+    /// present, the returned slice must contain exactly one positive value for
+    /// each atom in the system. The default implementation returns the
+    /// `"density_scaling"` atom data (see `System::atom_data`).
+    fn density_scaling(&self) -> Result<Option<&[f64]>, Error> {
+        return self.atom_data("density_scaling");
+    }
+
+    /// Get a per-atom width for the gaussian atomic density, if any. This can
+    /// be used to create environment-dependent smearing schemes. When present,
+    /// the returned slice must contain exactly one strictly positive value for
+    /// each atom in the system. The default implementation returns the
+    /// `"atomic_gaussian_width"` atom data (see `System::atom_data`).
+    fn atomic_gaussian_width(&self) -> Result<Option<&[f64]>, Error> {
+        return self.atom_data("atomic_gaussian_width");
+    }
+
+    /// Get the charge of each atom in this system, if any. This is used by
+    /// calculators that need atomic charges, such as some LODE variants. When
+    /// present, the returned slice must contain exactly one value for each
+    /// atom in the system. The default implementation returns the
+    /// `"charges"` atom data (see `System::atom_data`).
+    fn charges(&self) -> Result<Option<&[f64]>, Error> {
+        return self.atom_data("charges");
+    }
+
+    /// Get the mass of each atom in this system, if any. When present, the
+    /// returned slice must contain exactly one strictly positive value for
+    /// each atom in the system. The default implementation returns the
+    /// `"masses"` atom data (see `System::atom_data`).
+    fn masses(&self) -> Result<Option<&[f64]>, Error> {
+        return self.atom_data("masses");
+    }
+
+    /// Get the velocity of each atom in this system, if any. When present,
+    /// the returned slice must contain exactly one value for each atom in the
+    /// system. The default implementation returns `None`.
+    fn velocities(&self) -> Result<Option<&[Vector3D]>, Error> {
+        return Ok(None);
+    }
+
+    /// Get the values of the per-atom data with the given `name`, if any.
+    /// This allows systems to carry arbitrary named per-atom properties (for
+    /// example `"hirshfeld_volume"` or `"spin"`) that calculators can query.
+    /// When present, the returned slice must contain exactly one value for
+    /// each atom in the system. The default implementation returns `None`.
+    ///
+    /// The `"density_scaling"`, `"atomic_gaussian_width"`, `"charges"` and
+    /// `"masses"` names are used by the default implementation of the
+    /// corresponding functions, so systems only need to implement this
+    /// function to provide all of these.
+    fn atom_data(&self, name: &str) -> Result<Option<&[f64]>, Error> {
+        let _ = name;
+        return Ok(None);
+    }
+
+    /// Get the names of all the per-atom data available through
+    /// `System::atom_data`, if they are known. This is used to copy the data
+    /// when converting the system to a `SimpleSystem`. The default
+    /// implementation returns an empty list.
+    fn atom_data_names(&self) -> Result<Vec<&str>, Error> {
+        return Ok(Vec::new());
+    }
+
+    /// Get this system as `Any`, allowing to recover the concrete type of
+    /// the system. The default implementation returns `None`.
+    fn as_any_mut(&mut self) -> Option<&mut dyn std::any::Any> {
+        return None;
+    }
+
+    /// Get the owner of each ghost atom in this system, if known. When
+    /// present, the returned slice must contain one entry for each ghost atom,
+    /// i.e. `self.size() - self.local_size()` entries, in the same order as
+    /// the atoms. The default implementation returns `None`.
+    fn ghost_atoms(&self) -> Result<Option<&[GhostAtom]>, Error> {
+        return Ok(None);
+    }
+
+    /// Get a per-atom mask indicating which atoms should be used as centers by
+    /// atom-centered calculators, if any. This can be used to only compute
+    /// representations for a subset of the atoms, for example only the
+    /// adsorbate on a large slab: all atoms are still used as neighbors. When
+    /// present, the returned slice must contain exactly one value for each
+    /// atom in the system; ghost atoms are never used as centers regardless
+    /// of the mask. The default implementation returns `None`, i.e. all local
+    /// atoms are centers.
+    fn centers(&self) -> Result<Option<&[bool]>, Error> {
+        return Ok(None);
+    }
+
+    /// Get the mapping between the species of this system and chemical
+    /// symbols (or other names for the atomic types), if any. The default
+    /// implementation returns `None`.
+    fn species_map(&self) -> Result<Option<&SpeciesMap>, Error> {
+        return Ok(None);
+    }
+
+    /// Check that this system is valid, returning an `Error::InvalidSystem`
+    /// describing the problem otherwise. This checks that all per-atom data
+    /// contains one value per atom, that all positions are finite, that the
+    /// unit cell has a positive volume (and right-handed cell vectors), and
+    /// that no two atoms are overlapping.
+    ///
+    /// Calculators do not run this validation automatically, since it
+    /// requires computing a (short) neighbor list. It should be used to get
+    /// clearer error messages when a calculation gives unexpected NaN values.
+    fn validate(&self) -> Result<(), Error> {
+        return validation::validate_system(self);
+    }
+}
+
+/// Get which atoms of `system` should be used as centers, accounting for both
+/// ghost atoms (see `System::local_size`) and the mask from `System::centers`.
+pub(crate) fn center_atoms(system: &dyn System) -> Result<Vec<bool>, Error> {
+    let size = system.size()?;
+    let local_size = system.local_size()?;
+
+    let mut centers = vec![true; size];
+    for center in &mut centers[local_size..] {
+        *center = false;
+    }
+
+    if let Some(mask) = system.centers()? {
+        if mask.len() != size {
+            return Err(Error::InvalidParameter(format!(
+                "expected {} values in the centers mask, got {}", size, mask.len()
+            )));
+        }
+
+        for (center, &selected) in centers.iter_mut().zip(mask) {
+            *center &= selected;
+        }
+    }
+
+    return Ok(centers);
 }
@@ -1,13 +1,19 @@
 use log::warn;
 use ndarray::Array3;
+use rayon::prelude::*;
 
-use crate::{Matrix3, Vector3D};
+use crate::{Error, Matrix3, Vector3D};
 use super::{UnitCell, Pair};
+use super::kdtree::KdTree;
 
 /// Maximal number of cells, we need to use this to prevent having too many
 /// cells with a small unit cell and a large cutoff
 const MAX_NUMBER_OF_CELLS: f64 = 1e5;
 
+/// Maximal number of neighbor lists with different cutoffs stored in a
+/// `NeighborsCache`
+const MAX_CACHED_NEIGHBORS_LISTS: usize = 4;
+
 /// A cell shift represents the displacement along cell axis between the actual
 /// position of an atom and a periodic image of this atom.
 ///
@@ -84,6 +90,9 @@ pub struct AtomData {
 /// The list of potential pairs is then constructed by looking through all
 /// neighboring cells (the number of cells to search depends on the cutoff and
 /// the size of the cells) for each atom to create pair candidates.
+///
+/// The cell list is only used for systems with a finite unit cell, pairs in
+/// infinite cells are found with a [`KdTree`] instead.
 #[derive(Debug, Clone)]
 pub struct CellList {
     /// How many cells do we need to look at when searching neighbors to include
@@ -96,16 +105,11 @@ pub struct CellList {
 }
 
 impl CellList {
-    /// Create a new `CellList` for the given unit cell and cutoff, determining
-    /// all required parameters.
+    /// Create a new `CellList` for the given (finite) unit cell and cutoff,
+    /// determining all required parameters.
     pub fn new(unit_cell: UnitCell, cutoff: f64) -> CellList {
-        let distances_between_faces = if unit_cell.is_infinite() {
-            // use a pseudo orthorhombic cell with size 1, `n_search` below will
-            // make sure we look to every cell up to the cutoff
-            Vector3D::new(1.0, 1.0, 1.0)
-        } else {
-            unit_cell.distances_between_faces()
-        };
+        assert!(!unit_cell.is_infinite(), "the cell list requires a finite unit cell");
+        let distances_between_faces = unit_cell.distances_between_faces();
 
         let mut n_cells = [
             f64::clamp(f64::trunc(distances_between_faces[0] / cutoff), 1.0, f64::INFINITY),
@@ -130,11 +134,13 @@ impl CellList {
         }
 
         // number of cells to search in each direction to make sure all possible
-        // pairs below the cutoff are accounted for.
+        // pairs below the cutoff are accounted for. If the cutoff is larger
+        // than the cell, this will include multiple periodic images of the
+        // same atoms.
         let mut n_search = [
-            f64::trunc(cutoff * n_cells[0] / distances_between_faces[0]) as isize,
-            f64::trunc(cutoff * n_cells[1] / distances_between_faces[1]) as isize,
-            f64::trunc(cutoff * n_cells[2] / distances_between_faces[2]) as isize,
+            f64::ceil(cutoff * n_cells[0] / distances_between_faces[0]) as isize,
+            f64::ceil(cutoff * n_cells[1] / distances_between_faces[1]) as isize,
+            f64::ceil(cutoff * n_cells[2] / distances_between_faces[2]) as isize,
         ];
 
         let n_cells = [
@@ -147,12 +153,6 @@ impl CellList {
             if n_search[spatial] < 1 {
                 n_search[spatial] = 1;
             }
-
-            // don't look for neighboring cells if we have only one cell and no
-            // periodic boundary condition
-            if n_cells[spatial] == 1 && unit_cell.is_infinite() {
-                n_search[spatial] = 0;
-            }
         }
 
         CellList {
@@ -162,14 +162,28 @@ impl CellList {
         }
     }
 
-    /// Add a single atom to the cell list at the given `position`. The atom is
-    /// uniquely identified by its `index`.
-    pub fn add_atom(&mut self, index: usize, position: Vector3D) {
-        let fractional = if self.unit_cell.is_infinite() {
-            position
-        } else {
-            self.unit_cell.fractional(position)
-        };
+    /// Add all the atoms at `positions` to the cell list, using the index of
+    /// each atom in `positions` to identify it. The cell of each atom is
+    /// determined in parallel.
+    pub fn add_atoms(&mut self, positions: &[Vector3D]) {
+        let cells = positions.par_iter()
+            .map(|&position| self.find_cell(position))
+            .collect::<Vec<_>>();
+
+        // atoms are still added sequentially, to keep the order of atoms
+        // inside each cell deterministic
+        for (index, (shift, cell_index)) in cells.into_iter().enumerate() {
+            self.cells[cell_index].push(AtomData {
+                index: index,
+                shift: CellShift(shift),
+            });
+        }
+    }
+
+    /// Find the cell in which an atom at the given `position` should go, and
+    /// the shift from this position to the image of the atom inside the cell.
+    fn find_cell(&self, position: Vector3D) -> ([isize; 3], [usize; 3]) {
+        let fractional = self.unit_cell.fractional(position);
 
         let n_cells = self.cells.shape();
         let n_cells = [n_cells[0], n_cells[1], n_cells[2]];
@@ -183,21 +197,7 @@ impl CellList {
 
         // deal with pbc by wrapping the atom inside if it was outside of the
         // cell
-        let (shift, cell_index) = if self.unit_cell.is_infinite() {
-            let cell_index = [
-                usize::clamp(cell_index[0] as usize, 0, n_cells[0] - 1),
-                usize::clamp(cell_index[1] as usize, 0, n_cells[1] - 1),
-                usize::clamp(cell_index[2] as usize, 0, n_cells[2] - 1),
-            ];
-            ([0, 0, 0], cell_index)
-        } else {
-            divmod_vec(cell_index, n_cells)
-        };
-
-        self.cells[cell_index].push(AtomData {
-            index: index,
-            shift: CellShift(shift),
-        });
+        return divmod_vec(cell_index, n_cells);
     }
 
     /// Get the list of candidate pair. Some pairs might be separated by more
@@ -214,67 +214,86 @@ impl CellList {
     /// distances/directions are still included. Using the example above and
     /// with a cutoff of 5 Å, we can have a pair between atoms 33-64 at 2.6 Å
     /// and another pair between atoms 33-64 at 4.8 Å.
+    ///
+    /// The different cells are searched in parallel, and the pairs are
+    /// returned in the same order as a sequential search would produce.
     pub fn pairs(&self) -> Vec<CellPair> {
+        let cells = self.cells.indexed_iter()
+            .filter(|(_, current_cell)| !current_cell.is_empty())
+            .map(|(cell_i, _)| cell_i)
+            .collect::<Vec<_>>();
+
+        return cells.into_par_iter()
+            .flat_map_iter(|cell_i| self.cell_pairs(cell_i))
+            .collect();
+    }
+
+    /// Get the candidate pairs where the first atom is in the cell at index
+    /// `(cell_i_x, cell_i_y, cell_i_z)`
+    fn cell_pairs(&self, (cell_i_x, cell_i_y, cell_i_z): (usize, usize, usize)) -> Vec<CellPair> {
         let mut pairs = Vec::new();
 
         let n_cells = self.cells.shape();
         let n_cells = [n_cells[0], n_cells[1], n_cells[2]];
 
+        let periodic = self.unit_cell.periodic();
+
         let search_x = -self.n_search[0]..=self.n_search[0];
         let search_y = -self.n_search[1]..=self.n_search[1];
         let search_z = -self.n_search[2]..=self.n_search[2];
 
-        // for each cell in the cell list
-        for ((cell_i_x, cell_i_y, cell_i_z), current_cell) in self.cells.indexed_iter() {
-            // look through each neighboring cell
-            for delta_x in search_x.clone() {
-                for delta_y in search_y.clone() {
-                    for delta_z in search_z.clone() {
-                        let cell_i = [
-                            cell_i_x as isize + delta_x,
-                            cell_i_y as isize + delta_y,
-                            cell_i_z as isize + delta_z,
-                        ];
-
-                        // shift vector from one cell to the other and index of
-                        // the neighboring cell
-                        let (cell_shift, neighbor_cell_i) = divmod_vec(cell_i, n_cells);
-
-                        for atom_i in current_cell {
-                            for atom_j in &self.cells[neighbor_cell_i] {
-                                // create a half neighbor list
-                                if atom_i.index > atom_j.index {
-                                    continue;
-                                }
-
-                                let shift = CellShift(cell_shift) + atom_i.shift - atom_j.shift;
-                                let shift_is_zero = shift[0] == 0 && shift[1] == 0 && shift[2] == 0;
-
-                                if atom_i.index == atom_j.index && shift_is_zero {
-                                    // only create pair with the same atom twice
-                                    // if the pair spans more than one unit cell
-                                    continue;
-                                }
-
-                                if self.unit_cell.is_infinite() && !shift_is_zero {
-                                    // do not create pairs crossing the periodic
-                                    // boundaries in an infinite cell
-                                    continue;
-                                }
-
-                                pairs.push(CellPair {
-                                    first: atom_i.index,
-                                    second: atom_j.index,
-                                    shift: shift,
-                                });
+        let current_cell = &self.cells[[cell_i_x, cell_i_y, cell_i_z]];
+
+        // look through each neighboring cell
+        for delta_x in search_x {
+            for delta_y in search_y.clone() {
+                for delta_z in search_z.clone() {
+                    let cell_i = [
+                        cell_i_x as isize + delta_x,
+                        cell_i_y as isize + delta_y,
+                        cell_i_z as isize + delta_z,
+                    ];
+
+                    // shift vector from one cell to the other and index of
+                    // the neighboring cell
+                    let (cell_shift, neighbor_cell_i) = divmod_vec(cell_i, n_cells);
+
+                    for atom_i in current_cell {
+                        for atom_j in &self.cells[neighbor_cell_i] {
+                            // create a half neighbor list
+                            if atom_i.index > atom_j.index {
+                                continue;
                             }
-                        } // loop over atoms in current neighbor cells
 
-                    }
-                }
-            } // loop over neighboring cells
+                            let shift = CellShift(cell_shift) + atom_i.shift - atom_j.shift;
+                            let shift_is_zero = shift[0] == 0 && shift[1] == 0 && shift[2] == 0;
 
-        }
+                            if atom_i.index == atom_j.index && shift_is_zero {
+                                // only create pair with the same atom twice
+                                // if the pair spans more than one unit cell
+                                continue;
+                            }
+
+                            let crosses_open_boundary = (0..3).any(|spatial| {
+                                !periodic[spatial] && shift[spatial] != 0
+                            });
+                            if crosses_open_boundary {
+                                // do not create pairs crossing the cell
+                                // boundaries along non-periodic directions
+                                continue;
+                            }
+
+                            pairs.push(CellPair {
+                                first: atom_i.index,
+                                second: atom_j.index,
+                                shift: shift,
+                            });
+                        }
+                    } // loop over atoms in current neighbor cells
+
+                }
+            }
+        } // loop over neighboring cells
 
         return pairs;
     }
@@ -303,6 +322,71 @@ fn divmod_vec(a: [isize; 3], b: [usize; 3]) -> ([isize; 3], [usize; 3]) {
     return ([qx, qy, qz], [rx, ry, rz]);
 }
 
+/// Candidate pairs for a Verlet neighbor list, i.e. all pairs separated by
+/// less than `cutoff + skin`. The same candidates can be used to build the
+/// neighbor list with `cutoff` as long as no atom moved by more than `skin / 2`
+/// since the candidates were created.
+#[derive(Clone, Debug)]
+pub struct VerletCandidates {
+    /// the cutoff of the neighbor lists created from these candidates
+    pub cutoff: f64,
+    /// additional distance included in the candidates
+    pub skin: f64,
+    /// positions of the atoms when creating the candidates
+    reference_positions: Vec<Vector3D>,
+    /// candidate pairs
+    pairs: Vec<CellPair>,
+}
+
+impl VerletCandidates {
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "VerletCandidates"))]
+    pub fn new(positions: &[Vector3D], unit_cell: UnitCell, cutoff: f64, skin: f64) -> VerletCandidates {
+        let max_distance = cutoff + skin;
+
+        let pairs = if unit_cell.is_infinite() {
+            // without periodic boundary conditions, the kd-tree adapts better
+            // to the distribution of atoms than the cell list, and directly
+            // gives the pairs below the cutoff + skin
+            KdTree::new(positions).pairs(max_distance)
+        } else {
+            let mut cell_list = CellList::new(unit_cell, max_distance);
+            cell_list.add_atoms(positions);
+
+            let cell_matrix = unit_cell.matrix();
+            let max_distance2 = max_distance * max_distance;
+
+            // the cell list creates too many pairs, we only need to keep the
+            // one where the distance is actually below the cutoff + skin
+            cell_list.pairs().into_par_iter().filter(|pair| {
+                let mut vector = positions[pair.second] - positions[pair.first];
+                vector += pair.shift.cartesian(&cell_matrix);
+                vector * vector < max_distance2
+            }).collect()
+        };
+
+        return VerletCandidates {
+            cutoff: cutoff,
+            skin: skin,
+            reference_positions: positions.to_vec(),
+            pairs: pairs,
+        };
+    }
+
+    /// Check if these candidates can be used to create the neighbor list for
+    /// atoms at the given `positions`
+    pub fn is_valid_for(&self, positions: &[Vector3D]) -> bool {
+        if positions.len() != self.reference_positions.len() {
+            return false;
+        }
+
+        let max_displacement2 = 0.25 * self.skin * self.skin;
+        return positions.iter().zip(&self.reference_positions).all(|(&position, &reference)| {
+            let displacement = position - reference;
+            displacement * displacement < max_displacement2
+        });
+    }
+}
+
 /// A neighbor list implementation usable with any system
 #[derive(Clone, Debug)]
 pub struct NeighborsList {
@@ -315,53 +399,56 @@ pub struct NeighborsList {
 }
 
 impl NeighborsList {
-    #[time_graph::instrument(name = "NeighborsList")]
     pub fn new(positions: &[Vector3D], unit_cell: UnitCell, cutoff: f64) -> NeighborsList {
-        let mut cell_list = CellList::new(unit_cell, cutoff);
-
-        for (index, &position) in positions.iter().enumerate() {
-            cell_list.add_atom(index, position);
-        }
+        let candidates = VerletCandidates::new(positions, unit_cell, cutoff, 0.0);
+        return NeighborsList::from_candidates(&candidates, positions, unit_cell);
+    }
 
+    /// Create a neighbor list for atoms at `positions` from existing
+    /// `candidates`, which must have been created with the same positions or
+    /// be valid for these positions (see [`VerletCandidates::is_valid_for`]).
+    #[cfg_attr(feature = "profiling", time_graph::instrument(name = "NeighborsList"))]
+    pub fn from_candidates(candidates: &VerletCandidates, positions: &[Vector3D], unit_cell: UnitCell) -> NeighborsList {
+        let cutoff = candidates.cutoff;
         let cell_matrix = unit_cell.matrix();
         let cutoff2 = cutoff * cutoff;
 
-        // the cell list creates too many pairs, we only need to keep the one where
-        // the distance is actually below the cutoff
-        let mut pairs = Vec::new();
-        let mut pairs_by_center = vec![Vec::new(); positions.len()];
-
-        for pair in cell_list.pairs() {
+        // only keep the candidates where the distance is below the cutoff
+        let mut pairs = candidates.pairs.par_iter().filter_map(|pair| {
             let mut vector = positions[pair.second] - positions[pair.first];
             vector += pair.shift.cartesian(&cell_matrix);
 
             let distance2 = vector * vector;
-            if distance2 < cutoff2 {
-                if distance2 < 1e-3 {
-                    warn!(
-                        "atoms {} and {} are very close to one another ({} A)",
-                        pair.first, pair.second, distance2.sqrt()
-                    );
-                }
-
-                let pair = Pair {
-                    first: pair.first,
-                    second: pair.second,
-                    distance: distance2.sqrt(),
-                    vector: vector,
-                };
+            if distance2 >= cutoff2 {
+                return None;
+            }
 
-                pairs.push(pair);
-                pairs_by_center[pair.first].push(pair);
-                pairs_by_center[pair.second].push(pair);
+            if distance2 < 1e-3 {
+                warn!(
+                    "atoms {} and {} are very close to one another ({} A)",
+                    pair.first, pair.second, distance2.sqrt()
+                );
             }
-        }
+
+            Some(Pair {
+                first: pair.first,
+                second: pair.second,
+                distance: distance2.sqrt(),
+                vector: vector,
+                cell_shift: [pair.shift[0] as i32, pair.shift[1] as i32, pair.shift[2] as i32],
+            })
+        }).collect::<Vec<_>>();
 
         // sort the pairs to make sure the final output of rascaline is ordered
         // naturally
-        pairs.sort_unstable_by_key(|pair| (pair.first, pair.second));
-        for pairs in &mut pairs_by_center {
-            pairs.sort_unstable_by_key(|pair| (pair.first, pair.second));
+        pairs.par_sort_unstable_by_key(|pair| (pair.first, pair.second));
+
+        // since the pairs are sorted, the pairs for each center are also
+        // sorted
+        let mut pairs_by_center = vec![Vec::new(); positions.len()];
+        for &pair in &pairs {
+            pairs_by_center[pair.first].push(pair);
+            pairs_by_center[pair.second].push(pair);
         }
 
         return NeighborsList {
@@ -370,6 +457,93 @@ impl NeighborsList {
             pairs_by_center: pairs_by_center,
         };
     }
+
+    /// Sort all the pairs in this neighbor list by increasing distance. Pairs
+    /// at the same distance are sorted by atomic indexes.
+    pub fn sort_by_distance(&mut self) {
+        let by_distance = |a: &Pair, b: &Pair| {
+            a.distance.partial_cmp(&b.distance).expect("got NaN distance")
+                .then((a.first, a.second).cmp(&(b.first, b.second)))
+        };
+
+        self.pairs.par_sort_by(by_distance);
+        self.pairs_by_center.par_iter_mut().for_each(|pairs| pairs.sort_by(by_distance));
+    }
+
+    /// Create a new neighbor list containing only the pairs of this list
+    /// separated by less than `cutoff`, which must be smaller than the cutoff
+    /// of this list. The order of the pairs is preserved.
+    pub fn restricted(&self, cutoff: f64) -> NeighborsList {
+        assert!(cutoff <= self.cutoff, "can not restrict a neighbor list to a larger cutoff");
+
+        let filter = |pairs: &[Pair]| {
+            pairs.iter().filter(|pair| pair.distance < cutoff).copied().collect::<Vec<_>>()
+        };
+
+        return NeighborsList {
+            cutoff: cutoff,
+            pairs: filter(&self.pairs),
+            pairs_by_center: self.pairs_by_center.iter().map(|pairs| filter(pairs)).collect(),
+        };
+    }
+}
+
+/// Cache of neighbor lists computed with different cutoffs for the same
+/// positions. This allows multiple calculators with different cutoffs to run
+/// on the same system without re-computing the neighbor list every time.
+/// The neighbor list for a cutoff smaller than one already in the cache is
+/// created by filtering the pairs of the larger one.
+#[derive(Clone, Debug, Default)]
+pub struct NeighborsCache {
+    /// all the cached neighbor lists, from the oldest to the newest
+    lists: Vec<NeighborsList>,
+    /// index of the neighbor list for the last requested cutoff
+    current: Option<usize>,
+}
+
+impl NeighborsCache {
+    /// Remove all the neighbor lists from this cache, for example because
+    /// the atoms moved.
+    pub fn clear(&mut self) {
+        self.lists.clear();
+        self.current = None;
+    }
+
+    /// Get the neighbor list for the cutoff used in the last call to
+    /// `select`, if any.
+    pub fn current(&self) -> Option<&NeighborsList> {
+        self.current.map(|i| &self.lists[i])
+    }
+
+    /// Select the neighbor list with the given `cutoff`, making it available
+    /// through `current`. If this neighbor list can not be created from the
+    /// cached ones, it is computed by calling `compute`.
+    #[allow(clippy::float_cmp)]
+    pub fn select<F>(&mut self, cutoff: f64, compute: F) -> Result<(), Error>
+        where F: FnOnce() -> Result<NeighborsList, Error>
+    {
+        if let Some(i) = self.lists.iter().position(|list| list.cutoff == cutoff) {
+            self.current = Some(i);
+            return Ok(());
+        }
+
+        let larger = self.lists.iter()
+            .filter(|list| list.cutoff > cutoff)
+            .min_by(|a, b| a.cutoff.partial_cmp(&b.cutoff).expect("got NaN cutoff"));
+
+        let list = match larger {
+            Some(larger) => larger.restricted(cutoff),
+            None => compute()?,
+        };
+
+        if self.lists.len() >= MAX_CACHED_NEIGHBORS_LISTS {
+            self.lists.remove(0);
+        }
+        self.lists.push(list);
+        self.current = Some(self.lists.len() - 1);
+
+        return Ok(());
+    }
 }
 
 #[cfg(test)]
@@ -413,6 +587,220 @@ mod tests {
         }
     }
 
+    #[test]
+    fn large_non_periodic() {
+        // atoms on a line, far from each other compared to the cutoff
+        let positions = (0..200).map(|i| {
+            Vector3D::new(1.5 * i as f64, 0.1 * (i % 3) as f64, -0.2 * (i % 2) as f64)
+        }).collect::<Vec<_>>();
+
+        let neighbors = NeighborsList::new(&positions, UnitCell::infinite(), 2.0);
+
+        let mut expected = Vec::new();
+        for i in 0..positions.len() {
+            for j in (i + 1)..positions.len() {
+                let distance = (positions[j] - positions[i]).norm();
+                if distance < 2.0 {
+                    expected.push((i, j, distance));
+                }
+            }
+        }
+
+        assert_eq!(neighbors.pairs.len(), expected.len());
+        for (pair, expected) in neighbors.pairs.iter().zip(&expected) {
+            assert_eq!(pair.first, expected.0);
+            assert_eq!(pair.second, expected.1);
+            assert_ulps_eq!(pair.distance, expected.2);
+        }
+    }
+
+    /// Get all the pairs below `cutoff` by looking through `max_shift`
+    /// periodic images in each periodic direction, sorted by atoms and
+    /// distances
+    fn brute_force_pairs(positions: &[Vector3D], cell: UnitCell, cutoff: f64, max_shift: isize) -> Vec<(usize, usize, f64)> {
+        let matrix = cell.matrix();
+        let periodic = cell.periodic();
+        let shifts = |spatial: usize| {
+            if periodic[spatial] { -max_shift..=max_shift } else { 0..=0 }
+        };
+
+        let mut pairs = Vec::new();
+        for i in 0..positions.len() {
+            for j in i..positions.len() {
+                for a in shifts(0) {
+                    for b in shifts(1) {
+                        for c in shifts(2) {
+                            if i == j && a == 0 && b == 0 && c == 0 {
+                                continue;
+                            }
+
+                            let shift = CellShift([a, b, c]).cartesian(&matrix);
+                            let distance = (positions[j] - positions[i] + shift).norm();
+                            if distance < cutoff {
+                                pairs.push((i, j, distance));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        pairs.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)).then(a.2.partial_cmp(&b.2).expect("got NaN distance")));
+        return pairs;
+    }
+
+    fn sorted_pairs(neighbors: &NeighborsList) -> Vec<(usize, usize, f64)> {
+        let mut pairs = neighbors.pairs.iter()
+            .map(|pair| (pair.first, pair.second, pair.distance))
+            .collect::<Vec<_>>();
+        pairs.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)).then(a.2.partial_cmp(&b.2).expect("got NaN distance")));
+        return pairs;
+    }
+
+    #[test]
+    fn cutoff_larger_than_cell() {
+        let cell = UnitCell::cubic(2.0);
+        let positions = [
+            Vector3D::new(1.8, 0.0, 0.0),
+            Vector3D::new(0.0, 0.3, 0.0),
+        ];
+
+        for cutoff in [1.5, 2.5, 4.5] {
+            let neighbors = NeighborsList::new(&positions, cell, cutoff);
+            let expected = brute_force_pairs(&positions, cell, cutoff, 5);
+
+            let pairs = sorted_pairs(&neighbors);
+            assert_eq!(pairs.len(), expected.len());
+            for (pair, expected) in pairs.iter().zip(&expected) {
+                assert_eq!(pair.0, expected.0);
+                assert_eq!(pair.1, expected.1);
+                assert_ulps_eq!(pair.2, expected.2, max_ulps=10);
+            }
+        }
+    }
+
+    #[test]
+    fn cell_shifts() {
+        let cell = UnitCell::from(Matrix3::new([[3.0, 0.0, 0.0], [2.7, 1.1, 0.0], [-2.4, 0.9, 1.6]]));
+        let matrix = cell.matrix();
+        let positions = [
+            Vector3D::new(1.8, 0.0, 0.0),
+            Vector3D::new(0.0, 0.3, 0.0),
+            Vector3D::new(-4.0, 2.1, 0.7),
+        ];
+
+        let neighbors = NeighborsList::new(&positions, cell, 4.5);
+        for pair in &neighbors.pairs {
+            let shift = CellShift([pair.cell_shift[0] as isize, pair.cell_shift[1] as isize, pair.cell_shift[2] as isize]);
+            let vector = positions[pair.second] - positions[pair.first] + shift.cartesian(&matrix);
+            assert_ulps_eq!(pair.vector, vector, epsilon=1e-12);
+        }
+
+        // the different images of the same atom have different cell shifts
+        let mut images = neighbors.pairs.iter()
+            .filter(|pair| pair.first == 0 && pair.second == 0)
+            .map(|pair| pair.cell_shift)
+            .collect::<Vec<_>>();
+        assert!(images.len() > 2);
+
+        let count = images.len();
+        images.sort_unstable();
+        images.dedup();
+        assert_eq!(images.len(), count);
+        assert!(!images.contains(&[0, 0, 0]));
+    }
+
+    #[test]
+    fn skewed_triclinic_cells() {
+        // simple deterministic pseudo-random number generator, returning
+        // values in [0, 1)
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 11) as f64 / (1_u64 << 53) as f64
+        };
+
+        let cells = [
+            Matrix3::new([[3.0, 0.0, 0.0], [2.7, 1.1, 0.0], [-2.4, 0.9, 1.6]]),
+            Matrix3::new([[4.2, 0.3, -0.2], [-3.9, 1.4, 0.1], [0.5, 3.8, 1.2]]),
+            UnitCell::triclinic(3.2, 4.1, 2.9, 80.0, 60.0, 120.0).matrix(),
+            UnitCell::triclinic(5.0, 5.0, 5.0, 25.0, 25.0, 25.0).matrix(),
+        ];
+
+        for matrix in cells {
+            let cell = UnitCell::from(matrix);
+            let distances_between_faces = cell.distances_between_faces();
+            let min_distance = f64::min(
+                distances_between_faces[0],
+                f64::min(distances_between_faces[1], distances_between_faces[2])
+            );
+
+            for cutoff in [1.0, 2.3, 4.1] {
+                // atoms inside and outside of the cell
+                let positions = (0..6).map(|_| {
+                    let fractional = Vector3D::new(
+                        2.0 * random() - 0.5,
+                        2.0 * random() - 0.5,
+                        2.0 * random() - 0.5,
+                    );
+                    cell.cartesian(fractional)
+                }).collect::<Vec<_>>();
+
+                let max_shift = f64::ceil(cutoff / min_distance) as isize + 2;
+                let expected = brute_force_pairs(&positions, cell, cutoff, max_shift);
+                let pairs = sorted_pairs(&NeighborsList::new(&positions, cell, cutoff));
+
+                assert_eq!(pairs.len(), expected.len());
+                for (pair, expected) in pairs.iter().zip(&expected) {
+                    assert_eq!(pair.0, expected.0);
+                    assert_eq!(pair.1, expected.1);
+                    assert_ulps_eq!(pair.2, expected.2, epsilon=1e-12);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mixed_periodicity() {
+        let matrix = Matrix3::new([[3.0, 0.0, 0.0], [0.8, 3.2, 0.0], [0.0, 0.0, 3.0]]);
+        let positions = [
+            Vector3D::new(0.1, 0.2, 0.3),
+            Vector3D::new(2.9, 0.3, 2.8),
+            Vector3D::new(1.5, 3.0, 0.1),
+            // outside of the cell along the non-periodic direction
+            Vector3D::new(0.4, 0.1, 4.2),
+        ];
+
+        let cutoff = 3.5;
+        for periodic in [[true, true, false], [false, true, false], [false, false, false]] {
+            let cell = UnitCell::from(matrix).with_periodicity(periodic);
+            let neighbors = NeighborsList::new(&positions, cell, cutoff);
+
+            let expected = brute_force_pairs(&positions, cell, cutoff, 5);
+
+            let pairs = sorted_pairs(&neighbors);
+            assert_eq!(pairs.len(), expected.len());
+            for (pair, expected) in pairs.iter().zip(&expected) {
+                assert_eq!(pair.0, expected.0);
+                assert_eq!(pair.1, expected.1);
+                assert_ulps_eq!(pair.2, expected.2, epsilon=1e-12);
+            }
+        }
+
+        // without periodicity, this is the same as an infinite cell
+        let cell = UnitCell::from(matrix).with_periodicity([false, false, false]);
+        let neighbors = sorted_pairs(&NeighborsList::new(&positions, cell, cutoff));
+        let infinite = sorted_pairs(&NeighborsList::new(&positions, UnitCell::infinite(), cutoff));
+        assert_eq!(neighbors.len(), infinite.len());
+        for (pair, expected) in neighbors.iter().zip(&infinite) {
+            assert_eq!(pair.0, expected.0);
+            assert_eq!(pair.1, expected.1);
+            assert_ulps_eq!(pair.2, expected.2, epsilon=1e-12);
+        }
+    }
+
     #[test]
     fn fcc_cell() {
         let cell = UnitCell::from(Matrix3::from([
@@ -476,4 +864,49 @@ mod tests {
             assert_ulps_eq!(pair.distance, 2.0);
         }
     }
+
+    #[test]
+    fn neighbors_cache() {
+        let cell = UnitCell::cubic(5.0);
+        let positions = (0..30).map(|i| {
+            let i = i as f64;
+            Vector3D::new(5.0 * f64::sin(1.3 * i), 5.0 * f64::sin(2.1 * i), 5.0 * f64::sin(0.7 * i))
+        }).collect::<Vec<_>>();
+
+        let as_tuples = |list: &NeighborsList| {
+            list.pairs.iter().map(|pair| (pair.first, pair.second, pair.cell_shift)).collect::<Vec<_>>()
+        };
+
+        let mut computed = 0;
+        let mut cache = NeighborsCache::default();
+        assert!(cache.current().is_none());
+
+        for &cutoff in &[3.0, 2.0, 3.0, 2.5] {
+            cache.select(cutoff, || {
+                computed += 1;
+                Ok(NeighborsList::new(&positions, cell, cutoff))
+            }).unwrap();
+
+            let current = cache.current().unwrap();
+            let expected = NeighborsList::new(&positions, cell, cutoff);
+            assert_eq!(current.cutoff, cutoff);
+            assert_eq!(as_tuples(current), as_tuples(&expected));
+            for (actual, expected) in current.pairs_by_center.iter().zip(&expected.pairs_by_center) {
+                assert_eq!(actual.len(), expected.len());
+            }
+        }
+
+        // only the first neighbor list had to be computed, the other ones
+        // are created from it
+        assert_eq!(computed, 1);
+
+        cache.select(4.0, || {
+            computed += 1;
+            Ok(NeighborsList::new(&positions, cell, 4.0))
+        }).unwrap();
+        assert_eq!(computed, 2);
+
+        cache.clear();
+        assert!(cache.current().is_none());
+    }
 }
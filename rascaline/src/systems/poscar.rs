@@ -0,0 +1,266 @@
+use std::path::Path;
+
+use crate::{Error, Matrix3, Vector3D};
+
+use super::{SimpleSystem, UnitCell};
+use super::files::read_to_string;
+use super::elements::SpeciesAssigner;
+
+/// Read the VASP [POSCAR] (or CONTCAR) file at the given `path`, and convert
+/// it to a `SimpleSystem`.
+///
+/// This reader does not require chemfiles. Both VASP 5 files (with a line
+/// containing the element names) and VASP 4 files (where the element names are
+/// taken from the comment line) are supported, as well as the different forms
+/// of the scaling factor. When the file uses selective dynamics, the flags are
+/// stored as a per-atom mask in the `selective_dynamics_x`,
+/// `selective_dynamics_y` and `selective_dynamics_z` atom data, containing 1
+/// if the atom is allowed to move along this direction and 0 otherwise. The
+/// velocities block at the end of CONTCAR files is ignored.
+///
+/// [POSCAR]: https://www.vasp.at/wiki/index.php/POSCAR
+pub fn read_poscar(path: impl AsRef<Path>) -> Result<SimpleSystem, Error> {
+    let content = read_to_string(path.as_ref())?;
+
+    return parse_poscar(&content);
+}
+
+/// Parse the given VASP [POSCAR] formatted `content`, see [`read_poscar`] for
+/// more information.
+///
+/// [POSCAR]: https://www.vasp.at/wiki/index.php/POSCAR
+pub fn parse_poscar(content: &str) -> Result<SimpleSystem, Error> {
+    let mut lines = content.lines().enumerate();
+
+    let (_, comment) = next_line(&mut lines, 0)?;
+
+    let (scale_i, scale) = next_line(&mut lines, 1)?;
+    let scale = parse_reals(scale).map_err(|e| poscar_error(scale_i, e))?;
+
+    let mut rows = [[0.0; 3]; 3];
+    for (i, row) in rows.iter_mut().enumerate() {
+        let (line_i, line) = next_line(&mut lines, scale_i + 1 + i)?;
+        let values = parse_reals(line).map_err(|e| poscar_error(line_i, e))?;
+        if values.len() < 3 {
+            return Err(poscar_error(line_i, "expected 3 values for the lattice vector"));
+        }
+        *row = [values[0], values[1], values[2]];
+    }
+
+    let scale = match *scale.as_slice() {
+        [factor] if factor < 0.0 => {
+            // a negative scaling factor is the volume of the cell
+            let volume = Matrix3::new(rows).determinant().abs();
+            [f64::cbrt(-factor / volume); 3]
+        }
+        [factor] => [factor; 3],
+        [x, y, z] => [x, y, z],
+        _ => return Err(poscar_error(scale_i, format!(
+            "expected 1 or 3 values for the scaling factor, got {}", scale.len()
+        ))),
+    };
+
+    for row in &mut rows {
+        for (value, factor) in row.iter_mut().zip(&scale) {
+            *value *= factor;
+        }
+    }
+
+    let matrix = Matrix3::new(rows);
+    if matrix.determinant() <= 1e-6 {
+        return Err(poscar_error(scale_i + 1,
+            "lattice vectors must define a non-degenerate right-handed cell"
+        ));
+    }
+
+    let (mut counts_i, mut counts) = next_line(&mut lines, scale_i + 4)?;
+    let names = if counts.split_whitespace().all(|v| v.parse::<usize>().is_err()) {
+        let names = counts;
+        let next = next_line(&mut lines, counts_i + 1)?;
+        counts_i = next.0;
+        counts = next.1;
+        names
+    } else {
+        // VASP 4 files do not contain the element names, the convention is
+        // to put them on the comment line instead
+        comment
+    };
+
+    let counts = counts.split_whitespace()
+        .map(|v| v.parse::<usize>().map_err(|_| poscar_error(counts_i, format!("invalid number of atoms '{}'", v))))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let names = names.split_whitespace()
+        .map(|name| {
+            // POTCAR names can contain a suffix (`Si_pv`) or a hash (`Si/a1b2`)
+            let name = name.split('/').next().unwrap_or(name);
+            name.split('_').next().unwrap_or(name)
+        })
+        .collect::<Vec<_>>();
+
+    if names.len() < counts.len() {
+        return Err(poscar_error(counts_i, format!(
+            "expected {} element names, got {}", counts.len(), names.len()
+        )));
+    }
+
+    let mut species_assigner = SpeciesAssigner::default();
+    let species = names.iter().zip(&counts)
+        .flat_map(|(name, &count)| std::iter::repeat(species_assigner.get(name)).take(count))
+        .collect::<Vec<_>>();
+
+    let (mut mode_i, mut mode) = next_line(&mut lines, counts_i + 1)?;
+    let selective_dynamics = mode.trim_start().starts_with(['s', 'S']);
+    if selective_dynamics {
+        let next = next_line(&mut lines, mode_i + 1)?;
+        mode_i = next.0;
+        mode = next.1;
+    }
+
+    let cartesian = mode.trim_start().starts_with(['c', 'C', 'k', 'K']);
+
+    let mut system = SimpleSystem::new(UnitCell::from(matrix));
+    let mut masks = [Vec::new(), Vec::new(), Vec::new()];
+    for (atom, &species) in species.iter().enumerate() {
+        let (line_i, line) = next_line(&mut lines, mode_i + 1 + atom)?;
+        let values = line.split_whitespace().collect::<Vec<_>>();
+
+        let expected = if selective_dynamics { 6 } else { 3 };
+        if values.len() < expected {
+            return Err(poscar_error(line_i, format!(
+                "expected {} values for this atom, got {}", expected, values.len()
+            )));
+        }
+
+        let mut position = [0.0; 3];
+        for (value, string) in position.iter_mut().zip(&values) {
+            *value = string.parse::<f64>().map_err(|_| poscar_error(
+                line_i, format!("invalid number '{}'", string)
+            ))?;
+        }
+
+        let position = if cartesian {
+            Vector3D::new(position[0] * scale[0], position[1] * scale[1], position[2] * scale[2])
+        } else {
+            matrix.transposed() * Vector3D::from(position)
+        };
+
+        if selective_dynamics {
+            for (mask, flag) in masks.iter_mut().zip(&values[3..6]) {
+                let value = match *flag {
+                    "T" | "t" => 1.0,
+                    "F" | "f" => 0.0,
+                    _ => return Err(poscar_error(line_i, format!(
+                        "invalid selective dynamics flag '{}'", flag
+                    ))),
+                };
+                mask.push(value);
+            }
+        }
+
+        system.add_atom(species, position);
+    }
+
+    if selective_dynamics {
+        let [mask_x, mask_y, mask_z] = masks;
+        system.set_atom_data("selective_dynamics_x", mask_x)?;
+        system.set_atom_data("selective_dynamics_y", mask_y)?;
+        system.set_atom_data("selective_dynamics_z", mask_z)?;
+    }
+
+    system.set_species_map(species_assigner.map().clone());
+
+    return Ok(system);
+}
+
+fn poscar_error(line_i: usize, message: impl Into<String>) -> Error {
+    Error::InvalidParameter(format!(
+        "invalid POSCAR file at line {}: {}", line_i + 1, message.into()
+    ))
+}
+
+fn next_line<'a>(lines: &mut impl Iterator<Item=(usize, &'a str)>, line_i: usize) -> Result<(usize, &'a str), Error> {
+    lines.next().ok_or_else(|| poscar_error(line_i, "unexpected end of file"))
+}
+
+fn parse_reals(line: &str) -> Result<Vec<f64>, String> {
+    line.split_whitespace()
+        // ignore comments at the end of the line
+        .take_while(|v| !v.starts_with(['#', '!']))
+        .map(|v| v.parse::<f64>().map_err(|_| format!("invalid number '{}'", v)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use crate::System;
+    use super::*;
+
+    #[test]
+    fn poscar() {
+        let content = "rutile
+-62.4
+   4.6 0.0 0.0
+   0.0 4.6 0.0
+   0.0 0.0 2.95
+   Ti_pv O
+   2 1
+Direct
+  0.0 0.0 0.0
+  0.5 0.5 0.5
+  0.3 0.3 0.0
+";
+        let system = parse_poscar(content).unwrap();
+        assert_eq!(system.species().unwrap(), [22, 22, 8].as_ref());
+        assert_eq!(system.species_map().unwrap().unwrap().symbol(22), Some("Ti"));
+
+        let cell = system.cell().unwrap();
+        assert_relative_eq!(cell.volume(), 62.4, max_relative=1e-12);
+        assert_eq!(cell.periodic(), [true, true, true]);
+
+        let matrix = cell.matrix();
+        let positions = system.positions().unwrap();
+        assert_relative_eq!(positions[1], 0.5 * Vector3D::new(matrix[0][0], matrix[1][1], matrix[2][2]));
+        assert_eq!(system.atom_data("selective_dynamics_x").unwrap(), None);
+    }
+
+    #[test]
+    fn selective_dynamics() {
+        let content = "Si C
+2.0
+   2.0 0.0 0.0
+   0.0 2.0 0.0
+   0.0 0.0 2.0
+   1 1
+Selective dynamics
+Cartesian
+  0.0 0.0 0.0 F F F
+  0.5 0.5 0.5 T T F
+";
+        let system = parse_poscar(content).unwrap();
+        assert_eq!(system.species().unwrap(), [14, 6].as_ref());
+        assert_eq!(system.cell().unwrap().matrix()[0], [4.0, 0.0, 0.0]);
+        assert_eq!(system.positions().unwrap()[1], Vector3D::new(1.0, 1.0, 1.0));
+
+        assert_eq!(system.atom_data("selective_dynamics_x").unwrap(), Some(&[0.0, 1.0][..]));
+        assert_eq!(system.atom_data("selective_dynamics_y").unwrap(), Some(&[0.0, 1.0][..]));
+        assert_eq!(system.atom_data("selective_dynamics_z").unwrap(), Some(&[0.0, 0.0][..]));
+    }
+
+    #[test]
+    fn errors() {
+        let error = parse_poscar("comment\n1.0\n").unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: invalid POSCAR file at line 3: unexpected end of file");
+
+        let error = parse_poscar("comment\n1.0\n1 0 0\n0 1 0\n0 0 0\nSi\n1\nDirect\n0 0 0\n").unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: invalid POSCAR file at line 3: lattice vectors must define a non-degenerate right-handed cell");
+
+        let error = parse_poscar("comment\n1.0\n1 0 0\n0 1 0\n0 0 1\nSi O\n1 1\nDirect\n0 0 0\n").unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: invalid POSCAR file at line 10: unexpected end of file");
+
+        let error = parse_poscar("comment\n1.0\n1 0 0\n0 1 0\n0 0 1\nSi\n1\nSelective\nDirect\n0 0 0 T X T\n").unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: invalid POSCAR file at line 10: invalid selective dynamics flag 'X'");
+    }
+}
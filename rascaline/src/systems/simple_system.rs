@@ -1,8 +1,10 @@
+use std::collections::BTreeMap;
+
 use crate::Error;
 
-use super::{UnitCell, System, Vector3D, Pair};
+use super::{UnitCell, System, Vector3D, Pair, GhostAtom, SpeciesMap};
 
-use super::neighbors::NeighborsList;
+use super::neighbors::{NeighborsList, NeighborsCache, VerletCandidates};
 
 /// A simple implementation of `System` to use when no other is available
 #[derive(Clone, Debug)]
@@ -10,7 +12,27 @@ pub struct SimpleSystem {
     pub(crate) cell: UnitCell,
     species: Vec<i32>,
     positions: Vec<Vector3D>,
-    neighbors: Option<NeighborsList>,
+    bonds: Vec<[usize; 2]>,
+    density_scaling: Option<Vec<f64>>,
+    atomic_gaussian_width: Option<Vec<f64>>,
+    charges: Option<Vec<f64>>,
+    masses: Option<Vec<f64>>,
+    velocities: Option<Vec<Vector3D>>,
+    /// named per-atom data, see `System::atom_data`
+    atom_data: BTreeMap<String, Vec<f64>>,
+    /// mask of the atoms to use as centers, see `System::centers`
+    centers: Option<Vec<bool>>,
+    /// owners of the ghost atoms, stored after all the local atoms
+    ghost_atoms: Vec<GhostAtom>,
+    /// mapping between species and chemical symbols, see `System::species_map`
+    species_map: Option<SpeciesMap>,
+    /// neighbor lists for the current positions, with different cutoffs
+    neighbors: NeighborsCache,
+    /// skin distance for the Verlet neighbor list, 0 if not using one
+    neighbors_skin: f64,
+    /// should the pairs be sorted by distance
+    sort_pairs_by_distance: bool,
+    verlet_candidates: Option<VerletCandidates>,
 }
 
 impl SimpleSystem {
@@ -20,27 +42,399 @@ impl SimpleSystem {
             cell: cell,
             species: Vec::new(),
             positions: Vec::new(),
-            neighbors: None,
+            bonds: Vec::new(),
+            density_scaling: None,
+            atomic_gaussian_width: None,
+            charges: None,
+            masses: None,
+            velocities: None,
+            atom_data: BTreeMap::new(),
+            centers: None,
+            ghost_atoms: Vec::new(),
+            species_map: None,
+            neighbors: NeighborsCache::default(),
+            neighbors_skin: 0.0,
+            sort_pairs_by_distance: false,
+            verlet_candidates: None,
         }
     }
 
     /// Add an atom with the given species and position to this system
+    ///
+    /// # Panics
+    ///
+    /// If this system already contains ghost atoms, since all the local atoms
+    /// must come before the ghost atoms.
     pub fn add_atom(&mut self, species: i32, position: Vector3D) {
+        assert!(self.ghost_atoms.is_empty(), "local atoms must be added before ghost atoms");
+        self.push_atom(species, position);
+    }
+
+    /// Add a ghost atom with the given species and position to this system.
+    /// `ghost` indicates which domain owns the original atom, and the index of
+    /// this atom in the owning domain. Ghost atoms are used as neighbors of the
+    /// local atoms, but are never used as centers by the calculators.
+    pub fn add_ghost_atom(&mut self, species: i32, position: Vector3D, ghost: GhostAtom) {
+        self.push_atom(species, position);
+        self.ghost_atoms.push(ghost);
+    }
+
+    fn push_atom(&mut self, species: i32, position: Vector3D) {
         self.species.push(species);
         self.positions.push(position);
+
+        if let Some(ref mut density_scaling) = self.density_scaling {
+            // new atoms use an unscaled density
+            density_scaling.push(1.0);
+        }
+
+        if let Some(ref mut charges) = self.charges {
+            // new atoms are neutral
+            charges.push(0.0);
+        }
+
+        if let Some(ref mut velocities) = self.velocities {
+            // new atoms are at rest
+            velocities.push(Vector3D::zero());
+        }
+
+        if let Some(ref mut centers) = self.centers {
+            // new atoms are used as centers
+            centers.push(true);
+        }
+
+        // there is no sensible default for the width, mass or other data of
+        // new atoms, so these must be set again after adding atoms
+        self.atomic_gaussian_width = None;
+        self.masses = None;
+        self.atom_data.clear();
+    }
+
+    /// Add a bond between the atoms at indexes `i` and `j` to this system.
+    ///
+    /// The atoms must already be part of the system, and bonds between an
+    /// atom and itself are not allowed. Adding the same bond multiple times
+    /// has no effect.
+    pub fn add_bond(&mut self, i: usize, j: usize) -> Result<(), Error> {
+        let size = self.species.len();
+        if i >= size || j >= size {
+            return Err(Error::InvalidParameter(format!(
+                "can not add a bond between atoms {} and {}: this system only contains {} atoms",
+                i, j, size
+            )));
+        }
+
+        if i == j {
+            return Err(Error::InvalidParameter(format!(
+                "can not add a bond between atom {} and itself", i
+            )));
+        }
+
+        let bond = if i < j { [i, j] } else { [j, i] };
+        if !self.bonds.contains(&bond) {
+            self.bonds.push(bond);
+        }
+
+        return Ok(());
+    }
+
+    /// Replace all the bonds in this system with the given `bonds`, following
+    /// the same rules as `add_bond`. If any of the bonds is invalid, the
+    /// existing bonds are left unchanged.
+    pub fn set_bonds(&mut self, bonds: &[[usize; 2]]) -> Result<(), Error> {
+        let previous = std::mem::take(&mut self.bonds);
+        for &[i, j] in bonds {
+            if let Err(error) = self.add_bond(i, j) {
+                self.bonds = previous;
+                return Err(error);
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Set the per-atom density scaling factors for this system (see
+    /// `System::density_scaling`). `scaling` must contain one strictly
+    /// positive value for each atom currently in the system. Atoms added
+    /// afterwards will use a scaling factor of 1.
+    pub fn set_density_scaling(&mut self, scaling: Vec<f64>) -> Result<(), Error> {
+        if scaling.len() != self.species.len() {
+            return Err(Error::InvalidParameter(format!(
+                "expected {} density scaling factors, got {}",
+                self.species.len(), scaling.len()
+            )));
+        }
+
+        if scaling.iter().any(|&v| !(v > 0.0 && v.is_finite())) {
+            return Err(Error::InvalidParameter(
+                "density scaling factors must be strictly positive".into()
+            ));
+        }
+
+        self.density_scaling = Some(scaling);
+        return Ok(());
+    }
+
+    /// Set the per-atom gaussian density widths for this system (see
+    /// `System::atomic_gaussian_width`). `widths` must contain one strictly
+    /// positive value for each atom currently in the system. Adding atoms to
+    /// the system afterwards removes all the per-atom widths.
+    pub fn set_atomic_gaussian_width(&mut self, widths: Vec<f64>) -> Result<(), Error> {
+        if widths.len() != self.species.len() {
+            return Err(Error::InvalidParameter(format!(
+                "expected {} atomic gaussian widths, got {}",
+                self.species.len(), widths.len()
+            )));
+        }
+
+        if widths.iter().any(|&v| !(v > 0.0 && v.is_finite())) {
+            return Err(Error::InvalidParameter(
+                "atomic gaussian widths must be strictly positive".into()
+            ));
+        }
+
+        self.atomic_gaussian_width = Some(widths);
+        return Ok(());
+    }
+
+    /// Set the per-atom charges for this system (see `System::charges`).
+    /// `charges` must contain one finite value for each atom currently in the
+    /// system. Atoms added afterwards will have a charge of 0.
+    pub fn set_charges(&mut self, charges: Vec<f64>) -> Result<(), Error> {
+        if charges.len() != self.species.len() {
+            return Err(Error::InvalidParameter(format!(
+                "expected {} charges, got {}",
+                self.species.len(), charges.len()
+            )));
+        }
+
+        if charges.iter().any(|v| !v.is_finite()) {
+            return Err(Error::InvalidParameter(
+                "atomic charges must be finite numbers".into()
+            ));
+        }
+
+        self.charges = Some(charges);
+        return Ok(());
+    }
+
+    /// Set the per-atom masses for this system (see `System::masses`).
+    /// `masses` must contain one strictly positive value for each atom
+    /// currently in the system. Adding atoms to the system afterwards removes
+    /// all the masses.
+    pub fn set_masses(&mut self, masses: Vec<f64>) -> Result<(), Error> {
+        if masses.len() != self.species.len() {
+            return Err(Error::InvalidParameter(format!(
+                "expected {} masses, got {}",
+                self.species.len(), masses.len()
+            )));
+        }
+
+        if masses.iter().any(|&v| !(v > 0.0 && v.is_finite())) {
+            return Err(Error::InvalidParameter(
+                "atomic masses must be strictly positive".into()
+            ));
+        }
+
+        self.masses = Some(masses);
+        return Ok(());
+    }
+
+    /// Set the per-atom velocities for this system (see
+    /// `System::velocities`). `velocities` must contain one value for each
+    /// atom currently in the system. Atoms added afterwards will have a zero
+    /// velocity.
+    pub fn set_velocities(&mut self, velocities: Vec<Vector3D>) -> Result<(), Error> {
+        if velocities.len() != self.species.len() {
+            return Err(Error::InvalidParameter(format!(
+                "expected {} velocities, got {}",
+                self.species.len(), velocities.len()
+            )));
+        }
+
+        if velocities.iter().any(|v| !(v[0].is_finite() && v[1].is_finite() && v[2].is_finite())) {
+            return Err(Error::InvalidParameter(
+                "atomic velocities must be finite".into()
+            ));
+        }
+
+        self.velocities = Some(velocities);
+        return Ok(());
+    }
+
+    /// Set the per-atom data with the given `name` for this system (see
+    /// `System::atom_data`), replacing any existing data with the same name.
+    /// `values` must contain one value for each atom currently in the system.
+    /// Adding atoms to the system afterwards removes all the per-atom data.
+    ///
+    /// The `"density_scaling"`, `"atomic_gaussian_width"`, `"charges"` and
+    /// `"masses"` names are forwarded to the corresponding setters (e.g.
+    /// `set_charges`), and are checked in the same way.
+    pub fn set_atom_data(&mut self, name: &str, values: Vec<f64>) -> Result<(), Error> {
+        match name {
+            "density_scaling" => return self.set_density_scaling(values),
+            "atomic_gaussian_width" => return self.set_atomic_gaussian_width(values),
+            "charges" => return self.set_charges(values),
+            "masses" => return self.set_masses(values),
+            _ => {}
+        }
+
+        if values.len() != self.species.len() {
+            return Err(Error::InvalidParameter(format!(
+                "expected {} values for '{}' atom data, got {}",
+                self.species.len(), name, values.len()
+            )));
+        }
+
+        self.atom_data.insert(name.into(), values);
+        return Ok(());
+    }
+
+    /// Set which atoms in this system should be used as centers by
+    /// atom-centered calculators (see `System::centers`). `centers` must
+    /// contain one value for each atom currently in the system. Atoms added
+    /// afterwards will be used as centers.
+    pub fn set_centers(&mut self, centers: Vec<bool>) -> Result<(), Error> {
+        if centers.len() != self.species.len() {
+            return Err(Error::InvalidParameter(format!(
+                "expected {} values in the centers mask, got {}",
+                self.species.len(), centers.len()
+            )));
+        }
+
+        self.centers = Some(centers);
+        return Ok(());
+    }
+
+    /// Set the mapping between the species of this system and chemical
+    /// symbols. All species in this system should be part of the mapping.
+    pub fn set_species_map(&mut self, map: SpeciesMap) {
+        self.species_map = Some(map);
+    }
+
+    /// Use a Verlet neighbor list with the given `skin` distance. The pairs
+    /// up to `cutoff + skin` are stored when computing the neighbor list, and
+    /// re-used in later calls to `compute_neighbors` with the same cutoff as
+    /// long as no atom moved by more than `skin / 2`. Setting the skin to 0
+    /// (the default) disables the Verlet neighbor list.
+    pub fn set_neighbors_skin(&mut self, skin: f64) -> Result<(), Error> {
+        if !(skin >= 0.0 && skin.is_finite()) {
+            return Err(Error::InvalidParameter(format!(
+                "neighbors list skin must be a positive number, got {}", skin
+            )));
+        }
+
+        self.neighbors_skin = skin;
+        self.verlet_candidates = None;
+        return Ok(());
+    }
+
+    /// Sort the pairs in the neighbor list of this system by increasing
+    /// distance instead of atomic indexes (see
+    /// `System::pairs_sorted_by_distance`).
+    pub fn set_sort_pairs_by_distance(&mut self, sort: bool) {
+        if sort != self.sort_pairs_by_distance {
+            self.neighbors.clear();
+        }
+        self.sort_pairs_by_distance = sort;
+    }
+
+    /// Update the positions of all the atoms in this system, for example
+    /// between two steps of a molecular dynamics simulation.
+    pub fn set_positions(&mut self, positions: &[Vector3D]) -> Result<(), Error> {
+        if positions.len() != self.positions.len() {
+            return Err(Error::InvalidParameter(format!(
+                "expected {} positions, got {}",
+                self.positions.len(), positions.len()
+            )));
+        }
+
+        self.positions_mut().copy_from_slice(positions);
+        return Ok(());
+    }
+
+    /// Move all the atoms in this system by the given `displacements`, updating
+    /// the positions in place.
+    ///
+    /// As with `set_positions`, the neighbor list is invalidated and will be
+    /// re-computed on the next call to `compute_neighbors`. If a Verlet skin
+    /// is set (see `set_neighbors_skin`), this re-uses the stored candidate
+    /// pairs as long as the atoms did not move too much.
+    pub fn update_positions(&mut self, displacements: &[Vector3D]) -> Result<(), Error> {
+        if displacements.len() != self.positions.len() {
+            return Err(Error::InvalidParameter(format!(
+                "expected {} displacements, got {}",
+                self.positions.len(), displacements.len()
+            )));
+        }
+
+        for (position, &displacement) in self.positions_mut().iter_mut().zip(displacements) {
+            *position += displacement;
+        }
+        return Ok(());
+    }
+
+    /// Replace the content of this system with the given `cell`, `species`
+    /// and `positions`, re-using the existing allocations.
+    ///
+    /// If the species and cell are the same as the current ones, this is
+    /// equivalent to `set_positions`, and all the other per-atom data is kept.
+    /// Otherwise, all the other data (bonds, charges, ghost atoms, …) is
+    /// removed from the system. In both cases, the neighbors list options are
+    /// kept.
+    pub(crate) fn refill(&mut self, cell: UnitCell, species: &[i32], positions: &[Vector3D]) -> Result<(), Error> {
+        if species.len() != positions.len() {
+            return Err(Error::InvalidParameter(format!(
+                "expected {} positions, got {}", species.len(), positions.len()
+            )));
+        }
+
+        if self.species == species && self.cell == cell {
+            return self.set_positions(positions);
+        }
+
+        if self.cell != cell {
+            self.set_cell(cell);
+        }
+
+        self.species.clear();
+        self.species.extend_from_slice(species);
+        self.neighbors.clear();
+        self.positions.clear();
+        self.positions.extend_from_slice(positions);
+
+        self.bonds.clear();
+        self.density_scaling = None;
+        self.atomic_gaussian_width = None;
+        self.charges = None;
+        self.masses = None;
+        self.velocities = None;
+        self.atom_data.clear();
+        self.centers = None;
+        self.ghost_atoms.clear();
+        self.species_map = None;
+        // the atoms might be completely different, the Verlet candidates can
+        // not be re-used
+        self.verlet_candidates = None;
+
+        return Ok(());
+    }
+
+    pub(crate) fn species_mut(&mut self) -> &mut [i32] {
+        return &mut self.species;
     }
 
-    #[cfg(test)]
     pub(crate) fn positions_mut(&mut self) -> &mut [Vector3D] {
-        // any position access invalidates the neighbor list
-        self.neighbors = None;
+        // any position access invalidates the neighbor list, the Verlet
+        // candidates check the displacements before being re-used
+        self.neighbors.clear();
         return &mut self.positions;
     }
 
-    #[cfg(test)]
     pub(crate) fn set_cell(&mut self, cell: UnitCell) {
         // cell change invalidate the neighbor list
-        self.neighbors = None;
+        self.neighbors.clear();
+        self.verlet_candidates = None;
         self.cell = cell;
     }
 }
@@ -54,6 +448,10 @@ impl System for SimpleSystem {
         Ok(&self.positions)
     }
 
+    fn local_size(&self) -> Result<usize, Error> {
+        Ok(self.species.len() - self.ghost_atoms.len())
+    }
+
     fn species(&self) -> Result<&[i32], Error> {
         Ok(&self.species)
     }
@@ -64,40 +462,202 @@ impl System for SimpleSystem {
 
     #[allow(clippy::float_cmp)]
     fn compute_neighbors(&mut self, cutoff: f64) -> Result<(), Error> {
-        // re-use already computed NL is possible
-        if let Some(ref nl) = self.neighbors {
-            if nl.cutoff == cutoff {
-                return Ok(());
+        let positions = &self.positions;
+        let cell = self.cell;
+        let skin = self.neighbors_skin;
+        let sort_pairs_by_distance = self.sort_pairs_by_distance;
+        let verlet_candidates = &mut self.verlet_candidates;
+
+        // re-use already computed NL if possible
+        self.neighbors.select(cutoff, || {
+            let mut neighbors = if skin > 0.0 {
+                let valid_candidates = match *verlet_candidates {
+                    Some(ref candidates) => candidates.cutoff == cutoff && candidates.is_valid_for(positions),
+                    None => false,
+                };
+
+                if !valid_candidates {
+                    *verlet_candidates = Some(VerletCandidates::new(positions, cell, cutoff, skin));
+                }
+
+                let candidates = verlet_candidates.as_ref().expect("missing Verlet candidates");
+                NeighborsList::from_candidates(candidates, positions, cell)
+            } else {
+                NeighborsList::new(positions, cell, cutoff)
+            };
+
+            if sort_pairs_by_distance {
+                neighbors.sort_by_distance();
             }
-        }
 
-        self.neighbors = Some(NeighborsList::new(self.positions()?, self.cell()?, cutoff));
-        Ok(())
+            Ok(neighbors)
+        })
     }
 
     fn pairs(&self) -> Result<&[Pair], Error> {
-        let neighbors = self.neighbors.as_ref().ok_or_else(|| Error::Internal(
+        let neighbors = self.neighbors.current().ok_or_else(|| Error::Internal(
             "neighbor list is not initialized".into()
         ))?;
         Ok(&neighbors.pairs)
     }
 
     fn pairs_containing(&self, center: usize) -> Result<&[Pair], Error> {
-        let neighbors = self.neighbors.as_ref().ok_or_else(|| Error::Internal(
+        let neighbors = self.neighbors.current().ok_or_else(|| Error::Internal(
             "neighbor list is not initialized".into()
         ))?;
         Ok(&neighbors.pairs_by_center[center])
     }
+
+    fn pairs_sorted_by_distance(&self) -> Result<bool, Error> {
+        Ok(self.sort_pairs_by_distance)
+    }
+
+    fn bonds(&self) -> Result<&[[usize; 2]], Error> {
+        Ok(&self.bonds)
+    }
+
+    fn density_scaling(&self) -> Result<Option<&[f64]>, Error> {
+        Ok(self.density_scaling.as_deref())
+    }
+
+    fn atomic_gaussian_width(&self) -> Result<Option<&[f64]>, Error> {
+        Ok(self.atomic_gaussian_width.as_deref())
+    }
+
+    fn charges(&self) -> Result<Option<&[f64]>, Error> {
+        Ok(self.charges.as_deref())
+    }
+
+    fn masses(&self) -> Result<Option<&[f64]>, Error> {
+        Ok(self.masses.as_deref())
+    }
+
+    fn velocities(&self) -> Result<Option<&[Vector3D]>, Error> {
+        Ok(self.velocities.as_deref())
+    }
+
+    fn atom_data(&self, name: &str) -> Result<Option<&[f64]>, Error> {
+        match name {
+            "density_scaling" => Ok(self.density_scaling.as_deref()),
+            "atomic_gaussian_width" => Ok(self.atomic_gaussian_width.as_deref()),
+            "charges" => Ok(self.charges.as_deref()),
+            "masses" => Ok(self.masses.as_deref()),
+            _ => Ok(self.atom_data.get(name).map(|values| &**values)),
+        }
+    }
+
+    fn atom_data_names(&self) -> Result<Vec<&str>, Error> {
+        let mut names = Vec::new();
+        if self.density_scaling.is_some() {
+            names.push("density_scaling");
+        }
+        if self.atomic_gaussian_width.is_some() {
+            names.push("atomic_gaussian_width");
+        }
+        if self.charges.is_some() {
+            names.push("charges");
+        }
+        if self.masses.is_some() {
+            names.push("masses");
+        }
+        names.extend(self.atom_data.keys().map(|name| &**name));
+        Ok(names)
+    }
+
+    fn as_any_mut(&mut self) -> Option<&mut dyn std::any::Any> {
+        Some(self)
+    }
+
+    fn ghost_atoms(&self) -> Result<Option<&[GhostAtom]>, Error> {
+        if self.ghost_atoms.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(&self.ghost_atoms))
+        }
+    }
+
+    fn centers(&self) -> Result<Option<&[bool]>, Error> {
+        Ok(self.centers.as_deref())
+    }
+
+    fn species_map(&self) -> Result<Option<&SpeciesMap>, Error> {
+        Ok(self.species_map.as_ref())
+    }
 }
 
+/// Convert any system to a `SimpleSystem`. Named per-atom data (see
+/// `System::atom_data`) is copied for all the names given by
+/// `System::atom_data_names`.
 impl std::convert::TryFrom<&dyn System> for SimpleSystem {
     type Error = Error;
 
     fn try_from(system: &dyn System) -> Result<SimpleSystem, Error> {
         let mut new = SimpleSystem::new(system.cell()?);
-        for (&species, &position) in system.species()?.iter().zip(system.positions()?) {
-            new.add_atom(species, position);
+
+        let local_size = system.local_size()?;
+        let species = system.species()?;
+        let positions = system.positions()?;
+        for atom_i in 0..local_size {
+            new.add_atom(species[atom_i], positions[atom_i]);
+        }
+
+        if local_size != species.len() {
+            let ghost_atoms = system.ghost_atoms()?.ok_or_else(|| Error::InvalidParameter(
+                "the owners of ghost atoms must be known to convert a system to SimpleSystem".into()
+            ))?;
+
+            if ghost_atoms.len() != species.len() - local_size {
+                return Err(Error::InvalidParameter(format!(
+                    "expected {} ghost atoms owners, got {}",
+                    species.len() - local_size, ghost_atoms.len()
+                )));
+            }
+
+            for (ghost_i, &ghost) in ghost_atoms.iter().enumerate() {
+                let atom_i = local_size + ghost_i;
+                new.add_ghost_atom(species[atom_i], positions[atom_i], ghost);
+            }
+        }
+
+        for &[i, j] in system.bonds()? {
+            new.add_bond(i, j)?;
+        }
+
+        if let Some(scaling) = system.density_scaling()? {
+            new.set_density_scaling(scaling.to_vec())?;
         }
+
+        if let Some(widths) = system.atomic_gaussian_width()? {
+            new.set_atomic_gaussian_width(widths.to_vec())?;
+        }
+
+        if let Some(charges) = system.charges()? {
+            new.set_charges(charges.to_vec())?;
+        }
+
+        if let Some(masses) = system.masses()? {
+            new.set_masses(masses.to_vec())?;
+        }
+
+        if let Some(velocities) = system.velocities()? {
+            new.set_velocities(velocities.to_vec())?;
+        }
+
+        if let Some(centers) = system.centers()? {
+            new.set_centers(centers.to_vec())?;
+        }
+
+        if let Some(map) = system.species_map()? {
+            new.set_species_map(map.clone());
+        }
+
+        for name in system.atom_data_names()? {
+            let values = system.atom_data(name)?.ok_or_else(|| Error::InvalidParameter(format!(
+                "missing '{}' atom data, which is part of the atom data names", name
+            )))?;
+            new.set_atom_data(name, values.to_vec())?;
+        }
+
         return Ok(new);
     }
 }
@@ -124,4 +684,282 @@ mod tests {
             Vector3D::new(5.0, 3.0, 4.0),
         ]);
     }
+
+    #[test]
+    fn ghost_atoms() {
+        let mut system = SimpleSystem::new(UnitCell::infinite());
+        system.add_atom(8, Vector3D::new(0.0, 0.0, 0.0));
+        assert_eq!(system.ghost_atoms().unwrap(), None);
+
+        let ghost = GhostAtom { rank: 3, local_index: 12 };
+        system.add_ghost_atom(1, Vector3D::new(0.0, 0.75, -0.59), ghost);
+
+        assert_eq!(system.size().unwrap(), 2);
+        assert_eq!(system.local_size().unwrap(), 1);
+        assert_eq!(system.ghost_atoms().unwrap(), Some(&[ghost][..]));
+
+        let copy = SimpleSystem::try_from(&system as &dyn System).unwrap();
+        assert_eq!(copy.local_size().unwrap(), 1);
+        assert_eq!(copy.ghost_atoms().unwrap(), Some(&[ghost][..]));
+    }
+
+    #[test]
+    fn centers() {
+        let mut system = SimpleSystem::new(UnitCell::cubic(10.0));
+        system.add_atom(8, Vector3D::new(0.0, 0.0, 0.0));
+        system.add_atom(1, Vector3D::new(0.0, 0.75, -0.59));
+        assert_eq!(system.centers().unwrap(), None);
+
+        let error = system.set_centers(vec![true]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: expected 2 values in the centers mask, got 1");
+
+        system.set_centers(vec![false, true]).unwrap();
+        system.add_atom(1, Vector3D::new(0.0, -0.75, -0.59));
+        assert_eq!(system.centers().unwrap(), Some(&[false, true, true][..]));
+
+        let copy = SimpleSystem::try_from(&system as &dyn System).unwrap();
+        assert_eq!(copy.centers().unwrap(), Some(&[false, true, true][..]));
+    }
+
+    #[test]
+    #[should_panic = "local atoms must be added before ghost atoms"]
+    fn local_atom_after_ghost() {
+        let mut system = SimpleSystem::new(UnitCell::infinite());
+        system.add_ghost_atom(1, Vector3D::new(0.0, 0.75, -0.59), GhostAtom { rank: 0, local_index: 0 });
+        system.add_atom(8, Vector3D::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn add_bonds() {
+        let mut system = SimpleSystem::new(UnitCell::cubic(10.0));
+        system.add_atom(8, Vector3D::new(0.0, 0.0, 0.0));
+        system.add_atom(1, Vector3D::new(0.0, 0.75, -0.59));
+        system.add_atom(1, Vector3D::new(0.0, -0.75, -0.59));
+
+        assert!(system.bonds().unwrap().is_empty());
+
+        system.add_bond(0, 1).unwrap();
+        system.add_bond(2, 0).unwrap();
+        // adding the same bond again does nothing
+        system.add_bond(1, 0).unwrap();
+        assert_eq!(system.bonds().unwrap(), &[[0, 1], [0, 2]]);
+
+        let error = system.add_bond(0, 3).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: can not add a bond between atoms 0 and 3: this system only contains 3 atoms");
+
+        let error = system.add_bond(1, 1).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: can not add a bond between atom 1 and itself");
+
+        system.set_bonds(&[[2, 1], [1, 2]]).unwrap();
+        assert_eq!(system.bonds().unwrap(), &[[1, 2]]);
+
+        let error = system.set_bonds(&[[0, 1], [0, 5]]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: can not add a bond between atoms 0 and 5: this system only contains 3 atoms");
+        assert_eq!(system.bonds().unwrap(), &[[1, 2]]);
+    }
+
+    #[test]
+    fn verlet_neighbors_list() {
+        let mut system = SimpleSystem::new(UnitCell::cubic(10.0));
+        system.add_atom(8, Vector3D::new(0.0, 0.0, 0.0));
+        system.add_atom(1, Vector3D::new(0.0, 0.75, -0.59));
+        system.add_atom(1, Vector3D::new(0.0, -0.75, -0.59));
+        system.add_atom(6, Vector3D::new(2.3, 0.0, 0.0));
+        system.set_neighbors_skin(1.0).unwrap();
+
+        let check_pairs = |system: &mut SimpleSystem| {
+            system.compute_neighbors(2.0).unwrap();
+            let reference = NeighborsList::new(system.positions().unwrap(), system.cell, 2.0);
+
+            let pairs = system.pairs().unwrap();
+            assert_eq!(pairs.len(), reference.pairs.len());
+            for (pair, expected) in pairs.iter().zip(&reference.pairs) {
+                assert_eq!((pair.first, pair.second), (expected.first, expected.second));
+                assert_eq!(pair.distance, expected.distance);
+            }
+        };
+
+        check_pairs(&mut system);
+        assert_eq!(system.pairs().unwrap().len(), 3);
+
+        // small displacement, the candidates are re-used and include the new
+        // pair between atoms 0 and 3
+        let mut positions = system.positions().unwrap().to_vec();
+        positions[3] = Vector3D::new(1.9, 0.0, 0.0);
+        system.set_positions(&positions).unwrap();
+        check_pairs(&mut system);
+        assert_eq!(system.pairs().unwrap().len(), 4);
+
+        // large displacement, the candidates are re-computed
+        positions[3] = Vector3D::new(-1.5, 0.0, 0.0);
+        system.set_positions(&positions).unwrap();
+        check_pairs(&mut system);
+
+        // in-place update with displacements
+        let mut displacements = vec![Vector3D::zero(); 4];
+        displacements[3] = Vector3D::new(4.25, 0.0, 0.0);
+        system.update_positions(&displacements).unwrap();
+        assert_eq!(system.positions().unwrap()[3], Vector3D::new(2.75, 0.0, 0.0));
+        check_pairs(&mut system);
+        assert_eq!(system.pairs().unwrap().len(), 3);
+
+        let error = system.set_positions(&positions[..2]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: expected 4 positions, got 2");
+
+        let error = system.update_positions(&displacements[..2]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: expected 4 displacements, got 2");
+
+        let error = system.set_neighbors_skin(-1.0).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: neighbors list skin must be a positive number, got -1");
+    }
+
+    #[test]
+    fn pairs_sorted_by_distance() {
+        let mut system = SimpleSystem::new(UnitCell::cubic(10.0));
+        system.add_atom(8, Vector3D::new(0.0, 0.0, 0.0));
+        system.add_atom(1, Vector3D::new(0.0, 0.75, -0.59));
+        system.add_atom(1, Vector3D::new(0.0, -0.75, -0.59));
+        system.add_atom(6, Vector3D::new(0.0, 0.0, 0.5));
+
+        let get_pairs = |pairs: &[Pair]| pairs.iter().map(|p| (p.first, p.second)).collect::<Vec<_>>();
+
+        system.compute_neighbors(2.0).unwrap();
+        assert!(!system.pairs_sorted_by_distance().unwrap());
+        assert_eq!(get_pairs(system.pairs().unwrap()), [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]);
+
+        system.set_sort_pairs_by_distance(true);
+        system.compute_neighbors(2.0).unwrap();
+        assert!(system.pairs_sorted_by_distance().unwrap());
+        assert_eq!(get_pairs(system.pairs().unwrap()), [(0, 3), (0, 1), (0, 2), (1, 3), (2, 3), (1, 2)]);
+        assert_eq!(get_pairs(system.pairs_containing(1).unwrap()), [(0, 1), (1, 3), (1, 2)]);
+    }
+
+    #[test]
+    fn density_scaling() {
+        let mut system = SimpleSystem::new(UnitCell::cubic(10.0));
+        system.add_atom(8, Vector3D::new(0.0, 0.0, 0.0));
+        system.add_atom(1, Vector3D::new(0.0, 0.75, -0.59));
+
+        assert_eq!(system.density_scaling().unwrap(), None);
+
+        system.set_density_scaling(vec![1.5, 0.5]).unwrap();
+        assert_eq!(system.density_scaling().unwrap(), Some(&[1.5, 0.5][..]));
+
+        system.add_atom(1, Vector3D::new(0.0, -0.75, -0.59));
+        assert_eq!(system.density_scaling().unwrap(), Some(&[1.5, 0.5, 1.0][..]));
+
+        let error = system.set_density_scaling(vec![1.0]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: expected 3 density scaling factors, got 1");
+
+        let error = system.set_density_scaling(vec![1.0, -2.0, 1.0]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: density scaling factors must be strictly positive");
+    }
+
+    #[test]
+    fn charges() {
+        let mut system = SimpleSystem::new(UnitCell::cubic(10.0));
+        system.add_atom(8, Vector3D::new(0.0, 0.0, 0.0));
+        system.add_atom(1, Vector3D::new(0.0, 0.75, -0.59));
+
+        assert_eq!(system.charges().unwrap(), None);
+
+        system.set_charges(vec![-0.8, 0.4]).unwrap();
+        assert_eq!(system.charges().unwrap(), Some(&[-0.8, 0.4][..]));
+
+        system.add_atom(1, Vector3D::new(0.0, -0.75, -0.59));
+        assert_eq!(system.charges().unwrap(), Some(&[-0.8, 0.4, 0.0][..]));
+
+        let error = system.set_charges(vec![1.0]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: expected 3 charges, got 1");
+
+        let error = system.set_charges(vec![1.0, f64::NAN, 1.0]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: atomic charges must be finite numbers");
+    }
+
+    #[test]
+    fn masses_and_velocities() {
+        let mut system = SimpleSystem::new(UnitCell::cubic(10.0));
+        system.add_atom(8, Vector3D::new(0.0, 0.0, 0.0));
+        system.add_atom(1, Vector3D::new(0.0, 0.75, -0.59));
+
+        assert_eq!(system.masses().unwrap(), None);
+        assert_eq!(system.velocities().unwrap(), None);
+
+        system.set_masses(vec![15.999, 1.008]).unwrap();
+        assert_eq!(system.masses().unwrap(), Some(&[15.999, 1.008][..]));
+
+        let velocities = vec![Vector3D::new(0.1, 0.0, 0.0), Vector3D::new(0.0, -0.2, 0.3)];
+        system.set_velocities(velocities.clone()).unwrap();
+        assert_eq!(system.velocities().unwrap(), Some(&velocities[..]));
+
+        system.add_atom(1, Vector3D::new(0.0, -0.75, -0.59));
+        assert_eq!(system.masses().unwrap(), None);
+        assert_eq!(system.velocities().unwrap().unwrap()[2], Vector3D::zero());
+
+        let error = system.set_masses(vec![1.0]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: expected 3 masses, got 1");
+
+        let error = system.set_masses(vec![1.0, 0.0, 1.0]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: atomic masses must be strictly positive");
+
+        let error = system.set_velocities(vec![Vector3D::zero()]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: expected 3 velocities, got 1");
+    }
+
+    #[test]
+    fn atom_data() {
+        let mut system = SimpleSystem::new(UnitCell::cubic(10.0));
+        system.add_atom(8, Vector3D::new(0.0, 0.0, 0.0));
+        system.add_atom(1, Vector3D::new(0.0, 0.75, -0.59));
+
+        assert_eq!(system.atom_data("spin").unwrap(), None);
+
+        system.set_atom_data("spin", vec![0.5, -0.5]).unwrap();
+        system.set_atom_data("hirshfeld_volume", vec![0.8, 0.6]).unwrap();
+        assert_eq!(system.atom_data("spin").unwrap(), Some(&[0.5, -0.5][..]));
+        assert_eq!(system.atom_data("hirshfeld_volume").unwrap(), Some(&[0.8, 0.6][..]));
+        assert_eq!(system.atom_data("other").unwrap(), None);
+
+        let error = system.set_atom_data("spin", vec![1.0]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: expected 2 values for 'spin' atom data, got 1");
+
+        // names with a dedicated function use the corresponding setter
+        system.set_atom_data("charges", vec![-0.8, 0.8]).unwrap();
+        assert_eq!(system.charges().unwrap(), Some(&[-0.8, 0.8][..]));
+        assert_eq!(system.atom_data("charges").unwrap(), Some(&[-0.8, 0.8][..]));
+
+        let error = system.set_atom_data("masses", vec![1.0, -1.0]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: atomic masses must be strictly positive");
+
+        assert_eq!(system.atom_data_names().unwrap(), ["charges", "hirshfeld_volume", "spin"]);
+
+        let copy = SimpleSystem::try_from(&system as &dyn System).unwrap();
+        assert_eq!(copy.atom_data_names().unwrap(), ["charges", "hirshfeld_volume", "spin"]);
+        assert_eq!(copy.atom_data("spin").unwrap(), Some(&[0.5, -0.5][..]));
+        assert_eq!(copy.atom_data("hirshfeld_volume").unwrap(), Some(&[0.8, 0.6][..]));
+
+        system.add_atom(1, Vector3D::new(0.0, -0.75, -0.59));
+        assert_eq!(system.atom_data("spin").unwrap(), None);
+    }
+
+    #[test]
+    fn atomic_gaussian_width() {
+        let mut system = SimpleSystem::new(UnitCell::cubic(10.0));
+        system.add_atom(8, Vector3D::new(0.0, 0.0, 0.0));
+        system.add_atom(1, Vector3D::new(0.0, 0.75, -0.59));
+
+        assert_eq!(system.atomic_gaussian_width().unwrap(), None);
+
+        system.set_atomic_gaussian_width(vec![0.3, 0.5]).unwrap();
+        assert_eq!(system.atomic_gaussian_width().unwrap(), Some(&[0.3, 0.5][..]));
+
+        system.add_atom(1, Vector3D::new(0.0, -0.75, -0.59));
+        assert_eq!(system.atomic_gaussian_width().unwrap(), None);
+
+        let error = system.set_atomic_gaussian_width(vec![0.3]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: expected 3 atomic gaussian widths, got 1");
+
+        let error = system.set_atomic_gaussian_width(vec![0.3, 0.0, 0.3]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: atomic gaussian widths must be strictly positive");
+    }
 }
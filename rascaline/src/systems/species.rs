@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+
+use crate::Error;
+
+use super::System;
+
+/// Mapping between the integer species used by rascaline and chemical symbols
+/// (or any other name for atomic types).
+///
+/// Systems can carry such a mapping (see `System::species_map`) when their
+/// species are not plain atomic numbers, or to keep track of the original
+/// name of the atomic types. The readers in this module fill it
+/// automatically.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpeciesMap {
+    symbols: BTreeMap<i32, String>,
+}
+
+impl SpeciesMap {
+    /// Create a new empty mapping
+    pub fn new() -> SpeciesMap {
+        SpeciesMap::default()
+    }
+
+    /// Associate the given `species` with the given `symbol`. Each species
+    /// can only have one symbol and each symbol can only correspond to one
+    /// species: trying to insert conflicting entries gives an error.
+    pub fn insert(&mut self, species: i32, symbol: &str) -> Result<(), Error> {
+        if let Some(existing) = self.symbols.get(&species) {
+            if existing == symbol {
+                return Ok(());
+            }
+
+            return Err(Error::InvalidParameter(format!(
+                "species {} is already associated with '{}', can not associate it with '{}'",
+                species, existing, symbol
+            )));
+        }
+
+        if let Some(existing) = self.species(symbol) {
+            return Err(Error::InvalidParameter(format!(
+                "'{}' is already associated with species {}, can not associate it with species {}",
+                symbol, existing, species
+            )));
+        }
+
+        self.symbols.insert(species, symbol.into());
+        return Ok(());
+    }
+
+    /// Get the species associated with the given `symbol`, if any
+    pub fn species(&self, symbol: &str) -> Option<i32> {
+        self.symbols.iter()
+            .find(|(_, s)| *s == symbol)
+            .map(|(&species, _)| species)
+    }
+
+    /// Get the symbol associated with the given `species`, if any
+    pub fn symbol(&self, species: i32) -> Option<&str> {
+        self.symbols.get(&species).map(|s| &**s)
+    }
+
+    /// Get the number of entries in this mapping
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    /// Check if this mapping is empty
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Iterate over all the `(species, symbol)` pairs in this mapping, sorted
+    /// by species
+    pub fn iter(&self) -> impl Iterator<Item=(i32, &str)> + '_ {
+        self.symbols.iter().map(|(&species, symbol)| (species, &**symbol))
+    }
+
+    /// Add all the entries from `other` to this mapping, returning an error
+    /// if they conflict with the existing entries.
+    pub fn merge(&mut self, other: &SpeciesMap) -> Result<(), Error> {
+        for (species, symbol) in other.iter() {
+            self.insert(species, symbol)?;
+        }
+        return Ok(());
+    }
+
+    /// Get the union of the species mapping of all the given `systems`.
+    ///
+    /// This can be used to go from the species used in the keys and samples
+    /// of a calculation output back to chemical symbols.
+    pub fn from_systems(systems: &[Box<dyn System>]) -> Result<SpeciesMap, Error> {
+        let mut map = SpeciesMap::new();
+        for system in systems {
+            if let Some(other) = system.species_map()? {
+                map.merge(other)?;
+            }
+        }
+        return Ok(map);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn species_map() {
+        let mut map = SpeciesMap::new();
+        map.insert(8, "O").unwrap();
+        map.insert(120, "CH3").unwrap();
+        map.insert(8, "O").unwrap();
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(map.species("CH3"), Some(120));
+        assert_eq!(map.species("H"), None);
+        assert_eq!(map.symbol(8), Some("O"));
+        assert_eq!(map.symbol(1), None);
+        assert_eq!(map.iter().collect::<Vec<_>>(), [(8, "O"), (120, "CH3")]);
+
+        let error = map.insert(8, "Ox").unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: species 8 is already associated with 'O', can not associate it with 'Ox'");
+
+        let error = map.insert(121, "CH3").unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: 'CH3' is already associated with species 120, can not associate it with species 121");
+
+        let mut other = SpeciesMap::new();
+        other.insert(1, "H").unwrap();
+        map.merge(&other).unwrap();
+        assert_eq!(map.symbol(1), Some("H"));
+    }
+}
@@ -0,0 +1,177 @@
+use crate::Error;
+
+use super::System;
+use super::neighbors::NeighborsList;
+
+/// Atoms closer than this distance are considered to be overlapping. This is
+/// the same threshold used to warn about very close atoms in the neighbor
+/// list.
+const OVERLAP_DISTANCE: f64 = 0.0316;
+
+/// The different problems that can be found when validating a system with
+/// `System::validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InvalidSystem {
+    /// Some per-atom data does not contain one value per atom
+    WrongSize {
+        /// name of the data (`"species"`, `"positions"`, `"charges"`, …)
+        name: &'static str,
+        /// expected number of values, i.e. the number of atoms
+        expected: usize,
+        /// actual number of values
+        actual: usize,
+    },
+    /// The number of local atoms is larger than the total number of atoms
+    LocalSize {
+        /// number of local atoms
+        local_size: usize,
+        /// total number of atoms
+        size: usize,
+    },
+    /// The position of an atom contains NaN or infinite values
+    NonFinitePosition {
+        /// index of the atom
+        atom: usize,
+    },
+    /// The cell has zero volume, or the cell vectors are not right-handed
+    DegenerateCell {
+        /// determinant of the cell matrix
+        determinant: f64,
+    },
+    /// Two atoms are at the same position (or very close to each other)
+    OverlappingAtoms {
+        /// index of the first atom
+        first: usize,
+        /// index of the second atom
+        second: usize,
+        /// distance between the atoms
+        distance: f64,
+    },
+}
+
+impl std::fmt::Display for InvalidSystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvalidSystem::WrongSize { name, expected, actual } => write!(
+                f, "expected {} values for {}, got {}", expected, name, actual
+            ),
+            InvalidSystem::LocalSize { local_size, size } => write!(
+                f, "the number of local atoms ({}) is larger than the number of atoms ({})", local_size, size
+            ),
+            InvalidSystem::NonFinitePosition { atom } => write!(
+                f, "the position of atom {} is not finite", atom
+            ),
+            InvalidSystem::DegenerateCell { determinant } => write!(
+                f, "the cell matrix must have a positive determinant, got {}", determinant
+            ),
+            InvalidSystem::OverlappingAtoms { first, second, distance } => write!(
+                f, "atoms {} and {} are overlapping (distance is {})", first, second, distance
+            ),
+        }
+    }
+}
+
+impl From<InvalidSystem> for Error {
+    fn from(error: InvalidSystem) -> Error {
+        Error::InvalidSystem(error)
+    }
+}
+
+/// Implementation of `System::validate`
+pub(crate) fn validate_system<S: System + ?Sized>(system: &S) -> Result<(), Error> {
+    let size = system.size()?;
+
+    let check_size = |name, actual| {
+        if actual == size {
+            Ok(())
+        } else {
+            Err(InvalidSystem::WrongSize { name: name, expected: size, actual: actual })
+        }
+    };
+
+    check_size("species", system.species()?.len())?;
+    check_size("positions", system.positions()?.len())?;
+
+    let local_size = system.local_size()?;
+    if local_size > size {
+        return Err(InvalidSystem::LocalSize { local_size: local_size, size: size }.into());
+    }
+
+    if let Some(scaling) = system.density_scaling()? {
+        check_size("density scaling", scaling.len())?;
+    }
+
+    if let Some(widths) = system.atomic_gaussian_width()? {
+        check_size("atomic gaussian width", widths.len())?;
+    }
+
+    if let Some(charges) = system.charges()? {
+        check_size("charges", charges.len())?;
+    }
+
+    if let Some(masses) = system.masses()? {
+        check_size("masses", masses.len())?;
+    }
+
+    if let Some(velocities) = system.velocities()? {
+        check_size("velocities", velocities.len())?;
+    }
+
+    if let Some(centers) = system.centers()? {
+        check_size("centers", centers.len())?;
+    }
+
+    let positions = system.positions()?;
+    for (atom, position) in positions.iter().enumerate() {
+        if !(position[0].is_finite() && position[1].is_finite() && position[2].is_finite()) {
+            return Err(InvalidSystem::NonFinitePosition { atom: atom }.into());
+        }
+    }
+
+    let cell = system.cell()?;
+    if !cell.is_infinite() {
+        let determinant = cell.matrix().determinant();
+        if determinant.is_nan() || determinant <= 1e-6 {
+            return Err(InvalidSystem::DegenerateCell { determinant: determinant }.into());
+        }
+    }
+
+    let neighbors = NeighborsList::new(positions, cell, OVERLAP_DISTANCE);
+    if let Some(pair) = neighbors.pairs.first() {
+        return Err(InvalidSystem::OverlappingAtoms {
+            first: pair.first,
+            second: pair.second,
+            distance: pair.distance,
+        }.into());
+    }
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::systems::{SimpleSystem, UnitCell};
+    use crate::{System, Vector3D};
+
+    use super::*;
+
+    #[test]
+    fn validate() {
+        let mut system = SimpleSystem::new(UnitCell::cubic(10.0));
+        system.add_atom(8, Vector3D::new(0.0, 0.0, 0.0));
+        system.add_atom(1, Vector3D::new(0.0, 0.75, -0.59));
+        system.add_atom(1, Vector3D::new(0.0, -0.75, -0.59));
+        system.validate().unwrap();
+
+        // overlapping periodic images
+        system.add_atom(1, Vector3D::new(10.0, 0.0, 0.0));
+        let error = system.validate().unwrap_err();
+        assert!(matches!(error, Error::InvalidSystem(InvalidSystem::OverlappingAtoms { first: 0, second: 3, .. })));
+
+        let mut positions = system.positions().unwrap().to_vec();
+        positions[3] = Vector3D::new(f64::NAN, 0.0, 0.0);
+        system.set_positions(&positions).unwrap();
+        let error = system.validate().unwrap_err();
+        assert_eq!(error.to_string(), "invalid system: the position of atom 3 is not finite");
+    }
+}
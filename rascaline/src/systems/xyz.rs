@@ -0,0 +1,456 @@
+use std::path::Path;
+
+use crate::{Error, Matrix3, Vector3D};
+
+use super::{SimpleSystem, UnitCell};
+use super::files::read_to_string;
+use super::elements::{SpeciesAssigner, element_symbol};
+
+/// Read all structures in the [extended XYZ] file at the given `path`, and
+/// convert them to `SimpleSystem`s.
+///
+/// This reader does not require chemfiles. The unit cell is taken from the
+/// `Lattice` and `pbc` keys in the comment line, and the atomic properties
+/// from the `Properties` key (defaulting to `species:S:1:pos:R:3` for plain
+/// XYZ files). Velocities (`velo`), masses (`masses`) and charges (`charges`
+/// or `initial_charges`) are stored in the corresponding fields of the system,
+/// and all other scalar per-atom properties are available with
+/// `System::atom_data`.
+///
+/// [extended XYZ]: https://github.com/libAtoms/extxyz
+pub fn read_xyz(path: impl AsRef<Path>) -> Result<Vec<SimpleSystem>, Error> {
+    let content = read_to_string(path.as_ref())?;
+
+    return parse_xyz(&content);
+}
+
+/// Parse all structures in the given [extended XYZ] formatted `content`, see
+/// [`read_xyz`] for more information.
+///
+/// [extended XYZ]: https://github.com/libAtoms/extxyz
+pub fn parse_xyz(content: &str) -> Result<Vec<SimpleSystem>, Error> {
+    let mut species_assigner = SpeciesAssigner::default();
+    let mut systems = Vec::new();
+
+    let mut lines = content.lines().enumerate();
+    // skip empty lines between frames and at the end of the file
+    while let Some((line_i, line)) = lines.find(|(_, line)| !line.trim().is_empty()) {
+        let n_atoms = line.trim().parse::<usize>().map_err(|_| xyz_error(
+            line_i, format!("expected the number of atoms, got '{}'", line.trim())
+        ))?;
+
+        let (comment_i, comment) = lines.next().ok_or_else(|| xyz_error(
+            line_i + 1, "missing comment line"
+        ))?;
+
+        let mut lattice = None;
+        let mut pbc = None;
+        let mut properties = String::from("species:S:1:pos:R:3");
+        for (key, value) in parse_comment(comment) {
+            if key.eq_ignore_ascii_case("lattice") {
+                lattice = Some(parse_lattice(&value).map_err(|e| xyz_error(comment_i, e))?);
+            } else if key.eq_ignore_ascii_case("pbc") {
+                pbc = Some(parse_pbc(&value).map_err(|e| xyz_error(comment_i, e))?);
+            } else if key.eq_ignore_ascii_case("properties") {
+                properties = value;
+            }
+        }
+
+        let properties = parse_properties(&properties).map_err(|e| xyz_error(comment_i, e))?;
+        let mut frame = FrameData::new(&properties, n_atoms);
+        for atom in 0..n_atoms {
+            let (atom_i, line) = lines.next().ok_or_else(|| xyz_error(
+                comment_i + 1 + atom, format!("expected {} atoms in this frame", n_atoms)
+            ))?;
+
+            frame.add_atom(&properties, line, &mut species_assigner).map_err(|e| xyz_error(atom_i, e))?;
+        }
+
+        let cell = match (lattice, pbc) {
+            (None, _) | (Some(_), Some([false, false, false])) => UnitCell::infinite(),
+            (Some(matrix), pbc) => UnitCell::from(matrix).with_periodicity(pbc.unwrap_or([true; 3])),
+        };
+
+        let mut system = frame.into_system(cell).map_err(|e| xyz_error(comment_i, e.to_string()))?;
+        system.set_species_map(species_assigner.map().clone());
+        systems.push(system);
+    }
+
+    return Ok(systems);
+}
+
+fn xyz_error(line_i: usize, message: impl Into<String>) -> Error {
+    Error::InvalidParameter(format!(
+        "invalid XYZ file at line {}: {}", line_i + 1, message.into()
+    ))
+}
+
+/// Split the comment line of an extended XYZ frame in `key=value` pairs.
+/// Values can be quoted with `"` or `{}` to contain spaces, and keys without
+/// value are treated as flags set to `T`. Anything that does not follow this
+/// syntax is ignored, since plain XYZ files can contain arbitrary comments.
+fn parse_comment(comment: &str) -> Vec<(String, String)> {
+    let mut result = Vec::new();
+    let mut chars = comment.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|&c| c != '=' && !c.is_whitespace()) {
+            key.push(c);
+        }
+
+        if chars.next_if_eq(&'=').is_none() {
+            result.push((key, "T".into()));
+            continue;
+        }
+
+        let mut value = String::new();
+        let closing = match chars.peek() {
+            Some('"') => Some('"'),
+            Some('{') => Some('}'),
+            _ => None,
+        };
+
+        if let Some(closing) = closing {
+            chars.next();
+            for c in chars.by_ref() {
+                if c == closing {
+                    break;
+                }
+                value.push(c);
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                value.push(c);
+            }
+        }
+
+        result.push((key, value));
+    }
+
+    return result;
+}
+
+fn parse_lattice(value: &str) -> Result<Matrix3, String> {
+    let values = value.split_whitespace()
+        .map(|v| v.parse::<f64>().map_err(|_| format!("invalid number '{}' in Lattice", v)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if values.len() != 9 {
+        return Err(format!("expected 9 values for Lattice, got {}", values.len()));
+    }
+
+    // the lattice vectors are stored one after the other, which is already
+    // the convention used by `UnitCell` (one vector per row)
+    let matrix = Matrix3::new([
+        [values[0], values[1], values[2]],
+        [values[3], values[4], values[5]],
+        [values[6], values[7], values[8]],
+    ]);
+
+    if matrix.determinant() <= 1e-6 {
+        return Err("Lattice vectors must define a non-degenerate right-handed cell".into());
+    }
+
+    return Ok(matrix);
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "T" | "t" | "True" | "true" | "TRUE" | "1" => Some(true),
+        "F" | "f" | "False" | "false" | "FALSE" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_pbc(value: &str) -> Result<[bool; 3], String> {
+    let values = value.split_whitespace()
+        .map(|v| parse_bool(v).ok_or_else(|| format!("invalid boolean '{}' in pbc", v)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if values.len() != 3 {
+        return Err(format!("expected 3 values for pbc, got {}", values.len()));
+    }
+
+    return Ok([values[0], values[1], values[2]]);
+}
+
+/// A single entry in the `Properties` key of extended XYZ
+struct Property {
+    name: String,
+    kind: char,
+    count: usize,
+}
+
+fn parse_properties(value: &str) -> Result<Vec<Property>, String> {
+    let fields = value.split(':').collect::<Vec<_>>();
+    if fields.len() % 3 != 0 {
+        return Err(format!("invalid Properties '{}'", value));
+    }
+
+    let mut properties = Vec::new();
+    for chunk in fields.chunks(3) {
+        let kind = match chunk[1] {
+            "S" | "R" | "I" | "L" => chunk[1].chars().next().expect("empty kind"),
+            other => return Err(format!("unknown type '{}' for property '{}'", other, chunk[0])),
+        };
+
+        let count = chunk[2].parse::<usize>().map_err(|_| format!(
+            "invalid number of columns '{}' for property '{}'", chunk[2], chunk[0]
+        ))?;
+
+        properties.push(Property {
+            name: chunk[0].into(),
+            kind: kind,
+            count: count,
+        });
+    }
+
+    let has_species = properties.iter().any(|p| is_species(p) || is_atomic_number(p));
+    if !has_species {
+        return Err("missing species in Properties".into());
+    }
+
+    if !properties.iter().any(is_positions) {
+        return Err("missing positions in Properties".into());
+    }
+
+    return Ok(properties);
+}
+
+fn is_species(property: &Property) -> bool {
+    property.name == "species" && property.kind == 'S' && property.count == 1
+}
+
+fn is_atomic_number(property: &Property) -> bool {
+    property.name == "Z" && property.kind == 'I' && property.count == 1
+}
+
+fn is_positions(property: &Property) -> bool {
+    property.name == "pos" && property.kind == 'R' && property.count == 3
+}
+
+fn is_velocities(property: &Property) -> bool {
+    matches!(property.name.as_str(), "velo" | "vel" | "velocities") && property.kind == 'R' && property.count == 3
+}
+
+fn is_masses(property: &Property) -> bool {
+    matches!(property.name.as_str(), "masses" | "mass") && property.kind == 'R' && property.count == 1
+}
+
+fn is_charges(property: &Property) -> bool {
+    matches!(property.name.as_str(), "charges" | "initial_charges") && property.kind == 'R' && property.count == 1
+}
+
+/// Data accumulated while reading the atoms of a single frame
+struct FrameData {
+    species: Vec<i32>,
+    positions: Vec<Vector3D>,
+    velocities: Option<Vec<Vector3D>>,
+    masses: Option<Vec<f64>>,
+    charges: Option<Vec<f64>>,
+    atom_data: Vec<(String, Vec<f64>)>,
+}
+
+impl FrameData {
+    fn new(properties: &[Property], n_atoms: usize) -> FrameData {
+        let atom_data = properties.iter()
+            .filter(|p| p.count == 1 && p.kind != 'S')
+            .filter(|p| !is_atomic_number(p) && !is_masses(p) && !is_charges(p))
+            .map(|p| (p.name.clone(), Vec::with_capacity(n_atoms)))
+            .collect();
+
+        FrameData {
+            species: Vec::with_capacity(n_atoms),
+            positions: Vec::with_capacity(n_atoms),
+            velocities: properties.iter().any(is_velocities).then(|| Vec::with_capacity(n_atoms)),
+            masses: properties.iter().any(is_masses).then(|| Vec::with_capacity(n_atoms)),
+            charges: properties.iter().any(is_charges).then(|| Vec::with_capacity(n_atoms)),
+            atom_data: atom_data,
+        }
+    }
+
+    fn add_atom(&mut self, properties: &[Property], line: &str, species_assigner: &mut SpeciesAssigner) -> Result<(), String> {
+        let values = line.split_whitespace().collect::<Vec<_>>();
+        let expected = properties.iter().map(|p| p.count).sum::<usize>();
+        if values.len() < expected {
+            return Err(format!("expected {} values for this atom, got {}", expected, values.len()));
+        }
+
+        let parse_real = |value: &str| value.parse::<f64>().map_err(|_| format!("invalid number '{}'", value));
+        let parse_vector = |values: &[&str]| -> Result<Vector3D, String> {
+            Ok(Vector3D::new(parse_real(values[0])?, parse_real(values[1])?, parse_real(values[2])?))
+        };
+
+        let mut species = None;
+        let mut start = 0;
+        for property in properties {
+            let columns = &values[start..start + property.count];
+            start += property.count;
+
+            if is_species(property) {
+                species = Some(species_assigner.get(columns[0]));
+            } else if is_atomic_number(property) {
+                if species.is_none() {
+                    let number = columns[0].parse::<i32>().map_err(|_| format!(
+                        "invalid atomic number '{}'", columns[0]
+                    ))?;
+
+                    // record the element symbol in the species map
+                    if let Some(symbol) = element_symbol(number) {
+                        species_assigner.get(symbol);
+                    }
+                    species = Some(number);
+                }
+            } else if is_positions(property) {
+                self.positions.push(parse_vector(columns)?);
+            } else if is_velocities(property) {
+                let velocities = self.velocities.as_mut().expect("missing velocities");
+                velocities.push(parse_vector(columns)?);
+            } else if is_masses(property) {
+                let masses = self.masses.as_mut().expect("missing masses");
+                masses.push(parse_real(columns[0])?);
+            } else if is_charges(property) {
+                let charges = self.charges.as_mut().expect("missing charges");
+                charges.push(parse_real(columns[0])?);
+            } else if let Some((_, data)) = self.atom_data.iter_mut().find(|(name, _)| name == &property.name) {
+                let value = match property.kind {
+                    'L' => {
+                        let value = parse_bool(columns[0]).ok_or_else(|| format!(
+                            "invalid boolean '{}'", columns[0]
+                        ))?;
+                        if value { 1.0 } else { 0.0 }
+                    }
+                    _ => parse_real(columns[0])?,
+                };
+                data.push(value);
+            }
+        }
+
+        self.species.push(species.expect("missing species"));
+        return Ok(());
+    }
+
+    fn into_system(self, cell: UnitCell) -> Result<SimpleSystem, Error> {
+        let mut system = SimpleSystem::new(cell);
+        for (&species, &position) in self.species.iter().zip(&self.positions) {
+            system.add_atom(species, position);
+        }
+
+        if let Some(velocities) = self.velocities {
+            system.set_velocities(velocities)?;
+        }
+
+        if let Some(masses) = self.masses {
+            system.set_masses(masses)?;
+        }
+
+        if let Some(charges) = self.charges {
+            system.set_charges(charges)?;
+        }
+
+        for (name, values) in self.atom_data {
+            system.set_atom_data(&name, values)?;
+        }
+
+        return Ok(system);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use crate::System;
+    use crate::systems::CellShape;
+    use super::*;
+
+    #[test]
+    fn plain_xyz() {
+        let content = "3\nwater molecule\nO 0.0 0.0 0.0\nH 0.0 0.75 -0.59\nH 0.0 -0.75 -0.59\n\n";
+        let systems = parse_xyz(content).unwrap();
+        assert_eq!(systems.len(), 1);
+
+        let system = &systems[0];
+        assert_eq!(system.species().unwrap(), [8, 1, 1].as_ref());
+        assert_eq!(system.positions().unwrap()[1], Vector3D::new(0.0, 0.75, -0.59));
+        assert_eq!(system.cell().unwrap().shape(), CellShape::Infinite);
+    }
+
+    #[test]
+    fn extended_xyz() {
+        let content = r#"2
+Lattice="5.0 0.0 0.0 0.0 6.0 0.0 1.0 0.0 7.0" Properties=species:S:1:pos:R:3:velo:R:3:hirshfeld_volume:R:1:fixed:L:1 pbc="T T F" energy=-3.2
+Si 0.0 0.0 0.0 0.1 0.2 0.3 0.8 T
+Xx 1.0 1.0 1.0 0.0 0.0 0.0 0.6 F
+1
+Properties=Z:I:1:pos:R:3:masses:R:1
+6 1.0 2.0 3.0 12.011
+"#;
+        let systems = parse_xyz(content).unwrap();
+        assert_eq!(systems.len(), 2);
+
+        let system = &systems[0];
+        assert_eq!(system.species().unwrap(), [14, 120].as_ref());
+
+        let cell = system.cell().unwrap();
+        assert_eq!(cell.matrix()[2], [1.0, 0.0, 7.0]);
+        assert_eq!(cell.periodic(), [true, true, false]);
+
+        let velocities = system.velocities().unwrap().unwrap();
+        assert_relative_eq!(velocities[0], Vector3D::new(0.1, 0.2, 0.3));
+        assert_eq!(system.atom_data("hirshfeld_volume").unwrap(), Some(&[0.8, 0.6][..]));
+        assert_eq!(system.atom_data("fixed").unwrap(), Some(&[1.0, 0.0][..]));
+
+        let species_map = system.species_map().unwrap().unwrap();
+        assert_eq!(species_map.symbol(14), Some("Si"));
+        assert_eq!(species_map.symbol(120), Some("Xx"));
+
+        let system = &systems[1];
+        assert_eq!(system.species().unwrap(), [6].as_ref());
+        assert_eq!(system.species_map().unwrap().unwrap().symbol(6), Some("C"));
+        assert_eq!(system.masses().unwrap(), Some(&[12.011][..]));
+        assert_eq!(system.cell().unwrap().shape(), CellShape::Infinite);
+    }
+
+    #[test]
+    fn read_file() {
+        let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("benches");
+        path.push("data");
+        path.push("silicon_bulk.xyz");
+
+        let systems = read_xyz(&path).unwrap();
+        assert_eq!(systems.len(), 30);
+        assert_eq!(systems[0].size().unwrap(), 54);
+        assert_eq!(systems[0].species().unwrap(), [14; 54].as_ref());
+
+        let matrix = systems[0].cell().unwrap().matrix();
+        assert_eq!(matrix[0], [7.84785, 0.0, 7.84785]);
+        assert_eq!(matrix[1], [7.84785, 7.84785, 0.0]);
+        assert_eq!(matrix[2], [0.0, 7.84785, 7.84785]);
+    }
+
+    #[test]
+    fn errors() {
+        let error = parse_xyz("foo\n").unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: invalid XYZ file at line 1: expected the number of atoms, got 'foo'");
+
+        let error = parse_xyz("2\n\nO 0.0 0.0 0.0\n").unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: invalid XYZ file at line 4: expected 2 atoms in this frame");
+
+        let error = parse_xyz("1\n\nO 0.0 0.0\n").unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: invalid XYZ file at line 3: expected 4 values for this atom, got 3");
+
+        let error = parse_xyz("1\nLattice=\"1 2 3\"\nO 0.0 0.0 0.0\n").unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: invalid XYZ file at line 2: expected 9 values for Lattice, got 3");
+
+        let error = parse_xyz("1\nProperties=species:S:1\nO\n").unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: invalid XYZ file at line 2: missing positions in Properties");
+    }
+}
@@ -0,0 +1,294 @@
+//! Utilities to check the gradients computed by a calculator against finite
+//! differences.
+//!
+//! These are the same checks used to validate the gradients of the
+//! calculators in rascaline, and can be used by authors of new calculators to
+//! test their own implementation. All the functions in this module panic if
+//! the analytical gradients do not match the finite differences.
+//!
+//! ```
+//! # use rascaline::{Calculator, SimpleSystem, Vector3D};
+//! # use rascaline::systems::UnitCell;
+//! use rascaline::testing::{FiniteDifferenceOptions, finite_differences_positions};
+//!
+//! let calculator = Calculator::new("sorted_distances", r#"{
+//!     "cutoff": 3.0,
+//!     "max_neighbors": 4,
+//!     "separate_neighbor_species": false
+//! }"#.into()).unwrap();
+//!
+//! let mut system = SimpleSystem::new(UnitCell::infinite());
+//! system.add_atom(8, Vector3D::new(0.0, 0.0, 0.0));
+//! system.add_atom(1, Vector3D::new(0.0, 0.8, 0.6));
+//! system.add_atom(1, Vector3D::new(0.3, -0.7, 0.5));
+//!
+//! let options = FiniteDifferenceOptions {
+//!     displacement: 1e-6,
+//!     max_relative: 1e-5,
+//!     epsilon: 1e-16,
+//! };
+//! finite_differences_positions(calculator, &system, options);
+//! ```
+
+use ndarray::Axis;
+use approx::assert_relative_eq;
+
+use crate::{CalculationOptions, Calculator};
+use crate::systems::{System, SimpleSystem, UnitCell};
+
+/// Options for the finite differences checks
+#[derive(Debug, Clone, Copy)]
+pub struct FiniteDifferenceOptions {
+    /// distance each atom will be displaced in each direction when computing
+    /// finite differences
+    pub displacement: f64,
+    /// Maximal relative error. 10 * displacement is a good starting point
+    pub max_relative: f64,
+    /// Threshold below which all values are considered zero. This should be
+    /// very small (1e-16) to prevent false positives (if all values & gradients
+    /// are below that threshold, tests will pass even with wrong gradients)
+    pub epsilon: f64,
+}
+
+/// Check that analytical gradients with respect to positions agree with a
+/// finite difference calculation of the gradients.
+pub fn finite_differences_positions(mut calculator: Calculator, system: &SimpleSystem, options: FiniteDifferenceOptions) {
+    let calculation_options = CalculationOptions {
+        gradients: &["positions"],
+        ..Default::default()
+    };
+    let reference = calculator.compute(&mut [Box::new(system.clone())], calculation_options).unwrap();
+
+    for atom_i in 0..system.size().unwrap() {
+        for spatial in 0..3 {
+            let mut system_pos = system.clone();
+            system_pos.positions_mut()[atom_i][spatial] += options.displacement / 2.0;
+            let updated_pos = calculator.compute(&mut [Box::new(system_pos)], Default::default()).unwrap();
+
+            let mut system_neg = system.clone();
+            system_neg.positions_mut()[atom_i][spatial] -= options.displacement / 2.0;
+            let updated_neg = calculator.compute(&mut [Box::new(system_neg)], Default::default()).unwrap();
+
+            assert_eq!(updated_pos.keys(), reference.keys());
+            assert_eq!(updated_neg.keys(), reference.keys());
+
+            for (block_i, (_, block)) in reference.iter().enumerate() {
+                let gradients = &block.gradient("positions").unwrap();
+                let block_pos = &updated_pos.block_by_id(block_i);
+                let block_neg = &updated_neg.block_by_id(block_i);
+
+                for (gradient_i, [sample_i, _, atom]) in gradients.samples().iter_fixed_size().enumerate() {
+                    if atom.usize() != atom_i {
+                        continue;
+                    }
+                    let sample_i = sample_i.usize();
+
+                    // check that the same sample is here in both descriptors
+                    assert_eq!(block_pos.samples()[sample_i], block.samples()[sample_i]);
+                    assert_eq!(block_neg.samples()[sample_i], block.samples()[sample_i]);
+
+                    let value_pos = block_pos.values().to_array().index_axis(Axis(0), sample_i);
+                    let value_neg = block_neg.values().to_array().index_axis(Axis(0), sample_i);
+                    let gradient = gradients.values().to_array().index_axis(Axis(0), gradient_i);
+                    let gradient = gradient.index_axis(Axis(0), spatial);
+
+                    assert_eq!(value_pos.shape(), gradient.shape());
+                    assert_eq!(value_neg.shape(), gradient.shape());
+
+                    let mut finite_difference = value_pos.to_owned().clone();
+                    finite_difference -= &value_neg;
+                    finite_difference /= options.displacement;
+
+                    assert_relative_eq!(
+                        finite_difference, gradient,
+                        epsilon=options.epsilon,
+                        max_relative=options.max_relative,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Check that analytical gradients with respect to the per-atom density
+/// scaling agree with a finite difference calculation of the gradients. The
+/// `system` must define a density scaling.
+pub fn finite_differences_density_scaling(mut calculator: Calculator, system: &SimpleSystem, options: FiniteDifferenceOptions) {
+    let calculation_options = CalculationOptions {
+        gradients: &["density_scaling"],
+        ..Default::default()
+    };
+    let reference = calculator.compute(&mut [Box::new(system.clone())], calculation_options).unwrap();
+
+    let scaling = system.density_scaling().unwrap().expect("missing density scaling").to_vec();
+    for atom_i in 0..system.size().unwrap() {
+        let mut system_pos = system.clone();
+        let mut scaling_pos = scaling.clone();
+        scaling_pos[atom_i] += options.displacement / 2.0;
+        system_pos.set_density_scaling(scaling_pos).unwrap();
+        let updated_pos = calculator.compute(&mut [Box::new(system_pos)], Default::default()).unwrap();
+
+        let mut system_neg = system.clone();
+        let mut scaling_neg = scaling.clone();
+        scaling_neg[atom_i] -= options.displacement / 2.0;
+        system_neg.set_density_scaling(scaling_neg).unwrap();
+        let updated_neg = calculator.compute(&mut [Box::new(system_neg)], Default::default()).unwrap();
+
+        assert_eq!(updated_pos.keys(), reference.keys());
+        assert_eq!(updated_neg.keys(), reference.keys());
+
+        for (block_i, (_, block)) in reference.iter().enumerate() {
+            let gradients = &block.gradient("density_scaling").unwrap();
+            let block_pos = &updated_pos.block_by_id(block_i);
+            let block_neg = &updated_neg.block_by_id(block_i);
+
+            for (gradient_i, [sample_i, _, atom]) in gradients.samples().iter_fixed_size().enumerate() {
+                if atom.usize() != atom_i {
+                    continue;
+                }
+                let sample_i = sample_i.usize();
+
+                let value_pos = block_pos.values().to_array().index_axis(Axis(0), sample_i).to_owned();
+                let value_neg = block_neg.values().to_array().index_axis(Axis(0), sample_i).to_owned();
+                let gradient = gradients.values().to_array().index_axis(Axis(0), gradient_i);
+
+                let finite_difference = (value_pos - value_neg) / options.displacement;
+
+                assert_relative_eq!(
+                    finite_difference, gradient,
+                    epsilon=options.epsilon,
+                    max_relative=options.max_relative,
+                );
+            }
+        }
+    }
+}
+
+/// Check that analytical second derivatives with respect to positions agree
+/// with a finite difference calculation using the analytical gradients.
+pub fn finite_differences_positions_hessian(mut calculator: Calculator, system: &SimpleSystem, options: FiniteDifferenceOptions) {
+    let calculation_options = CalculationOptions {
+        gradients: &["positions_hessian"],
+        ..Default::default()
+    };
+    let reference = calculator.compute(&mut [Box::new(system.clone())], calculation_options).unwrap();
+
+    let calculation_options = CalculationOptions {
+        gradients: &["positions"],
+        ..Default::default()
+    };
+
+    for atom_i in 0..system.size().unwrap() {
+        for spatial in 0..3 {
+            let mut system_pos = system.clone();
+            system_pos.positions_mut()[atom_i][spatial] += options.displacement / 2.0;
+            let updated_pos = calculator.compute(&mut [Box::new(system_pos)], calculation_options).unwrap();
+
+            let mut system_neg = system.clone();
+            system_neg.positions_mut()[atom_i][spatial] -= options.displacement / 2.0;
+            let updated_neg = calculator.compute(&mut [Box::new(system_neg)], calculation_options).unwrap();
+
+            assert_eq!(updated_pos.keys(), reference.keys());
+            assert_eq!(updated_neg.keys(), reference.keys());
+
+            for (block_i, (_, block)) in reference.iter().enumerate() {
+                let hessian = &block.gradient("positions_hessian").unwrap();
+                let gradient_pos = &updated_pos.block_by_id(block_i).gradient("positions").unwrap();
+                let gradient_neg = &updated_neg.block_by_id(block_i).gradient("positions").unwrap();
+
+                for (hessian_i, &[sample_i, structure, atom_1, atom_2]) in hessian.samples().iter_fixed_size().enumerate() {
+                    if atom_2.usize() != atom_i {
+                        continue;
+                    }
+
+                    let gradient_sample = [sample_i, structure, atom_1];
+                    let gradient_pos_i = gradient_pos.samples().position(&gradient_sample).expect("missing gradient sample");
+                    let gradient_neg_i = gradient_neg.samples().position(&gradient_sample).expect("missing gradient sample");
+
+                    let value_pos = gradient_pos.values().to_array().index_axis(Axis(0), gradient_pos_i).to_owned();
+                    let value_neg = gradient_neg.values().to_array().index_axis(Axis(0), gradient_neg_i).to_owned();
+
+                    // [direction_1, direction_2, ...] => [direction_1, ...]
+                    let second_derivative = hessian.values().to_array().index_axis(Axis(0), hessian_i).to_owned();
+                    let second_derivative = second_derivative.index_axis(Axis(1), spatial);
+
+                    let finite_difference = (value_pos - value_neg) / options.displacement;
+
+                    assert_relative_eq!(
+                        finite_difference, second_derivative,
+                        epsilon=options.epsilon,
+                        max_relative=options.max_relative,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Check that analytical gradients with respect to cell agree with a
+/// finite difference calculation of the gradients.
+pub fn finite_differences_cell(mut calculator: Calculator, system: &SimpleSystem, options: FiniteDifferenceOptions) {
+    let calculation_options = CalculationOptions {
+        gradients: &["cell"],
+        ..Default::default()
+    };
+    let reference = calculator.compute(&mut [Box::new(system.clone())], calculation_options).unwrap();
+    let original_cell = system.cell().unwrap().matrix();
+    let original_cell_inverse = original_cell.inverse();
+
+    for spatial_1 in 0..3 {
+        for spatial_2 in 0..3 {
+            let mut deformed_cell = original_cell;
+            deformed_cell[spatial_1][spatial_2] += options.displacement / 2.0;
+
+            let mut system_pos = system.clone();
+            system_pos.set_cell(UnitCell::from(deformed_cell));
+            for position in system_pos.positions_mut() {
+                *position = deformed_cell * (original_cell_inverse * *position);
+            }
+            let updated_pos = calculator.compute(&mut [Box::new(system_pos)], Default::default()).unwrap();
+
+            deformed_cell[spatial_1][spatial_2] -= options.displacement;
+
+            let mut system_neg = system.clone();
+            system_neg.set_cell(UnitCell::from(deformed_cell));
+            for position in system_neg.positions_mut() {
+                *position = deformed_cell * (original_cell_inverse * *position);
+            }
+            let updated_neg = calculator.compute(&mut [Box::new(system_neg)], Default::default()).unwrap();
+
+            for (block_i, (_, block)) in reference.iter().enumerate() {
+                let gradients = &block.gradient("cell").unwrap();
+                let block_pos = &updated_pos.block_by_id(block_i);
+                let block_neg = &updated_neg.block_by_id(block_i);
+
+                for (gradient_i, [sample_i]) in gradients.samples().iter_fixed_size().enumerate() {
+                    let sample_i = sample_i.usize();
+
+                    // check that the same sample is here in both descriptors
+                    assert_eq!(block_pos.samples()[sample_i], block.samples()[sample_i]);
+                    assert_eq!(block_neg.samples()[sample_i], block.samples()[sample_i]);
+
+                    let value_pos = block_pos.values().to_array().index_axis(Axis(0), sample_i);
+                    let value_neg = block_neg.values().to_array().index_axis(Axis(0), sample_i);
+                    let gradient = gradients.values().to_array().index_axis(Axis(0), gradient_i);
+                    let gradient = gradient.index_axis(Axis(0), spatial_1);
+                    let gradient = gradient.index_axis(Axis(0), spatial_2);
+
+                    assert_eq!(value_pos.shape(), gradient.shape());
+                    assert_eq!(value_neg.shape(), gradient.shape());
+
+                    let mut finite_difference = value_pos.to_owned().clone();
+                    finite_difference -= &value_neg;
+                    finite_difference /= options.displacement;
+
+                    assert_relative_eq!(
+                        finite_difference, gradient,
+                        epsilon=options.epsilon,
+                        max_relative=options.max_relative,
+                    );
+                }
+            }
+        }
+    }
+}
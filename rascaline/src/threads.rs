@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use once_cell::sync::Lazy;
+
+use crate::Error;
+
+/// Maximal number of threads set with `set_max_threads`, 0 meaning that no
+/// limit was set.
+static MAX_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Maximal number of threads taken from the `RASCALINE_NUM_THREADS`
+/// environment variable, if it is set.
+static ENV_MAX_THREADS: Lazy<Option<usize>> = Lazy::new(|| {
+    let value = std::env::var("RASCALINE_NUM_THREADS").ok()?;
+    match value.trim().parse::<usize>() {
+        Ok(0) => None,
+        Ok(max_threads) => Some(max_threads),
+        Err(_) => {
+            log::warn!(
+                "ignoring invalid value for RASCALINE_NUM_THREADS: '{}', expected a positive integer",
+                value
+            );
+            None
+        }
+    }
+});
+
+/// Thread pools created for a given number of threads, re-used between
+/// calculations
+static THREAD_POOLS: Lazy<Mutex<BTreeMap<usize, Arc<rayon::ThreadPool>>>> = Lazy::new(Default::default);
+
+/// Set the maximal number of threads used by all calculators. Individual
+/// calculators can override this value with `Calculator::set_max_threads`.
+///
+/// Using `None` removes the limit set by a previous call to this function.
+/// The `RASCALINE_NUM_THREADS` environment variable is then used if it is set,
+/// and all the available threads are used otherwise.
+pub fn set_max_threads(max_threads: Option<usize>) -> Result<(), Error> {
+    if max_threads == Some(0) {
+        return Err(Error::InvalidParameter(
+            "the maximal number of threads must be at least 1".into()
+        ));
+    }
+
+    MAX_THREADS.store(max_threads.unwrap_or(0), Ordering::Relaxed);
+    return Ok(());
+}
+
+/// Get the maximal number of threads used by calculators, either set with
+/// `set_max_threads` or from the `RASCALINE_NUM_THREADS` environment variable.
+/// This is `None` if there is no limit on the number of threads.
+pub fn max_threads() -> Option<usize> {
+    match MAX_THREADS.load(Ordering::Relaxed) {
+        0 => *ENV_MAX_THREADS,
+        max_threads => Some(max_threads),
+    }
+}
+
+/// Run `function` using at most `max_threads` threads for all the parallel
+/// code it contains, or with rayon's global thread pool if `max_threads` is
+/// `None`.
+pub(crate) fn install<F, T>(max_threads: Option<usize>, function: F) -> Result<T, Error>
+    where F: FnOnce() -> Result<T, Error> + Send,
+          T: Send,
+{
+    let max_threads = if let Some(max_threads) = max_threads {
+        max_threads
+    } else {
+        return function();
+    };
+
+    let pool = {
+        let mut pools = THREAD_POOLS.lock().expect("mutex was poisoned");
+        if let Some(pool) = pools.get(&max_threads) {
+            Arc::clone(pool)
+        } else {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(max_threads)
+                .thread_name(|i| format!("rascaline-{}", i))
+                .build()
+                .map_err(|e| Error::Internal(format!("failed to create thread pool: {}", e)))?;
+
+            let pool = Arc::new(pool);
+            pools.insert(max_threads, Arc::clone(&pool));
+            pool
+        }
+    };
+
+    return pool.install(function);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn install() {
+        let threads = super::install(Some(2), || Ok(rayon::current_num_threads())).unwrap();
+        assert_eq!(threads, 2);
+
+        let threads = super::install(Some(1), || {
+            // nested calls with a different number of threads
+            let inner = super::install(Some(3), || Ok(rayon::current_num_threads()))?;
+            Ok((rayon::current_num_threads(), inner))
+        }).unwrap();
+        assert_eq!(threads, (1, 3));
+
+        let threads = super::install(None, || Ok(rayon::current_num_threads())).unwrap();
+        assert_eq!(threads, rayon::current_num_threads());
+    }
+
+    #[test]
+    fn invalid_max_threads() {
+        let error = set_max_threads(Some(0)).unwrap_err();
+        assert_eq!(error.to_string(), "invalid parameter: the maximal number of threads must be at least 1");
+    }
+}
@@ -25,6 +25,8 @@ impl CalculatorBase for GeometricMoments {
         let builder = CenterSingleNeighborsSpeciesKeys {
             cutoff: self.cutoff,
             self_pairs: false,
+            species_center: SpeciesFilter::Any,
+            species_neighbor: SpeciesFilter::Any,
         };
         return builder.keys(systems);
     }
@@ -389,13 +391,13 @@ mod more_tests {
 
         let system = test_system("water");
 
-        let options = crate::calculators::tests_utils::FinalDifferenceOptions {
+        let options = crate::testing::FiniteDifferenceOptions {
             displacement: 1e-6,
             max_relative: 1e-6,
             epsilon: 1e-20,
         };
 
-        crate::calculators::tests_utils::finite_differences_positions(calculator, &system, options);
+        crate::testing::finite_differences_positions(calculator, &system, options);
     }
     // [finite-differences-test]
 }
@@ -34,6 +34,8 @@ impl CalculatorBase for GeometricMoments {
             // self pairs would have a distance of 0 and would not contribute
             // anything meaningful to a GeometricMoments representation
             self_pairs: false,
+            species_center: SpeciesFilter::Any,
+            species_neighbor: SpeciesFilter::Any,
         };
         return builder.keys(systems);
     }
@@ -96,6 +96,7 @@ fn madelung() {
                     center_atom_weight: 0.0,
                     potential_exponent: 1,
                     radial_basis: RadialBasis::splined_gto(1e-8),
+                    excluded_neighbor_species: Vec::new(),
                 };
 
                 let mut calculator = Calculator::from(Box::new(LodeSphericalExpansion::new(
@@ -141,6 +142,7 @@ fn madelung_high_accuracy() {
             center_atom_weight: 0.0,
             potential_exponent: 1,
             radial_basis: RadialBasis::splined_gto(1e-8),
+            excluded_neighbor_species: Vec::new(),
         };
 
         let mut calculator = Calculator::from(Box::new(LodeSphericalExpansion::new(